@@ -0,0 +1,61 @@
+//! Generates the protocol types from `proto/`.
+
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // A protoc on PROTOC wins; otherwise the vendored one, so crates that
+    // depend on this one build without installing anything
+    if std::env::var_os("PROTOC").is_none() {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    }
+    let feature = |name: &str| std::env::var_os(format!("CARGO_FEATURE_{}", name)).is_some();
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
+
+    let mut config = tonic_build::configure()
+        .build_server(feature("SERVER"))
+        .build_client(feature("CLIENT"))
+        // The client's `connect` constructor would clash with the Connect
+        // RPC; build it from a channel with `NpcSocietyServiceClient::new`
+        .build_transport(false)
+        // Six item slots inline would make every ServerMessage as large as
+        // a RestoreNpcState; a whole NPC state even more so
+        .boxed(".npc_society.v1.RestoreNpcState.equipment")
+        .boxed(".npc_society.v1.SpawnTransferredNpc.snapshot")
+        .boxed(".npc_society.v1.NpcTransferUpdate.snapshot")
+        // BTreeMap works without std (and encodes in a stable order); a
+        // HashMap only under `std` would make the feature non-additive
+        .btree_map(["."])
+        .file_descriptor_set_path(out_dir.join("npc_society_descriptor.bin"));
+
+    // Audio payloads as `bytes::Bytes` so chunks can share one buffer and
+    // be passed along without copying
+    if feature("BYTES") {
+        config = config.bytes([
+            ".npc_society.v1.VoicePcmFrame.pcm_data",
+            ".npc_society.v1.AudioChunk.pcm_data",
+            ".npc_society.v1.RegisterAudioAsset.pcm_data",
+        ]);
+    }
+
+    // JSON/TOML/etc. for fixtures, storage and config. Missing fields take
+    // their proto3 defaults; oneof variants and enum values are snake_case
+    // (`{"message": {"world_tick": {...}}}`).
+    if feature("SERDE") {
+        config = config
+            .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
+            .message_attribute(".", "#[serde(default)]")
+            .enum_attribute(".", "#[serde(rename_all = \"snake_case\")]");
+    }
+
+    // Packaged crates carry their own copy; in the repository `proto` links
+    // to the top-level directory
+    let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR")?);
+    let include = manifest_dir.join("proto");
+    println!("cargo:rerun-if-changed={}", include.display());
+    config.compile_protos(
+        &[include.join("npc_society/v1/npc_society.proto")],
+        &[include],
+    )?;
+
+    Ok(())
+}
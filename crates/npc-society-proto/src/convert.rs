@@ -0,0 +1,179 @@
+//! `From` conversions from a oneof payload to the message that carries it,
+//! so `hello.into()` gives a `ClientMessage` and `move_action.into()` an
+//! `action_directive::Action`.
+//!
+//! A new oneof variant needs a line here.
+
+use crate::v1::{
+    action_directive, client_message, server_message, ActionDirective, ActionResult, AttackAction,
+    AudioBufferStatus, AudioChunk, AudioStreamHello, BlockWatchUpdate, BreakBlockAction,
+    BreedAnimalsAction, BrewAction, ChangeDimensionObservation, ChatDirective, ChatObservation,
+    CheckLineOfSightAction, ChoreographyDirective, ChoreographyResult, ChunkLoadObservation,
+    ClaimContainerDirective, ClientMessage, CombatPolicyObservation, ConsumeItemAction,
+    ContainerAccessObservation, CraftAction, DebugPathDirective, DepositToChestAction,
+    DialogueChoiceObservation, DialogueOptionsDirective, DirectiveAck, DirectiveRejected,
+    EnchantItemAction, EquipArmorAction, EventObservation, FinishNpcTransfer, FormationDirective,
+    FreezeNpcDirective, GiveItemAction, GuardZoneDirective, Hello, HelloAck, InteractAction,
+    IntruderObservation, InventoryAction, KeepChunkLoadedDirective, LandmarkList, ListLandmarks,
+    LookAction, MarkMapAction, MilkAction, ModerationFlag, MoveAction, NpcMessage,
+    NpcTransferUpdate, PlaceBlockAction, PlayAudioAssetDirective, PlayMusicDirective,
+    PlayParticleDirective, PlaySoundDirective, PrepareNpcTransfer, PressButtonAction, Probe,
+    ProbeReply, QuarantineNpcDirective, QuestOffer, QuestUpdate, RaycastLookAction,
+    ReadRedstonePowerAction, RegionSnapshotAction, RegisterAudioAsset, RegisterLandmark,
+    RemoveDisplayDirective, RepairItemAction, RequestVoiceCapture, RestoreNpcState,
+    ResumeNpcDirective, RideAndDriveAction, ScanBlocksAction, ServerMessage,
+    SetCombatPolicyDirective, SetMechanismAction, SetTimeDirective, SetWeatherDirective,
+    ShearAction, ShopDefinition, ShopTradeObservation, ShowDisplayDirective, SmeltAction,
+    SpawnTransferredNpc, SpeakDirective, SpeakResult, SpeechInterrupted, StationOutputObservation,
+    StopAction, StopSpeaking, StopVoiceCapture, SubscribeEvents, TameAnimalAction,
+    ToggleLeverAction, TransactionObservation, TransferCurrencyDirective, TransportItemsDirective,
+    TransportItemsResult, TransportProgress, UnwatchBlocksAction, VisemeTimeline,
+    VoiceConsentObservation, VoicePcmFrame, WatchBlocksAction, WorldBootstrap, WorldTick,
+    WriteBookAction, WriteSignAction,
+};
+
+macro_rules! into_envelope {
+    ($message:ident / $oneof:ident { $($payload:ident => $variant:ident),* $(,)? }) => {
+        $(
+            impl From<$payload> for $message {
+                fn from(payload: $payload) -> Self {
+                    Self {
+                        message: Some($oneof::Message::$variant(payload)),
+                        ..Default::default()
+                    }
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! into_action {
+    ($($payload:ident => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$payload> for action_directive::Action {
+                fn from(action: $payload) -> Self {
+                    Self::$variant(action)
+                }
+            }
+        )*
+    };
+}
+
+into_envelope!(ClientMessage / client_message {
+    Hello => Hello,
+    WorldTick => WorldTick,
+    ChatObservation => ChatObservation,
+    EventObservation => EventObservation,
+    VoicePcmFrame => VoicePcmFrame,
+    ActionResult => ActionResult,
+    SpeechInterrupted => SpeechInterrupted,
+    SpeakResult => SpeakResult,
+    NpcMessage => NpcMessage,
+    QuestUpdate => QuestUpdate,
+    TransactionObservation => TransactionObservation,
+    ChangeDimensionObservation => ChangeDimension,
+    BlockWatchUpdate => BlockWatchUpdate,
+    StationOutputObservation => StationOutput,
+    CombatPolicyObservation => CombatPolicy,
+    DirectiveRejected => DirectiveRejected,
+    DirectiveAck => DirectiveAck,
+    NpcTransferUpdate => NpcTransferUpdate,
+    ChoreographyResult => ChoreographyResult,
+    DialogueChoiceObservation => DialogueChoice,
+    ShopTradeObservation => ShopTrade,
+    AudioBufferStatus => AudioBuffer,
+    IntruderObservation => Intruder,
+    ContainerAccessObservation => ContainerAccess,
+    LandmarkList => LandmarkList,
+    VoiceConsentObservation => VoiceConsent,
+    ModerationFlag => ModerationFlag,
+    AudioStreamHello => AudioStreamHello,
+    WorldBootstrap => WorldBootstrap,
+    ProbeReply => ProbeReply,
+    ChunkLoadObservation => ChunkLoad,
+    TransportProgress => TransportProgress,
+    TransportItemsResult => TransportItemsResult,
+});
+
+into_envelope!(ServerMessage / server_message {
+    ActionDirective => ActionDirective,
+    SpeakDirective => SpeakDirective,
+    AudioChunk => AudioChunk,
+    HelloAck => HelloAck,
+    StopSpeaking => StopSpeaking,
+    VisemeTimeline => VisemeTimeline,
+    NpcMessage => NpcMessage,
+    QuestOffer => QuestOffer,
+    TransferCurrencyDirective => TransferCurrency,
+    SetCombatPolicyDirective => SetCombatPolicy,
+    SubscribeEvents => SubscribeEvents,
+    RestoreNpcState => RestoreNpcState,
+    PrepareNpcTransfer => PrepareNpcTransfer,
+    SpawnTransferredNpc => SpawnTransferredNpc,
+    FinishNpcTransfer => FinishNpcTransfer,
+    ChatDirective => Chat,
+    FreezeNpcDirective => FreezeNpc,
+    ResumeNpcDirective => ResumeNpc,
+    ChoreographyDirective => Choreography,
+    DialogueOptionsDirective => DialogueOptions,
+    ShopDefinition => Shop,
+    ShowDisplayDirective => ShowDisplay,
+    RemoveDisplayDirective => RemoveDisplay,
+    PlayParticleDirective => PlayParticle,
+    PlaySoundDirective => PlaySound,
+    SetTimeDirective => SetTime,
+    SetWeatherDirective => SetWeather,
+    RegisterAudioAsset => RegisterAudioAsset,
+    PlayAudioAssetDirective => PlayAudioAsset,
+    PlayMusicDirective => PlayMusic,
+    FormationDirective => Formation,
+    GuardZoneDirective => GuardZone,
+    ClaimContainerDirective => ClaimContainer,
+    RegisterLandmark => RegisterLandmark,
+    ListLandmarks => ListLandmarks,
+    RequestVoiceCapture => RequestVoiceCapture,
+    StopVoiceCapture => StopVoiceCapture,
+    QuarantineNpcDirective => QuarantineNpc,
+    DebugPathDirective => DebugPath,
+    Probe => Probe,
+    KeepChunkLoadedDirective => KeepChunkLoaded,
+    TransportItemsDirective => TransportItems,
+});
+
+into_action!(
+    MoveAction => Move,
+    BreakBlockAction => BreakBlock,
+    PlaceBlockAction => PlaceBlock,
+    AttackAction => Attack,
+    InteractAction => Interact,
+    InventoryAction => Inventory,
+    LookAction => Look,
+    StopAction => Stop,
+    ScanBlocksAction => ScanBlocks,
+    RaycastLookAction => RaycastLook,
+    DepositToChestAction => DepositToChest,
+    RegionSnapshotAction => RegionSnapshot,
+    WatchBlocksAction => WatchBlocks,
+    UnwatchBlocksAction => UnwatchBlocks,
+    SmeltAction => Smelt,
+    BrewAction => Brew,
+    BreedAnimalsAction => BreedAnimals,
+    TameAnimalAction => TameAnimal,
+    ShearAction => Shear,
+    MilkAction => Milk,
+    RideAndDriveAction => RideAndDrive,
+    EquipArmorAction => EquipArmor,
+    EnchantItemAction => EnchantItem,
+    RepairItemAction => RepairItem,
+    ConsumeItemAction => ConsumeItem,
+    CraftAction => Craft,
+    CheckLineOfSightAction => CheckLineOfSight,
+    GiveItemAction => GiveItem,
+    WriteSignAction => WriteSign,
+    WriteBookAction => WriteBook,
+    MarkMapAction => MarkMap,
+    ToggleLeverAction => ToggleLever,
+    PressButtonAction => PressButton,
+    SetMechanismAction => SetMechanism,
+    ReadRedstonePowerAction => ReadRedstonePower,
+);
@@ -0,0 +1,84 @@
+//! Generated types of the NPC Society protocol: the prost messages of
+//! `npc_society.v1` and, behind features, the tonic stubs of
+//! `NpcSocietyService`.
+//!
+//! | Feature  | Adds |
+//! |----------|------|
+//! | `std`    | on by default; without it the crate is `no_std` (needs `alloc`) |
+//! | `client` | `v1::npc_society_service_client::NpcSocietyServiceClient` |
+//! | `server` | the `v1::npc_society_service_server::NpcSocietyService` trait and its server |
+//! | `serde`  | `Serialize`/`Deserialize` on every message and enum |
+//! | `bytes`  | audio payloads as `bytes::Bytes` instead of `Vec<u8>` |
+//!
+//! The client has no `connect` constructor (it would clash with the
+//! Connect RPC); build one from a channel:
+//!
+//! ```ignore
+//! let channel = tonic::transport::Channel::from_static("http://localhost:50051")
+//!     .connect()
+//!     .await?;
+//! let mut client = NpcSocietyServiceClient::new(channel);
+//! let stream = client.connect(outbound).await?.into_inner();
+//! ```
+//!
+//! Only tonic's codegen is used, not its transport, so the client also
+//! builds for `wasm32-unknown-unknown`. In a browser, give it a gRPC-Web
+//! service such as `tonic_web_wasm_client::Client`; gRPC-Web has no
+//! client streaming, so that covers the unary RPCs (`GetSnapshot`,
+//! `GetSessionInfo`) but not `Connect`.
+//!
+//! Every oneof payload converts into its envelope with `From`, e.g.
+//! `ClientMessage::from(hello)`, and every action into
+//! `v1::action_directive::Action`.
+//!
+//! With `default-features = false` and neither `client` nor `server`, the
+//! only dependency is prost: no tonic, tokio or std, so relays, log
+//! processors and WASM tools can decode traffic with
+//! `ClientMessage::decode`.
+//!
+//! The crate builds its own protoc, so dependents need nothing but Cargo.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+pub mod v1 {
+    #![allow(clippy::enum_variant_names)]
+    include!(concat!(env!("OUT_DIR"), "/npc_society.v1.rs"));
+}
+
+mod convert;
+
+/// Encoded `FileDescriptorSet` of the protocol, with source info, e.g.
+/// for gRPC reflection or schema checks
+pub const FILE_DESCRIPTOR_SET: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/npc_society_descriptor.bin"));
+
+#[cfg(test)]
+mod tests {
+    use super::v1::{client_message::Message, ClientMessage, Hello};
+    use prost::Message as _;
+
+    #[test]
+    fn test_roundtrip() {
+        let msg = ClientMessage {
+            message: Some(Message::Hello(Hello {
+                protocol_version: "1".to_string(),
+                server_id: "lobby".to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        assert_eq!(
+            ClientMessage::decode(&msg.encode_to_vec()[..]).unwrap(),
+            msg
+        );
+        assert_eq!(
+            ClientMessage::from(Hello {
+                protocol_version: "1".to_string(),
+                server_id: "lobby".to_string(),
+                ..Default::default()
+            }),
+            msg
+        );
+        assert!(!super::FILE_DESCRIPTOR_SET.is_empty());
+    }
+}
@@ -5,7 +5,10 @@ import io.grpc.ManagedChannel;
 import io.grpc.ManagedChannelBuilder;
 import io.grpc.stub.StreamObserver;
 
+import java.util.Map;
+import java.util.Set;
 import java.util.UUID;
+import java.util.concurrent.ConcurrentHashMap;
 import java.util.concurrent.CountDownLatch;
 import java.util.concurrent.TimeUnit;
 
@@ -22,6 +25,7 @@ import java.util.concurrent.TimeUnit;
  * - SpeakDirective handling with directive_id/stream_id correlation
  * - ActionResult sending for MoveAction
  * - ScanBlocksResult mock response
+ * - MessageTiming stamps and echoes for latency tracking (v1.2+)
  */
 public class ExampleClient {
     
@@ -31,12 +35,30 @@ public class ExampleClient {
     // Track directive IDs for correlation
     private String lastMoveDirectiveId = null;
     private String lastScanDirectiveId = null;
+    // directive_id is an idempotency key: a daemon resends unanswered
+    // directives after a reconnect, and none may run twice (v1.2+)
+    private final Set<String> seenDirectiveIds = ConcurrentHashMap.newKeySet();
+    // NPCs halted by FreezeNpcDirective, with the reason (v1.2+)
+    private final Map<String, String> frozenNpcs = new ConcurrentHashMap<>();
+    // Whether the daemon may change time and weather on this connection:
+    // offered in Hello, asked for in HelloAck (v1.2+)
+    private final boolean worldControlAvailable = false;
+    private volatile boolean worldControl = false;
+    // Latest daemon message, echoed in every MessageTiming so the daemon
+    // can sync clocks and measure both directions (v1.2+)
+    private volatile long echoSentAtMs = 0;
+    private volatile long echoReceivedAtMs = 0;
+    // A real plugin sends VoicePcmFrames here instead of on Connect once the
+    // daemon granted a ConnectAudio stream (v1.2+); null while audio
+    // shares Connect
+    private volatile StreamObserver<ClientMessage> audioStream = null;
     
     public ExampleClient(String host, int port) {
         this.channel = ManagedChannelBuilder.forAddress(host, port)
                 .usePlaintext() // Use TLS in production
                 .build();
-        this.asyncStub = NpcSocietyServiceGrpc.newStub(channel);
+        // Compress WorldTicks; the daemon must accept gzip (Rust example: --features compression)
+        this.asyncStub = NpcSocietyServiceGrpc.newStub(channel).withCompression("gzip");
     }
     
     /**
@@ -49,6 +71,10 @@ public class ExampleClient {
         StreamObserver<ServerMessage> responseObserver = new StreamObserver<>() {
             @Override
             public void onNext(ServerMessage message) {
+                if (message.hasTiming()) {
+                    echoSentAtMs = message.getTiming().getSentAtMs();
+                    echoReceivedAtMs = System.currentTimeMillis();
+                }
                 handleServerMessage(message);
             }
             
@@ -72,6 +98,9 @@ public class ExampleClient {
             // Example A: Send Hello handshake with v1.1+ fields
             sendHello(requestObserver);
             
+            // v1.2+: the world as it is, before the first WorldTick
+            sendWorldBootstrap(requestObserver);
+            
             // Simulate sending WorldTick updates at ~10Hz
             for (int i = 0; i < 50; i++) {
                 sendWorldTick(requestObserver, i);
@@ -100,8 +129,12 @@ public class ExampleClient {
             throw e;
         }
         
-        // Signal completion
+        // Signal completion; the audio stream ends with Connect
         requestObserver.onCompleted();
+        StreamObserver<ClientMessage> audio = audioStream;
+        if (audio != null) {
+            audio.onCompleted();
+        }
         
         // Wait for stream to finish
         if (!finishLatch.await(30, TimeUnit.SECONDS)) {
@@ -109,6 +142,18 @@ public class ExampleClient {
         }
     }
     
+    /**
+     * MessageTiming for a message sent now (v1.2+). Stamp right before
+     * onNext so time spent in the plugin does not count as network delay.
+     */
+    private MessageTiming timing() {
+        return MessageTiming.newBuilder()
+                .setSentAtMs(System.currentTimeMillis())
+                .setEchoSentAtMs(echoSentAtMs)
+                .setEchoReceivedAtMs(echoReceivedAtMs)
+                .build();
+    }
+    
     /**
      * Example A: Hello handshake with v1.1+ fields.
      */
@@ -122,16 +167,88 @@ public class ExampleClient {
                 .setVoiceAvailable(true)        // Simple Voice Chat is installed
                 .setServerName("Example Server") // Optional display name
                 .setDaemonMode("external")       // Diagnostics: daemon runs separately
+                // v1.2+ audio negotiation (daemon answers with HelloAck)
+                .addSupportedAudioFormats(PcmFormat.PCM_FORMAT_S16LE)
+                // v1.2+: only when the admin enabled world control in the config
+                .setWorldControlAvailable(worldControlAvailable)
+                // v1.2+ voice routing: Simple Voice Chat channels this plugin
+                // can play NPC speech on (groups via its group API)
+                .addSupportedDeliveries(SpeechDelivery.SPEECH_DELIVERY_SPATIAL)
+                .addSupportedDeliveries(SpeechDelivery.SPEECH_DELIVERY_DIRECT)
+                .addSupportedDeliveries(SpeechDelivery.SPEECH_DELIVERY_GROUP)
+                // v1.2+: audio may move to its own ConnectAudio stream
+                .setAudioStreamAvailable(true)
+                // v1.2+: a WorldBootstrap follows, so no ListLandmarks is needed
+                .setWorldBootstrapAvailable(true)
+                // v1.2+: NPC paths can be drawn for admins
+                .setDebugPathAvailable(true)
+                // v1.2+: Probes are echoed for the daemon's self-checks
+                .setProbeAvailable(true)
+                // v1.2+: NPCs' chunks can be kept loaded on request
+                .setKeepChunkLoadedAvailable(true)
                 .build();
         
         ClientMessage message = ClientMessage.newBuilder()
                 .setHello(hello)
+                .setTiming(timing())
                 .build();
         
         requestObserver.onNext(message);
         System.out.println("Sent Hello handshake (voice_available=true, server_name='Example Server')");
     }
     
+    /**
+     * Send every NPC, landmark, claim and world's clock and weather right
+     * after Hello (v1.2+), so the daemon does not have to wait for
+     * WorldTicks to learn about them.
+     */
+    private void sendWorldBootstrap(StreamObserver<ClientMessage> requestObserver) {
+        Position chest = Position.newBuilder()
+                .setWorld("world")
+                .setX(100.5)
+                .setY(64.0)
+                .setZ(-199.5)
+                .build();
+        // In real plugin: every managed NPC (loaded or not), the persisted
+        // landmarks and container claims, and regions in loaded chunks
+        WorldBootstrap bootstrap = WorldBootstrap.newBuilder()
+                .addNpcs(NpcSnapshot.newBuilder()
+                        .setNpcId("miner_01")
+                        .setPosition(chest)
+                        .setHealthNorm(1.0f)
+                        .build())
+                .addLandmarks(Landmark.newBuilder()
+                        .setName("miners_chest")
+                        .setPosition(chest)
+                        .setDescription("The chest by the mine entrance")
+                        .addTags("storage")
+                        .setSetBy("daemon")
+                        .build())
+                .addRegions(RegionClaim.newBuilder()
+                        .setRegionId("spawn")
+                        .setPlugin("WorldGuard")
+                        .setMin(BlockPosition.newBuilder().setWorld("world").setX(50).setY(0).setZ(-250))
+                        .setMax(BlockPosition.newBuilder().setWorld("world").setX(150).setY(255).setZ(-150))
+                        .setBuildAllowed(true)
+                        .setInteractAllowed(true)
+                        .build())
+                .addEnvironments(EnvironmentState.newBuilder()
+                        .setWorld("world")
+                        .setTimeOfDay(6000)
+                        .setDay(42)
+                        .build())
+                .setServerTick(0)
+                .setTimestampMs(System.currentTimeMillis())
+                .build();
+        
+        requestObserver.onNext(ClientMessage.newBuilder()
+                .setWorldBootstrap(bootstrap)
+                .setTiming(timing())
+                .build());
+        System.out.println("Sent WorldBootstrap (" + bootstrap.getNpcsCount() + " NPCs, "
+                + bootstrap.getLandmarksCount() + " landmarks)");
+    }
+    
     private void sendWorldTick(StreamObserver<ClientMessage> requestObserver, int tick) {
         // Create example NPC snapshot
         NpcSnapshot npc = NpcSnapshot.newBuilder()
@@ -144,12 +261,38 @@ public class ExampleClient {
                         .setZ(-200.5)
                         .setYaw(90.0f)
                         .setPitch(0.0f)
+                        .setDimension(Dimension.DIMENSION_OVERWORLD) // v1.2+
                         .build())
                 .setHealthNorm(1.0f)
                 .setInCombat(false)
                 .setHungerNorm(0.8f)
                 .setHeldItem("minecraft:diamond_pickaxe")
                 .setCurrentActivity("mining")
+                // v1.2+: held items and armor, with durability
+                .setEquipment(Equipment.newBuilder()
+                        .setMainHand(ItemStack.newBuilder()
+                                .setItemType("minecraft:diamond_pickaxe")
+                                .setQuantity(1)
+                                .setDamage(312)
+                                .setMaxDamage(1561))
+                        .setHead(ItemStack.newBuilder()
+                                .setItemType("minecraft:iron_helmet")
+                                .setQuantity(1)
+                                .setMaxDamage(165))
+                        .build())
+                .setXpLevel(12) // v1.2+
+                // v1.2+: halted by FreezeNpcDirective
+                .setFrozen(frozenNpcs.containsKey("miner_01"))
+                .setFreezeReason(frozenNpcs.getOrDefault("miner_01", ""))
+                // v1.2+: land claims around the NPC (e.g. from WorldGuard); this one trusts the miner
+                .addRegions(RegionClaim.newBuilder()
+                        .setRegionId("spawn")
+                        .setPlugin("WorldGuard")
+                        .setMin(BlockPosition.newBuilder().setWorld("world").setX(50).setY(0).setZ(-250))
+                        .setMax(BlockPosition.newBuilder().setWorld("world").setX(150).setY(255).setZ(-150))
+                        .setBuildAllowed(true)
+                        .setInteractAllowed(true)
+                        .build())
                 .build();
         
         // Create example player snapshot
@@ -169,17 +312,33 @@ public class ExampleClient {
                 .setSneaking(false)
                 .setSprinting(false)
                 .setGameMode("survival")
+                // v1.2+: operator status, reported permissions and armor
+                .setOp(false)
+                .addPermissions("npcsociety.vip")
+                .setEquipment(Equipment.newBuilder()
+                        .setMainHand(ItemStack.newBuilder()
+                                .setItemType("minecraft:diamond_pickaxe")
+                                .setQuantity(1))
+                        .setChest(ItemStack.newBuilder()
+                                .setItemType("minecraft:leather_chestplate")
+                                .setQuantity(1)))
                 .build();
         
         WorldTick worldTick = WorldTick.newBuilder()
                 .setServerTick(tick)
                 .setTimestampMs(System.currentTimeMillis())
+                // In real plugin: Bukkit.getTPS()[0] and Bukkit.getAverageTickTime()
+                .setTps(20.0f)
+                .setMspt(12.5f)
+                .setLoadedChunks(441)
+                .setEntityCount(312)
                 .addNpcs(npc)
                 .addNearbyPlayers(player)
                 .build();
         
         ClientMessage message = ClientMessage.newBuilder()
                 .setWorldTick(worldTick)
+                .setTiming(timing())
                 .build();
         
         requestObserver.onNext(message);
@@ -204,6 +363,7 @@ public class ExampleClient {
         
         ClientMessage message = ClientMessage.newBuilder()
                 .setChatObservation(chat)
+                .setTiming(timing())
                 .build();
         
         requestObserver.onNext(message);
@@ -256,6 +416,7 @@ public class ExampleClient {
                                 .setZ(-195)
                                 .build())
                         .setBlockType("minecraft:diamond_ore")
+                        .setMatchedBy("#minecraft:diamond_ores")
                         .build())
                 .addMatches(BlockMatch.newBuilder()
                         .setPosition(BlockPosition.newBuilder()
@@ -265,6 +426,7 @@ public class ExampleClient {
                                 .setZ(-198)
                                 .build())
                         .setBlockType("minecraft:deepslate_diamond_ore")
+                        .setMatchedBy("#minecraft:diamond_ores")
                         .build())
                 .addMatches(BlockMatch.newBuilder()
                         .setPosition(BlockPosition.newBuilder()
@@ -274,6 +436,7 @@ public class ExampleClient {
                                 .setZ(-200)
                                 .build())
                         .setBlockType("minecraft:diamond_ore")
+                        .setMatchedBy("#minecraft:diamond_ores")
                         .build())
                 .build();
         
@@ -313,14 +476,128 @@ public class ExampleClient {
         System.out.println("Sent failed BreakBlockResult: 'Block is out of reach'");
     }
     
+    /**
+     * Report that an NPC's chunk unloaded, parking its directives (v1.2+).
+     * Send the same with loaded=true when the chunk loads again.
+     */
+    private void sendChunkUnloaded(StreamObserver<ClientMessage> requestObserver,
+                                   String parkedDirectiveId) {
+        ChunkLoadObservation chunk = ChunkLoadObservation.newBuilder()
+                .setNpcId("miner_01")
+                .setLoaded(false)
+                .setWorld("world")
+                .setChunkX(62)
+                .setChunkZ(-13)
+                .addParkedDirectiveIds(parkedDirectiveId)
+                .setTimestampMs(System.currentTimeMillis())
+                .build();
+        
+        ClientMessage message = ClientMessage.newBuilder()
+                .setChunkLoad(chunk)
+                .build();
+        
+        requestObserver.onNext(message);
+        System.out.println("Sent ChunkLoadObservation: miner_01 parked in chunk 62,-13");
+    }
+    
+    /**
+     * Report that a courier finished the travel leg of a trip (v1.2+). The
+     * same goes out as each leg of a TransportItemsDirective starts and ends,
+     * then one TransportItemsResult when the run ends.
+     */
+    private void sendTransportProgress(StreamObserver<ClientMessage> requestObserver,
+                                       String transportDirectiveId) {
+        TransportProgress progress = TransportProgress.newBuilder()
+                .setDirectiveId(transportDirectiveId)
+                .setNpcId("miner_01")
+                .setStage(TransportStage.TRANSPORT_STAGE_TRAVEL)
+                .setFinished(true)
+                .setTrip(2)
+                .setCarried(64)
+                .setDelivered(64)
+                .setTimestampMs(System.currentTimeMillis())
+                .build();
+        
+        ClientMessage message = ClientMessage.newBuilder()
+                .setTransportProgress(progress)
+                .build();
+        
+        requestObserver.onNext(message);
+        System.out.println("Sent TransportProgress: miner_01 arrived on trip 2 carrying 64");
+    }
+    
+    /**
+     * Open the ConnectAudio stream the HelloAck granted (v1.2+). AudioChunks
+     * and VisemeTimelines arrive on it from now on and VoicePcmFrames are
+     * sent on it, so PCM never delays directives and results on Connect.
+     * If it fails or ends, audio goes back to Connect.
+     */
+    private void openAudioStream(String token) {
+        StreamObserver<ServerMessage> audioObserver = new StreamObserver<>() {
+            @Override
+            public void onNext(ServerMessage message) {
+                handleServerMessage(message);
+            }
+            
+            @Override
+            public void onError(Throwable t) {
+                System.err.println("Audio stream error, audio back on Connect: " + t.getMessage());
+                audioStream = null;
+            }
+            
+            @Override
+            public void onCompleted() {
+                System.out.println("Audio stream completed, audio back on Connect");
+                audioStream = null;
+            }
+        };
+        StreamObserver<ClientMessage> stream = asyncStub.connectAudio(audioObserver);
+        stream.onNext(ClientMessage.newBuilder()
+                .setAudioStreamHello(AudioStreamHello.newBuilder()
+                        .setServerId("example-server")
+                        .setToken(token)
+                        .build())
+                .setTiming(timing())
+                .build());
+        audioStream = stream;
+        System.out.println("Opened ConnectAudio stream");
+    }
+    
     private void handleServerMessage(ServerMessage message) {
+        // deadline_ms is on the daemon's clock; a real plugin would convert
+        // it with the offset from GetSessionInfo. Late replies are skipped.
+        long deadline = message.getTiming().getDeadlineMs();
+        if (deadline != 0 && System.currentTimeMillis() > deadline) {
+            System.out.println("Skipping " + message.getMessageCase() + " past its deadline");
+            return;
+        }
         switch (message.getMessageCase()) {
+            case HELLO_ACK -> {
+                HelloAck ack = message.getHelloAck();
+                worldControl = worldControlAvailable && ack.getWorldControl();
+                System.out.println("Received HelloAck: protocol=" + ack.getProtocolVersion()
+                        + ", voice_format=" + ack.getVoiceFormat()
+                        + ", playback_format=" + ack.getPlaybackFormat()
+                        + ", world_control=" + worldControl);
+                if (!ack.getAudioStreamToken().isEmpty()) {
+                    openAudioStream(ack.getAudioStreamToken());
+                }
+            }
             case ACTION_DIRECTIVE -> {
                 ActionDirective directive = message.getActionDirective();
                 System.out.println("Received ActionDirective: id=" + directive.getDirectiveId() 
                         + ", npc=" + directive.getNpcId()
                         + ", action=" + directive.getActionCase());
                 
+                // In real plugin: resend the stored ActionResult with replayed=true
+                // if the first copy finished
+                if (!seenDirectiveIds.add(directive.getDirectiveId())) {
+                    System.out.println("  duplicate directive_id, not running it again");
+                    return;
+                }
+                
+                // In real plugin: send a DirectiveAck with the NPC's queue position
+                
                 // Track directive IDs for sending results
                 switch (directive.getActionCase()) {
                     case MOVE -> lastMoveDirectiveId = directive.getDirectiveId();
@@ -338,6 +615,14 @@ public class ExampleClient {
                 System.out.println("  npc_id: " + speak.getNpcId());
                 System.out.println("  text: " + speak.getText());
                 System.out.println("  emotion: " + speak.getEmotion());
+                // v1.2+ canonical emotion: animate from this, not the free text
+                if (speak.getEmotionType() != Emotion.EMOTION_UNSPECIFIED) {
+                    System.out.println("  emotion_type: " + speak.getEmotionType());
+                }
+                // v1.2+ language of the text, for subtitles and fonts
+                if (!speak.getLocale().isEmpty()) {
+                    System.out.println("  locale: " + speak.getLocale());
+                }
                 System.out.println("  duration_ms: " + speak.getDurationMs());
                 
                 // v1.1+ correlation fields
@@ -354,7 +639,25 @@ public class ExampleClient {
                     System.out.println("  stream_id: " + speak.getStreamId() + " (expect matching AudioChunks)");
                 }
                 
-                // In real plugin: display subtitle
+                // v1.2+ addressing: only targets see/hear it when set
+                if (speak.getTargetPlayerUuidsCount() > 0) {
+                    System.out.println("  targets: " + speak.getTargetPlayerUuidsList()
+                            + " (" + speak.getDelivery() + ")");
+                }
+                switch (speak.getDelivery()) {
+                    case SPEECH_DELIVERY_GROUP -> System.out.println("  group: " + speak.getGroup());
+                    case SPEECH_DELIVERY_GLOBAL -> System.out.println("  global broadcast");
+                    default -> {
+                        if (speak.getRange() > 0) {
+                            System.out.println("  range: " + speak.getRange() + " blocks");
+                        }
+                    }
+                }
+                
+                // In real plugin: display subtitle, and play the stream on a
+                // locational channel at the NPC (distance = range), a static
+                // channel per target (DIRECT), the group's channel (GROUP) or
+                // a static channel per online player (GLOBAL)
             }
             case AUDIO_CHUNK -> {
                 AudioChunk audio = message.getAudioChunk();
@@ -372,7 +675,405 @@ public class ExampleClient {
                     System.out.println("  directive_id: " + audio.getDirectiveId() + " (correlates with SpeakDirective)");
                 }
                 
-                // In real plugin: queue audio for Simple Voice Chat playback
+                // In real plugin: queue audio for Simple Voice Chat playback,
+                // and while the stream plays report the queue about every
+                // 250ms (and on every underrun) so the daemon can pace it:
+                //   AudioBufferStatus.newBuilder().setNpcId(audio.getNpcId())
+                //       .setStreamId(audio.getStreamId()).setBufferedMs(queuedMs)
+                //       .setPlayedMs(playedMs).setUnderruns(underruns)
+                //       .setTimestampMs(System.currentTimeMillis())
+            }
+            case STOP_SPEAKING -> {
+                StopSpeaking stop = message.getStopSpeaking();
+                System.out.println("Received StopSpeaking: npc=" + stop.getNpcId()
+                        + ", stream_id=" + (stop.getStreamId().isEmpty() ? "(all)" : stop.getStreamId()));
+                
+                // In real plugin: stop playback and drop buffered AudioChunks
+            }
+            case NPC_MESSAGE -> {
+                NpcMessage npcMessage = message.getNpcMessage();
+                System.out.println("Received NpcMessage: " + npcMessage.getSenderNpcId()
+                        + " -> " + (npcMessage.getRecipientNpcId().isEmpty() ? "(all)" : npcMessage.getRecipientNpcId())
+                        + ", topic=" + npcMessage.getTopic());
+                
+                // In real plugin: set timestamp_ms and forward it as a
+                // ClientMessage to every daemon controlling a recipient
+            }
+            case QUEST_OFFER -> {
+                QuestOffer offer = message.getQuestOffer();
+                System.out.println("Received QuestOffer: \"" + offer.getTitle() + "\" from " + offer.getNpcId()
+                        + " for " + offer.getPlayerUuid() + " (" + offer.getObjectivesCount() + " objectives)");
+                
+                // In real plugin: show the offer, track the objectives once
+                // accepted, grant the rewards and report QuestUpdates
+            }
+            case TRANSFER_CURRENCY -> {
+                TransferCurrencyDirective transfer = message.getTransferCurrency();
+                System.out.println("Received TransferCurrencyDirective: npc=" + transfer.getNpcId()
+                        + ", player=" + transfer.getPlayerUuid()
+                        + ", " + transfer.getDirection() + " " + transfer.getAmount()
+                        + " (" + transfer.getReason() + ")");
+                
+                // In real plugin: move the money through Vault and answer
+                // with a TransactionObservation
+            }
+            case SUBSCRIBE_EVENTS -> {
+                SubscribeEvents subscription = message.getSubscribeEvents();
+                System.out.println("Received SubscribeEvents: types="
+                        + (subscription.getEventTypesCount() == 0 ? "(all)" : subscription.getEventTypesList())
+                        + ", npcs=" + (subscription.getNpcIdsCount() == 0 ? "(all)" : subscription.getNpcIdsList()));
+                
+                // In real plugin: replace the connection's event filter and
+                // skip non-matching events before building EventObservations
+            }
+            case RESTORE_NPC_STATE -> {
+                RestoreNpcState restore = message.getRestoreNpcState();
+                System.out.println("Received RestoreNpcState: npc=" + restore.getNpcId()
+                        + (restore.hasPosition() ? ", to " + restore.getPosition().getWorld() : "")
+                        + ", " + restore.getInventoryCount() + " inventory stacks");
+                
+                // In real plugin: teleport the NPC and set its equipment,
+                // inventory and experience; unset fields stay as they are
+            }
+            case PREPARE_NPC_TRANSFER -> {
+                PrepareNpcTransfer prepare = message.getPrepareNpcTransfer();
+                System.out.println("Received PrepareNpcTransfer: transfer=" + prepare.getTransferId()
+                        + ", npc=" + prepare.getNpcId()
+                        + ", to " + prepare.getTargetServerId());
+                
+                // In real plugin: freeze the NPC (no AI, invulnerable) and send
+                // NpcTransferUpdate PREPARED with its snapshot and inventory
+            }
+            case SPAWN_TRANSFERRED_NPC -> {
+                SpawnTransferredNpc spawn = message.getSpawnTransferredNpc();
+                System.out.println("Received SpawnTransferredNpc: transfer=" + spawn.getTransferId()
+                        + ", npc=" + spawn.getSnapshot().getNpcId()
+                        + ", from " + spawn.getSourceServerId());
+                
+                // In real plugin: spawn the NPC frozen from the snapshot and
+                // send NpcTransferUpdate SPAWNED, or FAILED if it cannot
+            }
+            case FINISH_NPC_TRANSFER -> {
+                FinishNpcTransfer finish = message.getFinishNpcTransfer();
+                System.out.println("Received FinishNpcTransfer: transfer=" + finish.getTransferId()
+                        + ", npc=" + finish.getNpcId()
+                        + (finish.getCommit() ? ", commit" : ", roll back"));
+                
+                // In real plugin: on the source remove the NPC (commit) or
+                // unfreeze it; on the target unfreeze the copy (commit) or
+                // despawn it. Answer COMMITTED or ROLLED_BACK either way.
+            }
+            case CHAT -> {
+                ChatDirective chat = message.getChat();
+                System.out.println("Received ChatDirective: players=" + chat.getPlayerUuidsList()
+                        + ", text=" + chat.getText());
+                
+                // In real plugin: send each line to each online player in
+                // player_uuids, prefixed with the NPC's name if npc_id is set
+            }
+            case FREEZE_NPC -> {
+                FreezeNpcDirective freeze = message.getFreezeNpc();
+                frozenNpcs.put(freeze.getNpcId(), freeze.getReason());
+                System.out.println("Received FreezeNpcDirective: npc=" + freeze.getNpcId()
+                        + ", reason=" + freeze.getReason());
+                
+                // In real plugin: stop the current action (it starts over on
+                // resume), pathing, combat policy and vanilla AI, and hold
+                // ActionDirectives in the queue until ResumeNpcDirective
+            }
+            case RESUME_NPC -> {
+                ResumeNpcDirective resume = message.getResumeNpc();
+                frozenNpcs.remove(resume.getNpcId());
+                System.out.println("Received ResumeNpcDirective: npc=" + resume.getNpcId()
+                        + (resume.getDiscardHeld() ? ", discarding held directives" : ""));
+                
+                // In real plugin: run the held directives in order, or fail
+                // each with "discarded on resume" if discard_held is set
+            }
+            case CHOREOGRAPHY -> {
+                ChoreographyDirective scene = message.getChoreography();
+                System.out.println("Received ChoreographyDirective: id=" + scene.getDirectiveId()
+                        + ", steps=" + scene.getStepsCount()
+                        + (scene.getAbortOnFailure() ? ", abort on failure" : ""));
+                for (ChoreographyStep step : scene.getStepsList()) {
+                    String npc = step.hasAction() ? step.getAction().getNpcId() : step.getSpeak().getNpcId();
+                    System.out.println("  +" + step.getAtMs() + " ms " + npc + ": " + step.getStepCase()
+                            + (step.getAfterPrevious() ? " (after previous)" : ""));
+                }
+                
+                // In real plugin: start at start_at_ms (or now), run each step
+                // on the main thread at its offset, then send one
+                // ChoreographyResult with a ChoreographyStepResult per step
+            }
+            case DIALOGUE_OPTIONS -> {
+                DialogueOptionsDirective prompt = message.getDialogueOptions();
+                System.out.println("Received DialogueOptionsDirective: prompt=" + prompt.getPromptId()
+                        + ", npc=" + prompt.getNpcId() + ", player=" + prompt.getPlayerUuid());
+                for (DialogueOption option : prompt.getOptionsList()) {
+                    System.out.println("  [" + option.getLabel() + "] -> " + option.getOptionId());
+                }
+                
+                // In real plugin: send the options to the player as clickable
+                // chat components (or open a GUI) whose click answers with a
+                // DialogueChoiceObservation; on expiry or a newer prompt for
+                // the same NPC and player, answer with an empty option_id
+            }
+            case SHOP -> {
+                ShopDefinition shop = message.getShop();
+                System.out.println("Received ShopDefinition: shop=" + shop.getShopId()
+                        + ", npc=" + shop.getNpcId() + ", listings=" + shop.getListingsCount());
+                for (ShopListing listing : shop.getListingsList()) {
+                    System.out.println("  " + listing.getListingId() + ": " + listing.getItem().getQuantity()
+                            + "x " + listing.getItem().getItemType() + " buy=" + listing.getBuyPrice()
+                            + " sell=" + listing.getSellPrice() + " stock=" + listing.getStock());
+                }
+                
+                // In real plugin: replace the NPC's shop (or close it if there
+                // are no listings), open the chest GUI for open_for_player_uuids,
+                // and on each click charge or pay through Vault, move the items,
+                // update the stock and send a ShopTradeObservation
+            }
+            case SHOW_DISPLAY -> {
+                ShowDisplayDirective display = message.getShowDisplay();
+                System.out.println("Received ShowDisplayDirective: id=" + display.getDisplayId()
+                        + ", npc=" + display.getNpcId() + ", kind=" + display.getDisplayCase()
+                        + (display.getPlayerUuidsCount() > 0 ? ", players=" + display.getPlayerUuidsList() : ""));
+                
+                // In real plugin: create or update the text display entity
+                // (hologram), BossBar or sidebar Scoreboard under display_id,
+                // visible to player_uuids or to players within range
+            }
+            case REMOVE_DISPLAY -> {
+                RemoveDisplayDirective remove = message.getRemoveDisplay();
+                System.out.println("Received RemoveDisplayDirective: id=" + remove.getDisplayId());
+                
+                // In real plugin: remove the display; ignore unknown ids
+            }
+            case PLAY_PARTICLE -> {
+                PlayParticleDirective particle = message.getPlayParticle();
+                System.out.println("Received PlayParticleDirective: " + particle.getParticle()
+                        + " x" + Math.max(1, particle.getCount()) + " at "
+                        + (particle.hasPosition() ? "position" : "npc=" + particle.getNpcId()));
+                
+                // In real plugin: World.spawnParticle at the position (or the
+                // NPC's body) with the spread and speed; skip unknown ids
+            }
+            case PLAY_SOUND -> {
+                PlaySoundDirective sound = message.getPlaySound();
+                System.out.println("Received PlaySoundDirective: " + sound.getSound() + " at "
+                        + (sound.hasPosition() ? "position" : "npc=" + sound.getNpcId()));
+                
+                // In real plugin: World.playSound (or Player.playSound for
+                // player_uuids) with volume, pitch and category, 0 = defaults
+            }
+            case SET_TIME -> {
+                SetTimeDirective time = message.getSetTime();
+                System.out.println("Received SetTimeDirective: id=" + time.getDirectiveId()
+                        + ", time_of_day=" + time.getTimeOfDay() + ", reason=" + time.getReason()
+                        + (worldControl ? "" : " (not permitted)"));
+                
+                // In real plugin: without world control, answer with
+                // DirectiveRejected(REJECTION_CODE_NOT_PERMITTED); otherwise
+                // World.setTime on world (or the NPC's world) and log the reason
+            }
+            case SET_WEATHER -> {
+                SetWeatherDirective weather = message.getSetWeather();
+                System.out.println("Received SetWeatherDirective: id=" + weather.getDirectiveId()
+                        + ", weather=" + weather.getWeather() + ", reason=" + weather.getReason()
+                        + (worldControl ? "" : " (not permitted)"));
+                
+                // In real plugin: same permission check as SET_TIME, then
+                // World.setStorm / setThundering with duration_ticks (0 = the
+                // server picks), or clear weather for WEATHER_CLEAR
+            }
+            case REGISTER_AUDIO_ASSET -> {
+                RegisterAudioAsset asset = message.getRegisterAudioAsset();
+                System.out.println("Received RegisterAudioAsset: asset=" + asset.getAssetId()
+                        + ", hash=" + asset.getHash() + ", bytes=" + asset.getPcmData().size()
+                        + ", duration_ms=" + asset.getDurationMs());
+                
+                // In real plugin: store the PCM under asset_id (replacing an
+                // older hash), on disk if it should survive restarts, and
+                // list it in Hello.cached_audio_assets next time
+            }
+            case PLAY_AUDIO_ASSET -> {
+                PlayAudioAssetDirective play = message.getPlayAudioAsset();
+                System.out.println("Received PlayAudioAssetDirective: asset=" + play.getAssetId()
+                        + ", npc=" + play.getSpeak().getNpcId() + ", text='" + play.getSpeak().getText() + "'");
+                
+                // In real plugin: if the cached hash differs or the asset is
+                // unknown, send DirectiveRejected (REJECTION_CODE_ASSET_MISSING,
+                // directive_id of speak); otherwise show the subtitle, play the
+                // clip routed like speak, and send a SpeakResult
+            }
+            case PLAY_MUSIC -> {
+                PlayMusicDirective music = message.getPlayMusic();
+                System.out.println("Received PlayMusicDirective: npc=" + music.getNpcId()
+                        + ", title='" + music.getTitle() + "', notes=" + music.getNotesCount()
+                        + ", length_ms=" + music.getLengthMs() + (music.getRepeat() ? " (repeat)" : ""));
+                
+                // In real plugin: cancel the NPC's current song, then schedule
+                // each note at at_ms (ticks are 50ms, so use an async timer for
+                // finer timing) as the instrument's note block sound at pitch
+                // 2^((pitch - 12) / 12); with repeat, start over after length_ms
+            }
+            case FORMATION -> {
+                FormationDirective formation = message.getFormation();
+                String leader = formation.hasLeaderNpcId()
+                        ? formation.getLeaderNpcId() : formation.getLeaderPlayerUuid();
+                System.out.println("Received FormationDirective: id=" + formation.getFormationId()
+                        + ", leader=" + leader + ", shape=" + formation.getShape()
+                        + ", members=" + formation.getMembersCount());
+                
+                // In real plugin: with no members, drop the formation and let
+                // its NPCs stop. Otherwise replace any formation with this id,
+                // and each tick path every member whose place (relative to the
+                // leader's position and yaw) is more than tolerance blocks away
+                // back to it, following when the leader teleports or changes world
+            }
+            case GUARD_ZONE -> {
+                GuardZoneDirective zone = message.getGuardZone();
+                System.out.println("Received GuardZoneDirective: npc=" + zone.getNpcId()
+                        + ", zone=" + zone.getZoneId() + (zone.hasFrom() ? "" : " (lifted)")
+                        + ", allowed=" + zone.getAllowedPlayersCount()
+                        + ", alert_radius=" + zone.getAlertRadius());
+                
+                // In real plugin: walk the NPC between random points of the
+                // cuboid. When a player not in allowed_players comes within
+                // alert_radius (0 = 16) of it inside the zone, send an
+                // IntruderObservation (INTRUDER_TRIGGER_SPOTTED), and another
+                // (INTRUDER_TRIGGER_LEFT) when they leave, die or log out.
+                // Do not act on intruders; the daemon decides
+            }
+            case CLAIM_CONTAINER -> {
+                ClaimContainerDirective claim = message.getClaimContainer();
+                System.out.println("Received ClaimContainerDirective: npc=" + claim.getNpcId()
+                        + ", at=" + claim.getPosition().getX() + "," + claim.getPosition().getY()
+                        + "," + claim.getPosition().getZ()
+                        + (claim.getRelease() ? " (released)" : ", name=" + claim.getName()
+                                + ", locked=" + claim.getLocked()
+                                + ", allowed=" + claim.getAllowedPlayersCount()));
+                
+                // In real plugin: reject a position without a container as
+                // MALFORMED. Otherwise store the claim in the block's
+                // PersistentDataContainer (or remove it on release) and use
+                // name as the inventory title. Cancel InventoryOpenEvent and
+                // BlockBreakEvent for players outside allowed_players while
+                // locked, sending CONTAINER_ACCESS_DENIED; otherwise report
+                // OPENED, then TOOK and PUT per item type on close, and BROKE
+                // with the spilled items. Fail other NPCs' deposits and
+                // withdrawals with ACTION_ERROR_CODE_CONTAINER_LOCKED
+            }
+            case REGISTER_LANDMARK -> {
+                RegisterLandmark register = message.getRegisterLandmark();
+                System.out.println("Received RegisterLandmark: " + register.getLandmark().getName()
+                        + (register.getRemove() ? " (removed)" : ""));
+                
+                // In real plugin: store or delete the landmark in the
+                // plugin's data folder with set_by "daemon" and the time,
+                // then push a LandmarkList with just this change to every
+                // connected daemon (the same as for /landmark commands)
+            }
+            case LIST_LANDMARKS -> {
+                ListLandmarks list = message.getListLandmarks();
+                System.out.println("Received ListLandmarks: request=" + list.getRequestId()
+                        + ", tag=" + list.getTag() + ", world=" + list.getWorld());
+                
+                // In real plugin: answer with a LandmarkList of the matching
+                // landmarks, complete=true when there was no filter
+            }
+            case REQUEST_VOICE_CAPTURE -> {
+                RequestVoiceCapture request = message.getRequestVoiceCapture();
+                System.out.println("Received RequestVoiceCapture: npc=" + request.getNpcId()
+                        + ", players=" + request.getPlayerUuidsList()
+                        + ", max_duration=" + request.getMaxDurationMs() + "ms");
+                
+                // In real plugin: start forwarding the named players' Simple
+                // Voice Chat audio near the NPC (everyone nearby if none are
+                // named), skipping players without VOICE_CONSENT_GRANTED, and
+                // stop by itself after max_duration_ms
+            }
+            case STOP_VOICE_CAPTURE -> {
+                StopVoiceCapture stop = message.getStopVoiceCapture();
+                System.out.println("Received StopVoiceCapture: npc=" + stop.getNpcId()
+                        + ", players=" + stop.getPlayerUuidsList());
+                
+                // In real plugin: stop forwarding those players' voice to the
+                // NPC (all of them if none are named)
+            }
+            case QUARANTINE_NPC -> {
+                QuarantineNpcDirective quarantine = message.getQuarantineNpc();
+                System.out.println("Received QuarantineNpcDirective: npc=" + quarantine.getNpcId()
+                        + ", quarantined=" + quarantine.getQuarantined()
+                        + ", reason=" + quarantine.getReason()
+                        + ", flags=" + quarantine.getFlagIdsList());
+                
+                // In real plugin: on quarantine, stop the NPC's speech and
+                // freeze it, then reject its Speak, Chat, audio and Action
+                // directives with REJECTION_CODE_QUARANTINED and report
+                // NpcSnapshot.quarantined until released. On release, resume
+                // it, dropping what was held. Persist the state across
+                // daemon reconnects.
+            }
+            case DEBUG_PATH -> {
+                DebugPathDirective debugPath = message.getDebugPath();
+                System.out.println("Received DebugPathDirective: npc=" + debugPath.getNpcId()
+                        + ", viewer=" + debugPath.getViewerUuid()
+                        + ", enabled=" + debugPath.getEnabled()
+                        + ", style=" + debugPath.getStyle());
+                
+                // In real plugin: if the viewer may see paths, draw the
+                // NPC's current path for them only (particles, or fake
+                // glass blocks sent with sendBlockChange) and redraw on every
+                // replan until disabled; put fake blocks back when it ends
+            }
+            case PROBE -> {
+                Probe probe = message.getProbe();
+                long receivedAtMs = System.currentTimeMillis();
+                ProbeReply reply = ProbeReply.newBuilder()
+                        .setNonce(probe.getNonce())
+                        .setSequence(probe.getSequence())
+                        .setPayload(probe.getPayload())
+                        .setProbeSentAtMs(probe.getSentAtMs())
+                        .setReceivedAtMs(receivedAtMs)
+                        .setSentAtMs(System.currentTimeMillis())
+                        .build();
+                System.out.println("Received Probe: nonce=" + probe.getNonce()
+                        + ", sequence=" + probe.getSequence()
+                        + ", payload=" + probe.getPayload().size() + " bytes");
+                
+                // In real plugin: send the reply on Connect at once, ahead
+                // of queued messages, in the order the Probes arrived
+            }
+            case KEEP_CHUNK_LOADED -> {
+                KeepChunkLoadedDirective keep = message.getKeepChunkLoaded();
+                System.out.println("Received KeepChunkLoadedDirective: npc=" + keep.getNpcId()
+                        + ", keep_loaded=" + keep.getKeepLoaded()
+                        + ", radius=" + keep.getRadiusChunks()
+                        + ", duration_ms=" + keep.getDurationMs()
+                        + ", reason=" + keep.getReason());
+                
+                // In real plugin: if server policy allows, add a plugin
+                // chunk ticket around the NPC (World.addPluginChunkTicket)
+                // and follow it as it moves, removing the tickets when
+                // released or expired; ack, or reject with
+                // REJECTION_CODE_NOT_PERMITTED past the force-load limit
+            }
+            case TRANSPORT_ITEMS -> {
+                TransportItemsDirective haul = message.getTransportItems();
+                BlockPosition from = haul.getFromContainer();
+                BlockPosition to = haul.getToContainer();
+                System.out.println("Received TransportItemsDirective: id=" + haul.getDirectiveId()
+                        + ", npc=" + haul.getNpcId()
+                        + ", from=" + from.getX() + "," + from.getY() + "," + from.getZ()
+                        + ", to=" + to.getX() + "," + to.getY() + "," + to.getZ()
+                        + ", items=" + haul.getItemTypesList()
+                        + ", vehicle=" + haul.getRouteHint().getVehicle());
+                
+                // In real plugin: ack, hold the NPC's other directives, then
+                // loop withdraw, travel, deposit and return as the steps of a
+                // scene, sending a TransportProgress as each leg starts and
+                // ends; finish with one TransportItemsResult listing the legs
             }
             default -> System.out.println("Unknown message type: " + message.getMessageCase());
         }
@@ -0,0 +1,288 @@
+//! WorldTick throughput of HTTP/2 window settings on LAN and WAN links.
+//!
+//! Run with `cargo bench --bench http2_window`. Streams busy WorldTicks
+//! over Connect through a local proxy that delays every byte by half the
+//! round trip each way, so the only limit is how much the windows let the
+//! plugin have in flight. Loopback has no bandwidth limit: on a real link
+//! the smaller of this and the bandwidth is what a server gets.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::{Channel, Server};
+use tonic::{Request, Response, Status, Streaming};
+
+use npc_society_example::http2::Http2Config;
+use npc_society_example::npc_society::v1::npc_society_service_client::NpcSocietyServiceClient;
+use npc_society_example::npc_society::v1::npc_society_service_server::{
+    NpcSocietyService, NpcSocietyServiceServer,
+};
+use npc_society_example::npc_society::v1::*;
+use prost::Message;
+
+const NPCS: usize = 300;
+const TICKS: usize = 60;
+
+fn busy_tick() -> ClientMessage {
+    let position = |i: usize| {
+        Some(Position {
+            world: "world".to_string(),
+            x: i as f64,
+            y: 64.0,
+            z: -(i as f64),
+            ..Default::default()
+        })
+    };
+    let tick = WorldTick {
+        server_tick: 1200,
+        timestamp_ms: 1_700_000_000_000,
+        npcs: (0..NPCS)
+            .map(|i| NpcSnapshot {
+                npc_id: format!("villager_{}", i),
+                entity_uuid: format!("00000000-0000-0000-0000-{:012}", i),
+                position: position(i),
+                health_norm: 1.0,
+                held_item: "minecraft:iron_pickaxe".to_string(),
+                current_activity: "mining".to_string(),
+                ..Default::default()
+            })
+            .collect(),
+        nearby_players: (0..20)
+            .map(|i| PlayerSnapshot {
+                player_uuid: format!("10000000-0000-0000-0000-{:012}", i),
+                player_name: format!("player{}", i),
+                position: position(i),
+                game_mode: "survival".to_string(),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    };
+    ClientMessage {
+        message: Some(client_message::Message::WorldTick(tick)),
+        ..Default::default()
+    }
+}
+
+/// Reads every message of Connect and answers once the plugin is done
+struct Sink;
+
+type ServerStream = Pin<Box<dyn Stream<Item = Result<ServerMessage, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl NpcSocietyService for Sink {
+    type ConnectStream = ServerStream;
+    type ConnectAudioStream = ServerStream;
+
+    async fn connect(
+        &self,
+        request: Request<Streaming<ClientMessage>>,
+    ) -> Result<Response<ServerStream>, Status> {
+        let mut messages = request.into_inner();
+        let (tx, rx) = mpsc::channel(1);
+        tokio::spawn(async move {
+            while let Some(Ok(_)) = messages.next().await {}
+            let _ = tx.send(Ok(ServerMessage::default())).await;
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn connect_audio(
+        &self,
+        _: Request<Streaming<ClientMessage>>,
+    ) -> Result<Response<ServerStream>, Status> {
+        Err(Status::unimplemented("connect_audio"))
+    }
+
+    async fn get_snapshot(
+        &self,
+        _: Request<GetSnapshotRequest>,
+    ) -> Result<Response<GetSnapshotResponse>, Status> {
+        Err(Status::unimplemented("get_snapshot"))
+    }
+
+    async fn list_npcs(
+        &self,
+        _: Request<ListNpcsRequest>,
+    ) -> Result<Response<ListNpcsResponse>, Status> {
+        Err(Status::unimplemented("list_npcs"))
+    }
+
+    async fn get_npc_state(
+        &self,
+        _: Request<GetNpcStateRequest>,
+    ) -> Result<Response<GetNpcStateResponse>, Status> {
+        Err(Status::unimplemented("get_npc_state"))
+    }
+
+    async fn list_pending_directives(
+        &self,
+        _: Request<ListPendingDirectivesRequest>,
+    ) -> Result<Response<ListPendingDirectivesResponse>, Status> {
+        Err(Status::unimplemented("list_pending_directives"))
+    }
+
+    async fn get_session_info(
+        &self,
+        _: Request<GetSessionInfoRequest>,
+    ) -> Result<Response<GetSessionInfoResponse>, Status> {
+        Err(Status::unimplemented("get_session_info"))
+    }
+
+    async fn list_servers(
+        &self,
+        _: Request<ListServersRequest>,
+    ) -> Result<Response<ListServersResponse>, Status> {
+        Err(Status::unimplemented("list_servers"))
+    }
+
+    async fn send_directive(
+        &self,
+        _: Request<SendDirectiveRequest>,
+    ) -> Result<Response<SendDirectiveResponse>, Status> {
+        Err(Status::unimplemented("send_directive"))
+    }
+
+    async fn export_npc_state(
+        &self,
+        _: Request<ExportNpcStateRequest>,
+    ) -> Result<Response<ExportNpcStateResponse>, Status> {
+        Err(Status::unimplemented("export_npc_state"))
+    }
+
+    async fn import_npc_state(
+        &self,
+        _: Request<ImportNpcStateRequest>,
+    ) -> Result<Response<ImportNpcStateResponse>, Status> {
+        Err(Status::unimplemented("import_npc_state"))
+    }
+
+    async fn transfer_npc(
+        &self,
+        _: Request<TransferNpcRequest>,
+    ) -> Result<Response<TransferNpcResponse>, Status> {
+        Err(Status::unimplemented("transfer_npc"))
+    }
+}
+
+/// Copy one direction of a connection, each chunk `delay` late
+async fn delayed_copy(mut from: OwnedReadHalf, mut to: OwnedWriteHalf, delay: Duration) {
+    let (tx, mut rx) = mpsc::unbounded_channel::<(Instant, Vec<u8>)>();
+    tokio::spawn(async move {
+        let mut buf = vec![0; 64 * 1024];
+        while let Ok(n @ 1..) = from.read(&mut buf).await {
+            if tx
+                .send((Instant::now() + delay, buf[..n].to_vec()))
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+    while let Some((due, chunk)) = rx.recv().await {
+        tokio::time::sleep_until(due.into()).await;
+        if to.write_all(&chunk).await.is_err() {
+            break;
+        }
+    }
+    let _ = to.shutdown().await;
+}
+
+/// A proxy to `upstream` with this round trip; returns its address
+async fn latency_proxy(upstream: SocketAddr, rtt: Duration) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((client, _)) = listener.accept().await {
+            let server = TcpStream::connect(upstream).await.unwrap();
+            client.set_nodelay(true).unwrap();
+            server.set_nodelay(true).unwrap();
+            let (client_read, client_write) = client.into_split();
+            let (server_read, server_write) = server.into_split();
+            tokio::spawn(delayed_copy(client_read, server_write, rtt / 2));
+            tokio::spawn(delayed_copy(server_read, client_write, rtt / 2));
+        }
+    });
+    addr
+}
+
+/// Time to stream TICKS WorldTicks to a daemon `rtt` away, with `http2` on
+/// both ends (None: tonic's defaults)
+async fn stream_ticks(http2: Option<Http2Config>, rtt: Duration, tick: &ClientMessage) -> Duration {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut server = match http2 {
+        Some(http2) => http2.server(Server::builder()),
+        None => Server::builder(),
+    };
+    let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+    let serving = tokio::spawn(
+        server
+            .add_service(NpcSocietyServiceServer::new(Sink))
+            .serve_with_incoming(incoming),
+    );
+
+    let proxy = latency_proxy(addr, rtt).await;
+    let endpoint = Channel::from_shared(format!("http://{}", proxy)).unwrap();
+    let endpoint = match http2 {
+        Some(http2) => http2.endpoint(endpoint),
+        None => endpoint,
+    };
+    let mut client = NpcSocietyServiceClient::new(endpoint.connect().await.unwrap())
+        .max_decoding_message_size(usize::MAX)
+        .max_encoding_message_size(usize::MAX);
+
+    let ticks = tokio_stream::iter(std::iter::repeat_n(tick.clone(), TICKS));
+    let start = Instant::now();
+    let mut replies = client.connect(ticks).await.unwrap().into_inner();
+    replies.next().await.unwrap().unwrap();
+    let elapsed = start.elapsed();
+    serving.abort();
+    elapsed
+}
+
+#[tokio::main]
+async fn main() {
+    let tick = busy_tick();
+    let size = tick.encoded_len();
+    println!(
+        "{} WorldTicks with {} NPCs and 20 players ({} bytes each)",
+        TICKS, NPCS, size
+    );
+    let windows_64k = Http2Config {
+        stream_window_bytes: 65_535,
+        connection_window_bytes: 65_535,
+        ..Http2Config::lan()
+    };
+    let settings = [
+        ("h2 default (64 KiB)", Some(windows_64k)),
+        ("tonic default", None),
+        ("Http2Config::lan()", Some(Http2Config::lan())),
+        ("Http2Config::wan()", Some(Http2Config::wan())),
+    ];
+    for (link, rtt) in [("LAN", 1), ("WAN", 80), ("WAN", 150)] {
+        let rtt = Duration::from_millis(rtt);
+        println!("{} ({:?} round trip)", link, rtt);
+        for (name, http2) in settings {
+            let elapsed = stream_ticks(http2, rtt, &tick).await;
+            // The last tick's round trip is not throughput
+            let streaming = elapsed.saturating_sub(rtt).max(Duration::from_micros(1));
+            let per_second = TICKS as f64 / streaming.as_secs_f64();
+            println!(
+                "  {:<20} {:>9.1?}  {:>8.1} ticks/s  {:>7.1} MB/s",
+                name,
+                elapsed,
+                per_second,
+                per_second * size as f64 / 1e6
+            );
+        }
+    }
+}
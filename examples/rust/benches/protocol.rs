@@ -0,0 +1,143 @@
+//! Criterion suite for the hot paths: WorldTick encode/decode at several
+//! NPC counts, audio chunking and encoding, and event dispatch.
+//!
+//! Run with `cargo bench --bench protocol`; compare against a saved
+//! baseline with `-- --save-baseline main` / `-- --baseline main`.
+
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use npc_society_example::connection::ConnectionContext;
+use npc_society_example::events::{self, ClientEvent, NpcSocietyHandler};
+use npc_society_example::npc_society::v1::{
+    client_message::Message as ClientMsg, server_message::Message as ServerMsg, ChatObservation,
+    ClientMessage, NpcSnapshot, Position, ServerMessage, SpeakDirective, WorldTick,
+};
+use npc_society_example::outbound::{self, Outbound, QueueConfig};
+use npc_society_example::tts::{self, SynthesizedAudio};
+use prost::Message;
+
+fn world_tick(npcs: usize) -> ClientMessage {
+    let tick = WorldTick {
+        server_tick: 1200,
+        timestamp_ms: 1_700_000_000_000,
+        npcs: (0..npcs)
+            .map(|i| NpcSnapshot {
+                npc_id: format!("villager_{}", i),
+                entity_uuid: format!("00000000-0000-0000-0000-{:012}", i),
+                position: Some(Position {
+                    world: "world".to_string(),
+                    x: i as f64,
+                    y: 64.0,
+                    z: -(i as f64),
+                    ..Default::default()
+                }),
+                health_norm: 1.0,
+                held_item: "minecraft:iron_pickaxe".to_string(),
+                current_activity: "mining".to_string(),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    };
+    ClientMessage {
+        message: Some(ClientMsg::WorldTick(tick)),
+        ..Default::default()
+    }
+}
+
+fn bench_world_tick(c: &mut Criterion) {
+    let mut group = c.benchmark_group("world_tick");
+    for npcs in [10, 100, 500] {
+        let msg = world_tick(npcs);
+        let bytes = msg.encode_to_vec();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::new("encode", npcs), &msg, |b, msg| {
+            b.iter(|| black_box(msg).encode_to_vec())
+        });
+        group.bench_with_input(BenchmarkId::new("decode", npcs), &bytes, |b, bytes| {
+            b.iter(|| ClientMessage::decode(black_box(&bytes[..])).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_audio(c: &mut Criterion) {
+    let speak = SpeakDirective {
+        npc_id: "blacksmith".to_string(),
+        stream_id: "s1".to_string(),
+        text: "Fine steel, fresh from the forge.".to_string(),
+        ..Default::default()
+    };
+    // One second at a typical TTS engine rate
+    let synthesized = SynthesizedAudio {
+        samples: (0..24_000).map(|i| (i as f32 / 24.0).sin() * 0.5).collect(),
+        sample_rate_hz: 24_000,
+        ..Default::default()
+    };
+    let chunks = tts::chunk_speech(&speak, &synthesized);
+
+    let mut group = c.benchmark_group("audio");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("chunk_speech_1s", |b| {
+        b.iter(|| tts::chunk_speech(black_box(&speak), black_box(&synthesized)))
+    });
+    let pcm_bytes: usize = chunks.iter().map(|c| c.pcm_data.len()).sum();
+    group.throughput(Throughput::Bytes(pcm_bytes as u64));
+    group.bench_function("encode_chunks_1s", |b| {
+        b.iter(|| {
+            for chunk in &chunks {
+                let msg = ServerMessage {
+                    message: Some(ServerMsg::AudioChunk(chunk.clone())),
+                    ..Default::default()
+                };
+                black_box(msg.encode_to_vec());
+            }
+        })
+    });
+    group.finish();
+}
+
+#[derive(Default)]
+struct Counter {
+    chats: AtomicUsize,
+}
+
+impl NpcSocietyHandler for Counter {
+    fn on_chat(&self, _chat: ChatObservation, _cx: &ConnectionContext, _tx: &Outbound) {
+        self.chats.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn bench_dispatch(c: &mut Criterion) {
+    let (tx, _rx) = outbound::queue(QueueConfig::default());
+    let handler = Counter::default();
+    let cx = ConnectionContext::new("bench", 0);
+    let chat = ClientMessage::from(ChatObservation {
+        npc_id: "blacksmith".to_string(),
+        player_name: "Steve".to_string(),
+        message: "got any swords?".to_string(),
+        ..Default::default()
+    });
+
+    let mut group = c.benchmark_group("dispatch");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("chat", |b| {
+        b.iter(|| {
+            let event = ClientEvent::try_from(black_box(chat.clone())).unwrap();
+            events::dispatch(&handler, event, &cx, &tx);
+        })
+    });
+    let bytes = chat.encode_to_vec();
+    group.bench_function("decode_and_dispatch_chat", |b| {
+        b.iter(|| {
+            let msg = ClientMessage::decode(black_box(&bytes[..])).unwrap();
+            events::dispatch(&handler, ClientEvent::try_from(msg).unwrap(), &cx, &tx);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_world_tick, bench_audio, bench_dispatch);
+criterion_main!(benches);
@@ -0,0 +1,136 @@
+//! Plain decoding vs `TickPool` for a busy WorldTick.
+//!
+//! Run with `cargo bench --bench tick_decode`. Counts allocations with a
+//! wrapping global allocator, so no benchmark framework is needed.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use npc_society_example::npc_society::v1::{
+    client_message::Message as ClientMsg, ClientMessage, EntitySnapshot, NpcSnapshot,
+    PlayerSnapshot, Position, WorldTick,
+};
+use npc_society_example::pool::TickPool;
+use prost::Message;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const NPCS: usize = 300;
+const TICKS: u32 = 2_000;
+
+fn position(i: usize) -> Option<Position> {
+    Some(Position {
+        world: "world".to_string(),
+        x: i as f64,
+        y: 64.0,
+        z: -(i as f64),
+        ..Default::default()
+    })
+}
+
+fn busy_tick() -> Vec<u8> {
+    let tick = WorldTick {
+        server_tick: 1200,
+        timestamp_ms: 1_700_000_000_000,
+        npcs: (0..NPCS)
+            .map(|i| NpcSnapshot {
+                npc_id: format!("villager_{}", i),
+                entity_uuid: format!("00000000-0000-0000-0000-{:012}", i),
+                position: position(i),
+                health_norm: 1.0,
+                held_item: "minecraft:iron_pickaxe".to_string(),
+                current_activity: "mining".to_string(),
+                ..Default::default()
+            })
+            .collect(),
+        nearby_players: (0..20)
+            .map(|i| PlayerSnapshot {
+                player_uuid: format!("10000000-0000-0000-0000-{:012}", i),
+                player_name: format!("player{}", i),
+                position: position(i),
+                game_mode: "survival".to_string(),
+                ..Default::default()
+            })
+            .collect(),
+        nearby_entities: (0..100)
+            .map(|i| EntitySnapshot {
+                entity_uuid: format!("20000000-0000-0000-0000-{:012}", i),
+                entity_type: "minecraft:zombie".to_string(),
+                position: position(i),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    };
+    ClientMessage {
+        message: Some(ClientMsg::WorldTick(tick)),
+        ..Default::default()
+    }
+    .encode_to_vec()
+}
+
+/// Time and allocations per call of `decode`
+fn measure(mut decode: impl FnMut()) -> (Duration, usize) {
+    // Warm up (fills the pool)
+    for _ in 0..10 {
+        decode();
+    }
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        decode();
+    }
+    let elapsed = start.elapsed() / TICKS;
+    let allocated = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    (elapsed, allocated / TICKS as usize)
+}
+
+fn main() {
+    let bytes = busy_tick();
+    println!(
+        "WorldTick with {} NPCs, 20 players, 100 entities ({} bytes)",
+        NPCS,
+        bytes.len()
+    );
+
+    let (time, allocations) = measure(|| {
+        black_box(ClientMessage::decode(black_box(&bytes[..])).unwrap());
+    });
+    println!(
+        "ClientMessage::decode  {:>10.1?}/tick  {:>6} allocations/tick",
+        time, allocations
+    );
+
+    let mut pool = TickPool::default();
+    let (time, allocations) = measure(|| {
+        let msg = pool.decode(black_box(&bytes)).unwrap();
+        pool.recycle(black_box(msg));
+    });
+    println!(
+        "TickPool::decode       {:>10.1?}/tick  {:>6} allocations/tick",
+        time, allocations
+    );
+}
@@ -0,0 +1,379 @@
+//! One task per NPC.
+//!
+//! [`NpcDispatcher`] fans the shared `Connect` stream out to per-NPC
+//! mailboxes and fans the replies back into the outbound stream. Each NPC
+//! is an [`NpcActor`] running in its own task, so daemon logic is written
+//! for a single NPC and never sees another NPC's traffic.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+
+use crate::npc_society::v1::{
+    client_message::Message as ClientMsg, ActionResult, BlockWatchUpdate,
+    ChangeDimensionObservation, ChatObservation, ChunkLoadObservation, ClientMessage,
+    CombatPolicyObservation, ContainerAccessObservation, DialogueChoiceObservation, DirectiveAck,
+    DirectiveRejected, EventObservation, IntruderObservation, ModerationFlag, NpcMessage,
+    NpcSnapshot, NpcTransferUpdate, QuestUpdate, ServerMessage, ShopTradeObservation, SpeakResult,
+    SpeechInterrupted, StationOutputObservation, TransactionObservation, TransportItemsResult,
+    TransportProgress, VoicePcmFrame, WorldTick,
+};
+use crate::outbound::Outbound;
+
+/// Something that happened to one NPC
+#[derive(Debug, Clone, PartialEq)]
+pub enum NpcEvent {
+    /// A WorldTick arrived; `npc` is this NPC's snapshot from it
+    Tick { npc: Box<NpcSnapshot>, tick: Arc<WorldTick> },
+    /// A player chatted near the NPC
+    Chat(ChatObservation),
+    /// A game event happened near the NPC
+    Event(EventObservation),
+    /// A player is talking to the NPC
+    Voice(VoicePcmFrame),
+    /// One of the NPC's directives finished
+    ActionResult(ActionResult),
+    /// The NPC finished speaking
+    SpeakResult(SpeakResult),
+    /// A player talked over the NPC
+    SpeechInterrupted(SpeechInterrupted),
+    /// Another NPC sent this NPC a message
+    Message(NpcMessage),
+    /// A player answered or progressed on one of the NPC's quests
+    Quest(QuestUpdate),
+    /// Currency moved between the NPC and a player
+    Transaction(TransactionObservation),
+    /// The NPC arrived in another world
+    ChangeDimension(ChangeDimensionObservation),
+    /// Blocks changed in one of the NPC's watches
+    BlockWatch(BlockWatchUpdate),
+    /// A station the NPC loaded finished
+    StationOutput(StationOutputObservation),
+    /// The NPC's combat policy acted on its own
+    CombatPolicy(CombatPolicyObservation),
+    /// The plugin refused a directive for the NPC
+    DirectiveRejected(DirectiveRejected),
+    /// The plugin received a directive for the NPC
+    DirectiveAck(DirectiveAck),
+    /// A server reached a stage of moving the NPC to another server
+    Transfer(NpcTransferUpdate),
+    /// A player picked one of the NPC's dialogue options
+    DialogueChoice(DialogueChoiceObservation),
+    /// A player traded in the NPC's shop
+    ShopTrade(ShopTradeObservation),
+    /// A player the NPC guards its zone against came or went
+    Intruder(IntruderObservation),
+    /// A player opened, looted or was locked out of the NPC's container
+    ContainerAccess(ContainerAccessObservation),
+    /// A player or moderator flagged what the NPC said or did
+    ModerationFlag(ModerationFlag),
+    /// The NPC's chunk unloaded, parking it, or loaded again
+    ChunkLoad(ChunkLoadObservation),
+    /// A leg of the NPC's courier run started or ended
+    TransportProgress(TransportProgress),
+    /// The NPC's courier run ended
+    TransportResult(TransportItemsResult),
+}
+
+/// Logic for a single NPC.
+pub trait NpcActor: Send + 'static {
+    /// Handle one event, returning the messages to send to the plugin.
+    /// Spawn a task for anything slow; the mailbox waits while this runs.
+    fn handle(&mut self, event: NpcEvent) -> Vec<ServerMessage>;
+}
+
+type ActorFactory = Box<dyn Fn(&str) -> Box<dyn NpcActor> + Send + Sync>;
+
+/// Routes client messages to one [`NpcActor`] task per npc_id.
+pub struct NpcDispatcher {
+    factory: ActorFactory,
+    outbound: Outbound,
+    mailbox_size: usize,
+    mailboxes: HashMap<String, mpsc::Sender<NpcEvent>>,
+}
+
+impl NpcDispatcher {
+    /// Create a dispatcher whose actors reply on `outbound`.
+    /// `factory` creates the actor for an npc_id the first time it is seen.
+    pub fn new(
+        outbound: Outbound,
+        mailbox_size: usize,
+        factory: impl Fn(&str) -> Box<dyn NpcActor> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            factory: Box::new(factory),
+            outbound,
+            mailbox_size: mailbox_size.max(1),
+            mailboxes: HashMap::new(),
+        }
+    }
+
+    /// Deliver a client message to the NPC(s) it concerns, waiting if a
+    /// mailbox is full. Returns false for messages that are not about one
+    /// NPC (Hello, ChoreographyResult); handle those yourself.
+    pub async fn dispatch(&mut self, msg: ClientMessage) -> bool {
+        let (npc_id, event) = match msg.message {
+            Some(ClientMsg::WorldTick(tick)) => {
+                let tick = Arc::new(tick);
+                for npc in &tick.npcs {
+                    let event = NpcEvent::Tick {
+                        npc: Box::new(npc.clone()),
+                        tick: tick.clone(),
+                    };
+                    self.deliver(&npc.npc_id, event).await;
+                }
+                return true;
+            }
+            Some(ClientMsg::ChatObservation(chat)) => (chat.npc_id.clone(), NpcEvent::Chat(chat)),
+            Some(ClientMsg::EventObservation(event)) => (event.npc_id.clone(), NpcEvent::Event(event)),
+            Some(ClientMsg::VoicePcmFrame(frame)) => (frame.npc_id.clone(), NpcEvent::Voice(frame)),
+            Some(ClientMsg::ActionResult(result)) => {
+                (result.npc_id.clone(), NpcEvent::ActionResult(result))
+            }
+            Some(ClientMsg::SpeakResult(spoken)) => {
+                (spoken.npc_id.clone(), NpcEvent::SpeakResult(spoken))
+            }
+            Some(ClientMsg::SpeechInterrupted(interrupted)) => {
+                (interrupted.npc_id.clone(), NpcEvent::SpeechInterrupted(interrupted))
+            }
+            Some(ClientMsg::NpcMessage(message)) if message.recipient_npc_id.is_empty() => {
+                // Broadcast: every running actor except the sender
+                let npc_ids: Vec<String> = self
+                    .mailboxes
+                    .keys()
+                    .filter(|id| **id != message.sender_npc_id)
+                    .cloned()
+                    .collect();
+                for npc_id in npc_ids {
+                    self.deliver(&npc_id, NpcEvent::Message(message.clone())).await;
+                }
+                return true;
+            }
+            Some(ClientMsg::NpcMessage(message)) => {
+                (message.recipient_npc_id.clone(), NpcEvent::Message(message))
+            }
+            Some(ClientMsg::QuestUpdate(update)) => (update.npc_id.clone(), NpcEvent::Quest(update)),
+            Some(ClientMsg::TransactionObservation(transaction)) => {
+                (transaction.npc_id.clone(), NpcEvent::Transaction(transaction))
+            }
+            Some(ClientMsg::ChangeDimension(change)) => {
+                (change.npc_id.clone(), NpcEvent::ChangeDimension(change))
+            }
+            Some(ClientMsg::BlockWatchUpdate(update)) => {
+                (update.npc_id.clone(), NpcEvent::BlockWatch(update))
+            }
+            Some(ClientMsg::StationOutput(output)) => {
+                (output.npc_id.clone(), NpcEvent::StationOutput(output))
+            }
+            Some(ClientMsg::CombatPolicy(observation)) => {
+                (observation.npc_id.clone(), NpcEvent::CombatPolicy(observation))
+            }
+            Some(ClientMsg::DirectiveRejected(rejected)) => {
+                (rejected.npc_id.clone(), NpcEvent::DirectiveRejected(rejected))
+            }
+            Some(ClientMsg::DirectiveAck(ack)) => (ack.npc_id.clone(), NpcEvent::DirectiveAck(ack)),
+            Some(ClientMsg::NpcTransferUpdate(update)) => {
+                (update.npc_id.clone(), NpcEvent::Transfer(update))
+            }
+            Some(ClientMsg::DialogueChoice(choice)) => {
+                (choice.npc_id.clone(), NpcEvent::DialogueChoice(choice))
+            }
+            Some(ClientMsg::ShopTrade(trade)) => (trade.npc_id.clone(), NpcEvent::ShopTrade(trade)),
+            Some(ClientMsg::Intruder(observation)) => {
+                (observation.npc_id.clone(), NpcEvent::Intruder(observation))
+            }
+            Some(ClientMsg::ContainerAccess(access)) => {
+                (access.npc_id.clone(), NpcEvent::ContainerAccess(access))
+            }
+            Some(ClientMsg::ModerationFlag(flag)) => {
+                (flag.npc_id.clone(), NpcEvent::ModerationFlag(flag))
+            }
+            Some(ClientMsg::ChunkLoad(chunk)) => (chunk.npc_id.clone(), NpcEvent::ChunkLoad(chunk)),
+            Some(ClientMsg::TransportProgress(progress)) => {
+                (progress.npc_id.clone(), NpcEvent::TransportProgress(progress))
+            }
+            Some(ClientMsg::TransportItemsResult(result)) => {
+                (result.npc_id.clone(), NpcEvent::TransportResult(result))
+            }
+            Some(
+                ClientMsg::Hello(_)
+                | ClientMsg::ChoreographyResult(_)
+                | ClientMsg::AudioBuffer(_)
+                | ClientMsg::LandmarkList(_)
+                | ClientMsg::VoiceConsent(_)
+                | ClientMsg::AudioStreamHello(_)
+                | ClientMsg::WorldBootstrap(_)
+                | ClientMsg::ProbeReply(_),
+            )
+            | None => return false,
+        };
+        self.deliver(&npc_id, event).await;
+        true
+    }
+
+    /// Stop an NPC's actor (e.g. it was removed). Events already in its
+    /// mailbox are still handled.
+    pub fn remove(&mut self, npc_id: &str) {
+        self.mailboxes.remove(npc_id);
+    }
+
+    /// Number of running actors
+    pub fn len(&self) -> usize {
+        self.mailboxes.len()
+    }
+
+    /// Whether no actors are running
+    pub fn is_empty(&self) -> bool {
+        self.mailboxes.is_empty()
+    }
+
+    async fn deliver(&mut self, npc_id: &str, event: NpcEvent) {
+        let mailbox = match self.mailboxes.get(npc_id) {
+            Some(mailbox) => mailbox.clone(),
+            None => self.spawn(npc_id),
+        };
+        if mailbox.send(event).await.is_err() {
+            // The actor stopped because the outbound stream closed
+            self.mailboxes.remove(npc_id);
+        }
+    }
+
+    fn spawn(&mut self, npc_id: &str) -> mpsc::Sender<NpcEvent> {
+        let (tx, mut rx) = mpsc::channel(self.mailbox_size);
+        let mut actor = (self.factory)(npc_id);
+        let outbound = self.outbound.clone();
+
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                for msg in actor.handle(event) {
+                    if outbound.send_wait(msg).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        self.mailboxes.insert(npc_id.to_string(), tx.clone());
+        tx
+    }
+}
+
+impl std::fmt::Debug for NpcDispatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NpcDispatcher")
+            .field("mailbox_size", &self.mailbox_size)
+            .field("npcs", &self.mailboxes.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{server_message::Message as ServerMsg, SpeakDirective};
+    use crate::outbound::{self, OutboundReceiver, QueueConfig};
+    use tokio_stream::StreamExt;
+
+    /// Answers every chat and NPC message and counts the ticks it saw
+    struct Echo {
+        npc_id: String,
+        ticks: usize,
+    }
+
+    impl NpcActor for Echo {
+        fn handle(&mut self, event: NpcEvent) -> Vec<ServerMessage> {
+            match event {
+                NpcEvent::Tick { npc, .. } => {
+                    assert_eq!(npc.npc_id, self.npc_id);
+                    self.ticks += 1;
+                    Vec::new()
+                }
+                NpcEvent::Chat(chat) => self.say(format!("{} after {} ticks", chat.message, self.ticks)),
+                NpcEvent::Message(message) => {
+                    self.say(format!("{} from {}", message.topic, message.sender_npc_id))
+                }
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    impl Echo {
+        fn say(&self, text: String) -> Vec<ServerMessage> {
+            vec![ServerMessage {
+                message: Some(ServerMsg::SpeakDirective(SpeakDirective {
+                    npc_id: self.npc_id.clone(),
+                    text,
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }]
+        }
+    }
+
+    async fn next_speech(rx: &mut OutboundReceiver) -> SpeakDirective {
+        match rx.next().await.and_then(|m| m.message) {
+            Some(ServerMsg::SpeakDirective(speak)) => speak,
+            other => panic!("expected SpeakDirective, got {other:?}"),
+        }
+    }
+
+    fn chat(npc_id: &str, message: &str) -> ClientMessage {
+        ClientMessage {
+            message: Some(ClientMsg::ChatObservation(ChatObservation {
+                npc_id: npc_id.to_string(),
+                message: message.to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fans_out_per_npc() {
+        let (tx, mut rx) = outbound::queue(QueueConfig::default());
+        let mut dispatcher = NpcDispatcher::new(tx, 8, |npc_id| {
+            Box::new(Echo {
+                npc_id: npc_id.to_string(),
+                ticks: 0,
+            })
+        });
+
+        let tick = ClientMessage {
+            message: Some(ClientMsg::WorldTick(WorldTick {
+                npcs: ["a", "b"]
+                    .iter()
+                    .map(|id| NpcSnapshot {
+                        npc_id: id.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        assert!(dispatcher.dispatch(tick).await);
+        assert_eq!(dispatcher.len(), 2);
+
+        assert!(dispatcher.dispatch(chat("b", "hi")).await);
+        let speak = next_speech(&mut rx).await;
+        assert_eq!(speak.npc_id, "b");
+        assert_eq!(speak.text, "hi after 1 ticks");
+
+        // A broadcast reaches everyone but the sender
+        let broadcast = ClientMessage {
+            message: Some(ClientMsg::NpcMessage(NpcMessage {
+                sender_npc_id: "a".to_string(),
+                topic: "found_ore".to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        assert!(dispatcher.dispatch(broadcast).await);
+        let speak = next_speech(&mut rx).await;
+        assert_eq!(speak.npc_id, "b");
+        assert_eq!(speak.text, "found_ore from a");
+
+        assert!(!dispatcher.dispatch(ClientMessage::default()).await);
+    }
+}
@@ -0,0 +1,586 @@
+//! LLM agent integration.
+//!
+//! An [`LlmBackend`] takes a conversation plus tool schemas and answers
+//! with text and/or tool calls. [`ToolBridge`] is the protocol side of
+//! that loop: it describes NPC actions as tools ([`tool_schemas`]), turns
+//! tool calls into `ActionDirective`s / `SpeakDirective`s, and turns the
+//! matching `ActionResult` / `SpeakResult` back into tool results for the
+//! next completion.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+
+use serde_json::{json, Value};
+
+use crate::dimension::block_at;
+use crate::emotion::{Spoken, Tone};
+use crate::npc_society::v1::{
+    action_directive::Action, action_result::Result as ActionResultType,
+    check_line_of_sight_action::Target, raycast_look_action::Aim,
+    server_message::Message as ServerMsg, ActionDirective, ActionResult, BlockPosition,
+    BreakBlockAction, CheckLineOfSightAction, MoveAction, NpcSnapshot, Position, RaycastLookAction,
+    ScanBlocksAction, ServerMessage, SpeakDirective, SpeakResult,
+};
+
+/// Result of [`LlmBackend::complete`]
+pub type CompletionFuture = Pin<Box<dyn Future<Output = Result<AgentResponse, AgentError>> + Send>>;
+
+/// A function the model may call
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolSchema {
+    /// Tool name, e.g. `move_to`
+    pub name: &'static str,
+    /// What the tool does, for the model
+    pub description: &'static str,
+    /// JSON Schema of the arguments object
+    pub parameters: Value,
+}
+
+/// A tool invocation requested by the model
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    /// Backend-assigned id, echoed in the [`ToolResult`]
+    pub id: String,
+    /// Which tool to call
+    pub name: String,
+    /// Arguments object
+    pub arguments: Value,
+}
+
+/// Outcome of a [`ToolCall`], fed back to the model
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolResult {
+    /// id of the ToolCall this answers
+    pub call_id: String,
+    /// Result object
+    pub content: Value,
+}
+
+/// One turn of the conversation sent to the model
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentMessage {
+    /// Instructions (persona, rules)
+    System(String),
+    /// Input from the world (what a player said, what the NPC sees)
+    User(String),
+    /// An earlier model response
+    Assistant { text: String, tool_calls: Vec<ToolCall> },
+    /// Result of an earlier tool call
+    Tool(ToolResult),
+}
+
+/// Input to [`LlmBackend::complete`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AgentRequest {
+    /// Conversation so far, oldest first
+    pub messages: Vec<AgentMessage>,
+    /// Tools the model may call
+    pub tools: Vec<ToolSchema>,
+}
+
+/// Output of [`LlmBackend::complete`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AgentResponse {
+    /// Free text (may be empty when only tools are called)
+    pub text: String,
+    /// Tools to call, in order
+    pub tool_calls: Vec<ToolCall>,
+}
+
+/// Error from the agent layer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AgentError {
+    /// The model backend failed
+    Backend(String),
+    /// The model called a tool that does not exist
+    UnknownTool(String),
+    /// The model passed arguments that don't match the schema
+    BadArguments { tool: String, reason: String },
+}
+
+impl fmt::Display for AgentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AgentError::Backend(msg) => write!(f, "LLM backend error: {msg}"),
+            AgentError::UnknownTool(name) => write!(f, "unknown tool '{name}'"),
+            AgentError::BadArguments { tool, reason } => {
+                write!(f, "bad arguments for '{tool}': {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AgentError {}
+
+/// A language model that can call tools.
+pub trait LlmBackend: Send + Sync {
+    /// Produce the next response for `request`.
+    fn complete(&self, request: AgentRequest) -> CompletionFuture;
+}
+
+/// The NPC actions exposed as tools
+pub fn tool_schemas() -> Vec<ToolSchema> {
+    let xyz = json!({
+        "x": { "type": "integer" },
+        "y": { "type": "integer" },
+        "z": { "type": "integer" }
+    });
+    vec![
+        ToolSchema {
+            name: "move_to",
+            description: "Walk to a block position, pathfinding around obstacles.",
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "x": { "type": "number" },
+                    "y": { "type": "number" },
+                    "z": { "type": "number" },
+                    "sprint": { "type": "boolean", "description": "Move at full speed" }
+                },
+                "required": ["x", "y", "z"]
+            }),
+        },
+        ToolSchema {
+            name: "break_block",
+            description: "Break the block at a position within reach.",
+            parameters: json!({
+                "type": "object",
+                "properties": xyz,
+                "required": ["x", "y", "z"]
+            }),
+        },
+        ToolSchema {
+            name: "speak",
+            description: "Say something out loud.",
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string" },
+                    "emotion": { "type": "string", "description": "Tone: neutral, happy, sad, angry, fearful, surprised, disgusted, calm or excited (other words are passed on as-is)" }
+                },
+                "required": ["text"]
+            }),
+        },
+        ToolSchema {
+            name: "scan",
+            description: "Find nearby blocks of the given types, nearest first.",
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "block_types": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Block ids (minecraft:diamond_ore), tags (#minecraft:logs) or patterns (minecraft:*_ore)"
+                    },
+                    "radius": { "type": "integer", "minimum": 1, "maximum": 32 }
+                },
+                "required": ["block_types"]
+            }),
+        },
+        ToolSchema {
+            name: "can_see",
+            description: "Check whether you can see an entity or player, or a position. Walls block sight; react only to what you can see.",
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "entity_uuid": { "type": "string", "description": "UUID of the entity or player; or give x, y and z" },
+                    "x": { "type": "number" },
+                    "y": { "type": "number" },
+                    "z": { "type": "number" }
+                }
+            }),
+        },
+        ToolSchema {
+            name: "raycast",
+            description: "Find the first block or creature in the direction of an entity, player or position, e.g. what you would point at or hit.",
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "entity_uuid": { "type": "string", "description": "UUID of the entity or player to aim at; or give x, y and z" },
+                    "x": { "type": "number" },
+                    "y": { "type": "number" },
+                    "z": { "type": "number" },
+                    "max_distance": { "type": "number", "minimum": 1, "maximum": 64 }
+                }
+            }),
+        },
+    ]
+}
+
+/// Converts between tool calls and protocol messages for one NPC.
+///
+/// Directive ids are `<npc_id>-tool-<n>`; the bridge remembers which tool
+/// call each one answers.
+#[derive(Debug, Default)]
+pub struct ToolBridge {
+    npc_id: String,
+    counter: u64,
+    pending: HashMap<String, String>,
+}
+
+impl ToolBridge {
+    /// Create a bridge for `npc_id`
+    pub fn new(npc_id: &str) -> Self {
+        Self {
+            npc_id: npc_id.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Build the message that performs `call`. `npc` supplies the world and
+    /// the scan center.
+    pub fn call_to_message(&mut self, call: &ToolCall, npc: &NpcSnapshot) -> Result<ServerMessage, AgentError> {
+        let args = Args { tool: &call.name, value: &call.arguments };
+        let position = npc.position.clone().unwrap_or_default();
+        self.counter += 1;
+        let directive_id = format!("{}-tool-{}", self.npc_id, self.counter);
+
+        let message = match call.name.as_str() {
+            "speak" => {
+                let mut speak = SpeakDirective {
+                    npc_id: self.npc_id.clone(),
+                    text: args.string("text")?,
+                    directive_id: directive_id.clone(),
+                    ..Default::default()
+                };
+                speak.set_tone(Tone::parse(&args.optional_string("emotion")));
+                ServerMsg::SpeakDirective(speak)
+            }
+            name => {
+                let action = match name {
+                    "move_to" => Action::Move(MoveAction {
+                        target: Some(Position {
+                            world: position.world,
+                            x: args.number("x")?,
+                            y: args.number("y")?,
+                            z: args.number("z")?,
+                            dimension: position.dimension,
+                            ..Default::default()
+                        }),
+                        speed: if args.value["sprint"].as_bool() == Some(true) { 1.0 } else { 0.5 },
+                        pathfind: true,
+                        ..Default::default()
+                    }),
+                    "break_block" => Action::BreakBlock(BreakBlockAction {
+                        position: Some(BlockPosition {
+                            world: position.world,
+                            x: args.number("x")? as i32,
+                            y: args.number("y")? as i32,
+                            z: args.number("z")? as i32,
+                            dimension: position.dimension,
+                        }),
+                    }),
+                    "scan" => Action::ScanBlocks(ScanBlocksAction {
+                        center: Some(block_at(&position)),
+                        radius: args.value["radius"].as_i64().unwrap_or(16).clamp(1, 32) as i32,
+                        block_types: args.strings("block_types")?,
+                        max_results: 10,
+                        include_properties: false,
+                    }),
+                    "can_see" => Action::CheckLineOfSight(CheckLineOfSightAction {
+                        target: Some(match args.value["entity_uuid"].as_str() {
+                            Some(uuid) => Target::EntityUuid(uuid.to_string()),
+                            None => Target::Position(Position {
+                                world: position.world,
+                                x: args.number("x")?,
+                                y: args.number("y")?,
+                                z: args.number("z")?,
+                                dimension: position.dimension,
+                                ..Default::default()
+                            }),
+                        }),
+                        max_distance: 0.0,
+                    }),
+                    "raycast" => Action::RaycastLook(RaycastLookAction {
+                        max_distance: args.value["max_distance"]
+                            .as_f64()
+                            .unwrap_or(32.0)
+                            .clamp(1.0, 64.0) as f32,
+                        aim: Some(match args.value["entity_uuid"].as_str() {
+                            Some(uuid) => Aim::TargetEntityUuid(uuid.to_string()),
+                            None => Aim::Target(Position {
+                                world: position.world,
+                                x: args.number("x")?,
+                                y: args.number("y")?,
+                                z: args.number("z")?,
+                                dimension: position.dimension,
+                                ..Default::default()
+                            }),
+                        }),
+                        include_entities: true,
+                        ..Default::default()
+                    }),
+                    other => return Err(AgentError::UnknownTool(other.to_string())),
+                };
+                ServerMsg::ActionDirective(ActionDirective {
+                    directive_id: directive_id.clone(),
+                    npc_id: self.npc_id.clone(),
+                    priority: 5,
+                    action: Some(action),
+                    ..Default::default()
+                })
+            }
+        };
+
+        self.pending.insert(directive_id, call.id.clone());
+        Ok(ServerMessage { message: Some(message), ..Default::default() })
+    }
+
+    /// Tool result for an action started by [`call_to_message`](Self::call_to_message)
+    pub fn on_action_result(&mut self, result: &ActionResult) -> Option<ToolResult> {
+        let call_id = self.pending.remove(&result.directive_id)?;
+        Some(ToolResult {
+            call_id,
+            content: action_result_json(result),
+        })
+    }
+
+    /// Tool result for a `speak` call
+    pub fn on_speak_result(&mut self, result: &SpeakResult) -> Option<ToolResult> {
+        let call_id = self.pending.remove(&result.directive_id)?;
+        Some(ToolResult {
+            call_id,
+            content: json!({
+                "success": true,
+                "listeners": result.listeners_count,
+                "interrupted": result.truncated,
+            }),
+        })
+    }
+}
+
+/// Summarize an ActionResult for the model
+pub fn action_result_json(result: &ActionResult) -> Value {
+    let block = |p: Option<&BlockPosition>| {
+        p.map(|p| json!({ "x": p.x, "y": p.y, "z": p.z }))
+    };
+    if !result.success {
+        let mut failed = json!({ "success": false, "error": result.error_message });
+        // A locked door is something the model can route around
+        if let Some(ActionResultType::MoveResult(r)) = &result.result {
+            failed["blocked_by_door"] = json!(block(r.blocked_by.as_ref()));
+        }
+        return failed;
+    }
+    match &result.result {
+        Some(ActionResultType::MoveResult(r)) => json!({
+            "success": true,
+            "reached_destination": r.reached_destination,
+            "path_length": r.path_length,
+            // Where the best path ended, for a move that fell short
+            "closest_reached": block(r.partial_path.last()),
+        }),
+        Some(ActionResultType::BreakBlockResult(r)) => json!({
+            "success": true,
+            "items_dropped": r.items_dropped.iter()
+                .map(|i| json!({ "item": i.item_type, "quantity": i.quantity }))
+                .collect::<Vec<_>>(),
+        }),
+        Some(ActionResultType::ScanBlocksResult(r)) => json!({
+            "success": true,
+            "matches": r.matches.iter()
+                .map(|m| json!({ "block_type": m.block_type, "position": block(m.position.as_ref()) }))
+                .collect::<Vec<_>>(),
+        }),
+        Some(ActionResultType::RaycastLookResult(r)) => json!({
+            "success": true,
+            "hit": r.hit,
+            "block_type": r.block_type,
+            "position": block(r.hit_position.as_ref()),
+            "entity_uuid": r.hit_entity_uuid,
+            "entity_type": r.hit_entity_type,
+            "distance": r.distance,
+        }),
+        Some(ActionResultType::CheckLineOfSightResult(r)) => json!({
+            "success": true,
+            "visible": r.visible,
+            "in_field_of_view": r.in_field_of_view,
+            "distance": r.distance,
+            "blocked_by": r.blocking_block_type,
+        }),
+        _ => json!({ "success": true }),
+    }
+}
+
+/// Typed access to a tool call's arguments
+struct Args<'a> {
+    tool: &'a str,
+    value: &'a Value,
+}
+
+impl Args<'_> {
+    fn error(&self, reason: String) -> AgentError {
+        AgentError::BadArguments {
+            tool: self.tool.to_string(),
+            reason,
+        }
+    }
+
+    fn number(&self, key: &str) -> Result<f64, AgentError> {
+        self.value[key]
+            .as_f64()
+            .ok_or_else(|| self.error(format!("'{key}' must be a number")))
+    }
+
+    fn string(&self, key: &str) -> Result<String, AgentError> {
+        self.value[key]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| self.error(format!("'{key}' must be a string")))
+    }
+
+    fn optional_string(&self, key: &str) -> String {
+        self.value[key].as_str().unwrap_or_default().to_string()
+    }
+
+    fn strings(&self, key: &str) -> Result<Vec<String>, AgentError> {
+        self.value[key]
+            .as_array()
+            .and_then(|items| items.iter().map(|i| i.as_str().map(str::to_string)).collect())
+            .ok_or_else(|| self.error(format!("'{key}' must be an array of strings")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{
+        BlockMatch, CheckLineOfSightResult, RaycastLookResult, ScanBlocksResult,
+    };
+
+    fn call(name: &str, arguments: Value) -> ToolCall {
+        ToolCall {
+            id: "call-1".to_string(),
+            name: name.to_string(),
+            arguments,
+        }
+    }
+
+    fn npc() -> NpcSnapshot {
+        NpcSnapshot {
+            npc_id: "miner".to_string(),
+            position: Some(Position {
+                world: "world".to_string(),
+                x: 10.5,
+                y: 12.0,
+                z: -3.2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_scan_round_trip() {
+        let mut bridge = ToolBridge::new("miner");
+        let msg = bridge
+            .call_to_message(&call("scan", json!({ "block_types": ["minecraft:diamond_ore"] })), &npc())
+            .unwrap();
+        let Some(ServerMsg::ActionDirective(directive)) = msg.message else {
+            panic!("expected an ActionDirective");
+        };
+        let Some(Action::ScanBlocks(scan)) = &directive.action else {
+            panic!("expected a scan");
+        };
+        assert_eq!(scan.center.as_ref().map(|c| (c.x, c.z)), Some((10, -4)));
+
+        let result = ActionResult {
+            directive_id: directive.directive_id.clone(),
+            npc_id: "miner".to_string(),
+            success: true,
+            result: Some(ActionResultType::ScanBlocksResult(ScanBlocksResult {
+                matches: vec![BlockMatch {
+                    position: Some(BlockPosition { x: 1, y: 2, z: 3, ..Default::default() }),
+                    block_type: "minecraft:diamond_ore".to_string(),
+                    ..Default::default()
+                }],
+            })),
+            ..Default::default()
+        };
+        let tool_result = bridge.on_action_result(&result).unwrap();
+        assert_eq!(tool_result.call_id, "call-1");
+        assert_eq!(tool_result.content["matches"][0]["position"]["y"], 2);
+        assert_eq!(bridge.on_action_result(&result), None);
+    }
+
+    #[test]
+    fn test_rejects_bad_calls() {
+        let mut bridge = ToolBridge::new("miner");
+        assert_eq!(
+            bridge.call_to_message(&call("fly", json!({})), &npc()),
+            Err(AgentError::UnknownTool("fly".to_string()))
+        );
+        assert!(matches!(
+            bridge.call_to_message(&call("move_to", json!({ "x": 1 })), &npc()),
+            Err(AgentError::BadArguments { .. })
+        ));
+        assert!(matches!(
+            bridge.call_to_message(&call("can_see", json!({})), &npc()),
+            Err(AgentError::BadArguments { .. })
+        ));
+        assert_eq!(tool_schemas().len(), 6);
+    }
+
+    #[test]
+    fn test_can_see() {
+        let mut bridge = ToolBridge::new("guard");
+        let msg = bridge
+            .call_to_message(&call("can_see", json!({ "entity_uuid": "p1" })), &npc())
+            .unwrap();
+        let Some(ServerMsg::ActionDirective(directive)) = msg.message else {
+            panic!("expected an ActionDirective");
+        };
+        let Some(Action::CheckLineOfSight(sight)) = &directive.action else {
+            panic!("expected a line of sight check");
+        };
+        assert_eq!(sight.target, Some(Target::EntityUuid("p1".to_string())));
+
+        let result = ActionResult {
+            directive_id: directive.directive_id.clone(),
+            success: true,
+            result: Some(ActionResultType::CheckLineOfSightResult(CheckLineOfSightResult {
+                distance: 12.0,
+                blocking_block_type: "minecraft:stone_bricks".to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let content = bridge.on_action_result(&result).unwrap().content;
+        assert_eq!(content["visible"], false);
+        assert_eq!(content["blocked_by"], "minecraft:stone_bricks");
+    }
+
+    #[test]
+    fn test_raycast() {
+        let mut bridge = ToolBridge::new("hunter");
+        let msg = bridge
+            .call_to_message(&call("raycast", json!({ "x": 40, "y": 90, "z": -3 })), &npc())
+            .unwrap();
+        let Some(ServerMsg::ActionDirective(directive)) = msg.message else {
+            panic!("expected an ActionDirective");
+        };
+        let Some(Action::RaycastLook(ray)) = &directive.action else {
+            panic!("expected a raycast");
+        };
+        assert!(ray.include_entities);
+        assert!(matches!(&ray.aim, Some(Aim::Target(p)) if p.world == "world" && p.y == 90.0));
+
+        let result = ActionResult {
+            directive_id: directive.directive_id.clone(),
+            success: true,
+            result: Some(ActionResultType::RaycastLookResult(RaycastLookResult {
+                hit: true,
+                distance: 7.5,
+                hit_entity_uuid: "cow-1".to_string(),
+                hit_entity_type: "minecraft:cow".to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let content = bridge.on_action_result(&result).unwrap().content;
+        assert_eq!(content["entity_type"], "minecraft:cow");
+        assert_eq!(content["position"], Value::Null);
+    }
+}
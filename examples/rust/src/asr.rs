@@ -0,0 +1,239 @@
+//! Pluggable speech recognition.
+//!
+//! An [`AsrProvider`] turns a stream of 16kHz mono f32 frames (see
+//! [`crate::audio`]) into partial and final transcripts. Daemon logic
+//! consumes [`VoiceTranscription`]s ("player X said Y near NPC Z") and
+//! never touches audio bytes.
+//!
+//! A whisper.cpp server backend is available behind the `asr-whisper`
+//! feature.
+
+use std::fmt;
+use std::pin::Pin;
+
+use tokio_stream::{Stream, StreamExt};
+
+/// Audio input for an [`AsrProvider`]: 16kHz mono f32 frames
+pub type FrameStream = Pin<Box<dyn Stream<Item = Vec<f32>> + Send>>;
+
+/// Transcript output of an [`AsrProvider`]
+pub type TranscriptStream = Pin<Box<dyn Stream<Item = Result<Transcript, AsrError>> + Send>>;
+
+/// A partial or final transcript of the audio seen so far
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    /// Recognized text
+    pub text: String,
+    /// Whether this transcript will not be revised further
+    pub is_final: bool,
+}
+
+/// What a player said to an NPC, as recognized from voice
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceTranscription {
+    /// NPC that heard the speech
+    pub npc_id: String,
+    /// UUID of the player speaking
+    pub player_uuid: String,
+    /// Recognized text
+    pub text: String,
+    /// Whether this transcript will not be revised further
+    pub is_final: bool,
+}
+
+/// Error reported by an [`AsrProvider`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsrError {
+    /// The backend could not be reached
+    Unavailable(String),
+    /// The backend rejected the request or returned garbage
+    Backend(String),
+}
+
+impl fmt::Display for AsrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsrError::Unavailable(msg) => write!(f, "ASR backend unavailable: {msg}"),
+            AsrError::Backend(msg) => write!(f, "ASR backend error: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for AsrError {}
+
+/// A speech recognition backend.
+pub trait AsrProvider: Send + Sync {
+    /// Transcribe `frames`, yielding any number of partial transcripts
+    /// followed by at most one final transcript.
+    fn transcribe(&self, frames: FrameStream) -> TranscriptStream;
+}
+
+/// Transcribe one complete utterance (e.g. from [`crate::vad`]) and tag
+/// the transcripts with the speaker and listening NPC.
+pub fn transcribe_utterance(
+    provider: &dyn AsrProvider,
+    npc_id: &str,
+    player_uuid: &str,
+    audio: Vec<f32>,
+) -> impl Stream<Item = Result<VoiceTranscription, AsrError>> {
+    let npc_id = npc_id.to_string();
+    let player_uuid = player_uuid.to_string();
+
+    provider
+        .transcribe(Box::pin(tokio_stream::once(audio)))
+        .map(move |result| {
+            result.map(|t| VoiceTranscription {
+                npc_id: npc_id.clone(),
+                player_uuid: player_uuid.clone(),
+                text: t.text,
+                is_final: t.is_final,
+            })
+        })
+}
+
+#[cfg(feature = "asr-whisper")]
+pub use whisper::WhisperServerAsr;
+
+#[cfg(feature = "asr-whisper")]
+mod whisper {
+    use super::*;
+    use crate::audio;
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::ReceiverStream;
+
+    /// Transcribes via a whisper.cpp `server` (`POST /inference`).
+    ///
+    /// Whisper is not streaming, so all frames are collected and a single
+    /// final transcript is returned once the input stream ends.
+    #[derive(Debug, Clone)]
+    pub struct WhisperServerAsr {
+        client: reqwest::Client,
+        inference_url: String,
+    }
+
+    impl WhisperServerAsr {
+        /// `base_url` is the server root, e.g. `http://127.0.0.1:8080`
+        pub fn new(base_url: &str) -> Self {
+            Self {
+                client: reqwest::Client::new(),
+                inference_url: format!("{}/inference", base_url.trim_end_matches('/')),
+            }
+        }
+
+        async fn request(&self, samples: Vec<f32>) -> Result<Transcript, AsrError> {
+            let wav = reqwest::multipart::Part::bytes(encode_wav(&samples))
+                .file_name("utterance.wav")
+                .mime_str("audio/wav")
+                .map_err(|e| AsrError::Backend(e.to_string()))?;
+            let form = reqwest::multipart::Form::new()
+                .part("file", wav)
+                .text("response_format", "json");
+
+            let response = self
+                .client
+                .post(&self.inference_url)
+                .multipart(form)
+                .send()
+                .await
+                .map_err(|e| AsrError::Unavailable(e.to_string()))?
+                .error_for_status()
+                .map_err(|e| AsrError::Backend(e.to_string()))?;
+
+            let body: serde_json::Value = response
+                .json()
+                .await
+                .map_err(|e| AsrError::Backend(e.to_string()))?;
+            let text = body["text"]
+                .as_str()
+                .ok_or_else(|| AsrError::Backend("response has no 'text' field".to_string()))?;
+
+            Ok(Transcript {
+                text: text.trim().to_string(),
+                is_final: true,
+            })
+        }
+    }
+
+    impl AsrProvider for WhisperServerAsr {
+        fn transcribe(&self, mut frames: FrameStream) -> TranscriptStream {
+            let (tx, rx) = mpsc::channel(1);
+            let asr = self.clone();
+
+            tokio::spawn(async move {
+                let mut samples = Vec::new();
+                while let Some(frame) = frames.next().await {
+                    samples.extend(frame);
+                }
+                let _ = tx.send(asr.request(samples).await).await;
+            });
+
+            Box::pin(ReceiverStream::new(rx))
+        }
+    }
+
+    /// Wrap 16kHz mono f32 samples in a 16-bit PCM WAV file
+    fn encode_wav(samples: &[f32]) -> Vec<u8> {
+        let pcm = audio::f32_to_s16le(samples);
+        let rate = audio::ASR_SAMPLE_RATE_HZ;
+
+        let mut wav = Vec::with_capacity(44 + pcm.len());
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + pcm.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes()); // fmt chunk size
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+        wav.extend_from_slice(&rate.to_le_bytes());
+        wav.extend_from_slice(&(rate * 2).to_le_bytes()); // byte rate
+        wav.extend_from_slice(&2u16.to_le_bytes()); // block align
+        wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(pcm.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&pcm);
+        wav
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reports how many samples it heard
+    struct CountingAsr;
+
+    impl AsrProvider for CountingAsr {
+        fn transcribe(&self, mut frames: FrameStream) -> TranscriptStream {
+            let (tx, rx) = tokio::sync::mpsc::channel(1);
+            tokio::spawn(async move {
+                let mut total = 0;
+                while let Some(frame) = frames.next().await {
+                    total += frame.len();
+                }
+                let _ = tx
+                    .send(Ok(Transcript {
+                        text: format!("{total} samples"),
+                        is_final: true,
+                    }))
+                    .await;
+            });
+            Box::pin(tokio_stream::wrappers::ReceiverStream::new(rx))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transcribe_utterance_tags_speaker() {
+        let results: Vec<_> = transcribe_utterance(&CountingAsr, "npc", "player", vec![0.0; 320])
+            .collect()
+            .await;
+
+        assert_eq!(
+            results,
+            vec![Ok(VoiceTranscription {
+                npc_id: "npc".to_string(),
+                player_uuid: "player".to_string(),
+                text: "320 samples".to_string(),
+                is_final: true,
+            })]
+        );
+    }
+}
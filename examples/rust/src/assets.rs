@@ -0,0 +1,246 @@
+//! Pre-recorded audio cached plugin-side (`RegisterAudioAsset`,
+//! `PlayAudioAssetDirective`, v1.2+).
+//!
+//! [`AudioAssets`] keeps the clips a daemon says over and over (greetings,
+//! farewells, barks) and tracks which of them the plugin of the current
+//! connection has cached, from `Hello.cached_audio_assets` and the uploads
+//! sent since. [`AudioAssets::play`] uploads a clip only when the plugin
+//! lacks it; a play the plugin rejects with `REJECTION_CODE_ASSET_MISSING`
+//! is answered by [`AudioAssets::on_rejected`] with a fresh upload and the
+//! same play.
+
+use std::collections::{HashMap, HashSet};
+
+use bytes::Bytes;
+
+use crate::audio;
+use crate::npc_society::v1::{
+    AudioAssetRef, DirectiveRejected, Hello, PlayAudioAssetDirective, RegisterAudioAsset,
+    RejectionCode, ServerMessage, SpeakDirective,
+};
+use crate::validate::MAX_AUDIO_ASSET_BYTES;
+
+#[derive(Debug, Clone)]
+struct Asset {
+    hash: String,
+    pcm: Bytes,
+    duration_ms: i64,
+}
+
+/// Audio assets of a daemon and the plugin's cache of them
+#[derive(Debug, Default)]
+pub struct AudioAssets {
+    assets: HashMap<String, Asset>,
+    /// (asset_id, hash) the plugin has cached
+    cached: HashSet<(String, String)>,
+    /// Plays not yet answered, by the SpeakDirective's directive_id
+    pending: HashMap<String, PlayAudioAssetDirective>,
+}
+
+impl AudioAssets {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or replace the clip `asset_id` (mono f32 at `sample_rate_hz`).
+    /// Returns false if it is longer than [`MAX_AUDIO_ASSET_BYTES`] allows.
+    pub fn add(&mut self, asset_id: &str, samples: &[f32], sample_rate_hz: u32) -> bool {
+        let samples = audio::resample(samples, sample_rate_hz, audio::PROTOCOL_SAMPLE_RATE_HZ);
+        let pcm = audio::f32_to_s16le(&samples);
+        if pcm.len() > MAX_AUDIO_ASSET_BYTES {
+            return false;
+        }
+        let duration_ms = samples.len() as i64 * 1000 / audio::PROTOCOL_SAMPLE_RATE_HZ as i64;
+        self.assets.insert(
+            asset_id.to_string(),
+            Asset {
+                hash: content_hash(&pcm),
+                pcm: Bytes::from(pcm),
+                duration_ms,
+            },
+        );
+        true
+    }
+
+    /// Whether `asset_id` was added
+    pub fn contains(&self, asset_id: &str) -> bool {
+        self.assets.contains_key(asset_id)
+    }
+
+    /// Start of a connection: the plugin has what it listed, nothing else
+    pub fn on_hello(&mut self, hello: &Hello) {
+        self.cached = hello
+            .cached_audio_assets
+            .iter()
+            .map(|a| (a.asset_id.clone(), a.hash.clone()))
+            .collect();
+        self.pending.clear();
+    }
+
+    /// Messages that make the NPC say `asset_id` with `speak`'s subtitle
+    /// and routing: the upload if the plugin lacks the clip, then the play.
+    /// `speak.duration_ms` is set to the clip's length and its stream_id
+    /// cleared. None if no such asset was added.
+    pub fn play(
+        &mut self,
+        asset_id: &str,
+        mut speak: SpeakDirective,
+    ) -> Option<Vec<ServerMessage>> {
+        let asset = self.assets.get(asset_id)?;
+        speak.duration_ms = asset.duration_ms as i32;
+        speak.stream_id.clear();
+        let play = PlayAudioAssetDirective {
+            asset_id: asset_id.to_string(),
+            hash: asset.hash.clone(),
+            speak: Some(speak),
+        };
+        let mut messages = Vec::new();
+        if !self.is_cached(asset_id) {
+            messages.extend(self.register(asset_id));
+        }
+        if let Some(speak) = &play.speak {
+            self.pending
+                .insert(speak.directive_id.clone(), play.clone());
+        }
+        messages.push(play.into());
+        Some(messages)
+    }
+
+    /// Whether the plugin has the current version of `asset_id`
+    pub fn is_cached(&self, asset_id: &str) -> bool {
+        self.assets.get(asset_id).is_some_and(|a| {
+            self.cached
+                .contains(&(asset_id.to_string(), a.hash.clone()))
+        })
+    }
+
+    /// The play of `directive_id` was answered (SpeakResult)
+    pub fn finished(&mut self, directive_id: &str) {
+        self.pending.remove(directive_id);
+    }
+
+    /// Handle a rejection; for a play of a clip the plugin turned out not
+    /// to have, returns the upload and the play to send again (once)
+    pub fn on_rejected(&mut self, rejected: &DirectiveRejected) -> Option<Vec<ServerMessage>> {
+        if rejected.code() != RejectionCode::AssetMissing {
+            return None;
+        }
+        let mut play = self.pending.remove(&rejected.directive_id)?;
+        self.cached
+            .remove(&(play.asset_id.clone(), play.hash.clone()));
+        let register = self.register(&play.asset_id)?;
+        // The clip may have been replaced since; play what is uploaded now
+        play.hash = self.assets[&play.asset_id].hash.clone();
+        Some(vec![register, play.into()])
+    }
+
+    /// Upload `asset_id`, counting it as cached from now on
+    fn register(&mut self, asset_id: &str) -> Option<ServerMessage> {
+        let asset = self.assets.get(asset_id)?;
+        self.cached
+            .insert((asset_id.to_string(), asset.hash.clone()));
+        Some(
+            RegisterAudioAsset {
+                asset_id: asset_id.to_string(),
+                hash: asset.hash.clone(),
+                pcm_data: asset.pcm.clone(),
+                duration_ms: asset.duration_ms,
+            }
+            .into(),
+        )
+    }
+
+    /// What the plugin has cached, as it would list it in Hello
+    pub fn cached(&self) -> Vec<AudioAssetRef> {
+        let mut cached: Vec<AudioAssetRef> = self
+            .cached
+            .iter()
+            .map(|(asset_id, hash)| AudioAssetRef {
+                asset_id: asset_id.clone(),
+                hash: hash.clone(),
+            })
+            .collect();
+        cached.sort_by(|a, b| a.asset_id.cmp(&b.asset_id));
+        cached
+    }
+}
+
+/// FNV-1a of the PCM, as hex; stable across builds, unlike std's hasher
+fn content_hash(pcm: &[u8]) -> String {
+    let hash = pcm.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::server_message::Message as ServerMsg;
+
+    fn speak(directive_id: &str) -> SpeakDirective {
+        SpeakDirective {
+            npc_id: "guard".to_string(),
+            text: "Stay out of trouble.".to_string(),
+            directive_id: directive_id.to_string(),
+            stream_id: "s-1".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn kinds(messages: &[ServerMessage]) -> Vec<&'static str> {
+        messages
+            .iter()
+            .map(|m| match &m.message {
+                Some(ServerMsg::RegisterAudioAsset(_)) => "register",
+                Some(ServerMsg::PlayAudioAsset(_)) => "play",
+                _ => "other",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_asset_uploaded_once() {
+        let mut assets = AudioAssets::new();
+        assert!(assets.add("farewell", &[0.1; 24_000], 24_000));
+        assert!(assets.play("missing", speak("d-0")).is_none());
+
+        let first = assets.play("farewell", speak("d-1")).unwrap();
+        assert_eq!(kinds(&first), ["register", "play"]);
+        let Some(ServerMsg::PlayAudioAsset(play)) = &first[1].message else {
+            unreachable!();
+        };
+        let spoken = play.speak.as_ref().unwrap();
+        assert_eq!((spoken.duration_ms, spoken.stream_id.as_str()), (1_000, ""));
+        assert_eq!(
+            kinds(&assets.play("farewell", speak("d-2")).unwrap()),
+            ["play"]
+        );
+
+        // The plugin restarted and lost its cache
+        let rejected = DirectiveRejected {
+            directive_id: "d-2".to_string(),
+            code: RejectionCode::AssetMissing as i32,
+            ..Default::default()
+        };
+        assert_eq!(
+            kinds(&assets.on_rejected(&rejected).unwrap()),
+            ["register", "play"]
+        );
+        assert!(assets.on_rejected(&rejected).is_none());
+
+        // A new connection whose plugin kept the clip
+        let hello = Hello {
+            cached_audio_assets: assets.cached(),
+            ..Default::default()
+        };
+        assets.on_hello(&hello);
+        assert_eq!(
+            kinds(&assets.play("farewell", speak("d-3")).unwrap()),
+            ["play"]
+        );
+        assets.on_hello(&Hello::default());
+        assert!(!assets.is_cached("farewell"));
+    }
+}
@@ -0,0 +1,233 @@
+//! Audio format conversion helpers.
+//!
+//! The plugin sends 48kHz mono s16le PCM, while most ASR engines expect
+//! 16kHz mono f32 samples (and most TTS engines produce them). Plugins
+//! that can resample capture voice at a lower rate if the HelloAck asks
+//! for it; see [`negotiate_voice_rate`].
+
+// Defined in `connection` so that text-only builds can negotiate rates too
+pub use crate::connection::{negotiate_voice_rate, ASR_SAMPLE_RATE_HZ, PROTOCOL_SAMPLE_RATE_HZ};
+
+/// Decode 16-bit signed little-endian PCM into f32 samples in -1.0..1.0.
+/// A trailing odd byte is ignored.
+pub fn s16le_to_f32(pcm: &[u8]) -> Vec<f32> {
+    pcm.chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+        .collect()
+}
+
+/// Encode f32 samples as 16-bit signed little-endian PCM.
+/// Samples outside -1.0..=1.0 are clamped.
+pub fn f32_to_s16le(samples: &[f32]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|s| ((s.clamp(-1.0, 1.0) * 32767.0).round() as i16).to_le_bytes())
+        .collect()
+}
+
+/// Downmix interleaved stereo samples to mono by averaging both channels.
+/// A trailing unpaired sample is ignored.
+pub fn stereo_to_mono(samples: &[f32]) -> Vec<f32> {
+    samples
+        .chunks_exact(2)
+        .map(|lr| (lr[0] + lr[1]) * 0.5)
+        .collect()
+}
+
+/// Duplicate mono samples into interleaved stereo.
+pub fn mono_to_stereo(samples: &[f32]) -> Vec<f32> {
+    samples.iter().flat_map(|&s| [s, s]).collect()
+}
+
+/// Resample mono audio from `from_hz` to `to_hz`.
+///
+/// Integer downsampling ratios (48k -> 16k) average each group of input
+/// samples, which doubles as a simple anti-aliasing filter. Everything else
+/// uses linear interpolation. The function is stateless, so resample whole
+/// frames whose length is a multiple of the ratio (a 20ms frame always is)
+/// to avoid discontinuities at frame boundaries.
+pub fn resample(samples: &[f32], from_hz: u32, to_hz: u32) -> Vec<f32> {
+    if from_hz == to_hz || from_hz == 0 || to_hz == 0 || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let factor = from_hz / to_hz;
+    if factor > 1 && factor * to_hz == from_hz {
+        return samples
+            .chunks(factor as usize)
+            .map(|group| group.iter().sum::<f32>() / group.len() as f32)
+            .collect();
+    }
+
+    let out_len = (samples.len() as u64 * to_hz as u64 / from_hz as u64) as usize;
+    let step = from_hz as f64 / to_hz as f64;
+    let last = samples.len() - 1;
+
+    (0..out_len)
+        .map(|i| {
+            let pos = i as f64 * step;
+            let idx = (pos as usize).min(last);
+            let frac = (pos - idx as f64) as f32;
+            let next = samples[(idx + 1).min(last)];
+            samples[idx] + (next - samples[idx]) * frac
+        })
+        .collect()
+}
+
+/// Opus failed to set up a stream or to code a frame
+#[cfg(feature = "audio-opus")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpusError(pub String);
+
+#[cfg(feature = "audio-opus")]
+impl std::fmt::Display for OpusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "opus: {}", self.0)
+    }
+}
+
+#[cfg(feature = "audio-opus")]
+impl std::error::Error for OpusError {}
+
+/// Encoder for one mono Opus stream. Opus is stateful, so keep one per
+/// stream and feed it frames in order.
+#[cfg(feature = "audio-opus")]
+pub struct OpusEncoder {
+    inner: mousiki::Encoder,
+}
+
+#[cfg(feature = "audio-opus")]
+impl OpusEncoder {
+    /// `sample_rate_hz` must be one Opus supports (8, 12, 16, 24 or 48kHz)
+    pub fn new(sample_rate_hz: u32) -> Result<Self, OpusError> {
+        mousiki::Encoder::new(sample_rate_hz, mousiki::Channels::Mono, mousiki::Application::Voip)
+            .map(|inner| Self { inner })
+            .map_err(|e| OpusError(format!("{e:?}")))
+    }
+
+    /// Encode one s16le frame (20ms, as the protocol sends them) into one
+    /// Opus packet
+    pub fn encode_frame(&mut self, pcm: &[u8]) -> Result<Vec<u8>, OpusError> {
+        let samples: Vec<i16> = pcm
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        self.inner
+            .encode_vec(&samples, MAX_OPUS_PACKET_BYTES)
+            .map_err(|e| OpusError(format!("{e:?}")))
+    }
+}
+
+#[cfg(feature = "audio-opus")]
+impl std::fmt::Debug for OpusEncoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpusEncoder").finish_non_exhaustive()
+    }
+}
+
+/// Decoder for one mono Opus stream, e.g. one player's voice to one NPC
+#[cfg(feature = "audio-opus")]
+pub struct OpusDecoder {
+    inner: mousiki::Decoder,
+    sample_rate_hz: u32,
+}
+
+#[cfg(feature = "audio-opus")]
+impl OpusDecoder {
+    /// `sample_rate_hz` must be one Opus supports (8, 12, 16, 24 or 48kHz)
+    pub fn new(sample_rate_hz: u32) -> Result<Self, OpusError> {
+        mousiki::Decoder::new(sample_rate_hz, mousiki::Channels::Mono)
+            .map(|inner| Self { inner, sample_rate_hz })
+            .map_err(|e| OpusError(format!("{e:?}")))
+    }
+
+    /// Rate this decoder produces samples at
+    pub fn sample_rate_hz(&self) -> u32 {
+        self.sample_rate_hz
+    }
+
+    /// Decode one Opus packet into s16le PCM
+    pub fn decode_frame(&mut self, packet: &[u8]) -> Result<Vec<u8>, OpusError> {
+        // The longest packet Opus allows is 120ms
+        let mut samples = vec![0i16; self.sample_rate_hz as usize * 120 / 1000];
+        let decoded = self
+            .inner
+            .decode(packet, &mut samples, false)
+            .map_err(|e| OpusError(format!("{e:?}")))?;
+        Ok(samples[..decoded].iter().flat_map(|s| s.to_le_bytes()).collect())
+    }
+}
+
+#[cfg(feature = "audio-opus")]
+impl std::fmt::Debug for OpusDecoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpusDecoder")
+            .field("sample_rate_hz", &self.sample_rate_hz)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Upper bound of one encoded packet, as recommended by libopus
+#[cfg(feature = "audio-opus")]
+const MAX_OPUS_PACKET_BYTES: usize = 4000;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_s16le_roundtrip() {
+        let samples = vec![0.0, 0.5, -0.5, 1.0, -1.0];
+        let pcm = f32_to_s16le(&samples);
+        assert_eq!(pcm.len(), samples.len() * 2);
+
+        let decoded = s16le_to_f32(&pcm);
+        for (a, b) in samples.iter().zip(&decoded) {
+            assert!((a - b).abs() < 1e-3, "{a} != {b}");
+        }
+    }
+
+    #[test]
+    fn test_resample_20ms_frame_between_48k_and_16k() {
+        // 20ms at 48kHz
+        let frame = vec![0.25f32; 960];
+
+        let down = resample(&frame, PROTOCOL_SAMPLE_RATE_HZ, ASR_SAMPLE_RATE_HZ);
+        assert_eq!(down.len(), 320);
+        assert!(down.iter().all(|s| (s - 0.25).abs() < 1e-6));
+
+        let up = resample(&down, ASR_SAMPLE_RATE_HZ, PROTOCOL_SAMPLE_RATE_HZ);
+        assert_eq!(up.len(), 960);
+        assert!(up.iter().all(|s| (s - 0.25).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_stereo_mono_conversion() {
+        let stereo = mono_to_stereo(&[0.1, 0.2]);
+        assert_eq!(stereo, vec![0.1, 0.1, 0.2, 0.2]);
+        assert_eq!(stereo_to_mono(&[0.0, 1.0, -1.0, 1.0]), vec![0.5, 0.0]);
+    }
+
+    #[cfg(feature = "audio-opus")]
+    #[test]
+    fn test_opus_frame_roundtrip() {
+        let mut encoder = OpusEncoder::new(PROTOCOL_SAMPLE_RATE_HZ).unwrap();
+        let mut decoder = OpusDecoder::new(PROTOCOL_SAMPLE_RATE_HZ).unwrap();
+
+        // A 440Hz tone, 20ms per frame; the codec needs a few frames to settle
+        let mut energy = 0.0;
+        for frame in 0..10 {
+            let tone: Vec<f32> = (0..960)
+                .map(|i| ((frame * 960 + i) as f32 * 440.0 * std::f32::consts::TAU / 48_000.0).sin() * 0.25)
+                .collect();
+            let packet = encoder.encode_frame(&f32_to_s16le(&tone)).unwrap();
+            assert!(packet.len() < 960 * 2, "{} bytes is no compression", packet.len());
+
+            let pcm = decoder.decode_frame(&packet).unwrap();
+            assert_eq!(pcm.len(), 960 * 2);
+            energy = s16le_to_f32(&pcm).iter().map(|s| s * s).sum::<f32>() / 960.0;
+        }
+        // A sine of amplitude 0.25 has a mean square of 0.03125
+        assert!((energy - 0.03125).abs() < 0.01, "mean square {energy}");
+    }
+}
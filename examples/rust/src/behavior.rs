@@ -0,0 +1,434 @@
+//! Behavior trees for NPC autonomy.
+//!
+//! A [`BehaviorTree`] drives one NPC. Every `WorldTick` refreshes its
+//! [`Blackboard`] and ticks the tree; action leaves emit an
+//! `ActionDirective` and stay running until the matching `ActionResult`
+//! is delivered, so multi-step behavior ("scan, then break what was found,
+//! then deposit") is a declarative tree instead of hand-written state.
+//!
+//! Composites remember their running child between ticks. When the root
+//! finishes, the tree resets and starts over on the next tick.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::ids::DirectiveIdFactory;
+use crate::npc_society::v1::{
+    action_directive::Action, ActionDirective, ActionResult, NpcSnapshot, PlayerSnapshot, WorldTick,
+};
+
+/// Outcome of ticking a node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Still in progress; tick again later
+    Running,
+    /// Finished successfully
+    Success,
+    /// Finished unsuccessfully
+    Failure,
+}
+
+/// What the tree knows about its NPC
+#[derive(Debug, Clone, Default)]
+pub struct Blackboard {
+    /// Latest snapshot of the NPC
+    pub npc: NpcSnapshot,
+    /// server_tick of the latest WorldTick
+    pub server_tick: i64,
+    /// Players reported in the latest WorldTick
+    pub nearby_players: Vec<PlayerSnapshot>,
+    results: HashMap<String, ActionResult>,
+}
+
+impl Blackboard {
+    /// Result of the action leaf called `name` in the current run of the tree
+    pub fn result(&self, name: &str) -> Option<&ActionResult> {
+        self.results.get(name)
+    }
+
+    pub(crate) fn for_npc(npc_id: &str) -> Self {
+        Self {
+            npc: NpcSnapshot {
+                npc_id: npc_id.to_string(),
+                ..NpcSnapshot::default()
+            },
+            ..Self::default()
+        }
+    }
+
+    /// Take the NPC's snapshot and the players from `tick`. Returns false
+    /// if the tick doesn't mention the NPC.
+    pub(crate) fn refresh(&mut self, tick: &WorldTick) -> bool {
+        let Some(npc) = tick.npcs.iter().find(|npc| npc.npc_id == self.npc.npc_id) else {
+            return false;
+        };
+        self.npc = npc.clone();
+        self.server_tick = tick.server_tick;
+        self.nearby_players = tick.nearby_players.clone();
+        true
+    }
+
+    pub(crate) fn record(&mut self, name: String, result: &ActionResult) {
+        self.results.insert(name, result.clone());
+    }
+
+    pub(crate) fn clear_results(&mut self) {
+        self.results.clear();
+    }
+}
+
+type ConditionFn = Box<dyn Fn(&Blackboard) -> bool + Send + Sync>;
+type ActionFn = Box<dyn Fn(&Blackboard) -> Option<Action> + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LeafState {
+    Idle,
+    Waiting(String),
+    Done(bool),
+}
+
+enum NodeKind {
+    Sequence {
+        children: Vec<Node>,
+        current: usize,
+    },
+    Selector {
+        children: Vec<Node>,
+        current: usize,
+    },
+    Condition(ConditionFn),
+    Action {
+        name: String,
+        priority: i32,
+        make: ActionFn,
+        state: LeafState,
+    },
+}
+
+/// A node of a [`BehaviorTree`]; build with [`sequence`], [`selector`],
+/// [`condition`] and [`action`].
+pub struct Node {
+    kind: NodeKind,
+}
+
+/// Runs children in order until one fails. Fails if any child fails.
+pub fn sequence(children: Vec<Node>) -> Node {
+    Node {
+        kind: NodeKind::Sequence {
+            children,
+            current: 0,
+        },
+    }
+}
+
+/// Runs children in order until one succeeds. Fails if all children fail.
+pub fn selector(children: Vec<Node>) -> Node {
+    Node {
+        kind: NodeKind::Selector {
+            children,
+            current: 0,
+        },
+    }
+}
+
+/// Succeeds if `check` returns true, fails otherwise.
+pub fn condition(check: impl Fn(&Blackboard) -> bool + Send + Sync + 'static) -> Node {
+    Node {
+        kind: NodeKind::Condition(Box::new(check)),
+    }
+}
+
+/// Sends the action returned by `make` and waits for its ActionResult.
+///
+/// Succeeds or fails with the result's `success`; the result is available
+/// as `blackboard.result(name)` to later nodes. Fails immediately if
+/// `make` returns None.
+pub fn action(
+    name: &str,
+    priority: i32,
+    make: impl Fn(&Blackboard) -> Option<Action> + Send + Sync + 'static,
+) -> Node {
+    Node {
+        kind: NodeKind::Action {
+            name: name.to_string(),
+            priority,
+            make: Box::new(make),
+            state: LeafState::Idle,
+        },
+    }
+}
+
+impl Node {
+    fn tick(&mut self, ctx: &mut TickContext<'_>) -> Status {
+        match &mut self.kind {
+            NodeKind::Sequence { children, current } => {
+                Self::tick_composite(children, current, ctx, Status::Success)
+            }
+            NodeKind::Selector { children, current } => {
+                Self::tick_composite(children, current, ctx, Status::Failure)
+            }
+            NodeKind::Condition(check) => Self::status(check(ctx.blackboard)),
+            NodeKind::Action {
+                priority,
+                make,
+                state,
+                ..
+            } => match state.clone() {
+                LeafState::Idle => match make(ctx.blackboard) {
+                    Some(action) => {
+                        let directive_id = ctx.next_id();
+                        ctx.directives.push(ActionDirective {
+                            directive_id: directive_id.clone(),
+                            npc_id: ctx.blackboard.npc.npc_id.clone(),
+                            priority: *priority,
+                            action: Some(action),
+                            ..Default::default()
+                        });
+                        *state = LeafState::Waiting(directive_id);
+                        Status::Running
+                    }
+                    None => Status::Failure,
+                },
+                LeafState::Waiting(_) => Status::Running,
+                LeafState::Done(success) => {
+                    *state = LeafState::Idle;
+                    Self::status(success)
+                }
+            },
+        }
+    }
+
+    fn status(success: bool) -> Status {
+        if success {
+            Status::Success
+        } else {
+            Status::Failure
+        }
+    }
+
+    /// Shared logic of sequence (`done_on` = Success) and selector
+    /// (`done_on` = Failure): continue while children return `done_on`.
+    fn tick_composite(
+        children: &mut [Node],
+        current: &mut usize,
+        ctx: &mut TickContext<'_>,
+        done_on: Status,
+    ) -> Status {
+        while let Some(child) = children.get_mut(*current) {
+            let status = child.tick(ctx);
+            if status != done_on {
+                if status != Status::Running {
+                    *current = 0;
+                }
+                return status;
+            }
+            *current += 1;
+        }
+        *current = 0;
+        done_on
+    }
+
+    /// Mark the leaf waiting on `result` as done. Returns its name.
+    fn deliver(&mut self, result: &ActionResult) -> Option<String> {
+        match &mut self.kind {
+            NodeKind::Sequence { children, .. } | NodeKind::Selector { children, .. } => {
+                children.iter_mut().find_map(|child| child.deliver(result))
+            }
+            NodeKind::Condition(_) => None,
+            NodeKind::Action { name, state, .. } => match state {
+                LeafState::Waiting(id) if *id == result.directive_id => {
+                    *state = LeafState::Done(result.success);
+                    Some(name.clone())
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+impl fmt::Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            NodeKind::Sequence { children, current } => f
+                .debug_struct("Sequence")
+                .field("current", current)
+                .field("children", children)
+                .finish(),
+            NodeKind::Selector { children, current } => f
+                .debug_struct("Selector")
+                .field("current", current)
+                .field("children", children)
+                .finish(),
+            NodeKind::Condition(_) => f.write_str("Condition"),
+            NodeKind::Action { name, state, .. } => f
+                .debug_struct("Action")
+                .field("name", name)
+                .field("state", state)
+                .finish(),
+        }
+    }
+}
+
+struct TickContext<'a> {
+    blackboard: &'a Blackboard,
+    directives: Vec<ActionDirective>,
+    counter: &'a mut u64,
+    ids: Option<&'a DirectiveIdFactory>,
+}
+
+impl TickContext<'_> {
+    fn next_id(&mut self) -> String {
+        let npc_id = &self.blackboard.npc.npc_id;
+        if let Some(ids) = self.ids {
+            return ids.next_for(npc_id, "bt");
+        }
+        *self.counter += 1;
+        format!("{}-bt-{}", npc_id, self.counter)
+    }
+}
+
+/// A behavior tree bound to one NPC.
+#[derive(Debug)]
+pub struct BehaviorTree {
+    root: Node,
+    blackboard: Blackboard,
+    counter: u64,
+    ids: Option<Arc<DirectiveIdFactory>>,
+}
+
+impl BehaviorTree {
+    /// Create a tree for `npc_id`
+    pub fn new(npc_id: &str, root: Node) -> Self {
+        Self {
+            root,
+            blackboard: Blackboard::for_npc(npc_id),
+            counter: 0,
+            ids: None,
+        }
+    }
+
+    /// Take directive ids from `ids` instead of the tree's own counter,
+    /// which starts over with every new tree
+    pub fn with_ids(mut self, ids: Arc<DirectiveIdFactory>) -> Self {
+        self.ids = Some(ids);
+        self
+    }
+
+    /// What the tree currently knows
+    pub fn blackboard(&self) -> &Blackboard {
+        &self.blackboard
+    }
+
+    /// Refresh the blackboard from `tick` and tick the tree.
+    /// Returns the directives to send; directive_ids are `<npc_id>-bt-<n>`,
+    /// or come from the factory given to [`BehaviorTree::with_ids`].
+    pub fn on_world_tick(&mut self, tick: &WorldTick) -> Vec<ActionDirective> {
+        if !self.blackboard.refresh(tick) {
+            return Vec::new();
+        }
+
+        let mut ctx = TickContext {
+            blackboard: &self.blackboard,
+            directives: Vec::new(),
+            counter: &mut self.counter,
+            ids: self.ids.as_deref(),
+        };
+        let status = self.root.tick(&mut ctx);
+        let directives = ctx.directives;
+
+        if status != Status::Running {
+            // Start the next run with a clean slate
+            self.blackboard.clear_results();
+        }
+        directives
+    }
+
+    /// Deliver an ActionResult. Returns false if no leaf was waiting for it.
+    pub fn on_action_result(&mut self, result: &ActionResult) -> bool {
+        match self.root.deliver(result) {
+            Some(name) => {
+                self.blackboard.record(name, result);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{LookAction, StopAction};
+
+    fn tick(server_tick: i64) -> WorldTick {
+        WorldTick {
+            server_tick,
+            npcs: vec![NpcSnapshot {
+                npc_id: "npc".to_string(),
+                ..NpcSnapshot::default()
+            }],
+            ..WorldTick::default()
+        }
+    }
+
+    fn result(directive_id: &str, success: bool) -> ActionResult {
+        ActionResult {
+            directive_id: directive_id.to_string(),
+            npc_id: "npc".to_string(),
+            success,
+            ..ActionResult::default()
+        }
+    }
+
+    fn look() -> Option<Action> {
+        Some(Action::Look(LookAction::default()))
+    }
+
+    #[test]
+    fn test_sequence_waits_for_results() {
+        let mut tree = BehaviorTree::new(
+            "npc",
+            sequence(vec![
+                action("first", 1, |_| look()),
+                action("second", 1, |bb| {
+                    // Only runs once the first result is known
+                    bb.result("first")?;
+                    Some(Action::Stop(StopAction::default()))
+                }),
+            ]),
+        );
+
+        let sent = tree.on_world_tick(&tick(1));
+        assert_eq!(sent.len(), 1);
+        assert!(tree.on_world_tick(&tick(2)).is_empty());
+
+        assert!(tree.on_action_result(&result(&sent[0].directive_id, true)));
+        let sent = tree.on_world_tick(&tick(3));
+        assert!(matches!(sent[0].action, Some(Action::Stop(_))));
+        assert!(!tree.on_action_result(&result("unknown", true)));
+    }
+
+    #[test]
+    fn test_selector_falls_back_on_failure() {
+        let mut tree = BehaviorTree::new(
+            "npc",
+            selector(vec![
+                sequence(vec![
+                    condition(|bb| bb.server_tick % 2 == 0),
+                    action("even", 1, |_| look()),
+                ]),
+                action("fallback", 1, |_| Some(Action::Stop(StopAction::default()))),
+            ]),
+        );
+
+        let sent = tree.on_world_tick(&tick(1));
+        assert!(matches!(sent[0].action, Some(Action::Stop(_))));
+
+        // Failed action finishes the run; the next run starts from the top
+        tree.on_action_result(&result(&sent[0].directive_id, false));
+        assert!(tree.on_world_tick(&tick(3)).is_empty());
+        let sent = tree.on_world_tick(&tick(4));
+        assert!(matches!(sent[0].action, Some(Action::Look(_))));
+    }
+}
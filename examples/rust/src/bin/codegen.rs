@@ -0,0 +1,45 @@
+//! Python and TypeScript handler bindings from the protocol schema.
+//!
+//! ```text
+//! cargo run --bin codegen -- python|typescript [OUT]
+//! ```
+//!
+//! Writes to OUT, or to stdout without one. See `codegen` in the library
+//! for what the bindings contain and where they go.
+
+use std::process::ExitCode;
+
+use npc_society_example::codegen::{self, Language};
+use npc_society_example::compat;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (language, out) = match &args[..] {
+        [language] => (language, None),
+        [language, out] => (language, Some(out)),
+        _ => {
+            eprintln!("usage: codegen python|typescript [OUT]");
+            return ExitCode::from(2);
+        }
+    };
+    let code = match language
+        .parse::<Language>()
+        .and_then(|language| codegen::generate(language, &compat::current()))
+    {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+    match out {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, code) {
+                eprintln!("{}: {}", path, e);
+                return ExitCode::FAILURE;
+            }
+        }
+        None => print!("{}", code),
+    }
+    ExitCode::SUCCESS
+}
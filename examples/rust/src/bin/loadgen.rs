@@ -0,0 +1,233 @@
+//! Load generator: connects to a daemon as LOADGEN_SERVERS fake plugins and
+//! reports how fast it answers.
+//!
+//! ```text
+//! LOADGEN_SERVERS=10 LOADGEN_NPCS=100 cargo run --release --bin loadgen
+//! ```
+//!
+//! Every connection streams WorldTicks, chat and voice frames as described
+//! by a `LoadProfile` (LOADGEN_NPCS, LOADGEN_PLAYERS, LOADGEN_TICK_HZ,
+//! LOADGEN_CHATS_PER_MINUTE, LOADGEN_VOICE_STREAMS) for LOADGEN_SECONDS,
+//! then the totals are printed: messages each way, Hello to HelloAck and
+//! chat to SpeakDirective latency percentiles. LOADGEN_HTTP2=wan uses the
+//! HTTP/2 windows of `Http2Config::wan` for a daemon far away.
+
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+use tokio::time::MissedTickBehavior;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use tonic::transport::Channel;
+
+use npc_society_example::http2::Http2Config;
+use npc_society_example::loadgen::{
+    LatencyRecorder, LatencySummary, LoadProfile, RoundTrips, SyntheticServer, VOICE_FRAME_MS,
+};
+use npc_society_example::npc_society::v1::{
+    client_message::Message as ClientMsg, npc_society_service_client::NpcSocietyServiceClient,
+    server_message::Message as ServerMsg, ClientMessage,
+};
+use npc_society_example::time::now_ms;
+
+/// How long to wait for late replies after the last message was sent
+const DRAIN: Duration = Duration::from_secs(2);
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Results of all connections
+#[derive(Debug, Default)]
+struct Totals {
+    sent: u64,
+    received: u64,
+    unanswered: usize,
+    handshake: LatencyRecorder,
+    chat: LatencyRecorder,
+    failed: usize,
+}
+
+/// `name` from the environment, or `default` if unset or unparsable
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Stream `server`'s traffic for `duration` and add the results to `totals`
+async fn run(
+    channel: Channel,
+    mut server: SyntheticServer,
+    profile: LoadProfile,
+    duration: Duration,
+    totals: Arc<Mutex<Totals>>,
+) -> Result<(), BoxError> {
+    let (tx, rx) = mpsc::channel::<ClientMessage>(1_024);
+    let mut client = NpcSocietyServiceClient::new(channel);
+
+    let started = Instant::now();
+    tx.send(server.hello()).await?;
+    let mut inbound = client.connect(ReceiverStream::new(rx)).await?.into_inner();
+
+    let trips = Arc::new(Mutex::new(RoundTrips::<Instant>::default()));
+    let sender = {
+        let trips = trips.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(profile.tick_interval());
+            let mut voice = tokio::time::interval(Duration::from_millis(VOICE_FRAME_MS));
+            let chat_interval = profile.chat_interval();
+            let mut chats = tokio::time::interval(chat_interval.unwrap_or(duration));
+            // A daemon that falls behind slows the sender down rather than
+            // getting bursts to catch up, so the sent rate shows it
+            for interval in [&mut ticks, &mut voice, &mut chats] {
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            }
+            let deadline = tokio::time::sleep(duration);
+            tokio::pin!(deadline);
+
+            let mut sent = 1; // the Hello
+            loop {
+                let messages = tokio::select! {
+                    _ = &mut deadline => break,
+                    _ = ticks.tick() => vec![server.world_tick(now_ms())],
+                    _ = voice.tick(), if profile.voice_streams > 0 => server.voice_frames(now_ms()),
+                    _ = chats.tick(), if chat_interval.is_some() => {
+                        let chat = server.chat(now_ms());
+                        if let Some(ClientMsg::ChatObservation(c)) = &chat.message {
+                            trips.lock().unwrap().sent(&c.npc_id, Instant::now());
+                        }
+                        vec![chat]
+                    }
+                };
+                for message in messages {
+                    if tx.send(message).await.is_err() {
+                        return sent;
+                    }
+                    sent += 1;
+                }
+            }
+            sent
+        })
+    };
+
+    let mut handshake = LatencyRecorder::default();
+    let mut chat = LatencyRecorder::default();
+    let mut received = 0;
+    let finished = async {
+        let sent = sender.await;
+        tokio::time::sleep(DRAIN).await;
+        sent
+    };
+    tokio::pin!(finished);
+    let sent = loop {
+        tokio::select! {
+            sent = &mut finished => break sent?,
+            message = inbound.next() => {
+                let Some(message) = message.transpose()? else {
+                    break (&mut finished).await?;
+                };
+                received += 1;
+                match message.message {
+                    Some(ServerMsg::HelloAck(_)) => handshake.record(started.elapsed()),
+                    Some(ServerMsg::SpeakDirective(speak)) => {
+                        if let Some(sent) = trips.lock().unwrap().answered(&speak.npc_id) {
+                            chat.record(sent.elapsed());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    };
+
+    let mut totals = totals.lock().unwrap();
+    totals.sent += sent;
+    totals.received += received;
+    totals.unanswered += trips.lock().unwrap().unanswered();
+    totals.handshake.merge(&handshake);
+    totals.chat.merge(&chat);
+    Ok(())
+}
+
+fn describe(summary: LatencySummary) -> String {
+    if summary.count == 0 {
+        return "no samples".to_string();
+    }
+    format!(
+        "n={} p50={:?} p90={:?} p99={:?} max={:?}",
+        summary.count, summary.p50, summary.p90, summary.p99, summary.max
+    )
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let host = std::env::var("DAEMON_HOST").unwrap_or_else(|_| "localhost".to_string());
+    let port: u16 = env_or("DAEMON_PORT", 50051);
+    let servers: usize = env_or("LOADGEN_SERVERS", 10);
+    let duration = Duration::from_secs(env_or("LOADGEN_SECONDS", 60));
+    let defaults = LoadProfile::default();
+    let profile = LoadProfile {
+        npcs: env_or("LOADGEN_NPCS", defaults.npcs),
+        players: env_or("LOADGEN_PLAYERS", defaults.players),
+        tick_hz: env_or("LOADGEN_TICK_HZ", defaults.tick_hz),
+        chats_per_minute: env_or("LOADGEN_CHATS_PER_MINUTE", defaults.chats_per_minute),
+        voice_streams: env_or("LOADGEN_VOICE_STREAMS", defaults.voice_streams),
+    };
+    println!(
+        "Loading {}:{} with {} servers x {} NPCs for {:?}: {:?}",
+        host, port, servers, profile.npcs, duration, profile
+    );
+
+    let http2 = match std::env::var("LOADGEN_HTTP2").as_deref() {
+        Ok("wan") => Http2Config::wan(),
+        Ok("lan") | Err(_) => Http2Config::lan(),
+        Ok(other) => return Err(format!("LOADGEN_HTTP2={} (expected lan or wan)", other).into()),
+    };
+
+    let totals = Arc::new(Mutex::new(Totals::default()));
+    let mut connections = Vec::new();
+    for i in 0..servers {
+        // One HTTP/2 connection per server, like real plugins
+        let channel = http2
+            .endpoint(Channel::from_shared(format!("http://{}:{}", host, port))?)
+            .connect()
+            .await?;
+        let server = SyntheticServer::new(&format!("load_{}", i), profile, i as u64 + 1);
+        let totals = totals.clone();
+        connections.push(tokio::spawn(run(
+            channel, server, profile, duration, totals,
+        )));
+    }
+    for (i, connection) in connections.into_iter().enumerate() {
+        if let Err(e) = connection.await? {
+            eprintln!("load_{}: {}", i, e);
+            totals.lock().unwrap().failed += 1;
+        }
+    }
+
+    let totals = totals.lock().unwrap();
+    let seconds = duration.as_secs_f64().max(1.0);
+    println!(
+        "Messages: {} sent ({:.0}/s), {} received ({:.0}/s)",
+        totals.sent,
+        totals.sent as f64 / seconds,
+        totals.received,
+        totals.received as f64 / seconds
+    );
+    println!(
+        "Hello -> HelloAck: {}",
+        describe(totals.handshake.summary())
+    );
+    println!(
+        "Chat -> SpeakDirective: {}",
+        describe(totals.chat.summary())
+    );
+    println!("Chats unanswered: {}", totals.unanswered);
+    if totals.failed > 0 {
+        println!("Connections failed: {}", totals.failed);
+    }
+    Ok(())
+}
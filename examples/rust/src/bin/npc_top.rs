@@ -0,0 +1,253 @@
+//! npc-top: live view of a daemon's servers and NPCs.
+//!
+//! ```text
+//! cargo run --features tui --bin npc-top -- [http://127.0.0.1:50051]
+//! ```
+//!
+//! Polls `ListServers`, `ListNpcs` and `GetNpcState` once a second and
+//! shows each server's connection, message rates and outbound queue, and
+//! each NPC's position, current directive, last result and pending
+//! directives (see `top` for the columns). `d` types a directive
+//! (`<npc_id> <action> [args]`), Enter sends it with `SendDirective` and
+//! Esc cancels; `q` quits.
+
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use tonic::transport::Channel;
+
+use npc_society_example::npc_society::v1::npc_society_service_client::NpcSocietyServiceClient;
+use npc_society_example::npc_society::v1::{
+    GetNpcStateRequest, ListNpcsRequest, ListServersRequest, NpcSnapshot, SendDirectiveRequest,
+};
+use npc_society_example::time::now_ms;
+use npc_society_example::top::{self, Monitor, NpcRow, ServerRow};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct App {
+    monitor: Monitor,
+    servers: Vec<ServerRow>,
+    npcs: Vec<NpcRow>,
+    /// Latest snapshots, for the world of typed coordinates
+    snapshots: Vec<NpcSnapshot>,
+    /// Directive being typed
+    input: Option<String>,
+    /// Outcome of the last poll or directive
+    status: String,
+}
+
+impl App {
+    async fn poll(
+        &mut self,
+        client: &mut NpcSocietyServiceClient<Channel>,
+    ) -> Result<(), tonic::Status> {
+        let list = client
+            .list_servers(ListServersRequest {})
+            .await?
+            .into_inner();
+        self.servers = self.monitor.servers(&list, now_ms());
+        let mut npcs = Vec::new();
+        let mut snapshots = Vec::new();
+        for info in list.servers.iter().filter(|info| info.connected) {
+            let server_id = top::server_id(info).to_string();
+            let npc_ids = client
+                .list_npcs(ListNpcsRequest {
+                    server_id: server_id.clone(),
+                })
+                .await?
+                .into_inner()
+                .npc_ids;
+            for npc_id in npc_ids {
+                let request = GetNpcStateRequest {
+                    npc_id,
+                    server_id: server_id.clone(),
+                };
+                // NPCs that left between the two calls are skipped
+                let Ok(state) = client.get_npc_state(request).await else {
+                    continue;
+                };
+                let state = state.into_inner();
+                npcs.push(top::npc_row(&server_id, &state));
+                snapshots.extend(state.npc);
+            }
+        }
+        self.npcs = npcs;
+        self.snapshots = snapshots;
+        Ok(())
+    }
+
+    /// Send a typed directive; the status line to show
+    async fn send(&self, client: &mut NpcSocietyServiceClient<Channel>, line: &str) -> String {
+        let directive = match top::parse_directive(line, &self.snapshots) {
+            Ok(directive) => directive,
+            Err(e) => return e.to_string(),
+        };
+        let server_id = self
+            .npcs
+            .iter()
+            .find(|row| row.npc_id == directive.npc_id)
+            .map(|row| row.server_id.clone())
+            .unwrap_or_default();
+        let request = SendDirectiveRequest {
+            directive: Some(directive),
+            server_id,
+        };
+        match client.send_directive(request).await {
+            Ok(response) => format!("sent {}", response.into_inner().directive_id),
+            Err(status) => format!("refused: {}", status.message()),
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, app: &App, address: &str) {
+    let [servers_area, npcs_area, input_area] = Layout::vertical([
+        Constraint::Length(app.servers.len() as u16 + 3),
+        Constraint::Min(5),
+        Constraint::Length(3),
+    ])
+    .areas(frame.area());
+    let header = |cells: &[&'static str]| {
+        Row::new(cells.to_vec()).style(Style::new().add_modifier(Modifier::BOLD))
+    };
+
+    let servers = app.servers.iter().map(|s| {
+        Row::new(vec![
+            s.server_id.clone(),
+            if s.connected { "up" } else { "down" }.to_string(),
+            s.peer_address.clone(),
+            format!("{:.1}", s.inbound_rate),
+            format!("{:.1}", s.outbound_rate),
+            s.queue_depth.to_string(),
+            format!("{} ms", s.clock_offset_ms),
+        ])
+    });
+    let servers = Table::new(
+        servers,
+        [
+            Constraint::Fill(2),
+            Constraint::Length(5),
+            Constraint::Fill(2),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Length(6),
+            Constraint::Length(10),
+        ],
+    )
+    .header(header(&[
+        "server", "", "peer", "in/s", "out/s", "queue", "offset",
+    ]))
+    .block(Block::bordered().title(format!(" npc-top {} ", address)));
+    frame.render_widget(servers, servers_area);
+
+    let npcs = app.npcs.iter().map(|n| {
+        Row::new(vec![
+            n.server_id.clone(),
+            n.npc_id.clone(),
+            n.position.clone(),
+            n.current.clone(),
+            n.last_result.clone(),
+            n.queue_depth.to_string(),
+        ])
+    });
+    let npcs = Table::new(
+        npcs,
+        [
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Fill(2),
+            Constraint::Fill(2),
+            Constraint::Fill(3),
+            Constraint::Length(7),
+        ],
+    )
+    .header(header(&[
+        "server",
+        "npc",
+        "position",
+        "current",
+        "last result",
+        "pending",
+    ]))
+    .block(Block::bordered().title(" NPCs "));
+    frame.render_widget(npcs, npcs_area);
+
+    let (text, title) = match &app.input {
+        Some(line) => (
+            format!("> {}", line),
+            " directive: <npc_id> <action> [args], Enter sends, Esc cancels ",
+        ),
+        None => (app.status.clone(), " d: send directive  q: quit "),
+    };
+    frame.render_widget(
+        Paragraph::new(text).block(Block::bordered().title(title)),
+        input_area,
+    );
+}
+
+async fn run(
+    terminal: &mut DefaultTerminal,
+    client: &mut NpcSocietyServiceClient<Channel>,
+    address: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut app = App::default();
+    let mut next_poll = Instant::now();
+    loop {
+        if Instant::now() >= next_poll {
+            if let Err(status) = app.poll(client).await {
+                app.status = format!("poll failed: {}", status.message());
+            }
+            next_poll = Instant::now() + POLL_INTERVAL;
+        }
+        terminal.draw(|frame| draw(frame, &app, address))?;
+
+        if !event::poll(Duration::from_millis(100))? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Ok(());
+        }
+        match (&mut app.input, key.code) {
+            (None, KeyCode::Char('q')) => return Ok(()),
+            (None, KeyCode::Char('d')) => app.input = Some(String::new()),
+            (Some(_), KeyCode::Esc) => app.input = None,
+            (Some(line), KeyCode::Char(c)) => line.push(c),
+            (Some(line), KeyCode::Backspace) => {
+                line.pop();
+            }
+            (Some(_), KeyCode::Enter) => {
+                let line = app.input.take().unwrap_or_default();
+                app.status = app.send(client, &line).await;
+                // Show the directive as pending right away
+                next_poll = Instant::now();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "http://127.0.0.1:50051".to_string());
+    let channel = Channel::from_shared(address.clone())?.connect().await?;
+    let mut client = NpcSocietyServiceClient::new(channel);
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut client, &address).await;
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,61 @@
+//! Schema compatibility check: what would break between two versions of
+//! the protocol.
+//!
+//! ```text
+//! buf build -o old.binpb            # on the version peers run today
+//! cargo run --bin schema-check -- old.binpb [new.binpb]
+//! ```
+//!
+//! Without a second file the check runs against the schema this binary
+//! was built from. Prints every finding (see `compat` for the rules) and
+//! exits with 1 if any change breaks compatibility, so it can gate CI.
+//! Descriptor sets made by `protoc` need `--include_source_info` for the
+//! version note check.
+
+use std::process::ExitCode;
+
+use npc_society_example::compat::{self, Severity};
+use prost_types::FileDescriptorSet;
+
+fn load(path: &str) -> Result<FileDescriptorSet, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    compat::load(&bytes).map_err(|e| format!("{}: not a descriptor set: {}", path, e))
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (old, new) = match &args[..] {
+        [old] => (load(old), Ok(compat::current())),
+        [old, new] => (load(old), load(new)),
+        _ => {
+            eprintln!("usage: schema-check OLD.binpb [NEW.binpb]");
+            return ExitCode::from(2);
+        }
+    };
+    let (old, new) = match (old, new) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let findings = compat::check(&old, &new);
+    for finding in &findings {
+        println!("{}", finding);
+    }
+    let breaking = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Breaking)
+        .count();
+    println!(
+        "{} breaking, {} warnings",
+        breaking,
+        findings.len() - breaking
+    );
+    if breaking > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,176 @@
+//! Entries of `ScanBlocksAction.block_types` (v1.2+).
+//!
+//! An entry is an exact block id (`minecraft:diamond_ore`), a block tag
+//! (`#minecraft:logs`) or a pattern in which `*` matches any run of
+//! characters (`minecraft:*_ore`). The plugin expands tags and patterns
+//! against its registry, so new blocks are found without a daemon update,
+//! and reports the entry that matched in `BlockMatch.matched_by`.
+//!
+//! [`BlockPattern::parse`] catches malformed entries before they are sent.
+//! The daemon has no block registry: [`BlockPattern::matches`] can test
+//! ids and patterns locally (against a [`Region`](crate::region::Region),
+//! say) but cannot tell for tags.
+//!
+//! Entity types in a combat policy's `TargetFilter` use the same syntax
+//! (`#minecraft:undead`, `minecraft:*zombie`).
+
+use std::fmt;
+
+/// A parsed `block_types` entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockPattern {
+    /// An exact id, e.g. `minecraft:stone`
+    Id(String),
+    /// A block tag without the `#`, e.g. `minecraft:logs`
+    Tag(String),
+    /// An id containing `*` wildcards, e.g. `minecraft:*_ore`
+    Wildcard(String),
+}
+
+/// Why an entry is not a valid id, tag or pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError {
+    /// The entry as given
+    pub entry: String,
+    /// What is wrong with it
+    pub reason: &'static str,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid block pattern {:?}: {}", self.entry, self.reason)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl BlockPattern {
+    /// Parse an entry. Ids may omit the `minecraft:` namespace, as in
+    /// commands; tags cannot contain wildcards.
+    pub fn parse(entry: &str) -> Result<Self, PatternError> {
+        let error = |reason| PatternError {
+            entry: entry.to_string(),
+            reason,
+        };
+        let (tag, id) = match entry.strip_prefix('#') {
+            Some(id) => (true, id),
+            None => (false, entry),
+        };
+        if id.is_empty() {
+            return Err(error("empty"));
+        }
+        if id.matches(':').count() > 1 {
+            return Err(error("more than one ':'"));
+        }
+        if id.starts_with(':') || id.ends_with(':') {
+            return Err(error("empty namespace or path"));
+        }
+        let allowed =
+            |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || "_-./:*".contains(c);
+        if !id.chars().all(allowed) {
+            return Err(error(
+                "only a-z, 0-9, '_', '-', '.', '/', ':' and '*' are allowed",
+            ));
+        }
+        let wildcard = id.contains('*');
+        match (tag, wildcard) {
+            (true, true) => Err(error("tags cannot contain '*'")),
+            (true, false) => Ok(Self::Tag(id.to_string())),
+            (false, true) => Ok(Self::Wildcard(id.to_string())),
+            (false, false) => Ok(Self::Id(id.to_string())),
+        }
+    }
+
+    /// Whether `block_type` matches, or None for tags, which only the
+    /// plugin can resolve
+    pub fn matches(&self, block_type: &str) -> Option<bool> {
+        match self {
+            Self::Id(id) => Some(qualified(id) == qualified(block_type)),
+            Self::Wildcard(pattern) => Some(glob(&qualified(pattern), &qualified(block_type))),
+            Self::Tag(_) => None,
+        }
+    }
+}
+
+/// An id with the default namespace made explicit
+fn qualified(id: &str) -> String {
+    if id.contains(':') {
+        id.to_string()
+    } else {
+        format!("minecraft:{}", id)
+    }
+}
+
+/// Match `text` against `pattern`, where `*` matches any run of characters
+fn glob(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No '*' at all
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            BlockPattern::parse("#minecraft:logs"),
+            Ok(BlockPattern::Tag("minecraft:logs".to_string()))
+        );
+        assert_eq!(
+            BlockPattern::parse("minecraft:*_ore"),
+            Ok(BlockPattern::Wildcard("minecraft:*_ore".to_string()))
+        );
+        assert_eq!(
+            BlockPattern::parse("stone"),
+            Ok(BlockPattern::Id("stone".to_string()))
+        );
+        for bad in [
+            "",
+            "#",
+            "#minecraft:*_logs",
+            "a:b:c",
+            "minecraft:",
+            "Minecraft:Stone",
+        ] {
+            assert!(
+                BlockPattern::parse(bad).is_err(),
+                "{bad:?} should not parse"
+            );
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let ores = BlockPattern::parse("minecraft:*_ore").unwrap();
+        assert_eq!(ores.matches("minecraft:diamond_ore"), Some(true));
+        assert_eq!(ores.matches("minecraft:deepslate_diamond_ore"), Some(true));
+        assert_eq!(ores.matches("minecraft:ore_block"), Some(false));
+
+        let diamonds = BlockPattern::parse("*diamond*").unwrap();
+        assert_eq!(diamonds.matches("minecraft:diamond_block"), Some(true));
+        assert_eq!(diamonds.matches("minecraft:emerald_ore"), Some(false));
+
+        let stone = BlockPattern::parse("stone").unwrap();
+        assert_eq!(stone.matches("minecraft:stone"), Some(true));
+        assert_eq!(stone.matches("minecraft:stone_bricks"), Some(false));
+
+        let logs = BlockPattern::parse("#minecraft:logs").unwrap();
+        assert_eq!(logs.matches("minecraft:oak_log"), None);
+    }
+}
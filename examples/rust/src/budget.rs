@@ -0,0 +1,276 @@
+//! What each NPC's external AI calls cost, against a budget.
+//!
+//! Every LLM completion, TTS line and ASR transcription an NPC causes is
+//! billed to the daemon's operator. A [`SpendLedger`] adds up what each NPC
+//! used in the current budget period, prices it with the rates in
+//! [`BudgetConfig`] and tells callers what the NPC may still do:
+//!
+//! - [`AiBudgetState::Normal`] below [`BudgetConfig::downgrade_at`] of the
+//!   budget: everything.
+//! - [`AiBudgetState::Downgraded`] past it: LLM calls (on a cheaper model,
+//!   if the caller has one) and ASR, but replies go out as text only.
+//! - [`AiBudgetState::Exhausted`] once the budget is spent: no external AI
+//!   calls until the period ends.
+//!
+//! ```ignore
+//! if ledger.allows(npc_id, Call::Llm, now_ms) {
+//!     let reply = llm.complete(&prompt).await?;
+//!     ledger.record_llm(npc_id, reply.prompt_tokens, reply.completion_tokens, now_ms);
+//! }
+//! ```
+//!
+//! A period starts with an NPC's first call and lasts
+//! [`BudgetConfig::period_ms`]; the next call after that starts a new one.
+//! [`SpendLedger::spend`] reports the current period as the `AiSpend` that
+//! GetNpcState returns.
+
+use std::collections::HashMap;
+
+use crate::npc_society::v1::{AiBudgetState, AiSpend};
+
+/// Prices of external AI calls and the budget they are held to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BudgetConfig {
+    /// Budget per NPC and period in US dollars; 0 is unlimited
+    pub usd_per_npc: f64,
+    /// Length of a budget period
+    pub period_ms: i64,
+    /// Share of the budget, 0.0-1.0, after which an NPC is downgraded
+    pub downgrade_at: f64,
+    /// Price of 1000 prompt tokens
+    pub usd_per_1k_prompt_tokens: f64,
+    /// Price of 1000 completion tokens
+    pub usd_per_1k_completion_tokens: f64,
+    /// Price of 1000 characters of TTS
+    pub usd_per_1k_tts_chars: f64,
+    /// Price of a minute of transcribed audio
+    pub usd_per_asr_minute: f64,
+}
+
+impl Default for BudgetConfig {
+    /// Unlimited daily budgets at small hosted model prices
+    fn default() -> Self {
+        Self {
+            usd_per_npc: 0.0,
+            period_ms: 24 * 60 * 60 * 1000,
+            downgrade_at: 0.8,
+            usd_per_1k_prompt_tokens: 0.00015,
+            usd_per_1k_completion_tokens: 0.0006,
+            usd_per_1k_tts_chars: 0.015,
+            usd_per_asr_minute: 0.006,
+        }
+    }
+}
+
+/// A kind of external AI call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Call {
+    /// A completion for dialogue or decisions
+    Llm,
+    /// Speech for a reply
+    Tts,
+    /// Transcription of a player's voice
+    Asr,
+}
+
+/// Spend of every NPC in its current period
+#[derive(Debug, Clone, Default)]
+pub struct SpendLedger {
+    config: BudgetConfig,
+    spend: HashMap<String, AiSpend>,
+}
+
+impl SpendLedger {
+    /// Hold every NPC to `config`
+    pub fn new(config: BudgetConfig) -> Self {
+        Self {
+            config,
+            spend: HashMap::new(),
+        }
+    }
+
+    /// Prices and budget the ledger applies
+    pub fn config(&self) -> &BudgetConfig {
+        &self.config
+    }
+
+    /// Record an LLM completion
+    pub fn record_llm(
+        &mut self,
+        npc_id: &str,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+        now_ms: i64,
+    ) -> AiBudgetState {
+        let cost = prompt_tokens as f64 / 1000.0 * self.config.usd_per_1k_prompt_tokens
+            + completion_tokens as f64 / 1000.0 * self.config.usd_per_1k_completion_tokens;
+        self.record(npc_id, cost, now_ms, |spend| {
+            spend.llm_calls += 1;
+            spend.prompt_tokens += prompt_tokens;
+            spend.completion_tokens += completion_tokens;
+        })
+    }
+
+    /// Record `characters` of synthesized speech
+    pub fn record_tts(&mut self, npc_id: &str, characters: u64, now_ms: i64) -> AiBudgetState {
+        let cost = characters as f64 / 1000.0 * self.config.usd_per_1k_tts_chars;
+        self.record(npc_id, cost, now_ms, |spend| {
+            spend.tts_characters += characters
+        })
+    }
+
+    /// Record `audio_ms` of transcribed player audio
+    pub fn record_asr(&mut self, npc_id: &str, audio_ms: u64, now_ms: i64) -> AiBudgetState {
+        let cost = audio_ms as f64 / 60_000.0 * self.config.usd_per_asr_minute;
+        self.record(npc_id, cost, now_ms, |spend| spend.asr_audio_ms += audio_ms)
+    }
+
+    /// How far the NPC is into its budget
+    pub fn state(&self, npc_id: &str, now_ms: i64) -> AiBudgetState {
+        self.current(npc_id, now_ms)
+            .map_or(AiBudgetState::Normal, |spend| self.state_at(spend.cost_usd))
+    }
+
+    /// Whether the NPC's budget leaves room for a call of this kind
+    pub fn allows(&self, npc_id: &str, call: Call, now_ms: i64) -> bool {
+        match self.state(npc_id, now_ms) {
+            AiBudgetState::Exhausted => false,
+            AiBudgetState::Downgraded => call != Call::Tts,
+            _ => true,
+        }
+    }
+
+    /// The NPC's spend in its current period
+    pub fn spend(&self, npc_id: &str, now_ms: i64) -> AiSpend {
+        let spend = self.current(npc_id, now_ms).cloned().unwrap_or_default();
+        let state = self.state_at(spend.cost_usd);
+        AiSpend {
+            budget_usd: self.config.usd_per_npc,
+            state: state as i32,
+            ..spend
+        }
+    }
+
+    /// The NPC's spend unless its period is over
+    fn current(&self, npc_id: &str, now_ms: i64) -> Option<&AiSpend> {
+        self.spend
+            .get(npc_id)
+            .filter(|spend| now_ms - spend.period_started_at_ms < self.config.period_ms)
+    }
+
+    fn state_at(&self, cost_usd: f64) -> AiBudgetState {
+        let budget = self.config.usd_per_npc;
+        if budget <= 0.0 {
+            AiBudgetState::Normal
+        } else if cost_usd >= budget {
+            AiBudgetState::Exhausted
+        } else if cost_usd >= budget * self.config.downgrade_at {
+            AiBudgetState::Downgraded
+        } else {
+            AiBudgetState::Normal
+        }
+    }
+
+    fn record(
+        &mut self,
+        npc_id: &str,
+        cost_usd: f64,
+        now_ms: i64,
+        add: impl FnOnce(&mut AiSpend),
+    ) -> AiBudgetState {
+        if self.current(npc_id, now_ms).is_none() {
+            self.spend.insert(
+                npc_id.to_string(),
+                AiSpend {
+                    period_started_at_ms: now_ms,
+                    ..Default::default()
+                },
+            );
+        }
+        let spend = self.spend.get_mut(npc_id).expect("inserted above");
+        add(spend);
+        spend.cost_usd += cost_usd;
+        let cost = spend.cost_usd;
+        self.state_at(cost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ledger() -> SpendLedger {
+        SpendLedger::new(BudgetConfig {
+            usd_per_npc: 1.0,
+            period_ms: 60_000,
+            downgrade_at: 0.5,
+            usd_per_1k_prompt_tokens: 0.1,
+            usd_per_1k_completion_tokens: 0.2,
+            usd_per_1k_tts_chars: 1.0,
+            usd_per_asr_minute: 0.6,
+        })
+    }
+
+    #[test]
+    fn test_downgrade_and_exhaust() {
+        let mut ledger = ledger();
+        assert_eq!(
+            ledger.record_llm("miner_01", 2000, 500, 1_000),
+            AiBudgetState::Normal
+        );
+        assert!(ledger.allows("miner_01", Call::Tts, 1_000));
+
+        // 0.3 + 0.3: past half the budget, speech stops first
+        assert_eq!(
+            ledger.record_tts("miner_01", 300, 2_000),
+            AiBudgetState::Downgraded
+        );
+        assert!(!ledger.allows("miner_01", Call::Tts, 2_000));
+        assert!(ledger.allows("miner_01", Call::Asr, 2_000));
+        assert!(ledger.allows("miner_01", Call::Llm, 2_000));
+
+        // Other NPCs have budgets of their own
+        assert!(ledger.allows("farmer_01", Call::Tts, 2_000));
+
+        assert_eq!(
+            ledger.record_asr("miner_01", 50_000, 3_000),
+            AiBudgetState::Exhausted
+        );
+        assert!(!ledger.allows("miner_01", Call::Llm, 3_000));
+
+        let spend = ledger.spend("miner_01", 3_000);
+        assert_eq!(spend.llm_calls, 1);
+        assert_eq!(spend.prompt_tokens, 2000);
+        assert_eq!(spend.completion_tokens, 500);
+        assert_eq!(spend.tts_characters, 300);
+        assert_eq!(spend.asr_audio_ms, 50_000);
+        assert!((spend.cost_usd - 1.1).abs() < 1e-9);
+        assert_eq!(spend.budget_usd, 1.0);
+        assert_eq!(spend.period_started_at_ms, 1_000);
+        assert_eq!(spend.state(), AiBudgetState::Exhausted);
+    }
+
+    #[test]
+    fn test_period_resets() {
+        let mut ledger = ledger();
+        ledger.record_tts("miner_01", 2000, 0);
+        assert_eq!(ledger.state("miner_01", 59_999), AiBudgetState::Exhausted);
+
+        // The period is over, so the NPC starts from nothing
+        assert_eq!(ledger.state("miner_01", 60_000), AiBudgetState::Normal);
+        assert_eq!(ledger.spend("miner_01", 60_000).tts_characters, 0);
+        ledger.record_tts("miner_01", 100, 70_000);
+        let spend = ledger.spend("miner_01", 70_000);
+        assert_eq!(spend.tts_characters, 100);
+        assert_eq!(spend.period_started_at_ms, 70_000);
+
+        // Without a budget nothing is ever held back
+        let mut unlimited = SpendLedger::default();
+        unlimited.record_tts("miner_01", 10_000_000, 0);
+        assert!(unlimited.allows("miner_01", Call::Tts, 0));
+        assert_eq!(
+            unlimited.spend("miner_01", 0).state(),
+            AiBudgetState::Normal
+        );
+    }
+}
@@ -0,0 +1,1316 @@
+//! Builders for actions and directives.
+//!
+//! Every action and server-sent directive has a `builder()` with sensible
+//! defaults; `build()` checks required fields and ranges, so a mistake
+//! shows up as a [`BuildError`] here instead of a cryptic plugin failure:
+//!
+//! ```ignore
+//! let directive = ActionDirective::builder()
+//!     .directive_id(&next_id)
+//!     .npc_id(&npc)
+//!     .action(MoveAction::builder().target(home).speed(1.0).build()?)
+//!     .build()?;
+//! ```
+//!
+//! `builder()` comes from the [`Buildable`] trait. Each action also
+//! converts into `action_directive::Action` with `From` (implemented in
+//! `npc-society-proto`).
+
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::block_pattern::BlockPattern;
+use crate::emotion::{Spoken, Tone};
+use crate::npc_society::v1::{
+    action_directive::Action, check_line_of_sight_action, choreography_step::Step, interact_action,
+    look_action, raycast_look_action, set_mechanism_action, show_display_directive::Display,
+    ActionDirective, AttackAction, BlockPosition, BossBarDisplay, BreakBlockAction,
+    BreedAnimalsAction, BrewAction, CheckLineOfSightAction, ChoreographyDirective, ChoreographyStep,
+    CombatStance, ComparatorMode, ConsumeItemAction, CraftAction, DepositToChestAction,
+    DialogueOption, DialogueOptionsDirective, DoorPolicy, EnchantItemAction, EquipArmorAction,
+    EquipmentSlot, EventType, GiveItemAction, HologramDisplay, InteractAction, InventoryAction,
+    InventoryActionType, ItemStack, LookAction, MapMarker, MapMarkerType, MarkMapAction, MilkAction,
+    MoveAction, NpcMessage, PlaceBlockAction, PlayParticleDirective, PlaySoundDirective, Position,
+    PressButtonAction, QuestObjective, QuestOffer, RaycastLookAction, ReadRedstonePowerAction,
+    RegionSnapshotAction, RepairItemAction, RideAndDriveAction, ScanBlocksAction, ScoreboardDisplay,
+    SetCombatPolicyDirective, SetMechanismAction, SetTimeDirective, SetWeatherDirective,
+    ShearAction, ShopDefinition, ShopListing, ShowDisplayDirective, SmeltAction, SpeakDirective,
+    SpeechDelivery, StopAction, StopSpeaking, SubscribeEvents, TameAnimalAction, TargetFilter,
+    ToggleLeverAction, TransferCurrencyDirective, TransferDirection, TransportItemsDirective,
+    TransportRoute, UnwatchBlocksAction, WatchBlocksAction, Weather, WriteBookAction,
+    WriteSignAction,
+};
+use crate::time;
+use crate::types::{DirectiveId, NpcId, PlayerUuid, StreamId};
+
+/// A required field is missing or a value is out of range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildError(pub String);
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid message: {}", self.0)
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// A message with a builder. Generated types live in `npc-society-proto`,
+/// so `builder()` comes from this trait instead of an inherent method;
+/// bring it into scope with `use builders::Buildable`.
+pub trait Buildable {
+    /// The message's builder
+    type Builder;
+
+    /// Start building the message with its defaults
+    fn builder() -> Self::Builder;
+}
+
+fn require(ok: bool, what: &str) -> Result<(), BuildError> {
+    if ok {
+        Ok(())
+    } else {
+        Err(BuildError(what.to_string()))
+    }
+}
+
+macro_rules! builder {
+    (
+        $builder:ident for $message:ident { $($default:ident: $value:expr),* $(,)? }
+        $( $(#[$doc:meta])* fn $setter:ident($arg:ident: $ty:ty) => $field:ident = $set:expr; )*
+        check($m:ident) $check:block
+    ) => {
+        #[doc = concat!("Builder for [`", stringify!($message), "`]")]
+        #[derive(Debug, Clone)]
+        #[must_use]
+        pub struct $builder($message);
+
+        impl Buildable for $message {
+            type Builder = $builder;
+
+            #[allow(clippy::needless_update)]
+            fn builder() -> $builder {
+                $builder($message {
+                    $($default: $value,)*
+                    ..Default::default()
+                })
+            }
+        }
+
+        impl $builder {
+            $(
+                $(#[$doc])*
+                pub fn $setter(mut self, $arg: $ty) -> Self {
+                    self.0.$field = $set;
+                    self
+                }
+            )*
+
+            /// Check the message and return it
+            pub fn build(self) -> Result<$message, BuildError> {
+                let $m = &self.0;
+                $check
+                Ok(self.0)
+            }
+        }
+    };
+}
+
+builder! {
+    MoveActionBuilder for MoveAction {
+        speed: 0.5,
+        pathfind: true,
+        doors: DoorPolicy::CloseBehind as i32,
+        fence_gates: DoorPolicy::CloseBehind as i32,
+        trapdoors: DoorPolicy::CloseBehind as i32,
+    }
+    /// Where to go (required)
+    fn target(target: Position) => target = Some(target);
+    /// 0.0-1.0, 1.0 = sprint (default 0.5)
+    fn speed(speed: f32) => speed = speed;
+    /// Pathfind instead of walking straight (default true)
+    fn pathfind(pathfind: bool) => pathfind = pathfind;
+    /// Swim through water (default: the plugin's choice)
+    fn allow_swim(allow: bool) => allow_swim = Some(allow);
+    /// Climb ladders and vines (default: the plugin's choice)
+    fn allow_climb(allow: bool) => allow_climb = Some(allow);
+    /// Jump gaps (default: the plugin's choice)
+    fn allow_parkour_jumps(allow: bool) => allow_parkour_jumps = Some(allow);
+    /// Go around water when it can (default false)
+    fn avoid_water(avoid: bool) => avoid_water = avoid;
+    /// How to pass doors (default: close them behind)
+    fn doors(policy: DoorPolicy) => doors = policy as i32;
+    /// How to pass fence gates (default: close them behind)
+    fn fence_gates(policy: DoorPolicy) => fence_gates = policy as i32;
+    /// How to pass trapdoors (default: close them behind)
+    fn trapdoors(policy: DoorPolicy) => trapdoors = policy as i32;
+    check(m) {
+        require(m.target.is_some(), "MoveAction.target is required")?;
+        require((0.0..=1.0).contains(&m.speed), "MoveAction.speed must be within 0.0-1.0")?;
+    }
+}
+
+builder! {
+    BreakBlockActionBuilder for BreakBlockAction {}
+    /// Block to break (required)
+    fn position(position: BlockPosition) => position = Some(position);
+    check(m) {
+        require(m.position.is_some(), "BreakBlockAction.position is required")?;
+    }
+}
+
+builder! {
+    PlaceBlockActionBuilder for PlaceBlockAction {}
+    /// Where to place (required)
+    fn position(position: BlockPosition) => position = Some(position);
+    /// Block type, e.g. "minecraft:torch" (required)
+    fn block_type(block_type: impl Into<String>) => block_type = block_type.into();
+    check(m) {
+        require(m.position.is_some(), "PlaceBlockAction.position is required")?;
+        require(!m.block_type.is_empty(), "PlaceBlockAction.block_type is required")?;
+    }
+}
+
+builder! {
+    AttackActionBuilder for AttackAction {}
+    /// Entity to attack (required)
+    fn target_uuid(uuid: impl Into<String>) => target_uuid = uuid.into();
+    check(m) {
+        require(!m.target_uuid.is_empty(), "AttackAction.target_uuid is required")?;
+    }
+}
+
+builder! {
+    InteractActionBuilder for InteractAction { main_hand: true }
+    /// Use a block (this or `entity` is required)
+    fn block(block: BlockPosition) => target = Some(interact_action::Target::Block(block));
+    /// Use an entity (this or `block` is required)
+    fn entity(uuid: impl Into<String>) => target = Some(interact_action::Target::EntityUuid(uuid.into()));
+    /// Main hand (default) or off hand
+    fn main_hand(main_hand: bool) => main_hand = main_hand;
+    check(m) {
+        require(m.target.is_some(), "InteractAction needs a block or entity")?;
+    }
+}
+
+builder! {
+    InventoryActionBuilder for InventoryAction {}
+    /// What to do (required)
+    fn action_type(action_type: InventoryActionType) => action_type = action_type as i32;
+    /// Item type involved
+    fn item_type(item_type: impl Into<String>) => item_type = item_type.into();
+    /// How many
+    fn quantity(quantity: i32) => quantity = quantity;
+    /// Inventory slot, if applicable
+    fn slot(slot: i32) => slot = slot;
+    check(m) {
+        require(
+            m.action_type() != InventoryActionType::Unspecified,
+            "InventoryAction.action_type is required",
+        )?;
+        require(m.quantity >= 0, "InventoryAction.quantity must not be negative")?;
+        require(m.slot >= 0, "InventoryAction.slot must not be negative")?;
+    }
+}
+
+builder! {
+    LookActionBuilder for LookAction {}
+    /// Look at a position (this or `entity` is required)
+    fn position(position: Position) => target = Some(look_action::Target::Position(position));
+    /// Look at an entity (this or `position` is required)
+    fn entity(uuid: impl Into<String>) => target = Some(look_action::Target::EntityUuid(uuid.into()));
+    check(m) {
+        require(m.target.is_some(), "LookAction needs a position or entity")?;
+    }
+}
+
+builder! {
+    StopActionBuilder for StopAction {}
+    /// Also drop queued directives (default false)
+    fn cancel_pending(cancel_pending: bool) => cancel_pending = cancel_pending;
+    check(_m) {}
+}
+
+builder! {
+    ScanBlocksActionBuilder for ScanBlocksAction { radius: 16, max_results: 10 }
+    /// Center of the scan (required)
+    fn center(center: BlockPosition) => center = Some(center);
+    /// Radius in blocks (default 16)
+    fn radius(radius: i32) => radius = radius;
+    /// Block ids, `#tags` or `*` patterns to look for (required)
+    fn block_types(types: impl IntoIterator<Item = impl Into<String>>) => block_types = types.into_iter().map(Into::into).collect();
+    /// Result cap (default 10)
+    fn max_results(max_results: i32) => max_results = max_results;
+    /// Report passability, hardness and light per match (default false)
+    fn include_properties(include: bool) => include_properties = include;
+    check(m) {
+        require(m.center.is_some(), "ScanBlocksAction.center is required")?;
+        require(m.radius > 0, "ScanBlocksAction.radius must be positive")?;
+        require(!m.block_types.is_empty(), "ScanBlocksAction.block_types is required")?;
+        require(
+            m.block_types.iter().all(|t| BlockPattern::parse(t).is_ok()),
+            "ScanBlocksAction.block_types has a malformed entry",
+        )?;
+        require(m.max_results > 0, "ScanBlocksAction.max_results must be positive")?;
+    }
+}
+
+builder! {
+    RaycastLookActionBuilder for RaycastLookAction { max_distance: 6.0 }
+    /// Ray length in blocks (default 6)
+    fn max_distance(max_distance: f32) => max_distance = max_distance;
+    /// Stop at water and lava (default false)
+    fn include_fluids(include_fluids: bool) => include_fluids = include_fluids;
+    /// Start here, along its yaw and pitch (default: the NPC's eyes and look direction)
+    fn origin(origin: Position) => origin = Some(origin);
+    /// Aim at a position
+    fn target(target: Position) => aim = Some(raycast_look_action::Aim::Target(target));
+    /// Aim at an entity's eyes
+    fn target_entity(uuid: impl Into<String>) => aim = Some(raycast_look_action::Aim::TargetEntityUuid(uuid.into()));
+    /// Stop at entities too (default false)
+    fn include_entities(include_entities: bool) => include_entities = include_entities;
+    check(m) {
+        require(m.max_distance > 0.0, "RaycastLookAction.max_distance must be positive")?;
+        require(
+            !matches!(&m.aim, Some(raycast_look_action::Aim::TargetEntityUuid(uuid)) if uuid.is_empty()),
+            "RaycastLookAction.target_entity_uuid must not be empty",
+        )?;
+    }
+}
+
+builder! {
+    DepositToChestActionBuilder for DepositToChestAction { max_items: 64 }
+    /// Chest to fill (required)
+    fn chest_position(position: BlockPosition) => chest_position = Some(position);
+    /// Item types to deposit (default: everything allowed)
+    fn item_types(types: impl IntoIterator<Item = impl Into<String>>) => item_types = types.into_iter().map(Into::into).collect();
+    /// Item cap (default 64)
+    fn max_items(max_items: i32) => max_items = max_items;
+    check(m) {
+        require(m.chest_position.is_some(), "DepositToChestAction.chest_position is required")?;
+        require(m.max_items > 0, "DepositToChestAction.max_items must be positive")?;
+    }
+}
+
+builder! {
+    RegionSnapshotActionBuilder for RegionSnapshotAction {}
+    /// One corner of the cuboid (required)
+    fn from(corner: BlockPosition) => from = Some(corner);
+    /// Opposite corner (required)
+    fn to(corner: BlockPosition) => to = Some(corner);
+    /// Report passability, hardness and light too (default false)
+    fn include_properties(include: bool) => include_properties = include;
+    check(m) {
+        let (Some(from), Some(to)) = (&m.from, &m.to) else {
+            return Err(BuildError("RegionSnapshotAction.from and .to are required".to_string()));
+        };
+        require(from.world == to.world, "RegionSnapshotAction corners must be in the same world")?;
+    }
+}
+
+builder! {
+    WatchBlocksActionBuilder for WatchBlocksAction { interval_ticks: 20 }
+    /// One corner of the cuboid (required)
+    fn from(corner: BlockPosition) => from = Some(corner);
+    /// Opposite corner (required)
+    fn to(corner: BlockPosition) => to = Some(corner);
+    /// Block ids, `#tags` or `*` patterns to watch (required)
+    fn block_types(types: impl IntoIterator<Item = impl Into<String>>) => block_types = types.into_iter().map(Into::into).collect();
+    /// Ticks between checks (default 20)
+    fn interval_ticks(ticks: i32) => interval_ticks = ticks;
+    /// Most matches to track (default: the plugin's)
+    fn max_matches(max_matches: i32) => max_matches = max_matches;
+    /// Report passability, hardness and light of found blocks (default false)
+    fn include_properties(include: bool) => include_properties = include;
+    check(m) {
+        let (Some(from), Some(to)) = (&m.from, &m.to) else {
+            return Err(BuildError("WatchBlocksAction.from and .to are required".to_string()));
+        };
+        require(from.world == to.world, "WatchBlocksAction corners must be in the same world")?;
+        require(!m.block_types.is_empty(), "WatchBlocksAction.block_types is required")?;
+        require(
+            m.block_types.iter().all(|t| BlockPattern::parse(t).is_ok()),
+            "WatchBlocksAction.block_types has a malformed entry",
+        )?;
+        require(m.interval_ticks >= 0, "WatchBlocksAction.interval_ticks must not be negative")?;
+        require(m.max_matches >= 0, "WatchBlocksAction.max_matches must not be negative")?;
+    }
+}
+
+builder! {
+    SmeltActionBuilder for SmeltAction { count: 1 }
+    /// Furnace, blast furnace or smoker to load (required)
+    fn furnace_position(position: BlockPosition) => furnace_position = Some(position);
+    /// Item to smelt (required unless collecting only)
+    fn input_item(item: impl Into<String>) => input_item = item.into();
+    /// Fuel to add (default: any, if needed)
+    fn fuel_item(item: impl Into<String>) => fuel_item = item.into();
+    /// Input items to load; 0 only collects the output (default 1)
+    fn count(count: i32) => count = count;
+    check(m) {
+        require(m.furnace_position.is_some(), "SmeltAction.furnace_position is required")?;
+        require(m.count >= 0, "SmeltAction.count must not be negative")?;
+        require(m.count == 0 || !m.input_item.is_empty(), "SmeltAction.input_item is required")?;
+    }
+}
+
+builder! {
+    BrewActionBuilder for BrewAction { bottle_item: "minecraft:potion".to_string(), bottle_count: 3 }
+    /// Brewing stand to load (required)
+    fn stand_position(position: BlockPosition) => stand_position = Some(position);
+    /// Ingredient to add (required unless collecting only)
+    fn ingredient_item(item: impl Into<String>) => ingredient_item = item.into();
+    /// Bottles to brew into (default minecraft:potion, water bottles)
+    fn bottle_item(item: impl Into<String>) => bottle_item = item.into();
+    /// Bottles to load, 0-3; 0 only collects the potions (default 3)
+    fn bottle_count(count: i32) => bottle_count = count;
+    check(m) {
+        require(m.stand_position.is_some(), "BrewAction.stand_position is required")?;
+        require((0..=3).contains(&m.bottle_count), "BrewAction.bottle_count must be 0-3")?;
+        require(m.bottle_count == 0 || !m.ingredient_item.is_empty(), "BrewAction.ingredient_item is required")?;
+    }
+}
+
+builder! {
+    RideAndDriveActionBuilder for RideAndDriveAction {}
+    /// Where to ride to (required)
+    fn target(target: Position) => target = Some(target);
+    /// Fraction of the vehicle's top speed, 0.0-1.0 (default: the plugin's)
+    fn speed(speed: f32) => speed = speed;
+    /// Get off on arrival (default false)
+    fn dismount(dismount: bool) => dismount = dismount;
+    check(m) {
+        require(m.target.is_some(), "RideAndDriveAction.target is required")?;
+        require((0.0..=1.0).contains(&m.speed), "RideAndDriveAction.speed must be 0.0-1.0")?;
+    }
+}
+
+builder! {
+    BreedAnimalsActionBuilder for BreedAnimalsAction {}
+    /// First animal (required)
+    fn first(uuid: impl Into<String>) => first_uuid = uuid.into();
+    /// Its partner, of the same species (required)
+    fn second(uuid: impl Into<String>) => second_uuid = uuid.into();
+    /// Food to use (default: the species' usual food)
+    fn food_item(item: impl Into<String>) => food_item = item.into();
+    check(m) {
+        require(!m.first_uuid.is_empty() && !m.second_uuid.is_empty(), "BreedAnimalsAction needs two animals")?;
+        require(m.first_uuid != m.second_uuid, "BreedAnimalsAction needs two different animals")?;
+    }
+}
+
+builder! {
+    TameAnimalActionBuilder for TameAnimalAction {}
+    /// Animal to tame (required)
+    fn target(uuid: impl Into<String>) => target_uuid = uuid.into();
+    /// Food to use (default: the species' usual food)
+    fn food_item(item: impl Into<String>) => food_item = item.into();
+    /// Feeding attempts at most (default: the plugin's)
+    fn max_attempts(attempts: i32) => max_attempts = attempts;
+    check(m) {
+        require(!m.target_uuid.is_empty(), "TameAnimalAction.target_uuid is required")?;
+        require(m.max_attempts >= 0, "TameAnimalAction.max_attempts must not be negative")?;
+    }
+}
+
+builder! {
+    ShearActionBuilder for ShearAction {}
+    /// Animal to shear (required)
+    fn target(uuid: impl Into<String>) => target_uuid = uuid.into();
+    check(m) {
+        require(!m.target_uuid.is_empty(), "ShearAction.target_uuid is required")?;
+    }
+}
+
+builder! {
+    MilkActionBuilder for MilkAction {}
+    /// Animal to milk (required)
+    fn target(uuid: impl Into<String>) => target_uuid = uuid.into();
+    /// Empty container to fill (default minecraft:bucket)
+    fn container_item(item: impl Into<String>) => container_item = item.into();
+    check(m) {
+        require(!m.target_uuid.is_empty(), "MilkAction.target_uuid is required")?;
+    }
+}
+
+builder! {
+    EquipArmorActionBuilder for EquipArmorAction {}
+    /// Item to equip (empty = unequip the slot)
+    fn item(item: impl Into<String>) => item_type = item.into();
+    /// Slot to fill (default: the item's own; required to unequip)
+    fn slot(slot: EquipmentSlot) => slot = slot as i32;
+    check(m) {
+        require(
+            !m.item_type.is_empty() || m.slot() != EquipmentSlot::Unspecified,
+            "EquipArmorAction needs an item_type or a slot",
+        )?;
+    }
+}
+
+builder! {
+    EnchantItemActionBuilder for EnchantItemAction {}
+    /// Enchanting table (required)
+    fn table(position: BlockPosition) => table_position = Some(position);
+    /// Item to enchant (required)
+    fn item(item: impl Into<String>) => item_type = item.into();
+    /// Offer 1-3 (default 0: the most expensive affordable one)
+    fn option(option: i32) => option = option;
+    check(m) {
+        require(m.table_position.is_some(), "EnchantItemAction.table_position is required")?;
+        require(!m.item_type.is_empty(), "EnchantItemAction.item_type is required")?;
+        require((0..=3).contains(&m.option), "EnchantItemAction.option must be 0-3")?;
+    }
+}
+
+builder! {
+    RepairItemActionBuilder for RepairItemAction {}
+    /// Anvil (required)
+    fn anvil(position: BlockPosition) => anvil_position = Some(position);
+    /// Item to repair (required)
+    fn item(item: impl Into<String>) => item_type = item.into();
+    /// Material or second item to repair with (required)
+    fn material(item: impl Into<String>) => material_item = item.into();
+    /// New name for the item
+    fn rename(name: impl Into<String>) => rename = name.into();
+    check(m) {
+        require(m.anvil_position.is_some(), "RepairItemAction.anvil_position is required")?;
+        require(!m.item_type.is_empty(), "RepairItemAction.item_type is required")?;
+        require(!m.material_item.is_empty(), "RepairItemAction.material_item is required")?;
+    }
+}
+
+builder! {
+    ConsumeItemActionBuilder for ConsumeItemAction {}
+    /// Food or potion to consume (default: the most nourishing food)
+    fn item(item: impl Into<String>) => item_type = item.into();
+    /// Potion type, e.g. minecraft:healing (default: any)
+    fn potion(potion: impl Into<String>) => potion_type = potion.into();
+    check(m) {
+        require(
+            m.potion_type.is_empty() || !m.item_type.is_empty(),
+            "ConsumeItemAction.potion_type needs an item_type",
+        )?;
+    }
+}
+
+builder! {
+    CraftActionBuilder for CraftAction { count: 1 }
+    /// Item to craft (required)
+    fn item(item: impl Into<String>) => item_type = item.into();
+    /// Items wanted, rounded up to whole crafts (default 1)
+    fn count(count: i32) => count = count;
+    /// Crafting table for 3x3 recipes (default: the inventory grid)
+    fn table(position: BlockPosition) => table_position = Some(position);
+    check(m) {
+        require(!m.item_type.is_empty(), "CraftAction.item_type is required")?;
+        require(m.count > 0, "CraftAction.count must be positive")?;
+    }
+}
+
+builder! {
+    CheckLineOfSightActionBuilder for CheckLineOfSightAction {}
+    /// Look for a position (this or `entity` is required)
+    fn position(position: Position) => target = Some(check_line_of_sight_action::Target::Position(position));
+    /// Look for an entity or player (this or `position` is required)
+    fn entity(uuid: impl Into<String>) => target = Some(check_line_of_sight_action::Target::EntityUuid(uuid.into()));
+    /// Farthest the NPC sees in blocks (default: the plugin's)
+    fn max_distance(max_distance: f32) => max_distance = max_distance;
+    check(m) {
+        require(m.target.is_some(), "CheckLineOfSightAction needs a position or entity")?;
+        require(m.max_distance >= 0.0, "CheckLineOfSightAction.max_distance must not be negative")?;
+    }
+}
+
+builder! {
+    GiveItemActionBuilder for GiveItemAction {}
+    /// Player receiving the items (required)
+    fn player_uuid(player: &PlayerUuid) => player_uuid = player.to_string();
+    /// Item to give (required)
+    fn item_type(item_type: impl Into<String>) => item_type = item_type.into();
+    /// How many (required, positive)
+    fn count(count: i32) => count = count;
+    /// Take the items from the NPC's inventory instead of creating them
+    fn from_inventory(from_inventory: bool) => from_inventory = from_inventory;
+    check(m) {
+        require(!m.player_uuid.is_empty(), "GiveItemAction.player_uuid is required")?;
+        require(!m.item_type.is_empty(), "GiveItemAction.item_type is required")?;
+        require(m.count > 0, "GiveItemAction.count must be positive")?;
+    }
+}
+
+builder! {
+    WriteSignActionBuilder for WriteSignAction {}
+    /// The sign's block (required)
+    fn position(position: BlockPosition) => position = Some(position);
+    /// Up to 4 lines, top first
+    fn lines(lines: impl IntoIterator<Item = impl Into<String>>) => lines = lines.into_iter().map(Into::into).collect();
+    /// Write on the back (default: the front)
+    fn back(back: bool) => back = back;
+    /// Glowing text
+    fn glowing(glowing: bool) => glowing = glowing;
+    /// Wax the sign so players cannot edit it
+    fn wax(wax: bool) => wax = wax;
+    /// Sign to place if there is none, e.g. "minecraft:oak_sign" (default:
+    /// only write on an existing sign)
+    fn sign_type(sign_type: impl Into<String>) => sign_type = sign_type.into();
+    check(m) {
+        require(m.position.is_some(), "WriteSignAction.position is required")?;
+        require(m.lines.len() <= 4, "WriteSignAction has more than 4 lines")?;
+    }
+}
+
+builder! {
+    WriteBookActionBuilder for WriteBookAction {}
+    /// Book title, at most 32 characters (required)
+    fn title(title: impl Into<String>) => title = title.into();
+    /// Pages in order (at least one)
+    fn pages(pages: impl IntoIterator<Item = impl Into<String>>) => pages = pages.into_iter().map(Into::into).collect();
+    /// Author shown on the book (default: the NPC's name)
+    fn author(author: impl Into<String>) => author = author.into();
+    /// Player to hand the book to (default: the NPC keeps it)
+    fn player_uuid(player: &PlayerUuid) => player_uuid = player.to_string();
+    /// Copies to make (default 1)
+    fn copies(copies: i32) => copies = copies;
+    /// Use a book and quill from the NPC's inventory instead of creating it
+    fn from_inventory(from_inventory: bool) => from_inventory = from_inventory;
+    check(m) {
+        require(!m.title.is_empty(), "WriteBookAction.title is required")?;
+        require(m.title.chars().count() <= 32, "WriteBookAction.title is longer than 32 characters")?;
+        require(!m.pages.is_empty(), "WriteBookAction needs at least one page")?;
+        require(m.copies >= 0, "WriteBookAction.copies must not be negative")?;
+    }
+}
+
+builder! {
+    MarkMapActionBuilder for MarkMapAction {}
+    /// Center of the map (required)
+    fn center(center: BlockPosition) => center = Some(center);
+    /// Zoom, 0 (1 block per pixel) to 4 (default 0)
+    fn scale(scale: i32) => scale = scale;
+    /// Item name of the map (default "Map")
+    fn title(title: impl Into<String>) => title = title.into();
+    /// Player to hand the map to (default: the NPC keeps it)
+    fn player_uuid(player: &PlayerUuid) => player_uuid = player.to_string();
+    check(m) {
+        require(m.center.is_some(), "MarkMapAction.center is required")?;
+        require((0..=4).contains(&m.scale), "MarkMapAction.scale must be within 0-4")?;
+        require(!m.markers.is_empty(), "MarkMapAction needs at least one marker")?;
+        require(
+            m.markers.iter().all(|marker| marker.position.is_some()),
+            "MapMarker.position is required",
+        )?;
+    }
+}
+
+builder! {
+    ToggleLeverActionBuilder for ToggleLeverAction {}
+    /// The lever's block (required)
+    fn position(position: BlockPosition) => position = Some(position);
+    /// State to leave the lever in (default: flip it)
+    fn powered(powered: bool) => powered = Some(powered);
+    check(m) {
+        require(m.position.is_some(), "ToggleLeverAction.position is required")?;
+    }
+}
+
+builder! {
+    PressButtonActionBuilder for PressButtonAction {}
+    /// The button's block (required)
+    fn position(position: BlockPosition) => position = Some(position);
+    check(m) {
+        require(m.position.is_some(), "PressButtonAction.position is required")?;
+    }
+}
+
+builder! {
+    SetMechanismActionBuilder for SetMechanismAction {}
+    /// The mechanism's block (required)
+    fn position(position: BlockPosition) => position = Some(position);
+    /// Tune a note block, 0 (F#3) to 24 (F#5)
+    fn note(note: i32) => state = Some(set_mechanism_action::State::Note(note));
+    /// Set a comparator's mode
+    fn comparator_mode(mode: ComparatorMode) => state = Some(set_mechanism_action::State::ComparatorMode(mode as i32));
+    /// Set a repeater's delay, 1-4 redstone ticks
+    fn repeater_delay(delay: i32) => state = Some(set_mechanism_action::State::RepeaterDelay(delay));
+    check(m) {
+        require(m.position.is_some(), "SetMechanismAction.position is required")?;
+        match m.state {
+            Some(set_mechanism_action::State::Note(note)) => {
+                require((0..=24).contains(&note), "SetMechanismAction.note must be within 0-24")?
+            }
+            Some(set_mechanism_action::State::ComparatorMode(mode)) => require(
+                mode != ComparatorMode::Unspecified as i32,
+                "SetMechanismAction.comparator_mode is unspecified",
+            )?,
+            Some(set_mechanism_action::State::RepeaterDelay(delay)) => require(
+                (1..=4).contains(&delay),
+                "SetMechanismAction.repeater_delay must be within 1-4",
+            )?,
+            None => require(false, "SetMechanismAction needs a note, comparator mode or repeater delay")?,
+        }
+    }
+}
+
+builder! {
+    ReadRedstonePowerActionBuilder for ReadRedstonePowerAction {}
+    /// Block to read (required)
+    fn position(position: BlockPosition) => position = Some(position);
+    check(m) {
+        require(m.position.is_some(), "ReadRedstonePowerAction.position is required")?;
+    }
+}
+
+impl MarkMapActionBuilder {
+    /// Add a mark; `label` only shows for banners
+    pub fn marker(
+        mut self,
+        position: BlockPosition,
+        kind: MapMarkerType,
+        label: impl Into<String>,
+    ) -> Self {
+        self.0.markers.push(MapMarker {
+            position: Some(position),
+            r#type: kind as i32,
+            label: label.into(),
+        });
+        self
+    }
+}
+
+builder! {
+    UnwatchBlocksActionBuilder for UnwatchBlocksAction {}
+    /// directive_id of the WatchBlocksAction to end (required)
+    fn watch_id(id: &DirectiveId) => watch_id = id.to_string();
+    check(m) {
+        require(!m.watch_id.is_empty(), "UnwatchBlocksAction.watch_id is required")?;
+    }
+}
+
+builder! {
+    ActionDirectiveBuilder for ActionDirective { priority: 1 }
+    /// Correlation id (required)
+    fn directive_id(id: &DirectiveId) => directive_id = id.to_string();
+    /// NPC to act (required)
+    fn npc_id(npc: &NpcId) => npc_id = npc.to_string();
+    /// Higher = more urgent (default 1)
+    fn priority(priority: i32) => priority = priority;
+    /// What to do (required)
+    fn action(action: impl Into<Action>) => action = Some(action.into());
+    check(m) {
+        require(!m.directive_id.is_empty(), "ActionDirective.directive_id is required")?;
+        require(!m.npc_id.is_empty(), "ActionDirective.npc_id is required")?;
+        require(m.action.is_some(), "ActionDirective.action is required")?;
+    }
+}
+
+builder! {
+    SpeakDirectiveBuilder for SpeakDirective { volume: 1.0 }
+    /// NPC to speak (required)
+    fn npc_id(npc: &NpcId) => npc_id = npc.to_string();
+    /// Subtitle text (required)
+    fn text(text: impl Into<String>) => text = text.into();
+    /// Tone hint
+    fn emotion(emotion: impl Into<String>) => emotion = emotion.into();
+    /// Subtitle duration
+    fn duration_ms(duration_ms: i32) => duration_ms = duration_ms;
+    /// Subtitle duration, as a [`Duration`]
+    fn duration(duration: Duration) => duration_ms = i32::try_from(time::millis(duration)).unwrap_or(i32::MAX);
+    /// Correlation id for the audio
+    fn directive_id(id: &DirectiveId) => directive_id = id.to_string();
+    /// TTS voice
+    fn voice_id(voice_id: impl Into<String>) => voice_id = voice_id.into();
+    /// 0.0-1.0 (default 1.0)
+    fn volume(volume: f32) => volume = volume;
+    /// AudioChunk stream that goes with the subtitle
+    fn stream_id(id: &StreamId) => stream_id = id.to_string();
+    /// Only these players see and hear it (default: everyone in range)
+    fn target_players(players: impl IntoIterator<Item = PlayerUuid>) => target_player_uuids = players.into_iter().map(String::from).collect();
+    /// Spatial (default), direct, group or global
+    fn delivery(delivery: SpeechDelivery) => delivery = delivery as i32;
+    /// How far spatial audio carries, in blocks (default: the voice chat's)
+    fn range(blocks: f32) => range = blocks;
+    /// Voice chat group for group delivery
+    fn group(group: impl Into<String>) => group = group.into();
+    check(m) {
+        require(!m.npc_id.is_empty(), "SpeakDirective.npc_id is required")?;
+        require(!m.text.is_empty(), "SpeakDirective.text is required")?;
+        require(m.duration_ms >= 0, "SpeakDirective.duration_ms must not be negative")?;
+        require((0.0..=1.0).contains(&m.volume), "SpeakDirective.volume must be within 0.0-1.0")?;
+        require(
+            m.delivery() != SpeechDelivery::Direct || !m.target_player_uuids.is_empty(),
+            "SpeakDirective with DIRECT delivery needs target players",
+        )?;
+        require(m.range >= 0.0, "SpeakDirective.range must not be negative")?;
+        require(
+            m.delivery() != SpeechDelivery::Group || !m.group.is_empty(),
+            "SpeakDirective with GROUP delivery needs a group",
+        )?;
+        require(
+            m.delivery() != SpeechDelivery::Global || m.target_player_uuids.is_empty(),
+            "SpeakDirective with GLOBAL delivery cannot have target players",
+        )?;
+    }
+}
+
+impl SpeakDirectiveBuilder {
+    /// Emotion to animate and voice the line with; sets `emotion_type` and
+    /// the `emotion` name older plugins read
+    pub fn tone(mut self, tone: impl Into<Tone>) -> Self {
+        self.0.set_tone(tone);
+        self
+    }
+}
+
+builder! {
+    StopSpeakingBuilder for StopSpeaking {}
+    /// NPC to silence (required)
+    fn npc_id(npc: &NpcId) => npc_id = npc.to_string();
+    /// Only this stream (default: every stream of the NPC)
+    fn stream_id(id: &StreamId) => stream_id = id.to_string();
+    check(m) {
+        require(!m.npc_id.is_empty(), "StopSpeaking.npc_id is required")?;
+    }
+}
+
+builder! {
+    SetCombatPolicyDirectiveBuilder for SetCombatPolicyDirective { stance: CombatStance::Defensive as i32 }
+    /// NPC to configure (required)
+    fn npc_id(npc: &NpcId) => npc_id = npc.to_string();
+    /// When to fight (default defensive)
+    fn stance(stance: CombatStance) => stance = stance as i32;
+    /// Flee below this health, 0.0-1.0 (default 0, never)
+    fn flee_below(health: f32) => flee_health_threshold = health;
+    /// Targets, highest priority first (default: hostile mobs)
+    fn target_filters(filters: impl IntoIterator<Item = TargetFilter>) => target_filters = filters.into_iter().collect();
+    /// Farthest to chase, in blocks (default: the plugin's)
+    fn max_chase_distance(blocks: f32) => max_chase_distance = blocks;
+    check(m) {
+        require(!m.npc_id.is_empty(), "SetCombatPolicyDirective.npc_id is required")?;
+        require(
+            (0.0..=1.0).contains(&m.flee_health_threshold),
+            "SetCombatPolicyDirective.flee_health_threshold must be 0.0-1.0",
+        )?;
+        require(
+            m.target_filters.iter().all(|f| BlockPattern::parse(&f.entity_type).is_ok()),
+            "SetCombatPolicyDirective.target_filters has a malformed entity type",
+        )?;
+    }
+}
+
+builder! {
+    SubscribeEventsBuilder for SubscribeEvents {}
+    /// Event types to receive (default: all)
+    fn event_types(types: impl IntoIterator<Item = EventType>) => event_types = types.into_iter().map(|t| t as i32).collect();
+    /// Only events of these NPCs (default: all)
+    fn npcs(npcs: impl IntoIterator<Item = NpcId>) => npc_ids = npcs.into_iter().map(String::from).collect();
+    check(m) {
+        require(
+            !m.event_types.contains(&(EventType::Unspecified as i32)),
+            "SubscribeEvents.event_types must not contain EVENT_TYPE_UNSPECIFIED",
+        )?;
+    }
+}
+
+builder! {
+    TransportItemsDirectiveBuilder for TransportItemsDirective {}
+    /// Correlation id (required)
+    fn directive_id(id: &DirectiveId) => directive_id = id.to_string();
+    /// The courier (required)
+    fn npc_id(npc: &NpcId) => npc_id = npc.to_string();
+    /// Container to take items from (required)
+    fn from_container(position: BlockPosition) => from_container = Some(position);
+    /// Container to put them in (required)
+    fn to_container(position: BlockPosition) => to_container = Some(position);
+    /// Item types to carry (default: everything)
+    fn item_types(types: impl IntoIterator<Item = impl Into<String>>) => item_types = types.into_iter().map(Into::into).collect();
+    /// Item cap over all trips (default: until from_container has no more)
+    fn max_items(max_items: i32) => max_items = max_items;
+    /// Round trip cap (default: as many as it takes)
+    fn max_trips(max_trips: i32) => max_trips = max_trips;
+    /// How to travel (default: walk)
+    fn route_hint(route: TransportRoute) => route_hint = Some(route);
+    check(m) {
+        require(!m.directive_id.is_empty(), "TransportItemsDirective.directive_id is required")?;
+        require(!m.npc_id.is_empty(), "TransportItemsDirective.npc_id is required")?;
+        require(m.from_container.is_some(), "TransportItemsDirective.from_container is required")?;
+        require(m.to_container.is_some(), "TransportItemsDirective.to_container is required")?;
+        require(
+            m.from_container != m.to_container,
+            "TransportItemsDirective containers must differ",
+        )?;
+        require(m.max_items >= 0, "TransportItemsDirective.max_items must not be negative")?;
+        require(m.max_trips >= 0, "TransportItemsDirective.max_trips must not be negative")?;
+    }
+}
+
+builder! {
+    TransferCurrencyDirectiveBuilder for TransferCurrencyDirective {}
+    /// Correlation id (required)
+    fn directive_id(id: &DirectiveId) => directive_id = id.to_string();
+    /// NPC whose account is used (required)
+    fn npc_id(npc: &NpcId) => npc_id = npc.to_string();
+    /// Player on the other side (required)
+    fn player_uuid(player: &PlayerUuid) => player_uuid = player.to_string();
+    /// Who pays whom (required)
+    fn direction(direction: TransferDirection) => direction = direction as i32;
+    /// Amount in the smallest unit (required, positive)
+    fn amount(amount: i64) => amount = amount;
+    /// Currency (default: the economy's default)
+    fn currency(currency: impl Into<String>) => currency = currency.into();
+    /// Shown to the player
+    fn reason(reason: impl Into<String>) => reason = reason.into();
+    check(m) {
+        require(!m.directive_id.is_empty(), "TransferCurrencyDirective.directive_id is required")?;
+        require(!m.npc_id.is_empty(), "TransferCurrencyDirective.npc_id is required")?;
+        require(!m.player_uuid.is_empty(), "TransferCurrencyDirective.player_uuid is required")?;
+        require(
+            m.direction() != TransferDirection::Unspecified,
+            "TransferCurrencyDirective.direction is required",
+        )?;
+        require(m.amount > 0, "TransferCurrencyDirective.amount must be positive")?;
+    }
+}
+
+builder! {
+    QuestOfferBuilder for QuestOffer {}
+    /// Quest id, echoed in QuestUpdate (required)
+    fn quest_id(quest_id: impl Into<String>) => quest_id = quest_id.into();
+    /// NPC giving the quest (required)
+    fn npc_id(npc: &NpcId) => npc_id = npc.to_string();
+    /// Player the offer is for (required)
+    fn player_uuid(player: &PlayerUuid) => player_uuid = player.to_string();
+    /// Short title (required)
+    fn title(title: impl Into<String>) => title = title.into();
+    /// Text shown with the offer
+    fn description(description: impl Into<String>) => description = description.into();
+    /// Goals (at least one)
+    fn objectives(objectives: impl IntoIterator<Item = QuestObjective>) => objectives = objectives.into_iter().collect();
+    /// Items given on completion
+    fn reward_items(items: impl IntoIterator<Item = ItemStack>) => reward_items = items.into_iter().collect();
+    /// Experience given on completion
+    fn reward_experience(experience: i32) => reward_experience = experience;
+    /// When the offer expires (Unix ms, default never)
+    fn expires_at_ms(expires_at_ms: i64) => expires_at_ms = expires_at_ms;
+    /// When the offer expires, as a [`SystemTime`]
+    fn expires_at(time: SystemTime) => expires_at_ms = time::unix_ms(time);
+    check(m) {
+        require(!m.quest_id.is_empty(), "QuestOffer.quest_id is required")?;
+        require(!m.npc_id.is_empty(), "QuestOffer.npc_id is required")?;
+        require(!m.player_uuid.is_empty(), "QuestOffer.player_uuid is required")?;
+        require(!m.title.is_empty(), "QuestOffer.title is required")?;
+        require(!m.objectives.is_empty(), "QuestOffer needs at least one objective")?;
+        require(
+            m.objectives.iter().all(|o| o.required_count > 0),
+            "QuestObjective.required_count must be positive",
+        )?;
+        require(m.reward_experience >= 0, "QuestOffer.reward_experience must not be negative")?;
+    }
+}
+
+builder! {
+    NpcMessageBuilder for NpcMessage {}
+    /// Unique message id (required)
+    fn message_id(message_id: impl Into<String>) => message_id = message_id.into();
+    /// Sending NPC (required)
+    fn sender(npc: &NpcId) => sender_npc_id = npc.to_string();
+    /// Receiving NPC (default: broadcast)
+    fn recipient(npc: &NpcId) => recipient_npc_id = npc.to_string();
+    /// Message kind (required)
+    fn topic(topic: impl Into<String>) => topic = topic.into();
+    /// Body
+    fn payload(payload: impl Into<Vec<u8>>) => payload = payload.into();
+    /// message_id this answers
+    fn reply_to(message_id: impl Into<String>) => reply_to = message_id.into();
+    check(m) {
+        require(!m.message_id.is_empty(), "NpcMessage.message_id is required")?;
+        require(!m.sender_npc_id.is_empty(), "NpcMessage.sender_npc_id is required")?;
+        require(!m.topic.is_empty(), "NpcMessage.topic is required")?;
+    }
+}
+
+/// Builder for [`ChoreographyDirective`]
+///
+/// Steps can be added in any order; `build` sorts them by `at_ms`,
+/// keeping the order of steps at the same time.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct ChoreographyDirectiveBuilder(ChoreographyDirective);
+
+impl Buildable for ChoreographyDirective {
+    type Builder = ChoreographyDirectiveBuilder;
+
+    fn builder() -> ChoreographyDirectiveBuilder {
+        ChoreographyDirectiveBuilder(ChoreographyDirective::default())
+    }
+}
+
+impl ChoreographyDirectiveBuilder {
+    /// Correlation id (required)
+    pub fn directive_id(mut self, id: &DirectiveId) -> Self {
+        self.0.directive_id = id.to_string();
+        self
+    }
+
+    /// Run `action` on `npc` at `at_ms` after the scene starts
+    pub fn action(self, at_ms: i64, npc: &NpcId, action: impl Into<Action>) -> Self {
+        let directive = ActionDirective {
+            npc_id: npc.to_string(),
+            action: Some(action.into()),
+            priority: 1,
+            ..Default::default()
+        };
+        self.push(at_ms, Step::Action(directive))
+    }
+
+    /// Speak at `at_ms` after the scene starts
+    pub fn speak(self, at_ms: i64, speak: SpeakDirective) -> Self {
+        self.push(at_ms, Step::Speak(speak))
+    }
+
+    /// Also wait for the same NPC's previous step to finish (applies to the
+    /// step added last)
+    pub fn after_previous(mut self) -> Self {
+        if let Some(step) = self.0.steps.last_mut() {
+            step.after_previous = true;
+        }
+        self
+    }
+
+    /// Plugin clock time to start at (default: on receipt)
+    pub fn start_at_ms(mut self, start_at_ms: i64) -> Self {
+        self.0.start_at_ms = start_at_ms;
+        self
+    }
+
+    /// Plugin clock time to start at, as a [`SystemTime`]
+    pub fn start_at(self, time: SystemTime) -> Self {
+        self.start_at_ms(time::unix_ms(time))
+    }
+
+    /// Stop the remaining steps when one fails (default false)
+    pub fn abort_on_failure(mut self, abort: bool) -> Self {
+        self.0.abort_on_failure = abort;
+        self
+    }
+
+    fn push(mut self, at_ms: i64, step: Step) -> Self {
+        self.0.steps.push(ChoreographyStep {
+            at_ms,
+            step: Some(step),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Sort the steps, check the message and return it
+    pub fn build(mut self) -> Result<ChoreographyDirective, BuildError> {
+        let m = &mut self.0;
+        // Stable, so steps at the same time keep the order they were added in
+        m.steps.sort_by_key(|step| step.at_ms);
+        require(!m.directive_id.is_empty(), "ChoreographyDirective.directive_id is required")?;
+        require(!m.steps.is_empty(), "ChoreographyDirective needs at least one step")?;
+        require(
+            m.steps.iter().all(|step| step.at_ms >= 0),
+            "ChoreographyStep.at_ms must not be negative",
+        )?;
+        Ok(self.0)
+    }
+}
+
+builder! {
+    DialogueOptionsDirectiveBuilder for DialogueOptionsDirective {}
+    /// Prompt id, echoed in DialogueChoiceObservation (required)
+    fn prompt_id(prompt_id: impl Into<String>) => prompt_id = prompt_id.into();
+    /// NPC asking (required)
+    fn npc_id(npc: &NpcId) => npc_id = npc.to_string();
+    /// Player to show the options to (required)
+    fn player_uuid(player: &PlayerUuid) => player_uuid = player.to_string();
+    /// Shown above the options
+    fn text(text: impl Into<String>) => text = text.into();
+    /// Choices in display order (at least one), see `prompts::option`
+    fn options(options: impl IntoIterator<Item = DialogueOption>) => options = options.into_iter().collect();
+    /// When the prompt closes (Unix ms, default: when answered or replaced)
+    fn expires_at_ms(expires_at_ms: i64) => expires_at_ms = expires_at_ms;
+    /// When the prompt closes, as a [`SystemTime`]
+    fn expires_at(time: SystemTime) => expires_at_ms = time::unix_ms(time);
+    check(m) {
+        require(!m.prompt_id.is_empty(), "DialogueOptionsDirective.prompt_id is required")?;
+        require(!m.npc_id.is_empty(), "DialogueOptionsDirective.npc_id is required")?;
+        require(!m.player_uuid.is_empty(), "DialogueOptionsDirective.player_uuid is required")?;
+        require(!m.options.is_empty(), "DialogueOptionsDirective needs at least one option")?;
+        require(
+            m.options.iter().all(|o| !o.option_id.is_empty() && !o.label.is_empty()),
+            "DialogueOption.option_id and label are required",
+        )?;
+        require(
+            m.options
+                .iter()
+                .enumerate()
+                .all(|(i, o)| m.options[..i].iter().all(|p| p.option_id != o.option_id)),
+            "DialogueOption.option_id must be unique within the prompt",
+        )?;
+    }
+}
+
+builder! {
+    ShopDefinitionBuilder for ShopDefinition {}
+    /// Shop id, echoed in ShopTradeObservation (required)
+    fn shop_id(shop_id: impl Into<String>) => shop_id = shop_id.into();
+    /// NPC running the shop (required)
+    fn npc_id(npc: &NpcId) => npc_id = npc.to_string();
+    /// GUI title (default: the NPC's name)
+    fn title(title: impl Into<String>) => title = title.into();
+    /// Listings in slot order, at most 54; see `shop::listing` (none closes the shop)
+    fn listings(listings: impl IntoIterator<Item = ShopListing>) => listings = listings.into_iter().collect();
+    /// Currency of all prices (default: the economy's)
+    fn currency(currency: impl Into<String>) => currency = currency.into();
+    /// Open the GUI for these players now (default: on interaction)
+    fn open_for(players: impl IntoIterator<Item = PlayerUuid>) => open_for_player_uuids = players.into_iter().map(String::from).collect();
+    check(m) {
+        require(!m.shop_id.is_empty(), "ShopDefinition.shop_id is required")?;
+        require(!m.npc_id.is_empty(), "ShopDefinition.npc_id is required")?;
+        require(m.listings.len() <= 54, "ShopDefinition has more than 54 listings")?;
+        require(
+            m.listings
+                .iter()
+                .enumerate()
+                .all(|(i, l)| !l.listing_id.is_empty() && m.listings[..i].iter().all(|o| o.listing_id != l.listing_id)),
+            "ShopListing.listing_id is required and must be unique within the shop",
+        )?;
+        require(
+            m.listings.iter().all(|l| l.item.as_ref().is_some_and(|i| !i.item_type.is_empty() && i.quantity > 0)),
+            "ShopListing.item needs an item type and a positive quantity",
+        )?;
+        require(
+            m.listings.iter().all(|l| l.buy_price >= 0 && l.sell_price >= 0 && l.buy_price + l.sell_price > 0),
+            "ShopListing needs a buy or sell price, neither negative",
+        )?;
+        require(m.listings.iter().all(|l| l.stock >= -1), "ShopListing.stock must be -1 (unlimited) or more")?;
+    }
+}
+
+builder! {
+    ShowDisplayDirectiveBuilder for ShowDisplayDirective {}
+    /// Display id; showing it again updates it (required)
+    fn display_id(display_id: impl Into<String>) => display_id = display_id.into();
+    /// NPC the display belongs to (required)
+    fn npc_id(npc: &NpcId) => npc_id = npc.to_string();
+    /// Only these players see it (default: everyone in range)
+    fn players(players: impl IntoIterator<Item = PlayerUuid>) => player_uuids = players.into_iter().map(String::from).collect();
+    /// Visibility range in blocks (default: the plugin's)
+    fn range(blocks: f32) => range = blocks;
+    /// Floating text
+    fn hologram(hologram: HologramDisplay) => display = Some(Display::Hologram(hologram));
+    /// Bar at the top of the screen
+    fn boss_bar(boss_bar: BossBarDisplay) => display = Some(Display::BossBar(boss_bar));
+    /// Sidebar
+    fn scoreboard(scoreboard: ScoreboardDisplay) => display = Some(Display::Scoreboard(scoreboard));
+    check(m) {
+        require(!m.display_id.is_empty(), "ShowDisplayDirective.display_id is required")?;
+        require(!m.npc_id.is_empty(), "ShowDisplayDirective.npc_id is required")?;
+        require(m.range >= 0.0, "ShowDisplayDirective.range must not be negative")?;
+        match &m.display {
+            Some(Display::Hologram(h)) => require(!h.lines.is_empty(), "HologramDisplay needs a line")?,
+            Some(Display::BossBar(b)) => {
+                require((0.0..=1.0).contains(&b.progress), "BossBarDisplay.progress must be within 0.0-1.0")?;
+                require(
+                    matches!(b.segments, 0 | 6 | 10 | 12 | 20),
+                    "BossBarDisplay.segments must be 0, 6, 10, 12 or 20",
+                )?;
+            }
+            Some(Display::Scoreboard(s)) => {
+                require(s.lines.len() <= 15, "ScoreboardDisplay has more than 15 lines")?
+            }
+            None => require(false, "ShowDisplayDirective needs a hologram, boss bar or scoreboard")?,
+        }
+    }
+}
+
+builder! {
+    PlayParticleDirectiveBuilder for PlayParticleDirective {}
+    /// Particle id, e.g. "minecraft:heart" (required)
+    fn particle(particle: impl Into<String>) => particle = particle.into();
+    /// Play it around this NPC (this or `position` is required)
+    fn npc_id(npc: &NpcId) => npc_id = npc.to_string();
+    /// Play it here (this or `npc_id` is required)
+    fn position(position: Position) => position = Some(position);
+    /// Number of particles (default 1)
+    fn count(count: i32) => count = count;
+    /// Particle speed
+    fn speed(speed: f32) => speed = speed;
+    /// Only these players see it (default: everyone nearby)
+    fn players(players: impl IntoIterator<Item = PlayerUuid>) => player_uuids = players.into_iter().map(String::from).collect();
+    check(m) {
+        require(!m.particle.is_empty(), "PlayParticleDirective.particle is required")?;
+        require(!m.npc_id.is_empty() || m.position.is_some(), "PlayParticleDirective needs an npc_id or position")?;
+        require(m.count >= 0, "PlayParticleDirective.count must not be negative")?;
+        require(
+            m.offset_x >= 0.0 && m.offset_y >= 0.0 && m.offset_z >= 0.0,
+            "PlayParticleDirective spread must not be negative",
+        )?;
+    }
+}
+
+impl PlayParticleDirectiveBuilder {
+    /// Random spread around the spot along each axis, in blocks
+    pub fn spread(mut self, x: f32, y: f32, z: f32) -> Self {
+        self.0.offset_x = x;
+        self.0.offset_y = y;
+        self.0.offset_z = z;
+        self
+    }
+}
+
+builder! {
+    PlaySoundDirectiveBuilder for PlaySoundDirective {}
+    /// Sound id, e.g. "minecraft:block.anvil.use" (required)
+    fn sound(sound: impl Into<String>) => sound = sound.into();
+    /// Play it at this NPC (this or `position` is required)
+    fn npc_id(npc: &NpcId) => npc_id = npc.to_string();
+    /// Play it here (this or `npc_id` is required)
+    fn position(position: Position) => position = Some(position);
+    /// Loudness (default 1.0)
+    fn volume(volume: f32) => volume = volume;
+    /// 0.5-2.0 (default 1.0)
+    fn pitch(pitch: f32) => pitch = pitch;
+    /// Category players set the volume of (default "neutral")
+    fn category(category: impl Into<String>) => category = category.into();
+    /// Only these players hear it (default: everyone in range)
+    fn players(players: impl IntoIterator<Item = PlayerUuid>) => player_uuids = players.into_iter().map(String::from).collect();
+    check(m) {
+        require(!m.sound.is_empty(), "PlaySoundDirective.sound is required")?;
+        require(!m.npc_id.is_empty() || m.position.is_some(), "PlaySoundDirective needs an npc_id or position")?;
+        require(m.volume >= 0.0, "PlaySoundDirective.volume must not be negative")?;
+        require(m.pitch == 0.0 || (0.5..=2.0).contains(&m.pitch), "PlaySoundDirective.pitch must be within 0.5-2.0")?;
+    }
+}
+
+builder! {
+    SetTimeDirectiveBuilder for SetTimeDirective {}
+    /// Unique ID, echoed in DirectiveRejected (required)
+    fn directive_id(id: &DirectiveId) => directive_id = id.to_string();
+    /// NPC staging the event; its world is used when `world` is unset
+    fn npc_id(npc: &NpcId) => npc_id = npc.to_string();
+    /// World to change (this or `npc_id` is required)
+    fn world(world: impl Into<String>) => world = world.into();
+    /// Time of day in ticks, 0-23999 (default 0 = 06:00)
+    fn time_of_day(ticks: i64) => time_of_day = ticks;
+    /// Why, for the plugin's log
+    fn reason(reason: impl Into<String>) => reason = reason.into();
+    check(m) {
+        require(!m.directive_id.is_empty(), "SetTimeDirective.directive_id is required")?;
+        require(!m.world.is_empty() || !m.npc_id.is_empty(), "SetTimeDirective needs a world or npc_id")?;
+        require((0..24_000).contains(&m.time_of_day), "SetTimeDirective.time_of_day must be within 0-23999")?;
+    }
+}
+
+builder! {
+    SetWeatherDirectiveBuilder for SetWeatherDirective {}
+    /// Unique ID, echoed in DirectiveRejected (required)
+    fn directive_id(id: &DirectiveId) => directive_id = id.to_string();
+    /// NPC staging the event; its world is used when `world` is unset
+    fn npc_id(npc: &NpcId) => npc_id = npc.to_string();
+    /// World to change (this or `npc_id` is required)
+    fn world(world: impl Into<String>) => world = world.into();
+    /// Weather to set (required)
+    fn weather(weather: Weather) => weather = weather as i32;
+    /// Ticks until the server's weather cycle resumes (default: the server picks)
+    fn duration_ticks(ticks: i32) => duration_ticks = ticks;
+    /// Why, for the plugin's log
+    fn reason(reason: impl Into<String>) => reason = reason.into();
+    check(m) {
+        require(!m.directive_id.is_empty(), "SetWeatherDirective.directive_id is required")?;
+        require(!m.world.is_empty() || !m.npc_id.is_empty(), "SetWeatherDirective needs a world or npc_id")?;
+        require(m.weather() != Weather::Unspecified, "SetWeatherDirective.weather is required")?;
+        require(m.duration_ticks >= 0, "SetWeatherDirective.duration_ticks must not be negative")?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_and_validation() {
+        let action = MoveAction::builder().target(Position::default()).build().unwrap();
+        assert_eq!((action.speed, action.pathfind), (0.5, true));
+        assert_eq!(action.allow_swim, None);
+        let action = MoveAction::builder()
+            .target(Position::default())
+            .allow_swim(false)
+            .avoid_water(true)
+            .build()
+            .unwrap();
+        assert_eq!((action.allow_swim, action.avoid_water), (Some(false), true));
+        assert_eq!(action.doors(), DoorPolicy::CloseBehind);
+        let action = MoveAction::builder()
+            .target(Position::default())
+            .fence_gates(DoorPolicy::Avoid)
+            .build()
+            .unwrap();
+        assert_eq!((action.fence_gates(), action.trapdoors()), (DoorPolicy::Avoid, DoorPolicy::CloseBehind));
+        assert_eq!(
+            MoveAction::builder().speed(2.0).target(Position::default()).build().unwrap_err().to_string(),
+            "invalid message: MoveAction.speed must be within 0.0-1.0"
+        );
+        assert!(ScanBlocksAction::builder().center(BlockPosition::default()).build().is_err());
+
+        let directive = ActionDirective::builder()
+            .directive_id(&DirectiveId::new("d-1").unwrap())
+            .npc_id(&NpcId::new("miner").unwrap())
+            .action(StopAction::builder().cancel_pending(true).build().unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(directive.priority, 1);
+        assert!(matches!(directive.action, Some(Action::Stop(StopAction { cancel_pending: true }))));
+        assert!(ActionDirective::builder().npc_id(&NpcId::new("miner").unwrap()).build().is_err());
+
+        let whisper = SpeakDirective::builder()
+            .npc_id(&NpcId::new("bard").unwrap())
+            .text("psst")
+            .delivery(SpeechDelivery::Direct)
+            .duration(std::time::Duration::from_secs(2))
+            .tone(Tone::parse("scared"));
+        assert!(whisper.clone().build().is_err());
+        let player = PlayerUuid::new("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap();
+        let speak = whisper.target_players([player]).build().unwrap();
+        assert_eq!(speak.volume, 1.0);
+        assert_eq!(speak.duration_ms, 2_000);
+        assert_eq!(speak.emotion, "fearful");
+    }
+
+    #[test]
+    fn test_choreography_sorts_steps() {
+        let guard = NpcId::new("guard").unwrap();
+        let line = |text: &str| SpeakDirective::builder().npc_id(&guard).text(text).build().unwrap();
+        let scene = ChoreographyDirective::builder()
+            .directive_id(&DirectiveId::new("scene-1").unwrap())
+            .speak(2000, line("Halt!"))
+            .action(0, &guard, StopAction::builder().build().unwrap())
+            .speak(2000, line("Who goes there?"))
+            .after_previous()
+            .build()
+            .unwrap();
+        let texts: Vec<_> = scene
+            .steps
+            .iter()
+            .map(|step| match &step.step {
+                Some(Step::Speak(m)) => m.text.as_str(),
+                _ => "action",
+            })
+            .collect();
+        assert_eq!(texts, ["action", "Halt!", "Who goes there?"]);
+        assert!(scene.steps[2].after_previous);
+        assert!(crate::validate::Validate::validate(&scene).is_ok());
+
+        assert!(ChoreographyDirective::builder()
+            .directive_id(&DirectiveId::new("scene-2").unwrap())
+            .build()
+            .is_err());
+        assert!(ChoreographyDirective::builder()
+            .directive_id(&DirectiveId::new("scene-3").unwrap())
+            .speak(-1, line("too soon"))
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,378 @@
+//! Chat preprocessing.
+//!
+//! Every conversational daemon does the same things to a ChatObservation
+//! before deciding how to answer: work out the language, filter
+//! profanity, guess what the player wants. A [`ChatPipeline`] runs such
+//! [`ChatStage`]s in order and hands the handler an [`EnrichedChat`]: the
+//! observation (rewritten by stages like [`ProfanityFilter`]) plus
+//! [`ChatAnnotations`]. Any stage can drop the message.
+//!
+//! Stages run on the stream's task, so keep them fast; classify with a
+//! model from a spawned task instead.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use crate::npc_society::v1::ChatObservation;
+
+/// What a player wants, as guessed by an [`IntentClassifier`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Intent {
+    /// Classifier-defined label, e.g. `trade`
+    pub label: String,
+    /// 0.0-1.0
+    pub confidence: f32,
+}
+
+/// Structured results of the stages that ran
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatAnnotations {
+    /// ISO 639-1 code, e.g. `en`
+    pub language: Option<String>,
+    /// -1.0 (hostile) to 1.0 (friendly)
+    pub sentiment: Option<f32>,
+    /// What the player wants
+    pub intent: Option<Intent>,
+    /// Whether a [`ProfanityFilter`] masked anything
+    pub profane: bool,
+    /// Annotations of custom stages, by key
+    pub labels: BTreeMap<String, String>,
+}
+
+/// A chat message after the pipeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnrichedChat {
+    /// The observation, as rewritten by the stages
+    pub chat: ChatObservation,
+    /// What the stages found out
+    pub annotations: ChatAnnotations,
+}
+
+/// What a stage decided
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StageOutcome {
+    /// Run the next stage
+    Continue,
+    /// Drop the message, for the given reason
+    Drop(String),
+}
+
+/// A message dropped by a stage
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatDropped {
+    /// [`ChatStage::name`] of the stage
+    pub stage: &'static str,
+    /// Why
+    pub reason: String,
+}
+
+impl fmt::Display for ChatDropped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "chat dropped by {}: {}", self.stage, self.reason)
+    }
+}
+
+impl std::error::Error for ChatDropped {}
+
+/// One preprocessing step.
+pub trait ChatStage: Send + Sync {
+    /// Name for logs and [`ChatDropped`]
+    fn name(&self) -> &'static str;
+
+    /// Annotate or rewrite `chat`
+    fn enrich(&self, chat: &mut EnrichedChat) -> StageOutcome;
+}
+
+/// Stages run on every ChatObservation, in order.
+#[derive(Default)]
+pub struct ChatPipeline {
+    stages: Vec<Box<dyn ChatStage>>,
+}
+
+impl fmt::Debug for ChatPipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.stages.iter().map(|s| s.name()))
+            .finish()
+    }
+}
+
+impl ChatPipeline {
+    /// Append a stage
+    pub fn stage(mut self, stage: impl ChatStage + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Run every stage on `chat`
+    pub fn run(&self, chat: ChatObservation) -> Result<EnrichedChat, ChatDropped> {
+        let mut enriched = EnrichedChat {
+            chat,
+            annotations: ChatAnnotations::default(),
+        };
+        for stage in &self.stages {
+            if let StageOutcome::Drop(reason) = stage.enrich(&mut enriched) {
+                return Err(ChatDropped {
+                    stage: stage.name(),
+                    reason,
+                });
+            }
+        }
+        Ok(enriched)
+    }
+}
+
+/// Lowercase words of `text`
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Guesses the language from common short words. Good enough to pick a
+/// reply language for a sentence of chat; one-word messages often stay
+/// undetected.
+#[derive(Debug, Clone)]
+pub struct LanguageDetector {
+    /// ISO 639-1 code and stopwords per language
+    pub languages: Vec<(&'static str, &'static [&'static str])>,
+}
+
+const ENGLISH: &[&str] = &["the", "and", "you", "is", "are", "what", "where", "i", "to", "have"];
+const GERMAN: &[&str] = &["der", "die", "das", "und", "ist", "ich", "du", "nicht", "wo", "hast"];
+const FRENCH: &[&str] = &["le", "la", "les", "et", "est", "je", "tu", "pas", "où", "avez"];
+const SPANISH: &[&str] = &["el", "los", "y", "es", "yo", "tú", "no", "dónde", "que", "tienes"];
+
+impl Default for LanguageDetector {
+    /// English, German, French and Spanish
+    fn default() -> Self {
+        Self {
+            languages: vec![("en", ENGLISH), ("de", GERMAN), ("fr", FRENCH), ("es", SPANISH)],
+        }
+    }
+}
+
+impl ChatStage for LanguageDetector {
+    fn name(&self) -> &'static str {
+        "language"
+    }
+
+    fn enrich(&self, chat: &mut EnrichedChat) -> StageOutcome {
+        let words: Vec<String> = words(&chat.chat.message).collect();
+        let hits = |stopwords: &[&str]| {
+            words
+                .iter()
+                .filter(|w| stopwords.contains(&w.as_str()))
+                .count()
+        };
+        chat.annotations.language = self
+            .languages
+            .iter()
+            .map(|(code, stopwords)| (hits(stopwords), *code))
+            .filter(|(hits, _)| *hits > 0)
+            .max_by_key(|(hits, _)| *hits)
+            .map(|(_, code)| code.to_string());
+        StageOutcome::Continue
+    }
+}
+
+/// Masks listed words with `*`, or drops the message
+#[derive(Debug, Clone, Default)]
+pub struct ProfanityFilter {
+    /// Lowercase words to filter
+    pub words: HashSet<String>,
+    /// Drop messages containing them instead of masking
+    pub drop: bool,
+}
+
+impl ProfanityFilter {
+    /// Filter `words`, masking them
+    pub fn new<'a>(words: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            words: words.into_iter().map(str::to_lowercase).collect(),
+            drop: false,
+        }
+    }
+
+    /// `text` with the listed words masked; with `drop`, the reason to
+    /// drop it instead
+    pub fn filter(&self, text: &str) -> Result<String, String> {
+        let mut masked = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(char::is_alphanumeric) {
+            masked.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest
+                .find(|c: char| !c.is_alphanumeric())
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            if self.words.contains(&word.to_lowercase()) {
+                if self.drop {
+                    return Err(format!("contains {word:?}"));
+                }
+                masked.extend(word.chars().map(|_| '*'));
+            } else {
+                masked.push_str(word);
+            }
+            rest = &rest[end..];
+        }
+        masked.push_str(rest);
+        Ok(masked)
+    }
+}
+
+impl ChatStage for ProfanityFilter {
+    fn name(&self) -> &'static str {
+        "profanity"
+    }
+
+    fn enrich(&self, chat: &mut EnrichedChat) -> StageOutcome {
+        match self.filter(&chat.chat.message) {
+            Ok(masked) => {
+                chat.annotations.profane |= masked != chat.chat.message;
+                chat.chat.message = masked;
+                StageOutcome::Continue
+            }
+            Err(reason) => StageOutcome::Drop(reason),
+        }
+    }
+}
+
+/// Scores sentiment with a hook like [`ReputationRules::sentiment`](crate::reputation::ReputationRules::sentiment)
+#[derive(Debug, Clone, Copy)]
+pub struct SentimentStage(pub fn(&str) -> f32);
+
+impl ChatStage for SentimentStage {
+    fn name(&self) -> &'static str {
+        "sentiment"
+    }
+
+    fn enrich(&self, chat: &mut EnrichedChat) -> StageOutcome {
+        chat.annotations.sentiment = Some((self.0)(&chat.chat.message).clamp(-1.0, 1.0));
+        StageOutcome::Continue
+    }
+}
+
+/// Guesses what a player wants from a chat message.
+pub trait IntentClassifier: Send + Sync {
+    /// The most likely intent, if any
+    fn classify(&self, text: &str) -> Option<Intent>;
+}
+
+/// Runs an [`IntentClassifier`] as a stage
+#[derive(Debug, Clone)]
+pub struct IntentStage<C>(pub C);
+
+impl<C: IntentClassifier> ChatStage for IntentStage<C> {
+    fn name(&self) -> &'static str {
+        "intent"
+    }
+
+    fn enrich(&self, chat: &mut EnrichedChat) -> StageOutcome {
+        chat.annotations.intent = self.0.classify(&chat.chat.message);
+        StageOutcome::Continue
+    }
+}
+
+/// Intent by keyword: the label with the most keywords in the message
+/// wins, with the share of its keywords found as confidence
+#[derive(Debug, Clone, Default)]
+pub struct KeywordIntents {
+    /// Label and lowercase keywords
+    pub intents: Vec<(String, Vec<String>)>,
+}
+
+impl KeywordIntents {
+    /// Add an intent recognized by `keywords`
+    pub fn intent<'a>(mut self, label: &str, keywords: impl IntoIterator<Item = &'a str>) -> Self {
+        let keywords = keywords.into_iter().map(str::to_lowercase).collect();
+        self.intents.push((label.to_string(), keywords));
+        self
+    }
+}
+
+impl IntentClassifier for KeywordIntents {
+    fn classify(&self, text: &str) -> Option<Intent> {
+        let words: HashSet<String> = words(text).collect();
+        self.intents
+            .iter()
+            .map(|(label, keywords)| {
+                let hits = keywords.iter().filter(|k| words.contains(*k)).count();
+                (hits, label, keywords.len())
+            })
+            .filter(|(hits, _, _)| *hits > 0)
+            .max_by_key(|(hits, _, _)| *hits)
+            .map(|(hits, label, total)| Intent {
+                label: label.clone(),
+                confidence: hits as f32 / total as f32,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat(message: &str) -> ChatObservation {
+        ChatObservation {
+            npc_id: "miner".to_string(),
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn pipeline() -> ChatPipeline {
+        ChatPipeline::default()
+            .stage(LanguageDetector::default())
+            .stage(ProfanityFilter::new(["darn"]))
+            .stage(SentimentStage(|text| {
+                if text.contains("thanks") {
+                    1.0
+                } else {
+                    0.0
+                }
+            }))
+            .stage(IntentStage(
+                KeywordIntents::default()
+                    .intent("mine", ["diamond", "diamonds", "mine", "dig"])
+                    .intent("trade", ["buy", "sell", "trade"]),
+            ))
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let enriched = pipeline()
+            .run(chat("Where are the darn DIAMONDS? Dig, thanks!"))
+            .unwrap();
+        assert_eq!(
+            enriched.chat.message,
+            "Where are the **** DIAMONDS? Dig, thanks!"
+        );
+        let annotations = enriched.annotations;
+        assert_eq!(annotations.language.as_deref(), Some("en"));
+        assert!(annotations.profane);
+        assert_eq!(annotations.sentiment, Some(1.0));
+        let intent = annotations.intent.unwrap();
+        assert_eq!(intent.label, "mine");
+        assert!((intent.confidence - 0.5).abs() < 1e-6);
+
+        let enriched = pipeline().run(chat("Wo ist der Schmied?")).unwrap();
+        assert_eq!(enriched.annotations.language.as_deref(), Some("de"));
+        assert_eq!(enriched.annotations.intent, None);
+        assert_eq!(
+            format!("{:?}", pipeline()),
+            r#"["language", "profanity", "sentiment", "intent"]"#
+        );
+    }
+
+    #[test]
+    fn test_drop() {
+        let strict = ChatPipeline::default().stage(ProfanityFilter {
+            drop: true,
+            ..ProfanityFilter::new(["darn"])
+        });
+        let dropped = strict.run(chat("darn it")).unwrap_err();
+        assert_eq!(dropped.stage, "profanity");
+        assert!(strict.run(chat("darnation")).is_ok());
+    }
+}
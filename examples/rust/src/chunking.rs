@@ -0,0 +1,438 @@
+//! Message size limits and split ActionResults.
+//!
+//! tonic rejects messages over 4 MB in either direction, and a big
+//! ScanBlocks, RegionSnapshot or container Inventory result easily exceeds
+//! that; the stream then fails with a bare
+//! `OUT_OF_RANGE`/`RESOURCE_EXHAUSTED`. [`MessageLimits`] sets both limits on
+//! the service. Results still too large are split by the plugin into
+//! parts numbered by `ActionResult.part`: [`split_result`] does the
+//! splitting (plugin bridges, tests, replay tools) and [`ResultAssembler`]
+//! puts the parts back together on the daemon.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use prost::Message;
+
+use crate::npc_society::v1::{
+    action_result::Result as ActionResultType, npc_society_service_server::NpcSocietyServiceServer,
+    ActionResult, BlockMatch, ItemStack, ResultPart,
+};
+
+/// tonic's default limit for both directions
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 4 * 1024 * 1024;
+
+/// Most parts a result may be split into
+pub const MAX_RESULT_PARTS: i32 = 1024;
+
+/// Largest message the service accepts and sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageLimits {
+    /// Inbound (plugin -> daemon) limit
+    pub max_decoding_bytes: usize,
+    /// Outbound (daemon -> plugin) limit
+    pub max_encoding_bytes: usize,
+}
+
+impl Default for MessageLimits {
+    /// 16 MB inbound for large scans, tonic's 4 MB outbound
+    fn default() -> Self {
+        Self {
+            max_decoding_bytes: 16 * 1024 * 1024,
+            max_encoding_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+        }
+    }
+}
+
+impl MessageLimits {
+    /// Apply the limits to the daemon's service
+    pub fn server<T>(&self, server: NpcSocietyServiceServer<T>) -> NpcSocietyServiceServer<T> {
+        server
+            .max_decoding_message_size(self.max_decoding_bytes)
+            .max_encoding_message_size(self.max_encoding_bytes)
+    }
+}
+
+/// Split `result` into parts of at most `max_bytes` encoded, or return it
+/// whole if it fits. ScanBlocks matches, RegionSnapshot runs and Inventory
+/// items are split; a single match or item larger than `max_bytes` still
+/// gets a part of its own.
+pub fn split_result(mut result: ActionResult, max_bytes: usize) -> Vec<ActionResult> {
+    if result.encoded_len() <= max_bytes {
+        return vec![result];
+    }
+    // Everything but the split data goes into every part, with room for the
+    // largest part numbers and the packed run fields' own tags and lengths
+    let base = |result: &mut ActionResult| {
+        result.part = Some(ResultPart {
+            index: MAX_RESULT_PARTS,
+            count: MAX_RESULT_PARTS,
+        });
+        max_bytes.saturating_sub(result.encoded_len() + 4 * (1 + 5))
+    };
+    match &mut result.result {
+        Some(ActionResultType::ScanBlocksResult(scan)) => {
+            let matches = std::mem::take(&mut scan.matches);
+            let budget = base(&mut result);
+            let size = |m: &BlockMatch| {
+                let len = m.encoded_len();
+                // Tag byte plus length prefix
+                1 + prost::length_delimiter_len(len) + len
+            };
+            parts(&result, pack(matches, budget, size), |part, matches| {
+                if let Some(ActionResultType::ScanBlocksResult(scan)) = &mut part.result {
+                    scan.matches = matches;
+                }
+            })
+        }
+        Some(ActionResultType::InventoryResult(inventory)) => {
+            let items = std::mem::take(&mut inventory.items);
+            let budget = base(&mut result);
+            let size = |item: &ItemStack| {
+                let len = item.encoded_len();
+                1 + prost::length_delimiter_len(len) + len
+            };
+            parts(&result, pack(items, budget, size), |part, items| {
+                if let Some(ActionResultType::InventoryResult(inventory)) = &mut part.result {
+                    inventory.items = items;
+                }
+            })
+        }
+        Some(ActionResultType::RegionSnapshotResult(region)) => {
+            // Block runs first, then light runs
+            let take = std::mem::take;
+            let blocks = take(&mut region.run_palette_indices)
+                .into_iter()
+                .zip(take(&mut region.run_lengths))
+                .map(|(value, length)| Run::Block(value, length));
+            let light = take(&mut region.light_run_levels)
+                .into_iter()
+                .zip(take(&mut region.light_run_lengths))
+                .map(|(value, length)| Run::Light(value, length));
+            let runs = blocks.chain(light).collect();
+            let budget = base(&mut result);
+            let size = |run: &Run| {
+                let (Run::Block(value, length) | Run::Light(value, length)) = *run;
+                prost::length_delimiter_len(value as usize)
+                    + prost::length_delimiter_len(length as usize)
+            };
+            parts(&result, pack(runs, budget, size), |part, runs| {
+                if let Some(ActionResultType::RegionSnapshotResult(region)) = &mut part.result {
+                    for run in runs {
+                        match run {
+                            Run::Block(value, length) => {
+                                region.run_palette_indices.push(value);
+                                region.run_lengths.push(length);
+                            }
+                            Run::Light(value, length) => {
+                                region.light_run_levels.push(value);
+                                region.light_run_lengths.push(length);
+                            }
+                        }
+                    }
+                }
+            })
+        }
+        _ => vec![result],
+    }
+}
+
+/// A (value, length) run of a RegionSnapshotResult
+#[derive(Clone, Copy)]
+enum Run {
+    Block(u32, u32),
+    Light(u32, u32),
+}
+
+/// Group `items` into slices of at most `budget` bytes (at least one item each)
+fn pack<T>(items: Vec<T>, budget: usize, size: impl Fn(&T) -> usize) -> Vec<Vec<T>> {
+    let mut slices = vec![Vec::new()];
+    let mut used = 0;
+    for item in items {
+        let size = size(&item);
+        if !slices.last().unwrap().is_empty() && used + size > budget {
+            slices.push(Vec::new());
+            used = 0;
+        }
+        slices.last_mut().unwrap().push(item);
+        used += size;
+    }
+    slices
+}
+
+/// One numbered copy of `result` per slice, filled in by `fill`
+fn parts<T>(
+    result: &ActionResult,
+    slices: Vec<Vec<T>>,
+    fill: impl Fn(&mut ActionResult, Vec<T>),
+) -> Vec<ActionResult> {
+    let count = slices.len() as i32;
+    slices
+        .into_iter()
+        .enumerate()
+        .map(|(index, slice)| {
+            let mut part = result.clone();
+            part.part = Some(ResultPart {
+                index: index as i32,
+                count,
+            });
+            fill(&mut part, slice);
+            part
+        })
+        .collect()
+}
+
+/// A part whose numbering doesn't fit: index out of range, count changed
+/// between parts, or a part sent twice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartError {
+    /// Result the part belongs to
+    pub directive_id: String,
+    /// `ResultPart.index` as received
+    pub index: i32,
+    /// `ResultPart.count` as received
+    pub count: i32,
+}
+
+impl fmt::Display for PartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unexpected part {}/{} of result {}",
+            self.index, self.count, self.directive_id
+        )
+    }
+}
+
+impl std::error::Error for PartError {}
+
+#[derive(Debug)]
+struct Pending {
+    parts: Vec<Option<ActionResult>>,
+    received: usize,
+}
+
+/// Reassembles split ActionResults, keyed by directive_id.
+#[derive(Debug, Default)]
+pub struct ResultAssembler {
+    pending: HashMap<String, Pending>,
+}
+
+impl ResultAssembler {
+    /// The complete result once its last part arrived; results that were
+    /// not split pass straight through. A bad part discards the whole
+    /// result.
+    pub fn push(&mut self, result: ActionResult) -> Result<Option<ActionResult>, PartError> {
+        let Some(ResultPart { index, count }) = result.part else {
+            return Ok(Some(result));
+        };
+        let error = || PartError {
+            directive_id: result.directive_id.clone(),
+            index,
+            count,
+        };
+        if !(1..=MAX_RESULT_PARTS).contains(&count) || !(0..count).contains(&index) {
+            self.pending.remove(&result.directive_id);
+            return Err(error());
+        }
+
+        let pending = self
+            .pending
+            .entry(result.directive_id.clone())
+            .or_insert_with(|| Pending {
+                parts: vec![None; count as usize],
+                received: 0,
+            });
+        if pending.parts.len() != count as usize || pending.parts[index as usize].is_some() {
+            let error = error();
+            self.pending.remove(&result.directive_id);
+            return Err(error);
+        }
+        let directive_id = result.directive_id.clone();
+        pending.parts[index as usize] = Some(result);
+        pending.received += 1;
+        if pending.received < pending.parts.len() {
+            return Ok(None);
+        }
+
+        let mut parts = self
+            .pending
+            .remove(&directive_id)
+            .unwrap()
+            .parts
+            .into_iter()
+            .flatten();
+        let mut whole = parts.next().unwrap();
+        whole.part = None;
+        for part in parts {
+            match (&mut whole.result, part.result) {
+                (
+                    Some(ActionResultType::ScanBlocksResult(whole)),
+                    Some(ActionResultType::ScanBlocksResult(part)),
+                ) => whole.matches.extend(part.matches),
+                (
+                    Some(ActionResultType::InventoryResult(whole)),
+                    Some(ActionResultType::InventoryResult(part)),
+                ) => whole.items.extend(part.items),
+                (
+                    Some(ActionResultType::RegionSnapshotResult(whole)),
+                    Some(ActionResultType::RegionSnapshotResult(part)),
+                ) => {
+                    whole.run_palette_indices.extend(part.run_palette_indices);
+                    whole.run_lengths.extend(part.run_lengths);
+                    whole.light_run_levels.extend(part.light_run_levels);
+                    whole.light_run_lengths.extend(part.light_run_lengths);
+                }
+                _ => {}
+            }
+        }
+        Ok(Some(whole))
+    }
+
+    /// Forget the parts received for a result (e.g. its NPC despawned)
+    pub fn discard(&mut self, directive_id: &str) {
+        self.pending.remove(directive_id);
+    }
+
+    /// Results waiting for more parts
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{BlockPosition, Enchantment, InventoryResult, ScanBlocksResult};
+    use crate::region::Region;
+
+    fn scan(matches: i32) -> ActionResult {
+        ActionResult {
+            directive_id: "scan-1".to_string(),
+            npc_id: "miner".to_string(),
+            success: true,
+            result: Some(ActionResultType::ScanBlocksResult(ScanBlocksResult {
+                matches: (0..matches)
+                    .map(|x| BlockMatch {
+                        position: Some(BlockPosition {
+                            world: "world".to_string(),
+                            x,
+                            y: 12,
+                            z: -x,
+                            ..Default::default()
+                        }),
+                        block_type: "minecraft:stone".to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_split_and_reassemble() {
+        let result = scan(1000);
+        assert_eq!(
+            split_result(result.clone(), usize::MAX),
+            vec![result.clone()]
+        );
+
+        let mut parts = split_result(result.clone(), 4096);
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|p| p.encoded_len() <= 4096));
+
+        // Parts may arrive in any order
+        parts.reverse();
+        let last = parts.pop().unwrap();
+        let mut assembler = ResultAssembler::default();
+        for part in parts {
+            assert_eq!(assembler.push(part), Ok(None));
+        }
+        assert_eq!(assembler.pending(), 1);
+        assert_eq!(assembler.push(last), Ok(Some(result)));
+        assert_eq!(assembler.pending(), 0);
+    }
+
+    #[test]
+    fn test_split_region() {
+        // Alternating blocks and changing light: one run per block
+        let min = BlockPosition {
+            world: "world".to_string(),
+            ..Default::default()
+        };
+        let mut region = Region::new(min.clone(), [64, 4, 64]).unwrap();
+        for (x, y, z) in
+            (0..64).flat_map(|x| (0..4).flat_map(move |y| (0..64).map(move |z| (x, y, z))))
+        {
+            let block = if (x + z) % 2 == 0 {
+                "minecraft:stone"
+            } else {
+                "minecraft:dirt"
+            };
+            let position = BlockPosition {
+                x,
+                y,
+                z,
+                ..min.clone()
+            };
+            region.set(&position, block);
+            region.set_light_level(&position, (x % 16) as u8);
+        }
+        let result = ActionResult {
+            directive_id: "region-1".to_string(),
+            result: Some(ActionResultType::RegionSnapshotResult(region.encode())),
+            ..Default::default()
+        };
+
+        let parts = split_result(result.clone(), 4096);
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|p| p.encoded_len() <= 4096));
+        let mut assembler = ResultAssembler::default();
+        let whole = parts.into_iter().find_map(|p| assembler.push(p).unwrap());
+        assert_eq!(whole, Some(result));
+    }
+
+    #[test]
+    fn test_split_inventory() {
+        // A chest full of enchanted books
+        let items = (0..54)
+            .map(|slot| ItemStack {
+                item_type: "minecraft:enchanted_book".to_string(),
+                quantity: 1,
+                enchantments: (0..8)
+                    .map(|level| Enchantment {
+                        enchantment_id: format!("minecraft:enchantment_{slot}"),
+                        level,
+                    })
+                    .collect(),
+                ..Default::default()
+            })
+            .collect();
+        let result = ActionResult {
+            directive_id: "withdraw-1".to_string(),
+            result: Some(ActionResultType::InventoryResult(InventoryResult { items })),
+            ..Default::default()
+        };
+
+        let parts = split_result(result.clone(), 2048);
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|p| p.encoded_len() <= 2048));
+        let mut assembler = ResultAssembler::default();
+        let whole = parts.into_iter().find_map(|p| assembler.push(p).unwrap());
+        assert_eq!(whole, Some(result));
+    }
+
+    #[test]
+    fn test_bad_parts() {
+        let mut assembler = ResultAssembler::default();
+        let parts = split_result(scan(100), 512);
+        assert_eq!(assembler.push(parts[0].clone()), Ok(None));
+        assert!(assembler.push(parts[0].clone()).is_err());
+        assert_eq!(assembler.pending(), 0);
+
+        let mut bad = scan(1);
+        bad.part = Some(ResultPart { index: 2, count: 2 });
+        assert!(assembler.push(bad).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! Game time to wall time.
+//!
+//! Minecraft runs 20 ticks per second only when it keeps up; a lagging
+//! server at 15 TPS takes 4 seconds for what should be 3. Every WorldTick
+//! pairs its `server_tick` with the wall-clock `timestamp_ms` at which it
+//! ran, and plugins from v1.2 also report the server's own TPS. A
+//! [`TickClock`] fed with WorldTicks converts between ticks and
+//! milliseconds at the current rate, so "speak for 3000ms, then move" can
+//! be scheduled against the tick it will actually finish on.
+//!
+//! Without reported TPS, the rate is measured from the WorldTicks of the
+//! last [`TickClock::new`] `window` ticks.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::npc_society::v1::WorldTick;
+
+/// Ticks per second of a server without lag
+pub const NOMINAL_TPS: f64 = 20.0;
+
+/// Lowest rate a clock assumes, so conversions stay finite on a frozen server
+const MIN_TPS: f64 = 0.1;
+
+/// Converts between server ticks and Unix milliseconds.
+#[derive(Debug, Clone)]
+pub struct TickClock {
+    /// `(server_tick, timestamp_ms)` of recent WorldTicks, oldest first
+    samples: VecDeque<(i64, i64)>,
+    window: usize,
+    /// `WorldTick.tps` of the latest tick, if reported
+    reported_tps: Option<f64>,
+}
+
+impl Default for TickClock {
+    /// Measures over the last 20 WorldTicks
+    fn default() -> Self {
+        Self::new(20)
+    }
+}
+
+impl TickClock {
+    /// Create a clock that measures TPS over the last `window` WorldTicks
+    /// (at least 2)
+    pub fn new(window: usize) -> Self {
+        Self {
+            samples: VecDeque::new(),
+            window: window.max(2),
+            reported_tps: None,
+        }
+    }
+
+    /// Record a WorldTick. A tick counter that went backwards means the
+    /// server restarted, and earlier samples are discarded.
+    pub fn observe(&mut self, tick: &WorldTick) {
+        if let Some(&(last_tick, last_ms)) = self.samples.back() {
+            if tick.server_tick == last_tick {
+                return;
+            }
+            if tick.server_tick < last_tick || tick.timestamp_ms < last_ms {
+                self.samples.clear();
+            }
+        }
+        self.samples
+            .push_back((tick.server_tick, tick.timestamp_ms));
+        if self.samples.len() > self.window {
+            self.samples.pop_front();
+        }
+        self.reported_tps = (tick.tps > 0.0).then_some(f64::from(tick.tps));
+    }
+
+    /// Current ticks per second: as reported by the server, else as
+    /// measured, else [`NOMINAL_TPS`]
+    pub fn tps(&self) -> f64 {
+        self.reported_tps
+            .or_else(|| self.measured_tps())
+            .unwrap_or(NOMINAL_TPS)
+            .max(MIN_TPS)
+    }
+
+    /// Ticks per second between the oldest and newest WorldTick of the
+    /// window (None before two of them arrived)
+    pub fn measured_tps(&self) -> Option<f64> {
+        let (&(first_tick, first_ms), &(last_tick, last_ms)) =
+            (self.samples.front()?, self.samples.back()?);
+        let elapsed_ms = last_ms - first_ms;
+        (elapsed_ms > 0).then(|| (last_tick - first_tick) as f64 * 1_000.0 / elapsed_ms as f64)
+    }
+
+    /// Whether the server runs noticeably slower than [`NOMINAL_TPS`]
+    pub fn is_lagging(&self) -> bool {
+        self.tps() < NOMINAL_TPS * 0.9
+    }
+
+    /// Wall time a tick takes at the current rate
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.tps())
+    }
+
+    /// Ticks `duration` spans at the current rate, rounded up
+    pub fn ticks_in(&self, duration: Duration) -> i64 {
+        (duration.as_secs_f64() * self.tps()).ceil() as i64
+    }
+
+    /// Wall time `ticks` take at the current rate
+    pub fn duration_of(&self, ticks: i64) -> Duration {
+        Duration::from_secs_f64(ticks.max(0) as f64 / self.tps())
+    }
+
+    /// When `server_tick` did or will run, in Unix milliseconds (None
+    /// before the first WorldTick)
+    pub fn wall_ms(&self, server_tick: i64) -> Option<i64> {
+        let &(tick, ms) = self.samples.back()?;
+        Some(ms + ((server_tick - tick) as f64 * 1_000.0 / self.tps()).round() as i64)
+    }
+
+    /// The server tick running at `wall_ms` (None before the first
+    /// WorldTick)
+    pub fn tick_at(&self, wall_ms: i64) -> Option<i64> {
+        let &(tick, ms) = self.samples.back()?;
+        Some(tick + ((wall_ms - ms) as f64 * self.tps() / 1_000.0).floor() as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(server_tick: i64, timestamp_ms: i64, tps: f32) -> WorldTick {
+        WorldTick {
+            server_tick,
+            timestamp_ms,
+            tps,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_measured_rate() {
+        let mut clock = TickClock::default();
+        assert_eq!(clock.tps(), NOMINAL_TPS);
+        assert_eq!(clock.wall_ms(100), None);
+
+        // A server at 15 TPS, reporting every 5 ticks
+        for i in 0..10 {
+            clock.observe(&tick(1_000 + i * 5, 10_000 + i * 5_000 / 15, 0.0));
+        }
+        assert!((clock.tps() - 15.0).abs() < 0.1);
+        assert!(clock.is_lagging());
+
+        // 3 seconds of speech take 45 ticks, not 60
+        assert_eq!(clock.ticks_in(Duration::from_secs(3)), 45);
+        let now = clock.tick_at(13_000).unwrap();
+        assert_eq!(now, 1_045);
+        let done = clock.wall_ms(now + 45).unwrap();
+        assert!((done - 16_000).abs() <= 1);
+
+        // A restart resets the counter
+        clock.observe(&tick(5, 20_000, 0.0));
+        assert_eq!(clock.measured_tps(), None);
+        assert_eq!(clock.tps(), NOMINAL_TPS);
+    }
+
+    #[test]
+    fn test_reported_rate() {
+        let mut clock = TickClock::default();
+        clock.observe(&tick(0, 0, 10.0));
+        assert_eq!(clock.tps(), 10.0);
+        assert_eq!(clock.duration_of(20), Duration::from_secs(2));
+        assert_eq!(clock.wall_ms(20), Some(2_000));
+
+        clock.observe(&tick(20, 1_000, 0.0));
+        assert_eq!(clock.tps(), 20.0);
+        assert!(!clock.is_lagging());
+    }
+}
@@ -0,0 +1,553 @@
+//! Python and TypeScript bindings with the shape of [`crate::events`], so
+//! daemons prototyped in other languages keep the Rust semantics.
+//!
+//! Each binding has a handler with one `on_*` method per ClientMessage
+//! payload, named after the [`ClientEvent`] variants like
+//! [`NpcSocietyHandler`](crate::events::NpcSocietyHandler)'s, a `dispatch`
+//! function, a wrapper from payload to ServerMessage (`From` in Rust) and a
+//! lookup of the correlation keys a payload carries. Everything else comes
+//! from the schema, so the bindings follow the proto without edits here.
+//!
+//! The bindings import the message code `buf generate` writes
+//! (`npc_society_pb2.py` from protocolbuffers/python, `npc_society_pb.ts`
+//! from protoc-gen-es v2) and go next to it:
+//!
+//! ```text
+//! buf generate
+//! cargo run --bin codegen -- python ../../gen/python/npc_society/v1/handler.py
+//! cargo run --bin codegen -- typescript ../../gen/ts/npc_society/v1/handler.ts
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+
+use crate::compat::CORRELATION_KEYS;
+use crate::events::ClientEvent;
+
+const PACKAGE: &str = "npc_society.v1";
+
+/// Language of the bindings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Python,
+    TypeScript,
+}
+
+impl FromStr for Language {
+    type Err = CodegenError;
+
+    fn from_str(s: &str) -> Result<Self, CodegenError> {
+        match s {
+            "python" | "py" => Ok(Self::Python),
+            "typescript" | "ts" => Ok(Self::TypeScript),
+            _ => Err(CodegenError(format!("unknown language {:?}", s))),
+        }
+    }
+}
+
+/// The schema lacks what the bindings need
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodegenError(pub String);
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot generate bindings: {}", self.0)
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+/// One payload of an envelope's oneof
+struct Case {
+    /// Oneof field, e.g. `chat_observation`
+    field: String,
+    /// Payload message, e.g. `ChatObservation`
+    message: String,
+    /// First sentence of the field's comment, or else the message's
+    doc: String,
+    /// [`ClientEvent`] variant the handler method is named after
+    variant: Option<&'static str>,
+}
+
+struct Protocol {
+    client: Vec<Case>,
+    server: Vec<Case>,
+    /// Correlation keys by payload message
+    keys: BTreeMap<String, Vec<&'static str>>,
+}
+
+/// Bindings in `language` for the schema in `set` (normally
+/// [`crate::compat::current`])
+pub fn generate(language: Language, set: &FileDescriptorSet) -> Result<String, CodegenError> {
+    let protocol = protocol(set)?;
+    let mut out = String::new();
+    match language {
+        Language::Python => python(&protocol, &mut out),
+        Language::TypeScript => typescript(&protocol, &mut out),
+    }
+    .map_err(|e| CodegenError(e.to_string()))?;
+    Ok(out)
+}
+
+fn protocol(set: &FileDescriptorSet) -> Result<Protocol, CodegenError> {
+    let file = set
+        .file
+        .iter()
+        .find(|f| f.package() == PACKAGE)
+        .ok_or_else(|| CodegenError(format!("no file of package {}", PACKAGE)))?;
+    let comments: HashMap<&[i32], &str> = file
+        .source_code_info
+        .iter()
+        .flat_map(|info| &info.location)
+        .map(|l| (&l.path[..], l.leading_comments()))
+        .collect();
+    let doc = |path: &[i32]| first_sentence(comments.get(path).copied().unwrap_or(""));
+
+    let mut client = cases(file, "ClientMessage", &doc)?;
+    for case in &mut client {
+        let variant = ClientEvent::VARIANTS
+            .iter()
+            .find(|(_, field)| *field == upper_camel(&case.field))
+            .map(|(variant, _)| *variant);
+        if variant.is_none() {
+            return Err(CodegenError(format!(
+                "ClientMessage.{} has no ClientEvent variant",
+                case.field
+            )));
+        }
+        case.variant = variant;
+    }
+    let server = cases(file, "ServerMessage", &doc)?;
+
+    let keys = client
+        .iter()
+        .chain(&server)
+        .filter_map(|case| {
+            let (_, message) = find(file, &case.message)?;
+            let keys: Vec<_> = CORRELATION_KEYS
+                .into_iter()
+                .filter(|key| message.field.iter().any(|f| f.name() == *key))
+                .collect();
+            (!keys.is_empty()).then(|| (case.message.clone(), keys))
+        })
+        .collect();
+    Ok(Protocol {
+        client,
+        server,
+        keys,
+    })
+}
+
+/// The payloads of `envelope`'s `message` oneof, in field order
+fn cases(
+    file: &FileDescriptorProto,
+    envelope: &str,
+    doc: &dyn Fn(&[i32]) -> String,
+) -> Result<Vec<Case>, CodegenError> {
+    let (index, message) =
+        find(file, envelope).ok_or_else(|| CodegenError(format!("no message {}", envelope)))?;
+    let oneof = message
+        .oneof_decl
+        .iter()
+        .position(|o| o.name() == "message")
+        .ok_or_else(|| CodegenError(format!("{} has no message oneof", envelope)))?;
+    let prefix = format!(".{}.", PACKAGE);
+    let mut cases = Vec::new();
+    for (i, field) in message.field.iter().enumerate() {
+        if field.oneof_index != Some(oneof as i32) {
+            continue;
+        }
+        let payload = field.type_name().strip_prefix(&prefix).ok_or_else(|| {
+            CodegenError(format!("{}.{} is not a message", envelope, field.name()))
+        })?;
+        let mut text = doc(&[4, index as i32, 2, i as i32]);
+        if text.is_empty() {
+            text = find(file, payload)
+                .map(|(at, _)| doc(&[4, at as i32]))
+                .unwrap_or_default();
+        }
+        cases.push(Case {
+            field: field.name().to_string(),
+            message: payload.to_string(),
+            doc: text,
+            variant: None,
+        });
+    }
+    Ok(cases)
+}
+
+/// A top-level message and its index in the file
+fn find<'a>(file: &'a FileDescriptorProto, name: &str) -> Option<(usize, &'a DescriptorProto)> {
+    file.message_type
+        .iter()
+        .enumerate()
+        .find(|(_, m)| m.name() == name)
+}
+
+/// The first sentence of a comment on one line, without version notes or
+/// characters that would end a docstring
+fn first_sentence(comment: &str) -> String {
+    let text = comment.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut sentence = match text.find(". ") {
+        Some(end) => &text[..end],
+        None => text.trim_end_matches('.'),
+    };
+    if let Some(note) = sentence.rfind(" (v") {
+        if sentence.ends_with("+)") {
+            sentence = &sentence[..note];
+        }
+    }
+    sentence.replace('"', "'").replace("*/", "* /")
+}
+
+fn upper_camel(snake: &str) -> String {
+    snake
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+fn lower_camel(snake: &str) -> String {
+    let upper = upper_camel(snake);
+    let mut chars = upper.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => upper,
+    }
+}
+
+fn snake(camel: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in camel.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn python(p: &Protocol, out: &mut String) -> fmt::Result {
+    writeln!(
+        out,
+        "# Generated by `cargo run --bin codegen -- python` from {}; do not edit.",
+        PACKAGE
+    )?;
+    out.push_str(
+        r#""""NPC Society handler bindings, shaped like the Rust example's
+NpcSocietyHandler: subclass NpcSocietyHandler, override the on_* methods
+you need and pass every ClientMessage from the Connect stream to dispatch.
+Hello opens the session; answer it with a HelloAck before anything else."""
+
+from typing import Dict, Optional, Protocol, Tuple, Union
+
+from . import npc_society_pb2 as pb
+
+"#,
+    );
+    writeln!(out, "CORRELATION_KEYS = {:?}", CORRELATION_KEYS)?;
+    out.push_str(
+        r#"
+
+class Outbound(Protocol):
+    """Where replies go, e.g. a queue feeding the Connect response stream"""
+
+    def send(self, message: pb.ServerMessage) -> None: ...
+
+
+class NpcSocietyHandler:
+    """Daemon logic, one method per ClientMessage payload. Every method
+    defaults to doing nothing, so override only what you need."""
+"#,
+    );
+    for case in &p.client {
+        let method = snake(case.variant.unwrap_or_default());
+        writeln!(out)?;
+        writeln!(
+            out,
+            "    def on_{}(self, message: pb.{}, tx: Outbound) -> None:",
+            method, case.message
+        )?;
+        writeln!(out, "        \"\"\"{}\"\"\"", case.doc)?;
+    }
+
+    out.push_str("\n\n_HANDLERS: Dict[str, str] = {\n");
+    for case in &p.client {
+        let method = snake(case.variant.unwrap_or_default());
+        writeln!(out, "    \"{}\": \"on_{}\",", case.field, method)?;
+    }
+    out.push_str(
+        r#"}
+
+
+def dispatch(handler: NpcSocietyHandler, message: pb.ClientMessage, tx: Outbound) -> None:
+    """Call the handler method for message; an unset oneof is ignored"""
+    case = message.WhichOneof("message")
+    if case is not None:
+        getattr(handler, _HANDLERS[case])(getattr(message, case), tx)
+
+
+ServerPayload = Union[
+"#,
+    );
+    for case in &p.server {
+        writeln!(out, "    pb.{},", case.message)?;
+    }
+    out.push_str("]\n\n_SERVER_FIELDS: Dict[str, str] = {\n");
+    for case in &p.server {
+        writeln!(
+            out,
+            "    \"{}.{}\": \"{}\",",
+            PACKAGE, case.message, case.field
+        )?;
+    }
+    out.push_str(
+        r#"}
+
+
+def server_message(payload: ServerPayload) -> pb.ServerMessage:
+    """Wrap payload in its ServerMessage, like ServerMessage::from in Rust"""
+    return pb.ServerMessage(**{_SERVER_FIELDS[payload.DESCRIPTOR.full_name]: payload})
+
+
+_CORRELATION: Dict[str, Tuple[str, ...]] = {
+"#,
+    );
+    for (message, keys) in &p.keys {
+        let keys: String = keys.iter().map(|k| format!("\"{}\", ", k)).collect();
+        writeln!(
+            out,
+            "    \"{}.{}\": ({}),",
+            PACKAGE,
+            message,
+            keys.trim_end_matches(' ')
+        )?;
+    }
+    out.push_str(
+        r#"}
+
+
+def correlation(payload) -> Optional[Tuple[str, str]]:
+    """The first correlation key set on payload as (key, value), e.g. the
+    directive_id that ties an ActionResult to its ActionDirective"""
+    for key in _CORRELATION.get(payload.DESCRIPTOR.full_name, ()):
+        value = getattr(payload, key)
+        if value:
+            return key, value
+    return None
+"#,
+    );
+    Ok(())
+}
+
+fn typescript(p: &Protocol, out: &mut String) -> fmt::Result {
+    writeln!(
+        out,
+        "// Generated by `cargo run --bin codegen -- typescript` from {}; do not edit.",
+        PACKAGE
+    )?;
+    out.push_str(
+        r#"//
+// NPC Society handler bindings, shaped like the Rust example's
+// NpcSocietyHandler, for messages generated by protoc-gen-es v2: implement
+// the on* methods you need and pass every ClientMessage from the Connect
+// stream to dispatch. Hello opens the session; answer it with a HelloAck
+// before anything else.
+
+import { create } from "@bufbuild/protobuf";
+import { ServerMessageSchema } from "./npc_society_pb";
+import type {
+"#,
+    );
+    let types: BTreeSet<&str> = p
+        .client
+        .iter()
+        .chain(&p.server)
+        .map(|case| case.message.as_str())
+        .chain(["ClientMessage", "ServerMessage"])
+        .collect();
+    for name in types {
+        writeln!(out, "  {},", name)?;
+    }
+    out.push_str("} from \"./npc_society_pb\";\n\n");
+    let keys: Vec<String> = CORRELATION_KEYS
+        .iter()
+        .map(|k| format!("\"{}\"", k))
+        .collect();
+    writeln!(
+        out,
+        "export const CORRELATION_KEYS = [{}] as const;",
+        keys.join(", ")
+    )?;
+    out.push_str(
+        r#"
+/** Where replies go, e.g. a queue feeding the Connect response stream */
+export interface Outbound {
+  send(message: ServerMessage): void;
+}
+
+/** Daemon logic, one optional method per ClientMessage payload */
+export interface NpcSocietyHandler {
+"#,
+    );
+    for (i, case) in p.client.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "  /** {} */", case.doc)?;
+        writeln!(
+            out,
+            "  on{}?(message: {}, tx: Outbound): void;",
+            case.variant.unwrap_or_default(),
+            case.message
+        )?;
+    }
+    out.push_str(
+        r#"}
+
+/** Call the handler method for message; an unset oneof is ignored */
+export function dispatch(handler: NpcSocietyHandler, message: ClientMessage, tx: Outbound): void {
+  const payload = message.message;
+  switch (payload.case) {
+"#,
+    );
+    for case in &p.client {
+        writeln!(out, "    case \"{}\":", lower_camel(&case.field))?;
+        writeln!(
+            out,
+            "      handler.on{}?.(payload.value, tx);",
+            case.variant.unwrap_or_default()
+        )?;
+        writeln!(out, "      break;")?;
+    }
+    out.push_str("  }\n}\n\nexport type ServerPayload =\n");
+    for (i, case) in p.server.iter().enumerate() {
+        let end = if i + 1 == p.server.len() { ";" } else { "" };
+        writeln!(out, "  | {}{}", case.message, end)?;
+    }
+    out.push_str(
+        r#"
+/** Wrap payload in its ServerMessage, like ServerMessage::from in Rust */
+export function serverMessage(payload: ServerPayload): ServerMessage {
+  switch (payload.$typeName) {
+"#,
+    );
+    for case in &p.server {
+        writeln!(out, "    case \"{}.{}\":", PACKAGE, case.message)?;
+        writeln!(
+            out,
+            "      return create(ServerMessageSchema, {{ message: {{ case: \"{}\", value: payload }} }});",
+            lower_camel(&case.field)
+        )?;
+    }
+    out.push_str(
+        r#"  }
+}
+
+const CORRELATION: Record<string, readonly (readonly [string, string])[]> = {
+"#,
+    );
+    for (message, keys) in &p.keys {
+        let keys: Vec<String> = keys
+            .iter()
+            .map(|k| format!("[\"{}\", \"{}\"]", k, lower_camel(k)))
+            .collect();
+        writeln!(out, "  \"{}.{}\": [{}],", PACKAGE, message, keys.join(", "))?;
+    }
+    out.push_str(
+        r#"};
+
+/**
+ * The first correlation key set on payload as [key, value], e.g. the
+ * directive_id that ties an ActionResult to its ActionDirective
+ */
+export function correlation(payload: { $typeName: string }): [string, string] | undefined {
+  for (const [key, field] of CORRELATION[payload.$typeName] ?? []) {
+    const value = (payload as unknown as Record<string, unknown>)[field];
+    if (typeof value === "string" && value !== "") {
+      return [key, value];
+    }
+  }
+  return undefined;
+}
+"#,
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compat;
+
+    #[test]
+    fn test_python_follows_rust_handler() {
+        let py = generate(Language::Python, &compat::current()).unwrap();
+        // Method names come from the ClientEvent variants, not the fields
+        assert!(py.contains("    def on_chat(self, message: pb.ChatObservation, tx: Outbound)"));
+        assert!(py.contains("\"npc_transfer_update\": \"on_npc_transfer\","));
+        assert!(py.contains("\"npc_society.v1.TransferCurrencyDirective\": \"transfer_currency\","));
+        assert!(py.contains("\"npc_society.v1.ActionResult\": (\"directive_id\",),"));
+        // Comments lose their version notes
+        assert!(py.contains("\"\"\"Barge-in report\"\"\""));
+        let handlers = py.matches("    def on_").count();
+        assert_eq!(handlers, ClientEvent::VARIANTS.len());
+    }
+
+    #[test]
+    fn test_typescript_cases() {
+        let ts = generate(Language::TypeScript, &compat::current()).unwrap();
+        assert!(ts.contains(
+            "    case \"changeDimension\":\n      handler.onChangeDimension?.(payload.value, tx);"
+        ));
+        assert!(ts.contains("{ message: { case: \"setCombatPolicy\", value: payload } }"));
+        assert!(ts.contains("[\"directive_id\", \"directiveId\"]"));
+        assert!(ts.contains("  onHello?(message: Hello, tx: Outbound): void;"));
+    }
+
+    #[test]
+    fn test_missing_variant() {
+        let mut set = compat::current();
+        let file = set
+            .file
+            .iter_mut()
+            .find(|f| f.package() == PACKAGE)
+            .unwrap();
+        let client = file
+            .message_type
+            .iter_mut()
+            .find(|m| m.name() == "ClientMessage")
+            .unwrap();
+        client.field[0].name = Some("greeting".to_string());
+        let err = generate(Language::Python, &set).unwrap_err();
+        assert_eq!(err.0, "ClientMessage.greeting has no ClientEvent variant");
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(snake("NpcTransfer"), "npc_transfer");
+        assert_eq!(upper_camel("change_dimension"), "ChangeDimension");
+        assert_eq!(lower_camel("directive_id"), "directiveId");
+        assert_eq!(
+            first_sentence("Handshake reply (v1.2+)\n"),
+            "Handshake reply"
+        );
+        assert_eq!(
+            first_sentence(" ChatObservation is sent when a\n player chats. More.\n"),
+            "ChatObservation is sent when a player chats"
+        );
+    }
+}
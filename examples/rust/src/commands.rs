@@ -0,0 +1,829 @@
+//! Admin commands typed in chat.
+//!
+//! Every server owner wants to take over an NPC by hand now and then:
+//! `!npc goto 100 64 -200`, `!npc say Follow me`, `!npc freeze`. A
+//! [`CommandRouter`] recognizes ChatObservations that start with its
+//! prefix, checks that the player may run the [`ChatCommand`], runs it and
+//! answers the player with a ChatDirective. `!npc help` (or just `!npc`)
+//! lists the commands the player may run.
+//!
+//! A command acts on the NPC that observed the chat. Every NPC in range
+//! observes it, so `!npc freeze` freezes all NPCs near the player (with a
+//! FreezeNpcDirective, until `!npc unfreeze`); the same reply to the same
+//! chat is only sent once.
+//!
+//! `!npc quarantine` takes an NPC out of play pending review (a
+//! QuarantineNpcDirective) and `!npc release` puts it back; moderators may
+//! run those two with [`MODERATOR_PERMISSION`] alone.
+//!
+//! `!npc path on` draws the paths of the NPCs in range for the player who
+//! typed it, to see why an NPC walks the way it does; `!npc path off`
+//! hides them. The plugin has to offer Hello.debug_path_available.
+//!
+//! Operators may run every command. Other players need the command's
+//! permission node (default [`ADMIN_PERMISSION`]) in
+//! `PlayerSnapshot.permissions`, so configure the plugin to report it.
+//! Players missing from the latest WorldTick are treated as having no
+//! permissions.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::builders::Buildable;
+use crate::npc_society::v1::{
+    action_directive::Action, ActionDirective, ChatDirective, ChatObservation, DebugPathDirective,
+    DebugPathStyle, FreezeNpcDirective, MoveAction, NpcSnapshot, PlayerSnapshot, Position,
+    QuarantineNpcDirective, ResumeNpcDirective, ServerMessage, SpeakDirective,
+};
+use crate::players;
+
+/// Prefix of [`CommandRouter::default`]
+pub const PREFIX: &str = "!npc";
+/// Permission node the built-in commands require
+pub const ADMIN_PERMISSION: &str = "npcsociety.admin";
+/// Permission node of `quarantine` and `release`
+pub const MODERATOR_PERMISSION: &str = "npcsociety.moderate";
+/// Priority of directives sent by commands, above the NPCs' own work
+pub const PRIORITY: i32 = 10;
+/// Replies remembered to send each only once
+const RECENT_REPLIES: usize = 64;
+
+/// A command as typed, with what the daemon knows about who typed it
+#[derive(Debug, Clone, Copy)]
+pub struct CommandContext<'a> {
+    /// The chat message
+    pub chat: &'a ChatObservation,
+    /// The player, from the latest WorldTick
+    pub player: Option<&'a PlayerSnapshot>,
+    /// The NPC that observed the chat, from the latest WorldTick
+    pub npc: Option<&'a NpcSnapshot>,
+    /// Words after the command name
+    pub args: &'a [&'a str],
+}
+
+/// What a command did
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandOutput {
+    /// Messages to send, in order. ActionDirectives have no directive_id
+    /// yet; the daemon assigns it.
+    pub messages: Vec<ServerMessage>,
+    /// Answer to the player who ran the command
+    pub reply: Option<String>,
+}
+
+/// Why a command could not run, shown to the player with its usage
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandError(pub String);
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// A command registered on a [`CommandRouter`]
+pub trait ChatCommand: Send + Sync {
+    /// Word after the prefix, e.g. `goto`
+    fn name(&self) -> &str;
+
+    /// Arguments, for help, e.g. `<x> <y> <z>`
+    fn usage(&self) -> &str {
+        ""
+    }
+
+    /// One line for help
+    fn summary(&self) -> &str;
+
+    /// Permission node a non-operator needs (None = any player)
+    fn permission(&self) -> Option<&str> {
+        Some(ADMIN_PERMISSION)
+    }
+
+    /// Run the command
+    fn run(&self, ctx: &CommandContext<'_>) -> Result<CommandOutput, CommandError>;
+}
+
+fn directive(npc_id: &str, action: impl Into<Action>) -> ServerMessage {
+    ActionDirective {
+        npc_id: npc_id.to_string(),
+        priority: PRIORITY,
+        action: Some(action.into()),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// `goto <x> <y> <z>`: walk to a position in the NPC's world
+pub struct Goto;
+
+impl ChatCommand for Goto {
+    fn name(&self) -> &str {
+        "goto"
+    }
+
+    fn usage(&self) -> &str {
+        "<x> <y> <z>"
+    }
+
+    fn summary(&self) -> &str {
+        "walk to a position"
+    }
+
+    fn run(&self, ctx: &CommandContext<'_>) -> Result<CommandOutput, CommandError> {
+        let [x, y, z] = ctx.args else {
+            return Err(CommandError("expected three coordinates".to_string()));
+        };
+        let number = |word: &str| {
+            word.parse::<f64>()
+                .map_err(|_| CommandError(format!("not a number: {}", word)))
+        };
+        let here = ctx
+            .npc
+            .and_then(|npc| npc.position.clone())
+            .unwrap_or_default();
+        let target = Position {
+            x: number(x)?,
+            y: number(y)?,
+            z: number(z)?,
+            ..here
+        };
+        let action = MoveAction::builder()
+            .target(target)
+            .build()
+            .map_err(|e| CommandError(e.to_string()))?;
+        Ok(CommandOutput {
+            messages: vec![directive(&ctx.chat.npc_id, action)],
+            reply: Some(format!("{}: walking to {} {} {}", ctx.chat.npc_id, x, y, z)),
+        })
+    }
+}
+
+/// `say <text>`: say something, subtitle only
+pub struct Say;
+
+impl ChatCommand for Say {
+    fn name(&self) -> &str {
+        "say"
+    }
+
+    fn usage(&self) -> &str {
+        "<text>"
+    }
+
+    fn summary(&self) -> &str {
+        "say something"
+    }
+
+    fn run(&self, ctx: &CommandContext<'_>) -> Result<CommandOutput, CommandError> {
+        if ctx.args.is_empty() {
+            return Err(CommandError("nothing to say".to_string()));
+        }
+        let speak = SpeakDirective {
+            npc_id: ctx.chat.npc_id.clone(),
+            text: ctx.args.join(" "),
+            duration_ms: 3000,
+            volume: 1.0,
+            ..Default::default()
+        };
+        Ok(CommandOutput {
+            messages: vec![speak.into()],
+            reply: None,
+        })
+    }
+}
+
+/// `freeze [reason]`: halt the NPC plugin-side until `unfreeze`
+pub struct Freeze;
+
+impl ChatCommand for Freeze {
+    fn name(&self) -> &str {
+        "freeze"
+    }
+
+    fn usage(&self) -> &str {
+        "[reason]"
+    }
+
+    fn summary(&self) -> &str {
+        "stop and hold directives"
+    }
+
+    fn run(&self, ctx: &CommandContext<'_>) -> Result<CommandOutput, CommandError> {
+        let npc_id = &ctx.chat.npc_id;
+        if ctx.npc.is_some_and(|npc| npc.frozen) {
+            return Err(CommandError(format!("{} is already frozen", npc_id)));
+        }
+        let reason = match ctx.args {
+            [] => format!("frozen by {}", ctx.chat.player_name),
+            args => args.join(" "),
+        };
+        let freeze = FreezeNpcDirective {
+            npc_id: npc_id.clone(),
+            reason,
+        };
+        Ok(CommandOutput {
+            messages: vec![freeze.into()],
+            reply: Some(format!("{}: frozen", npc_id)),
+        })
+    }
+}
+
+/// `unfreeze [discard]`: resume, running the held directives unless
+/// `discard` is given
+pub struct Unfreeze;
+
+impl ChatCommand for Unfreeze {
+    fn name(&self) -> &str {
+        "unfreeze"
+    }
+
+    fn usage(&self) -> &str {
+        "[discard]"
+    }
+
+    fn summary(&self) -> &str {
+        "go back to work"
+    }
+
+    fn run(&self, ctx: &CommandContext<'_>) -> Result<CommandOutput, CommandError> {
+        let npc_id = &ctx.chat.npc_id;
+        if ctx.npc.is_some_and(|npc| !npc.frozen) {
+            return Err(CommandError(format!("{} is not frozen", npc_id)));
+        }
+        let discard_held = match ctx.args {
+            [] => false,
+            ["discard"] => true,
+            _ => return Err(CommandError("unexpected arguments".to_string())),
+        };
+        let resume = ResumeNpcDirective {
+            npc_id: npc_id.clone(),
+            discard_held,
+        };
+        Ok(CommandOutput {
+            messages: vec![resume.into()],
+            reply: Some(format!("{}: back to work", npc_id)),
+        })
+    }
+}
+
+/// `quarantine [reason]`: silence and freeze the NPC until `release`
+pub struct Quarantine;
+
+impl ChatCommand for Quarantine {
+    fn name(&self) -> &str {
+        "quarantine"
+    }
+
+    fn usage(&self) -> &str {
+        "[reason]"
+    }
+
+    fn summary(&self) -> &str {
+        "take out of play pending review"
+    }
+
+    fn permission(&self) -> Option<&str> {
+        Some(MODERATOR_PERMISSION)
+    }
+
+    fn run(&self, ctx: &CommandContext<'_>) -> Result<CommandOutput, CommandError> {
+        let npc_id = &ctx.chat.npc_id;
+        if ctx.npc.is_some_and(|npc| npc.quarantined) {
+            return Err(CommandError(format!("{} is already quarantined", npc_id)));
+        }
+        let reason = match ctx.args {
+            [] => format!("quarantined by {}", ctx.chat.player_name),
+            args => args.join(" "),
+        };
+        let quarantine = QuarantineNpcDirective {
+            npc_id: npc_id.clone(),
+            quarantined: true,
+            reason,
+            flag_ids: Vec::new(),
+        };
+        Ok(CommandOutput {
+            messages: vec![quarantine.into()],
+            reply: Some(format!("{}: quarantined", npc_id)),
+        })
+    }
+}
+
+/// `release`: end a quarantine after review
+pub struct Release;
+
+impl ChatCommand for Release {
+    fn name(&self) -> &str {
+        "release"
+    }
+
+    fn summary(&self) -> &str {
+        "end a quarantine"
+    }
+
+    fn permission(&self) -> Option<&str> {
+        Some(MODERATOR_PERMISSION)
+    }
+
+    fn run(&self, ctx: &CommandContext<'_>) -> Result<CommandOutput, CommandError> {
+        let npc_id = &ctx.chat.npc_id;
+        if ctx.npc.is_some_and(|npc| !npc.quarantined) {
+            return Err(CommandError(format!("{} is not quarantined", npc_id)));
+        }
+        if !ctx.args.is_empty() {
+            return Err(CommandError("unexpected arguments".to_string()));
+        }
+        let release = QuarantineNpcDirective {
+            npc_id: npc_id.clone(),
+            quarantined: false,
+            reason: format!("released by {}", ctx.chat.player_name),
+            flag_ids: Vec::new(),
+        };
+        Ok(CommandOutput {
+            messages: vec![release.into()],
+            reply: Some(format!("{}: released", npc_id)),
+        })
+    }
+}
+
+/// `path <on|off|blocks> [npc]`: show the NPC's path to the player who
+/// ran it (a DebugPathDirective), drawn with particles or, with `blocks`,
+/// fake blocks. Naming an NPC leaves the other NPCs in range alone.
+pub struct DebugPath;
+
+impl ChatCommand for DebugPath {
+    fn name(&self) -> &str {
+        "path"
+    }
+
+    fn usage(&self) -> &str {
+        "<on|off|blocks> [npc]"
+    }
+
+    fn summary(&self) -> &str {
+        "show where the NPC is walking"
+    }
+
+    fn run(&self, ctx: &CommandContext<'_>) -> Result<CommandOutput, CommandError> {
+        let npc_id = &ctx.chat.npc_id;
+        let (mode, only) = match ctx.args {
+            [mode] => (*mode, None),
+            [mode, only] => (*mode, Some(*only)),
+            _ => return Err(CommandError("expected on, off or blocks".to_string())),
+        };
+        let (enabled, style) = match mode {
+            "on" => (true, DebugPathStyle::Particles),
+            "blocks" => (true, DebugPathStyle::Blocks),
+            "off" => (false, DebugPathStyle::Unspecified),
+            _ => return Err(CommandError(format!("not on, off or blocks: {}", mode))),
+        };
+        if only.is_some_and(|only| only != npc_id) {
+            return Ok(CommandOutput::default());
+        }
+        let debug_path = DebugPathDirective {
+            npc_id: npc_id.clone(),
+            viewer_uuid: ctx.chat.player_uuid.clone(),
+            enabled,
+            style: style as i32,
+        };
+        let reply = match enabled {
+            true => format!("{}: showing path", npc_id),
+            false => format!("{}: path hidden", npc_id),
+        };
+        Ok(CommandOutput {
+            messages: vec![debug_path.into()],
+            reply: Some(reply),
+        })
+    }
+}
+
+/// Recognizes chat commands and runs them
+pub struct CommandRouter {
+    prefix: String,
+    commands: BTreeMap<String, Box<dyn ChatCommand>>,
+    /// (player_uuid, timestamp_ms, message, reply) of recent replies
+    replied: Mutex<VecDeque<(String, i64, String, String)>>,
+}
+
+impl Default for CommandRouter {
+    fn default() -> Self {
+        Self::new(PREFIX)
+    }
+}
+
+impl fmt::Debug for CommandRouter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandRouter")
+            .field("prefix", &self.prefix)
+            .field("commands", &self.commands.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl CommandRouter {
+    /// A router for chat starting with `prefix`, without commands
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            commands: BTreeMap::new(),
+            replied: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// The default router with `goto`, `say`, `freeze`, `unfreeze`,
+    /// `quarantine`, `release` and `path`
+    pub fn admin() -> Self {
+        Self::default()
+            .command(Goto)
+            .command(Say)
+            .command(Freeze)
+            .command(Unfreeze)
+            .command(Quarantine)
+            .command(Release)
+            .command(DebugPath)
+    }
+
+    /// Register a command, replacing one with the same name
+    pub fn command(mut self, command: impl ChatCommand + 'static) -> Self {
+        self.commands
+            .insert(command.name().to_string(), Box::new(command));
+        self
+    }
+
+    /// Words of the command if `chat` is one
+    fn words<'a>(&self, chat: &'a ChatObservation) -> Option<Vec<&'a str>> {
+        let mut words = chat.message.split_whitespace();
+        (words.next()? == self.prefix).then(|| words.collect())
+    }
+
+    /// Whether `chat` is a command (and not for the NPC to answer)
+    pub fn is_command(&self, chat: &ChatObservation) -> bool {
+        self.words(chat).is_some()
+    }
+
+    fn allowed(command: &dyn ChatCommand, player: Option<&PlayerSnapshot>) -> bool {
+        match (command.permission(), player) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(node), Some(player)) => player.op || players::has_permission(player, node),
+        }
+    }
+
+    fn help(&self, player: Option<&PlayerSnapshot>) -> String {
+        let lines: Vec<String> = self
+            .commands
+            .values()
+            .filter(|c| Self::allowed(c.as_ref(), player))
+            .map(|c| {
+                let usage = [self.prefix.as_str(), c.name(), c.usage()].join(" ");
+                format!("{}: {}", usage.trim_end(), c.summary())
+            })
+            .collect();
+        if lines.is_empty() {
+            return format!("You may not run any {} commands", self.prefix);
+        }
+        lines.join("\n")
+    }
+
+    /// Run `chat` if it is a command: the messages to send, replies to the
+    /// player included. None if it is not a command.
+    pub fn dispatch(
+        &self,
+        chat: &ChatObservation,
+        player: Option<&PlayerSnapshot>,
+        npc: Option<&NpcSnapshot>,
+    ) -> Option<Vec<ServerMessage>> {
+        let words = self.words(chat)?;
+        let (mut messages, reply) = match words.split_first() {
+            None | Some((&"help", _)) => (Vec::new(), self.help(player)),
+            Some((name, args)) => match self.commands.get(*name) {
+                None => (
+                    Vec::new(),
+                    format!("Unknown command {}; {} help lists them", name, self.prefix),
+                ),
+                Some(command) if !Self::allowed(command.as_ref(), player) => (
+                    Vec::new(),
+                    format!("You may not run {} {}", self.prefix, name),
+                ),
+                Some(command) => {
+                    let ctx = CommandContext {
+                        chat,
+                        player,
+                        npc,
+                        args,
+                    };
+                    match command.run(&ctx) {
+                        Ok(output) => (output.messages, output.reply.unwrap_or_default()),
+                        Err(e) => {
+                            let usage = [self.prefix.as_str(), name, command.usage()].join(" ");
+                            (Vec::new(), format!("{} (usage: {})", e, usage.trim_end()))
+                        }
+                    }
+                }
+            },
+        };
+        if !reply.is_empty() && self.first_reply(chat, &reply) {
+            let reply = ChatDirective {
+                player_uuids: vec![chat.player_uuid.clone()],
+                text: reply,
+                npc_id: String::new(),
+                // Commands answer in English only
+                locale: "en_us".to_string(),
+            };
+            messages.push(reply.into());
+        }
+        Some(messages)
+    }
+
+    /// Whether `reply` to `chat` was not sent yet, for chat observed by
+    /// several NPCs
+    fn first_reply(&self, chat: &ChatObservation, reply: &str) -> bool {
+        let key = (
+            chat.player_uuid.clone(),
+            chat.timestamp_ms,
+            chat.message.clone(),
+            reply.to_string(),
+        );
+        let mut replied = self.replied.lock().unwrap();
+        if replied.contains(&key) {
+            return false;
+        }
+        if replied.len() == RECENT_REPLIES {
+            replied.pop_front();
+        }
+        replied.push_back(key);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::server_message::Message as ServerMsg;
+
+    fn chat(npc_id: &str, message: &str) -> ChatObservation {
+        ChatObservation {
+            npc_id: npc_id.to_string(),
+            player_uuid: "p-1".to_string(),
+            player_name: "Steve".to_string(),
+            message: message.to_string(),
+            timestamp_ms: 1_000,
+            ..Default::default()
+        }
+    }
+
+    fn player(op: bool, permissions: &[&str]) -> PlayerSnapshot {
+        PlayerSnapshot {
+            player_uuid: "p-1".to_string(),
+            op,
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn reply(messages: &[ServerMessage]) -> Option<&str> {
+        messages.iter().find_map(|m| match &m.message {
+            Some(ServerMsg::Chat(chat)) => Some(chat.text.as_str()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_goto_and_permissions() {
+        let router = CommandRouter::admin();
+        let npc = NpcSnapshot {
+            npc_id: "miner_1".to_string(),
+            position: Some(Position {
+                world: "world_nether".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let goto = chat("miner_1", "!npc goto 100 64 -200");
+
+        assert!(router
+            .dispatch(&chat("miner_1", "hello !npc"), None, None)
+            .is_none());
+        assert!(!router.is_command(&chat("miner_1", "!npcs are cool")));
+
+        // Neither an operator nor granted the node
+        let denied = router
+            .dispatch(&goto, Some(&player(false, &[])), Some(&npc))
+            .unwrap();
+        assert_eq!(denied.len(), 1);
+        assert_eq!(reply(&denied), Some("You may not run !npc goto"));
+
+        let staff = player(false, &[ADMIN_PERMISSION]);
+        let sent = router.dispatch(&goto, Some(&staff), Some(&npc)).unwrap();
+        let Some(ServerMsg::ActionDirective(directive)) = &sent[0].message else {
+            panic!("expected an ActionDirective, got {:?}", sent[0]);
+        };
+        assert_eq!(directive.npc_id, "miner_1");
+        assert_eq!(directive.priority, PRIORITY);
+        let Some(Action::Move(action)) = &directive.action else {
+            panic!("expected a MoveAction");
+        };
+        let target = action.target.as_ref().unwrap();
+        assert_eq!((target.x, target.y, target.z), (100.0, 64.0, -200.0));
+        assert_eq!(target.world, "world_nether");
+        assert_eq!(reply(&sent), Some("miner_1: walking to 100 64 -200"));
+
+        let bad = chat("miner_1", "!npc goto 100 up");
+        let sent = router
+            .dispatch(&bad, Some(&player(true, &[])), None)
+            .unwrap();
+        assert_eq!(
+            reply(&sent),
+            Some("expected three coordinates (usage: !npc goto <x> <y> <z>)")
+        );
+    }
+
+    #[test]
+    fn test_freeze_and_help() {
+        let router = CommandRouter::admin();
+        let op = player(true, &[]);
+        let mut npc = NpcSnapshot {
+            npc_id: "miner_1".to_string(),
+            ..Default::default()
+        };
+
+        let sent = router
+            .dispatch(&chat("miner_1", "!npc freeze"), Some(&op), Some(&npc))
+            .unwrap();
+        let Some(ServerMsg::FreezeNpc(freeze)) = &sent[0].message else {
+            panic!("expected a FreezeNpcDirective, got {:?}", sent[0]);
+        };
+        assert_eq!(freeze.npc_id, "miner_1");
+        assert_eq!(freeze.reason, "frozen by Steve");
+
+        // The plugin reports the freeze in the next WorldTick
+        npc.frozen = true;
+        let sent = router
+            .dispatch(&chat("miner_1", "!npc freeze again"), Some(&op), Some(&npc))
+            .unwrap();
+        assert_eq!(
+            reply(&sent),
+            Some("miner_1 is already frozen (usage: !npc freeze [reason])")
+        );
+        let sent = router
+            .dispatch(
+                &chat("miner_1", "!npc unfreeze discard"),
+                Some(&op),
+                Some(&npc),
+            )
+            .unwrap();
+        assert!(matches!(
+            &sent[0].message,
+            Some(ServerMsg::ResumeNpc(ResumeNpcDirective {
+                discard_held: true,
+                ..
+            }))
+        ));
+
+        // Help lists what the player may run, once per chat however many
+        // NPCs observed it
+        let help = router
+            .dispatch(&chat("miner_1", "!npc"), Some(&op), None)
+            .unwrap();
+        let text = reply(&help).unwrap();
+        assert!(text.contains("!npc goto <x> <y> <z>: walk to a position"));
+        assert!(text.contains("!npc freeze [reason]: stop and hold directives"));
+        let again = router
+            .dispatch(&chat("miner_2", "!npc"), Some(&op), None)
+            .unwrap();
+        assert!(again.is_empty());
+
+        let stranger = router
+            .dispatch(&chat("miner_1", "!npc help"), None, None)
+            .unwrap();
+        assert_eq!(reply(&stranger), Some("You may not run any !npc commands"));
+    }
+
+    #[test]
+    fn test_quarantine_by_moderator() {
+        let router = CommandRouter::admin();
+        let moderator = player(false, &[MODERATOR_PERMISSION]);
+        let mut npc = NpcSnapshot {
+            npc_id: "miner_1".to_string(),
+            ..Default::default()
+        };
+
+        // Moderators see only their two commands
+        let help = router
+            .dispatch(&chat("miner_1", "!npc"), Some(&moderator), None)
+            .unwrap();
+        let text = reply(&help).unwrap();
+        assert!(text.contains("!npc quarantine [reason]: take out of play pending review"));
+        assert!(text.contains("!npc release: end a quarantine"));
+        assert!(!text.contains("freeze"));
+
+        let sent = router
+            .dispatch(
+                &chat("miner_1", "!npc quarantine rude to new players"),
+                Some(&moderator),
+                Some(&npc),
+            )
+            .unwrap();
+        let Some(ServerMsg::QuarantineNpc(quarantine)) = &sent[0].message else {
+            panic!("expected a QuarantineNpcDirective, got {:?}", sent[0]);
+        };
+        assert!(quarantine.quarantined);
+        assert_eq!(quarantine.reason, "rude to new players");
+
+        npc.quarantined = true;
+        let sent = router
+            .dispatch(
+                &chat("miner_1", "!npc release"),
+                Some(&moderator),
+                Some(&npc),
+            )
+            .unwrap();
+        let Some(ServerMsg::QuarantineNpc(release)) = &sent[0].message else {
+            panic!("expected a QuarantineNpcDirective, got {:?}", sent[0]);
+        };
+        assert!(!release.quarantined);
+        assert_eq!(release.reason, "released by Steve");
+    }
+
+    #[test]
+    fn test_debug_path() {
+        let router = CommandRouter::admin();
+        let op = player(true, &[]);
+
+        let sent = router
+            .dispatch(&chat("miner_1", "!npc path blocks"), Some(&op), None)
+            .unwrap();
+        let Some(ServerMsg::DebugPath(debug_path)) = &sent[0].message else {
+            panic!("expected a DebugPathDirective, got {:?}", sent[0]);
+        };
+        assert_eq!(debug_path.viewer_uuid, "p-1");
+        assert!(debug_path.enabled);
+        assert_eq!(debug_path.style(), DebugPathStyle::Blocks);
+        assert_eq!(reply(&sent), Some("miner_1: showing path"));
+
+        // Naming an NPC leaves the others that observed the chat alone
+        let sent = router
+            .dispatch(&chat("miner_1", "!npc path off miner_2"), Some(&op), None)
+            .unwrap();
+        assert!(sent.is_empty());
+        let sent = router
+            .dispatch(&chat("miner_2", "!npc path off miner_2"), Some(&op), None)
+            .unwrap();
+        let Some(ServerMsg::DebugPath(debug_path)) = &sent[0].message else {
+            panic!("expected a DebugPathDirective, got {:?}", sent[0]);
+        };
+        assert_eq!(debug_path.npc_id, "miner_2");
+        assert!(!debug_path.enabled);
+
+        let sent = router
+            .dispatch(&chat("miner_1", "!npc path sideways"), Some(&op), None)
+            .unwrap();
+        assert_eq!(
+            reply(&sent),
+            Some("not on, off or blocks: sideways (usage: !npc path <on|off|blocks> [npc])")
+        );
+    }
+
+    #[test]
+    fn test_custom_command() {
+        struct Ping;
+        impl ChatCommand for Ping {
+            fn name(&self) -> &str {
+                "ping"
+            }
+            fn summary(&self) -> &str {
+                "answer pong"
+            }
+            fn permission(&self) -> Option<&str> {
+                None
+            }
+            fn run(&self, _ctx: &CommandContext<'_>) -> Result<CommandOutput, CommandError> {
+                Ok(CommandOutput {
+                    messages: Vec::new(),
+                    reply: Some("pong".to_string()),
+                })
+            }
+        }
+
+        let router = CommandRouter::new("!bot").command(Ping);
+        let sent = router
+            .dispatch(&chat("miner_1", "!bot ping"), None, None)
+            .unwrap();
+        assert_eq!(reply(&sent), Some("pong"));
+        let ChatDirective {
+            player_uuids,
+            npc_id,
+            ..
+        } = match &sent[0].message {
+            Some(ServerMsg::Chat(chat)) => chat.clone(),
+            other => panic!("expected a ChatDirective, got {:?}", other),
+        };
+        assert_eq!(player_uuids, ["p-1"]);
+        assert!(npc_id.is_empty());
+        assert!(router
+            .dispatch(&chat("miner_1", "!npc ping"), None, None)
+            .is_none());
+    }
+}
@@ -0,0 +1,796 @@
+//! Schema compatibility between two versions of the protocol.
+//!
+//! v1 only grows: plugins and daemons of different releases talk to each
+//! other, so nothing may be removed, renumbered, retyped or moved in or
+//! out of a oneof, and reserved numbers stay reserved. [`check`] compares
+//! two descriptor sets by those rules, plus what generic breaking-change
+//! checkers do not know about this protocol:
+//!
+//! - correlation keys (`directive_id`, `stream_id`, `transfer_id`,
+//!   `quest_id`) tie messages together across the stream, so every field
+//!   by those names must stay a singular string
+//! - additions carry the version that introduced them, e.g. `(v1.2+)`, in
+//!   their comment (checked when the new set includes source info)
+//!
+//! Descriptor sets come from `buf build -o schema.binpb` or
+//! `protoc --include_imports --include_source_info --descriptor_set_out`;
+//! [`current`] is the schema this crate was built from.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use prost::Message;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::source_code_info::Location;
+use prost_types::{
+    DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+    FileDescriptorSet, ServiceDescriptorProto,
+};
+
+/// Fields that correlate messages with each other, e.g. a directive and
+/// its result
+pub const CORRELATION_KEYS: [&str; 4] = ["directive_id", "stream_id", "transfer_id", "quest_id"];
+
+/// The schema this crate was built from
+pub fn current() -> FileDescriptorSet {
+    FileDescriptorSet::decode(npc_society_proto::FILE_DESCRIPTOR_SET)
+        .expect("npc-society-proto embeds a valid descriptor set")
+}
+
+/// A descriptor set read from `buf build` or `protoc` output
+pub fn load(bytes: &[u8]) -> Result<FileDescriptorSet, prost::DecodeError> {
+    FileDescriptorSet::decode(bytes)
+}
+
+/// How bad a change is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Peers on either version will misread each other
+    Breaking,
+    /// Compatible on the wire, but against the protocol's conventions or
+    /// disruptive for generated code
+    Warning,
+}
+
+/// One change that matters
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Full name of the element, e.g. `npc_society.v1.ClientMessage.hello`
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Breaking => "BREAKING",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{} {}: {}", severity, self.path, self.message)
+    }
+}
+
+/// Every definition of a descriptor set by full name (no leading dot)
+#[derive(Default)]
+struct Schema<'a> {
+    messages: BTreeMap<String, &'a DescriptorProto>,
+    enums: BTreeMap<String, &'a EnumDescriptorProto>,
+    services: BTreeMap<String, &'a ServiceDescriptorProto>,
+    /// Elements covered by a version note
+    annotated: HashSet<String>,
+    has_source_info: bool,
+}
+
+// Field numbers in descriptor.proto, for source info paths
+const FILE_MESSAGE: i32 = 4;
+const FILE_ENUM: i32 = 5;
+const FILE_SERVICE: i32 = 6;
+const MESSAGE_FIELD: i32 = 2;
+const MESSAGE_NESTED: i32 = 3;
+const MESSAGE_ENUM: i32 = 4;
+const ENUM_VALUE: i32 = 2;
+const SERVICE_METHOD: i32 = 2;
+
+fn join(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+fn extend(path: &[i32], more: &[i32]) -> Vec<i32> {
+    let mut path = path.to_vec();
+    path.extend_from_slice(more);
+    path
+}
+
+/// Which of a list of siblings (fields, values, methods, ...) a version
+/// note covers. The proto marks additions three ways: on the element,
+/// on the first of a group of uncommented elements that follow it, or in
+/// a section comment set apart by a blank line.
+fn covered(
+    locations: &HashMap<Vec<i32>, &Location>,
+    siblings: impl IntoIterator<Item = Vec<i32>>,
+) -> Vec<bool> {
+    let (mut section, mut previous) = (false, false);
+    siblings
+        .into_iter()
+        .map(|path| {
+            let Some(location) = locations.get(&path) else {
+                return section || previous;
+            };
+            if !location.leading_detached_comments.is_empty() {
+                section = location
+                    .leading_detached_comments
+                    .iter()
+                    .any(|c| has_version_note(c));
+            }
+            let own = has_version_note(location.leading_comments())
+                || has_version_note(location.trailing_comments());
+            let uncommented = location.leading_comments().is_empty();
+            previous = own || section || (uncommented && previous);
+            previous
+        })
+        .collect()
+}
+
+impl<'a> Schema<'a> {
+    fn new(set: &'a FileDescriptorSet) -> Self {
+        let mut schema = Schema::default();
+        for file in &set.file {
+            schema.add_file(file);
+        }
+        schema
+    }
+
+    fn add_file(&mut self, file: &'a FileDescriptorProto) {
+        let locations: HashMap<Vec<i32>, &Location> = file
+            .source_code_info
+            .iter()
+            .flat_map(|s| &s.location)
+            .map(|l| (l.path.clone(), l))
+            .collect();
+        self.has_source_info |= !locations.is_empty();
+        let mut annotate = |names: Vec<String>, paths: Vec<Vec<i32>>| {
+            for (name, covered) in names.into_iter().zip(covered(&locations, paths)) {
+                if covered {
+                    self.annotated.insert(name);
+                }
+            }
+        };
+
+        let package = file.package();
+        let mut messages = Vec::new();
+        let mut enums = Vec::new();
+        for (i, message) in file.message_type.iter().enumerate() {
+            messages.push((package.to_string(), message, vec![FILE_MESSAGE, i as i32]));
+        }
+        for (i, e) in file.enum_type.iter().enumerate() {
+            enums.push((package.to_string(), e, vec![FILE_ENUM, i as i32]));
+        }
+        let top_level: Vec<_> = messages.iter().map(|(_, _, path)| path.clone()).collect();
+        annotate(
+            file.message_type
+                .iter()
+                .map(|m| join(package, m.name()))
+                .collect(),
+            top_level,
+        );
+        annotate(
+            file.enum_type
+                .iter()
+                .map(|e| join(package, e.name()))
+                .collect(),
+            enums.iter().map(|(_, _, path)| path.clone()).collect(),
+        );
+
+        let mut all_messages = Vec::new();
+        while let Some((scope, message, path)) = messages.pop() {
+            let name = join(&scope, message.name());
+            annotate(
+                message
+                    .field
+                    .iter()
+                    .map(|f| join(&name, f.name()))
+                    .collect(),
+                (0..message.field.len())
+                    .map(|i| extend(&path, &[MESSAGE_FIELD, i as i32]))
+                    .collect(),
+            );
+            annotate(
+                message
+                    .nested_type
+                    .iter()
+                    .map(|m| join(&name, m.name()))
+                    .collect(),
+                (0..message.nested_type.len())
+                    .map(|i| extend(&path, &[MESSAGE_NESTED, i as i32]))
+                    .collect(),
+            );
+            annotate(
+                message
+                    .enum_type
+                    .iter()
+                    .map(|e| join(&name, e.name()))
+                    .collect(),
+                (0..message.enum_type.len())
+                    .map(|i| extend(&path, &[MESSAGE_ENUM, i as i32]))
+                    .collect(),
+            );
+            for (i, nested) in message.nested_type.iter().enumerate() {
+                messages.push((
+                    name.clone(),
+                    nested,
+                    extend(&path, &[MESSAGE_NESTED, i as i32]),
+                ));
+            }
+            for (i, e) in message.enum_type.iter().enumerate() {
+                enums.push((name.clone(), e, extend(&path, &[MESSAGE_ENUM, i as i32])));
+            }
+            all_messages.push((name, message));
+        }
+        for (scope, e, path) in enums {
+            let name = join(&scope, e.name());
+            annotate(
+                e.value.iter().map(|v| join(&name, v.name())).collect(),
+                (0..e.value.len())
+                    .map(|i| extend(&path, &[ENUM_VALUE, i as i32]))
+                    .collect(),
+            );
+            self.enums.insert(name, e);
+        }
+        for (i, service) in file.service.iter().enumerate() {
+            let name = join(package, service.name());
+            let path = [FILE_SERVICE, i as i32];
+            annotate(
+                service
+                    .method
+                    .iter()
+                    .map(|m| join(&name, m.name()))
+                    .collect(),
+                (0..service.method.len())
+                    .map(|j| extend(&path, &[SERVICE_METHOD, j as i32]))
+                    .collect(),
+            );
+            self.services.insert(name, service);
+        }
+        self.messages.extend(all_messages);
+    }
+
+    /// Whether a version note covers the element
+    fn annotated(&self, name: &str) -> bool {
+        self.annotated.contains(name)
+    }
+}
+
+/// Finds `v1.2+`, as in `(v1.2+)` or `(barge-in, v1.2+)`
+fn has_version_note(comment: &str) -> bool {
+    comment.match_indices('v').any(|(i, _)| {
+        let word_start = comment[..i]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_alphanumeric());
+        let rest = &comment[i + 1..];
+        let version = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        word_start
+            && rest.starts_with(|c: char| c.is_ascii_digit())
+            && rest[version..].starts_with('+')
+    })
+}
+
+/// Scalar types that decode each other's values (possibly truncated)
+fn wire_group(t: Type) -> Option<u8> {
+    match t {
+        Type::Int32 | Type::Uint32 | Type::Int64 | Type::Uint64 | Type::Bool | Type::Enum => {
+            Some(0)
+        }
+        Type::Sint32 | Type::Sint64 => Some(1),
+        Type::Fixed32 | Type::Sfixed32 => Some(2),
+        Type::Fixed64 | Type::Sfixed64 => Some(3),
+        Type::String | Type::Bytes => Some(4),
+        _ => None,
+    }
+}
+
+/// `TYPE_STRING` -> `string`, message and enum fields by type name
+fn describe_type(field: &FieldDescriptorProto) -> String {
+    let name = match field.r#type() {
+        Type::Message | Type::Enum | Type::Group => {
+            field.type_name().trim_start_matches('.').to_string()
+        }
+        t => t.as_str_name().trim_start_matches("TYPE_").to_lowercase(),
+    };
+    if field.label() == Label::Repeated {
+        format!("repeated {}", name)
+    } else {
+        name
+    }
+}
+
+fn oneof_name(message: &DescriptorProto, field: &FieldDescriptorProto) -> Option<String> {
+    // proto3 `optional` is a synthetic oneof: not a real one to move out of
+    if field.proto3_optional() {
+        return None;
+    }
+    let index = field.oneof_index?;
+    message
+        .oneof_decl
+        .get(index as usize)
+        .map(|o| o.name().to_string())
+}
+
+/// Synthetic `map<K, V>` entry, declared by its map field
+fn is_map_entry(message: &DescriptorProto) -> bool {
+    message.options.as_ref().is_some_and(|o| o.map_entry())
+}
+
+fn is_reserved(message: &DescriptorProto, field: &FieldDescriptorProto) -> bool {
+    message
+        .reserved_range
+        .iter()
+        .any(|r| (r.start()..r.end()).contains(&field.number()))
+        || message.reserved_name.iter().any(|n| n == field.name())
+}
+
+struct Checker<'a> {
+    old: Schema<'a>,
+    new: Schema<'a>,
+    findings: Vec<Finding>,
+}
+
+impl Checker<'_> {
+    fn report(&mut self, severity: Severity, path: &str, message: impl Into<String>) {
+        self.findings.push(Finding {
+            severity,
+            path: path.to_string(),
+            message: message.into(),
+        });
+    }
+
+    /// New elements must say which version added them
+    fn check_annotated(&mut self, path: &str, what: &str) {
+        if self.new.has_source_info && !self.new.annotated(path) {
+            self.report(
+                Severity::Warning,
+                path,
+                format!("new {} has no version note such as (v1.2+)", what),
+            );
+        }
+    }
+
+    /// Messages and enums covered by a version note: their own, or that
+    /// of a field or rpc that uses them, or of a message whose fields use
+    /// them (a new message's field types need none of their own)
+    fn covered_types(&self) -> HashSet<String> {
+        let mut covered: HashSet<String> = self.new.annotated.clone();
+        loop {
+            let before = covered.len();
+            let mut uses = Vec::new();
+            for (name, message) in &self.new.messages {
+                let whole = covered.contains(name) && !self.old.messages.contains_key(name);
+                for field in &message.field {
+                    if whole || self.new.annotated(&join(name, field.name())) {
+                        uses.push(field.type_name());
+                    }
+                }
+            }
+            for (name, service) in &self.new.services {
+                for method in &service.method {
+                    if self.new.annotated(&join(name, method.name())) {
+                        uses.extend([method.input_type(), method.output_type()]);
+                    }
+                }
+            }
+            covered.extend(
+                uses.into_iter()
+                    .filter(|t| !t.is_empty())
+                    .map(|t| t.trim_start_matches('.').to_string()),
+            );
+            if covered.len() == before {
+                return covered;
+            }
+        }
+    }
+
+    /// Whether a new field's type is new too and carries the version note
+    fn introduces_annotated_type(&self, field: &FieldDescriptorProto) -> bool {
+        let type_name = field.type_name().trim_start_matches('.');
+        !type_name.is_empty()
+            && !self.old.messages.contains_key(type_name)
+            && !self.old.enums.contains_key(type_name)
+            && self.new.annotated(type_name)
+    }
+
+    fn check_messages(&mut self) {
+        let old: Vec<_> = self
+            .old
+            .messages
+            .iter()
+            .map(|(n, m)| (n.clone(), *m))
+            .collect();
+        for (name, old) in old {
+            match self.new.messages.get(&name).copied() {
+                Some(new) => self.check_fields(&name, old, new),
+                None => self.report(Severity::Breaking, &name, "message removed"),
+            }
+        }
+        let covered = self.covered_types();
+        let added: Vec<_> = self
+            .new
+            .messages
+            .iter()
+            .filter(|(n, m)| {
+                !self.old.messages.contains_key(*n) && !is_map_entry(m) && !covered.contains(*n)
+            })
+            .map(|(n, _)| n.clone())
+            .collect();
+        for name in added {
+            self.check_annotated(&name, "message");
+        }
+    }
+
+    fn check_fields(&mut self, name: &str, old: &DescriptorProto, new: &DescriptorProto) {
+        for field in &old.field {
+            let path = join(name, field.name());
+            let by_number = new.field.iter().find(|f| f.number() == field.number());
+            let by_name = new.field.iter().find(|f| f.name() == field.name());
+            let Some(moved) = by_number else {
+                match by_name {
+                    Some(f) => self.report(
+                        Severity::Breaking,
+                        &path,
+                        format!("number changed from {} to {}", field.number(), f.number()),
+                    ),
+                    None if is_reserved(new, field) => self.report(
+                        Severity::Breaking,
+                        &path,
+                        format!("field {} removed (now reserved)", field.number()),
+                    ),
+                    None => self.report(
+                        Severity::Breaking,
+                        &path,
+                        format!("field {} removed", field.number()),
+                    ),
+                }
+                continue;
+            };
+            if moved.name() != field.name() {
+                // Same wire format, but JSON and every generated accessor change
+                self.report(
+                    Severity::Breaking,
+                    &path,
+                    format!("field {} renamed to {}", field.number(), moved.name()),
+                );
+            }
+            self.check_field_type(&path, field, moved);
+            let (was, is) = (oneof_name(old, field), oneof_name(new, moved));
+            if was != is {
+                let describe = |o: &Option<String>| match o {
+                    Some(o) => format!("oneof {}", o),
+                    None => "no oneof".to_string(),
+                };
+                self.report(
+                    Severity::Breaking,
+                    &path,
+                    format!("moved from {} to {}", describe(&was), describe(&is)),
+                );
+            }
+        }
+
+        for field in &new.field {
+            if old.field.iter().any(|f| f.number() == field.number()) {
+                continue;
+            }
+            let path = join(name, field.name());
+            if is_reserved(old, field) {
+                self.report(
+                    Severity::Breaking,
+                    &path,
+                    format!("reuses reserved field {}", field.number()),
+                );
+            }
+            if old.field.iter().all(|f| f.name() != field.name())
+                && !self.introduces_annotated_type(field)
+            {
+                self.check_annotated(&path, "field");
+            }
+        }
+    }
+
+    fn check_field_type(
+        &mut self,
+        path: &str,
+        old: &FieldDescriptorProto,
+        new: &FieldDescriptorProto,
+    ) {
+        let (was, is) = (describe_type(old), describe_type(new));
+        if was == is {
+            return;
+        }
+        let same_label = old.label() == new.label();
+        let wire_compatible = same_label
+            && wire_group(old.r#type()).is_some()
+            && wire_group(old.r#type()) == wire_group(new.r#type());
+        if wire_compatible {
+            self.report(
+                Severity::Warning,
+                path,
+                format!(
+                    "type changed from {} to {}; decodes on the wire, but values may be truncated",
+                    was, is
+                ),
+            );
+        } else {
+            self.report(
+                Severity::Breaking,
+                path,
+                format!("type changed from {} to {}", was, is),
+            );
+        }
+    }
+
+    fn check_enums(&mut self) {
+        let old: Vec<_> = self
+            .old
+            .enums
+            .iter()
+            .map(|(n, e)| (n.clone(), *e))
+            .collect();
+        for (name, old) in old {
+            let Some(new) = self.new.enums.get(&name).copied() else {
+                self.report(Severity::Breaking, &name, "enum removed");
+                continue;
+            };
+            for value in &old.value {
+                let path = join(&name, value.name());
+                match new.value.iter().find(|v| v.name() == value.name()) {
+                    None => self.report(
+                        Severity::Breaking,
+                        &path,
+                        format!("value {} removed", value.number()),
+                    ),
+                    Some(v) if v.number() != value.number() => self.report(
+                        Severity::Breaking,
+                        &path,
+                        format!("number changed from {} to {}", value.number(), v.number()),
+                    ),
+                    Some(_) => {}
+                }
+            }
+            let added: Vec<_> = new
+                .value
+                .iter()
+                .filter(|v| old.value.iter().all(|o| o.name() != v.name()))
+                .map(|v| join(&name, v.name()))
+                .collect();
+            for path in added {
+                self.check_annotated(&path, "enum value");
+            }
+        }
+        let covered = self.covered_types();
+        let added: Vec<_> = self
+            .new
+            .enums
+            .keys()
+            .filter(|n| !self.old.enums.contains_key(*n) && !covered.contains(*n))
+            .cloned()
+            .collect();
+        for name in added {
+            self.check_annotated(&name, "enum");
+        }
+    }
+
+    fn check_services(&mut self) {
+        let old: Vec<_> = self
+            .old
+            .services
+            .iter()
+            .map(|(n, s)| (n.clone(), *s))
+            .collect();
+        for (name, old) in old {
+            let Some(new) = self.new.services.get(&name).copied() else {
+                self.report(Severity::Breaking, &name, "service removed");
+                continue;
+            };
+            for method in &old.method {
+                let path = join(&name, method.name());
+                let Some(m) = new.method.iter().find(|m| m.name() == method.name()) else {
+                    self.report(Severity::Breaking, &path, "rpc removed");
+                    continue;
+                };
+                if (m.input_type(), m.output_type()) != (method.input_type(), method.output_type())
+                {
+                    self.report(
+                        Severity::Breaking,
+                        &path,
+                        format!(
+                            "signature changed from ({}) -> {} to ({}) -> {}",
+                            method.input_type().trim_start_matches('.'),
+                            method.output_type().trim_start_matches('.'),
+                            m.input_type().trim_start_matches('.'),
+                            m.output_type().trim_start_matches('.'),
+                        ),
+                    );
+                }
+                if (m.client_streaming(), m.server_streaming())
+                    != (method.client_streaming(), method.server_streaming())
+                {
+                    self.report(Severity::Breaking, &path, "streaming changed");
+                }
+            }
+            let added: Vec<_> = new
+                .method
+                .iter()
+                .filter(|m| old.method.iter().all(|o| o.name() != m.name()))
+                .map(|m| join(&name, m.name()))
+                .collect();
+            for path in added {
+                self.check_annotated(&path, "rpc");
+            }
+        }
+    }
+
+    /// Correlation keys hold in the new schema, old fields or not
+    fn check_correlation_keys(&mut self) {
+        let mut wrong = Vec::new();
+        for (name, message) in &self.new.messages {
+            for field in &message.field {
+                let singular_string =
+                    field.r#type() == Type::String && field.label() != Label::Repeated;
+                if CORRELATION_KEYS.contains(&field.name()) && !singular_string {
+                    wrong.push((join(name, field.name()), describe_type(field)));
+                }
+            }
+        }
+        for (path, found) in wrong {
+            self.report(
+                Severity::Breaking,
+                &path,
+                format!(
+                    "correlation key must be a singular string like its peers, not {}",
+                    found
+                ),
+            );
+        }
+    }
+}
+
+/// Everything about `new` that breaks peers built from `old`, or strays
+/// from the protocol's conventions; breaking changes first
+pub fn check(old: &FileDescriptorSet, new: &FileDescriptorSet) -> Vec<Finding> {
+    let mut checker = Checker {
+        old: Schema::new(old),
+        new: Schema::new(new),
+        findings: Vec::new(),
+    };
+    checker.check_messages();
+    checker.check_enums();
+    checker.check_services();
+    checker.check_correlation_keys();
+    let mut findings = checker.findings;
+    findings.sort();
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::FieldDescriptorProto;
+
+    fn message<'a>(set: &'a mut FileDescriptorSet, name: &str) -> &'a mut DescriptorProto {
+        set.file
+            .iter_mut()
+            .flat_map(|f| &mut f.message_type)
+            .find(|m| m.name() == name)
+            .unwrap()
+    }
+
+    fn field<'a>(
+        set: &'a mut FileDescriptorSet,
+        msg: &str,
+        name: &str,
+    ) -> &'a mut FieldDescriptorProto {
+        message(set, msg)
+            .field
+            .iter_mut()
+            .find(|f| f.name() == name)
+            .unwrap()
+    }
+
+    fn breaking(findings: &[Finding]) -> Vec<String> {
+        findings
+            .iter()
+            .filter(|f| f.severity == Severity::Breaking)
+            .map(|f| format!("{}: {}", f.path, f.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_current_schema() {
+        let schema = current();
+        assert!(check(&schema, &schema).is_empty());
+        // The annotation check depends on comments being present
+        assert!(Schema::new(&schema).has_source_info);
+        assert!(has_version_note("Progress of a transfer (v1.2+)"));
+        assert!(has_version_note("sent on barge-in, v1.2+. The plugin"));
+        assert!(!has_version_note("Progress of a transfer (v+)"));
+        assert!(!has_version_note("server v1.20.4"));
+    }
+
+    #[test]
+    fn test_breaking_changes() {
+        let old = current();
+        let mut new = old.clone();
+        message(&mut new, "ChatObservation")
+            .field
+            .retain(|f| f.name() != "message");
+        field(&mut new, "ActionResult", "directive_id").set_type(Type::Int64);
+        // Swapping two oneof variants keeps the names but not the numbers
+        let hello = field(&mut new, "ClientMessage", "hello").number();
+        let tick = field(&mut new, "ClientMessage", "world_tick").number();
+        field(&mut new, "ClientMessage", "hello").number = Some(tick);
+        field(&mut new, "ClientMessage", "world_tick").number = Some(hello);
+        field(&mut new, "ClientMessage", "timing").oneof_index = Some(0);
+
+        let findings = breaking(&check(&old, &new));
+        for expected in [
+            "npc_society.v1.ChatObservation.message: field 4 removed",
+            "npc_society.v1.ActionResult.directive_id: type changed from string to int64",
+            "npc_society.v1.ActionResult.directive_id: correlation key must be a singular string like its peers, not int64",
+            "npc_society.v1.ClientMessage.hello: field 1 renamed to world_tick",
+            "npc_society.v1.ClientMessage.timing: moved from no oneof to oneof message",
+        ] {
+            assert!(
+                findings.iter().any(|f| f == expected),
+                "missing {:?} in {:#?}",
+                expected,
+                findings
+            );
+        }
+    }
+
+    #[test]
+    fn test_additions() {
+        let old = current();
+        let mut new = old.clone();
+        let chat = message(&mut new, "ChatObservation");
+        chat.field.push(FieldDescriptorProto {
+            name: Some("mood".to_string()),
+            number: Some(99),
+            r#type: Some(Type::String as i32),
+            label: Some(Label::Optional as i32),
+            ..Default::default()
+        });
+        // Commented, so it doesn't inherit the note of the field before it
+        let mood = chat.field.len() as i32 - 1;
+        let file = &mut new.file[0];
+        let chat_index = file
+            .message_type
+            .iter()
+            .position(|m| m.name() == "ChatObservation")
+            .unwrap() as i32;
+        let info = file.source_code_info.as_mut().unwrap();
+        info.location.push(Location {
+            path: vec![FILE_MESSAGE, chat_index, MESSAGE_FIELD, mood],
+            leading_comments: Some(" How the player feels\n".to_string()),
+            ..Default::default()
+        });
+        field(&mut new, "ChatObservation", "distance").set_type(Type::Double);
+        field(&mut new, "Hello", "voice_available").set_type(Type::Int32);
+
+        let findings = check(&old, &new);
+        let messages: Vec<_> = findings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "BREAKING npc_society.v1.ChatObservation.distance: type changed from float to double",
+                "warning npc_society.v1.ChatObservation.mood: new field has no version note such as (v1.2+)",
+                "warning npc_society.v1.Hello.voice_available: type changed from bool to int32; decodes on the wire, but values may be truncated",
+            ]
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! gzip/zstd compression for the gRPC service (`--features compression`).
+//!
+//! The peers negotiate per connection: each side lists the encodings it
+//! can decompress in `grpc-accept-encoding`, and a sender only compresses
+//! with an encoding the other side accepts, falling back to uncompressed.
+//! [`Compression::server`] enables them on the daemon; the plugin then
+//! compresses its WorldTicks, the largest messages on the wire (grpc-java:
+//! `stub.withCompression("gzip")`).
+//!
+//! tonic compresses a stream all or nothing, so audio cannot be skipped
+//! message by message. The Connect downstream is mostly AudioChunks
+//! (Opus gains nothing, PCM little), so it is sent uncompressed unless
+//! [`Compression::compress_connect`] is set; unary responses such as
+//! `GetSnapshot` are always compressed when negotiated.
+
+use std::fmt;
+
+pub use tonic::codec::CompressionEncoding;
+use tonic::Response;
+
+use crate::npc_society::v1::npc_society_service_server::NpcSocietyServiceServer;
+
+/// Compression settings for the daemon
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compression {
+    /// Encodings to decompress and to send with, in order of preference
+    pub encodings: Vec<CompressionEncoding>,
+    /// Compress the Connect stream from the daemon as well
+    pub compress_connect: bool,
+}
+
+impl Default for Compression {
+    /// zstd, then gzip (the only one grpc-java ships); Connect stream
+    /// uncompressed
+    fn default() -> Self {
+        Self {
+            encodings: vec![CompressionEncoding::Zstd, CompressionEncoding::Gzip],
+            compress_connect: false,
+        }
+    }
+}
+
+/// An encoding name other than `gzip`, `zstd` or `none`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownEncoding(pub String);
+
+impl fmt::Display for UnknownEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown compression encoding {:?} (expected gzip, zstd or none)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnknownEncoding {}
+
+impl Compression {
+    /// No compression either way
+    pub fn none() -> Self {
+        Self {
+            encodings: Vec::new(),
+            compress_connect: false,
+        }
+    }
+
+    /// Encodings from a comma-separated list such as `zstd,gzip`; `none`
+    /// (or an empty list) disables compression
+    pub fn from_names(names: &str) -> Result<Self, UnknownEncoding> {
+        let mut encodings = Vec::new();
+        for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let encoding = match name.to_ascii_lowercase().as_str() {
+                "gzip" => CompressionEncoding::Gzip,
+                "zstd" => CompressionEncoding::Zstd,
+                "none" => continue,
+                _ => return Err(UnknownEncoding(name.to_string())),
+            };
+            if !encodings.contains(&encoding) {
+                encodings.push(encoding);
+            }
+        }
+        Ok(Self {
+            encodings,
+            ..Self::default()
+        })
+    }
+
+    /// Enable the encodings on the daemon's service
+    pub fn server<T>(&self, mut server: NpcSocietyServiceServer<T>) -> NpcSocietyServiceServer<T> {
+        for &encoding in &self.encodings {
+            server = server.accept_compressed(encoding).send_compressed(encoding);
+        }
+        server
+    }
+
+    /// Apply the Connect opt-out to the stream's response
+    pub fn connect_response<T>(&self, response: &mut Response<T>) {
+        if !self.compress_connect {
+            response.disable_compression();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_names() {
+        let compression = Compression::from_names("gzip, ZSTD,gzip").unwrap();
+        assert_eq!(
+            compression.encodings,
+            [CompressionEncoding::Gzip, CompressionEncoding::Zstd]
+        );
+        assert!(!compression.compress_connect);
+        assert!(Compression::from_names("none")
+            .unwrap()
+            .encodings
+            .is_empty());
+        assert_eq!(
+            Compression::from_names("brotli"),
+            Err(UnknownEncoding("brotli".to_string()))
+        );
+    }
+}
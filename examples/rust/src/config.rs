@@ -0,0 +1,950 @@
+//! Daemon configuration from a file, the environment and command-line flags.
+//!
+//! [`DaemonConfig::load`] layers three sources over the defaults, later
+//! ones winning: a config file (`--config <path>` or `NPC_CONFIG`),
+//! `NPC_*` environment variables and `--key value` flags. Every setting
+//! has one dotted key that is the same in all three; `tls.client_ca` is
+//! `client_ca` under `[tls]` in the file, `NPC_TLS_CLIENT_CA` in the
+//! environment and `--tls.client_ca` on the command line.
+//!
+//! ```toml
+//! listen = "0.0.0.0:50051"
+//! max_message_bytes = 16777216
+//!
+//! [tls]
+//! cert = "daemon.pem"
+//! key = "daemon.key"
+//! client_ca = "plugins.pem"    # require client certificates
+//!
+//! [auth]
+//! tokens = ["change-me"]       # plugins send "authorization: Bearer <token>"
+//!
+//! [ticks]
+//! max_directives_per_second = 20.0
+//! min_share = 0.2
+//! healthy_mspt = 40.0
+//! overloaded_mspt = 100.0
+//!
+//! [audio]
+//! voice_mix = false
+//! pacing_lead_ms = 300
+//! pacing_max_lead_ms = 1000
+//!
+//! [policy]
+//! world_control = false
+//! profiles_dir = "npcs"
+//!
+//! [output]
+//! max_chars = 256              # longer NPC text is cut short
+//! truncate = true              # false rejects it instead
+//! strip_control = true
+//! format = "legacy"            # or "minimessage", "plain"
+//! profanity = ["darn", "heck"]
+//! reject_profanity = false     # true rejects instead of masking
+//!
+//! [budget]
+//! usd_per_npc = 0.5            # per period; 0 is unlimited
+//! period_ms = 86400000
+//! downgrade_at = 0.8           # text instead of speech past 80%
+//! usd_per_1k_prompt_tokens = 0.00015
+//! usd_per_1k_completion_tokens = 0.0006
+//! usd_per_1k_tts_chars = 0.015
+//! usd_per_asr_minute = 0.006
+//!
+//! [shutdown]
+//! grace_ms = 10000             # wait for in-flight directives on SIGTERM
+//!
+//! [http2]                      # defaults are Http2Config::lan()
+//! stream_window_bytes = 8388608        # Http2Config::wan() for plugins
+//! connection_window_bytes = 16777216   # more than ~5 ms away
+//! adaptive_window = false
+//! max_concurrent_streams = 32
+//! keepalive_interval_ms = 30000        # 0 never pings
+//! keepalive_timeout_ms = 20000
+//! tcp_nodelay = true
+//!
+//! [probe]                      # self-checks of each plugin connection
+//! count = 8                    # Probes per run; 0 for none
+//! payload_bytes = 4096
+//! timeout_ms = 5000
+//! max_clock_skew_ms = 1000
+//! interval_ms = 600000         # 0 only checks right after Hello
+//! ```
+//!
+//! The file is a TOML subset: tables, strings, numbers, booleans and
+//! one-line string arrays. In the environment and in flags a list is
+//! comma-separated. Unknown keys and values that do not parse are a
+//! [`ConfigError`] naming the key and where it was set. `PORT`,
+//! `MAX_MESSAGE_BYTES`, `WORLD_CONTROL`, `VOICE_MIX` and `NPC_PROFILES_DIR`
+//! are still read, below the `NPC_*` variables.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use tonic::{Request, Status};
+
+use crate::budget::BudgetConfig;
+use crate::http2::Http2Config;
+use crate::probe::ProbeConfig;
+use crate::sanitize::OutputConfig;
+use crate::throttle::ThrottleConfig;
+
+/// Every setting, as its dotted key
+pub const KEYS: [&str; 42] = [
+    "listen",
+    "max_message_bytes",
+    "tls.cert",
+    "tls.key",
+    "tls.client_ca",
+    "auth.tokens",
+    "ticks.max_directives_per_second",
+    "ticks.min_share",
+    "ticks.healthy_mspt",
+    "ticks.overloaded_mspt",
+    "audio.voice_mix",
+    "audio.pacing_lead_ms",
+    "audio.pacing_max_lead_ms",
+    "policy.world_control",
+    "policy.profiles_dir",
+    "output.max_chars",
+    "output.truncate",
+    "output.strip_control",
+    "output.format",
+    "output.profanity",
+    "output.reject_profanity",
+    "budget.usd_per_npc",
+    "budget.period_ms",
+    "budget.downgrade_at",
+    "budget.usd_per_1k_prompt_tokens",
+    "budget.usd_per_1k_completion_tokens",
+    "budget.usd_per_1k_tts_chars",
+    "budget.usd_per_asr_minute",
+    "shutdown.grace_ms",
+    "http2.stream_window_bytes",
+    "http2.connection_window_bytes",
+    "http2.adaptive_window",
+    "http2.max_concurrent_streams",
+    "http2.keepalive_interval_ms",
+    "http2.keepalive_timeout_ms",
+    "http2.tcp_nodelay",
+    "probe.count",
+    "probe.payload_bytes",
+    "probe.timeout_ms",
+    "probe.max_clock_skew_ms",
+    "probe.interval_ms",
+    "config",
+];
+
+/// Environment variables read before the `NPC_*` ones, and their keys
+const LEGACY_ENV: [(&str, &str); 5] = [
+    ("MAX_MESSAGE_BYTES", "max_message_bytes"),
+    ("WORLD_CONTROL", "policy.world_control"),
+    ("VOICE_MIX", "audio.voice_mix"),
+    ("NPC_PROFILES_DIR", "policy.profiles_dir"),
+    ("PORT", "listen"),
+];
+
+/// Where a setting came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// Not set anywhere
+    Default,
+    /// Line of a config file
+    File {
+        /// The file
+        path: PathBuf,
+        /// 1-based line number
+        line: usize,
+    },
+    /// Environment variable
+    Env(String),
+    /// Command-line flag
+    Flag,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File { path, line } => write!(f, "{}:{line}", path.display()),
+            Source::Env(var) => write!(f, "{var}"),
+            Source::Flag => write!(f, "command line"),
+        }
+    }
+}
+
+/// A setting that could not be loaded or does not make sense
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    /// Dotted key, e.g. "tls.cert"
+    pub key: String,
+    /// Where the offending value was set
+    pub source: Source,
+    /// What is wrong with it
+    pub reason: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.key, self.source, self.reason)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// TLS for the gRPC listener; off unless `cert` and `key` are set
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TlsConfig {
+    /// PEM certificate chain
+    pub cert: Option<PathBuf>,
+    /// PEM private key
+    pub key: Option<PathBuf>,
+    /// PEM CA that plugin client certificates must chain to (None = no
+    /// client certificates)
+    pub client_ca: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    /// Whether the listener serves TLS
+    pub fn is_enabled(&self) -> bool {
+        self.cert.is_some() && self.key.is_some()
+    }
+}
+
+/// Bearer tokens callers must present; none = no authentication
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuthConfig {
+    /// Accepted tokens
+    pub tokens: Vec<String>,
+}
+
+impl AuthConfig {
+    /// Whether a call with this `authorization` metadata may proceed
+    pub fn accepts(&self, authorization: Option<&str>) -> bool {
+        self.tokens.is_empty()
+            || authorization
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .is_some_and(|token| self.tokens.iter().any(|t| t == token))
+    }
+
+    /// Interceptor for the service that rejects calls without an accepted
+    /// token as UNAUTHENTICATED
+    #[allow(clippy::result_large_err)] // tonic's Interceptor signature
+    pub fn interceptor(&self) -> impl FnMut(Request<()>) -> Result<Request<()>, Status> + Clone {
+        let auth = self.clone();
+        move |request: Request<()>| {
+            let authorization = request
+                .metadata()
+                .get("authorization")
+                .and_then(|value| value.to_str().ok());
+            if auth.accepts(authorization) {
+                Ok(request)
+            } else {
+                Err(Status::unauthenticated("missing or unknown bearer token"))
+            }
+        }
+    }
+}
+
+/// Audio tuning
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioConfig {
+    /// One ASR feed per NPC with its own voice taken out, instead of one
+    /// per speaker
+    pub voice_mix: bool,
+    /// TTS audio sent ahead of playback
+    pub pacing_lead_ms: i64,
+    /// Longest the lead grows to after underruns
+    pub pacing_max_lead_ms: i64,
+}
+
+impl Default for AudioConfig {
+    /// Per-speaker feeds; the pacing defaults of `PacingConfig`
+    fn default() -> Self {
+        Self {
+            voice_mix: false,
+            pacing_lead_ms: 300,
+            pacing_max_lead_ms: 1_000,
+        }
+    }
+}
+
+/// What NPCs are allowed to do
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PolicyConfig {
+    /// Ask for SetTimeDirective / SetWeatherDirective on every connection
+    pub world_control: bool,
+    /// Per-NPC profiles (allowed actions, home, voice) to load and watch
+    pub profiles_dir: Option<PathBuf>,
+}
+
+/// How the daemon stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownConfig {
+    /// How long in-flight directives get to finish before the streams close
+    pub grace_ms: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self { grace_ms: 10_000 }
+    }
+}
+
+/// Everything a daemon deployment configures
+#[derive(Debug, Clone, PartialEq)]
+pub struct DaemonConfig {
+    /// gRPC listen address
+    pub listen: SocketAddr,
+    /// Largest message accepted from the plugin
+    pub max_message_bytes: usize,
+    /// TLS for the listener
+    pub tls: TlsConfig,
+    /// Tokens callers must present
+    pub auth: AuthConfig,
+    /// Directive rate per server and how it falls with server load
+    pub ticks: ThrottleConfig,
+    /// Voice and TTS tuning
+    pub audio: AudioConfig,
+    /// Permissions of NPCs
+    pub policy: PolicyConfig,
+    /// Cleaning of NPC text before it is sent
+    pub output: OutputConfig,
+    /// Prices of LLM, TTS and ASR calls and each NPC's budget for them
+    pub budget: BudgetConfig,
+    /// Draining on SIGTERM
+    pub shutdown: ShutdownConfig,
+    /// HTTP/2 windows and keepalive of plugin connections
+    pub http2: Http2Config,
+    /// Probe self-checks of plugin connections
+    pub probe: ProbeConfig,
+}
+
+impl Default for DaemonConfig {
+    /// 0.0.0.0:50051 in plaintext without authentication, 16 MB messages
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([0, 0, 0, 0], 50051)),
+            max_message_bytes: 16 * 1024 * 1024,
+            tls: TlsConfig::default(),
+            auth: AuthConfig::default(),
+            ticks: ThrottleConfig::default(),
+            audio: AudioConfig::default(),
+            policy: PolicyConfig::default(),
+            output: OutputConfig::default(),
+            budget: BudgetConfig::default(),
+            shutdown: ShutdownConfig::default(),
+            http2: Http2Config::default(),
+            probe: ProbeConfig::default(),
+        }
+    }
+}
+
+/// A value before it is parsed for its key
+enum Value {
+    Scalar(String),
+    List(Vec<String>),
+}
+
+impl DaemonConfig {
+    /// Configuration of this process: its arguments and environment
+    pub fn from_process() -> Result<Self, ConfigError> {
+        Self::load(std::env::args().skip(1), |var| std::env::var(var).ok())
+    }
+
+    /// Defaults, then the config file, then `env`, then `args` (without
+    /// the program name)
+    pub fn load(
+        args: impl IntoIterator<Item = String>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let flags = parse_args(args)?;
+        let mut config = Self::default();
+        let mut origins = HashMap::new();
+
+        let file = match flags.iter().rev().find(|(key, _)| key == "config") {
+            Some((_, path)) => Some(path.clone()),
+            None => env("NPC_CONFIG"),
+        };
+        if let Some(path) = file {
+            let path = PathBuf::from(path);
+            let text = std::fs::read_to_string(&path).map_err(|e| ConfigError {
+                key: "config".to_string(),
+                source: Source::Default,
+                reason: format!("cannot read {}: {e}", path.display()),
+            })?;
+            config.apply_file(&text, &path, &mut origins)?;
+        }
+
+        for (var, key) in LEGACY_ENV {
+            if let Some(value) = env(var) {
+                // PORT only ever meant the port, and the switches were on
+                // only when "1"
+                let value = match var {
+                    "PORT" => format!("0.0.0.0:{value}"),
+                    "WORLD_CONTROL" | "VOICE_MIX" => (value == "1").to_string(),
+                    _ => value,
+                };
+                config.apply(
+                    key,
+                    list_or_scalar(key, &value),
+                    Source::Env(var.to_string()),
+                    &mut origins,
+                )?;
+            }
+        }
+        for key in KEYS.into_iter().filter(|&key| key != "config") {
+            let var = env_var(key);
+            if let Some(value) = env(&var) {
+                config.apply(
+                    key,
+                    list_or_scalar(key, &value),
+                    Source::Env(var),
+                    &mut origins,
+                )?;
+            }
+        }
+        for (key, value) in flags.iter().filter(|(key, _)| key != "config") {
+            config.apply(key, list_or_scalar(key, value), Source::Flag, &mut origins)?;
+        }
+
+        config.validate().map_err(|(key, reason)| ConfigError {
+            key: key.to_string(),
+            source: origins.get(key).cloned().unwrap_or(Source::Default),
+            reason: reason.to_string(),
+        })?;
+        Ok(config)
+    }
+
+    /// Apply the settings of a config file read from `path`
+    fn apply_file(
+        &mut self,
+        text: &str,
+        path: &std::path::Path,
+        origins: &mut HashMap<String, Source>,
+    ) -> Result<(), ConfigError> {
+        let mut table = String::new();
+        for (number, line) in text.lines().enumerate() {
+            let source = Source::File {
+                path: path.to_path_buf(),
+                line: number + 1,
+            };
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                table = name.trim().to_string();
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(ConfigError {
+                    key: line.to_string(),
+                    source,
+                    reason: "expected `key = value`".to_string(),
+                });
+            };
+            let key = match table.as_str() {
+                "" => key.trim().to_string(),
+                table => format!("{table}.{}", key.trim()),
+            };
+            let value = parse_toml_value(value.trim()).map_err(|reason| ConfigError {
+                key: key.clone(),
+                source: source.clone(),
+                reason: reason.to_string(),
+            })?;
+            self.apply(&key, value, source, origins)?;
+        }
+        Ok(())
+    }
+
+    fn apply(
+        &mut self,
+        key: &str,
+        value: Value,
+        source: Source,
+        origins: &mut HashMap<String, Source>,
+    ) -> Result<(), ConfigError> {
+        self.set(key, value).map_err(|reason| ConfigError {
+            key: key.to_string(),
+            source: source.clone(),
+            reason,
+        })?;
+        origins.insert(key.to_string(), source);
+        Ok(())
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match key {
+            "listen" => self.listen = parse(value)?,
+            "max_message_bytes" => self.max_message_bytes = parse(value)?,
+            "tls.cert" => self.tls.cert = Some(parse(value)?),
+            "tls.key" => self.tls.key = Some(parse(value)?),
+            "tls.client_ca" => self.tls.client_ca = Some(parse(value)?),
+            "auth.tokens" => {
+                self.auth.tokens = match value {
+                    Value::List(tokens) => tokens,
+                    Value::Scalar(token) => vec![token],
+                }
+            }
+            "ticks.max_directives_per_second" => self.ticks.max_per_second = parse(value)?,
+            "ticks.min_share" => self.ticks.min_share = parse(value)?,
+            "ticks.healthy_mspt" => self.ticks.healthy_mspt = parse(value)?,
+            "ticks.overloaded_mspt" => self.ticks.overloaded_mspt = parse(value)?,
+            "audio.voice_mix" => self.audio.voice_mix = parse(value)?,
+            "audio.pacing_lead_ms" => self.audio.pacing_lead_ms = parse(value)?,
+            "audio.pacing_max_lead_ms" => self.audio.pacing_max_lead_ms = parse(value)?,
+            "policy.world_control" => self.policy.world_control = parse(value)?,
+            "policy.profiles_dir" => self.policy.profiles_dir = Some(parse(value)?),
+            "output.max_chars" => self.output.max_chars = parse(value)?,
+            "output.truncate" => self.output.truncate = parse(value)?,
+            "output.strip_control" => self.output.strip_control = parse(value)?,
+            "output.format" => self.output.format = parse(value)?,
+            "output.profanity" => {
+                self.output.profanity = match value {
+                    Value::List(words) => words,
+                    Value::Scalar(word) => vec![word],
+                }
+            }
+            "output.reject_profanity" => self.output.reject_profanity = parse(value)?,
+            "budget.usd_per_npc" => self.budget.usd_per_npc = parse(value)?,
+            "budget.period_ms" => self.budget.period_ms = parse(value)?,
+            "budget.downgrade_at" => self.budget.downgrade_at = parse(value)?,
+            "budget.usd_per_1k_prompt_tokens" => {
+                self.budget.usd_per_1k_prompt_tokens = parse(value)?
+            }
+            "budget.usd_per_1k_completion_tokens" => {
+                self.budget.usd_per_1k_completion_tokens = parse(value)?
+            }
+            "budget.usd_per_1k_tts_chars" => self.budget.usd_per_1k_tts_chars = parse(value)?,
+            "budget.usd_per_asr_minute" => self.budget.usd_per_asr_minute = parse(value)?,
+            "shutdown.grace_ms" => self.shutdown.grace_ms = parse(value)?,
+            "http2.stream_window_bytes" => self.http2.stream_window_bytes = parse(value)?,
+            "http2.connection_window_bytes" => self.http2.connection_window_bytes = parse(value)?,
+            "http2.adaptive_window" => self.http2.adaptive_window = parse(value)?,
+            "http2.max_concurrent_streams" => self.http2.max_concurrent_streams = parse(value)?,
+            "http2.keepalive_interval_ms" => self.http2.keepalive_interval_ms = parse(value)?,
+            "http2.keepalive_timeout_ms" => self.http2.keepalive_timeout_ms = parse(value)?,
+            "http2.tcp_nodelay" => self.http2.tcp_nodelay = parse(value)?,
+            "probe.count" => self.probe.count = parse(value)?,
+            "probe.payload_bytes" => self.probe.payload_bytes = parse(value)?,
+            "probe.timeout_ms" => self.probe.timeout_ms = parse(value)?,
+            "probe.max_clock_skew_ms" => self.probe.max_clock_skew_ms = parse(value)?,
+            "probe.interval_ms" => self.probe.interval_ms = parse(value)?,
+            _ => return Err("unknown setting".to_string()),
+        }
+        Ok(())
+    }
+
+    /// Check settings against each other; the error names the key to fix
+    pub fn validate(&self) -> Result<(), (&'static str, &'static str)> {
+        if self.max_message_bytes == 0 {
+            return Err(("max_message_bytes", "must be positive"));
+        }
+        match (&self.tls.cert, &self.tls.key) {
+            (Some(_), None) => return Err(("tls.key", "required with tls.cert")),
+            (None, Some(_)) => return Err(("tls.cert", "required with tls.key")),
+            _ => {}
+        }
+        if self.tls.client_ca.is_some() && !self.tls.is_enabled() {
+            return Err(("tls.client_ca", "requires tls.cert and tls.key"));
+        }
+        if self.auth.tokens.iter().any(|t| t.trim().is_empty()) {
+            return Err(("auth.tokens", "empty token"));
+        }
+        if self.ticks.max_per_second <= 0.0 {
+            return Err(("ticks.max_directives_per_second", "must be positive"));
+        }
+        if !(0.0..=1.0).contains(&self.ticks.min_share) {
+            return Err(("ticks.min_share", "must be between 0 and 1"));
+        }
+        if self.ticks.healthy_mspt >= self.ticks.overloaded_mspt {
+            return Err(("ticks.overloaded_mspt", "must be above ticks.healthy_mspt"));
+        }
+        if self.audio.pacing_lead_ms <= 0 {
+            return Err(("audio.pacing_lead_ms", "must be positive"));
+        }
+        if self.audio.pacing_max_lead_ms < self.audio.pacing_lead_ms {
+            return Err((
+                "audio.pacing_max_lead_ms",
+                "must be at least audio.pacing_lead_ms",
+            ));
+        }
+        if self.output.max_chars == 0 {
+            return Err(("output.max_chars", "must be positive"));
+        }
+        if self.budget.usd_per_npc < 0.0 {
+            return Err(("budget.usd_per_npc", "must not be negative"));
+        }
+        if self.budget.period_ms <= 0 {
+            return Err(("budget.period_ms", "must be positive"));
+        }
+        if !(0.0..=1.0).contains(&self.budget.downgrade_at) {
+            return Err(("budget.downgrade_at", "must be between 0 and 1"));
+        }
+        let rates = [
+            (
+                "budget.usd_per_1k_prompt_tokens",
+                self.budget.usd_per_1k_prompt_tokens,
+            ),
+            (
+                "budget.usd_per_1k_completion_tokens",
+                self.budget.usd_per_1k_completion_tokens,
+            ),
+            (
+                "budget.usd_per_1k_tts_chars",
+                self.budget.usd_per_1k_tts_chars,
+            ),
+            ("budget.usd_per_asr_minute", self.budget.usd_per_asr_minute),
+        ];
+        if let Some(&(key, _)) = rates.iter().find(|(_, rate)| *rate < 0.0) {
+            return Err((key, "must not be negative"));
+        }
+        // h2 windows run from its default of 64 KiB to 2^31 - 1
+        let windows = [
+            ("http2.stream_window_bytes", self.http2.stream_window_bytes),
+            (
+                "http2.connection_window_bytes",
+                self.http2.connection_window_bytes,
+            ),
+        ];
+        if let Some(&(key, _)) = windows
+            .iter()
+            .find(|(_, window)| !(65_535..=i32::MAX as u32).contains(window))
+        {
+            return Err((key, "must be between 65535 and 2147483647"));
+        }
+        if self.http2.connection_window_bytes < self.http2.stream_window_bytes {
+            return Err((
+                "http2.connection_window_bytes",
+                "must be at least http2.stream_window_bytes",
+            ));
+        }
+        if self.http2.max_concurrent_streams < 2 {
+            return Err((
+                "http2.max_concurrent_streams",
+                "must be at least 2 (Connect and ConnectAudio)",
+            ));
+        }
+        if self.http2.keepalive_timeout_ms == 0 {
+            return Err(("http2.keepalive_timeout_ms", "must be positive"));
+        }
+        if self.probe.payload_bytes >= self.max_message_bytes {
+            return Err(("probe.payload_bytes", "must be below max_message_bytes"));
+        }
+        if self.probe.timeout_ms <= 0 {
+            return Err(("probe.timeout_ms", "must be positive"));
+        }
+        let probe_times = [
+            ("probe.max_clock_skew_ms", self.probe.max_clock_skew_ms),
+            ("probe.interval_ms", self.probe.interval_ms),
+        ];
+        if let Some(&(key, _)) = probe_times.iter().find(|(_, ms)| *ms < 0) {
+            return Err((key, "must not be negative"));
+        }
+        Ok(())
+    }
+}
+
+/// Environment variable of a key: "tls.client_ca" is NPC_TLS_CLIENT_CA
+pub fn env_var(key: &str) -> String {
+    format!("NPC_{}", key.replace('.', "_").to_ascii_uppercase())
+}
+
+/// `--key value` and `--key=value` pairs; a boolean key alone means true
+fn parse_args(
+    args: impl IntoIterator<Item = String>,
+) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut args = args.into_iter().peekable();
+    let mut flags = Vec::new();
+    while let Some(arg) = args.next() {
+        let error = |reason: &str| ConfigError {
+            key: arg.clone(),
+            source: Source::Flag,
+            reason: reason.to_string(),
+        };
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(error("expected --key value"));
+        };
+        let (key, value) = match flag.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => {
+                let is_bool = matches!(
+                    flag,
+                    "audio.voice_mix"
+                        | "policy.world_control"
+                        | "output.truncate"
+                        | "output.strip_control"
+                        | "output.reject_profanity"
+                        | "http2.adaptive_window"
+                        | "http2.tcp_nodelay"
+                );
+                match args.next_if(|next| !(is_bool && next.starts_with("--"))) {
+                    Some(value) => (flag.to_string(), value),
+                    None if is_bool => (flag.to_string(), "true".to_string()),
+                    None => return Err(error("missing value")),
+                }
+            }
+        };
+        if !KEYS.contains(&key.as_str()) {
+            return Err(ConfigError {
+                key,
+                source: Source::Flag,
+                reason: "unknown setting".to_string(),
+            });
+        }
+        flags.push((key, value));
+    }
+    Ok(flags)
+}
+
+fn list_or_scalar(key: &str, value: &str) -> Value {
+    if key == "auth.tokens" || key == "output.profanity" {
+        Value::List(value.split(',').map(|t| t.trim().to_string()).collect())
+    } else {
+        Value::Scalar(value.to_string())
+    }
+}
+
+fn parse<T: FromStr>(value: Value) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    match value {
+        Value::Scalar(value) => value.trim().parse().map_err(|e| format!("{value:?}: {e}")),
+        Value::List(_) => Err("expected a single value, not a list".to_string()),
+    }
+}
+
+/// The line up to a `#` outside a string
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_toml_value(value: &str) -> Result<Value, &'static str> {
+    if let Some(items) = value.strip_prefix('[') {
+        let items = items.strip_suffix(']').ok_or("unterminated array")?;
+        let mut list = Vec::new();
+        let mut rest = items.trim();
+        while !rest.is_empty() {
+            let (item, after) = parse_string(rest)?;
+            list.push(item);
+            rest = after.trim_start();
+            rest = match rest.strip_prefix(',') {
+                Some(after) => after.trim_start(),
+                None if rest.is_empty() => rest,
+                None => return Err("expected `,` between array items"),
+            };
+        }
+        return Ok(Value::List(list));
+    }
+    if value.starts_with('"') {
+        let (string, rest) = parse_string(value)?;
+        if !rest.trim().is_empty() {
+            return Err("unexpected text after string");
+        }
+        return Ok(Value::Scalar(string));
+    }
+    if value.is_empty() {
+        return Err("missing value");
+    }
+    // Numbers and booleans; TOML allows `_` between digits
+    Ok(Value::Scalar(value.replace('_', "")))
+}
+
+/// A quoted string at the start of `text`, and what follows it
+fn parse_string(text: &str) -> Result<(String, &str), &'static str> {
+    let body = text.strip_prefix('"').ok_or("expected a quoted string")?;
+    let mut string = String::new();
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((string, &body[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => string.push('\n'),
+                Some((_, 't')) => string.push('\t'),
+                Some((_, c @ ('"' | '\\'))) => string.push(c),
+                _ => return Err("unsupported escape"),
+            },
+            c => string.push(c),
+        }
+    }
+    Err("unterminated string")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::sanitize::ChatFormat;
+
+    fn load(
+        file: Option<&str>,
+        env: &[(&str, &str)],
+        args: &[&str],
+    ) -> Result<DaemonConfig, ConfigError> {
+        let mut env: HashMap<String, String> = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        if let Some(text) = file {
+            static FILES: AtomicUsize = AtomicUsize::new(0);
+            let name = format!(
+                "npc-config-{}-{}.toml",
+                std::process::id(),
+                FILES.fetch_add(1, Ordering::Relaxed)
+            );
+            let path = std::env::temp_dir().join(name);
+            std::fs::write(&path, text).unwrap();
+            env.insert("NPC_CONFIG".to_string(), path.display().to_string());
+        }
+        DaemonConfig::load(args.iter().map(|a| a.to_string()), |var| {
+            env.get(var).cloned()
+        })
+    }
+
+    #[test]
+    fn test_layers() {
+        let file = r#"
+            listen = "127.0.0.1:6000"   # local only
+            [auth]
+            tokens = ["a#1", "b"]
+            [ticks]
+            max_directives_per_second = 10.0
+            healthy_mspt = 30
+            [audio]
+            pacing_lead_ms = 200
+            [output]
+            format = "minimessage"
+            profanity = ["darn"]
+            [budget]
+            usd_per_npc = 0.5
+        "#;
+        let config = load(
+            Some(file),
+            &[
+                ("NPC_AUDIO_PACING_LEAD_MS", "250"),
+                ("VOICE_MIX", "1"),
+                ("PORT", "7000"),
+                ("NPC_OUTPUT_PROFANITY", "darn, heck"),
+            ],
+            &[
+                "--listen",
+                "127.0.0.1:7001",
+                "--policy.world_control",
+                "--tls.cert=d.pem",
+                "--tls.key",
+                "d.key",
+                "--output.truncate",
+                "false",
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.listen, "127.0.0.1:7001".parse().unwrap());
+        assert_eq!(config.auth.tokens, ["a#1", "b"]);
+        assert_eq!(config.ticks.max_per_second, 10.0);
+        assert_eq!(config.ticks.healthy_mspt, 30.0);
+        assert_eq!(config.audio.pacing_lead_ms, 250);
+        assert!(config.audio.voice_mix && config.policy.world_control);
+        assert!(config.tls.is_enabled());
+        assert_eq!(config.output.format, ChatFormat::MiniMessage);
+        assert_eq!(config.output.profanity, ["darn", "heck"]);
+        assert!(!config.output.truncate);
+        assert_eq!(config.budget.usd_per_npc, 0.5);
+
+        // PORT alone still works
+        let config = load(None, &[("PORT", "7000")], &[]).unwrap();
+        assert_eq!(config.listen.port(), 7000);
+        assert_eq!(load(None, &[], &[]).unwrap(), DaemonConfig::default());
+    }
+
+    #[test]
+    fn test_errors_name_the_key() {
+        let err = load(None, &[("NPC_TICKS_MIN_SHARE", "lots")], &[]).unwrap_err();
+        assert_eq!(err.key, "ticks.min_share");
+        assert_eq!(err.source, Source::Env("NPC_TICKS_MIN_SHARE".to_string()));
+
+        let err = load(Some("[audio]\nvoice_mixx = true\n"), &[], &[]).unwrap_err();
+        assert_eq!(err.key, "audio.voice_mixx");
+        assert!(matches!(err.source, Source::File { line: 2, .. }));
+
+        let err = load(None, &[], &["--tls.cert", "d.pem"]).unwrap_err();
+        assert_eq!(
+            (err.key.as_str(), err.to_string().as_str()),
+            ("tls.key", "tls.key (default): required with tls.cert")
+        );
+
+        let err = load(None, &[], &["--ticks.healthy_mspt", "150"]).unwrap_err();
+        assert_eq!(
+            (err.key.as_str(), &err.source),
+            ("ticks.overloaded_mspt", &Source::Default)
+        );
+        assert_eq!(load(None, &[], &["--nope", "1"]).unwrap_err().key, "nope");
+        let err = load(None, &[], &["--output.format", "html"]).unwrap_err();
+        assert_eq!(
+            err.reason,
+            "\"html\": expected plain, legacy or minimessage"
+        );
+        let err = load(None, &[("NPC_BUDGET_DOWNGRADE_AT", "1.5")], &[]).unwrap_err();
+        assert_eq!(err.key, "budget.downgrade_at");
+        let err = load(None, &[], &["--http2.stream_window_bytes", "4194304"]).unwrap_err();
+        assert_eq!(
+            (err.key.as_str(), &err.source),
+            ("http2.connection_window_bytes", &Source::Default)
+        );
+    }
+
+    #[test]
+    fn test_http2() {
+        let file =
+            "[http2]\nstream_window_bytes = 8_388_608\nconnection_window_bytes = 16_777_216\n";
+        let config = load(
+            Some(file),
+            &[("NPC_HTTP2_KEEPALIVE_INTERVAL_MS", "30000")],
+            &["--http2.keepalive_timeout_ms", "20000"],
+        )
+        .unwrap();
+        assert_eq!(config.http2, Http2Config::wan());
+    }
+
+    #[test]
+    fn test_probe() {
+        let file = "[probe]\ncount = 4\ninterval_ms = 600_000\n";
+        let config = load(Some(file), &[("NPC_PROBE_TIMEOUT_MS", "2000")], &[]).unwrap();
+        assert_eq!(config.probe.count, 4);
+        assert_eq!(config.probe.interval_ms, 600_000);
+        assert_eq!(config.probe.timeout_ms, 2_000);
+        let err = load(None, &[], &["--probe.timeout_ms", "0"]).unwrap_err();
+        assert_eq!(
+            (err.key.as_str(), &err.source),
+            ("probe.timeout_ms", &Source::Flag)
+        );
+    }
+
+    #[test]
+    fn test_auth() {
+        assert!(AuthConfig::default().accepts(None));
+        let auth = AuthConfig {
+            tokens: vec!["s3cret".to_string()],
+        };
+        assert!(auth.accepts(Some("Bearer s3cret")));
+        assert!(!auth.accepts(Some("Bearer other")));
+        assert!(!auth.accepts(Some("s3cret")));
+        assert!(!auth.accepts(None));
+    }
+}
@@ -0,0 +1,308 @@
+//! What a handler knows about the connection a message came in on.
+//!
+//! A [`ConnectionContext`] is created when a plugin opens its Connect
+//! stream and lives as long as the stream. It holds the peer address, the
+//! Hello and the HelloAck (so the negotiated [`Capabilities`]), the
+//! outbound queue's counters and a count of messages received. It also
+//! hands out directive and stream ids from a [`DirectiveIdFactory`], and
+//! keeps an extensions map for anything else a daemon keeps per
+//! connection. [`crate::events::dispatch`] passes it to every handler
+//! method, so nothing about "the current connection" has to be global, and
+//! one daemon can serve several plugins at once.
+//!
+//! Give every connection of a daemon the same factory
+//! ([`ConnectionContext::with_ids`]): ids then never repeat across
+//! reconnects, restarts or replicas. Without one, a connection makes its
+//! own, with the connection time as its epoch.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+use tonic::Extensions;
+
+use crate::ids::DirectiveIdFactory;
+use crate::movement;
+use crate::npc_society::v1::{
+    AudioStreamHello, Hello, HelloAck, MovementMode, PcmFormat, SpeechDelivery,
+};
+use crate::outbound::OutboundMonitor;
+
+/// Sample rate used by Simple Voice Chat and the protocol default
+pub const PROTOCOL_SAMPLE_RATE_HZ: u32 = 48_000;
+
+/// Sample rate most ASR engines expect
+pub const ASR_SAMPLE_RATE_HZ: u32 = 16_000;
+
+/// `HelloAck.voice_sample_rate_hz` for a daemon that wants voice at
+/// `preferred_hz`: that rate if the plugin offers it, otherwise 0 (48kHz)
+pub fn negotiate_voice_rate(hello: &Hello, preferred_hz: u32) -> i32 {
+    let preferred = preferred_hz as i32;
+    if preferred_hz != PROTOCOL_SAMPLE_RATE_HZ
+        && hello.supported_voice_sample_rates_hz.contains(&preferred)
+    {
+        preferred
+    } else {
+        0
+    }
+}
+
+/// What the plugin offered in its Hello and the daemon took in its HelloAck
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capabilities {
+    /// Protocol version the plugin speaks
+    pub protocol_version: String,
+    /// Whether players' voice reaches the daemon
+    pub voice: bool,
+    /// Format of VoicePcmFrame
+    pub voice_format: PcmFormat,
+    /// Sample rate of VoicePcmFrame; 0 until the HelloAck
+    pub voice_sample_rate_hz: u32,
+    /// Format of AudioChunk
+    pub playback_format: PcmFormat,
+    /// SetTime/SetWeather allowed: offered by the plugin and asked for
+    pub world_control: bool,
+    /// Speech routings the plugin can deliver
+    pub deliveries: Vec<SpeechDelivery>,
+    /// Whether ActionDirective.dry_run is answered with a PlanEstimate
+    pub dry_run: bool,
+    /// Movement the plugin's pathfinder can plan besides walking
+    pub movement: Vec<MovementMode>,
+    /// Audio may move to a ConnectAudio stream: offered and a token given
+    pub audio_stream: bool,
+    /// Whether DebugPathDirectives draw anything
+    pub debug_path: bool,
+    /// Whether KeepChunkLoadedDirectives are honored
+    pub keep_chunk_loaded: bool,
+}
+
+/// One plugin connection, shared by every handler call on its stream
+#[derive(Debug)]
+pub struct ConnectionContext {
+    peer_address: String,
+    connected_at_ms: i64,
+    hello: OnceLock<Hello>,
+    ack: OnceLock<HelloAck>,
+    outbound: Option<OutboundMonitor>,
+    received: AtomicU64,
+    ids: Arc<DirectiveIdFactory>,
+    extensions: Mutex<Extensions>,
+}
+
+impl ConnectionContext {
+    /// A connection from `peer_address` opened at `connected_at_ms`
+    pub fn new(peer_address: impl Into<String>, connected_at_ms: i64) -> Self {
+        Self {
+            peer_address: peer_address.into(),
+            connected_at_ms,
+            hello: OnceLock::new(),
+            ack: OnceLock::new(),
+            outbound: None,
+            received: AtomicU64::new(0),
+            ids: Arc::new(DirectiveIdFactory::new("", connected_at_ms)),
+            extensions: Mutex::new(Extensions::new()),
+        }
+    }
+
+    /// Take ids from `ids`, shared with the daemon's other connections
+    pub fn with_ids(mut self, ids: Arc<DirectiveIdFactory>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    /// Report the counters of the connection's outbound queue
+    pub fn with_outbound(mut self, monitor: OutboundMonitor) -> Self {
+        self.outbound = Some(monitor);
+        self
+    }
+
+    /// Remote address of the plugin
+    pub fn peer_address(&self) -> &str {
+        &self.peer_address
+    }
+
+    /// When the stream opened (Unix ms)
+    pub fn connected_at_ms(&self) -> i64 {
+        self.connected_at_ms
+    }
+
+    /// The plugin's handshake, once it arrived
+    pub fn hello(&self) -> Option<&Hello> {
+        self.hello.get()
+    }
+
+    /// The daemon's answer to it, once sent
+    pub fn ack(&self) -> Option<&HelloAck> {
+        self.ack.get()
+    }
+
+    /// server_id from the Hello (empty before the handshake)
+    pub fn server_id(&self) -> &str {
+        self.hello()
+            .map(|h| h.server_id.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Keep the handshake; false if the connection already had one
+    pub fn record_hello(&self, hello: &Hello) -> bool {
+        self.hello.set(hello.clone()).is_ok()
+    }
+
+    /// Keep the HelloAck sent; false if one was already recorded
+    pub fn record_ack(&self, ack: &HelloAck) -> bool {
+        self.ack.set(ack.clone()).is_ok()
+    }
+
+    /// What was negotiated so far. Before the HelloAck the formats are
+    /// unset (S16LE) and world control is off.
+    pub fn capabilities(&self) -> Capabilities {
+        let Some(hello) = self.hello() else {
+            return Capabilities::default();
+        };
+        let ack = self.ack();
+        Capabilities {
+            protocol_version: hello.protocol_version.clone(),
+            voice: hello.voice_available,
+            voice_format: ack.map(HelloAck::voice_format).unwrap_or_default(),
+            voice_sample_rate_hz: ack.map_or(0, |a| match a.voice_sample_rate_hz {
+                0 => PROTOCOL_SAMPLE_RATE_HZ,
+                hz => hz as u32,
+            }),
+            playback_format: ack.map(HelloAck::playback_format).unwrap_or_default(),
+            world_control: hello.world_control_available && ack.is_some_and(|a| a.world_control),
+            deliveries: hello.supported_deliveries().collect(),
+            dry_run: hello.dry_run_available,
+            movement: movement::supported(hello),
+            audio_stream: hello.audio_stream_available
+                && ack.is_some_and(|a| !a.audio_stream_token.is_empty()),
+            debug_path: hello.debug_path_available,
+            keep_chunk_loaded: hello.keep_chunk_loaded_available,
+        }
+    }
+
+    /// Whether `hello` opens this connection's ConnectAudio stream: the
+    /// server and the token of its HelloAck match
+    pub fn accepts_audio_stream(&self, hello: &AudioStreamHello) -> bool {
+        self.capabilities().audio_stream
+            && hello.server_id == self.server_id()
+            && self
+                .ack()
+                .is_some_and(|a| a.audio_stream_token == hello.token)
+    }
+
+    /// Counters of the outbound queue, if one was attached
+    pub fn outbound(&self) -> Option<&OutboundMonitor> {
+        self.outbound.as_ref()
+    }
+
+    /// ClientMessages received so far
+    pub fn received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    /// Count a received ClientMessage
+    pub fn count_received(&self) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A fresh id of `kind` that belongs to no NPC, such as a stream id
+    pub fn next_id(&self, kind: &str) -> String {
+        self.ids.next(kind)
+    }
+
+    /// A fresh directive id for `npc_id`
+    pub fn next_directive_id(&self, npc_id: &str) -> String {
+        self.ids.next_for(npc_id, "dir")
+    }
+
+    /// Where this connection's ids come from
+    pub fn ids(&self) -> &DirectiveIdFactory {
+        &self.ids
+    }
+
+    /// Per-connection data of the daemon's own, by type
+    pub fn extensions(&self) -> MutexGuard<'_, Extensions> {
+        self.extensions.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_context() {
+        let cx = ConnectionContext::new("10.0.0.2:41000", 1_000);
+        assert_eq!(cx.server_id(), "");
+        assert_eq!(cx.capabilities(), Capabilities::default());
+        assert_eq!(cx.next_directive_id("smith"), "smith:dir:local:rs:1");
+        assert_eq!(cx.next_id("stream"), "stream:local:rs:2");
+        // A later connection never reuses an id
+        assert_ne!(
+            ConnectionContext::new("", 1_001).next_id("stream"),
+            "stream:local:rs:2"
+        );
+        let shared = Arc::new(DirectiveIdFactory::new("a", 5));
+        let (a, b) = (
+            ConnectionContext::new("", 1).with_ids(shared.clone()),
+            ConnectionContext::new("", 1).with_ids(shared),
+        );
+        assert_ne!(a.next_id("stream"), b.next_id("stream"));
+
+        let hello = Hello {
+            server_id: "survival".to_string(),
+            world_control_available: true,
+            supported_deliveries: vec![SpeechDelivery::Global as i32],
+            dry_run_available: true,
+            supported_movement: vec![MovementMode::Climb as i32],
+            supported_voice_sample_rates_hz: vec![16_000],
+            debug_path_available: true,
+            ..Default::default()
+        };
+        assert!(cx.record_hello(&hello));
+        assert!(!cx.record_hello(&Hello::default()));
+        assert_eq!(cx.server_id(), "survival");
+        assert!(!cx.capabilities().world_control);
+        cx.record_ack(&HelloAck {
+            world_control: true,
+            voice_sample_rate_hz: negotiate_voice_rate(&hello, ASR_SAMPLE_RATE_HZ),
+            ..Default::default()
+        });
+        let capabilities = cx.capabilities();
+        assert!(capabilities.world_control);
+        assert_eq!(capabilities.voice_sample_rate_hz, 16_000);
+        assert_eq!(capabilities.deliveries, [SpeechDelivery::Global]);
+        assert!(capabilities.dry_run);
+        assert_eq!(capabilities.movement, [MovementMode::Climb]);
+        assert!(capabilities.debug_path);
+        assert!(!capabilities.keep_chunk_loaded);
+        // Not offered, so no audio stream even with a token
+        assert!(!capabilities.audio_stream);
+        let audio = AudioStreamHello {
+            server_id: "survival".to_string(),
+            token: "t1".to_string(),
+        };
+        assert!(!cx.accepts_audio_stream(&audio));
+        let cx = ConnectionContext::new("", 1);
+        cx.record_hello(&Hello {
+            audio_stream_available: true,
+            ..hello
+        });
+        cx.record_ack(&HelloAck {
+            audio_stream_token: "t1".to_string(),
+            ..Default::default()
+        });
+        assert!(cx.capabilities().audio_stream);
+        assert!(cx.accepts_audio_stream(&audio));
+        assert!(!cx.accepts_audio_stream(&AudioStreamHello {
+            token: "t2".to_string(),
+            ..audio.clone()
+        }));
+        assert!(!cx.accepts_audio_stream(&AudioStreamHello {
+            server_id: "creative".to_string(),
+            ..audio
+        }));
+
+        cx.extensions().insert(7u32);
+        assert_eq!(cx.extensions().get::<u32>(), Some(&7));
+    }
+}
@@ -0,0 +1,297 @@
+//! Players' consent to voice capture (`VoiceConsent`, v1.2+).
+//!
+//! Servers whose policy requires consent say so in the Hello
+//! (`voice_consent_required`); the plugin then reports each player's answer
+//! in WorldTicks and as VoiceConsentObservations, and sends no voice of
+//! players who did not agree. [`VoiceConsents`] keeps those answers and
+//! holds the daemon to them as well:
+//!
+//! - [`VoiceConsents::admit`] drops frames of players without consent that
+//!   were in flight when they withdrew it, and, on servers that only
+//!   forward voice on request (`voice_capture_on_request`), frames no
+//!   capture asked for.
+//! - [`VoiceConsents::request`] builds a RequestVoiceCapture with the
+//!   players who may not be captured taken out, or nothing if none are
+//!   left.
+//! - [`VoiceConsents::observe`] answers a withdrawn consent with the
+//!   StopVoiceCaptures for the NPCs that were listening to the player.
+//!
+//! ```ignore
+//! if let Some(request) = consents.request(&npc_id, [&player_uuid], 60_000, now_ms) {
+//!     tx.send(request)?;
+//! }
+//! // ...
+//! if consents.admit(&frame, now_ms) {
+//!     conversations.push_frame(frame, now_ms);
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use crate::npc_society::v1::{
+    Hello, RequestVoiceCapture, StopVoiceCapture, VoiceConsent, VoiceConsentObservation,
+    VoicePcmFrame, WorldTick,
+};
+
+/// A RequestVoiceCapture in effect
+#[derive(Debug, Clone, Default)]
+struct Capture {
+    /// Players captured; empty is every consenting player
+    players: HashSet<String>,
+    /// When the plugin stops by itself, if ever
+    until_ms: Option<i64>,
+}
+
+impl Capture {
+    fn covers(&self, player_uuid: &str, now_ms: i64) -> bool {
+        self.until_ms.is_none_or(|until| now_ms < until)
+            && (self.players.is_empty() || self.players.contains(player_uuid))
+    }
+}
+
+/// Consent per player and the voice captures requested per NPC
+#[derive(Debug, Clone, Default)]
+pub struct VoiceConsents {
+    required: bool,
+    on_request: bool,
+    consents: HashMap<String, VoiceConsent>,
+    captures: HashMap<String, Capture>,
+    dropped: u64,
+}
+
+impl VoiceConsents {
+    /// Take the server's voice policy from its Hello. Captures end with the
+    /// connection they were requested on; consents are kept.
+    pub fn on_hello(&mut self, hello: &Hello) {
+        self.required = hello.voice_consent_required;
+        self.on_request = hello.voice_capture_on_request;
+        self.captures.clear();
+    }
+
+    /// Whether the server requires consent to voice capture
+    pub fn required(&self) -> bool {
+        self.required
+    }
+
+    /// Whether voice is only forwarded after a RequestVoiceCapture
+    pub fn on_request(&self) -> bool {
+        self.on_request
+    }
+
+    /// Take the consent of every player in the tick
+    pub fn observe_tick(&mut self, tick: &WorldTick) {
+        if !self.required {
+            return;
+        }
+        for player in &tick.nearby_players {
+            self.consents
+                .insert(player.player_uuid.clone(), player.voice_consent());
+        }
+    }
+
+    /// Take a changed consent; returns the StopVoiceCaptures for the NPCs
+    /// that were listening to the player by name if it was withdrawn
+    pub fn observe(&mut self, observation: &VoiceConsentObservation) -> Vec<StopVoiceCapture> {
+        let player = &observation.player_uuid;
+        self.consents.insert(player.clone(), observation.consent());
+        if self.allows(player) {
+            return Vec::new();
+        }
+        let mut stops = Vec::new();
+        self.captures.retain(|npc_id, capture| {
+            if !capture.players.remove(player) {
+                return true;
+            }
+            stops.push(StopVoiceCapture {
+                npc_id: npc_id.clone(),
+                player_uuids: vec![player.clone()],
+            });
+            // An empty list would mean everyone
+            !capture.players.is_empty()
+        });
+        stops
+    }
+
+    /// The player's consent as last reported
+    pub fn consent(&self, player_uuid: &str) -> VoiceConsent {
+        self.consents
+            .get(player_uuid)
+            .copied()
+            .unwrap_or(VoiceConsent::Unspecified)
+    }
+
+    /// Whether the player's voice may be captured
+    pub fn allows(&self, player_uuid: &str) -> bool {
+        !self.required || self.consent(player_uuid) == VoiceConsent::Granted
+    }
+
+    /// Whether `frame` may be processed; counts the ones that may not
+    pub fn admit(&mut self, frame: &VoicePcmFrame, now_ms: i64) -> bool {
+        let requested = !self.on_request
+            || self
+                .captures
+                .get(&frame.npc_id)
+                .is_some_and(|capture| capture.covers(&frame.player_uuid, now_ms));
+        let admitted = requested && self.allows(&frame.player_uuid);
+        if !admitted {
+            self.dropped += 1;
+        }
+        admitted
+    }
+
+    /// Frames [`admit`](Self::admit) turned away
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// A capture of `players` (none is every consenting player) for the
+    /// NPC, replacing its earlier one. `None` if every player named may not
+    /// be captured.
+    pub fn request<I, S>(
+        &mut self,
+        npc_id: &str,
+        players: I,
+        max_duration_ms: i32,
+        now_ms: i64,
+    ) -> Option<RequestVoiceCapture>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut named = false;
+        let mut allowed = HashSet::new();
+        for player in players {
+            let player = player.into();
+            named = true;
+            if self.allows(&player) {
+                allowed.insert(player);
+            }
+        }
+        if named && allowed.is_empty() {
+            return None;
+        }
+        let mut player_uuids: Vec<String> = allowed.iter().cloned().collect();
+        player_uuids.sort();
+        let request = RequestVoiceCapture {
+            npc_id: npc_id.to_string(),
+            player_uuids,
+            max_duration_ms,
+        };
+        let until_ms = (max_duration_ms > 0).then(|| now_ms + max_duration_ms as i64);
+        self.captures.insert(
+            npc_id.to_string(),
+            Capture {
+                players: allowed,
+                until_ms,
+            },
+        );
+        Some(request)
+    }
+
+    /// Stop every capture for the NPC
+    pub fn stop(&mut self, npc_id: &str) -> StopVoiceCapture {
+        self.captures.remove(npc_id);
+        StopVoiceCapture {
+            npc_id: npc_id.to_string(),
+            player_uuids: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::PlayerSnapshot;
+
+    fn frame(npc_id: &str, player_uuid: &str) -> VoicePcmFrame {
+        VoicePcmFrame {
+            npc_id: npc_id.to_string(),
+            player_uuid: player_uuid.to_string(),
+            pcm_data: vec![0; 640].into(),
+            ..Default::default()
+        }
+    }
+
+    fn consent(player_uuid: &str, consent: VoiceConsent) -> VoiceConsentObservation {
+        VoiceConsentObservation {
+            player_uuid: player_uuid.to_string(),
+            consent: consent as i32,
+            timestamp_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_consent_required() {
+        let mut consents = VoiceConsents::default();
+        // Without a policy every player may be heard
+        assert!(consents.admit(&frame("guide_01", "alice"), 0));
+
+        consents.on_hello(&Hello {
+            voice_consent_required: true,
+            ..Default::default()
+        });
+        consents.observe_tick(&WorldTick {
+            nearby_players: vec![
+                PlayerSnapshot {
+                    player_uuid: "alice".to_string(),
+                    voice_consent: VoiceConsent::Granted as i32,
+                    ..Default::default()
+                },
+                PlayerSnapshot {
+                    player_uuid: "bob".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        });
+        assert_eq!(consents.consent("bob"), VoiceConsent::Unspecified);
+        assert!(consents.admit(&frame("guide_01", "alice"), 0));
+        assert!(!consents.admit(&frame("guide_01", "bob"), 0));
+
+        // Withdrawn: frames still in flight are dropped
+        assert!(consents
+            .observe(&consent("alice", VoiceConsent::Denied))
+            .is_empty());
+        assert!(!consents.admit(&frame("guide_01", "alice"), 0));
+        assert_eq!(consents.dropped(), 2);
+    }
+
+    #[test]
+    fn test_capture_on_request() {
+        let mut consents = VoiceConsents::default();
+        consents.on_hello(&Hello {
+            voice_consent_required: true,
+            voice_capture_on_request: true,
+            ..Default::default()
+        });
+        consents.observe(&consent("alice", VoiceConsent::Granted));
+        consents.observe(&consent("bob", VoiceConsent::Denied));
+        assert!(!consents.admit(&frame("guide_01", "alice"), 0));
+
+        // Players who did not agree are never asked for
+        assert_eq!(consents.request("guide_01", ["bob"], 0, 0), None);
+        let request = consents
+            .request("guide_01", ["alice", "bob"], 10_000, 0)
+            .unwrap();
+        assert_eq!(request.player_uuids, vec!["alice".to_string()]);
+        assert!(consents.admit(&frame("guide_01", "alice"), 5_000));
+        assert!(!consents.admit(&frame("guard_01", "alice"), 5_000));
+        // The plugin stopped by itself
+        assert!(!consents.admit(&frame("guide_01", "alice"), 10_000));
+
+        // Withdrawing consent stops the NPCs listening to the player
+        consents.request("guide_01", ["alice"], 0, 20_000);
+        let stops = consents.observe(&consent("alice", VoiceConsent::Denied));
+        assert_eq!(stops.len(), 1);
+        assert_eq!(stops[0].npc_id, "guide_01");
+        assert_eq!(stops[0].player_uuids, vec!["alice".to_string()]);
+        consents.observe(&consent("alice", VoiceConsent::Granted));
+        assert!(!consents.admit(&frame("guide_01", "alice"), 20_000));
+
+        // A capture of everyone nearby, until stopped
+        consents.request("guide_01", Vec::<String>::new(), 0, 30_000);
+        assert!(consents.admit(&frame("guide_01", "alice"), 90_000));
+        assert!(consents.stop("guide_01").player_uuids.is_empty());
+        assert!(!consents.admit(&frame("guide_01", "alice"), 90_000));
+    }
+}
@@ -0,0 +1,456 @@
+//! Token-budgeted prompt context for LLM daemons.
+//!
+//! Every LLM daemon turns the same state into text before each completion:
+//! who the NPC is and how it is doing, what is around it, what it
+//! remembers, how it feels about players and what was just said.
+//! [`ContextBuilder`] collects that into named [`Section`]s from the
+//! [`WorldModel`], the NPC's memory map (as in `NpcStateSnapshot.memory`),
+//! its [`Relationship`]s and a [`DialogueSession`], and renders them as
+//! JSON or through a template. When the result is over the token budget,
+//! a [`Truncation`] strategy drops items one at a time until it fits:
+//!
+//! ```ignore
+//! let prompt = ContextBuilder::new(&npc, &world)
+//!     .surroundings(16.0)
+//!     .memory(&memory)
+//!     .relationships(&reputation.relationships(&npc.npc_id))
+//!     .conversation(session)
+//!     .budget(800)
+//!     .render(&Format::Template(PERSONA));
+//! ```
+//!
+//! Tokens are estimated at four characters each; models differ, so leave
+//! headroom in the budget.
+
+use std::collections::BTreeMap;
+
+use serde_json::{json, Map, Value};
+
+use crate::agent::AgentMessage;
+use crate::conversation::{DialogueSession, Speaker};
+use crate::geom;
+use crate::npc_society::v1::{NpcSnapshot, Relationship};
+use crate::world_model::WorldModel;
+
+/// Section of nearby players and entities
+pub const SURROUNDINGS: &str = "surroundings";
+/// Section of the NPC's memory
+pub const MEMORY: &str = "memory";
+/// Section of the NPC's standing with players
+pub const RELATIONSHIPS: &str = "relationships";
+/// Section of the current dialogue
+pub const CONVERSATION: &str = "conversation";
+
+/// Rough token count of `text`: four characters a token
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// A named list of prompt lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// Name in the JSON output and the template placeholder `{name}`
+    pub name: String,
+    /// Higher is kept longer by [`LowestPriorityFirst`]
+    pub priority: u8,
+    /// Lines, oldest or least important first
+    pub items: Vec<String>,
+}
+
+/// Picks what to drop from an over-budget context
+pub trait Truncation: Send + Sync {
+    /// The (section index, item index) to drop next; None when there is
+    /// nothing left to drop
+    fn next_drop(&self, sections: &[Section]) -> Option<(usize, usize)>;
+}
+
+/// Drop the first item of the lowest-priority section that has any, so
+/// a conversation loses its oldest turns before the memory loses anything
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LowestPriorityFirst;
+
+impl Truncation for LowestPriorityFirst {
+    fn next_drop(&self, sections: &[Section]) -> Option<(usize, usize)> {
+        sections
+            .iter()
+            .enumerate()
+            .filter(|(_, section)| !section.items.is_empty())
+            .min_by_key(|(_, section)| section.priority)
+            .map(|(index, _)| (index, 0))
+    }
+}
+
+/// Drop the first item of whichever section takes the most tokens, so
+/// every section keeps some of its lines
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LargestFirst;
+
+impl Truncation for LargestFirst {
+    fn next_drop(&self, sections: &[Section]) -> Option<(usize, usize)> {
+        sections
+            .iter()
+            .enumerate()
+            .filter(|(_, section)| !section.items.is_empty())
+            .max_by_key(|(_, section)| {
+                section
+                    .items
+                    .iter()
+                    .map(|i| estimate_tokens(i))
+                    .sum::<usize>()
+            })
+            .map(|(index, _)| (index, 0))
+    }
+}
+
+/// How [`ContextBuilder::render`] lays out the context
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// `{"npc": ..., "<section>": [...], ...}`
+    Json,
+    /// The text with `{npc}` and `{<section>}` replaced by their lines,
+    /// one `- item` per line; placeholders of empty sections become "(none)"
+    Template(String),
+}
+
+/// Collects and renders one NPC's prompt context
+#[derive(Debug)]
+pub struct ContextBuilder<'a> {
+    npc: &'a NpcSnapshot,
+    world: &'a WorldModel,
+    sections: Vec<Section>,
+    budget: Option<usize>,
+}
+
+impl<'a> ContextBuilder<'a> {
+    /// A context about `npc`, looking up players and entities in `world`
+    pub fn new(npc: &'a NpcSnapshot, world: &'a WorldModel) -> Self {
+        Self {
+            npc,
+            world,
+            sections: Vec::new(),
+            budget: None,
+        }
+    }
+
+    /// Add a section of `items` (replacing one of the same name)
+    pub fn section(mut self, name: &str, priority: u8, items: Vec<String>) -> Self {
+        self.sections.retain(|section| section.name != name);
+        self.sections.push(Section {
+            name: name.to_string(),
+            priority,
+            items,
+        });
+        self
+    }
+
+    /// Players and entities the latest WorldTick reported within `radius`,
+    /// furthest first (priority 2)
+    pub fn surroundings(self, radius: f64) -> Self {
+        let Some(position) = &self.npc.position else {
+            return self.section(SURROUNDINGS, 2, Vec::new());
+        };
+        let mut near: Vec<(f64, String)> = Vec::new();
+        for player in self.world.players_within(position, radius) {
+            let Some(at) = &player.position else { continue };
+            near.push((
+                geom::distance(position, at),
+                format!(
+                    "player {} ({:.0} blocks away)",
+                    player.player_name,
+                    geom::distance(position, at)
+                ),
+            ));
+        }
+        for entity in self.world.entities_within(position, radius) {
+            let Some(at) = &entity.position else { continue };
+            let name = if entity.custom_name.is_empty() {
+                entity.entity_type.clone()
+            } else {
+                format!("{} \"{}\"", entity.entity_type, entity.custom_name)
+            };
+            near.push((
+                geom::distance(position, at),
+                format!("{} ({:.0} blocks away)", name, geom::distance(position, at)),
+            ));
+        }
+        near.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        let items = near.into_iter().map(|(_, line)| line).collect();
+        self.section(SURROUNDINGS, 2, items)
+    }
+
+    /// The NPC's memory, one `key: value` per entry (priority 3)
+    pub fn memory(self, memory: &BTreeMap<String, String>) -> Self {
+        let items = memory
+            .iter()
+            .map(|(key, value)| format!("{key}: {value}"))
+            .collect();
+        self.section(MEMORY, 3, items)
+    }
+
+    /// How the NPC feels about players, weakest feelings first (priority 1).
+    /// Players are named as last seen in the world model.
+    pub fn relationships(self, relationships: &[Relationship]) -> Self {
+        let mut sorted: Vec<_> = relationships.iter().collect();
+        sorted.sort_by(|a, b| a.score.abs().total_cmp(&b.score.abs()));
+        let items = sorted
+            .into_iter()
+            .map(|relationship| {
+                let name = self
+                    .world
+                    .last_seen_player(&relationship.player_uuid)
+                    .map_or(relationship.player_uuid.as_str(), |s| {
+                        s.snapshot.player_name.as_str()
+                    });
+                format!(
+                    "{}: {} ({:+.0})",
+                    name,
+                    attitude(relationship.score),
+                    relationship.score
+                )
+            })
+            .collect();
+        self.section(RELATIONSHIPS, 1, items)
+    }
+
+    /// The turns of `session`, oldest first (priority 0)
+    pub fn conversation(self, session: &DialogueSession) -> Self {
+        let player = self
+            .world
+            .last_seen_player(&session.player_uuid)
+            .map_or("Player".to_string(), |s| s.snapshot.player_name.clone());
+        let items = session
+            .turns
+            .iter()
+            .map(|turn| match turn.speaker {
+                Speaker::Player => format!("{}: {}", player, turn.text),
+                Speaker::Npc => format!("You: {}", turn.text),
+            })
+            .collect();
+        self.section(CONVERSATION, 0, items)
+    }
+
+    /// Keep the rendered context within `tokens` (default: unlimited)
+    pub fn budget(mut self, tokens: usize) -> Self {
+        self.budget = Some(tokens);
+        self
+    }
+
+    /// Render with [`LowestPriorityFirst`] truncation
+    pub fn render(&self, format: &Format) -> String {
+        self.render_with(format, &LowestPriorityFirst)
+    }
+
+    /// Render, dropping the items `truncation` picks until the budget is
+    /// met. The NPC line and the template text are never dropped.
+    pub fn render_with(&self, format: &Format, truncation: &dyn Truncation) -> String {
+        let mut sections = self.sections.clone();
+        loop {
+            let text = self.layout(&sections, format);
+            if self
+                .budget
+                .is_none_or(|budget| estimate_tokens(&text) <= budget)
+            {
+                return text;
+            }
+            match truncation.next_drop(&sections) {
+                Some((section, item)) => {
+                    sections[section].items.remove(item);
+                }
+                None => return text,
+            }
+        }
+    }
+
+    /// [`Self::render`] as a system message
+    pub fn system_message(&self, format: &Format) -> AgentMessage {
+        AgentMessage::System(self.render(format))
+    }
+
+    fn layout(&self, sections: &[Section], format: &Format) -> String {
+        match format {
+            Format::Json => {
+                let mut object = Map::new();
+                object.insert("npc".to_string(), npc_json(self.npc));
+                for section in sections {
+                    object.insert(section.name.clone(), json!(section.items));
+                }
+                Value::Object(object).to_string()
+            }
+            Format::Template(template) => {
+                let mut text = template.replace("{npc}", &npc_line(self.npc));
+                for section in sections {
+                    let lines = if section.items.is_empty() {
+                        "(none)".to_string()
+                    } else {
+                        section
+                            .items
+                            .iter()
+                            .map(|item| format!("- {item}"))
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    };
+                    text = text.replace(&format!("{{{}}}", section.name), &lines);
+                }
+                text
+            }
+        }
+    }
+}
+
+/// Word for a reputation score
+fn attitude(score: f32) -> &'static str {
+    match score {
+        s if s <= -50.0 => "hostile",
+        s if s < -10.0 => "wary",
+        s if s < 10.0 => "neutral",
+        s if s < 50.0 => "friendly",
+        _ => "devoted",
+    }
+}
+
+fn npc_json(npc: &NpcSnapshot) -> Value {
+    json!({
+        "npc_id": npc.npc_id,
+        "position": npc.position.as_ref().map(|p| json!([p.world, p.x.round(), p.y.round(), p.z.round()])),
+        "health": npc.health_norm,
+        "hunger": npc.hunger_norm,
+        "in_combat": npc.in_combat,
+        "held_item": npc.held_item,
+        "activity": npc.current_activity,
+    })
+}
+
+fn npc_line(npc: &NpcSnapshot) -> String {
+    let mut line = npc.npc_id.clone();
+    if let Some(p) = &npc.position {
+        line += &format!(" at {} {:.0} {:.0} {:.0}", p.world, p.x, p.y, p.z);
+    }
+    line += &format!(
+        ", health {:.0}%, hunger {:.0}%",
+        npc.health_norm * 100.0,
+        npc.hunger_norm * 100.0
+    );
+    if npc.in_combat {
+        line += ", in combat";
+    }
+    if !npc.held_item.is_empty() {
+        line += &format!(", holding {}", npc.held_item);
+    }
+    if !npc.current_activity.is_empty() {
+        line += &format!(", {}", npc.current_activity);
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::conversation::Turn;
+    use crate::npc_society::v1::{PlayerSnapshot, Position, WorldTick};
+
+    fn at(x: f64) -> Option<Position> {
+        Some(Position {
+            world: "world".to_string(),
+            x,
+            y: 64.0,
+            ..Default::default()
+        })
+    }
+
+    fn fixture() -> (NpcSnapshot, WorldModel, DialogueSession) {
+        let npc = NpcSnapshot {
+            npc_id: "smith".to_string(),
+            position: at(0.0),
+            health_norm: 0.9,
+            hunger_norm: 0.5,
+            ..Default::default()
+        };
+        let mut world = WorldModel::default();
+        world.ingest_tick(&WorldTick {
+            timestamp_ms: 1,
+            nearby_players: vec![PlayerSnapshot {
+                player_uuid: "p1".to_string(),
+                player_name: "Alex".to_string(),
+                position: at(3.0),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        let turn = |speaker, text: &str| Turn {
+            speaker,
+            text: text.to_string(),
+            timestamp_ms: 1,
+        };
+        let session = DialogueSession {
+            npc_id: "smith".to_string(),
+            player_uuid: "p1".to_string(),
+            turns: VecDeque::from([
+                turn(Speaker::Player, "hello there, smith"),
+                turn(Speaker::Npc, "Welcome to my forge"),
+                turn(Speaker::Player, "can you fix my sword?"),
+            ]),
+            ..Default::default()
+        };
+        (npc, world, session)
+    }
+
+    #[test]
+    fn test_template_and_json() {
+        let (npc, world, session) = fixture();
+        let memory = BTreeMap::from([("favorite_ore".to_string(), "iron".to_string())]);
+        let relationships = [Relationship {
+            player_uuid: "p1".to_string(),
+            score: 25.0,
+            updated_at_ms: 1,
+        }];
+        let builder = ContextBuilder::new(&npc, &world)
+            .surroundings(16.0)
+            .memory(&memory)
+            .relationships(&relationships)
+            .conversation(&session);
+
+        let text = builder.render(&Format::Template(
+            "You are {npc}.\nNearby:\n{surroundings}\nYou know:\n{memory}\n{relationships}\n{conversation}".to_string(),
+        ));
+        assert!(text.starts_with("You are smith at world 0 64 0, health 90%, hunger 50%."));
+        assert!(text.contains("- player Alex (3 blocks away)"));
+        assert!(text.contains("- favorite_ore: iron"));
+        assert!(text.contains("- Alex: friendly (+25)"));
+        assert!(text.ends_with("- You: Welcome to my forge\n- Alex: can you fix my sword?"));
+
+        let value: Value = serde_json::from_str(&builder.render(&Format::Json)).unwrap();
+        assert_eq!(value["npc"]["npc_id"], "smith");
+        assert_eq!(value[CONVERSATION].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_budget_truncation() {
+        let (npc, world, session) = fixture();
+        let memory = BTreeMap::from([
+            ("a".to_string(), "x".repeat(80)),
+            ("b".to_string(), "y".repeat(80)),
+        ]);
+        let template = Format::Template("{memory}\n{conversation}".to_string());
+        let builder = ContextBuilder::new(&npc, &world)
+            .memory(&memory)
+            .conversation(&session)
+            .budget(50);
+
+        // The conversation has the lowest priority: its oldest turns go first
+        let text = builder.render(&template);
+        assert!(estimate_tokens(&text) <= 50);
+        assert!(text.contains("- a: ") && text.contains("- b: "));
+        assert!(!text.contains("hello there"));
+
+        // The memory is the largest section, so it loses a line first
+        let text = builder.render_with(&template, &LargestFirst);
+        assert!(!text.contains("- a: "));
+        assert!(text.contains("hello there"));
+
+        // Nothing left to drop: the rest is returned over budget
+        let tiny = ContextBuilder::new(&npc, &world).budget(1);
+        assert!(tiny.render(&Format::Json).contains("smith"));
+    }
+}
@@ -0,0 +1,817 @@
+//! Per-speaker voice sessions and conversation context.
+//!
+//! [`ConversationTracker`] takes raw `VoicePcmFrame`s, groups them by
+//! (npc_id, player_uuid), and runs each speaker through the jitter buffer
+//! ([`crate::jitter`]), 16kHz conversion ([`crate::audio`]) and VAD
+//! ([`crate::vad`]). It also remembers who has been talking near each NPC
+//! and what they said, so daemon logic can ask for a [`ConversationContext`]
+//! instead of juggling frames from several players at once. With
+//! [`ConversationConfig::mix`] set, utterances come from one
+//! [`VoiceMixer`] feed per NPC instead of one per speaker.
+//!
+//! [`ConversationManager`] keeps the dialogue itself: what each player said
+//! to each NPC (chat or transcribed voice) and what the NPC answered
+//! (SpeakDirectives), as one [`DialogueSession`] per (npc_id, player_uuid).
+//! Sessions end after a quiet spell, so an NPC remembers the last few
+//! exchanges with a player but not yesterday's.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::npc_society::v1::{ChatObservation, SpeakDirective};
+#[cfg(feature = "voice")]
+use crate::{
+    audio,
+    jitter::{AudioFrame, AudioStreamAssembler},
+    mixer::{MixerConfig, VoiceMixer},
+    npc_society::v1::VoicePcmFrame,
+    types::{NpcId, PlayerUuid},
+    vad::{EnergyVad, VadConfig, VadEvent},
+};
+
+/// Length of the frames the VAD counts (the protocol's 20ms)
+#[cfg(feature = "voice")]
+const VAD_FRAME_MS: i64 = 20;
+
+/// Tuning for [`ConversationTracker`]
+#[cfg(feature = "voice")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConversationConfig {
+    /// Utterances remembered per NPC
+    pub history_len: usize,
+    /// How long a player counts as a participant after they were last heard
+    pub participant_timeout_ms: i64,
+    /// Utterance segmentation for each speaker
+    pub vad: VadConfig,
+    /// Mix the speakers around each NPC into one utterance stream instead
+    /// of segmenting each on its own (None = per speaker)
+    pub mix: Option<MixerConfig>,
+}
+
+#[cfg(feature = "voice")]
+impl Default for ConversationConfig {
+    fn default() -> Self {
+        Self {
+            history_len: 10,
+            participant_timeout_ms: 60_000,
+            vad: VadConfig::default(),
+            mix: None,
+        }
+    }
+}
+
+/// Speech activity reported by [`ConversationTracker::push_frame`]
+#[cfg(feature = "voice")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpeakerEvent {
+    /// A player started talking near an NPC
+    Started { npc_id: String, player_uuid: String },
+    /// A player finished an utterance; `audio` is 16kHz mono f32, ready for ASR
+    Utterance {
+        npc_id: String,
+        player_uuid: String,
+        audio: Vec<f32>,
+    },
+}
+
+/// Something a player said near an NPC
+#[cfg(feature = "voice")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Utterance {
+    /// UUID of the player who spoke
+    pub player_uuid: String,
+    /// What they said (e.g. an ASR transcript)
+    pub text: String,
+    /// Unix timestamp in milliseconds
+    pub timestamp_ms: i64,
+}
+
+/// Who is talking to an NPC and what was said recently
+#[cfg(feature = "voice")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConversationContext {
+    /// The NPC being talked to
+    pub npc_id: String,
+    /// Players heard within the participant timeout, sorted
+    pub participants: Vec<String>,
+    /// Players speaking right now, sorted
+    pub speaking: Vec<String>,
+    /// Most recent utterances, oldest first
+    pub recent: Vec<Utterance>,
+}
+
+#[cfg(feature = "voice")]
+#[derive(Debug)]
+struct SpeakerSession {
+    vad: EnergyVad,
+    /// When the last frame arrived, on the daemon's clock
+    last_heard_ms: i64,
+    /// Timestamp of the newest frame, on the plugin's clock (for the mixer)
+    last_frame_ms: i64,
+    sample_rate_hz: u32,
+}
+
+#[cfg(feature = "voice")]
+#[derive(Debug, Default)]
+struct NpcConversation {
+    speakers: HashMap<String, SpeakerSession>,
+    history: VecDeque<Utterance>,
+}
+
+/// Groups voice frames into per-speaker sessions for every NPC.
+#[cfg(feature = "voice")]
+#[derive(Debug, Default)]
+pub struct ConversationTracker {
+    config: ConversationConfig,
+    voice: AudioStreamAssembler,
+    npcs: HashMap<String, NpcConversation>,
+    mixer: Option<VoiceMixer>,
+}
+
+#[cfg(feature = "voice")]
+impl ConversationTracker {
+    /// Create a tracker with the given tuning
+    pub fn new(config: ConversationConfig) -> Self {
+        Self {
+            config,
+            mixer: config.mix.map(VoiceMixer::new),
+            ..Self::default()
+        }
+    }
+
+    /// Whether utterances come from the mixed feed
+    pub fn is_mixing(&self) -> bool {
+        self.mixer.is_some()
+    }
+
+    /// Set a speaker's gain in the mix (no-op unless mixing)
+    pub fn set_gain(&mut self, npc: &NpcId, player: &PlayerUuid, gain: f32) {
+        if let Some(mixer) = &mut self.mixer {
+            mixer.set_gain(npc.as_str(), player.as_str(), gain);
+        }
+    }
+
+    /// Record what `npc` says from `start_ms` on (the voice frames' clock),
+    /// 16kHz mono, so its echo in players' microphones is suppressed (no-op
+    /// unless mixing)
+    pub fn play(&mut self, npc: &NpcId, samples: &[f32], start_ms: i64) {
+        if let Some(mixer) = &mut self.mixer {
+            mixer.play(npc.as_str(), samples, start_ms);
+        }
+    }
+
+    /// Process one voice frame, received at `now_ms` on the daemon's clock.
+    /// Frames must be S16LE; decode Opus first.
+    pub fn push_frame(&mut self, frame: VoicePcmFrame, now_ms: i64) -> Vec<SpeakerEvent> {
+        let sample_rate = match frame.sample_rate_hz {
+            0 => audio::PROTOCOL_SAMPLE_RATE_HZ,
+            hz => hz as u32,
+        };
+        let vad_config = self.config.vad;
+        let session = self
+            .npcs
+            .entry(frame.npc_id.clone())
+            .or_default()
+            .speakers
+            .entry(frame.player_uuid.clone())
+            .or_insert_with(|| SpeakerSession {
+                vad: EnergyVad::new(vad_config),
+                last_heard_ms: now_ms,
+                last_frame_ms: frame.timestamp_ms,
+                sample_rate_hz: sample_rate,
+            });
+        session.last_heard_ms = session.last_heard_ms.max(now_ms);
+        session.last_frame_ms = session.last_frame_ms.max(frame.timestamp_ms);
+        session.sample_rate_hz = sample_rate;
+
+        let (_, released) = self.voice.push(
+            &stream_key(&frame.npc_id, &frame.player_uuid),
+            AudioFrame {
+                sequence: frame.sequence,
+                pcm_data: frame.pcm_data,
+                is_final: false,
+            },
+        );
+        segment(
+            session,
+            self.mixer.as_mut(),
+            &frame.npc_id,
+            &frame.player_uuid,
+            frame.timestamp_ms,
+            released,
+        )
+    }
+
+    /// Remember what a player said (e.g. once ASR has transcribed an utterance).
+    pub fn record_utterance(&mut self, npc: &NpcId, player: &PlayerUuid, text: &str, timestamp_ms: i64) {
+        let history_len = self.config.history_len;
+        let conversation = self.npcs.entry(npc.to_string()).or_default();
+        conversation.history.push_back(Utterance {
+            player_uuid: player.to_string(),
+            text: text.to_string(),
+            timestamp_ms,
+        });
+        while conversation.history.len() > history_len {
+            conversation.history.pop_front();
+        }
+    }
+
+    /// Current conversation around `npc_id` at `now_ms` (the daemon's clock)
+    pub fn context(&self, npc_id: &str, now_ms: i64) -> ConversationContext {
+        let Some(conversation) = self.npcs.get(npc_id) else {
+            return ConversationContext {
+                npc_id: npc_id.to_string(),
+                ..ConversationContext::default()
+            };
+        };
+
+        let mut participants: Vec<String> = conversation
+            .speakers
+            .iter()
+            .filter(|(_, s)| now_ms - s.last_heard_ms <= self.config.participant_timeout_ms)
+            .map(|(uuid, _)| uuid.clone())
+            .collect();
+        participants.sort();
+
+        let mut speaking: Vec<String> = conversation
+            .speakers
+            .iter()
+            .filter(|(_, s)| s.vad.is_speaking())
+            .map(|(uuid, _)| uuid.clone())
+            .collect();
+        speaking.sort();
+
+        ConversationContext {
+            npc_id: npc_id.to_string(),
+            participants,
+            speaking,
+            recent: conversation.history.iter().cloned().collect(),
+        }
+    }
+
+    /// End a speaker's session (e.g. the player left), returning any
+    /// utterance that was still in progress.
+    pub fn end_speaker(&mut self, npc: &NpcId, player: &PlayerUuid) -> Option<SpeakerEvent> {
+        let (npc_id, player_uuid) = (npc.as_str(), player.as_str());
+        self.voice.remove(&stream_key(npc_id, player_uuid));
+        let mut session = self.npcs.get_mut(npc_id)?.speakers.remove(player_uuid)?;
+        if let Some(mixer) = &mut self.mixer {
+            // The mix goes on with the others
+            mixer.remove(npc_id, player_uuid);
+            return None;
+        }
+        match session.vad.finish() {
+            Some(VadEvent::UtteranceEnd { audio }) => Some(SpeakerEvent::Utterance {
+                npc_id: npc_id.to_string(),
+                player_uuid: player_uuid.to_string(),
+                audio,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Drop every session of `player` (e.g. they withdrew voice consent).
+    /// Unlike [`end_speaker`](Self::end_speaker), the utterance in progress
+    /// and the frames still in the jitter buffer are discarded, not
+    /// returned for transcription.
+    pub fn forget_player(&mut self, player: &PlayerUuid) {
+        let player_uuid = player.as_str();
+        for (npc_id, conversation) in &mut self.npcs {
+            conversation.speakers.remove(player_uuid);
+            self.voice.remove(&stream_key(npc_id, player_uuid));
+        }
+        if let Some(mixer) = &mut self.mixer {
+            mixer.forget(player_uuid);
+        }
+    }
+
+    /// End the utterances of speakers who stopped sending frames and drop
+    /// the sessions of speakers not heard for the participant timeout.
+    ///
+    /// The VAD only ends an utterance on silent frames, and a player who
+    /// releases push-to-talk or walks out of range sends none. Once nothing
+    /// came for the VAD hangover, the frames still waiting in the jitter
+    /// buffer are segmented and the utterance in progress is returned.
+    /// Call this regularly, e.g. on every WorldTick, with `now_ms` on the
+    /// same clock as [`push_frame`](Self::push_frame).
+    pub fn prune(&mut self, now_ms: i64) -> Vec<SpeakerEvent> {
+        let hangover_ms = self.config.vad.hangover_frames as i64 * VAD_FRAME_MS;
+        let timeout = self.config.participant_timeout_ms;
+        let mut events = Vec::new();
+        for (npc_id, conversation) in &mut self.npcs {
+            for (player_uuid, session) in &mut conversation.speakers {
+                if now_ms - session.last_heard_ms <= hangover_ms {
+                    continue;
+                }
+                let released = self.voice.flush(&stream_key(npc_id, player_uuid));
+                let last_frame_ms = session.last_frame_ms;
+                events.extend(segment(session, self.mixer.as_mut(), npc_id, player_uuid, last_frame_ms, released));
+                let ended = session.vad.finish();
+                // The mix has its own VAD; the others go on in it
+                if let (Some(VadEvent::UtteranceEnd { audio }), None) = (ended, &self.mixer) {
+                    events.push(SpeakerEvent::Utterance {
+                        npc_id: npc_id.clone(),
+                        player_uuid: player_uuid.clone(),
+                        audio,
+                    });
+                }
+            }
+
+            let voice = &mut self.voice;
+            let mut mixer = self.mixer.as_mut();
+            conversation.speakers.retain(|player_uuid, s| {
+                let keep = now_ms - s.last_heard_ms <= timeout;
+                if !keep {
+                    voice.remove(&stream_key(npc_id, player_uuid));
+                    if let Some(mixer) = mixer.as_deref_mut() {
+                        mixer.remove(npc_id, player_uuid);
+                    }
+                }
+                keep
+            });
+        }
+        events
+    }
+}
+
+/// Tuning for [`ConversationManager`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionConfig {
+    /// A session ends this long after its last turn
+    pub session_ttl_ms: i64,
+    /// Turns kept per session, oldest dropped first
+    pub max_turns: usize,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            session_ttl_ms: 5 * 60_000,
+            max_turns: 20,
+        }
+    }
+}
+
+/// Who spoke a [`Turn`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speaker {
+    /// The player of the session
+    Player,
+    /// The NPC of the session
+    Npc,
+}
+
+/// One message of a dialogue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Turn {
+    /// Who said it
+    pub speaker: Speaker,
+    /// What was said
+    pub text: String,
+    /// Unix timestamp in milliseconds
+    pub timestamp_ms: i64,
+}
+
+/// The dialogue between one NPC and one player
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DialogueSession {
+    /// The NPC
+    pub npc_id: String,
+    /// The player
+    pub player_uuid: String,
+    /// When the session started (Unix ms)
+    pub started_at_ms: i64,
+    /// Most recent turns, oldest first
+    pub turns: VecDeque<Turn>,
+    /// Dialogue state of the daemon's choosing, e.g. `topic` or `quest_step`
+    pub state: HashMap<String, String>,
+}
+
+impl DialogueSession {
+    /// When the last turn happened (Unix ms)
+    pub fn last_turn_ms(&self) -> i64 {
+        self.turns.back().map_or(self.started_at_ms, |t| t.timestamp_ms)
+    }
+
+    /// Turns spoken by the player among `turns`
+    pub fn player_turns(&self) -> usize {
+        self.turns.iter().filter(|t| t.speaker == Speaker::Player).count()
+    }
+}
+
+/// Dialogue sessions by (npc_id, player_uuid).
+#[derive(Debug, Default)]
+pub struct ConversationManager {
+    config: SessionConfig,
+    sessions: HashMap<(String, String), DialogueSession>,
+}
+
+impl ConversationManager {
+    /// Create a manager with the given tuning
+    pub fn new(config: SessionConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Record a player's chat message; returns the session it belongs to
+    pub fn on_chat(&mut self, chat: &ChatObservation) -> &DialogueSession {
+        self.record(&chat.npc_id, &chat.player_uuid, Speaker::Player, &chat.message, chat.timestamp_ms)
+    }
+
+    /// Record an NPC's reply in the sessions of the players it is
+    /// addressed to, or of every player in a live session with the NPC
+    /// if it is not addressed
+    pub fn on_speak(&mut self, speak: &SpeakDirective, now_ms: i64) {
+        let players: Vec<String> = if speak.target_player_uuids.is_empty() {
+            self.sessions_of(&speak.npc_id, now_ms)
+                .map(|s| s.player_uuid.clone())
+                .collect()
+        } else {
+            speak.target_player_uuids.clone()
+        };
+        for player_uuid in players {
+            self.record(&speak.npc_id, &player_uuid, Speaker::Npc, &speak.text, now_ms);
+        }
+    }
+
+    /// Record a turn, starting a new session if there is no live one
+    pub fn record(
+        &mut self,
+        npc_id: &str,
+        player_uuid: &str,
+        speaker: Speaker,
+        text: &str,
+        timestamp_ms: i64,
+    ) -> &DialogueSession {
+        let config = self.config;
+        let session = self
+            .sessions
+            .entry((npc_id.to_string(), player_uuid.to_string()))
+            .or_default();
+        if session.turns.is_empty() || timestamp_ms - session.last_turn_ms() > config.session_ttl_ms {
+            *session = DialogueSession {
+                npc_id: npc_id.to_string(),
+                player_uuid: player_uuid.to_string(),
+                started_at_ms: timestamp_ms,
+                ..DialogueSession::default()
+            };
+        }
+        session.turns.push_back(Turn {
+            speaker,
+            text: text.to_string(),
+            timestamp_ms,
+        });
+        while session.turns.len() > config.max_turns {
+            session.turns.pop_front();
+        }
+        session
+    }
+
+    /// The live session between `npc_id` and `player_uuid`, if any
+    pub fn session(&self, npc_id: &str, player_uuid: &str, now_ms: i64) -> Option<&DialogueSession> {
+        self.sessions
+            .get(&(npc_id.to_string(), player_uuid.to_string()))
+            .filter(|s| self.is_live(s, now_ms))
+    }
+
+    /// The live session, mutable to update its dialogue state
+    pub fn session_mut(
+        &mut self,
+        npc_id: &str,
+        player_uuid: &str,
+        now_ms: i64,
+    ) -> Option<&mut DialogueSession> {
+        let ttl = self.config.session_ttl_ms;
+        self.sessions
+            .get_mut(&(npc_id.to_string(), player_uuid.to_string()))
+            .filter(|s| now_ms - s.last_turn_ms() <= ttl)
+    }
+
+    /// Live sessions of `npc_id`
+    pub fn sessions_of<'a>(
+        &'a self,
+        npc_id: &'a str,
+        now_ms: i64,
+    ) -> impl Iterator<Item = &'a DialogueSession> + 'a {
+        self.sessions
+            .values()
+            .filter(move |s| s.npc_id == npc_id && self.is_live(s, now_ms))
+    }
+
+    /// Remove sessions past their TTL and return them, e.g. to summarize
+    /// into long-term memory
+    pub fn prune(&mut self, now_ms: i64) -> Vec<DialogueSession> {
+        let ttl = self.config.session_ttl_ms;
+        let expired: Vec<(String, String)> = self
+            .sessions
+            .iter()
+            .filter(|(_, s)| now_ms - s.last_turn_ms() > ttl)
+            .map(|(key, _)| key.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|key| self.sessions.remove(&key))
+            .collect()
+    }
+
+    fn is_live(&self, session: &DialogueSession, now_ms: i64) -> bool {
+        now_ms - session.last_turn_ms() <= self.config.session_ttl_ms
+    }
+}
+
+/// Convert a speaker's ordered frames to 16kHz and run them through its
+/// VAD, or into the NPC's mix
+#[cfg(feature = "voice")]
+fn segment(
+    session: &mut SpeakerSession,
+    mut mixer: Option<&mut VoiceMixer>,
+    npc_id: &str,
+    player_uuid: &str,
+    timestamp_ms: i64,
+    released: Vec<AudioFrame>,
+) -> Vec<SpeakerEvent> {
+    let mut events = Vec::new();
+    for ordered in released {
+        let samples = audio::s16le_to_f32(&ordered.pcm_data);
+        let samples = audio::resample(&samples, session.sample_rate_hz, audio::ASR_SAMPLE_RATE_HZ);
+        let event = session.vad.process(&samples);
+        // The speaker's own VAD still says who is talking
+        if let Some(mixer) = mixer.as_deref_mut() {
+            events.extend(mixer.push(npc_id, player_uuid, samples, timestamp_ms));
+            continue;
+        }
+        match event {
+            Some(VadEvent::UtteranceStart) => events.push(SpeakerEvent::Started {
+                npc_id: npc_id.to_string(),
+                player_uuid: player_uuid.to_string(),
+            }),
+            Some(VadEvent::UtteranceEnd { audio }) => events.push(SpeakerEvent::Utterance {
+                npc_id: npc_id.to_string(),
+                player_uuid: player_uuid.to_string(),
+                audio,
+            }),
+            None => {}
+        }
+    }
+    events
+}
+
+#[cfg(feature = "voice")]
+fn stream_key(npc_id: &str, player_uuid: &str) -> String {
+    format!("{npc_id}/{player_uuid}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// When frame `sequence` is taken, 20ms apart
+    #[cfg(feature = "voice")]
+    fn at(sequence: u64) -> i64 {
+        1_000 + sequence as i64 * 20
+    }
+
+    /// 20ms at 16kHz, so no resampling is involved
+    #[cfg(feature = "voice")]
+    fn frame(player: &str, sequence: u64, loud: bool) -> VoicePcmFrame {
+        let sample: i16 = if loud { 16_000 } else { 0 };
+        VoicePcmFrame {
+            npc_id: "npc".to_string(),
+            player_uuid: player.to_string(),
+            pcm_data: sample.to_le_bytes().repeat(320).into(),
+            sequence,
+            timestamp_ms: at(sequence),
+            sample_rate_hz: 16_000,
+            ..Default::default()
+        }
+    }
+
+    #[cfg(feature = "voice")]
+    fn tracker() -> ConversationTracker {
+        ConversationTracker::new(ConversationConfig {
+            history_len: 2,
+            vad: VadConfig {
+                min_speech_frames: 1,
+                hangover_frames: 2,
+                ..VadConfig::default()
+            },
+            ..ConversationConfig::default()
+        })
+    }
+
+    #[cfg(feature = "voice")]
+    #[test]
+    fn test_sessions_are_per_speaker() {
+        let mut tracker = tracker();
+
+        let events = tracker.push_frame(frame("alice", 0, true), at(0));
+        assert!(matches!(&events[..], [SpeakerEvent::Started { player_uuid, .. }] if player_uuid == "alice"));
+        tracker.push_frame(frame("bob", 0, true), at(0));
+        assert_eq!(tracker.context("npc", 1_000).speaking, vec!["alice", "bob"]);
+
+        // Alice stops; Bob keeps talking
+        tracker.push_frame(frame("alice", 1, false), at(1));
+        let events = tracker.push_frame(frame("alice", 2, false), at(2));
+        assert!(matches!(&events[..], [SpeakerEvent::Utterance { player_uuid, audio, .. }]
+            if player_uuid == "alice" && audio.len() == 3 * 320));
+        assert_eq!(tracker.context("npc", 1_040).speaking, vec!["bob"]);
+    }
+
+    #[cfg(feature = "voice")]
+    #[test]
+    fn test_context_history_and_participants() {
+        let mut tracker = tracker();
+        let alice = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+        let (npc, player) = (NpcId::new("npc").unwrap(), PlayerUuid::new(alice).unwrap());
+        tracker.push_frame(frame(alice, 0, false), at(0));
+        tracker.record_utterance(&npc, &player, "one", 1);
+        tracker.record_utterance(&npc, &player, "two", 2);
+        tracker.record_utterance(&npc, &player, "three", 3);
+
+        let context = tracker.context("npc", 2_000);
+        assert_eq!(context.participants, vec![alice]);
+        let texts: Vec<&str> = context.recent.iter().map(|u| u.text.as_str()).collect();
+        assert_eq!(texts, vec!["two", "three"]);
+
+        // Alice was last heard at 1000ms
+        tracker.prune(62_000);
+        assert!(tracker.context("npc", 62_000).participants.is_empty());
+    }
+
+    #[cfg(feature = "voice")]
+    #[test]
+    fn test_utterance_ends_when_frames_stop() {
+        let mut tracker = tracker();
+
+        // Alice talks, then releases push-to-talk: no silent frames follow.
+        // Frame 3 is still waiting in the jitter buffer for frame 2.
+        tracker.push_frame(frame("alice", 0, true), at(0));
+        tracker.push_frame(frame("alice", 1, true), at(1));
+        tracker.push_frame(frame("alice", 3, true), at(3));
+        // The last frame was taken at 1060ms; the hangover is 40ms
+        assert!(tracker.prune(1_100).is_empty());
+        assert_eq!(tracker.context("npc", 1_100).speaking, vec!["alice"]);
+
+        let events = tracker.prune(1_101);
+        assert!(matches!(&events[..], [SpeakerEvent::Utterance { player_uuid, audio, .. }]
+            if player_uuid == "alice" && audio.len() == 3 * 320));
+        let context = tracker.context("npc", 1_101);
+        assert!(context.speaking.is_empty());
+        assert_eq!(context.participants, vec!["alice"]);
+        assert!(tracker.prune(1_200).is_empty());
+
+        // Sessions past the participant timeout go, even mid-utterance
+        tracker.push_frame(frame("bob", 0, true), at(0));
+        assert!(matches!(&tracker.prune(70_000)[..], [SpeakerEvent::Utterance { player_uuid, .. }] if player_uuid == "bob"));
+        assert!(tracker.context("npc", 70_000).participants.is_empty());
+        assert!(tracker.voice.is_empty());
+    }
+
+    #[cfg(feature = "voice")]
+    #[test]
+    fn test_timeouts_use_the_daemon_clock() {
+        const HOUR_MS: i64 = 3_600_000;
+        let mut tracker = tracker();
+
+        // Alice's plugin clock is an hour behind the daemon's, Bob's an
+        // hour ahead; both send while the daemon's clock reads at(0..2)
+        for sequence in 0..2 {
+            let behind = VoicePcmFrame {
+                timestamp_ms: at(sequence) - HOUR_MS,
+                ..frame("alice", sequence, true)
+            };
+            tracker.push_frame(behind, at(sequence));
+            let ahead = VoicePcmFrame {
+                timestamp_ms: at(sequence) + HOUR_MS,
+                ..frame("bob", sequence, true)
+            };
+            tracker.push_frame(ahead, at(sequence));
+        }
+
+        // Both are within the hangover of their last frame's arrival
+        assert!(tracker.prune(at(1)).is_empty());
+        assert_eq!(tracker.context("npc", at(1)).participants, vec!["alice", "bob"]);
+
+        // Both end once nothing arrived for the hangover, whatever the skew
+        let events = tracker.prune(at(1) + 41);
+        assert_eq!(events.len(), 2);
+        assert!(tracker.prune(70_000).is_empty());
+        assert!(tracker.context("npc", 70_000).participants.is_empty());
+    }
+
+    #[cfg(feature = "voice")]
+    #[test]
+    fn test_forgotten_player_is_not_transcribed() {
+        let alice = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+        let player = PlayerUuid::new(alice).unwrap();
+
+        // Mid-utterance near two NPCs, with a frame behind a gap
+        let mut tracker = tracker();
+        for sequence in [0, 1, 3] {
+            tracker.push_frame(frame(alice, sequence, true), at(sequence));
+            tracker.push_frame(
+                VoicePcmFrame {
+                    npc_id: "npc2".to_string(),
+                    ..frame(alice, sequence, true)
+                },
+                at(sequence),
+            );
+        }
+        tracker.forget_player(&player);
+        assert!(tracker.prune(1_200).is_empty());
+        assert!(tracker.prune(70_000).is_empty());
+        for npc_id in ["npc", "npc2"] {
+            let context = tracker.context(npc_id, 1_200);
+            assert!(context.participants.is_empty() && context.speaking.is_empty());
+        }
+        assert!(tracker.voice.is_empty());
+
+        // The mix drops the utterance Alice's voice is in
+        let mut tracker = ConversationTracker::new(ConversationConfig {
+            mix: Some(MixerConfig {
+                vad: VadConfig {
+                    min_speech_frames: 1,
+                    hangover_frames: 2,
+                    ..VadConfig::default()
+                },
+                max_lag_frames: 1,
+                ..MixerConfig::default()
+            }),
+            ..ConversationConfig::default()
+        });
+        assert!(matches!(&tracker.push_frame(frame(alice, 0, true), at(0))[..], [SpeakerEvent::Started { .. }]));
+        tracker.forget_player(&player);
+        let events: Vec<SpeakerEvent> = (0..8).flat_map(|sequence| tracker.push_frame(frame("bob", sequence, false), at(sequence))).collect();
+        assert!(events.is_empty());
+    }
+
+    fn chat(player: &str, message: &str, timestamp_ms: i64) -> ChatObservation {
+        ChatObservation {
+            npc_id: "npc".to_string(),
+            player_uuid: player.to_string(),
+            message: message.to_string(),
+            timestamp_ms,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_dialogue_sessions() {
+        let mut manager = ConversationManager::new(SessionConfig {
+            session_ttl_ms: 60_000,
+            max_turns: 3,
+        });
+        manager.on_chat(&chat("alice", "hi", 0));
+        manager.on_chat(&chat("bob", "hey", 500));
+        manager.on_speak(
+            &SpeakDirective {
+                npc_id: "npc".to_string(),
+                text: "Hello, Alice!".to_string(),
+                target_player_uuids: vec!["alice".to_string()],
+                ..Default::default()
+            },
+            1_000,
+        );
+        // Unaddressed speech is heard by everyone in a session
+        manager.on_speak(
+            &SpeakDirective {
+                npc_id: "npc".to_string(),
+                text: "Welcome, all".to_string(),
+                ..Default::default()
+            },
+            2_000,
+        );
+
+        let alice = manager.session("npc", "alice", 2_000).unwrap();
+        let turns: Vec<_> = alice.turns.iter().map(|t| (t.speaker, t.text.as_str())).collect();
+        assert_eq!(
+            turns,
+            [
+                (Speaker::Player, "hi"),
+                (Speaker::Npc, "Hello, Alice!"),
+                (Speaker::Npc, "Welcome, all"),
+            ]
+        );
+        assert_eq!(manager.session("npc", "bob", 2_000).unwrap().turns.len(), 2);
+
+        manager
+            .session_mut("npc", "alice", 2_000)
+            .unwrap()
+            .state
+            .insert("topic".to_string(), "diamonds".to_string());
+        // Oldest turn dropped, state kept
+        let alice = manager.on_chat(&chat("alice", "where?", 3_000));
+        assert_eq!(alice.turns.front().unwrap().text, "Hello, Alice!");
+        assert_eq!(alice.state["topic"], "diamonds");
+
+        // Bob went quiet after the welcome: his session ends and a new one starts fresh
+        assert!(manager.session("npc", "bob", 62_500).is_none());
+        let ended = manager.prune(62_500);
+        assert_eq!(ended.len(), 1);
+        assert_eq!(ended[0].player_uuid, "bob");
+        let bob = manager.on_chat(&chat("bob", "back", 70_000));
+        assert_eq!((bob.turns.len(), bob.started_at_ms), (1, 70_000));
+        assert_eq!(manager.sessions_of("npc", 70_000).count(), 1);
+    }
+}
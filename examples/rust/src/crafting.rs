@@ -0,0 +1,370 @@
+//! Craft planning (`CraftAction`, v1.2+).
+//!
+//! The plugin crafts one item type per `CraftAction`; getting from logs to
+//! a pickaxe takes planks, then sticks, then the pickaxe. [`plan`] works
+//! that out from a [`RecipeBook`] and the NPC's inventory: it uses what the
+//! NPC already has, crafts intermediates before the items that need them,
+//! and lists raw materials that have to be gathered first. The daemon has
+//! no recipe registry, so the book is loaded from a data file (see
+//! `data/recipes.json` and [`RecipeBook::from_json`]).
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::npc_society::v1::{action_directive::Action, BlockPosition, CraftAction, ItemStack};
+
+/// One way to craft an item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipe {
+    /// Item crafted, e.g. `minecraft:stick`
+    pub output: String,
+    /// Items per craft
+    pub count: u32,
+    /// Ingredients per craft
+    pub ingredients: Vec<(String, u32)>,
+    /// Whether the recipe needs a crafting table (3x3 grid)
+    pub needs_table: bool,
+}
+
+/// A recipe file that could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecipeError(pub String);
+
+impl fmt::Display for RecipeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid recipes: {}", self.0)
+    }
+}
+
+impl std::error::Error for RecipeError {}
+
+/// Recipes by output item, one per item
+#[derive(Debug, Clone, Default)]
+pub struct RecipeBook {
+    recipes: HashMap<String, Recipe>,
+}
+
+impl RecipeBook {
+    /// Add a recipe, replacing any other recipe for its output
+    pub fn add(&mut self, recipe: Recipe) {
+        self.recipes.insert(recipe.output.clone(), recipe);
+    }
+
+    /// The recipe for `item_type`, if it can be crafted
+    pub fn get(&self, item_type: &str) -> Option<&Recipe> {
+        self.recipes.get(item_type)
+    }
+
+    /// Whether crafting `item_type` can need `item_type` again, as with
+    /// iron ingots and iron blocks
+    pub fn is_cyclic(&self, item_type: &str) -> bool {
+        let mut seen = HashSet::new();
+        let mut todo = vec![item_type];
+        while let Some(item) = todo.pop() {
+            for (ingredient, _) in self
+                .get(item)
+                .map(|r| &r.ingredients[..])
+                .unwrap_or_default()
+            {
+                if ingredient == item_type {
+                    return true;
+                }
+                if seen.insert(ingredient.as_str()) {
+                    todo.push(ingredient);
+                }
+            }
+        }
+        false
+    }
+
+    /// Number of recipes
+    pub fn len(&self) -> usize {
+        self.recipes.len()
+    }
+
+    /// Whether the book has no recipes
+    pub fn is_empty(&self) -> bool {
+        self.recipes.is_empty()
+    }
+
+    /// Load recipes from a JSON array of
+    /// `{"output": id, "count": n, "ingredients": {id: n, ...}, "table": bool}`;
+    /// `count` defaults to 1 and `table` to false
+    pub fn from_json(source: &str) -> Result<Self, RecipeError> {
+        let error = |what: String| RecipeError(what);
+        let entries: Value = serde_json::from_str(source).map_err(|e| error(e.to_string()))?;
+        let entries = entries
+            .as_array()
+            .ok_or_else(|| error("expected an array of recipes".to_string()))?;
+
+        let mut book = Self::default();
+        for (i, entry) in entries.iter().enumerate() {
+            let output = entry["output"]
+                .as_str()
+                .filter(|o| !o.is_empty())
+                .ok_or_else(|| error(format!("recipe {i}: missing output")))?;
+            let count = match &entry["count"] {
+                Value::Null => 1,
+                count => count
+                    .as_u64()
+                    .filter(|&c| c > 0)
+                    .and_then(|c| u32::try_from(c).ok())
+                    .ok_or_else(|| error(format!("{output}: count must be a positive integer")))?,
+            };
+            let ingredients = entry["ingredients"]
+                .as_object()
+                .filter(|i| !i.is_empty())
+                .ok_or_else(|| error(format!("{output}: missing ingredients")))?
+                .iter()
+                .map(|(item, n)| {
+                    let n = n
+                        .as_u64()
+                        .filter(|&n| n > 0)
+                        .and_then(|n| u32::try_from(n).ok());
+                    n.map(|n| (item.clone(), n))
+                        .ok_or_else(|| error(format!("{output}: bad quantity of {item}")))
+                })
+                .collect::<Result<_, _>>()?;
+            book.add(Recipe {
+                output: output.to_string(),
+                count,
+                ingredients,
+                needs_table: entry["table"].as_bool().unwrap_or(false),
+            });
+        }
+        Ok(book)
+    }
+}
+
+/// One CraftAction of a plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CraftStep {
+    /// Item to craft
+    pub item_type: String,
+    /// Items crafted (whole crafts, so possibly more than needed)
+    pub count: u32,
+    /// Whether it needs a crafting table
+    pub needs_table: bool,
+}
+
+/// How to get from an inventory to the target item
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CraftPlan {
+    /// Raw materials to gather first, in the order they are needed
+    pub gather: Vec<(String, u32)>,
+    /// Crafts in order, intermediates first
+    pub steps: Vec<CraftStep>,
+}
+
+impl CraftPlan {
+    /// Whether the inventory already holds everything the crafts need
+    pub fn is_ready(&self) -> bool {
+        self.gather.is_empty()
+    }
+
+    /// Whether any step needs a crafting table
+    pub fn needs_table(&self) -> bool {
+        self.steps.iter().any(|s| s.needs_table)
+    }
+
+    /// The CraftActions to send, in order; None if a step needs a table and
+    /// `table` is None
+    pub fn actions(&self, table: Option<&BlockPosition>) -> Option<Vec<Action>> {
+        self.steps
+            .iter()
+            .map(|step| {
+                let table_position = if step.needs_table {
+                    Some(table?.clone())
+                } else {
+                    None
+                };
+                Some(Action::Craft(CraftAction {
+                    item_type: step.item_type.clone(),
+                    count: i32::try_from(step.count).unwrap_or(i32::MAX),
+                    table_position,
+                }))
+            })
+            .collect()
+    }
+}
+
+struct Planner<'a> {
+    book: &'a RecipeBook,
+    stock: HashMap<String, u32>,
+    plan: CraftPlan,
+}
+
+impl Planner<'_> {
+    fn need(&mut self, item_type: &str, quantity: u32) {
+        let stock = self.stock.entry(item_type.to_string()).or_default();
+        let taken = quantity.min(*stock);
+        *stock -= taken;
+        let missing = quantity - taken;
+        if missing == 0 {
+            return;
+        }
+
+        let recipe = self.book.get(item_type).filter(|recipe| {
+            // Cycles are only crafted from what is on hand
+            !self.book.is_cyclic(item_type) || self.in_stock(recipe, missing.div_ceil(recipe.count))
+        });
+        let Some(recipe) = recipe else {
+            match self
+                .plan
+                .gather
+                .iter_mut()
+                .find(|(item, _)| item == item_type)
+            {
+                Some((_, n)) => *n += missing,
+                None => self.plan.gather.push((item_type.to_string(), missing)),
+            }
+            return;
+        };
+
+        let crafts = missing.div_ceil(recipe.count);
+        for (ingredient, n) in &recipe.ingredients {
+            self.need(ingredient, n.saturating_mul(crafts));
+        }
+
+        let crafted = crafts.saturating_mul(recipe.count);
+        *self.stock.entry(item_type.to_string()).or_default() += crafted - missing;
+        // Back-to-back crafts of the same item become one CraftAction
+        match self.plan.steps.last_mut() {
+            Some(last) if last.item_type == item_type => last.count += crafted,
+            _ => self.plan.steps.push(CraftStep {
+                item_type: item_type.to_string(),
+                count: crafted,
+                needs_table: recipe.needs_table,
+            }),
+        }
+    }
+
+    fn in_stock(&self, recipe: &Recipe, crafts: u32) -> bool {
+        recipe.ingredients.iter().all(|(item, n)| {
+            self.stock.get(item).copied().unwrap_or_default() >= n.saturating_mul(crafts)
+        })
+    }
+}
+
+/// Plan crafting `count` of `item_type` from `inventory`. Items without a
+/// recipe that the inventory lacks end up in [`CraftPlan::gather`], and so
+/// do items in recipe cycles (see [`RecipeBook::is_cyclic`]) unless the
+/// inventory holds their ingredients. An item already in the inventory
+/// gives an empty plan.
+pub fn plan(book: &RecipeBook, inventory: &[ItemStack], item_type: &str, count: u32) -> CraftPlan {
+    let mut stock: HashMap<String, u32> = HashMap::new();
+    for stack in inventory {
+        *stock.entry(stack.item_type.clone()).or_default() +=
+            u32::try_from(stack.quantity).unwrap_or(0);
+    }
+    let mut planner = Planner {
+        book,
+        stock,
+        plan: CraftPlan::default(),
+    };
+    planner.need(item_type, count);
+    planner.plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book() -> RecipeBook {
+        RecipeBook::from_json(include_str!("../data/recipes.json")).unwrap()
+    }
+
+    fn stack(item_type: &str, quantity: i32) -> ItemStack {
+        ItemStack {
+            item_type: item_type.to_string(),
+            quantity,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plan_from_logs() {
+        let book = book();
+        let plan = plan(
+            &book,
+            &[stack("minecraft:oak_log", 2)],
+            "minecraft:wooden_pickaxe",
+            1,
+        );
+        assert!(plan.is_ready());
+        let steps: Vec<_> = plan
+            .steps
+            .iter()
+            .map(|s| (s.item_type.as_str(), s.count))
+            .collect();
+        // 3 planks for the head, 2 for the sticks: one log's worth each
+        assert_eq!(
+            steps,
+            [
+                ("minecraft:oak_planks", 8),
+                ("minecraft:stick", 4),
+                ("minecraft:wooden_pickaxe", 1),
+            ]
+        );
+
+        assert!(plan.needs_table());
+        assert!(plan.actions(None).is_none());
+        let table = BlockPosition::default();
+        let actions = plan.actions(Some(&table)).unwrap();
+        assert!(matches!(&actions[0], Action::Craft(c) if c.table_position.is_none()));
+        assert!(matches!(&actions[2], Action::Craft(c) if c.table_position.is_some()));
+    }
+
+    #[test]
+    fn test_plan_gathers_and_reuses_stock() {
+        let book = book();
+        let inventory = [
+            stack("minecraft:stick", 2),
+            stack("minecraft:iron_ingot", 1),
+        ];
+        let plan = plan(&book, &inventory, "minecraft:iron_pickaxe", 1);
+        // Sticks are on hand; ingots come from iron blocks, which are made
+        // of ingots, so they are gathered (smelted) instead
+        assert_eq!(plan.gather, [("minecraft:iron_ingot".to_string(), 2)]);
+        assert!(!plan.is_ready());
+        assert!(book.is_cyclic("minecraft:iron_block"));
+        assert!(!book.is_cyclic("minecraft:stick"));
+
+        // ...unless there is a block to break up
+        let inventory = [
+            stack("minecraft:stick", 2),
+            stack("minecraft:iron_block", 1),
+        ];
+        let plan = super::plan(&book, &inventory, "minecraft:iron_pickaxe", 1);
+        assert!(plan.is_ready());
+        assert_eq!(plan.steps[0].item_type, "minecraft:iron_ingot");
+
+        assert_eq!(
+            super::plan(&book, &[stack("minecraft:torch", 8)], "minecraft:torch", 8),
+            CraftPlan::default()
+        );
+    }
+
+    #[test]
+    fn test_from_json() {
+        assert!(book().len() > 5);
+        let book = RecipeBook::from_json(
+            r#"[{"output": "minecraft:stick", "count": 4, "ingredients": {"minecraft:bamboo": 2}}]"#,
+        )
+        .unwrap();
+        assert_eq!(book.get("minecraft:stick").unwrap().ingredients[0].1, 2);
+        assert!(!book.get("minecraft:stick").unwrap().needs_table);
+
+        for bad in [
+            "{}",
+            r#"[{"count": 1, "ingredients": {"a": 1}}]"#,
+            r#"[{"output": "a", "ingredients": {}}]"#,
+            r#"[{"output": "a", "count": 0, "ingredients": {"b": 1}}]"#,
+            r#"[{"output": "a", "ingredients": {"b": -1}}]"#,
+        ] {
+            assert!(RecipeBook::from_json(bad).is_err(), "{bad} should not load");
+        }
+    }
+}
@@ -0,0 +1,656 @@
+//! Web dashboard for operators.
+//!
+//! A [`Dashboard`] is a wire tap observer: registered on the daemon's
+//! [`Tap`](crate::tap::Tap), it keeps a bounded view of what crossed the
+//! streams and serves it over HTTP with axum:
+//!
+//! - `/`: a page with a map of NPC and player positions per server and
+//!   world, and the tables below, refreshed every second
+//! - `/api/map`: latest position, health and activity of every NPC, and
+//!   the players near them
+//! - `/api/transcripts`: the last lines said to and by each NPC: chat,
+//!   SpeakDirectives and, if the daemon reports them with
+//!   [`Dashboard::transcript`], voice transcripts
+//! - `/api/directives`: the latest directives with when each was sent,
+//!   acknowledged and finished, and how
+//! - `/api/audio`: TTS streams to the plugin and player voice from it
+//!
+//! ```ignore
+//! let dashboard = Dashboard::new();
+//! tap.register(dashboard.clone());
+//! tokio::spawn(dashboard.serve("127.0.0.1:8080".parse()?));
+//! ```
+//!
+//! The pages carry no authentication; bind to localhost or put a proxy in
+//! front of it.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::State;
+use axum::response::Html;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+
+use crate::npc_society::v1::{
+    client_message::Message as ClientMsg, server_message::Message as ServerMsg, NpcSnapshot,
+    PcmFormat, PlayerSnapshot,
+};
+use crate::retry::action_kind;
+use crate::tap::{Frame, TapObserver, TapRecord};
+use crate::time::now_ms;
+
+/// Lines kept per NPC
+pub const TRANSCRIPT_LINES: usize = 50;
+/// Directives kept, oldest dropped first
+pub const DIRECTIVES: usize = 500;
+/// TTS streams kept, oldest dropped first
+pub const AUDIO_STREAMS: usize = 100;
+/// Player voice without a frame for this long is shown as idle
+pub const VOICE_IDLE_MS: i64 = 1_000;
+
+const INDEX_HTML: &str = include_str!("dashboard.html");
+
+struct Line {
+    at_ms: i64,
+    speaker: String,
+    text: String,
+    /// Chat or a directive, not voice
+    typed: bool,
+}
+
+struct DirectiveEntry {
+    server_id: String,
+    directive_id: String,
+    npc_id: String,
+    action: &'static str,
+    sent_ms: i64,
+    acked_ms: Option<i64>,
+    finished_ms: Option<i64>,
+    /// "pending", "ok", "failed" or "rejected"
+    outcome: &'static str,
+    error: String,
+}
+
+struct StreamEntry {
+    server_id: String,
+    stream_id: String,
+    npc_id: String,
+    directive_id: String,
+    format: PcmFormat,
+    chunks: u64,
+    bytes: u64,
+    started_ms: i64,
+    last_ms: i64,
+    /// "playing", "finished", "stopped" or "interrupted"
+    state: &'static str,
+}
+
+struct VoiceEntry {
+    server_id: String,
+    frames: u64,
+    bytes: u64,
+    started_ms: i64,
+    last_ms: i64,
+}
+
+#[derive(Default)]
+struct Views {
+    /// Latest snapshot and when it arrived, by (server_id, npc_id)
+    npcs: BTreeMap<(String, String), (NpcSnapshot, i64)>,
+    /// Players near NPCs in the latest WorldTick, by (server_id, player_uuid)
+    players: BTreeMap<(String, String), PlayerSnapshot>,
+    transcripts: BTreeMap<String, VecDeque<Line>>,
+    directives: VecDeque<DirectiveEntry>,
+    streams: VecDeque<StreamEntry>,
+    /// Player voice by (npc_id, player_uuid)
+    voice: BTreeMap<(String, String), VoiceEntry>,
+}
+
+impl Views {
+    fn player_name(&self, player_uuid: &str) -> String {
+        self.players
+            .iter()
+            .find(|((_, uuid), _)| uuid == player_uuid)
+            .map_or_else(|| player_uuid.to_string(), |(_, p)| p.player_name.clone())
+    }
+
+    fn say(&mut self, npc_id: &str, line: Line) {
+        let lines = self.transcripts.entry(npc_id.to_string()).or_default();
+        if lines.len() == TRANSCRIPT_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    fn directive(&mut self, directive_id: &str) -> Option<&mut DirectiveEntry> {
+        self.directives
+            .iter_mut()
+            .rev()
+            .find(|d| d.directive_id == directive_id)
+    }
+
+    fn stream(&mut self, stream_id: &str) -> Option<&mut StreamEntry> {
+        self.streams
+            .iter_mut()
+            .rev()
+            .find(|s| s.stream_id == stream_id)
+    }
+
+    fn inbound(&mut self, server_id: &str, msg: &ClientMsg, at_ms: i64) {
+        match msg {
+            ClientMsg::WorldTick(tick) => {
+                self.npcs.retain(|(server, _), _| server != server_id);
+                self.players.retain(|(server, _), _| server != server_id);
+                for npc in &tick.npcs {
+                    let key = (server_id.to_string(), npc.npc_id.clone());
+                    self.npcs.insert(key, (npc.clone(), at_ms));
+                }
+                for player in &tick.nearby_players {
+                    let key = (server_id.to_string(), player.player_uuid.clone());
+                    self.players.insert(key, player.clone());
+                }
+            }
+            ClientMsg::ChatObservation(chat) => self.say(
+                &chat.npc_id,
+                Line {
+                    at_ms,
+                    speaker: chat.player_name.clone(),
+                    text: chat.message.clone(),
+                    typed: true,
+                },
+            ),
+            ClientMsg::DirectiveAck(ack) => {
+                if let Some(entry) = self.directive(&ack.directive_id) {
+                    entry.acked_ms.get_or_insert(at_ms);
+                }
+            }
+            ClientMsg::ActionResult(result) => {
+                if let Some(entry) = self.directive(&result.directive_id) {
+                    if entry.finished_ms.is_none() {
+                        entry.finished_ms = Some(at_ms);
+                        entry.outcome = if result.success { "ok" } else { "failed" };
+                        entry.error = result.error_message.clone();
+                    }
+                }
+            }
+            ClientMsg::DirectiveRejected(rejected) => {
+                if let Some(entry) = self.directive(&rejected.directive_id) {
+                    entry.finished_ms = Some(at_ms);
+                    entry.outcome = "rejected";
+                    entry.error = format!("{:?}: {}", rejected.code(), rejected.detail);
+                }
+            }
+            ClientMsg::SpeechInterrupted(interrupted) => {
+                if let Some(stream) = self.stream(&interrupted.stream_id) {
+                    stream.state = "interrupted";
+                }
+            }
+            ClientMsg::VoicePcmFrame(frame) => {
+                let key = (frame.npc_id.clone(), frame.player_uuid.clone());
+                let entry = self.voice.entry(key).or_insert_with(|| VoiceEntry {
+                    server_id: server_id.to_string(),
+                    frames: 0,
+                    bytes: 0,
+                    started_ms: at_ms,
+                    last_ms: at_ms,
+                });
+                if at_ms - entry.last_ms > VOICE_IDLE_MS {
+                    // A new utterance
+                    entry.started_ms = at_ms;
+                }
+                entry.frames += 1;
+                entry.bytes += frame.pcm_data.len() as u64;
+                entry.last_ms = at_ms;
+            }
+            _ => {}
+        }
+    }
+
+    fn outbound(&mut self, server_id: &str, msg: &ServerMsg, at_ms: i64) {
+        match msg {
+            ServerMsg::ActionDirective(directive) => {
+                if self.directives.len() == DIRECTIVES {
+                    self.directives.pop_front();
+                }
+                self.directives.push_back(DirectiveEntry {
+                    server_id: server_id.to_string(),
+                    directive_id: directive.directive_id.clone(),
+                    npc_id: directive.npc_id.clone(),
+                    action: directive.action.as_ref().map_or("none", action_kind),
+                    sent_ms: at_ms,
+                    acked_ms: None,
+                    finished_ms: None,
+                    outcome: "pending",
+                    error: String::new(),
+                });
+            }
+            ServerMsg::SpeakDirective(speak) => self.say(
+                &speak.npc_id,
+                Line {
+                    at_ms,
+                    speaker: speak.npc_id.clone(),
+                    text: speak.text.clone(),
+                    typed: true,
+                },
+            ),
+            ServerMsg::AudioChunk(chunk) => {
+                if self.stream(&chunk.stream_id).is_none() {
+                    if self.streams.len() == AUDIO_STREAMS {
+                        self.streams.pop_front();
+                    }
+                    self.streams.push_back(StreamEntry {
+                        server_id: server_id.to_string(),
+                        stream_id: chunk.stream_id.clone(),
+                        npc_id: chunk.npc_id.clone(),
+                        directive_id: chunk.directive_id.clone(),
+                        format: chunk.format(),
+                        chunks: 0,
+                        bytes: 0,
+                        started_ms: at_ms,
+                        last_ms: at_ms,
+                        state: "playing",
+                    });
+                }
+                if let Some(stream) = self.stream(&chunk.stream_id) {
+                    stream.chunks += 1;
+                    stream.bytes += chunk.pcm_data.len() as u64;
+                    stream.last_ms = at_ms;
+                    if chunk.is_final && stream.state == "playing" {
+                        stream.state = "finished";
+                    }
+                }
+            }
+            ServerMsg::StopSpeaking(stop) => {
+                if let Some(stream) = self.stream(&stop.stream_id) {
+                    stream.state = "stopped";
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The dashboard's state and server. Cheap to clone; clones share the
+/// state.
+#[derive(Clone, Default)]
+pub struct Dashboard {
+    views: Arc<Mutex<Views>>,
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A voice transcript, which the tap never sees: `player_uuid` said
+    /// `text` to `npc_id`
+    pub fn transcript(&self, npc_id: &str, player_uuid: &str, text: &str, at_ms: i64) {
+        let mut views = self.views.lock().unwrap();
+        let speaker = views.player_name(player_uuid);
+        views.say(
+            npc_id,
+            Line {
+                at_ms,
+                speaker,
+                text: text.to_string(),
+                typed: false,
+            },
+        );
+    }
+
+    /// `/api/map`
+    pub fn map(&self) -> Value {
+        let views = self.views.lock().unwrap();
+        let npcs: Vec<Value> = views
+            .npcs
+            .iter()
+            .map(|((server_id, npc_id), (npc, updated_ms))| {
+                let p = npc.position.clone().unwrap_or_default();
+                json!({
+                    "server_id": server_id,
+                    "npc_id": npc_id,
+                    "world": p.world,
+                    "x": p.x,
+                    "y": p.y,
+                    "z": p.z,
+                    "health": npc.health_norm,
+                    "activity": npc.current_activity,
+                    "updated_ms": updated_ms,
+                })
+            })
+            .collect();
+        let players: Vec<Value> = views
+            .players
+            .iter()
+            .map(|((server_id, player_uuid), player)| {
+                let p = player.position.clone().unwrap_or_default();
+                json!({
+                    "server_id": server_id,
+                    "player_uuid": player_uuid,
+                    "name": player.player_name,
+                    "world": p.world,
+                    "x": p.x,
+                    "y": p.y,
+                    "z": p.z,
+                })
+            })
+            .collect();
+        json!({ "npcs": npcs, "players": players })
+    }
+
+    /// `/api/transcripts`: lines per NPC, oldest first
+    pub fn transcripts(&self) -> Value {
+        let views = self.views.lock().unwrap();
+        let transcripts: serde_json::Map<String, Value> = views
+            .transcripts
+            .iter()
+            .map(|(npc_id, lines)| {
+                let lines: Vec<Value> = lines
+                    .iter()
+                    .map(|line| {
+                        json!({
+                            "at_ms": line.at_ms,
+                            "speaker": line.speaker,
+                            "text": line.text,
+                            "voice": !line.typed,
+                        })
+                    })
+                    .collect();
+                (npc_id.clone(), Value::from(lines))
+            })
+            .collect();
+        Value::Object(transcripts)
+    }
+
+    /// `/api/directives`: oldest first
+    pub fn directives(&self) -> Value {
+        let views = self.views.lock().unwrap();
+        let directives: Vec<Value> = views
+            .directives
+            .iter()
+            .map(|d| {
+                json!({
+                    "server_id": d.server_id,
+                    "directive_id": d.directive_id,
+                    "npc_id": d.npc_id,
+                    "action": d.action,
+                    "sent_ms": d.sent_ms,
+                    "acked_ms": d.acked_ms,
+                    "finished_ms": d.finished_ms,
+                    "outcome": d.outcome,
+                    "error": d.error,
+                })
+            })
+            .collect();
+        Value::from(directives)
+    }
+
+    /// `/api/audio` at `now_ms`: TTS streams, newest last, and player voice
+    pub fn audio(&self, now_ms: i64) -> Value {
+        let views = self.views.lock().unwrap();
+        let streams: Vec<Value> = views
+            .streams
+            .iter()
+            .map(|s| {
+                json!({
+                    "server_id": s.server_id,
+                    "stream_id": s.stream_id,
+                    "npc_id": s.npc_id,
+                    "directive_id": s.directive_id,
+                    "format": s.format.as_str_name(),
+                    "chunks": s.chunks,
+                    "bytes": s.bytes,
+                    "started_ms": s.started_ms,
+                    "last_ms": s.last_ms,
+                    "state": s.state,
+                })
+            })
+            .collect();
+        let voice: Vec<Value> = views
+            .voice
+            .iter()
+            .map(|((npc_id, player_uuid), v)| {
+                let active = now_ms - v.last_ms <= VOICE_IDLE_MS;
+                json!({
+                    "server_id": v.server_id,
+                    "npc_id": npc_id,
+                    "player": views.player_name(player_uuid),
+                    "frames": v.frames,
+                    "bytes": v.bytes,
+                    "started_ms": v.started_ms,
+                    "last_ms": v.last_ms,
+                    "state": if active { "speaking" } else { "idle" },
+                })
+            })
+            .collect();
+        json!({ "streams": streams, "voice": voice })
+    }
+
+    /// The page and the JSON endpoints
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/", get(|| async { Html(INDEX_HTML) }))
+            .route(
+                "/api/map",
+                get(|State(d): State<Dashboard>| async move { Json(d.map()) }),
+            )
+            .route(
+                "/api/transcripts",
+                get(|State(d): State<Dashboard>| async move { Json(d.transcripts()) }),
+            )
+            .route(
+                "/api/directives",
+                get(|State(d): State<Dashboard>| async move { Json(d.directives()) }),
+            )
+            .route(
+                "/api/audio",
+                get(|State(d): State<Dashboard>| async move { Json(d.audio(now_ms())) }),
+            )
+            .with_state(self.clone())
+    }
+
+    /// Serve the dashboard on `addr` until the process ends
+    pub async fn serve(self, addr: SocketAddr) -> io::Result<()> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        axum::serve(listener, self.router()).await
+    }
+}
+
+impl TapObserver for Dashboard {
+    fn observe(&self, record: &TapRecord<'_>) {
+        let mut views = self.views.lock().unwrap();
+        match record.frame {
+            Frame::Client(msg) => {
+                if let Some(m) = &msg.message {
+                    views.inbound(record.server_id, m, record.timestamp_ms);
+                }
+            }
+            Frame::Server(msg) => {
+                if let Some(m) = &msg.message {
+                    views.outbound(record.server_id, m, record.timestamp_ms);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{
+        action_directive::Action, ActionDirective, ActionResult, AudioChunk, ChatObservation,
+        ClientMessage, DirectiveAck, Position, ServerMessage, SpeakDirective, StopAction,
+        VoicePcmFrame, WorldTick,
+    };
+
+    fn inbound(dashboard: &Dashboard, msg: impl Into<ClientMessage>, at_ms: i64) {
+        let msg = msg.into();
+        dashboard.observe(&TapRecord {
+            server_id: "survival",
+            timestamp_ms: at_ms,
+            size: 0,
+            frame: Frame::Client(&msg),
+        });
+    }
+
+    fn outbound(dashboard: &Dashboard, msg: impl Into<ServerMessage>, at_ms: i64) {
+        let msg = msg.into();
+        dashboard.observe(&TapRecord {
+            server_id: "survival",
+            timestamp_ms: at_ms,
+            size: 0,
+            frame: Frame::Server(&msg),
+        });
+    }
+
+    fn tick() -> WorldTick {
+        WorldTick {
+            npcs: vec![NpcSnapshot {
+                npc_id: "miner_01".to_string(),
+                position: Some(Position {
+                    world: "world".to_string(),
+                    x: 12.0,
+                    y: 64.0,
+                    z: -3.0,
+                    ..Default::default()
+                }),
+                health_norm: 0.5,
+                ..Default::default()
+            }],
+            nearby_players: vec![PlayerSnapshot {
+                player_uuid: "uuid-steve".to_string(),
+                player_name: "Steve".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_map_and_transcripts() {
+        let dashboard = Dashboard::new();
+        inbound(&dashboard, tick(), 1_000);
+        let map = dashboard.map();
+        assert_eq!(map["npcs"][0]["npc_id"], "miner_01");
+        assert_eq!(map["npcs"][0]["x"], 12.0);
+        assert_eq!(map["players"][0]["name"], "Steve");
+
+        inbound(
+            &dashboard,
+            ChatObservation {
+                npc_id: "miner_01".to_string(),
+                player_name: "Steve".to_string(),
+                message: "found any diamonds?".to_string(),
+                ..Default::default()
+            },
+            1_100,
+        );
+        outbound(
+            &dashboard,
+            SpeakDirective {
+                npc_id: "miner_01".to_string(),
+                text: "Not yet".to_string(),
+                ..Default::default()
+            },
+            1_200,
+        );
+        dashboard.transcript("miner_01", "uuid-steve", "keep digging", 1_300);
+        let lines = &dashboard.transcripts()["miner_01"];
+        assert_eq!(lines.as_array().unwrap().len(), 3);
+        assert_eq!(lines[1]["speaker"], "miner_01");
+        assert_eq!(lines[2]["speaker"], "Steve");
+        assert_eq!(lines[2]["voice"], true);
+
+        for i in 0..TRANSCRIPT_LINES {
+            dashboard.transcript("miner_01", "uuid-steve", &i.to_string(), 2_000);
+        }
+        let lines = &dashboard.transcripts()["miner_01"];
+        assert_eq!(lines.as_array().unwrap().len(), TRANSCRIPT_LINES);
+        assert_eq!(lines[0]["text"], "0");
+
+        // NPCs missing from the next tick leave the map
+        inbound(&dashboard, WorldTick::default(), 3_000);
+        assert_eq!(dashboard.map()["npcs"], json!([]));
+    }
+
+    #[test]
+    fn test_directive_timeline() {
+        let dashboard = Dashboard::new();
+        let directive = |id: &str| ActionDirective {
+            directive_id: id.to_string(),
+            npc_id: "miner_01".to_string(),
+            action: Some(Action::Stop(StopAction::default())),
+            ..Default::default()
+        };
+        outbound(&dashboard, directive("d1"), 1_000);
+        outbound(&dashboard, directive("d2"), 1_010);
+        let ack = DirectiveAck {
+            directive_id: "d1".to_string(),
+            ..Default::default()
+        };
+        inbound(&dashboard, ack, 1_050);
+        let result = ActionResult {
+            directive_id: "d1".to_string(),
+            success: false,
+            error_message: "path blocked".to_string(),
+            ..Default::default()
+        };
+        inbound(&dashboard, result.clone(), 1_500);
+        // A replayed result does not move the end
+        inbound(
+            &dashboard,
+            ActionResult {
+                replayed: true,
+                ..result
+            },
+            2_500,
+        );
+
+        let directives = dashboard.directives();
+        assert_eq!(directives[0]["action"], "stop");
+        assert_eq!(directives[0]["acked_ms"], 1_050);
+        assert_eq!(directives[0]["finished_ms"], 1_500);
+        assert_eq!(directives[0]["outcome"], "failed");
+        assert_eq!(directives[0]["error"], "path blocked");
+        assert_eq!(directives[1]["outcome"], "pending");
+        assert_eq!(directives[1]["finished_ms"], Value::Null);
+    }
+
+    #[test]
+    fn test_audio_status() {
+        let dashboard = Dashboard::new();
+        inbound(&dashboard, tick(), 900);
+        for (sequence, is_final) in [(0, false), (1, true)] {
+            let chunk = AudioChunk {
+                npc_id: "miner_01".to_string(),
+                stream_id: "s1".to_string(),
+                pcm_data: vec![0; 960].into(),
+                sequence,
+                is_final,
+                ..Default::default()
+            };
+            outbound(&dashboard, chunk, 1_000 + sequence as i64 * 20);
+        }
+        let frame = VoicePcmFrame {
+            npc_id: "miner_01".to_string(),
+            player_uuid: "uuid-steve".to_string(),
+            pcm_data: vec![0; 640].into(),
+            ..Default::default()
+        };
+        inbound(&dashboard, frame.clone(), 1_000);
+        inbound(&dashboard, frame, 1_020);
+
+        let audio = dashboard.audio(1_500);
+        assert_eq!(audio["streams"][0]["chunks"], 2);
+        assert_eq!(audio["streams"][0]["bytes"], 1_920);
+        assert_eq!(audio["streams"][0]["state"], "finished");
+        assert_eq!(audio["voice"][0]["player"], "Steve");
+        assert_eq!(audio["voice"][0]["frames"], 2);
+        assert_eq!(audio["voice"][0]["state"], "speaking");
+        assert_eq!(dashboard.audio(5_000)["voice"][0]["state"], "idle");
+    }
+}
@@ -0,0 +1,140 @@
+//! Worlds and dimensions.
+//!
+//! Every `Position` and `BlockPosition` carries a world name and (since
+//! v1.2) its [`Dimension`]. Coordinates in different worlds are unrelated,
+//! so code that compares, measures or paths between positions should go
+//! through [`World`] and [`same_world`] instead of looking at x/y/z alone.
+//! An NPC's world changes only with a `ChangeDimensionObservation`.
+
+use std::fmt;
+
+use crate::npc_society::v1::{BlockPosition, Dimension, NpcSnapshot, Position};
+
+/// Something that lives in a world
+pub trait Located {
+    /// World name
+    fn world(&self) -> &str;
+    /// Dimension of the world (may be unspecified for pre-v1.2 plugins)
+    fn dimension(&self) -> Dimension;
+}
+
+impl Located for Position {
+    fn world(&self) -> &str {
+        &self.world
+    }
+
+    fn dimension(&self) -> Dimension {
+        Position::dimension(self)
+    }
+}
+
+impl Located for BlockPosition {
+    fn world(&self) -> &str {
+        &self.world
+    }
+
+    fn dimension(&self) -> Dimension {
+        BlockPosition::dimension(self)
+    }
+}
+
+/// A world and its dimension
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct World {
+    /// World name, e.g. "world_nether"
+    pub name: String,
+    /// Dimension type
+    pub dimension: Dimension,
+}
+
+impl World {
+    /// The world `at` is in
+    pub fn of(at: &impl Located) -> Self {
+        Self {
+            name: at.world().to_string(),
+            dimension: at.dimension(),
+        }
+    }
+
+    /// The world `npc` is currently in, if its position is known
+    pub fn of_npc(npc: &NpcSnapshot) -> Option<Self> {
+        npc.position.as_ref().map(Self::of)
+    }
+
+    /// Whether `at` is in this world
+    pub fn contains(&self, at: &impl Located) -> bool {
+        at.world() == self.name && dimensions_match(self.dimension, at.dimension())
+    }
+}
+
+impl Located for World {
+    fn world(&self) -> &str {
+        &self.name
+    }
+
+    fn dimension(&self) -> Dimension {
+        self.dimension
+    }
+}
+
+impl fmt::Display for World {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.dimension {
+            Dimension::Unspecified => write!(f, "{}", self.name),
+            dimension => write!(f, "{} ({})", self.name, dimension.as_str_name()),
+        }
+    }
+}
+
+/// Whether two positions are in the same world. An unspecified dimension
+/// matches any, so positions from older plugins compare by name only.
+pub fn same_world(a: &impl Located, b: &impl Located) -> bool {
+    a.world() == b.world() && dimensions_match(a.dimension(), b.dimension())
+}
+
+fn dimensions_match(a: Dimension, b: Dimension) -> bool {
+    a == b || a == Dimension::Unspecified || b == Dimension::Unspecified
+}
+
+/// The block containing `p`, in the same world
+pub fn block_at(p: &Position) -> BlockPosition {
+    BlockPosition {
+        world: p.world.clone(),
+        x: p.x.floor() as i32,
+        y: p.y.floor() as i32,
+        z: p.z.floor() as i32,
+        dimension: p.dimension,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_world() {
+        let overworld = Position {
+            world: "world".to_string(),
+            x: -0.5,
+            dimension: Dimension::Overworld as i32,
+            ..Default::default()
+        };
+        let block = block_at(&overworld);
+        assert_eq!((block.x, block.dimension()), (-1, Dimension::Overworld));
+        assert!(same_world(&overworld, &block));
+
+        let legacy = BlockPosition {
+            world: "world".to_string(),
+            ..Default::default()
+        };
+        assert!(same_world(&overworld, &legacy));
+        let nether = Position {
+            world: "world_nether".to_string(),
+            dimension: Dimension::Nether as i32,
+            ..Default::default()
+        };
+        assert!(!same_world(&overworld, &nether));
+        assert!(!World::of(&nether).contains(&overworld));
+        assert_eq!(World::of(&nether).to_string(), "world_nether (DIMENSION_NETHER)");
+    }
+}
@@ -0,0 +1,151 @@
+//! Holograms, boss bars and scoreboards (`ShowDisplayDirective`, v1.2+).
+//!
+//! Displays are on-screen text an NPC owns: prices floating above a stall,
+//! a quest tracker in the sidebar. [`Displays`] remembers what the plugin
+//! shows, so a display that did not change is not sent again on every
+//! tick, and the lot can be shown again after a reconnect (the plugin
+//! drops them all when the connection closes).
+
+use std::collections::HashMap;
+
+use crate::npc_society::v1::{
+    show_display_directive::Display, BossBarDisplay, HologramDisplay, RemoveDisplayDirective,
+    ScoreboardDisplay, ShowDisplayDirective,
+};
+
+/// A hologram above the NPC's head, seen by everyone in range
+pub fn hologram(
+    display_id: impl Into<String>,
+    npc_id: impl Into<String>,
+    lines: impl IntoIterator<Item = String>,
+) -> ShowDisplayDirective {
+    ShowDisplayDirective {
+        display_id: display_id.into(),
+        npc_id: npc_id.into(),
+        display: Some(Display::Hologram(HologramDisplay {
+            lines: lines.into_iter().collect(),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+/// A boss bar for one player; `progress` is clamped to 0.0-1.0
+pub fn boss_bar(
+    display_id: impl Into<String>,
+    npc_id: impl Into<String>,
+    player_uuid: impl Into<String>,
+    title: impl Into<String>,
+    progress: f32,
+) -> ShowDisplayDirective {
+    ShowDisplayDirective {
+        display_id: display_id.into(),
+        npc_id: npc_id.into(),
+        player_uuids: vec![player_uuid.into()],
+        display: Some(Display::BossBar(BossBarDisplay {
+            title: title.into(),
+            progress: progress.clamp(0.0, 1.0),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+/// A sidebar for one player; lines past the 15th are cut
+pub fn scoreboard(
+    display_id: impl Into<String>,
+    npc_id: impl Into<String>,
+    player_uuid: impl Into<String>,
+    title: impl Into<String>,
+    lines: impl IntoIterator<Item = String>,
+) -> ShowDisplayDirective {
+    ShowDisplayDirective {
+        display_id: display_id.into(),
+        npc_id: npc_id.into(),
+        player_uuids: vec![player_uuid.into()],
+        display: Some(Display::Scoreboard(ScoreboardDisplay {
+            title: title.into(),
+            lines: lines.into_iter().take(15).collect(),
+        })),
+        ..Default::default()
+    }
+}
+
+/// Displays the plugin shows, by display_id
+#[derive(Debug, Default)]
+pub struct Displays {
+    shown: HashMap<String, ShowDisplayDirective>,
+}
+
+impl Displays {
+    /// Record `display` as shown; returns it if it has to be sent, None if
+    /// the plugin already shows exactly this
+    pub fn show(&mut self, display: ShowDisplayDirective) -> Option<ShowDisplayDirective> {
+        if self.shown.get(&display.display_id) == Some(&display) {
+            return None;
+        }
+        self.shown
+            .insert(display.display_id.clone(), display.clone());
+        Some(display)
+    }
+
+    /// The directive removing a display; None if it is not shown
+    pub fn remove(&mut self, display_id: &str) -> Option<RemoveDisplayDirective> {
+        let display = self.shown.remove(display_id)?;
+        Some(RemoveDisplayDirective {
+            display_id: display.display_id,
+            npc_id: display.npc_id,
+        })
+    }
+
+    /// Forget the displays of `npc_id`, e.g. once it left the server (the
+    /// plugin removed them with it); returns their ids
+    pub fn forget_npc(&mut self, npc_id: &str) -> Vec<String> {
+        let ids: Vec<String> = self
+            .shown
+            .values()
+            .filter(|d| d.npc_id == npc_id)
+            .map(|d| d.display_id.clone())
+            .collect();
+        for id in &ids {
+            self.shown.remove(id);
+        }
+        ids
+    }
+
+    /// Everything shown, to send again on a new connection
+    pub fn all(&self) -> impl Iterator<Item = &ShowDisplayDirective> {
+        self.shown.values()
+    }
+
+    /// Whether `display_id` is shown
+    pub fn is_shown(&self, display_id: &str) -> bool {
+        self.shown.contains_key(display_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_show_only_changes() {
+        let mut displays = Displays::default();
+        let price = |p: i64| hologram("stall", "smith", [format!("Iron sword: {}", p)]);
+        assert!(displays.show(price(50)).is_some());
+        assert!(displays.show(price(50)).is_none());
+        assert!(displays.show(price(45)).is_some());
+
+        let bar = boss_bar("quest", "smith", "p1", "Iron for the smith", 1.5);
+        let Some(Display::BossBar(b)) = &displays.show(bar).unwrap().display else {
+            panic!("expected a boss bar");
+        };
+        assert_eq!(b.progress, 1.0);
+        assert_eq!(displays.all().count(), 2);
+
+        assert_eq!(displays.remove("quest").unwrap().npc_id, "smith");
+        assert!(displays.remove("quest").is_none());
+        assert_eq!(displays.forget_npc("smith"), ["stall"]);
+        assert!(!displays.is_shown("stall"));
+    }
+}
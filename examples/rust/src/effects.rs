@@ -0,0 +1,94 @@
+//! Particles and sounds (`PlayParticleDirective`, `PlaySoundDirective`,
+//! v1.2+).
+//!
+//! Both are fire and forget: the plugin plays them and reports nothing
+//! back, so there is no state to keep. [`for_result`] picks the effects
+//! that make a finished action visible to players nearby, e.g. sparkles
+//! and the enchanting table chime after an NPC enchanted an item.
+
+use crate::npc_society::v1::{
+    action_result::Result as ActionResultType, ActionResult, PlayParticleDirective,
+    PlaySoundDirective, ServerMessage,
+};
+
+/// `count` particles spread around the NPC's body
+pub fn particles(
+    npc_id: impl Into<String>,
+    particle: impl Into<String>,
+    count: i32,
+) -> PlayParticleDirective {
+    PlayParticleDirective {
+        particle: particle.into(),
+        npc_id: npc_id.into(),
+        count,
+        offset_x: 0.5,
+        offset_y: 0.5,
+        offset_z: 0.5,
+        ..Default::default()
+    }
+}
+
+/// A sound at the NPC, at normal volume and pitch
+pub fn sound(npc_id: impl Into<String>, sound: impl Into<String>) -> PlaySoundDirective {
+    PlaySoundDirective {
+        sound: sound.into(),
+        npc_id: npc_id.into(),
+        ..Default::default()
+    }
+}
+
+/// The effects to play for a finished action; none for failures and
+/// replayed results, whose effects were played the first time
+pub fn for_result(result: &ActionResult) -> Vec<ServerMessage> {
+    if !result.success || result.replayed {
+        return Vec::new();
+    }
+    let npc = result.npc_id.as_str();
+    match &result.result {
+        Some(ActionResultType::EnchantItemResult(_)) => vec![
+            particles(npc, "minecraft:enchant", 30).into(),
+            sound(npc, "minecraft:block.enchantment_table.use").into(),
+        ],
+        Some(ActionResultType::RepairItemResult(_)) => {
+            vec![sound(npc, "minecraft:block.anvil.use").into()]
+        }
+        Some(ActionResultType::CraftResult(_)) => {
+            vec![sound(npc, "minecraft:block.smithing_table.use").into()]
+        }
+        Some(ActionResultType::BrewResult(_)) => {
+            vec![sound(npc, "minecraft:block.brewing_stand.brew").into()]
+        }
+        Some(ActionResultType::TameAnimalResult(_) | ActionResultType::BreedAnimalsResult(_)) => {
+            vec![particles(npc, "minecraft:heart", 7).into()]
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::EnchantItemResult;
+    use crate::validate::Validate;
+
+    #[test]
+    fn test_effects_for_result() {
+        let mut result = ActionResult {
+            npc_id: "smith".to_string(),
+            success: true,
+            result: Some(ActionResultType::EnchantItemResult(
+                EnchantItemResult::default(),
+            )),
+            ..Default::default()
+        };
+        let effects = for_result(&result);
+        assert_eq!(effects.len(), 2);
+        assert!(effects.iter().all(|m| m.validate().is_ok()));
+
+        result.replayed = true;
+        assert!(for_result(&result).is_empty());
+        result.replayed = false;
+        result.success = false;
+        assert!(for_result(&result).is_empty());
+    }
+}
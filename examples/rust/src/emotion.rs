@@ -0,0 +1,323 @@
+//! Emotions of NPC speech, and the TTS styles they sound like.
+//!
+//! `SpeakDirective.emotion` used to be free text, which a plugin's
+//! animation system cannot react to. From v1.2 a directive names one of the
+//! canonical [`Emotion`]s in `emotion_type`, or `EMOTION_CUSTOM` with the
+//! custom emotion's name in `emotion`. A [`Tone`] is either; [`Spoken`]
+//! reads and writes it on a `SpeakDirective` (filling in `emotion` for
+//! older plugins), and [`Tone::parse`] maps free text such as an LLM's
+//! "cheerful" onto the canonical set.
+//!
+//! TTS engines express emotion differently: some take a named speaking
+//! style, the rest only pitch and rate. A [`StyleTable`] maps each tone to
+//! the [`TtsStyle`] one engine understands; [`StyleTable::prosody`] works
+//! for any engine and [`StyleTable::ssml`] adds SSML `express-as` styles.
+
+use std::collections::HashMap;
+
+use crate::npc_society::v1::{Emotion, SpeakDirective};
+
+/// The canonical emotions, in enum order
+pub const EMOTIONS: [Emotion; 9] = [
+    Emotion::Neutral,
+    Emotion::Happy,
+    Emotion::Sad,
+    Emotion::Angry,
+    Emotion::Fearful,
+    Emotion::Surprised,
+    Emotion::Disgusted,
+    Emotion::Calm,
+    Emotion::Excited,
+];
+
+/// Free-text words understood as each canonical emotion
+const SYNONYMS: [(Emotion, &[&str]); 9] = [
+    (Emotion::Neutral, &["none", "normal", "plain"]),
+    (
+        Emotion::Happy,
+        &[
+            "cheerful", "joyful", "glad", "friendly", "helpful", "pleased",
+        ],
+    ),
+    (
+        Emotion::Sad,
+        &["sorrowful", "melancholy", "gloomy", "unhappy", "mournful"],
+    ),
+    (
+        Emotion::Angry,
+        &["mad", "furious", "annoyed", "irritated", "hostile"],
+    ),
+    (
+        Emotion::Fearful,
+        &["afraid", "scared", "terrified", "nervous", "anxious"],
+    ),
+    (Emotion::Surprised, &["shocked", "astonished", "amazed"]),
+    (Emotion::Disgusted, &["disgust", "revolted", "disgruntled"]),
+    (Emotion::Calm, &["relaxed", "soothing", "gentle", "serene"]),
+    (
+        Emotion::Excited,
+        &["eager", "enthusiastic", "thrilled", "energetic"],
+    ),
+];
+
+/// Lowercase name of `emotion` as written into `SpeakDirective.emotion`
+/// ("" for unspecified and custom)
+pub fn name(emotion: Emotion) -> &'static str {
+    match emotion {
+        Emotion::Unspecified | Emotion::Custom => "",
+        Emotion::Neutral => "neutral",
+        Emotion::Happy => "happy",
+        Emotion::Sad => "sad",
+        Emotion::Angry => "angry",
+        Emotion::Fearful => "fearful",
+        Emotion::Surprised => "surprised",
+        Emotion::Disgusted => "disgusted",
+        Emotion::Calm => "calm",
+        Emotion::Excited => "excited",
+    }
+}
+
+/// How a line should sound: a canonical emotion or a custom one
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Tone {
+    /// One of the protocol's emotions (Unspecified = no emotion given)
+    Canonical(Emotion),
+    /// A name only some plugins and engines know, e.g. "ominous"
+    Custom(String),
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Tone::Canonical(Emotion::Unspecified)
+    }
+}
+
+impl From<Emotion> for Tone {
+    fn from(emotion: Emotion) -> Self {
+        match emotion {
+            // Custom without a name says nothing
+            Emotion::Custom => Tone::default(),
+            emotion => Tone::Canonical(emotion),
+        }
+    }
+}
+
+impl Tone {
+    /// Read free text: canonical names and common synonyms become
+    /// canonical emotions, anything else stays custom
+    pub fn parse(text: &str) -> Self {
+        let word = text.trim().to_lowercase();
+        if word.is_empty() {
+            return Tone::default();
+        }
+        let canonical = EMOTIONS.into_iter().find(|emotion| name(*emotion) == word);
+        let synonym = || {
+            SYNONYMS
+                .iter()
+                .find(|(_, words)| words.contains(&word.as_str()))
+                .map(|(emotion, _)| *emotion)
+        };
+        match canonical.or_else(synonym) {
+            Some(emotion) => Tone::Canonical(emotion),
+            None => Tone::Custom(word),
+        }
+    }
+
+    /// The canonical emotion, Custom for custom tones
+    pub fn emotion(&self) -> Emotion {
+        match self {
+            Tone::Canonical(emotion) => *emotion,
+            Tone::Custom(_) => Emotion::Custom,
+        }
+    }
+
+    /// Name as written into `SpeakDirective.emotion`
+    pub fn name(&self) -> &str {
+        match self {
+            Tone::Canonical(emotion) => name(*emotion),
+            Tone::Custom(custom) => custom,
+        }
+    }
+}
+
+/// The emotion fields of a `SpeakDirective`. Generated types live in
+/// `npc-society-proto`, so these come from a trait.
+pub trait Spoken {
+    /// The line's tone; free text from a pre-v1.2 daemon is parsed
+    fn tone(&self) -> Tone;
+
+    /// Set `emotion_type` and `emotion` for `tone`
+    fn set_tone(&mut self, tone: impl Into<Tone>);
+}
+
+impl Spoken for SpeakDirective {
+    fn tone(&self) -> Tone {
+        match self.emotion_type() {
+            Emotion::Unspecified => Tone::parse(&self.emotion),
+            Emotion::Custom if self.emotion.is_empty() => Tone::default(),
+            Emotion::Custom => Tone::Custom(self.emotion.clone()),
+            emotion => Tone::Canonical(emotion),
+        }
+    }
+
+    fn set_tone(&mut self, tone: impl Into<Tone>) {
+        let tone = tone.into();
+        self.emotion_type = tone.emotion() as i32;
+        self.emotion = tone.name().to_string();
+    }
+}
+
+/// How a TTS engine should speak one tone
+#[derive(Debug, Clone, PartialEq)]
+pub struct TtsStyle {
+    /// Engine-specific speaking style, e.g. "cheerful" (None = the voice's
+    /// default)
+    pub style: Option<String>,
+    /// How strongly to apply `style`, 0.01-2.0 (1.0 = the engine's default)
+    pub degree: f32,
+    /// Pitch shift in semitones
+    pub pitch_semitones: f32,
+    /// Speaking rate, 1.0 = normal
+    pub rate: f32,
+}
+
+impl Default for TtsStyle {
+    fn default() -> Self {
+        Self {
+            style: None,
+            degree: 1.0,
+            pitch_semitones: 0.0,
+            rate: 1.0,
+        }
+    }
+}
+
+impl TtsStyle {
+    /// Only pitch and rate
+    pub fn prosody(pitch_semitones: f32, rate: f32) -> Self {
+        Self {
+            pitch_semitones,
+            rate,
+            ..Self::default()
+        }
+    }
+
+    /// `self` with the named engine style
+    pub fn with_style(mut self, style: impl Into<String>) -> Self {
+        self.style = Some(style.into());
+        self
+    }
+}
+
+/// Tone to [`TtsStyle`] for one TTS engine
+#[derive(Debug, Clone, Default)]
+pub struct StyleTable {
+    styles: HashMap<Tone, TtsStyle>,
+}
+
+impl StyleTable {
+    /// Pitch and rate only, for engines without speaking styles
+    pub fn prosody() -> Self {
+        Self::default()
+            .with(Emotion::Happy, TtsStyle::prosody(1.5, 1.05))
+            .with(Emotion::Sad, TtsStyle::prosody(-2.0, 0.9))
+            .with(Emotion::Angry, TtsStyle::prosody(-1.0, 1.1))
+            .with(Emotion::Fearful, TtsStyle::prosody(2.0, 1.15))
+            .with(Emotion::Surprised, TtsStyle::prosody(3.0, 1.05))
+            .with(Emotion::Disgusted, TtsStyle::prosody(-1.5, 0.95))
+            .with(Emotion::Calm, TtsStyle::prosody(-0.5, 0.9))
+            .with(Emotion::Excited, TtsStyle::prosody(2.0, 1.15))
+    }
+
+    /// [`StyleTable::prosody`] plus SSML `express-as` styles, for engines
+    /// that take them (Azure neural voices)
+    pub fn ssml() -> Self {
+        let mut table = Self::prosody();
+        for (emotion, style) in [
+            (Emotion::Happy, "cheerful"),
+            (Emotion::Sad, "sad"),
+            (Emotion::Angry, "angry"),
+            (Emotion::Fearful, "fearful"),
+            (Emotion::Disgusted, "disgruntled"),
+            (Emotion::Calm, "calm"),
+            (Emotion::Excited, "excited"),
+        ] {
+            let tone = Tone::Canonical(emotion);
+            // The engine's style carries the emotion; keep the voice's pitch
+            let styled = TtsStyle::default().with_style(style);
+            table.styles.insert(tone, styled);
+        }
+        table
+    }
+
+    /// Speak `tone` as `style`
+    pub fn with(mut self, tone: impl Into<Tone>, style: TtsStyle) -> Self {
+        self.styles.insert(tone.into(), style);
+        self
+    }
+
+    /// Speak the custom tone `name` as `style`
+    pub fn with_custom(self, name: &str, style: TtsStyle) -> Self {
+        self.with(Tone::Custom(name.to_lowercase()), style)
+    }
+
+    /// How to speak `tone`; tones without an entry sound neutral
+    pub fn style(&self, tone: &Tone) -> TtsStyle {
+        self.styles.get(tone).cloned().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tones() {
+        assert_eq!(Tone::parse("Happy"), Tone::Canonical(Emotion::Happy));
+        assert_eq!(Tone::parse(" cheerful "), Tone::Canonical(Emotion::Happy));
+        assert_eq!(Tone::parse("ominous"), Tone::Custom("ominous".to_string()));
+        assert_eq!(Tone::parse(""), Tone::default());
+
+        let mut speak = SpeakDirective::default();
+        speak.set_tone(Emotion::Angry);
+        assert_eq!(
+            (speak.emotion_type(), speak.emotion.as_str()),
+            (Emotion::Angry, "angry")
+        );
+        speak.set_tone(Tone::parse("ominous"));
+        assert_eq!(
+            (speak.emotion_type(), speak.emotion.as_str()),
+            (Emotion::Custom, "ominous")
+        );
+        assert_eq!(speak.tone(), Tone::Custom("ominous".to_string()));
+
+        // A pre-v1.2 daemon only sends the free text
+        let old = SpeakDirective {
+            emotion: "scared".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(old.tone(), Tone::Canonical(Emotion::Fearful));
+    }
+
+    #[test]
+    fn test_style_tables() {
+        let happy = Tone::Canonical(Emotion::Happy);
+        assert!(StyleTable::prosody().style(&happy).pitch_semitones > 0.0);
+        assert_eq!(StyleTable::prosody().style(&happy).style, None);
+        assert_eq!(
+            StyleTable::ssml().style(&happy).style.as_deref(),
+            Some("cheerful")
+        );
+        // Surprise has no SSML style; it keeps its prosody
+        let surprised = Tone::Canonical(Emotion::Surprised);
+        assert_eq!(
+            StyleTable::ssml().style(&surprised),
+            StyleTable::prosody().style(&surprised)
+        );
+
+        let ominous = Tone::parse("Ominous");
+        let table = StyleTable::prosody().with_custom("ominous", TtsStyle::prosody(-3.0, 0.8));
+        assert_eq!(table.style(&ominous).rate, 0.8);
+        assert_eq!(StyleTable::prosody().style(&ominous), TtsStyle::default());
+    }
+}
@@ -0,0 +1,122 @@
+//! What NPCs hold and wear (`NpcSnapshot.equipment`, v1.2+).
+//!
+//! Planners check these before sending directives that need a tool or
+//! armor: a BreakBlockAction on ore without a pickaxe wastes minutes and
+//! drops nothing. Plugins older than v1.2 only report
+//! `NpcSnapshot.held_item`, which [`main_hand`] falls back to.
+
+use crate::block_pattern::BlockPattern;
+use crate::npc_society::v1::{Equipment, EquipmentSlot, ItemStack, NpcSnapshot};
+
+/// The item in `slot`, if any
+pub fn item(equipment: &Equipment, slot: EquipmentSlot) -> Option<&ItemStack> {
+    let item = match slot {
+        EquipmentSlot::MainHand => &equipment.main_hand,
+        EquipmentSlot::OffHand => &equipment.off_hand,
+        EquipmentSlot::Head => &equipment.head,
+        EquipmentSlot::Chest => &equipment.chest,
+        EquipmentSlot::Legs => &equipment.legs,
+        EquipmentSlot::Feet => &equipment.feet,
+        EquipmentSlot::Unspecified => &None,
+    };
+    item.as_ref().filter(|i| !i.item_type.is_empty())
+}
+
+/// Item type in the NPC's main hand (None if the hand is empty)
+pub fn main_hand(npc: &NpcSnapshot) -> Option<&str> {
+    let held = npc
+        .equipment
+        .as_ref()
+        .and_then(|e| item(e, EquipmentSlot::MainHand))
+        .map(|i| i.item_type.as_str())
+        .unwrap_or(&npc.held_item);
+    Some(held).filter(|h| !h.is_empty())
+}
+
+/// Whether the NPC holds an item matching `pattern` (an id or `*`
+/// pattern, e.g. `minecraft:*_pickaxe`) in its main hand. Tags cannot be
+/// resolved without the plugin's registry and never match.
+pub fn holding(npc: &NpcSnapshot, pattern: &str) -> bool {
+    let Ok(pattern) = BlockPattern::parse(pattern) else {
+        return false;
+    };
+    main_hand(npc).is_some_and(|held| pattern.matches(held) == Some(true))
+}
+
+/// Remaining durability, 0.0-1.0 (None for items without durability)
+pub fn durability(item: &ItemStack) -> Option<f32> {
+    (item.max_damage > 0)
+        .then(|| 1.0 - item.damage.clamp(0, item.max_damage) as f32 / item.max_damage as f32)
+}
+
+/// The slot an `EquipArmorAction` without a slot puts `item_type` in
+pub fn slot_for(item_type: &str) -> EquipmentSlot {
+    let path = item_type.rsplit(':').next().unwrap_or_default();
+    if path.ends_with("_helmet") || matches!(path, "turtle_helmet" | "carved_pumpkin") {
+        EquipmentSlot::Head
+    } else if path.ends_with("_chestplate") || path == "elytra" {
+        EquipmentSlot::Chest
+    } else if path.ends_with("_leggings") {
+        EquipmentSlot::Legs
+    } else if path.ends_with("_boots") {
+        EquipmentSlot::Feet
+    } else if path == "shield" {
+        EquipmentSlot::OffHand
+    } else {
+        EquipmentSlot::MainHand
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack(item_type: &str, damage: i32, max_damage: i32) -> ItemStack {
+        ItemStack {
+            item_type: item_type.to_string(),
+            quantity: 1,
+            damage,
+            max_damage,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_holding() {
+        let miner = NpcSnapshot {
+            held_item: "minecraft:iron_pickaxe".to_string(),
+            equipment: Some(Equipment {
+                main_hand: Some(stack("minecraft:iron_pickaxe", 200, 250)),
+                chest: Some(stack("minecraft:leather_chestplate", 0, 80)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(holding(&miner, "minecraft:*_pickaxe"));
+        assert!(!holding(&miner, "minecraft:*_sword"));
+        assert!(!holding(&miner, "#minecraft:pickaxes"));
+
+        // Plugins before v1.2 only send held_item
+        let legacy = NpcSnapshot {
+            held_item: "minecraft:stone_pickaxe".to_string(),
+            ..Default::default()
+        };
+        assert!(holding(&legacy, "*pickaxe"));
+        assert_eq!(main_hand(&NpcSnapshot::default()), None);
+
+        let equipment = miner.equipment.as_ref().unwrap();
+        assert!(item(equipment, EquipmentSlot::Head).is_none());
+        let pickaxe = item(equipment, EquipmentSlot::MainHand).unwrap();
+        assert!((durability(pickaxe).unwrap() - 0.2).abs() < 1e-6);
+        assert_eq!(durability(&stack("minecraft:torch", 0, 0)), None);
+    }
+
+    #[test]
+    fn test_slot_for() {
+        assert_eq!(slot_for("minecraft:diamond_helmet"), EquipmentSlot::Head);
+        assert_eq!(slot_for("minecraft:elytra"), EquipmentSlot::Chest);
+        assert_eq!(slot_for("minecraft:iron_boots"), EquipmentSlot::Feet);
+        assert_eq!(slot_for("minecraft:shield"), EquipmentSlot::OffHand);
+        assert_eq!(slot_for("minecraft:iron_pickaxe"), EquipmentSlot::MainHand);
+    }
+}
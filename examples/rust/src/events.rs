@@ -0,0 +1,626 @@
+//! Typed events and a handler trait.
+//!
+//! Every message on the stream is a oneof wrapped in an `Option`. Convert
+//! it to a [`ClientEvent`] or [`ServerEvent`] once (an unset oneof is an
+//! error there) and work with the payload directly, or implement
+//! [`NpcSocietyHandler`] and let [`dispatch`] call the right method:
+//!
+//! ```ignore
+//! struct Greeter;
+//!
+//! impl NpcSocietyHandler for Greeter {
+//!     fn on_chat(&self, chat: ChatObservation, cx: &ConnectionContext, tx: &Outbound) {
+//!         let speak = SpeakDirective {
+//!             directive_id: cx.next_directive_id(&chat.npc_id),
+//!             npc_id: chat.npc_id,
+//!             text: format!("Hi, {}!", chat.player_name),
+//!             ..Default::default()
+//!         };
+//!         let _ = tx.send(speak);
+//!     }
+//! }
+//!
+//! let cx = ConnectionContext::new(peer_address, now_ms);
+//! let event = ClientEvent::try_from(msg)?;
+//! events::dispatch(&Greeter, event, &cx, &tx);
+//! ```
+
+use crate::connection::ConnectionContext;
+use crate::npc_society::v1::{
+    client_message::Message as ClientMsg, server_message::Message as ServerMsg, ActionDirective,
+    ActionResult, AudioBufferStatus, AudioChunk, AudioStreamHello, BlockWatchUpdate,
+    ChangeDimensionObservation, ChatDirective, ChatObservation, ChoreographyDirective,
+    ChoreographyResult, ChunkLoadObservation, ClaimContainerDirective, ClientMessage,
+    CombatPolicyObservation, ContainerAccessObservation, DebugPathDirective,
+    DialogueChoiceObservation, DialogueOptionsDirective, DirectiveAck, DirectiveRejected,
+    EventObservation, FinishNpcTransfer, FormationDirective, FreezeNpcDirective, GuardZoneDirective,
+    Hello, HelloAck, IntruderObservation, KeepChunkLoadedDirective, LandmarkList, ListLandmarks,
+    ModerationFlag, NpcMessage, NpcTransferUpdate, PlayAudioAssetDirective, PlayMusicDirective,
+    PlayParticleDirective, PlaySoundDirective, PrepareNpcTransfer, Probe, ProbeReply,
+    QuarantineNpcDirective, QuestOffer, QuestUpdate, RegisterAudioAsset, RegisterLandmark,
+    RemoveDisplayDirective, RequestVoiceCapture, RestoreNpcState, ResumeNpcDirective, ServerMessage,
+    SetCombatPolicyDirective, SetTimeDirective, SetWeatherDirective, ShopDefinition,
+    ShopTradeObservation, ShowDisplayDirective, SpawnTransferredNpc, SpeakDirective, SpeakResult,
+    SpeechInterrupted, StationOutputObservation, StopSpeaking, StopVoiceCapture, SubscribeEvents,
+    TransactionObservation, TransferCurrencyDirective, TransportItemsDirective,
+    TransportItemsResult, TransportProgress, VisemeTimeline, VoiceConsentObservation, VoicePcmFrame,
+    WorldBootstrap, WorldTick,
+};
+use crate::outbound::Outbound;
+use crate::validate::ValidationError;
+
+/// Generates the event enum plus conversions to and from its message
+macro_rules! event_enum {
+    (
+        $(#[$doc:meta])*
+        $event:ident <=> $message:ident / $oneof:ident {
+            $($(#[$variant_doc:meta])* $variant:ident($payload:ident) = $field:ident,)+
+        }
+    ) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum $event {
+            $($(#[$variant_doc])* $variant($payload),)+
+        }
+
+        impl TryFrom<$message> for $event {
+            type Error = ValidationError;
+
+            /// Fails only if the oneof is unset
+            fn try_from(message: $message) -> Result<Self, ValidationError> {
+                match message.message {
+                    $(Some($oneof::$field(payload)) => Ok(Self::$variant(payload)),)+
+                    None => Err(ValidationError::EmptyMessage(stringify!($message))),
+                }
+            }
+        }
+
+        impl From<$event> for $message {
+            fn from(event: $event) -> Self {
+                let message = match event {
+                    $($event::$variant(payload) => $oneof::$field(payload),)+
+                };
+                Self {
+                    message: Some(message),
+                    ..Default::default()
+                }
+            }
+        }
+
+        impl $event {
+            /// Every variant with its message's oneof variant, e.g.
+            /// `("Chat", "ChatObservation")`; [`NpcSocietyHandler`] and the
+            /// generated bindings name their methods after the first
+            pub const VARIANTS: &'static [(&'static str, &'static str)] =
+                &[$((stringify!($variant), stringify!($field)),)+];
+
+            /// Name of the message's oneof variant, e.g. `WorldTick`
+            pub fn message_type(&self) -> &'static str {
+                match self {
+                    $($event::$variant(_) => stringify!($field),)+
+                }
+            }
+
+            /// [`Self::message_type`] of a message not converted yet (None
+            /// if its oneof is unset)
+            pub fn type_of(message: &$message) -> Option<&'static str> {
+                match &message.message {
+                    $(Some($oneof::$field(_)) => Some(stringify!($field)),)+
+                    None => None,
+                }
+            }
+        }
+
+    };
+}
+
+event_enum! {
+    /// A message from the plugin
+    ClientEvent <=> ClientMessage / ClientMsg {
+        /// Handshake, first message on the stream
+        Hello(Hello) = Hello,
+        /// Periodic world state
+        WorldTick(WorldTick) = WorldTick,
+        /// A player chatted near an NPC
+        Chat(ChatObservation) = ChatObservation,
+        /// A game event near an NPC
+        Event(EventObservation) = EventObservation,
+        /// Voice audio from a player talking to an NPC
+        VoiceFrame(VoicePcmFrame) = VoicePcmFrame,
+        /// A directive finished
+        ActionResult(ActionResult) = ActionResult,
+        /// A player talked over an NPC
+        SpeechInterrupted(SpeechInterrupted) = SpeechInterrupted,
+        /// An NPC finished speaking
+        SpeakResult(SpeakResult) = SpeakResult,
+        /// Message between NPCs
+        NpcMessage(NpcMessage) = NpcMessage,
+        /// A player answered or progressed on a quest
+        QuestUpdate(QuestUpdate) = QuestUpdate,
+        /// Currency moved between an NPC and a player
+        Transaction(TransactionObservation) = TransactionObservation,
+        /// An NPC arrived in another world
+        ChangeDimension(ChangeDimensionObservation) = ChangeDimension,
+        /// Changes in a watched region
+        BlockWatchUpdate(BlockWatchUpdate) = BlockWatchUpdate,
+        /// A furnace or brewing stand finished
+        StationOutput(StationOutputObservation) = StationOutput,
+        /// An NPC's combat policy acted on its own
+        CombatPolicy(CombatPolicyObservation) = CombatPolicy,
+        /// The plugin refused a directive without acting on it
+        DirectiveRejected(DirectiveRejected) = DirectiveRejected,
+        /// The plugin received a directive
+        DirectiveAck(DirectiveAck) = DirectiveAck,
+        /// Progress of a cross-server NPC transfer
+        NpcTransfer(NpcTransferUpdate) = NpcTransferUpdate,
+        /// A scene ended
+        ChoreographyResult(ChoreographyResult) = ChoreographyResult,
+        /// A player clicked a dialogue option, or the prompt closed
+        DialogueChoice(DialogueChoiceObservation) = DialogueChoice,
+        /// A player traded in an NPC's shop
+        ShopTrade(ShopTradeObservation) = ShopTrade,
+        /// How much of an audio stream the plugin has queued
+        AudioBuffer(AudioBufferStatus) = AudioBuffer,
+        /// A guard spotted or lost an intruder in its zone
+        Intruder(IntruderObservation) = Intruder,
+        /// A player opened, looted or was locked out of an NPC's container
+        ContainerAccess(ContainerAccessObservation) = ContainerAccess,
+        /// Landmarks asked for, or changed
+        LandmarkList(LandmarkList) = LandmarkList,
+        /// A player granted or withdrew consent to voice capture
+        VoiceConsent(VoiceConsentObservation) = VoiceConsent,
+        /// A player or moderator flagged what an NPC said or did
+        ModerationFlag(ModerationFlag) = ModerationFlag,
+        /// Opens a ConnectAudio stream; the transport handles it
+        AudioStreamHello(AudioStreamHello) = AudioStreamHello,
+        /// The world as it was when the connection started
+        WorldBootstrap(WorldBootstrap) = WorldBootstrap,
+        /// The plugin's echo of a Probe
+        ProbeReply(ProbeReply) = ProbeReply,
+        /// An NPC's chunk unloaded, parking it, or loaded again
+        ChunkLoad(ChunkLoadObservation) = ChunkLoad,
+        /// A leg of a courier run started or ended
+        TransportProgress(TransportProgress) = TransportProgress,
+        /// A courier run ended
+        TransportItemsResult(TransportItemsResult) = TransportItemsResult,
+    }
+}
+
+event_enum! {
+    /// A message from the daemon
+    ServerEvent <=> ServerMessage / ServerMsg {
+        /// An action for an NPC
+        ActionDirective(ActionDirective) = ActionDirective,
+        /// A line for an NPC to say
+        SpeakDirective(SpeakDirective) = SpeakDirective,
+        /// Audio for a SpeakDirective
+        AudioChunk(AudioChunk) = AudioChunk,
+        /// Handshake reply
+        HelloAck(HelloAck) = HelloAck,
+        /// Cut off an NPC's speech
+        StopSpeaking(StopSpeaking) = StopSpeaking,
+        /// Lip-sync cues for a speech stream
+        VisemeTimeline(VisemeTimeline) = VisemeTimeline,
+        /// Message between NPCs
+        NpcMessage(NpcMessage) = NpcMessage,
+        /// A quest for a player
+        QuestOffer(QuestOffer) = QuestOffer,
+        /// Move currency between an NPC and a player
+        TransferCurrency(TransferCurrencyDirective) = TransferCurrency,
+        /// Configure an NPC's plugin-side self-defense
+        SetCombatPolicy(SetCombatPolicyDirective) = SetCombatPolicy,
+        /// Choose which EventObservations the plugin sends
+        SubscribeEvents(SubscribeEvents) = SubscribeEvents,
+        /// Put an NPC back as exported
+        RestoreNpcState(RestoreNpcState) = RestoreNpcState,
+        /// Freeze an NPC to move it to another server
+        PrepareNpcTransfer(PrepareNpcTransfer) = PrepareNpcTransfer,
+        /// Spawn an NPC moved from another server
+        SpawnTransferredNpc(SpawnTransferredNpc) = SpawnTransferredNpc,
+        /// Commit or roll back an NPC transfer
+        FinishNpcTransfer(FinishNpcTransfer) = FinishNpcTransfer,
+        /// Plain chat message to players
+        Chat(ChatDirective) = Chat,
+        /// Halt an NPC plugin-side
+        FreezeNpc(FreezeNpcDirective) = FreezeNpc,
+        /// End a freeze
+        ResumeNpc(ResumeNpcDirective) = ResumeNpc,
+        /// Timed steps across NPCs, run plugin-side
+        Choreography(ChoreographyDirective) = Choreography,
+        /// Clickable replies for a player
+        DialogueOptions(DialogueOptionsDirective) = DialogueOptions,
+        /// An NPC's shop GUI
+        Shop(ShopDefinition) = Shop,
+        /// Create or update a hologram, boss bar or scoreboard
+        ShowDisplay(ShowDisplayDirective) = ShowDisplay,
+        /// Remove a display
+        RemoveDisplay(RemoveDisplayDirective) = RemoveDisplay,
+        /// Particles at an NPC or position
+        PlayParticle(PlayParticleDirective) = PlayParticle,
+        /// A sound at an NPC or position
+        PlaySound(PlaySoundDirective) = PlaySound,
+        /// Set a world's time of day (world control)
+        SetTime(SetTimeDirective) = SetTime,
+        /// Set a world's weather (world control)
+        SetWeather(SetWeatherDirective) = SetWeather,
+        /// A clip for the plugin to cache
+        RegisterAudioAsset(RegisterAudioAsset) = RegisterAudioAsset,
+        /// Speech from a cached clip
+        PlayAudioAsset(PlayAudioAssetDirective) = PlayAudioAsset,
+        /// A note block song performed by an NPC
+        PlayMusic(PlayMusicDirective) = PlayMusic,
+        /// NPCs keeping places around a leader
+        Formation(FormationDirective) = Formation,
+        /// An NPC guarding a zone plugin-side
+        GuardZone(GuardZoneDirective) = GuardZone,
+        /// A container claimed as an NPC's storage
+        ClaimContainer(ClaimContainerDirective) = ClaimContainer,
+        /// A landmark set or removed
+        RegisterLandmark(RegisterLandmark) = RegisterLandmark,
+        /// A request for the plugin's landmarks
+        ListLandmarks(ListLandmarks) = ListLandmarks,
+        /// Voice capture started for an NPC
+        RequestVoiceCapture(RequestVoiceCapture) = RequestVoiceCapture,
+        /// Voice capture stopped for an NPC
+        StopVoiceCapture(StopVoiceCapture) = StopVoiceCapture,
+        /// An NPC taken out of play pending review, or released
+        QuarantineNpc(QuarantineNpcDirective) = QuarantineNpc,
+        /// An NPC's path shown to or hidden from a player
+        DebugPath(DebugPathDirective) = DebugPath,
+        /// A self-check the plugin echoes
+        Probe(Probe) = Probe,
+        /// An NPC's chunks kept loaded, or released
+        KeepChunkLoaded(KeepChunkLoadedDirective) = KeepChunkLoaded,
+        /// Courier runs between two containers, run plugin-side
+        TransportItems(TransportItemsDirective) = TransportItems,
+    }
+}
+
+impl ClientEvent {
+    /// The NPC the event is about (empty for Hello, WorldTick,
+    /// ChoreographyResult, LandmarkList, VoiceConsent, AudioStreamHello,
+    /// WorldBootstrap and ProbeReply)
+    pub fn npc_id(&self) -> &str {
+        match self {
+            Self::Hello(_)
+            | Self::WorldTick(_)
+            | Self::ChoreographyResult(_)
+            | Self::LandmarkList(_)
+            | Self::VoiceConsent(_)
+            | Self::AudioStreamHello(_)
+            | Self::WorldBootstrap(_)
+            | Self::ProbeReply(_) => "",
+            Self::Chat(m) => &m.npc_id,
+            Self::Event(m) => &m.npc_id,
+            Self::VoiceFrame(m) => &m.npc_id,
+            Self::ActionResult(m) => &m.npc_id,
+            Self::SpeechInterrupted(m) => &m.npc_id,
+            Self::SpeakResult(m) => &m.npc_id,
+            Self::NpcMessage(m) => &m.sender_npc_id,
+            Self::QuestUpdate(m) => &m.npc_id,
+            Self::Transaction(m) => &m.npc_id,
+            Self::ChangeDimension(m) => &m.npc_id,
+            Self::BlockWatchUpdate(m) => &m.npc_id,
+            Self::StationOutput(m) => &m.npc_id,
+            Self::CombatPolicy(m) => &m.npc_id,
+            Self::DirectiveRejected(m) => &m.npc_id,
+            Self::DirectiveAck(m) => &m.npc_id,
+            Self::NpcTransfer(m) => &m.npc_id,
+            Self::DialogueChoice(m) => &m.npc_id,
+            Self::ShopTrade(m) => &m.npc_id,
+            Self::AudioBuffer(m) => &m.npc_id,
+            Self::Intruder(m) => &m.npc_id,
+            Self::ContainerAccess(m) => &m.npc_id,
+            Self::ModerationFlag(m) => &m.npc_id,
+            Self::ChunkLoad(m) => &m.npc_id,
+            Self::TransportProgress(m) => &m.npc_id,
+            Self::TransportItemsResult(m) => &m.npc_id,
+        }
+    }
+}
+
+/// Daemon logic, one method per [`ClientEvent`]. Every method defaults to
+/// doing nothing, so implement only what you need.
+///
+/// Methods take `&self` so one handler can serve the stream and unary
+/// RPCs at once; keep mutable state behind a lock. `cx` is the connection
+/// the message came in on (server_id, capabilities, ids); replies go to
+/// `tx`.
+/// Methods run on the stream's task: spawn a task for anything slow.
+#[allow(unused_variables)]
+pub trait NpcSocietyHandler {
+    /// Handshake from the plugin; reply with a HelloAck
+    fn on_hello(&self, hello: Hello, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// Everything the plugin knows right after Hello: all NPCs, landmarks,
+    /// claims and each world's clock and weather. Feed it to
+    /// `WorldModel::ingest_bootstrap` and `Landmarks::ingest_bootstrap`.
+    fn on_world_bootstrap(
+        &self,
+        bootstrap: WorldBootstrap,
+        cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+    }
+
+    /// Periodic world state
+    fn on_world_tick(&self, tick: WorldTick, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// A player chatted near an NPC
+    fn on_chat(&self, chat: ChatObservation, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// A game event near an NPC
+    fn on_event(&self, event: EventObservation, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// Voice audio from a player talking to an NPC
+    fn on_voice_frame(&self, frame: VoicePcmFrame, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// A directive finished
+    fn on_action_result(&self, result: ActionResult, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// A player talked over an NPC
+    fn on_speech_interrupted(
+        &self,
+        interrupted: SpeechInterrupted,
+        cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+    }
+
+    /// An NPC finished speaking
+    fn on_speak_result(&self, spoken: SpeakResult, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// Message between NPCs
+    fn on_npc_message(&self, message: NpcMessage, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// A player answered or progressed on a quest
+    fn on_quest_update(&self, update: QuestUpdate, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// Currency moved between an NPC and a player
+    fn on_transaction(
+        &self,
+        transaction: TransactionObservation,
+        cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+    }
+
+    /// An NPC arrived in another world
+    fn on_change_dimension(
+        &self,
+        change: ChangeDimensionObservation,
+        cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+    }
+
+    /// Changes in a watched region
+    fn on_block_watch_update(
+        &self,
+        update: BlockWatchUpdate,
+        cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+    }
+
+    /// A furnace or brewing stand finished
+    fn on_station_output(
+        &self,
+        output: StationOutputObservation,
+        cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+    }
+
+    /// An NPC's combat policy engaged, disengaged or fled
+    fn on_combat_policy(
+        &self,
+        observation: CombatPolicyObservation,
+        cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+    }
+
+    /// A directive was rejected; no result will come for it
+    fn on_directive_rejected(
+        &self,
+        rejected: DirectiveRejected,
+        cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+    }
+
+    /// The plugin received a directive; its result follows when it finishes
+    fn on_directive_ack(&self, ack: DirectiveAck, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// A server reached a stage of a cross-server NPC transfer
+    fn on_npc_transfer(&self, update: NpcTransferUpdate, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// A ChoreographyDirective's scene ended
+    fn on_choreography_result(
+        &self,
+        result: ChoreographyResult,
+        cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+    }
+
+    /// A player answered a DialogueOptionsDirective, or it closed unanswered
+    fn on_dialogue_choice(
+        &self,
+        choice: DialogueChoiceObservation,
+        cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+    }
+
+    /// A player bought from or sold to an NPC's shop, or failed to
+    fn on_shop_trade(&self, trade: ShopTradeObservation, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// The plugin reported the playback buffer of an audio stream
+    fn on_audio_buffer(&self, status: AudioBufferStatus, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// A guard spotted an intruder in its zone, or one left
+    fn on_intruder(&self, observation: IntruderObservation, cx: &ConnectionContext, tx: &Outbound) {
+    }
+
+    /// A player opened, looted or was locked out of an NPC's container
+    fn on_container_access(
+        &self,
+        access: ContainerAccessObservation,
+        cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+    }
+
+    /// The plugin answered a ListLandmarks, or landmarks changed
+    fn on_landmark_list(&self, list: LandmarkList, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// A player granted or withdrew consent to voice capture
+    fn on_voice_consent(
+        &self,
+        consent: VoiceConsentObservation,
+        cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+    }
+
+    /// A player or moderator flagged what an NPC said or did; answer with
+    /// a QuarantineNpcDirective to take the NPC out of play
+    fn on_moderation_flag(&self, flag: ModerationFlag, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// The plugin echoed a Probe; give it to the `probe::SelfTest` that
+    /// sent it
+    fn on_probe_reply(&self, reply: ProbeReply, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// An NPC's chunk unloaded or loaded again; while unloaded its
+    /// directives are parked, so pause their deadlines
+    fn on_chunk_load(&self, chunk: ChunkLoadObservation, cx: &ConnectionContext, tx: &Outbound) {}
+
+    /// A leg of a TransportItemsDirective's courier run started or ended
+    fn on_transport_progress(
+        &self,
+        progress: TransportProgress,
+        cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+    }
+
+    /// A TransportItemsDirective's courier run ended
+    fn on_transport_items_result(
+        &self,
+        result: TransportItemsResult,
+        cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+    }
+}
+
+/// Call the `handler` method for `event`, which arrived on the connection
+/// `cx`. A Hello is recorded in `cx` before `on_hello` runs.
+pub fn dispatch<H: NpcSocietyHandler + ?Sized>(
+    handler: &H,
+    event: ClientEvent,
+    cx: &ConnectionContext,
+    tx: &Outbound,
+) {
+    match event {
+        ClientEvent::Hello(m) => {
+            // A second Hello on the same stream does not change it
+            cx.record_hello(&m);
+            handler.on_hello(m, cx, tx)
+        }
+        ClientEvent::WorldBootstrap(m) => handler.on_world_bootstrap(m, cx, tx),
+        ClientEvent::ProbeReply(m) => handler.on_probe_reply(m, cx, tx),
+        ClientEvent::ChunkLoad(m) => handler.on_chunk_load(m, cx, tx),
+        ClientEvent::WorldTick(m) => handler.on_world_tick(m, cx, tx),
+        ClientEvent::Chat(m) => handler.on_chat(m, cx, tx),
+        ClientEvent::Event(m) => handler.on_event(m, cx, tx),
+        ClientEvent::VoiceFrame(m) => handler.on_voice_frame(m, cx, tx),
+        ClientEvent::ActionResult(m) => handler.on_action_result(m, cx, tx),
+        ClientEvent::SpeechInterrupted(m) => handler.on_speech_interrupted(m, cx, tx),
+        ClientEvent::SpeakResult(m) => handler.on_speak_result(m, cx, tx),
+        ClientEvent::NpcMessage(m) => handler.on_npc_message(m, cx, tx),
+        ClientEvent::QuestUpdate(m) => handler.on_quest_update(m, cx, tx),
+        ClientEvent::Transaction(m) => handler.on_transaction(m, cx, tx),
+        ClientEvent::ChangeDimension(m) => handler.on_change_dimension(m, cx, tx),
+        ClientEvent::BlockWatchUpdate(m) => handler.on_block_watch_update(m, cx, tx),
+        ClientEvent::StationOutput(m) => handler.on_station_output(m, cx, tx),
+        ClientEvent::CombatPolicy(m) => handler.on_combat_policy(m, cx, tx),
+        ClientEvent::DirectiveRejected(m) => handler.on_directive_rejected(m, cx, tx),
+        ClientEvent::DirectiveAck(m) => handler.on_directive_ack(m, cx, tx),
+        ClientEvent::NpcTransfer(m) => handler.on_npc_transfer(m, cx, tx),
+        ClientEvent::ChoreographyResult(m) => handler.on_choreography_result(m, cx, tx),
+        ClientEvent::TransportProgress(m) => handler.on_transport_progress(m, cx, tx),
+        ClientEvent::TransportItemsResult(m) => handler.on_transport_items_result(m, cx, tx),
+        ClientEvent::DialogueChoice(m) => handler.on_dialogue_choice(m, cx, tx),
+        ClientEvent::ShopTrade(m) => handler.on_shop_trade(m, cx, tx),
+        ClientEvent::AudioBuffer(m) => handler.on_audio_buffer(m, cx, tx),
+        ClientEvent::Intruder(m) => handler.on_intruder(m, cx, tx),
+        ClientEvent::ContainerAccess(m) => handler.on_container_access(m, cx, tx),
+        ClientEvent::LandmarkList(m) => handler.on_landmark_list(m, cx, tx),
+        ClientEvent::VoiceConsent(m) => handler.on_voice_consent(m, cx, tx),
+        ClientEvent::ModerationFlag(m) => handler.on_moderation_flag(m, cx, tx),
+        // Only meaningful as the first message of ConnectAudio, which pairs
+        // the stream with its connection before any handler sees it
+        ClientEvent::AudioStreamHello(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outbound::{self, QueueConfig};
+    use tokio_stream::StreamExt;
+
+    struct Echo;
+
+    impl NpcSocietyHandler for Echo {
+        fn on_chat(&self, chat: ChatObservation, _cx: &ConnectionContext, tx: &Outbound) {
+            let speak = SpeakDirective {
+                npc_id: chat.npc_id,
+                text: chat.message,
+                ..Default::default()
+            };
+            tx.send(speak).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let (tx, mut rx) = outbound::queue(QueueConfig::default());
+        let chat = ClientMessage::from(ChatObservation {
+            npc_id: "parrot".to_string(),
+            message: "hello".to_string(),
+            ..Default::default()
+        });
+        let event = ClientEvent::try_from(chat).unwrap();
+        assert_eq!(event.npc_id(), "parrot");
+        let cx = ConnectionContext::new("127.0.0.1:40000", 0);
+        dispatch(&Echo, event, &cx, &tx);
+        // Unhandled events are ignored
+        dispatch(
+            &Echo,
+            ClientEvent::WorldTick(WorldTick::default()),
+            &cx,
+            &tx,
+        );
+        let hello = Hello {
+            server_id: "survival".to_string(),
+            ..Default::default()
+        };
+        dispatch(&Echo, ClientEvent::Hello(hello), &cx, &tx);
+        assert_eq!(cx.server_id(), "survival");
+
+        drop(tx);
+        match ServerEvent::try_from(rx.next().await.unwrap()) {
+            Ok(ServerEvent::SpeakDirective(speak)) => assert_eq!(speak.text, "hello"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(rx.next().await.is_none());
+        assert_eq!(
+            ClientEvent::try_from(ClientMessage::default()),
+            Err(ValidationError::EmptyMessage("ClientMessage"))
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Group movement with `FormationDirective` (v1.2+).
+//!
+//! NPCs walking together with a MoveAction each drift apart: every path is
+//! planned alone and every result arrives on its own tick. A
+//! FormationDirective hands the whole group to the plugin instead, which
+//! keeps each member at its place around a leader. [`escort`] rings a
+//! player with guards, [`disband`] ends a formation, and [`places`] /
+//! [`place_position`] lay places out the way the plugin does, to check
+//! who is out of place in a WorldTick.
+
+use crate::geom::Vec3;
+use crate::npc_society::v1::{
+    formation_directive::Leader, FormationDirective, FormationMember, FormationShape, Position,
+};
+
+/// Spacing of a formation that sets none, in blocks
+pub const DEFAULT_SPACING: f32 = 2.0;
+/// Most members a FormationDirective may have
+pub const MAX_FORMATION_MEMBERS: usize = 32;
+
+/// `npc_ids` in a ring around the player `player_uuid`, `radius` blocks out
+pub fn escort(
+    formation_id: &str,
+    player_uuid: &str,
+    npc_ids: &[String],
+    radius: f32,
+) -> FormationDirective {
+    FormationDirective {
+        formation_id: formation_id.to_string(),
+        leader: Some(Leader::LeaderPlayerUuid(player_uuid.to_string())),
+        shape: FormationShape::EscortRing as i32,
+        members: npc_ids
+            .iter()
+            .map(|npc_id| FormationMember {
+                npc_id: npc_id.clone(),
+                ..Default::default()
+            })
+            .collect(),
+        spacing: radius,
+        ..Default::default()
+    }
+}
+
+/// End the formation `formation_id`; its members stop where they are
+pub fn disband(formation_id: &str) -> FormationDirective {
+    FormationDirective {
+        formation_id: formation_id.to_string(),
+        ..Default::default()
+    }
+}
+
+/// Each member's place as `(right, ahead)` blocks from the leader, in
+/// member order
+pub fn places(formation: &FormationDirective) -> Vec<(f32, f32)> {
+    let spacing = if formation.spacing > 0.0 {
+        formation.spacing
+    } else {
+        DEFAULT_SPACING
+    };
+    let count = formation.members.len();
+    formation
+        .members
+        .iter()
+        .enumerate()
+        .map(|(i, member)| match formation.shape() {
+            FormationShape::Unspecified | FormationShape::Line => {
+                (0.0, -(i as f32 + 1.0) * spacing)
+            }
+            FormationShape::Wedge => {
+                let rank = (i / 2 + 1) as f32 * spacing;
+                let side = if i % 2 == 0 { -1.0 } else { 1.0 };
+                (side * rank, -rank)
+            }
+            FormationShape::EscortRing => {
+                let angle = std::f32::consts::TAU * i as f32 / count as f32;
+                (angle.sin() * spacing, angle.cos() * spacing)
+            }
+            FormationShape::Custom => (member.offset_right, member.offset_ahead),
+        })
+        .collect()
+}
+
+/// Where `(right, ahead)` is for a leader standing at `leader`, facing its
+/// yaw. Keeps the leader's world, height and facing.
+pub fn place_position(leader: &Position, (right, ahead): (f32, f32)) -> Position {
+    let forward = Vec3::from_yaw_pitch(leader.yaw, 0.0);
+    // A quarter turn clockwise seen from above
+    let rightward = Vec3::new(-forward.z, 0.0, forward.x);
+    let offset = forward * ahead as f64 + rightward * right as f64;
+    Position {
+        x: leader.x + offset.x,
+        z: leader.z + offset.z,
+        ..leader.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formation(shape: FormationShape, members: usize) -> FormationDirective {
+        FormationDirective {
+            shape: shape as i32,
+            members: (0..members)
+                .map(|i| FormationMember {
+                    npc_id: format!("guard_{}", i),
+                    offset_right: i as f32,
+                    offset_ahead: 1.0,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn near(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4
+    }
+
+    #[test]
+    fn test_places() {
+        assert_eq!(
+            places(&formation(FormationShape::Unspecified, 2)),
+            [(0.0, -2.0), (0.0, -4.0)]
+        );
+        assert_eq!(
+            places(&formation(FormationShape::Wedge, 3)),
+            [(-2.0, -2.0), (2.0, -2.0), (-4.0, -4.0)]
+        );
+        assert_eq!(
+            places(&formation(FormationShape::Custom, 2)),
+            [(0.0, 1.0), (1.0, 1.0)]
+        );
+
+        let ring = places(&FormationDirective {
+            spacing: 3.0,
+            ..formation(FormationShape::EscortRing, 4)
+        });
+        let expected = [(0.0, 3.0), (3.0, 0.0), (0.0, -3.0), (-3.0, 0.0)];
+        assert!(ring.iter().zip(expected).all(|(a, b)| near(*a, b)));
+    }
+
+    #[test]
+    fn test_place_position() {
+        // Facing west (-x): right is north (-z)
+        let leader = Position {
+            world: "world".to_string(),
+            x: 10.0,
+            y: 64.0,
+            z: 10.0,
+            yaw: 90.0,
+            ..Default::default()
+        };
+        let place = place_position(&leader, (2.0, -1.0));
+        assert!((place.x - 11.0).abs() < 1e-9);
+        assert!((place.z - 8.0).abs() < 1e-9);
+        assert_eq!((place.y, place.yaw), (64.0, 90.0));
+
+        let guards = ["guard_0".to_string(), "guard_1".to_string()];
+        let escort = escort("escort-p1", "p1", &guards, 3.0);
+        assert_eq!(
+            escort.leader,
+            Some(Leader::LeaderPlayerUuid("p1".to_string()))
+        );
+        assert_eq!(places(&escort).len(), 2);
+        assert!(disband("escort-p1").members.is_empty());
+    }
+}
@@ -0,0 +1,415 @@
+//! Finite state machines for reactive NPC behavior.
+//!
+//! Many behaviors are a handful of states ("patrol", "chase", "return")
+//! and the events that move between them. A [`StateMachine`] is declared
+//! with a builder instead of hand-written match arms: each state may send
+//! an action when it is entered, and each transition names the state it
+//! leaves, a [`Trigger`] and the state it enters. Feed it every
+//! [`ClientEvent`] for the NPC with [`StateMachine::handle`] and send the
+//! directives it returns.
+//!
+//! ```
+//! use npc_society_example::fsm::{StateMachine, Trigger};
+//!
+//! #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+//! enum Guard {
+//!     Patrol,
+//!     Rest,
+//! }
+//!
+//! let machine = StateMachine::builder("guard", Guard::Patrol)
+//!     .transition(Guard::Patrol, Trigger::Succeeded, Guard::Rest)
+//!     .transition(Guard::Rest, Trigger::After(100), Guard::Patrol)
+//!     .build();
+//! assert_eq!(machine.state(), Guard::Patrol);
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use crate::events::ClientEvent;
+use crate::ids::DirectiveIdFactory;
+use crate::npc_society::v1::{action_directive::Action, ActionDirective, NpcSnapshot};
+
+type Enter = Box<dyn Fn(&NpcSnapshot) -> Option<Action> + Send + Sync>;
+
+/// What moves the machine from one state to another
+pub enum Trigger {
+    /// The directive sent on entering the state succeeded
+    Succeeded,
+    /// The directive sent on entering the state failed (or was rejected)
+    Failed,
+    /// This many server ticks passed since entering the state
+    After(i64),
+    /// A WorldTick's snapshot of the NPC matches
+    When(Box<dyn Fn(&NpcSnapshot) -> bool + Send + Sync>),
+    /// An event about the NPC matches
+    On(Box<dyn Fn(&ClientEvent) -> bool + Send + Sync>),
+}
+
+impl Trigger {
+    /// [`Trigger::When`] from a closure
+    pub fn when(check: impl Fn(&NpcSnapshot) -> bool + Send + Sync + 'static) -> Self {
+        Trigger::When(Box::new(check))
+    }
+
+    /// [`Trigger::On`] from a closure
+    pub fn on(check: impl Fn(&ClientEvent) -> bool + Send + Sync + 'static) -> Self {
+        Trigger::On(Box::new(check))
+    }
+}
+
+impl fmt::Debug for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::Succeeded => f.write_str("Succeeded"),
+            Trigger::Failed => f.write_str("Failed"),
+            Trigger::After(ticks) => write!(f, "After({ticks})"),
+            Trigger::When(_) => f.write_str("When(..)"),
+            Trigger::On(_) => f.write_str("On(..)"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Transition<S> {
+    // None = from any state
+    from: Option<S>,
+    trigger: Trigger,
+    to: S,
+}
+
+/// Declares a [`StateMachine`]
+pub struct StateMachineBuilder<S> {
+    machine: StateMachine<S>,
+}
+
+impl<S: Copy + Eq + Hash + fmt::Debug> StateMachineBuilder<S> {
+    /// Send the action returned by `make` with `priority` whenever `state`
+    /// is entered (None sends nothing)
+    pub fn on_enter(
+        mut self,
+        state: S,
+        priority: i32,
+        make: impl Fn(&NpcSnapshot) -> Option<Action> + Send + Sync + 'static,
+    ) -> Self {
+        self.machine.enter.insert(state, (priority, Box::new(make)));
+        self
+    }
+
+    /// Go from `from` to `to` on `trigger`. Transitions are tried in the
+    /// order they were declared; going to the current state enters it again.
+    pub fn transition(mut self, from: S, trigger: Trigger, to: S) -> Self {
+        self.machine.transitions.push(Transition {
+            from: Some(from),
+            trigger,
+            to,
+        });
+        self
+    }
+
+    /// Go to `to` on `trigger` from any state other than `to`
+    pub fn from_any(mut self, trigger: Trigger, to: S) -> Self {
+        self.machine.transitions.push(Transition {
+            from: None,
+            trigger,
+            to,
+        });
+        self
+    }
+
+    /// Take directive ids from `ids` instead of the machine's own counter
+    pub fn with_ids(mut self, ids: Arc<DirectiveIdFactory>) -> Self {
+        self.machine.ids = Some(ids);
+        self
+    }
+
+    /// The machine, in its initial state; that state is entered on the
+    /// first WorldTick that reports the NPC
+    pub fn build(self) -> StateMachine<S> {
+        self.machine
+    }
+}
+
+/// A state machine driving one NPC.
+pub struct StateMachine<S> {
+    npc: NpcSnapshot,
+    state: S,
+    started: bool,
+    enter: HashMap<S, (i32, Enter)>,
+    transitions: Vec<Transition<S>>,
+    // Directive sent on entering the current state, until its result
+    pending: Option<String>,
+    server_tick: i64,
+    entered_at: i64,
+    counter: u64,
+    ids: Option<Arc<DirectiveIdFactory>>,
+}
+
+impl<S: Copy + Eq + Hash + fmt::Debug> StateMachine<S> {
+    /// Start declaring a machine for `npc_id` starting in `initial`
+    pub fn builder(npc_id: &str, initial: S) -> StateMachineBuilder<S> {
+        StateMachineBuilder {
+            machine: StateMachine {
+                npc: NpcSnapshot {
+                    npc_id: npc_id.to_string(),
+                    ..NpcSnapshot::default()
+                },
+                state: initial,
+                started: false,
+                enter: HashMap::new(),
+                transitions: Vec::new(),
+                pending: None,
+                server_tick: 0,
+                entered_at: 0,
+                counter: 0,
+                ids: None,
+            },
+        }
+    }
+
+    /// The current state
+    pub fn state(&self) -> S {
+        self.state
+    }
+
+    /// Latest snapshot of the NPC
+    pub fn npc(&self) -> &NpcSnapshot {
+        &self.npc
+    }
+
+    /// Whether the directive sent on entering the current state is still
+    /// waiting for its result
+    pub fn is_waiting(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Apply an event. At most one transition is taken per event. Returns
+    /// the directives to send; directive_ids are `<npc_id>-fsm-<n>`, or
+    /// come from the factory given to [`StateMachineBuilder::with_ids`].
+    /// Events about other NPCs are ignored.
+    pub fn handle(&mut self, event: &ClientEvent) -> Vec<ActionDirective> {
+        let npc_id = event.npc_id();
+        if !npc_id.is_empty() && npc_id != self.npc.npc_id {
+            return Vec::new();
+        }
+
+        let mut outcome = None;
+        match event {
+            ClientEvent::WorldTick(tick) => {
+                let Some(npc) = tick.npcs.iter().find(|npc| npc.npc_id == self.npc.npc_id) else {
+                    return Vec::new();
+                };
+                self.npc = npc.clone();
+                self.server_tick = tick.server_tick;
+                if !self.started {
+                    self.started = true;
+                    return self.enter(self.state);
+                }
+            }
+            ClientEvent::ActionResult(result)
+                if self.pending.as_ref() == Some(&result.directive_id) =>
+            {
+                self.pending = None;
+                outcome = Some(result.success);
+            }
+            ClientEvent::DirectiveRejected(rejected)
+                if self.pending.as_ref() == Some(&rejected.directive_id) =>
+            {
+                self.pending = None;
+                outcome = Some(false);
+            }
+            _ => {}
+        }
+        if !self.started {
+            return Vec::new();
+        }
+
+        let next = self
+            .transitions
+            .iter()
+            .filter(|t| match t.from {
+                Some(from) => from == self.state,
+                None => t.to != self.state,
+            })
+            .find(|t| match &t.trigger {
+                Trigger::Succeeded => outcome == Some(true),
+                Trigger::Failed => outcome == Some(false),
+                Trigger::After(ticks) => {
+                    matches!(event, ClientEvent::WorldTick(_))
+                        && self.server_tick - self.entered_at >= *ticks
+                }
+                Trigger::When(check) => {
+                    matches!(event, ClientEvent::WorldTick(_)) && check(&self.npc)
+                }
+                Trigger::On(check) => check(event),
+            })
+            .map(|t| t.to);
+        match next {
+            Some(state) => self.enter(state),
+            None => Vec::new(),
+        }
+    }
+
+    fn enter(&mut self, state: S) -> Vec<ActionDirective> {
+        self.state = state;
+        self.entered_at = self.server_tick;
+        self.pending = None;
+        let Some((priority, make)) = self.enter.get(&state) else {
+            return Vec::new();
+        };
+        let Some(action) = make(&self.npc) else {
+            return Vec::new();
+        };
+        let priority = *priority;
+        let directive_id = self.next_id();
+        self.pending = Some(directive_id.clone());
+        vec![ActionDirective {
+            directive_id,
+            npc_id: self.npc.npc_id.clone(),
+            priority,
+            action: Some(action),
+            ..Default::default()
+        }]
+    }
+
+    fn next_id(&mut self) -> String {
+        let npc_id = &self.npc.npc_id;
+        if let Some(ids) = &self.ids {
+            return ids.next_for(npc_id, "fsm");
+        }
+        self.counter += 1;
+        format!("{}-fsm-{}", npc_id, self.counter)
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for StateMachine<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StateMachine")
+            .field("npc_id", &self.npc.npc_id)
+            .field("state", &self.state)
+            .field("pending", &self.pending)
+            .field("transitions", &self.transitions)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{
+        ActionResult, ChatObservation, LookAction, StopAction, WorldTick,
+    };
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    enum Guard {
+        Patrol,
+        Rest,
+        Greet,
+    }
+
+    fn tick(server_tick: i64, in_combat: bool) -> ClientEvent {
+        ClientEvent::WorldTick(WorldTick {
+            server_tick,
+            npcs: vec![NpcSnapshot {
+                npc_id: "guard".to_string(),
+                in_combat,
+                ..NpcSnapshot::default()
+            }],
+            ..WorldTick::default()
+        })
+    }
+
+    fn result(directive_id: &str, success: bool) -> ClientEvent {
+        ClientEvent::ActionResult(ActionResult {
+            directive_id: directive_id.to_string(),
+            npc_id: "guard".to_string(),
+            success,
+            ..ActionResult::default()
+        })
+    }
+
+    fn machine() -> StateMachine<Guard> {
+        StateMachine::builder("guard", Guard::Patrol)
+            .on_enter(Guard::Patrol, 1, |_| {
+                Some(Action::Look(LookAction::default()))
+            })
+            .on_enter(Guard::Greet, 2, |_| {
+                Some(Action::Stop(StopAction::default()))
+            })
+            .transition(Guard::Patrol, Trigger::Succeeded, Guard::Patrol)
+            .transition(Guard::Patrol, Trigger::Failed, Guard::Rest)
+            .transition(Guard::Rest, Trigger::After(20), Guard::Patrol)
+            .transition(Guard::Greet, Trigger::Succeeded, Guard::Patrol)
+            .from_any(
+                Trigger::on(|event| matches!(event, ClientEvent::Chat(_))),
+                Guard::Greet,
+            )
+            .build()
+    }
+
+    #[test]
+    fn test_transitions() {
+        let mut machine = machine();
+        let sent = machine.handle(&tick(1, false));
+        assert!(matches!(sent[0].action, Some(Action::Look(_))));
+        assert!(machine.is_waiting());
+
+        // Success loops back into Patrol, sending a new directive
+        let again = machine.handle(&result(&sent[0].directive_id, true));
+        assert_eq!(machine.state(), Guard::Patrol);
+        assert_ne!(again[0].directive_id, sent[0].directive_id);
+
+        // Results for old directives are ignored
+        assert!(machine
+            .handle(&result(&sent[0].directive_id, false))
+            .is_empty());
+        assert!(machine
+            .handle(&result(&again[0].directive_id, false))
+            .is_empty());
+        assert_eq!(machine.state(), Guard::Rest);
+
+        assert!(machine.handle(&tick(15, false)).is_empty());
+        assert_eq!(machine.state(), Guard::Rest);
+        let sent = machine.handle(&tick(21, false));
+        assert_eq!(machine.state(), Guard::Patrol);
+        assert_eq!(sent.len(), 1);
+    }
+
+    #[test]
+    fn test_from_any_and_other_npcs() {
+        let mut machine = machine();
+        // Nothing happens before the first tick
+        let chat = ClientEvent::Chat(ChatObservation {
+            npc_id: "guard".to_string(),
+            ..ChatObservation::default()
+        });
+        assert!(machine.handle(&chat).is_empty());
+        machine.handle(&tick(1, false));
+
+        let sent = machine.handle(&chat);
+        assert_eq!(machine.state(), Guard::Greet);
+        assert_eq!(sent[0].priority, 2);
+
+        let other = ClientEvent::Chat(ChatObservation {
+            npc_id: "farmer".to_string(),
+            ..ChatObservation::default()
+        });
+        assert!(machine.handle(&other).is_empty());
+        // No transition from Greet to itself
+        assert!(machine.handle(&chat).is_empty());
+
+        let mut when = StateMachine::builder("guard", Guard::Patrol)
+            .transition(
+                Guard::Patrol,
+                Trigger::when(|npc| npc.in_combat),
+                Guard::Rest,
+            )
+            .build();
+        when.handle(&tick(1, false));
+        when.handle(&tick(2, true));
+        assert_eq!(when.state(), Guard::Rest);
+    }
+}
@@ -0,0 +1,230 @@
+//! Typed views of `EventObservation`s.
+//!
+//! An observation is an `EventType` plus a payload message, and most
+//! payloads have a kind of their own (a `BlockEvent` is a break, a place
+//! or an interaction). [`GameEvent::of`] folds both into one enum, so
+//! automation matches `GameEvent::BlockBroken(block)` or
+//! `GameEvent::RaidStarted(raid)` instead of checking two fields.
+//! Observations whose payload does not fit their `event_type` come out as
+//! [`GameEvent::Unknown`]; validation rejects them anyway.
+//!
+//! [`subscribed`] is the filter a plugin applies for `SubscribeEvents`.
+
+use crate::npc_society::v1::{
+    event_observation::Payload, BlockEvent, BlockEventType, CombatEvent, DeathEvent,
+    EventObservation, EventType, ExplosionEvent, ItemEvent, ItemEventType, ProximityEvent,
+    ProximityEventType, RaidEvent, RaidEventType, SubscribeEvents, VillagerTradeEvent,
+};
+
+/// What an NPC saw happen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameEvent<'a> {
+    /// Someone hit someone
+    Combat(&'a CombatEvent),
+    /// A block was broken
+    BlockBroken(&'a BlockEvent),
+    /// A block was placed
+    BlockPlaced(&'a BlockEvent),
+    /// A block was used (door, lever, chest)
+    BlockInteracted(&'a BlockEvent),
+    /// An item was picked up
+    ItemPickedUp(&'a ItemEvent),
+    /// An item was dropped
+    ItemDropped(&'a ItemEvent),
+    /// An item was used (eaten, thrown, drunk)
+    ItemUsed(&'a ItemEvent),
+    /// An entity came within range of the NPC
+    EntityApproached(&'a ProximityEvent),
+    /// An entity left the NPC's range
+    EntityLeft(&'a ProximityEvent),
+    /// Something exploded
+    Explosion(&'a ExplosionEvent),
+    /// A player died
+    PlayerDied(&'a DeathEvent),
+    /// A mob died
+    MobDied(&'a DeathEvent),
+    /// A raid began
+    RaidStarted(&'a RaidEvent),
+    /// A new raid wave spawned
+    RaidWave(&'a RaidEvent),
+    /// The village won the raid
+    RaidWon(&'a RaidEvent),
+    /// The raiders won
+    RaidLost(&'a RaidEvent),
+    /// A player traded with a villager
+    VillagerTrade(&'a VillagerTradeEvent),
+    /// No payload, a payload that does not match `event_type`, or a kind
+    /// this daemon does not know
+    Unknown,
+}
+
+impl<'a> GameEvent<'a> {
+    /// The typed view of `event`
+    pub fn of(event: &'a EventObservation) -> Self {
+        let Some(payload) = &event.payload else {
+            return Self::Unknown;
+        };
+        if payload_type(payload) != event.event_type() {
+            return Self::Unknown;
+        }
+        match payload {
+            Payload::Combat(combat) => Self::Combat(combat),
+            Payload::Block(block) => match block.event_type() {
+                BlockEventType::Break => Self::BlockBroken(block),
+                BlockEventType::Place => Self::BlockPlaced(block),
+                BlockEventType::Interact => Self::BlockInteracted(block),
+                BlockEventType::Unspecified => Self::Unknown,
+            },
+            Payload::Item(item) => match item.event_type() {
+                ItemEventType::Pickup => Self::ItemPickedUp(item),
+                ItemEventType::Drop => Self::ItemDropped(item),
+                ItemEventType::Use => Self::ItemUsed(item),
+                ItemEventType::Unspecified => Self::Unknown,
+            },
+            Payload::Proximity(proximity) => match proximity.event_type() {
+                ProximityEventType::Enter => Self::EntityApproached(proximity),
+                ProximityEventType::Leave => Self::EntityLeft(proximity),
+                ProximityEventType::Unspecified => Self::Unknown,
+            },
+            Payload::Explosion(explosion) => Self::Explosion(explosion),
+            Payload::Death(death) if death.entity_type == "minecraft:player" => {
+                Self::PlayerDied(death)
+            }
+            Payload::Death(death) => Self::MobDied(death),
+            Payload::Raid(raid) => match raid.event_type() {
+                RaidEventType::Started => Self::RaidStarted(raid),
+                RaidEventType::Wave => Self::RaidWave(raid),
+                RaidEventType::Victory => Self::RaidWon(raid),
+                RaidEventType::Defeat => Self::RaidLost(raid),
+                RaidEventType::Unspecified => Self::Unknown,
+            },
+            Payload::VillagerTrade(trade) => Self::VillagerTrade(trade),
+        }
+    }
+
+    /// Stable snake_case name, e.g. `block_broken`, for logs and scripts
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Combat(_) => "combat",
+            Self::BlockBroken(_) => "block_broken",
+            Self::BlockPlaced(_) => "block_placed",
+            Self::BlockInteracted(_) => "block_interacted",
+            Self::ItemPickedUp(_) => "item_picked_up",
+            Self::ItemDropped(_) => "item_dropped",
+            Self::ItemUsed(_) => "item_used",
+            Self::EntityApproached(_) => "entity_approached",
+            Self::EntityLeft(_) => "entity_left",
+            Self::Explosion(_) => "explosion",
+            Self::PlayerDied(_) => "player_died",
+            Self::MobDied(_) => "mob_died",
+            Self::RaidStarted(_) => "raid_started",
+            Self::RaidWave(_) => "raid_wave",
+            Self::RaidWon(_) => "raid_won",
+            Self::RaidLost(_) => "raid_lost",
+            Self::VillagerTrade(_) => "villager_trade",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// The `event_type` that goes with `payload`
+pub fn payload_type(payload: &Payload) -> EventType {
+    match payload {
+        Payload::Combat(_) => EventType::Combat,
+        Payload::Block(_) => EventType::Block,
+        Payload::Item(_) => EventType::Item,
+        Payload::Proximity(_) => EventType::Proximity,
+        Payload::Explosion(_) => EventType::Explosion,
+        Payload::Death(_) => EventType::Death,
+        Payload::Raid(_) => EventType::Raid,
+        Payload::VillagerTrade(_) => EventType::VillagerTrade,
+    }
+}
+
+/// Whether `subscription` lets `event` through (no subscription lets
+/// everything through)
+pub fn subscribed(subscription: Option<&SubscribeEvents>, event: &EventObservation) -> bool {
+    let Some(subscription) = subscription else {
+        return true;
+    };
+    (subscription.event_types.is_empty() || subscription.event_types.contains(&event.event_type))
+        && (subscription.npc_ids.is_empty() || subscription.npc_ids.contains(&event.npc_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observation(event_type: EventType, payload: Payload) -> EventObservation {
+        EventObservation {
+            npc_id: "guard".to_string(),
+            event_type: event_type as i32,
+            payload: Some(payload),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_of() {
+        let broken = observation(
+            EventType::Block,
+            Payload::Block(BlockEvent {
+                event_type: BlockEventType::Break as i32,
+                block_type: "minecraft:diamond_ore".to_string(),
+                ..Default::default()
+            }),
+        );
+        assert!(
+            matches!(GameEvent::of(&broken), GameEvent::BlockBroken(b) if b.block_type == "minecraft:diamond_ore")
+        );
+
+        let died = observation(
+            EventType::Death,
+            Payload::Death(DeathEvent {
+                entity_type: "minecraft:player".to_string(),
+                cause: "lava".to_string(),
+                ..Default::default()
+            }),
+        );
+        assert_eq!(GameEvent::of(&died).name(), "player_died");
+
+        let raid = observation(
+            EventType::Raid,
+            Payload::Raid(RaidEvent {
+                event_type: RaidEventType::Started as i32,
+                total_waves: 5,
+                ..Default::default()
+            }),
+        );
+        assert!(matches!(GameEvent::of(&raid), GameEvent::RaidStarted(r) if r.total_waves == 5));
+
+        // The payload must match event_type
+        let mismatched = observation(EventType::Combat, Payload::Raid(RaidEvent::default()));
+        assert_eq!(GameEvent::of(&mismatched), GameEvent::Unknown);
+        assert_eq!(
+            GameEvent::of(&EventObservation::default()),
+            GameEvent::Unknown
+        );
+    }
+
+    #[test]
+    fn test_subscribed() {
+        let raid = observation(EventType::Raid, Payload::Raid(RaidEvent::default()));
+        assert!(subscribed(None, &raid));
+        assert!(subscribed(Some(&SubscribeEvents::default()), &raid));
+
+        let blocks = SubscribeEvents {
+            event_types: vec![EventType::Block as i32, EventType::Raid as i32],
+            ..Default::default()
+        };
+        assert!(subscribed(Some(&blocks), &raid));
+        let combat = observation(EventType::Combat, Payload::Combat(CombatEvent::default()));
+        assert!(!subscribed(Some(&blocks), &combat));
+
+        let others = SubscribeEvents {
+            npc_ids: vec!["farmer".to_string()],
+            ..blocks
+        };
+        assert!(!subscribed(Some(&others), &raid));
+    }
+}
@@ -0,0 +1,364 @@
+//! Geometry on protocol positions.
+//!
+//! Distances, directions, bounding boxes, look angles and chunk coordinates
+//! for `Position` and `BlockPosition`. Block positions are measured from
+//! the block's center, so the distance from an NPC standing on a block to
+//! that block is 0.5, not 0.
+//!
+//! None of these check worlds; use [`same_world`](crate::dimension::same_world)
+//! first when the positions may be in different worlds.
+
+use std::ops::{Add, Mul, Sub};
+
+pub use crate::dimension::block_at;
+use crate::npc_society::v1::{BlockPosition, Position};
+
+/// Blocks per chunk side
+pub const CHUNK_SIZE: i32 = 16;
+
+/// A 3D vector in block units
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Vec3 {
+    /// East (+) / west (-)
+    pub x: f64,
+    /// Up (+) / down (-)
+    pub y: f64,
+    /// South (+) / north (-)
+    pub z: f64,
+}
+
+impl Vec3 {
+    /// Vector from components
+    pub const fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Squared length; cheaper than [`length`](Self::length) for comparisons
+    pub fn length_sq(self) -> f64 {
+        self.dot(self)
+    }
+
+    /// Euclidean length
+    pub fn length(self) -> f64 {
+        self.length_sq().sqrt()
+    }
+
+    /// Length ignoring the vertical component
+    pub fn horizontal_length(self) -> f64 {
+        (self.x * self.x + self.z * self.z).sqrt()
+    }
+
+    /// Dot product
+    pub fn dot(self, other: Vec3) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    /// Unit vector in the same direction, or None for the zero vector
+    pub fn normalized(self) -> Option<Vec3> {
+        let length = self.length();
+        if length > f64::EPSILON {
+            Some(self * (1.0 / length))
+        } else {
+            None
+        }
+    }
+
+    /// Minecraft yaw and pitch in degrees for looking along this vector.
+    /// Yaw is 0-360 with 0 = south (+z) and 90 = west (-x); pitch is
+    /// -90 (up) to 90 (down). The zero vector gives (0, 0).
+    pub fn yaw_pitch(self) -> (f32, f32) {
+        if self.length_sq() <= f64::EPSILON {
+            return (0.0, 0.0);
+        }
+        let yaw = (-self.x).atan2(self.z).to_degrees().rem_euclid(360.0);
+        let pitch = (-self.y).atan2(self.horizontal_length()).to_degrees();
+        (yaw as f32, pitch as f32)
+    }
+
+    /// Unit vector an entity with this yaw and pitch is looking along
+    pub fn from_yaw_pitch(yaw: f32, pitch: f32) -> Vec3 {
+        let (yaw, pitch) = ((yaw as f64).to_radians(), (pitch as f64).to_radians());
+        Vec3::new(
+            -yaw.sin() * pitch.cos(),
+            -pitch.sin(),
+            yaw.cos() * pitch.cos(),
+        )
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f64> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, factor: f64) -> Vec3 {
+        Vec3::new(self.x * factor, self.y * factor, self.z * factor)
+    }
+}
+
+/// A point in space: the position itself, or a block's center
+pub trait Point {
+    /// Coordinates of the point
+    fn point(&self) -> Vec3;
+}
+
+impl Point for Vec3 {
+    fn point(&self) -> Vec3 {
+        *self
+    }
+}
+
+impl Point for Position {
+    fn point(&self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+}
+
+impl Point for BlockPosition {
+    fn point(&self) -> Vec3 {
+        Vec3::new(
+            self.x as f64 + 0.5,
+            self.y as f64 + 0.5,
+            self.z as f64 + 0.5,
+        )
+    }
+}
+
+/// Squared distance between two points
+pub fn distance_sq(a: &impl Point, b: &impl Point) -> f64 {
+    (b.point() - a.point()).length_sq()
+}
+
+/// Distance between two points
+pub fn distance(a: &impl Point, b: &impl Point) -> f64 {
+    (b.point() - a.point()).length()
+}
+
+/// Distance between two points ignoring height
+pub fn horizontal_distance(a: &impl Point, b: &impl Point) -> f64 {
+    (b.point() - a.point()).horizontal_length()
+}
+
+/// Whether `p` is at most `radius` from `center`
+pub fn within_radius(center: &impl Point, p: &impl Point, radius: f64) -> bool {
+    distance_sq(center, p) <= radius * radius
+}
+
+/// Unit vector from `from` towards `to`, or None if they coincide
+pub fn direction(from: &impl Point, to: &impl Point) -> Option<Vec3> {
+    (to.point() - from.point()).normalized()
+}
+
+/// Yaw and pitch for an entity at `from` to look at `to`
+/// (see [`Vec3::yaw_pitch`])
+pub fn look_at(from: &impl Point, to: &impl Point) -> (f32, f32) {
+    (to.point() - from.point()).yaw_pitch()
+}
+
+/// Center of `block` as a position in the same world
+pub fn block_center(block: &BlockPosition) -> Position {
+    let center = block.point();
+    Position {
+        world: block.world.clone(),
+        x: center.x,
+        y: center.y,
+        z: center.z,
+        dimension: block.dimension,
+        ..Default::default()
+    }
+}
+
+/// Axis-aligned bounding box, `min` inclusive and `max` exclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    /// Lowest corner
+    pub min: Vec3,
+    /// Highest corner
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Box spanning two corners given in any order
+    pub fn new(a: Vec3, b: Vec3) -> Self {
+        Self {
+            min: Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    /// Box covering every block from `a` to `b`, both included
+    pub fn blocks(a: &BlockPosition, b: &BlockPosition) -> Self {
+        Self {
+            min: Vec3::new(
+                a.x.min(b.x) as f64,
+                a.y.min(b.y) as f64,
+                a.z.min(b.z) as f64,
+            ),
+            max: Vec3::new(
+                (a.x.max(b.x) + 1) as f64,
+                (a.y.max(b.y) + 1) as f64,
+                (a.z.max(b.z) + 1) as f64,
+            ),
+        }
+    }
+
+    /// Cube of half-width `radius` around `center`
+    pub fn around(center: &impl Point, radius: f64) -> Self {
+        let center = center.point();
+        let half = Vec3::new(radius, radius, radius);
+        Self::new(center - half, center + half)
+    }
+
+    /// Whether `p` is inside the box
+    pub fn contains(&self, p: &impl Point) -> bool {
+        let p = p.point();
+        (self.min.x..self.max.x).contains(&p.x)
+            && (self.min.y..self.max.y).contains(&p.y)
+            && (self.min.z..self.max.z).contains(&p.z)
+    }
+
+    /// Whether the two boxes overlap
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x < other.max.x
+            && other.min.x < self.max.x
+            && self.min.y < other.max.y
+            && other.min.y < self.max.y
+            && self.min.z < other.max.z
+            && other.min.z < self.max.z
+    }
+
+    /// Center of the box
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+}
+
+/// Chunk coordinates (16x16 columns of blocks)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkPos {
+    /// Chunk x (block x / 16, rounded down)
+    pub x: i32,
+    /// Chunk z (block z / 16, rounded down)
+    pub z: i32,
+}
+
+impl ChunkPos {
+    /// Chunk containing the block at `x`, `z`
+    pub const fn of_block(x: i32, z: i32) -> Self {
+        Self {
+            x: x.div_euclid(CHUNK_SIZE),
+            z: z.div_euclid(CHUNK_SIZE),
+        }
+    }
+
+    /// Chunk containing `block`
+    pub fn of(block: &BlockPosition) -> Self {
+        Self::of_block(block.x, block.z)
+    }
+
+    /// Chunk containing `p`
+    pub fn of_position(p: &Position) -> Self {
+        Self::of(&block_at(p))
+    }
+
+    /// Lowest block x and z in the chunk
+    pub const fn min_block(self) -> (i32, i32) {
+        (self.x * CHUNK_SIZE, self.z * CHUNK_SIZE)
+    }
+
+    /// Region file (32x32 chunks) the chunk is stored in
+    pub const fn region(self) -> (i32, i32) {
+        (self.x.div_euclid(32), self.z.div_euclid(32))
+    }
+
+    /// Chebyshev distance in chunks, as used for view and simulation distance
+    pub fn distance(self, other: ChunkPos) -> i32 {
+        (self.x - other.x).abs().max((self.z - other.z).abs())
+    }
+}
+
+/// Block x and z within its chunk (0-15)
+pub fn chunk_local(block: &BlockPosition) -> (i32, i32) {
+    (block.x.rem_euclid(CHUNK_SIZE), block.z.rem_euclid(CHUNK_SIZE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(x: i32, y: i32, z: i32) -> BlockPosition {
+        BlockPosition {
+            x,
+            y,
+            z,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_distances_and_boxes() {
+        let npc = Position {
+            x: 0.5,
+            y: 64.0,
+            z: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(distance(&npc, &block(0, 63, 0)), 0.5);
+        assert_eq!(distance_sq(&npc, &block(3, 63, 4)), 9.0 + 0.25 + 16.0);
+        assert_eq!(horizontal_distance(&npc, &block(3, 0, 4)), 5.0);
+        assert!(within_radius(&npc, &block(3, 63, 4), 5.1));
+        assert!(!within_radius(&npc, &block(3, 63, 4), 5.0));
+        assert_eq!(direction(&npc, &npc), None);
+
+        let area = Aabb::blocks(&block(2, 60, 2), &block(-2, 70, -2));
+        assert!(area.contains(&block(-2, 70, 2)));
+        assert!(!area.contains(&block(3, 65, 0)));
+        assert!(area.contains(&npc));
+        assert!(area.intersects(&Aabb::around(&block(3, 65, 3), 1.0)));
+        assert_eq!(block_at(&block_center(&block(-3, 5, 7))), block(-3, 5, 7));
+    }
+
+    #[test]
+    fn test_look_angles() {
+        let eye = Vec3::new(0.0, 0.0, 0.0);
+        assert_eq!(look_at(&eye, &Vec3::new(0.0, 0.0, 1.0)), (0.0, 0.0));
+        assert_eq!(look_at(&eye, &Vec3::new(-1.0, 0.0, 0.0)), (90.0, 0.0));
+        assert_eq!(look_at(&eye, &Vec3::new(1.0, 0.0, 0.0)), (270.0, 0.0));
+        assert_eq!(look_at(&eye, &Vec3::new(0.0, 1.0, 0.0)).1, -90.0);
+
+        let (yaw, pitch) = Vec3::new(2.0, -1.0, -3.0).yaw_pitch();
+        let back = Vec3::from_yaw_pitch(yaw, pitch);
+        let expected = Vec3::new(2.0, -1.0, -3.0).normalized().unwrap();
+        assert!((back - expected).length() < 1e-6);
+    }
+
+    #[test]
+    fn test_chunks() {
+        assert_eq!(ChunkPos::of(&block(15, 0, 16)), ChunkPos { x: 0, z: 1 });
+        assert_eq!(ChunkPos::of(&block(-1, 0, -16)), ChunkPos { x: -1, z: -1 });
+        assert_eq!(ChunkPos::of(&block(-17, 0, 0)).min_block(), (-32, 0));
+        assert_eq!(chunk_local(&block(-1, 0, 17)), (15, 1));
+        assert_eq!(ChunkPos { x: -1, z: 40 }.region(), (-1, 1));
+        assert_eq!(ChunkPos { x: 0, z: 0 }.distance(ChunkPos { x: -3, z: 2 }), 3);
+        let p = Position {
+            x: -0.1,
+            z: 31.9,
+            ..Default::default()
+        };
+        assert_eq!(ChunkPos::of_position(&p), ChunkPos { x: -1, z: 1 });
+    }
+}
@@ -0,0 +1,445 @@
+//! Golden-trace snapshot tests for handlers.
+//!
+//! [`run`] feeds a session to a [`NpcSocietyHandler`] and collects what it
+//! sends back. The session can be a capture made with the wire tap
+//! ([`recorded`]) or a [`Simulation`] the replies are fed back into
+//! ([`simulate`]). [`render`] turns the replies into text with the parts
+//! that change from run to run replaced: correlation ids become
+//! `<directive_id#1>`, `<directive_id#2>`, ... in order of first
+//! appearance, so which reply answers which is still visible, and
+//! wall-clock timestamps become `<time>`. [`Golden::assert`] compares that
+//! text with a snapshot checked in next to the test, like insta:
+//!
+//! ```ignore
+//! let capture = CaptureReader::new(File::open("tests/login.cap")?)?;
+//! let replies = golden::run(&MyHandler::default(), golden::recorded(capture)?);
+//! Golden::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots"))
+//!     .assert("login", &golden::render(&replies));
+//! ```
+//!
+//! A missing or different snapshot fails the test and leaves the new text
+//! in `<name>.snap.new` for review; run the tests with `UPDATE_GOLDEN=1`
+//! to accept it.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::path::PathBuf;
+
+use crate::compat::CORRELATION_KEYS;
+use crate::connection::ConnectionContext;
+use crate::events::{self, ClientEvent, NpcSocietyHandler, ServerEvent};
+use crate::npc_society::v1::{server_message::Message as ServerMsg, ClientMessage, ServerMessage};
+use crate::outbound::{self, OutboundReceiver, QueueConfig};
+use crate::sim::Simulation;
+use crate::tap::{Captured, Envelope};
+
+/// Set to accept new snapshots instead of failing on them
+pub const UPDATE_ENV: &str = "UPDATE_GOLDEN";
+
+/// Wall-clock fields besides `timestamp_ms` and `*_at_ms`
+const TIME_KEYS: [&str; 2] = ["deadline_ms", "estimated_start_ms"];
+
+/// The plugin's side of a capture, in order
+pub fn recorded(
+    captures: impl IntoIterator<Item = io::Result<Captured>>,
+) -> io::Result<Vec<ClientMessage>> {
+    let mut session = Vec::new();
+    for captured in captures {
+        if let Envelope::Client(msg) = captured?.envelope {
+            session.push(msg);
+        }
+    }
+    Ok(session)
+}
+
+/// Dispatch each message of `session` to `handler` and return what it
+/// sent, in the order the stream would carry it (the queue is drained
+/// after every message). Messages that are not events are skipped.
+pub fn run<H: NpcSocietyHandler + ?Sized>(
+    handler: &H,
+    session: impl IntoIterator<Item = ClientMessage>,
+) -> Vec<ServerMessage> {
+    let (tx, mut rx) = outbound::queue(QueueConfig::default());
+    let cx = ConnectionContext::new("golden", 0);
+    let mut sent = Vec::new();
+    for msg in session {
+        if let Ok(event) = ClientEvent::try_from(msg) {
+            events::dispatch(handler, event, &cx, &tx);
+            drain(&mut rx, &mut sent);
+        }
+    }
+    sent
+}
+
+/// Connect `handler` to `simulation` for `ticks` ticks, starting with the
+/// plugin's Hello. Directives it sends go back into the simulation, so
+/// their results arrive like they would from a plugin.
+pub fn simulate<H: NpcSocietyHandler + ?Sized>(
+    handler: &H,
+    simulation: &mut Simulation,
+    ticks: u64,
+) -> Vec<ServerMessage> {
+    let (tx, mut rx) = outbound::queue(QueueConfig::default());
+    let cx = ConnectionContext::new("simulation", 0);
+    let mut sent = Vec::new();
+    let mut session = vec![simulation.hello()];
+    for tick in 0..=ticks {
+        for msg in session {
+            if let Ok(event) = ClientEvent::try_from(msg) {
+                events::dispatch(handler, event, &cx, &tx);
+            }
+        }
+        let start = sent.len();
+        drain(&mut rx, &mut sent);
+        for msg in &sent[start..] {
+            if let Some(ServerMsg::ActionDirective(directive)) = &msg.message {
+                simulation.send(directive.clone());
+            }
+        }
+        if tick == ticks {
+            break;
+        }
+        session = simulation.step();
+    }
+    sent
+}
+
+fn drain(rx: &mut OutboundReceiver, sent: &mut Vec<ServerMessage>) {
+    while let Some(msg) = rx.try_next() {
+        sent.push(msg);
+    }
+}
+
+/// Stable names for the correlation ids of one snapshot
+#[derive(Default)]
+struct Ids {
+    names: BTreeMap<(String, String), String>,
+    counts: BTreeMap<String, usize>,
+}
+
+impl Ids {
+    fn name(&mut self, key: &str, value: &str) -> String {
+        let entry = (key.to_string(), value.to_string());
+        if let Some(name) = self.names.get(&entry) {
+            return name.clone();
+        }
+        let count = self.counts.entry(key.to_string()).or_default();
+        *count += 1;
+        let name = format!("<{}#{}>", key, count);
+        self.names.insert(entry, name.clone());
+        name
+    }
+}
+
+fn is_time(key: &str) -> bool {
+    key == "timestamp_ms" || key.ends_with("_at_ms") || TIME_KEYS.contains(&key)
+}
+
+/// `msg` with its byte fields emptied, and what to show for them instead
+fn without_bytes(msg: &ServerMessage) -> (ServerMessage, Option<(&'static str, String)>) {
+    let mut msg = msg.clone();
+    let shown = match &mut msg.message {
+        Some(ServerMsg::AudioChunk(chunk)) => {
+            let shown = format!("<{} bytes>", chunk.pcm_data.len());
+            chunk.pcm_data.clear();
+            Some(("pcm_data", shown))
+        }
+        Some(ServerMsg::NpcMessage(message)) => {
+            let shown = match std::str::from_utf8(&message.payload) {
+                Ok(text) => format!("{:?}", text),
+                Err(_) => format!("<{} bytes>", message.payload.len()),
+            };
+            message.payload.clear();
+            Some(("payload", shown))
+        }
+        _ => None,
+    };
+    (msg, shown)
+}
+
+fn normalize(line: &str, ids: &mut Ids, bytes: Option<&(&str, String)>) -> String {
+    let field = line.trim_start();
+    let indent = &line[..line.len() - field.len()];
+    let Some((key, value)) = field.split_once(": ") else {
+        return line.to_string();
+    };
+    let value = value.strip_suffix(',').unwrap_or(value);
+    let replaced = match bytes {
+        Some((name, shown)) if *name == key => shown.clone(),
+        _ if CORRELATION_KEYS.contains(&key) && value != "\"\"" => ids.name(key, value),
+        _ if is_time(key) && value != "0" => "<time>".to_string(),
+        _ => return line.to_string(),
+    };
+    format!("{}{}: {},", indent, key, replaced)
+}
+
+/// Text for `messages` that is the same on every run: one numbered
+/// block per message, correlation ids and timestamps replaced
+pub fn render(messages: &[ServerMessage]) -> String {
+    let mut ids = Ids::default();
+    let mut out = String::new();
+    for (i, msg) in messages.iter().enumerate() {
+        let message_type = ServerEvent::type_of(msg).unwrap_or("empty");
+        let _ = writeln!(out, "#{} {}", i, message_type);
+        let (msg, bytes) = without_bytes(msg);
+        let mut body = msg.message.map(|m| format!("{:#?}", m)).unwrap_or_default();
+        if let Some(timing) = msg.timing {
+            let _ = write!(body, "\ntiming: {:#?}", timing);
+        }
+        for line in body.lines() {
+            out.push_str(&normalize(line, &mut ids, bytes.as_ref()));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// A directory of checked-in snapshots, `<name>.snap` each
+#[derive(Debug, Clone)]
+pub struct Golden {
+    dir: PathBuf,
+}
+
+impl Golden {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Panic unless `actual` matches snapshot `name` (see [`Self::check`]);
+    /// with `UPDATE_GOLDEN` set, accept it instead
+    #[track_caller]
+    pub fn assert(&self, name: &str, actual: &str) {
+        let update = std::env::var_os(UPDATE_ENV).is_some_and(|v| v != "0");
+        if let Err(message) = self.check(name, actual, update) {
+            panic!("{}", message);
+        }
+    }
+
+    /// Compare `actual` with snapshot `name`. On a mismatch, or if there
+    /// is no snapshot yet, `actual` goes into `<name>.snap.new` and the
+    /// error shows the difference; with `update` it replaces the snapshot.
+    pub fn check(&self, name: &str, actual: &str, update: bool) -> Result<(), String> {
+        let path = self.dir.join(format!("{}.snap", name));
+        let new_path = self.dir.join(format!("{}.snap.new", name));
+        let expected = std::fs::read_to_string(&path).ok();
+        if expected.as_deref() == Some(actual) {
+            let _ = std::fs::remove_file(&new_path);
+            return Ok(());
+        }
+        let write = |path: &PathBuf| {
+            std::fs::create_dir_all(&self.dir)
+                .and_then(|()| std::fs::write(path, actual))
+                .map_err(|e| format!("{}: {}", path.display(), e))
+        };
+        if update {
+            let _ = std::fs::remove_file(&new_path);
+            return write(&path);
+        }
+        write(&new_path)?;
+        Err(match expected {
+            None => format!(
+                "no snapshot {}; review {} and rerun with {}=1 to accept it",
+                path.display(),
+                new_path.display(),
+                UPDATE_ENV
+            ),
+            Some(expected) => format!(
+                "snapshot {} differs (-expected +actual):\n{}rerun with {}=1 to accept {}",
+                path.display(),
+                diff(&expected, actual),
+                UPDATE_ENV,
+                new_path.display()
+            ),
+        })
+    }
+}
+
+/// The lines that differ, by position; enough to spot what changed
+fn diff(expected: &str, actual: &str) -> String {
+    const MAX_LINES: usize = 40;
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut out = String::new();
+    let mut shown = 0;
+    for i in 0..expected.len().max(actual.len()) {
+        let (old, new) = (expected.get(i), actual.get(i));
+        if old == new {
+            continue;
+        }
+        if shown == MAX_LINES {
+            out.push_str("     ...\n");
+            break;
+        }
+        shown += 1;
+        if let Some(old) = old {
+            let _ = writeln!(out, "{:>4} -{}", i + 1, old);
+        }
+        if let Some(new) = new {
+            let _ = writeln!(out, "{:>4} +{}", i + 1, new);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{
+        action_directive::Action, ActionDirective, ActionResult, ChatObservation, Hello, HelloAck,
+        MessageTiming, SpeakDirective, StopAction, WorldTick,
+    };
+    use crate::outbound::Outbound;
+    use crate::sim::SimConfig;
+    use crate::tap::{CaptureReader, CaptureWriter, Frame, TapRecord};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Tells NPC 0 to stop every 20 ticks and reports how it went. Its
+    /// ids and timestamps differ on every run, like a real daemon's.
+    struct Courier {
+        next_id: AtomicU64,
+    }
+
+    impl Courier {
+        fn new() -> Self {
+            let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            Self {
+                next_id: AtomicU64::new(nanos.subsec_nanos() as u64),
+            }
+        }
+    }
+
+    impl NpcSocietyHandler for Courier {
+        fn on_hello(&self, _hello: Hello, _cx: &ConnectionContext, tx: &Outbound) {
+            tx.send(HelloAck {
+                protocol_version: "1".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+
+        fn on_world_tick(&self, tick: WorldTick, _cx: &ConnectionContext, tx: &Outbound) {
+            if tick.server_tick % 20 != 0 {
+                return;
+            }
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let now_ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis();
+            tx.send(ServerMessage {
+                timing: Some(MessageTiming {
+                    sent_at_ms: now_ms as i64,
+                    ..Default::default()
+                }),
+                ..ActionDirective {
+                    directive_id: format!("stop-{}", id),
+                    npc_id: tick.npcs[0].npc_id.clone(),
+                    action: Some(Action::Stop(StopAction::default())),
+                    ..Default::default()
+                }
+                .into()
+            })
+            .unwrap();
+        }
+
+        fn on_action_result(&self, result: ActionResult, _cx: &ConnectionContext, tx: &Outbound) {
+            tx.send(SpeakDirective {
+                npc_id: result.npc_id,
+                text: if result.success {
+                    "Stopped"
+                } else {
+                    "Could not stop"
+                }
+                .to_string(),
+                directive_id: result.directive_id,
+                ..Default::default()
+            })
+            .unwrap();
+        }
+
+        fn on_chat(&self, chat: ChatObservation, _cx: &ConnectionContext, tx: &Outbound) {
+            tx.send(SpeakDirective {
+                npc_id: chat.npc_id,
+                text: chat.message,
+                ..Default::default()
+            })
+            .unwrap();
+        }
+    }
+
+    fn snapshots() -> Golden {
+        Golden::new(concat!(env!("CARGO_MANIFEST_DIR"), "/src/snapshots"))
+    }
+
+    #[test]
+    fn test_simulated_session() {
+        let replies = || {
+            let mut simulation = Simulation::new(SimConfig {
+                seed: 5,
+                ..Default::default()
+            });
+            render(&simulate(&Courier::new(), &mut simulation, 100))
+        };
+        let rendered = replies();
+        assert!(rendered.contains("directive_id: <directive_id#1>,"));
+        assert!(rendered.contains("sent_at_ms: <time>,"));
+        // Different ids and clocks, same snapshot
+        assert_eq!(replies(), rendered);
+        snapshots().assert("golden__courier", &rendered);
+    }
+
+    #[test]
+    fn test_recorded_session() {
+        let writer = CaptureWriter::new(Vec::new()).unwrap();
+        let chat = ChatObservation {
+            npc_id: "parrot".to_string(),
+            message: "hello".to_string(),
+            ..Default::default()
+        }
+        .into();
+        let echoed: ServerMessage = SpeakDirective::default().into();
+        for frame in [Frame::Client(&chat), Frame::Server(&echoed)] {
+            writer
+                .write(&TapRecord {
+                    server_id: "lobby",
+                    timestamp_ms: 1_000,
+                    size: 0,
+                    frame,
+                })
+                .unwrap();
+        }
+        let bytes = writer.into_inner();
+
+        // Only the plugin's side is replayed
+        let session = recorded(CaptureReader::new(&bytes[..]).unwrap()).unwrap();
+        assert_eq!(session, [chat]);
+        let replies = run(&Courier::new(), session);
+        assert_eq!(replies.len(), 1);
+        let rendered = render(&replies);
+        assert!(rendered.starts_with("#0 SpeakDirective\nSpeakDirective(\n"));
+        assert!(rendered.contains("        text: \"hello\",\n"));
+    }
+
+    #[test]
+    fn test_check_and_update() {
+        let golden =
+            Golden::new(std::env::temp_dir().join(format!("npc-golden-{}", std::process::id())));
+        let new_path = golden.dir.join("s.snap.new");
+
+        let missing = golden.check("s", "a\nb\n", false).unwrap_err();
+        assert!(missing.contains("no snapshot"));
+        assert_eq!(std::fs::read_to_string(&new_path).unwrap(), "a\nb\n");
+        golden.check("s", "a\nb\n", true).unwrap();
+        assert!(!new_path.exists());
+        golden.check("s", "a\nb\n", false).unwrap();
+
+        let differs = golden.check("s", "a\nc\n", false).unwrap_err();
+        assert!(differs.contains("   2 -b\n   2 +c\n"));
+        assert!(new_path.exists());
+        golden.check("s", "a\nb\n", false).unwrap();
+        assert!(!new_path.exists());
+        std::fs::remove_dir_all(&golden.dir).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+//! Guard zones (`GuardZoneDirective`, v1.2+).
+//!
+//! A guard walking its rounds with MoveActions needs a directive per leg
+//! and notices trespassers a WorldTick late. A GuardZoneDirective leaves
+//! the patrol to the plugin, which sends an IntruderObservation when the
+//! guard spots a player who is not allowed in the zone. What the guard
+//! does about it stays with the daemon: [`IntruderPolicy`] has it warn
+//! first, attack someone who stays, and call for help when outnumbered.
+//! [`zone`] and [`lift`] build the directives, [`contains`] checks a
+//! position against a zone.
+
+use std::collections::HashMap;
+
+use crate::geom::Aabb;
+use crate::npc_society::v1::{
+    BlockPosition, GuardZoneDirective, IntruderObservation, IntruderTrigger, Position,
+};
+
+/// Alert radius of a zone that sets none, in blocks
+pub const DEFAULT_ALERT_RADIUS: f32 = 16.0;
+/// Times [`IntruderPolicy::default`] warns an intruder before attacking
+pub const DEFAULT_WARNINGS: u32 = 1;
+
+/// `npc_id` guards the blocks from `from` to `to` against everyone but
+/// `allowed_players`
+pub fn zone(
+    npc_id: &str,
+    zone_id: &str,
+    from: BlockPosition,
+    to: BlockPosition,
+    allowed_players: &[String],
+) -> GuardZoneDirective {
+    GuardZoneDirective {
+        npc_id: npc_id.to_string(),
+        zone_id: zone_id.to_string(),
+        from: Some(from),
+        to: Some(to),
+        allowed_players: allowed_players.to_vec(),
+        ..Default::default()
+    }
+}
+
+/// Lift the zone `zone_id` of `npc_id`; the guard stops where it is
+pub fn lift(npc_id: &str, zone_id: &str) -> GuardZoneDirective {
+    GuardZoneDirective {
+        npc_id: npc_id.to_string(),
+        zone_id: zone_id.to_string(),
+        ..Default::default()
+    }
+}
+
+/// Whether `position` is inside the zone; never for a lifted zone
+pub fn contains(zone: &GuardZoneDirective, position: &Position) -> bool {
+    match (&zone.from, &zone.to) {
+        (Some(from), Some(to)) => {
+            from.world == position.world && Aabb::blocks(from, to).contains(position)
+        }
+        _ => false,
+    }
+}
+
+/// What a guard does about an intruder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Response {
+    /// Tell the intruder to leave
+    Warn,
+    /// Attack the intruder
+    Attack,
+    /// Alert the other NPCs
+    CallForHelp,
+}
+
+/// Decides the [`Response`] to each IntruderObservation.
+///
+/// Every spotting of an intruder still in the zone counts: the first
+/// `warnings` are warned, later ones attacked. A guard facing more than
+/// one intruder calls for help instead. Leaving the zone clears the count.
+#[derive(Debug)]
+pub struct IntruderPolicy {
+    warnings: u32,
+    /// Spottings per (npc_id, zone_id, player_uuid)
+    spotted: HashMap<(String, String, String), u32>,
+}
+
+impl Default for IntruderPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_WARNINGS)
+    }
+}
+
+impl IntruderPolicy {
+    /// Warn `warnings` times before attacking
+    pub fn new(warnings: u32) -> Self {
+        Self {
+            warnings,
+            spotted: HashMap::new(),
+        }
+    }
+
+    /// The response to `observation`; none when an intruder left
+    pub fn decide(&mut self, observation: &IntruderObservation) -> Option<Response> {
+        let key = (
+            observation.npc_id.clone(),
+            observation.zone_id.clone(),
+            observation.player_uuid.clone(),
+        );
+        match observation.trigger() {
+            IntruderTrigger::Spotted => {
+                let spotted = self.spotted.entry(key).or_default();
+                *spotted += 1;
+                Some(if observation.intruders > 1 {
+                    Response::CallForHelp
+                } else if *spotted <= self.warnings {
+                    Response::Warn
+                } else {
+                    Response::Attack
+                })
+            }
+            IntruderTrigger::Left => {
+                self.spotted.remove(&key);
+                None
+            }
+            IntruderTrigger::Unspecified => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(x: i32, y: i32, z: i32) -> BlockPosition {
+        BlockPosition {
+            world: "world".to_string(),
+            x,
+            y,
+            z,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_contains() {
+        let gate = zone("guard", "gate", block(10, 60, 10), block(0, 70, 0), &[]);
+        let at = |x, z| Position {
+            world: "world".to_string(),
+            x,
+            y: 64.0,
+            z,
+            ..Default::default()
+        };
+        assert!(contains(&gate, &at(10.9, 0.0)));
+        assert!(!contains(&gate, &at(11.0, 0.0)));
+        assert!(!contains(
+            &gate,
+            &Position {
+                world: "world_nether".to_string(),
+                ..at(5.0, 5.0)
+            }
+        ));
+        assert!(!contains(&lift("guard", "gate"), &at(5.0, 5.0)));
+    }
+
+    #[test]
+    fn test_policy() {
+        let mut policy = IntruderPolicy::default();
+        let observation =
+            |player_uuid: &str, trigger: IntruderTrigger, intruders| IntruderObservation {
+                npc_id: "guard".to_string(),
+                zone_id: "gate".to_string(),
+                trigger: trigger as i32,
+                player_uuid: player_uuid.to_string(),
+                intruders,
+                ..Default::default()
+            };
+        let spotted = observation("p1", IntruderTrigger::Spotted, 1);
+        assert_eq!(policy.decide(&spotted), Some(Response::Warn));
+        assert_eq!(policy.decide(&spotted), Some(Response::Attack));
+
+        // Leaving forgives
+        assert_eq!(
+            policy.decide(&observation("p1", IntruderTrigger::Left, 0)),
+            None
+        );
+        assert_eq!(policy.decide(&spotted), Some(Response::Warn));
+
+        assert_eq!(
+            policy.decide(&observation("p2", IntruderTrigger::Spotted, 2)),
+            Some(Response::CallForHelp)
+        );
+    }
+}
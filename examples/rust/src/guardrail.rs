@@ -0,0 +1,453 @@
+//! Guardrails: the last word on what NPCs do and say.
+//!
+//! [`ActionPolicy`](crate::policy::ActionPolicy) and the
+//! [`OutputFilter`](crate::sanitize::OutputFilter) apply fixed rules. A
+//! daemon that lets an LLM drive its NPCs needs checks of its own as well:
+//! a content classifier, spawn protection, "the blacksmith never talks
+//! about being an AI". A [`GuardChain`] runs every registered [`Guardrail`]
+//! over each outgoing ActionDirective and SpeakDirective (the subtitle of a
+//! PlayAudioAssetDirective included), in the order they were added. Each
+//! one lets the message pass, changes it (the next one sees the change) or
+//! vetoes it with a reason. A veto ends the chain and the message is not
+//! sent; it is logged, counted, kept in [`GuardChain::recent`] and handed
+//! to the observers registered with [`GuardChain::on_veto`].
+//!
+//! [`Outbound::with_guards`](crate::outbound::Outbound::with_guards) runs
+//! the chain on every message queued, before the output filter. Built in:
+//!
+//! - [`ProtectedRegions`]: no block changes inside the given regions
+//! - [`StayInCharacter`]: no lines that break the fourth wall, and each
+//!   NPC keeps the voice it was given
+//!
+//! Any `Fn(&mut Outgoing) -> Result<(), String>` is a guardrail too, with
+//! [`GuardChain::with_fn`].
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use tracing::warn;
+
+use crate::npc_society::v1::server_message::Message as ServerMsg;
+use crate::npc_society::v1::{ActionDirective, ServerMessage, SpeakDirective};
+use crate::policy::{target_block, Region};
+use crate::retry::action_kind;
+
+/// Vetoes kept for [`GuardChain::recent`]
+pub const RECENT_VETOES: usize = 64;
+
+/// A message on its way out, open to changes
+#[derive(Debug)]
+pub enum Outgoing<'a> {
+    /// An ActionDirective
+    Action(&'a mut ActionDirective),
+    /// A SpeakDirective, or the subtitle of a PlayAudioAssetDirective
+    Speech(&'a mut SpeakDirective),
+}
+
+impl Outgoing<'_> {
+    /// "ActionDirective" or "SpeakDirective"
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Action(_) => "ActionDirective",
+            Self::Speech(_) => "SpeakDirective",
+        }
+    }
+
+    /// NPC acting or speaking
+    pub fn npc_id(&self) -> &str {
+        match self {
+            Self::Action(directive) => &directive.npc_id,
+            Self::Speech(speak) => &speak.npc_id,
+        }
+    }
+
+    /// directive_id of the message
+    pub fn directive_id(&self) -> &str {
+        match self {
+            Self::Action(directive) => &directive.directive_id,
+            Self::Speech(speak) => &speak.directive_id,
+        }
+    }
+}
+
+/// One check in a [`GuardChain`]
+pub trait Guardrail: Send + Sync {
+    /// Name in logs and vetoes
+    fn name(&self) -> &str;
+
+    /// Let `outgoing` pass, possibly changed, or veto it with a reason
+    fn check(&self, outgoing: &mut Outgoing<'_>) -> Result<(), String>;
+}
+
+/// A message a guardrail stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vetoed {
+    /// Name of the guardrail
+    pub guard: String,
+    /// "ActionDirective" or "SpeakDirective"
+    pub kind: &'static str,
+    /// NPC it was for
+    pub npc_id: String,
+    /// Its directive_id
+    pub directive_id: String,
+    /// Why
+    pub reason: String,
+}
+
+impl fmt::Display for Vetoed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} vetoed by {}: {}",
+            self.kind, self.directive_id, self.guard, self.reason
+        )
+    }
+}
+
+impl std::error::Error for Vetoed {}
+
+type Observer = Box<dyn Fn(&Vetoed) + Send + Sync>;
+
+/// Guardrails in the order they run
+#[derive(Default)]
+pub struct GuardChain {
+    guards: Vec<Box<dyn Guardrail>>,
+    vetoed: AtomicU64,
+    recent: Mutex<VecDeque<Vetoed>>,
+    observers: Vec<Observer>,
+}
+
+impl fmt::Debug for GuardChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GuardChain")
+            .field(
+                "guards",
+                &self.guards.iter().map(|g| g.name()).collect::<Vec<_>>(),
+            )
+            .field("vetoed", &self.vetoed())
+            .finish()
+    }
+}
+
+impl GuardChain {
+    /// A chain without guardrails; everything passes
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `guard` after the guardrails added so far
+    pub fn with(mut self, guard: impl Guardrail + 'static) -> Self {
+        self.guards.push(Box::new(guard));
+        self
+    }
+
+    /// Run `check` as the guardrail `name`
+    pub fn with_fn(
+        self,
+        name: &str,
+        check: impl Fn(&mut Outgoing<'_>) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        self.with(FnGuard {
+            name: name.to_string(),
+            check,
+        })
+    }
+
+    /// Also call `observer` for every veto; it runs on the sending task,
+    /// so keep it quick
+    pub fn on_veto(mut self, observer: impl Fn(&Vetoed) + Send + Sync + 'static) -> Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    /// Run the chain over `outgoing`
+    pub fn inspect(&self, mut outgoing: Outgoing<'_>) -> Result<(), Vetoed> {
+        for guard in &self.guards {
+            if let Err(reason) = guard.check(&mut outgoing) {
+                return Err(self.veto(Vetoed {
+                    guard: guard.name().to_string(),
+                    kind: outgoing.kind(),
+                    npc_id: outgoing.npc_id().to_string(),
+                    directive_id: outgoing.directive_id().to_string(),
+                    reason,
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the chain over `msg` if it is an action or speech
+    pub fn inspect_message(&self, msg: &mut ServerMessage) -> Result<(), Vetoed> {
+        match &mut msg.message {
+            Some(ServerMsg::ActionDirective(directive)) => {
+                self.inspect(Outgoing::Action(directive))
+            }
+            Some(ServerMsg::SpeakDirective(speak)) => self.inspect(Outgoing::Speech(speak)),
+            Some(ServerMsg::PlayAudioAsset(play)) => match &mut play.speak {
+                Some(speak) => self.inspect(Outgoing::Speech(speak)),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Messages vetoed so far
+    pub fn vetoed(&self) -> u64 {
+        self.vetoed.load(Ordering::Relaxed)
+    }
+
+    /// The latest vetoes, oldest first; at most [`RECENT_VETOES`]
+    pub fn recent(&self) -> Vec<Vetoed> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn veto(&self, vetoed: Vetoed) -> Vetoed {
+        self.vetoed.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            npc_id = %vetoed.npc_id,
+            directive_id = %vetoed.directive_id,
+            guard = %vetoed.guard,
+            reason = %vetoed.reason,
+            "{} vetoed",
+            vetoed.kind
+        );
+        {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() == RECENT_VETOES {
+                recent.pop_front();
+            }
+            recent.push_back(vetoed.clone());
+        }
+        for observer in &self.observers {
+            observer(&vetoed);
+        }
+        vetoed
+    }
+}
+
+struct FnGuard<F> {
+    name: String,
+    check: F,
+}
+
+impl<F> Guardrail for FnGuard<F>
+where
+    F: Fn(&mut Outgoing<'_>) -> Result<(), String> + Send + Sync,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self, outgoing: &mut Outgoing<'_>) -> Result<(), String> {
+        (self.check)(outgoing)
+    }
+}
+
+/// Vetoes actions that change blocks inside any of its regions, for every
+/// NPC: spawn, a build, another player's base
+#[derive(Debug, Clone)]
+pub struct ProtectedRegions {
+    regions: Vec<Region>,
+    kinds: Vec<&'static str>,
+}
+
+impl ProtectedRegions {
+    /// Protect `regions` from breaking, placing and interacting
+    pub fn new(regions: Vec<Region>) -> Self {
+        Self {
+            regions,
+            kinds: vec!["break_block", "place_block", "interact"],
+        }
+    }
+
+    /// Protect against these [`action_kind`]s instead
+    pub fn kinds(mut self, kinds: &[&'static str]) -> Self {
+        self.kinds = kinds.to_vec();
+        self
+    }
+}
+
+impl Guardrail for ProtectedRegions {
+    fn name(&self) -> &str {
+        "protected_regions"
+    }
+
+    fn check(&self, outgoing: &mut Outgoing<'_>) -> Result<(), String> {
+        let Outgoing::Action(directive) = outgoing else {
+            return Ok(());
+        };
+        let Some(action) = &directive.action else {
+            return Ok(());
+        };
+        let kind = action_kind(action);
+        if !self.kinds.contains(&kind) {
+            return Ok(());
+        }
+        let Some(target) = target_block(action) else {
+            return Ok(());
+        };
+        match self.regions.iter().find(|r| r.contains(&target)) {
+            Some(region) => Err(format!(
+                "{kind} at {} {} {} is inside protected region '{}'",
+                target.x, target.y, target.z, region.name
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Keeps NPCs in character: vetoes lines that mention what they really
+/// are, and pins each NPC's voice
+#[derive(Debug, Clone)]
+pub struct StayInCharacter {
+    phrases: Vec<String>,
+    voices: HashMap<String, String>,
+}
+
+impl Default for StayInCharacter {
+    /// The usual ways an LLM gives itself away
+    fn default() -> Self {
+        Self::new(&[
+            "as an ai",
+            "language model",
+            "i'm an ai",
+            "i am an ai",
+            "my training data",
+            "i cannot browse",
+        ])
+    }
+}
+
+impl StayInCharacter {
+    /// Veto lines containing any of `phrases`, ignoring case
+    pub fn new(phrases: &[&str]) -> Self {
+        Self {
+            phrases: phrases.iter().map(|p| p.to_lowercase()).collect(),
+            voices: HashMap::new(),
+        }
+    }
+
+    /// Speak as `npc_id` with `voice_id` only, whatever the directive says
+    pub fn voice(mut self, npc_id: &str, voice_id: &str) -> Self {
+        self.voices.insert(npc_id.to_string(), voice_id.to_string());
+        self
+    }
+}
+
+impl Guardrail for StayInCharacter {
+    fn name(&self) -> &str {
+        "stay_in_character"
+    }
+
+    fn check(&self, outgoing: &mut Outgoing<'_>) -> Result<(), String> {
+        let Outgoing::Speech(speak) = outgoing else {
+            return Ok(());
+        };
+        let text = speak.text.to_lowercase();
+        if let Some(phrase) = self.phrases.iter().find(|p| text.contains(p.as_str())) {
+            return Err(format!("breaks character: \"{phrase}\""));
+        }
+        if let Some(voice_id) = self.voices.get(&speak.npc_id) {
+            speak.voice_id = voice_id.clone();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::npc_society::v1::{
+        action_directive::Action, BlockPosition, BreakBlockAction, MoveAction,
+    };
+
+    fn break_block(x: i32, y: i32, z: i32) -> ActionDirective {
+        ActionDirective {
+            directive_id: "miner_01-break-1".to_string(),
+            npc_id: "miner_01".to_string(),
+            action: Some(Action::BreakBlock(BreakBlockAction {
+                position: Some(BlockPosition {
+                    world: "world".to_string(),
+                    x,
+                    y,
+                    z,
+                    ..Default::default()
+                }),
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_protected_regions() {
+        let spawn = Region::new("spawn", "world", (-16, 0, -16), (16, 255, 16));
+        let chain = GuardChain::new().with(ProtectedRegions::new(vec![spawn]));
+
+        let vetoed = chain
+            .inspect(Outgoing::Action(&mut break_block(3, 64, -2)))
+            .unwrap_err();
+        assert_eq!(vetoed.guard, "protected_regions");
+        assert_eq!(vetoed.npc_id, "miner_01");
+        assert_eq!(
+            vetoed.reason,
+            "break_block at 3 64 -2 is inside protected region 'spawn'"
+        );
+        assert!(chain
+            .inspect(Outgoing::Action(&mut break_block(40, 64, 0)))
+            .is_ok());
+
+        // Walking through spawn is fine
+        let mut walk = ActionDirective {
+            action: Some(Action::Move(MoveAction::default())),
+            ..break_block(0, 64, 0)
+        };
+        assert!(chain.inspect(Outgoing::Action(&mut walk)).is_ok());
+        assert_eq!(chain.vetoed(), 1);
+    }
+
+    #[test]
+    fn test_chain_order_and_observers() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let chain = GuardChain::new()
+            .with_fn("shout", |outgoing| {
+                if let Outgoing::Speech(speak) = outgoing {
+                    speak.text = speak.text.to_uppercase();
+                }
+                Ok(())
+            })
+            .with(StayInCharacter::default().voice("smith_01", "gruff"))
+            .on_veto({
+                let seen = seen.clone();
+                move |vetoed| seen.lock().unwrap().push(vetoed.reason.clone())
+            });
+
+        // Later guardrails see what earlier ones changed
+        let mut speak: ServerMessage = SpeakDirective {
+            npc_id: "smith_01".to_string(),
+            text: "Fine steel.".to_string(),
+            voice_id: "cheerful".to_string(),
+            ..Default::default()
+        }
+        .into();
+        chain.inspect_message(&mut speak).unwrap();
+        let Some(ServerMsg::SpeakDirective(spoken)) = &speak.message else {
+            panic!("Expected a SpeakDirective");
+        };
+        assert_eq!(spoken.text, "FINE STEEL.");
+        assert_eq!(spoken.voice_id, "gruff");
+
+        let mut slip = SpeakDirective {
+            npc_id: "smith_01".to_string(),
+            text: "As an AI, I can't forge swords.".to_string(),
+            ..Default::default()
+        };
+        let vetoed = chain.inspect(Outgoing::Speech(&mut slip)).unwrap_err();
+        assert_eq!(vetoed.guard, "stay_in_character");
+        assert_eq!(*seen.lock().unwrap(), ["breaks character: \"as an ai\""]);
+        assert_eq!(chain.recent(), [vetoed]);
+    }
+}
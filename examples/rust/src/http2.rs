@@ -0,0 +1,141 @@
+//! HTTP/2 flow control and keepalive for the daemon and its clients.
+//!
+//! A plugin keeps one Connect stream open and most of its bytes are
+//! WorldTicks, tens of kilobytes each several times a second. h2 lets a
+//! sender have at most a window of unacknowledged bytes in flight, so a
+//! stream moves at most `window / round trip`: 64 KiB (the h2 default,
+//! which grpc-go starts from) over an 80 ms link is 800 KB/s, and a busy
+//! server's ticks back up behind it; tonic's 1 MiB is 13 MB/s shared by
+//! every stream of the connection. [`Http2Config::lan`] (the default) and
+//! [`Http2Config::wan`] size the windows for the protocol;
+//! [`Http2Config::server`] applies them to the daemon and
+//! [`Http2Config::endpoint`] to a client channel.
+//!
+//! The windows only bound what the receiver accepts. Directives are
+//! ordered by [`crate::outbound`] while they wait for the plugin's window,
+//! so a plugin that advertises a bigger window than it needs lets more
+//! bulk audio reach the socket ahead of a late critical directive. The
+//! plugin should use the same preset as the daemon (grpc-java:
+//! `NettyChannelBuilder.flowControlWindow`).
+//!
+//! `cargo bench --bench http2_window` compares the presets with tonic's
+//! defaults through a proxy that adds latency.
+
+use std::time::Duration;
+
+use tonic::transport::{Endpoint, Server};
+
+/// Window sizes, stream limit and keepalive of HTTP/2 connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Http2Config {
+    /// Bytes a sender may have in flight on one stream
+    pub stream_window_bytes: u32,
+    /// Bytes a sender may have in flight on all streams of a connection
+    pub connection_window_bytes: u32,
+    /// Grow the windows from the measured bandwidth-delay product instead
+    /// of using the fixed sizes
+    pub adaptive_window: bool,
+    /// Streams one connection may have open at once (daemon only)
+    pub max_concurrent_streams: u32,
+    /// How often an idle connection is pinged; 0 never
+    pub keepalive_interval_ms: u64,
+    /// How long a ping may go unanswered before the connection is closed
+    pub keepalive_timeout_ms: u64,
+    /// Send small messages (directives, acks) without waiting to batch
+    pub tcp_nodelay: bool,
+}
+
+impl Default for Http2Config {
+    /// [`Http2Config::lan`]
+    fn default() -> Self {
+        Self::lan()
+    }
+}
+
+impl Http2Config {
+    /// Daemon next to the Minecraft server (round trips under 5 ms): 1 MiB
+    /// per stream, 2 MiB per connection for Connect, ConnectAudio and
+    /// unary calls, pings every 20 s
+    pub fn lan() -> Self {
+        Self {
+            stream_window_bytes: 1 << 20,
+            connection_window_bytes: 2 << 20,
+            adaptive_window: false,
+            max_concurrent_streams: 32,
+            keepalive_interval_ms: 20_000,
+            keepalive_timeout_ms: 10_000,
+            tcp_nodelay: true,
+        }
+    }
+
+    /// Daemon in another region (round trips up to ~150 ms): 8 MiB per
+    /// stream, 16 MiB per connection, and a longer ping timeout
+    pub fn wan() -> Self {
+        Self {
+            stream_window_bytes: 8 << 20,
+            connection_window_bytes: 16 << 20,
+            keepalive_interval_ms: 30_000,
+            keepalive_timeout_ms: 20_000,
+            ..Self::lan()
+        }
+    }
+
+    /// Most bytes per second one stream can move over a link with this
+    /// round trip, whatever its bandwidth
+    pub fn stream_limit(&self, rtt: Duration) -> f64 {
+        let window = self.stream_window_bytes.min(self.connection_window_bytes);
+        window as f64 / rtt.as_secs_f64()
+    }
+
+    fn keepalive_interval(&self) -> Option<Duration> {
+        (self.keepalive_interval_ms > 0).then(|| Duration::from_millis(self.keepalive_interval_ms))
+    }
+
+    /// Apply the settings to the daemon's server. `tcp_nodelay` only
+    /// reaches listeners the server binds itself; pass it to
+    /// `TcpIncoming::from_listener` for `serve_with_incoming`
+    pub fn server(&self, server: Server) -> Server {
+        server
+            .initial_stream_window_size(self.stream_window_bytes)
+            .initial_connection_window_size(self.connection_window_bytes)
+            .http2_adaptive_window(Some(self.adaptive_window))
+            .max_concurrent_streams(self.max_concurrent_streams)
+            .http2_keepalive_interval(self.keepalive_interval())
+            .http2_keepalive_timeout(Some(Duration::from_millis(self.keepalive_timeout_ms)))
+            .tcp_nodelay(self.tcp_nodelay)
+    }
+
+    /// Apply the settings to a client channel
+    pub fn endpoint(&self, endpoint: Endpoint) -> Endpoint {
+        let endpoint = endpoint
+            .initial_stream_window_size(self.stream_window_bytes)
+            .initial_connection_window_size(self.connection_window_bytes)
+            .http2_adaptive_window(self.adaptive_window)
+            .keep_alive_timeout(Duration::from_millis(self.keepalive_timeout_ms))
+            .tcp_nodelay(self.tcp_nodelay);
+        match self.keepalive_interval() {
+            Some(interval) => endpoint
+                .http2_keep_alive_interval(interval)
+                .keep_alive_while_idle(true),
+            None => endpoint,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_limit() {
+        let rtt = Duration::from_millis(80);
+        // A 300-NPC WorldTick is ~40 KB; at 20 Hz that is 0.8 MB/s
+        assert!(Http2Config::lan().stream_limit(rtt) > 0.8e6);
+        assert!(Http2Config::wan().stream_limit(rtt) > 100e6);
+        let narrow = Http2Config {
+            connection_window_bytes: 65_535,
+            ..Http2Config::wan()
+        };
+        assert!(narrow.stream_limit(rtt) < 1e6);
+    }
+}
@@ -0,0 +1,166 @@
+//! Picking animals for rancher NPCs (v1.2+).
+//!
+//! Animals in `WorldTick.nearby_entities` carry an `AnimalState`; these
+//! helpers find the ones a `BreedAnimalsAction`, `ShearAction` or
+//! `MilkAction` would succeed on, so the daemon does not send directives
+//! the plugin would reject.
+
+use std::collections::HashMap;
+
+use crate::npc_society::v1::{AnimalState, EntitySnapshot};
+
+/// Usual breeding food of a species, if it can be bred
+pub fn breeding_food(entity_type: &str) -> Option<&'static str> {
+    let food = match entity_type {
+        "minecraft:cow" | "minecraft:mooshroom" | "minecraft:sheep" | "minecraft:goat" => {
+            "minecraft:wheat"
+        }
+        "minecraft:pig" => "minecraft:carrot",
+        "minecraft:chicken" => "minecraft:wheat_seeds",
+        "minecraft:rabbit" => "minecraft:dandelion",
+        "minecraft:horse" | "minecraft:donkey" => "minecraft:golden_carrot",
+        "minecraft:wolf" => "minecraft:beef",
+        "minecraft:cat" | "minecraft:ocelot" => "minecraft:cod",
+        "minecraft:llama" => "minecraft:hay_block",
+        "minecraft:turtle" => "minecraft:seagrass",
+        "minecraft:bee" => "minecraft:poppy",
+        _ => return None,
+    };
+    Some(food)
+}
+
+fn adult(entity: &EntitySnapshot) -> Option<&AnimalState> {
+    entity.animal.as_ref().filter(|a| !a.baby)
+}
+
+/// Pairs of adults of the same species that can breed now, each animal in
+/// at most one pair
+pub fn breeding_pairs(entities: &[EntitySnapshot]) -> Vec<(&EntitySnapshot, &EntitySnapshot)> {
+    let mut waiting: HashMap<&str, &EntitySnapshot> = HashMap::new();
+    let mut pairs = Vec::new();
+    for entity in entities {
+        let ready = adult(entity).is_some_and(|a| a.breed_cooldown_ticks == 0 && !a.in_love);
+        if !ready || breeding_food(&entity.entity_type).is_none() {
+            continue;
+        }
+        match waiting.remove(entity.entity_type.as_str()) {
+            Some(partner) => pairs.push((partner, entity)),
+            None => {
+                waiting.insert(&entity.entity_type, entity);
+            }
+        }
+    }
+    pairs
+}
+
+/// Whether a ShearAction would get anything from `entity`
+pub fn can_shear(entity: &EntitySnapshot) -> bool {
+    let shearable = matches!(
+        entity.entity_type.as_str(),
+        "minecraft:sheep" | "minecraft:mooshroom" | "minecraft:snow_golem"
+    );
+    shearable && !entity.animal.as_ref().is_some_and(|a| a.baby || a.sheared)
+}
+
+/// Whether a MilkAction with a bucket would work on `entity`
+pub fn can_milk(entity: &EntitySnapshot) -> bool {
+    let milkable = matches!(
+        entity.entity_type.as_str(),
+        "minecraft:cow" | "minecraft:goat" | "minecraft:mooshroom"
+    );
+    milkable && adult(entity).is_some()
+}
+
+/// Untamed animals that can be tamed
+pub fn tameable(entities: &[EntitySnapshot]) -> impl Iterator<Item = &EntitySnapshot> {
+    entities.iter().filter(|e| {
+        e.animal
+            .as_ref()
+            .is_some_and(|a| a.tameable && a.owner_uuid.is_empty())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animal(uuid: &str, entity_type: &str, state: AnimalState) -> EntitySnapshot {
+        EntitySnapshot {
+            entity_uuid: uuid.to_string(),
+            entity_type: entity_type.to_string(),
+            animal: Some(state),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_breeding_pairs() {
+        let adult = AnimalState::default();
+        let entities = vec![
+            animal("c1", "minecraft:cow", adult.clone()),
+            animal("p1", "minecraft:pig", adult.clone()),
+            animal(
+                "c2",
+                "minecraft:cow",
+                AnimalState {
+                    baby: true,
+                    ..Default::default()
+                },
+            ),
+            animal(
+                "c3",
+                "minecraft:cow",
+                AnimalState {
+                    breed_cooldown_ticks: 3000,
+                    ..Default::default()
+                },
+            ),
+            animal("c4", "minecraft:cow", adult.clone()),
+            animal("c5", "minecraft:cow", adult.clone()),
+            animal("z1", "minecraft:zombie", adult),
+        ];
+        let pairs: Vec<_> = breeding_pairs(&entities)
+            .into_iter()
+            .map(|(a, b)| (a.entity_uuid.as_str(), b.entity_uuid.as_str()))
+            .collect();
+        assert_eq!(pairs, [("c1", "c4")]);
+    }
+
+    #[test]
+    fn test_shear_milk_tame() {
+        let sheared = animal(
+            "s1",
+            "minecraft:sheep",
+            AnimalState {
+                sheared: true,
+                ..Default::default()
+            },
+        );
+        let sheep = animal("s2", "minecraft:sheep", AnimalState::default());
+        assert!(!can_shear(&sheared));
+        assert!(can_shear(&sheep));
+        assert!(!can_milk(&sheep));
+        assert!(can_milk(&animal("c1", "minecraft:cow", AnimalState::default())));
+
+        let wolf = animal(
+            "w1",
+            "minecraft:wolf",
+            AnimalState {
+                tameable: true,
+                ..Default::default()
+            },
+        );
+        let owned = animal(
+            "w2",
+            "minecraft:wolf",
+            AnimalState {
+                tameable: true,
+                owner_uuid: "steve".to_string(),
+                ..Default::default()
+            },
+        );
+        let entities = [wolf, owned, sheep];
+        let untamed: Vec<_> = tameable(&entities).map(|e| e.entity_uuid.as_str()).collect();
+        assert_eq!(untamed, ["w1"]);
+    }
+}
@@ -0,0 +1,267 @@
+//! Directive, stream and prompt ids that never repeat.
+//!
+//! The plugin runs each directive_id once and answers it with an
+//! ActionResult, so an id handed out twice makes a result correlate with
+//! the wrong directive: a restarted daemon whose counter starts over again,
+//! or two replicas driving the same server. A [`DirectiveIdFactory`] puts
+//! the replica and the time it was created into every id next to a
+//! sequence number:
+//!
+//! ```text
+//! blacksmith:dir:replica-a:lx8kuby8:1z
+//! ^ npc      ^ kind       ^ epoch   ^ sequence (base 36)
+//! ```
+//!
+//! The NPC part is optional ([`DirectiveIdFactory::next`] leaves it out for
+//! ids that belong to no NPC). [`ParsedId::parse`] takes an id apart again,
+//! and [`DirectiveIdFactory::issued`] tells whether an id came from this
+//! factory rather than an earlier run of the daemon.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::time::now_ms;
+use crate::types::MAX_ID_LEN;
+
+/// Separates the parts of an id
+const SEPARATOR: char = ':';
+
+/// Hands out ids unique across reconnects, restarts and replicas
+#[derive(Debug)]
+pub struct DirectiveIdFactory {
+    replica: String,
+    epoch_ms: i64,
+    /// replica and epoch as they appear in ids: `replica-a:lx8kuby8`
+    suffix: String,
+    next: AtomicU64,
+}
+
+impl DirectiveIdFactory {
+    /// Ids of replica `replica` started at `epoch_ms` (Unix ms). Characters
+    /// that would make the id ambiguous (`:`, whitespace) become `_`.
+    pub fn new(replica: &str, epoch_ms: i64) -> Self {
+        let replica = sanitize(replica);
+        let replica = if replica.is_empty() {
+            "local".to_string()
+        } else {
+            replica
+        };
+        let epoch_ms = epoch_ms.max(0);
+        Self {
+            suffix: format!("{replica}{SEPARATOR}{}", base36(epoch_ms as u64)),
+            replica,
+            epoch_ms,
+            next: AtomicU64::new(1),
+        }
+    }
+
+    /// Ids of replica `replica`, starting now
+    pub fn starting_now(replica: &str) -> Self {
+        Self::new(replica, now_ms())
+    }
+
+    /// Replica part of the ids
+    pub fn replica(&self) -> &str {
+        &self.replica
+    }
+
+    /// When the factory was created (Unix ms)
+    pub fn epoch_ms(&self) -> i64 {
+        self.epoch_ms
+    }
+
+    /// A fresh id of `kind` that belongs to no NPC: `stream:replica-a:lx8kuby8:1`
+    pub fn next(&self, kind: &str) -> String {
+        format!(
+            "{}{SEPARATOR}{}{SEPARATOR}{}",
+            sanitize(kind),
+            self.suffix,
+            self.sequence()
+        )
+    }
+
+    /// A fresh id of `kind` for `npc_id`: `blacksmith:dir:replica-a:lx8kuby8:2`.
+    /// An NPC id too long to fit in [`MAX_ID_LEN`] is cut short.
+    pub fn next_for(&self, npc_id: &str, kind: &str) -> String {
+        let rest = self.next(kind);
+        let room = MAX_ID_LEN.saturating_sub(rest.len() + 1);
+        let mut end = npc_id.len().min(room);
+        while !npc_id.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}{SEPARATOR}{rest}", sanitize_npc(&npc_id[..end]))
+    }
+
+    /// Whether `id` was handed out by this factory (same replica, same run)
+    pub fn issued(&self, id: &str) -> bool {
+        ParsedId::parse(id).is_some_and(|parsed| {
+            parsed.replica == self.replica
+                && parsed.epoch_ms == self.epoch_ms
+                && parsed.sequence < self.next.load(Ordering::Relaxed)
+        })
+    }
+
+    fn sequence(&self) -> String {
+        base36(self.next.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Default for DirectiveIdFactory {
+    fn default() -> Self {
+        Self::starting_now("")
+    }
+}
+
+/// The parts of an id made by a [`DirectiveIdFactory`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParsedId<'a> {
+    /// NPC the id was made for, if any (possibly cut short)
+    pub npc_id: Option<&'a str>,
+    /// What the id names: "dir", "stream", "prompt", ...
+    pub kind: &'a str,
+    /// Replica that made it
+    pub replica: &'a str,
+    /// When that replica's factory was created (Unix ms)
+    pub epoch_ms: i64,
+    /// Position in the factory's sequence, from 1
+    pub sequence: u64,
+}
+
+impl<'a> ParsedId<'a> {
+    /// Take `id` apart; None for ids made some other way
+    pub fn parse(id: &'a str) -> Option<Self> {
+        let mut parts = id.rsplitn(5, SEPARATOR);
+        let sequence = parse_base36(parts.next()?)?;
+        let epoch_ms = i64::try_from(parse_base36(parts.next()?)?).ok()?;
+        let replica = parts.next().filter(|part| !part.is_empty())?;
+        let kind = parts.next().filter(|part| !part.is_empty())?;
+        let npc_id = parts.next();
+        if npc_id == Some("") {
+            return None;
+        }
+        Some(Self {
+            npc_id,
+            kind,
+            replica,
+            epoch_ms,
+            sequence,
+        })
+    }
+}
+
+impl fmt::Display for ParsedId<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(npc_id) = self.npc_id {
+            write!(f, "{npc_id}{SEPARATOR}")?;
+        }
+        write!(
+            f,
+            "{}{SEPARATOR}{}{SEPARATOR}{}{SEPARATOR}{}",
+            self.kind,
+            self.replica,
+            base36(self.epoch_ms as u64),
+            base36(self.sequence)
+        )
+    }
+}
+
+/// `value` without separators or whitespace
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c == SEPARATOR || c.is_whitespace() || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// NPC ids may contain separators (parsing splits from the right), but no
+/// whitespace
+fn sanitize_npc(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_whitespace() || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+fn base36(mut value: u64) -> String {
+    const DIGITS: &[u8; 36] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut digits = Vec::new();
+    loop {
+        digits.push(DIGITS[(value % 36) as usize]);
+        value /= 36;
+        if value == 0 {
+            break;
+        }
+    }
+    digits.reverse();
+    String::from_utf8(digits).expect("base 36 digits are ASCII")
+}
+
+fn parse_base36(value: &str) -> Option<u64> {
+    if value.is_empty()
+        || value
+            .bytes()
+            .any(|b| !matches!(b, b'0'..=b'9' | b'a'..=b'z'))
+    {
+        return None;
+    }
+    u64::from_str_radix(value, 36).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DirectiveId;
+
+    #[test]
+    fn test_ids_roundtrip() {
+        let ids = DirectiveIdFactory::new("replica a", 1_718_000_000_000);
+        let stream = ids.next("stream");
+        let directive = ids.next_for("guild:blacksmith", "dir");
+        assert_eq!(stream, "stream:replica_a:lx8kuby8:1");
+        assert_eq!(directive, "guild:blacksmith:dir:replica_a:lx8kuby8:2");
+
+        let parsed = ParsedId::parse(&directive).unwrap();
+        assert_eq!(parsed.npc_id, Some("guild:blacksmith"));
+        assert_eq!(parsed.kind, "dir");
+        assert_eq!(parsed.replica, "replica_a");
+        assert_eq!(parsed.epoch_ms, 1_718_000_000_000);
+        assert_eq!(parsed.sequence, 2);
+        assert_eq!(parsed.to_string(), directive);
+        assert_eq!(ParsedId::parse(&stream).unwrap().npc_id, None);
+
+        assert!(ids.issued(&directive));
+        assert!(!ids.issued("guild:blacksmith:dir:replica_a:lx8kuby8:3"));
+        assert_eq!(ParsedId::parse("dir-17"), None);
+        assert_eq!(ParsedId::parse("blacksmith-bt-3"), None);
+    }
+
+    #[test]
+    fn test_ids_never_collide() {
+        // A restart (later epoch) and another replica both start over at 1
+        let first = DirectiveIdFactory::new("a", 1_000);
+        let restarted = DirectiveIdFactory::new("a", 1_001);
+        let other = DirectiveIdFactory::new("b", 1_000);
+        let id = first.next_for("npc", "dir");
+        assert_ne!(id, restarted.next_for("npc", "dir"));
+        assert_ne!(id, other.next_for("npc", "dir"));
+        assert!(!restarted.issued(&id));
+
+        // Long NPC ids are cut so the id stays valid
+        let long = first.next_for(&"é".repeat(100), "dir");
+        assert!(long.len() <= MAX_ID_LEN);
+        assert!(DirectiveId::new(long.as_str()).is_ok());
+        assert_eq!(ParsedId::parse(&long).unwrap().kind, "dir");
+    }
+}
@@ -3,11 +3,9 @@
 //! - receives SpeakDirective
 //! - sends ActionResult
 
-// Include the generated proto code
+// The generated proto code, as dependents get it
 pub mod npc_society {
-    pub mod v1 {
-        tonic::include_proto!("npc_society.v1");
-    }
+    pub use npc_society_proto::v1;
 }
 
 use npc_society::v1::{
@@ -21,6 +19,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_hello_with_v11_fields() {
+        use npc_society::v1::PcmFormat;
+        
         // Test Hello with v1.1+ fields
         let hello = Hello {
             plugin_version: "1.1.0".to_string(),
@@ -30,10 +30,25 @@ mod tests {
             voice_available: true,
             server_name: "Test Server".to_string(),
             daemon_mode: "external".to_string(),
+            supported_audio_formats: vec![PcmFormat::S16le as i32, PcmFormat::Opus as i32],
+            world_control_available: false,
+            supported_deliveries: Vec::new(),
+            cached_audio_assets: Vec::new(),
+            dry_run_available: false,
+            supported_movement: Vec::new(),
+            supported_voice_sample_rates_hz: vec![48_000, 16_000],
+            voice_consent_required: true,
+            voice_capture_on_request: false,
+            audio_stream_available: false,
+            world_bootstrap_available: false,
+            debug_path_available: false,
+            probe_available: false,
+            keep_chunk_loaded_available: false,
         };
 
         let msg = ClientMessage {
             message: Some(ClientMsg::Hello(hello)),
+            ..Default::default()
         };
 
         // Verify serialization works
@@ -46,6 +61,8 @@ mod tests {
                 assert!(h.voice_available);
                 assert_eq!(h.server_name, "Test Server");
                 assert_eq!(h.daemon_mode, "external");
+                assert_eq!(h.supported_audio_formats.len(), 2);
+                assert_eq!(h.supported_voice_sample_rates_hz, [48_000, 16_000]);
             }
             _ => panic!("Decoding failed"),
         }
@@ -66,10 +83,12 @@ mod tests {
             voice_id: "en-US-Neural2-D".to_string(),
             volume: 0.8,
             stream_id: "stream-1".to_string(),
+            ..Default::default()
         };
         
         let msg = ServerMessage {
             message: Some(ServerMsg::SpeakDirective(speak)),
+            ..Default::default()
         };
         
         use prost::Message;
@@ -107,12 +126,15 @@ mod tests {
                                 x: 10,
                                 y: 20,
                                 z: 30,
+                                ..Default::default()
                             }),
                             block_type: "minecraft:diamond_ore".to_string(),
+                            ..Default::default()
                         },
                     ],
                 },
             )),
+            ..Default::default()
         };
 
         // Verify serialization
@@ -139,14 +161,16 @@ mod tests {
         let audio = AudioChunk {
             npc_id: "test_npc".to_string(),
             stream_id: "stream-1".to_string(),
-            pcm_data: vec![0u8; 960],
+            pcm_data: vec![0u8; 960].into(),
             sequence: 0,
             is_final: true,
             directive_id: "speak-1".to_string(),
+            format: npc_society::v1::PcmFormat::S16le as i32,
         };
         
         let msg = ServerMessage {
             message: Some(ServerMsg::AudioChunk(audio)),
+            ..Default::default()
         };
         
         use prost::Message;
@@ -172,7 +196,7 @@ mod tests {
         let frame = VoicePcmFrame {
             npc_id: "test_npc".to_string(),
             player_uuid: "player-1".to_string(),
-            pcm_data: vec![0u8; 1920],
+            pcm_data: vec![0u8; 1920].into(),
             sequence: 0,
             timestamp_ms: 1234567890,
             sample_rate_hz: 48000,
@@ -181,6 +205,7 @@ mod tests {
         
         let msg = ClientMessage {
             message: Some(ClientMsg::VoicePcmFrame(frame)),
+            ..Default::default()
         };
         
         use prost::Message;
@@ -197,4 +222,3359 @@ mod tests {
         
         println!("✓ VoicePcmFrame with format serializes correctly");
     }
+    
+    #[tokio::test]
+    async fn test_get_snapshot_response() {
+        use npc_society::v1::{GetSnapshotResponse, NpcSnapshot, Position};
+        
+        let response = GetSnapshotResponse {
+            server_id: "test".to_string(),
+            server_tick: 1200,
+            timestamp_ms: 1234567890,
+            npcs: vec![NpcSnapshot {
+                npc_id: "miner_01".to_string(),
+                position: Some(Position {
+                    world: "world".to_string(),
+                    x: 100.5,
+                    y: 64.0,
+                    z: -200.5,
+                    ..Default::default()
+                }),
+                health_norm: 1.0,
+                ..Default::default()
+            }],
+            nearby_players: vec![],
+        };
+        
+        use prost::Message;
+        let bytes = response.encode_to_vec();
+        let decoded = GetSnapshotResponse::decode(&bytes[..]).unwrap();
+        
+        assert_eq!(decoded.server_tick, 1200);
+        assert_eq!(decoded.npcs.len(), 1);
+        assert_eq!(decoded.npcs[0].npc_id, "miner_01");
+        
+        println!("✓ GetSnapshotResponse serializes correctly");
+    }
+    
+    #[tokio::test]
+    async fn test_get_snapshot_handler() {
+        use crate::npc_society::v1::{
+            client_message::Message as ClientMsg, npc_society_service_server::NpcSocietyService,
+            ClientMessage, GetSnapshotRequest, Hello, NpcSnapshot, WorldTick,
+        };
+        use npc_society_example::connection::ConnectionContext;
+        use npc_society_example::outbound::{self, QueueConfig};
+        
+        // Set up as connect() does for a new plugin connection
+        let cx = std::sync::Arc::new(ConnectionContext::new("10.0.0.2:41000", 1_700_000_000_000));
+        let service = crate::ExampleNpcSocietyService {
+            state: std::sync::Arc::new(std::sync::Mutex::new(crate::SharedState {
+                connected: true,
+                connection: Some(cx.clone()),
+                ..crate::SharedState::new(&Default::default())
+            })),
+            ..Default::default()
+        };
+        let (tx, _rx) = outbound::queue(QueueConfig::default());
+        let request = |npc_ids: &[&str]| {
+            tonic::Request::new(GetSnapshotRequest {
+                server_id: "survival".to_string(),
+                npc_ids: npc_ids.iter().map(|id| id.to_string()).collect(),
+            })
+        };
+        // No plugin has said hello yet
+        assert!(service.get_snapshot(request(&[])).await.is_err());
+        
+        let hello = ClientMessage {
+            message: Some(ClientMsg::Hello(Hello {
+                server_id: "survival".to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        service.handle_client_message(hello, &cx, &tx);
+        let status = service.get_snapshot(request(&[])).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+        
+        let npc = |npc_id: &str| NpcSnapshot {
+            npc_id: npc_id.to_string(),
+            health_norm: 1.0,
+            ..Default::default()
+        };
+        let tick = ClientMessage {
+            message: Some(ClientMsg::WorldTick(WorldTick {
+                server_tick: 1201,
+                timestamp_ms: 1_700_000_000_050,
+                npcs: vec![npc("miner_01"), npc("guard_01")],
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        service.handle_client_message(tick, &cx, &tx);
+        
+        let snapshot = service.get_snapshot(request(&[])).await.unwrap().into_inner();
+        assert_eq!(snapshot.server_id, "survival");
+        assert_eq!(snapshot.server_tick, 1201);
+        assert_eq!(snapshot.npcs.len(), 2);
+        let filtered = service.get_snapshot(request(&["guard_01"])).await.unwrap().into_inner();
+        assert_eq!(filtered.npcs.len(), 1);
+        assert_eq!(filtered.npcs[0].npc_id, "guard_01");
+        
+        println!("✓ GetSnapshot serves the latest WorldTick, filtered by npc_ids");
+    }
+    
+    #[tokio::test]
+    async fn test_get_npc_state_with_pending_directives() {
+        use npc_society::v1::{
+            action_directive::Action, ActionDirective, AiBudgetState, AiSpend, DirectiveAck,
+            GetNpcStateResponse, MoveAction, NpcSnapshot, PendingDirective,
+        };
+        
+        let response = GetNpcStateResponse {
+            npc: Some(NpcSnapshot {
+                npc_id: "miner_01".to_string(),
+                current_activity: "mining".to_string(),
+                ..Default::default()
+            }),
+            server_tick: 1200,
+            pending_directives: vec![PendingDirective {
+                directive: Some(ActionDirective {
+                    directive_id: "dir-1".to_string(),
+                    npc_id: "miner_01".to_string(),
+                    priority: 1,
+                    dry_run: false,
+                    fail_if_unloaded: false,
+                    action: Some(Action::Move(MoveAction {
+                        target: None,
+                        speed: 0.5,
+                        pathfind: true,
+                        allow_swim: None,
+                        allow_climb: None,
+                        allow_parkour_jumps: None,
+                        avoid_water: false,
+                        doors: 0,
+                        fence_gates: 0,
+                        trapdoors: 0,
+                    })),
+                }),
+                sent_at_ms: 1234567890,
+                ack: Some(DirectiveAck {
+                    directive_id: "dir-1".to_string(),
+                    npc_id: "miner_01".to_string(),
+                    queued_position: 2,
+                    estimated_start_ms: 1234569890,
+                }),
+            }],
+            last_result: None,
+            spend: Some(AiSpend {
+                prompt_tokens: 12000,
+                tts_characters: 900,
+                cost_usd: 0.42,
+                budget_usd: 0.5,
+                state: AiBudgetState::Downgraded as i32,
+                ..Default::default()
+            }),
+        };
+        
+        use prost::Message;
+        let bytes = response.encode_to_vec();
+        let decoded = GetNpcStateResponse::decode(&bytes[..]).unwrap();
+        
+        assert_eq!(decoded.pending_directives.len(), 1);
+        let pending = &decoded.pending_directives[0];
+        assert_eq!(pending.directive.as_ref().unwrap().directive_id, "dir-1");
+        assert_eq!(pending.sent_at_ms, 1234567890);
+        assert_eq!(pending.ack.as_ref().unwrap().queued_position, 2);
+        assert_eq!(decoded.spend.unwrap().state(), AiBudgetState::Downgraded);
+        
+        println!("✓ GetNpcStateResponse with pending directives serializes correctly");
+    }
+    
+    #[tokio::test]
+    async fn test_hello_ack_negotiates_opus() {
+        use npc_society::v1::{server_message::Message as ServerMsg, HelloAck, PcmFormat, ServerMessage};
+        
+        let ack = HelloAck {
+            protocol_version: "1".to_string(),
+            daemon_version: "0.1.0".to_string(),
+            voice_format: PcmFormat::Opus as i32,
+            playback_format: PcmFormat::S16le as i32,
+            world_control: false,
+            voice_sample_rate_hz: 16_000,
+            audio_stream_token: String::new(),
+        };
+        
+        let msg = ServerMessage {
+            message: Some(ServerMsg::HelloAck(ack)),
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let bytes = msg.encode_to_vec();
+        let decoded = ServerMessage::decode(&bytes[..]).unwrap();
+        
+        match decoded.message {
+            Some(ServerMsg::HelloAck(a)) => {
+                assert_eq!(a.voice_format(), PcmFormat::Opus);
+                assert_eq!(a.playback_format(), PcmFormat::S16le);
+                assert_eq!(a.voice_sample_rate_hz, 16_000);
+            }
+            _ => panic!("Decoding failed"),
+        }
+        
+        println!("✓ HelloAck with negotiated formats serializes correctly");
+    }
+
+    #[cfg(feature = "audio-opus")]
+    #[tokio::test]
+    async fn test_opus_voice_is_decoded() {
+        use npc_society::v1::{server_message::Message as ServerMsg, PcmFormat, VoicePcmFrame};
+        use npc_society_example::audio::{self, OpusEncoder};
+        use npc_society_example::connection::ConnectionContext;
+        use npc_society_example::events::{self, ClientEvent};
+        use npc_society_example::outbound::{self, QueueConfig};
+        
+        let service = crate::ExampleNpcSocietyService::default();
+        let cx = ConnectionContext::new("10.0.0.2:41000", 1_700_000_000_000);
+        let (tx, mut rx) = outbound::queue(QueueConfig::default());
+        let hello = ClientMessage::from(Hello {
+            server_id: "survival".to_string(),
+            voice_available: true,
+            supported_audio_formats: vec![PcmFormat::S16le as i32, PcmFormat::Opus as i32],
+            ..Default::default()
+        });
+        events::dispatch(&service, ClientEvent::try_from(hello).unwrap(), &cx, &tx);
+        let ack = std::iter::from_fn(|| rx.try_next())
+            .find_map(|message| match message.message {
+                Some(ServerMsg::HelloAck(ack)) => Some(ack),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(ack.voice_format(), PcmFormat::Opus);
+        assert_eq!(ack.playback_format(), PcmFormat::S16le);
+        
+        // A loud tone, 20ms per frame at the negotiated rate
+        let rate = match ack.voice_sample_rate_hz {
+            0 => audio::PROTOCOL_SAMPLE_RATE_HZ,
+            hz => hz as u32,
+        };
+        let samples = rate as usize / 50;
+        let mut encoder = OpusEncoder::new(rate).unwrap();
+        for sequence in 0..20u64 {
+            let tone: Vec<f32> = (0..samples)
+                .map(|i| ((sequence as usize * samples + i) as f32 * 300.0 * std::f32::consts::TAU / rate as f32).sin() * 0.5)
+                .collect();
+            let frame = ClientMessage::from(VoicePcmFrame {
+                npc_id: "npc_miner_01".to_string(),
+                player_uuid: "player-1".to_string(),
+                pcm_data: encoder.encode_frame(&audio::f32_to_s16le(&tone)).unwrap().into(),
+                sequence,
+                sample_rate_hz: rate as i32,
+                format: PcmFormat::Opus as i32,
+                ..Default::default()
+            });
+            events::dispatch(&service, ClientEvent::try_from(frame).unwrap(), &cx, &tx);
+        }
+        
+        let state = service.state.lock().unwrap();
+        let context = state.conversations.context("npc_miner_01", crate::now_ms());
+        assert_eq!(context.speaking, vec!["player-1".to_string()]);
+        assert_eq!(state.opus_voice.len(), 1);
+        
+        println!("✓ Opus voice is negotiated and decoded before segmentation");
+    }
+
+    #[tokio::test]
+    async fn test_speak_directive_whisper() {
+        use npc_society::v1::{
+            server_message::Message as ServerMsg, Emotion, ServerMessage, SpeakDirective,
+            SpeechDelivery,
+        };
+        
+        let speak = SpeakDirective {
+            npc_id: "quest_giver".to_string(),
+            text: "Meet me behind the mill.".to_string(),
+            emotion: "fearful".to_string(),
+            emotion_type: Emotion::Fearful as i32,
+            directive_id: "speak-2".to_string(),
+            target_player_uuids: vec!["player-uuid-1".to_string()],
+            delivery: SpeechDelivery::Direct as i32,
+            ..Default::default()
+        };
+        
+        let msg = ServerMessage {
+            message: Some(ServerMsg::SpeakDirective(speak)),
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let bytes = msg.encode_to_vec();
+        let decoded = ServerMessage::decode(&bytes[..]).unwrap();
+        
+        match decoded.message {
+            Some(ServerMsg::SpeakDirective(s)) => {
+                assert_eq!(s.target_player_uuids, vec!["player-uuid-1".to_string()]);
+                assert_eq!(s.delivery(), SpeechDelivery::Direct);
+                assert_eq!(s.emotion_type(), Emotion::Fearful);
+            }
+            _ => panic!("Decoding failed"),
+        }
+        
+        println!("✓ SpeakDirective addressed to one player serializes correctly");
+    }
+    
+    #[tokio::test]
+    async fn test_quest_offer_and_update() {
+        use npc_society::v1::{
+            client_message::Message as ClientMsg, ClientMessage, ItemStack, ObjectiveProgress,
+            QuestObjective, QuestObjectiveType, QuestOffer, QuestStatus, QuestUpdate,
+        };
+        
+        let offer = QuestOffer {
+            quest_id: "quest-1".to_string(),
+            npc_id: "smith".to_string(),
+            player_uuid: "player-uuid-1".to_string(),
+            title: "Iron for the smith".to_string(),
+            objectives: vec![QuestObjective {
+                objective_id: "iron".to_string(),
+                r#type: QuestObjectiveType::MineBlock as i32,
+                target: "minecraft:iron_ore".to_string(),
+                required_count: 10,
+                ..Default::default()
+            }],
+            reward_items: vec![ItemStack {
+                item_type: "minecraft:iron_sword".to_string(),
+                quantity: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(offer.objectives[0].r#type(), QuestObjectiveType::MineBlock);
+        
+        let update = ClientMessage {
+            message: Some(ClientMsg::QuestUpdate(QuestUpdate {
+                quest_id: offer.quest_id.clone(),
+                npc_id: offer.npc_id.clone(),
+                player_uuid: offer.player_uuid.clone(),
+                status: QuestStatus::InProgress as i32,
+                progress: vec![ObjectiveProgress {
+                    objective_id: "iron".to_string(),
+                    count: 4,
+                    complete: false,
+                }],
+                timestamp_ms: 1234567890,
+            })),
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let bytes = update.encode_to_vec();
+        let decoded = ClientMessage::decode(&bytes[..]).unwrap();
+        
+        match decoded.message {
+            Some(ClientMsg::QuestUpdate(u)) => {
+                assert_eq!(u.status(), QuestStatus::InProgress);
+                assert_eq!(u.progress[0].count, 4);
+            }
+            _ => panic!("Decoding failed"),
+        }
+        
+        println!("✓ QuestOffer and QuestUpdate serialize correctly");
+    }
+    
+    #[tokio::test]
+    async fn test_transfer_currency_and_balance() {
+        use npc_society::v1::{
+            server_message::Message as ServerMsg, NpcSnapshot, ServerMessage,
+            TransferCurrencyDirective, TransferDirection,
+        };
+        
+        let msg = ServerMessage {
+            message: Some(ServerMsg::TransferCurrency(TransferCurrencyDirective {
+                directive_id: "pay-1".to_string(),
+                npc_id: "baker".to_string(),
+                player_uuid: "player-uuid-1".to_string(),
+                direction: TransferDirection::PlayerToNpc as i32,
+                amount: 350,
+                reason: "3 bread".to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let bytes = msg.encode_to_vec();
+        let decoded = ServerMessage::decode(&bytes[..]).unwrap();
+        match decoded.message {
+            Some(ServerMsg::TransferCurrency(t)) => {
+                assert_eq!(t.direction(), TransferDirection::PlayerToNpc);
+                assert_eq!(t.amount, 350);
+            }
+            _ => panic!("Decoding failed"),
+        }
+        
+        // A zero balance is distinguishable from "no economy plugin"
+        let broke = NpcSnapshot {
+            balance: Some(0),
+            ..Default::default()
+        };
+        let decoded = NpcSnapshot::decode(&broke.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.balance, Some(0));
+        assert_eq!(NpcSnapshot::default().balance, None);
+        
+        println!("✓ TransferCurrencyDirective and NpcSnapshot.balance serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_player_profile() {
+        use npc_society::v1::{Equipment, ItemStack, PlayerSnapshot, WorldTick};
+        
+        let msg = ClientMessage {
+            message: Some(ClientMsg::WorldTick(WorldTick {
+                nearby_players: vec![PlayerSnapshot {
+                    player_uuid: "069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string(),
+                    game_mode: "creative".to_string(),
+                    op: true,
+                    permissions: vec!["npcsociety.vip".to_string()],
+                    locale: "pt_br".to_string(),
+                    equipment: Some(Equipment {
+                        feet: Some(ItemStack {
+                            item_type: "minecraft:golden_boots".to_string(),
+                            quantity: 1,
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let decoded = ClientMessage::decode(&msg.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ClientMsg::WorldTick(tick)) => {
+                let player = &tick.nearby_players[0];
+                assert!(player.op);
+                assert_eq!(player.locale, "pt_br");
+                assert_eq!(player.permissions, ["npcsociety.vip"]);
+                assert!(player.equipment.as_ref().unwrap().feet.is_some());
+            }
+            _ => panic!("Decoding failed"),
+        }
+        
+        println!("✓ PlayerSnapshot profile fields serialize correctly");
+    }
+    
+    #[tokio::test]
+    async fn test_server_load() {
+        use npc_society::v1::WorldTick;
+        
+        let lagging = WorldTick {
+            server_tick: 72_000,
+            timestamp_ms: 1_700_000_000_000,
+            tps: 14.8,
+            mspt: 67.5,
+            loaded_chunks: 1_250,
+            entity_count: 4_800,
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let decoded = WorldTick::decode(&lagging.encode_to_vec()[..]).unwrap();
+        assert_eq!((decoded.server_tick, decoded.timestamp_ms), (72_000, 1_700_000_000_000));
+        assert!((decoded.tps - 14.8).abs() < 1e-6);
+        assert!((decoded.mspt - 67.5).abs() < 1e-6);
+        assert_eq!((decoded.loaded_chunks, decoded.entity_count), (1_250, 4_800));
+        // Older plugins report neither
+        assert_eq!(WorldTick::default().tps, 0.0);
+        
+        println!("✓ WorldTick load fields serialize correctly");
+    }
+    
+    #[tokio::test]
+    async fn test_equipment() {
+        use npc_society::v1::{
+            action_directive::Action, ActionDirective, EquipArmorAction, Equipment, EquipmentSlot,
+            ItemStack, NpcSnapshot,
+        };
+        
+        let npc = NpcSnapshot {
+            npc_id: "miner_01".to_string(),
+            held_item: "minecraft:iron_pickaxe".to_string(),
+            equipment: Some(Equipment {
+                main_hand: Some(ItemStack {
+                    item_type: "minecraft:iron_pickaxe".to_string(),
+                    quantity: 1,
+                    damage: 240,
+                    max_damage: 250,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let decoded = NpcSnapshot::decode(&npc.encode_to_vec()[..]).unwrap();
+        let main_hand = decoded.equipment.unwrap().main_hand.unwrap();
+        assert_eq!(main_hand.damage, 240);
+        assert_eq!(main_hand.max_damage, 250);
+        
+        let directive = ActionDirective {
+            directive_id: "equip-1".to_string(),
+            npc_id: "miner_01".to_string(),
+            priority: 1,
+            dry_run: false,
+            fail_if_unloaded: false,
+            action: Some(Action::EquipArmor(EquipArmorAction {
+                item_type: "minecraft:diamond_pickaxe".to_string(),
+                slot: EquipmentSlot::MainHand as i32,
+            })),
+        };
+        let decoded = ActionDirective::decode(&directive.encode_to_vec()[..]).unwrap();
+        match decoded.action {
+            Some(Action::EquipArmor(e)) => assert_eq!(e.slot(), EquipmentSlot::MainHand),
+            _ => panic!("Decoding failed"),
+        }
+        
+        println!("✓ NpcSnapshot.equipment and EquipArmorAction serialize correctly");
+    }
+    
+    #[tokio::test]
+    async fn test_enchant_and_repair() {
+        use npc_society::v1::{
+            action_result::Result as ActionResultType, EnchantItemResult, Enchantment, ItemStack,
+            RepairItemResult,
+        };
+        
+        let enchanted = ActionResult {
+            directive_id: "enchant-1".to_string(),
+            npc_id: "smith".to_string(),
+            success: true,
+            result: Some(ActionResultType::EnchantItemResult(EnchantItemResult {
+                item: Some(ItemStack {
+                    item_type: "minecraft:iron_pickaxe".to_string(),
+                    quantity: 1,
+                    max_damage: 250,
+                    enchantments: vec![Enchantment {
+                        enchantment_id: "minecraft:efficiency".to_string(),
+                        level: 3,
+                    }],
+                    ..Default::default()
+                }),
+                levels_spent: 3,
+                lapis_spent: 3,
+            })),
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let decoded = ActionResult::decode(&enchanted.encode_to_vec()[..]).unwrap();
+        match decoded.result {
+            Some(ActionResultType::EnchantItemResult(e)) => {
+                let item = e.item.unwrap();
+                assert_eq!(item.enchantments[0].enchantment_id, "minecraft:efficiency");
+                assert_eq!(item.enchantments[0].level, 3);
+                assert_eq!(e.levels_spent, 3);
+            }
+            _ => panic!("Decoding failed"),
+        }
+        
+        let repaired = ActionResult {
+            directive_id: "repair-1".to_string(),
+            success: true,
+            result: Some(ActionResultType::RepairItemResult(RepairItemResult {
+                levels_spent: 2,
+                materials_used: 1,
+                anvil_damaged: true,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let decoded = ActionResult::decode(&repaired.encode_to_vec()[..]).unwrap();
+        match decoded.result {
+            Some(ActionResultType::RepairItemResult(r)) => assert!(r.anvil_damaged),
+            _ => panic!("Decoding failed"),
+        }
+        
+        println!("✓ EnchantItemResult and RepairItemResult serialize correctly");
+    }
+    
+    #[tokio::test]
+    async fn test_consume_item() {
+        use npc_society::v1::{
+            action_result::Result as ActionResultType, ConsumeItemResult, ItemStack, NpcSnapshot,
+            PotionEffect,
+        };
+        
+        let result = ActionResult {
+            directive_id: "eat-1".to_string(),
+            npc_id: "miner_01".to_string(),
+            success: true,
+            result: Some(ActionResultType::ConsumeItemResult(ConsumeItemResult {
+                item_type: "minecraft:suspicious_stew".to_string(),
+                hunger_restored: 0.3,
+                hunger_norm: 0.55,
+                health_norm: 1.0,
+                effects_applied: vec![PotionEffect {
+                    effect_id: "minecraft:regeneration".to_string(),
+                    amplifier: 0,
+                    duration_ticks: 160,
+                }],
+                leftover: Some(ItemStack {
+                    item_type: "minecraft:bowl".to_string(),
+                    quantity: 1,
+                    ..Default::default()
+                }),
+            })),
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let decoded = ActionResult::decode(&result.encode_to_vec()[..]).unwrap();
+        match decoded.result {
+            Some(ActionResultType::ConsumeItemResult(c)) => {
+                assert_eq!(c.hunger_norm, 0.55);
+                assert_eq!(c.effects_applied[0].duration_ticks, 160);
+                assert_eq!(c.leftover.unwrap().item_type, "minecraft:bowl");
+            }
+            _ => panic!("Decoding failed"),
+        }
+        
+        let npc = NpcSnapshot {
+            effects: vec![PotionEffect {
+                effect_id: "minecraft:night_vision".to_string(),
+                amplifier: 0,
+                duration_ticks: -1,
+            }],
+            ..Default::default()
+        };
+        let decoded = NpcSnapshot::decode(&npc.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.effects[0].duration_ticks, -1);
+        
+        println!("✓ ConsumeItemResult and NpcSnapshot.effects serialize correctly");
+    }
+    
+    #[tokio::test]
+    async fn test_craft() {
+        use npc_society::v1::{
+            action_directive::Action, action_result::Result as ActionResultType, ActionDirective,
+            BlockPosition, CraftAction, CraftResult, ItemStack,
+        };
+        
+        let directive = ActionDirective {
+            directive_id: "craft-1".to_string(),
+            npc_id: "builder".to_string(),
+            priority: 1,
+            dry_run: false,
+            fail_if_unloaded: false,
+            action: Some(Action::Craft(CraftAction {
+                item_type: "minecraft:chest".to_string(),
+                count: 1,
+                table_position: Some(BlockPosition {
+                    world: "world".to_string(),
+                    x: 10,
+                    y: 64,
+                    z: 10,
+                    ..Default::default()
+                }),
+            })),
+        };
+        
+        use prost::Message;
+        let decoded = ActionDirective::decode(&directive.encode_to_vec()[..]).unwrap();
+        match decoded.action {
+            Some(Action::Craft(c)) => {
+                assert_eq!(c.item_type, "minecraft:chest");
+                assert!(c.table_position.is_some());
+            }
+            _ => panic!("Decoding failed"),
+        }
+        
+        let result = ActionResult {
+            directive_id: "craft-1".to_string(),
+            success: true,
+            result: Some(ActionResultType::CraftResult(CraftResult {
+                crafted: Some(ItemStack {
+                    item_type: "minecraft:chest".to_string(),
+                    quantity: 1,
+                    ..Default::default()
+                }),
+                consumed: vec![ItemStack {
+                    item_type: "minecraft:oak_planks".to_string(),
+                    quantity: 8,
+                    ..Default::default()
+                }],
+            })),
+            ..Default::default()
+        };
+        let decoded = ActionResult::decode(&result.encode_to_vec()[..]).unwrap();
+        match decoded.result {
+            Some(ActionResultType::CraftResult(c)) => assert_eq!(c.consumed[0].quantity, 8),
+            _ => panic!("Decoding failed"),
+        }
+        
+        println!("✓ CraftAction and CraftResult serialize correctly");
+    }
+    
+    #[tokio::test]
+    async fn test_directive_rejected() {
+        use npc_society::v1::{DirectiveRejected, RejectionCode};
+        
+        let msg = ClientMessage {
+            message: Some(ClientMsg::DirectiveRejected(DirectiveRejected {
+                directive_id: "dir-7".to_string(),
+                npc_id: "ghost".to_string(),
+                code: RejectionCode::UnknownNpc as i32,
+                detail: "no NPC named ghost".to_string(),
+            })),
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let decoded = ClientMessage::decode(&msg.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ClientMsg::DirectiveRejected(r)) => {
+                assert_eq!(r.directive_id, "dir-7");
+                assert_eq!(r.code(), RejectionCode::UnknownNpc);
+            }
+            _ => panic!("Decoding failed"),
+        }
+        
+        println!("✓ DirectiveRejected serializes correctly");
+    }
+    
+    #[tokio::test]
+    async fn test_combat_policy() {
+        use npc_society::v1::{
+            server_message::Message as ServerMsg, CombatPolicyObservation, CombatPolicyTrigger,
+            CombatStance, ServerMessage, SetCombatPolicyDirective, TargetFilter,
+        };
+        
+        let msg = ServerMessage {
+            message: Some(ServerMsg::SetCombatPolicy(SetCombatPolicyDirective {
+                npc_id: "guard".to_string(),
+                stance: CombatStance::Aggressive as i32,
+                flee_health_threshold: 0.25,
+                target_filters: vec![
+                    TargetFilter {
+                        entity_type: "#minecraft:raiders".to_string(),
+                        exclude: false,
+                    },
+                    TargetFilter {
+                        entity_type: "minecraft:*".to_string(),
+                        exclude: false,
+                    },
+                ],
+                max_chase_distance: 16.0,
+            })),
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let decoded = ServerMessage::decode(&msg.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ServerMsg::SetCombatPolicy(p)) => {
+                assert_eq!(p.stance(), CombatStance::Aggressive);
+                assert_eq!(p.target_filters[0].entity_type, "#minecraft:raiders");
+            }
+            _ => panic!("Decoding failed"),
+        }
+        
+        let msg = ClientMessage {
+            message: Some(ClientMsg::CombatPolicy(CombatPolicyObservation {
+                npc_id: "guard".to_string(),
+                trigger: CombatPolicyTrigger::Fled as i32,
+                target_type: "minecraft:pillager".to_string(),
+                health_norm: 0.2,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let decoded = ClientMessage::decode(&msg.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ClientMsg::CombatPolicy(o)) => assert_eq!(o.trigger(), CombatPolicyTrigger::Fled),
+            _ => panic!("Decoding failed"),
+        }
+        
+        println!("✓ SetCombatPolicyDirective and CombatPolicyObservation serialize correctly");
+    }
+    
+    #[tokio::test]
+    async fn test_region_claims_and_precondition() {
+        use npc_society::v1::{ActionErrorCode, BlockPosition, NpcSnapshot, RegionClaim};
+        
+        let corner = |x, y, z| Some(BlockPosition { world: "world".to_string(), x, y, z, ..Default::default() });
+        let npc = NpcSnapshot {
+            npc_id: "farmer".to_string(),
+            regions: vec![RegionClaim {
+                region_id: "spawn".to_string(),
+                plugin: "WorldGuard".to_string(),
+                min: corner(-50, 0, -50),
+                max: corner(50, 255, 50),
+                interact_allowed: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let decoded = NpcSnapshot::decode(&npc.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.regions.len(), 1);
+        assert!(!decoded.regions[0].build_allowed);
+        
+        let denied = ActionResult {
+            directive_id: "break-1".to_string(),
+            npc_id: "farmer".to_string(),
+            success: false,
+            error_message: "Protected by WorldGuard".to_string(),
+            error_code: ActionErrorCode::Precondition as i32,
+            denied_by_region_id: "spawn".to_string(),
+            ..Default::default()
+        };
+        let decoded = ActionResult::decode(&denied.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.error_code(), ActionErrorCode::Precondition);
+        assert_eq!(decoded.denied_by_region_id, "spawn");
+        
+        println!("✓ RegionClaim and PRECONDITION ActionResult serialize correctly");
+    }
+    
+    #[tokio::test]
+    async fn test_change_dimension() {
+        use npc_society::v1::{
+            client_message::Message as ClientMsg, ChangeDimensionObservation, ClientMessage,
+            Dimension, DimensionChangeCause, Position,
+        };
+        
+        let msg = ClientMessage {
+            message: Some(ClientMsg::ChangeDimension(ChangeDimensionObservation {
+                npc_id: "explorer".to_string(),
+                from: Some(Position {
+                    world: "world".to_string(),
+                    x: 80.0,
+                    dimension: Dimension::Overworld as i32,
+                    ..Default::default()
+                }),
+                to: Some(Position {
+                    world: "world_nether".to_string(),
+                    x: 10.0,
+                    dimension: Dimension::Nether as i32,
+                    ..Default::default()
+                }),
+                cause: DimensionChangeCause::NetherPortal as i32,
+                timestamp_ms: 1000,
+            })),
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let decoded = ClientMessage::decode(&msg.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ClientMsg::ChangeDimension(change)) => {
+                assert_eq!(change.cause(), DimensionChangeCause::NetherPortal);
+                assert_eq!(change.to.unwrap().dimension(), Dimension::Nether);
+            }
+            _ => panic!("Decoding failed"),
+        }
+        
+        println!("✓ ChangeDimensionObservation serializes correctly");
+    }
+
+    #[tokio::test]
+    async fn test_action_result_part() {
+        use npc_society::v1::{action_result::Result as ResultType, ResultPart, ScanBlocksResult};
+        
+        let msg = ClientMessage {
+            message: Some(ClientMsg::ActionResult(ActionResult {
+                directive_id: "scan-big".to_string(),
+                npc_id: "miner".to_string(),
+                success: true,
+                part: Some(ResultPart { index: 1, count: 3 }),
+                result: Some(ResultType::ScanBlocksResult(ScanBlocksResult::default())),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let decoded = ClientMessage::decode(&msg.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ClientMsg::ActionResult(result)) => {
+                assert_eq!(result.part, Some(ResultPart { index: 1, count: 3 }));
+            }
+            _ => panic!("Decoding failed"),
+        }
+        
+        println!("✓ ActionResult.part serializes correctly");
+    }
+
+    #[tokio::test]
+    async fn test_replayed_action_result() {
+        let msg = ClientMessage {
+            message: Some(ClientMsg::ActionResult(ActionResult {
+                directive_id: "dir-42".to_string(),
+                npc_id: "miner".to_string(),
+                success: true,
+                replayed: true,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let decoded = ClientMessage::decode(&msg.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ClientMsg::ActionResult(result)) => {
+                assert!(result.replayed);
+                assert_eq!(result.directive_id, "dir-42");
+            }
+            _ => panic!("Decoding failed"),
+        }
+        
+        println!("✓ ActionResult.replayed serializes correctly");
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_plan_estimate() {
+        use npc_society::v1::{
+            action_directive::Action, ActionDirective, BlockPosition, MoveAction, PlanEstimate,
+        };
+
+        let directive = ActionDirective {
+            directive_id: "plan-1".to_string(),
+            npc_id: "miner".to_string(),
+            dry_run: true,
+            action: Some(Action::Move(MoveAction::default())),
+            ..Default::default()
+        };
+        let msg = ClientMessage {
+            message: Some(ClientMsg::ActionResult(ActionResult {
+                directive_id: "plan-1".to_string(),
+                npc_id: "miner".to_string(),
+                success: true,
+                estimate: Some(PlanEstimate {
+                    path_length: 42.5,
+                    duration_ms: 9_800,
+                    affected_blocks: vec![BlockPosition {
+                        world: "world".to_string(),
+                        x: 1,
+                        y: 64,
+                        z: -3,
+                        ..Default::default()
+                    }],
+                }),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        assert!(ActionDirective::decode(&directive.encode_to_vec()[..]).unwrap().dry_run);
+        let decoded = ClientMessage::decode(&msg.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ClientMsg::ActionResult(result)) => {
+                let estimate = result.estimate.unwrap();
+                assert_eq!(estimate.path_length, 42.5);
+                assert_eq!(estimate.duration_ms, 9_800);
+                assert_eq!(estimate.affected_blocks[0].z, -3);
+                assert!(result.result.is_none());
+            }
+            _ => panic!("Decoding failed"),
+        }
+
+        println!("✓ ActionDirective.dry_run and PlanEstimate serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_line_of_sight() {
+        use npc_society::v1::{
+            action_directive::Action, action_result::Result as ActionResultType,
+            check_line_of_sight_action::Target, ActionDirective, BlockPosition,
+            CheckLineOfSightAction, CheckLineOfSightResult,
+        };
+        use npc_society_example::builders::Buildable;
+        use npc_society_example::validate::Validate;
+
+        let sight = CheckLineOfSightAction::builder()
+            .entity("p1")
+            .max_distance(32.0)
+            .build()
+            .unwrap();
+        assert!(CheckLineOfSightAction::builder().build().is_err());
+        let directive = ActionDirective {
+            directive_id: "look-1".to_string(),
+            npc_id: "guard".to_string(),
+            action: Some(Action::CheckLineOfSight(sight)),
+            ..Default::default()
+        };
+        assert!(directive.validate().is_ok());
+        let msg = ClientMessage {
+            message: Some(ClientMsg::ActionResult(ActionResult {
+                directive_id: "look-1".to_string(),
+                npc_id: "guard".to_string(),
+                success: true,
+                result: Some(ActionResultType::CheckLineOfSightResult(CheckLineOfSightResult {
+                    in_field_of_view: true,
+                    distance: 9.5,
+                    blocked_by: Some(BlockPosition {
+                        world: "world".to_string(),
+                        x: 3,
+                        y: 64,
+                        z: 1,
+                        ..Default::default()
+                    }),
+                    blocking_block_type: "minecraft:stone_bricks".to_string(),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        let decoded = ActionDirective::decode(&directive.encode_to_vec()[..]).unwrap();
+        match decoded.action {
+            Some(Action::CheckLineOfSight(s)) => {
+                assert_eq!(s.target, Some(Target::EntityUuid("p1".to_string())))
+            }
+            _ => panic!("Decoding failed"),
+        }
+        match ClientMessage::decode(&msg.encode_to_vec()[..]).unwrap().message {
+            Some(ClientMsg::ActionResult(ActionResult {
+                result: Some(ActionResultType::CheckLineOfSightResult(s)),
+                ..
+            })) => {
+                assert!(!s.visible && s.in_field_of_view);
+                assert_eq!(s.blocked_by.unwrap().x, 3);
+            }
+            _ => panic!("Decoding failed"),
+        }
+
+        println!("✓ CheckLineOfSightAction and CheckLineOfSightResult serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_aimed_raycast() {
+        use npc_society::v1::{
+            action_directive::Action, action_result::Result as ActionResultType,
+            raycast_look_action::Aim, ActionDirective, Position, RaycastLookAction,
+            RaycastLookResult,
+        };
+        use npc_society_example::builders::Buildable;
+        use npc_society_example::validate::Validate;
+
+        let mountain = Position {
+            world: "world".to_string(),
+            x: 200.0,
+            y: 120.0,
+            z: -40.0,
+            ..Default::default()
+        };
+        let ray = RaycastLookAction::builder()
+            .max_distance(64.0)
+            .target(mountain.clone())
+            .include_entities(true)
+            .build()
+            .unwrap();
+        assert!(RaycastLookAction::builder().target_entity("").build().is_err());
+        let directive = ActionDirective {
+            directive_id: "ray-1".to_string(),
+            npc_id: "guide".to_string(),
+            action: Some(Action::RaycastLook(ray)),
+            ..Default::default()
+        };
+        assert!(directive.validate().is_ok());
+        let msg = ClientMessage {
+            message: Some(ClientMsg::ActionResult(ActionResult {
+                directive_id: "ray-1".to_string(),
+                npc_id: "guide".to_string(),
+                success: true,
+                result: Some(ActionResultType::RaycastLookResult(RaycastLookResult {
+                    hit: true,
+                    distance: 12.0,
+                    hit_point: Some(mountain.clone()),
+                    hit_entity_uuid: "cow-1".to_string(),
+                    hit_entity_type: "minecraft:cow".to_string(),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        match ActionDirective::decode(&directive.encode_to_vec()[..]).unwrap().action {
+            Some(Action::RaycastLook(r)) => {
+                assert_eq!(r.aim, Some(Aim::Target(mountain)));
+                assert!(r.include_entities && r.origin.is_none());
+            }
+            _ => panic!("Decoding failed"),
+        }
+        match ClientMessage::decode(&msg.encode_to_vec()[..]).unwrap().message {
+            Some(ClientMsg::ActionResult(ActionResult {
+                result: Some(ActionResultType::RaycastLookResult(r)),
+                ..
+            })) => {
+                assert_eq!(r.hit_entity_type, "minecraft:cow");
+                assert!(r.hit_position.is_none());
+            }
+            _ => panic!("Decoding failed"),
+        }
+
+        println!("✓ Aimed RaycastLookAction serializes correctly");
+    }
+
+    #[tokio::test]
+    async fn test_give_item() {
+        use npc_society::v1::{
+            action_directive::Action, action_result::Result as ActionResultType, ActionDirective,
+            GiveItemAction, GiveItemResult,
+        };
+        use npc_society_example::builders::Buildable;
+        use npc_society_example::types::PlayerUuid;
+        use npc_society_example::validate::Validate;
+
+        let player = PlayerUuid::new("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap();
+        let gift = GiveItemAction::builder()
+            .player_uuid(&player)
+            .item_type("minecraft:emerald")
+            .count(5)
+            .build()
+            .unwrap();
+        assert!(GiveItemAction::builder()
+            .player_uuid(&player)
+            .item_type("minecraft:emerald")
+            .build()
+            .is_err());
+        let directive = ActionDirective {
+            directive_id: "gift-1".to_string(),
+            npc_id: "miner".to_string(),
+            action: Some(Action::GiveItem(gift)),
+            ..Default::default()
+        };
+        assert!(directive.validate().is_ok());
+        let msg = ClientMessage {
+            message: Some(ClientMsg::ActionResult(ActionResult {
+                directive_id: "gift-1".to_string(),
+                npc_id: "miner".to_string(),
+                success: true,
+                result: Some(ActionResultType::GiveItemResult(GiveItemResult {
+                    player_uuid: player.to_string(),
+                    item_type: "minecraft:emerald".to_string(),
+                    accepted: 3,
+                    dropped: 2,
+                })),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        let decoded = ActionDirective::decode(&directive.encode_to_vec()[..]).unwrap();
+        match decoded.action {
+            Some(Action::GiveItem(g)) => {
+                assert_eq!(g.count, 5);
+                assert!(!g.from_inventory);
+            }
+            _ => panic!("Decoding failed"),
+        }
+        match ClientMessage::decode(&msg.encode_to_vec()[..]).unwrap().message {
+            Some(ClientMsg::ActionResult(ActionResult {
+                result: Some(ActionResultType::GiveItemResult(g)),
+                ..
+            })) => assert_eq!((g.accepted, g.dropped), (3, 2)),
+            _ => panic!("Decoding failed"),
+        }
+
+        println!("✓ GiveItemAction and GiveItemResult serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_written_items() {
+        use npc_society::v1::{
+            action_directive::Action, action_result::Result as ActionResultType, ActionDirective,
+            BlockPosition, MapMarkerType, MarkMapAction, MarkMapResult, WriteBookAction,
+            WriteSignAction,
+        };
+        use npc_society_example::builders::Buildable;
+        use npc_society_example::validate::Validate;
+
+        let at = |x, y, z| BlockPosition {
+            world: "world".to_string(),
+            x,
+            y,
+            z,
+            ..Default::default()
+        };
+        let sign = WriteSignAction::builder()
+            .position(at(10, 64, -3))
+            .lines(["Smithy", "Open dawn", "to dusk"])
+            .wax(true)
+            .sign_type("minecraft:oak_sign")
+            .build()
+            .unwrap();
+        assert!(WriteSignAction::builder()
+            .position(at(10, 64, -3))
+            .lines(["1", "2", "3", "4", "5"])
+            .build()
+            .is_err());
+        assert!(WriteBookAction::builder().title("Empty").build().is_err());
+        assert!(WriteBookAction::builder()
+            .title("A".repeat(33))
+            .pages(["too long a title"])
+            .build()
+            .is_err());
+        let map = MarkMapAction::builder()
+            .center(at(0, 64, 0))
+            .scale(2)
+            .marker(at(120, 40, -80), MapMarkerType::RedX, "")
+            .marker(at(-30, 70, 15), MapMarkerType::Banner, "Village")
+            .build()
+            .unwrap();
+        assert!(MarkMapAction::builder().center(at(0, 64, 0)).build().is_err());
+        assert!(MarkMapAction::builder()
+            .center(at(0, 64, 0))
+            .scale(5)
+            .marker(at(1, 64, 1), MapMarkerType::RedX, "")
+            .build()
+            .is_err());
+
+        let directive = |action: Action| ActionDirective {
+            directive_id: "write-1".to_string(),
+            npc_id: "storyteller".to_string(),
+            action: Some(action),
+            ..Default::default()
+        };
+        assert!(directive(Action::WriteSign(sign.clone())).validate().is_ok());
+        assert!(directive(Action::MarkMap(map.clone())).validate().is_ok());
+        let unsigned_book = Action::WriteBook(WriteBookAction {
+            title: "Lore".to_string(),
+            ..Default::default()
+        });
+        assert!(directive(unsigned_book).validate().is_err());
+
+        use prost::Message;
+        let decoded = ActionDirective::decode(&directive(Action::MarkMap(map)).encode_to_vec()[..])
+            .unwrap();
+        match decoded.action {
+            Some(Action::MarkMap(m)) => {
+                assert_eq!(m.markers.len(), 2);
+                assert_eq!(m.markers[1].r#type(), MapMarkerType::Banner);
+                assert_eq!(m.markers[1].label, "Village");
+            }
+            _ => panic!("Decoding failed"),
+        }
+        let result = ActionResult {
+            directive_id: "write-1".to_string(),
+            npc_id: "storyteller".to_string(),
+            success: true,
+            result: Some(ActionResultType::MarkMapResult(MarkMapResult {
+                map_id: 42,
+                markers: 1,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        match ActionResult::decode(&result.encode_to_vec()[..]).unwrap().result {
+            Some(ActionResultType::MarkMapResult(m)) => assert_eq!((m.map_id, m.markers), (42, 1)),
+            _ => panic!("Decoding failed"),
+        }
+
+        println!("✓ Sign, book and map actions serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_mechanisms() {
+        use npc_society::v1::{
+            action_directive::Action, action_result::Result as ActionResultType,
+            set_mechanism_action::State, ActionDirective, BlockPosition, ComparatorMode,
+            ReadRedstonePowerResult, SetMechanismAction, ToggleLeverAction,
+        };
+        use npc_society_example::builders::Buildable;
+        use npc_society_example::validate::Validate;
+
+        let lever_at = BlockPosition {
+            world: "world".to_string(),
+            x: 4,
+            y: 65,
+            z: 12,
+            ..Default::default()
+        };
+        let lever = ToggleLeverAction::builder()
+            .position(lever_at.clone())
+            .powered(true)
+            .build()
+            .unwrap();
+        assert!(ToggleLeverAction::builder().build().is_err());
+        let comparator = SetMechanismAction::builder()
+            .position(lever_at.clone())
+            .comparator_mode(ComparatorMode::Subtract)
+            .build()
+            .unwrap();
+        assert!(SetMechanismAction::builder()
+            .position(lever_at.clone())
+            .build()
+            .is_err());
+        assert!(SetMechanismAction::builder()
+            .position(lever_at.clone())
+            .repeater_delay(5)
+            .build()
+            .is_err());
+
+        let directive = |action: Action| ActionDirective {
+            directive_id: "redstone-1".to_string(),
+            npc_id: "engineer".to_string(),
+            action: Some(action),
+            ..Default::default()
+        };
+        assert!(directive(Action::ToggleLever(lever.clone())).validate().is_ok());
+        assert!(directive(Action::SetMechanism(comparator)).validate().is_ok());
+        let out_of_tune = Action::SetMechanism(SetMechanismAction {
+            position: Some(lever_at.clone()),
+            state: Some(State::Note(25)),
+        });
+        assert!(directive(out_of_tune).validate().is_err());
+
+        use prost::Message;
+        let decoded =
+            ActionDirective::decode(&directive(Action::ToggleLever(lever)).encode_to_vec()[..])
+                .unwrap();
+        match decoded.action {
+            // An unset `powered` would flip the lever instead
+            Some(Action::ToggleLever(t)) => assert_eq!(t.powered, Some(true)),
+            _ => panic!("Decoding failed"),
+        }
+        let result = ActionResult {
+            directive_id: "redstone-1".to_string(),
+            npc_id: "engineer".to_string(),
+            success: true,
+            result: Some(ActionResultType::ReadRedstonePowerResult(ReadRedstonePowerResult {
+                position: Some(lever_at),
+                block_type: "minecraft:comparator".to_string(),
+                power: 15,
+                output: 7,
+            })),
+            ..Default::default()
+        };
+        match ActionResult::decode(&result.encode_to_vec()[..]).unwrap().result {
+            Some(ActionResultType::ReadRedstonePowerResult(r)) => {
+                assert_eq!((r.power, r.output), (15, 7))
+            }
+            _ => panic!("Decoding failed"),
+        }
+
+        println!("✓ Redstone mechanism actions serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_transport_items() {
+        use npc_society::v1::{
+            server_message::Message as ServerMsg, BlockPosition, ChoreographyStepResult, Position,
+            ServerMessage, TransportItemsDirective, TransportItemsResult, TransportProgress,
+            TransportRoute, TransportStage, TransportVehicle,
+        };
+        use npc_society_example::builders::Buildable;
+        use npc_society_example::types::{DirectiveId, NpcId};
+        use npc_society_example::validate::Validate;
+
+        let chest = |x| BlockPosition {
+            world: "world".to_string(),
+            x,
+            y: 64,
+            z: 0,
+            ..Default::default()
+        };
+        let id = DirectiveId::new("haul-1").unwrap();
+        let npc = NpcId::new("miner_01").unwrap();
+        let route = TransportRoute {
+            vehicle: TransportVehicle::Minecart as i32,
+            waypoints: vec![Position {
+                world: "world".to_string(),
+                x: 40.5,
+                y: 64.0,
+                z: 0.5,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let haul = TransportItemsDirective::builder()
+            .directive_id(&id)
+            .npc_id(&npc)
+            .from_container(chest(0))
+            .to_container(chest(80))
+            .item_types(["minecraft:diamond"])
+            .max_trips(3)
+            .route_hint(route)
+            .build()
+            .unwrap();
+        assert!(haul.validate().is_ok());
+        assert!(TransportItemsDirective::builder()
+            .directive_id(&id)
+            .npc_id(&npc)
+            .from_container(chest(0))
+            .to_container(chest(0))
+            .build()
+            .is_err());
+
+        let progress = TransportProgress {
+            directive_id: "haul-1".to_string(),
+            npc_id: "miner_01".to_string(),
+            stage: TransportStage::Travel as i32,
+            trip: 0,
+            ..Default::default()
+        };
+        assert!(progress.validate().is_err());
+
+        use prost::Message;
+        let msg = ServerMessage {
+            message: Some(ServerMsg::TransportItems(haul)),
+            ..Default::default()
+        };
+        match ServerMessage::decode(&msg.encode_to_vec()[..]).unwrap().message {
+            Some(ServerMsg::TransportItems(t)) => {
+                let route = t.route_hint.unwrap();
+                assert_eq!(route.vehicle(), TransportVehicle::Minecart);
+                assert_eq!(route.waypoints.len(), 1);
+                assert_eq!(t.item_types, vec!["minecraft:diamond".to_string()]);
+            }
+            _ => panic!("Decoding failed"),
+        }
+
+        let leg = |success| ChoreographyStepResult {
+            npc_id: "miner_01".to_string(),
+            success,
+            ..Default::default()
+        };
+        let result = TransportItemsResult {
+            directive_id: "haul-1".to_string(),
+            npc_id: "miner_01".to_string(),
+            trips: 1,
+            legs: vec![leg(true), leg(true), leg(false)],
+            ..Default::default()
+        };
+        let decoded = TransportItemsResult::decode(&result.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.legs.len(), 3);
+        assert!(!decoded.legs[2].success);
+
+        println!("✓ Transport directives serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_move_result_path_cost() {
+        use npc_society::v1::{
+            action_result::Result as ResultType, BlockPosition, MoveProgress, MoveResult,
+            NpcSnapshot,
+        };
+
+        let step = |x| BlockPosition {
+            world: "world".to_string(),
+            x,
+            y: 64,
+            z: 0,
+            ..Default::default()
+        };
+        let result = ActionResult {
+            directive_id: "move-1".to_string(),
+            success: true,
+            result: Some(ResultType::MoveResult(MoveResult {
+                reached_destination: false,
+                path_length: 2.0,
+                nodes_expanded: 512,
+                partial_path: vec![step(0), step(1), step(2)],
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let npc = NpcSnapshot {
+            npc_id: "miner".to_string(),
+            move_progress: Some(MoveProgress {
+                directive_id: "move-1".to_string(),
+                path_length: 12.0,
+                remaining_length: 4.5,
+                nodes_expanded: 40,
+                eta_ms: 1_050,
+            }),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        match ActionResult::decode(&result.encode_to_vec()[..]).unwrap().result {
+            Some(ResultType::MoveResult(r)) => {
+                assert!(!r.reached_destination);
+                assert_eq!(r.nodes_expanded, 512);
+                assert_eq!(r.partial_path.last(), Some(&step(2)));
+            }
+            _ => panic!("Decoding failed"),
+        }
+        let decoded = NpcSnapshot::decode(&npc.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.move_progress.unwrap().eta_ms, 1_050);
+
+        println!("✓ MoveResult path cost and MoveProgress serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_door_policy_and_locked_door() {
+        use npc_society::v1::{
+            action_result::Result as ResultType, ActionErrorCode, BlockPosition, DoorPolicy,
+            MoveAction, MoveResult,
+        };
+
+        let action = MoveAction {
+            doors: DoorPolicy::CloseBehind as i32,
+            fence_gates: DoorPolicy::Avoid as i32,
+            ..Default::default()
+        };
+        let door = BlockPosition {
+            world: "world".to_string(),
+            x: 4,
+            y: 65,
+            z: 9,
+            ..Default::default()
+        };
+        let result = ActionResult {
+            directive_id: "move-2".to_string(),
+            success: false,
+            error_code: ActionErrorCode::DoorLocked as i32,
+            result: Some(ResultType::MoveResult(MoveResult {
+                blocked_by: Some(door.clone()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        let decoded = MoveAction::decode(&action.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.doors(), DoorPolicy::CloseBehind);
+        assert_eq!(decoded.fence_gates(), DoorPolicy::Avoid);
+        assert_eq!(decoded.trapdoors(), DoorPolicy::Unspecified);
+        let decoded = ActionResult::decode(&result.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.error_code(), ActionErrorCode::DoorLocked);
+        match decoded.result {
+            Some(ResultType::MoveResult(r)) => assert_eq!(r.blocked_by, Some(door)),
+            _ => panic!("Decoding failed"),
+        }
+
+        println!("✓ DoorPolicy and ACTION_ERROR_CODE_DOOR_LOCKED serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_region_snapshot_result() {
+        use npc_society::v1::{
+            action_result::Result as ResultType, BlockPosition, RegionSnapshotResult,
+        };
+        
+        // 2x1x2: three stone, one air
+        let msg = ClientMessage {
+            message: Some(ClientMsg::ActionResult(ActionResult {
+                directive_id: "region-1".to_string(),
+                npc_id: "builder".to_string(),
+                success: true,
+                result: Some(ResultType::RegionSnapshotResult(RegionSnapshotResult {
+                    min: Some(BlockPosition {
+                        world: "world".to_string(),
+                        x: 10,
+                        y: 64,
+                        z: 10,
+                        ..Default::default()
+                    }),
+                    size_x: 2,
+                    size_y: 1,
+                    size_z: 2,
+                    palette: vec!["minecraft:stone".to_string(), "minecraft:air".to_string()],
+                    run_palette_indices: vec![0, 1],
+                    run_lengths: vec![3, 1],
+                    ..Default::default()
+                })),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        
+        use prost::Message;
+        let decoded = ClientMessage::decode(&msg.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ClientMsg::ActionResult(ActionResult {
+                result: Some(ResultType::RegionSnapshotResult(region)),
+                ..
+            })) => {
+                assert_eq!(region.palette.len(), 2);
+                assert_eq!(region.run_lengths.iter().sum::<u32>(), 4);
+            }
+            _ => panic!("Decoding failed"),
+        }
+        
+        println!("✓ RegionSnapshotResult serializes correctly");
+    }
+
+    #[tokio::test]
+    async fn test_block_properties() {
+        use npc_society::v1::{
+            action_result::Result as ResultType, BlockMatch, BlockProperties, ScanBlocksResult,
+        };
+
+        let result = ActionResult {
+            directive_id: "scan-2".to_string(),
+            npc_id: "miner".to_string(),
+            success: true,
+            result: Some(ResultType::ScanBlocksResult(ScanBlocksResult {
+                matches: vec![BlockMatch {
+                    block_type: "minecraft:water".to_string(),
+                    properties: Some(BlockProperties {
+                        passable: true,
+                        liquid: true,
+                        light_level: 12,
+                        hardness: 100.0,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+            })),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        let decoded = ActionResult::decode(&result.encode_to_vec()[..]).unwrap();
+        match decoded.result {
+            Some(ResultType::ScanBlocksResult(scan)) => {
+                let properties = scan.matches[0].properties.as_ref().unwrap();
+                assert!(properties.passable && properties.liquid && !properties.solid);
+                assert_eq!(properties.light_level, 12);
+            }
+            _ => panic!("Expected ScanBlocksResult"),
+        }
+
+        println!("✓ BlockMatch.properties serializes correctly");
+    }
+
+    #[tokio::test]
+    async fn test_scan_block_patterns() {
+        use npc_society::v1::{
+            action_directive::Action, action_result::Result as ResultType,
+            server_message::Message as ServerMsg, ActionDirective, BlockMatch, ScanBlocksAction,
+            ScanBlocksResult, ServerMessage,
+        };
+
+        let scan = ServerMessage {
+            message: Some(ServerMsg::ActionDirective(ActionDirective {
+                directive_id: "scan-3".to_string(),
+                npc_id: "lumberjack".to_string(),
+                action: Some(Action::ScanBlocks(ScanBlocksAction {
+                    radius: 16,
+                    block_types: vec!["#minecraft:logs".to_string(), "minecraft:*_ore".to_string()],
+                    max_results: 10,
+                    ..Default::default()
+                })),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let result = ActionResult {
+            directive_id: "scan-3".to_string(),
+            success: true,
+            result: Some(ResultType::ScanBlocksResult(ScanBlocksResult {
+                matches: vec![BlockMatch {
+                    block_type: "minecraft:birch_log".to_string(),
+                    matched_by: "#minecraft:logs".to_string(),
+                    ..Default::default()
+                }],
+            })),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        let decoded = ServerMessage::decode(&scan.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ServerMsg::ActionDirective(ActionDirective {
+                action: Some(Action::ScanBlocks(scan)),
+                ..
+            })) => assert_eq!(scan.block_types[0], "#minecraft:logs"),
+            _ => panic!("Decoding failed"),
+        }
+        let decoded = ActionResult::decode(&result.encode_to_vec()[..]).unwrap();
+        match decoded.result {
+            Some(ResultType::ScanBlocksResult(scan)) => {
+                assert_eq!(scan.matches[0].block_type, "minecraft:birch_log");
+                assert_eq!(scan.matches[0].matched_by, "#minecraft:logs");
+            }
+            _ => panic!("Expected ScanBlocksResult"),
+        }
+
+        println!("✓ ScanBlocks tags and patterns serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_block_watch() {
+        use npc_society::v1::{
+            action_directive::Action, ActionDirective, BlockMatch, BlockPosition,
+            BlockWatchUpdate, UnwatchBlocksAction, WatchBlocksAction,
+        };
+
+        let corner = |x| BlockPosition {
+            world: "world".to_string(),
+            x,
+            y: 12,
+            z: x,
+            ..Default::default()
+        };
+        let watch = ActionDirective {
+            directive_id: "watch-1".to_string(),
+            npc_id: "miner".to_string(),
+            action: Some(Action::WatchBlocks(WatchBlocksAction {
+                from: Some(corner(0)),
+                to: Some(corner(32)),
+                block_types: vec!["#minecraft:diamond_ores".to_string()],
+                interval_ticks: 20,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let update = ClientMessage {
+            message: Some(ClientMsg::BlockWatchUpdate(BlockWatchUpdate {
+                directive_id: "watch-1".to_string(),
+                npc_id: "miner".to_string(),
+                server_tick: 2400,
+                found: vec![BlockMatch {
+                    position: Some(corner(7)),
+                    block_type: "minecraft:deepslate_diamond_ore".to_string(),
+                    matched_by: "#minecraft:diamond_ores".to_string(),
+                    ..Default::default()
+                }],
+                removed: vec![corner(3)],
+                full: false,
+            })),
+            ..Default::default()
+        };
+        let unwatch = Action::UnwatchBlocks(UnwatchBlocksAction {
+            watch_id: "watch-1".to_string(),
+        });
+
+        use prost::Message;
+        let decoded = ActionDirective::decode(&watch.encode_to_vec()[..]).unwrap();
+        match decoded.action {
+            Some(Action::WatchBlocks(w)) => {
+                assert_eq!(w.interval_ticks, 20);
+                assert_eq!(w.to, Some(corner(32)));
+            }
+            _ => panic!("Expected WatchBlocksAction"),
+        }
+        let decoded = ClientMessage::decode(&update.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ClientMsg::BlockWatchUpdate(u)) => {
+                assert_eq!(u.directive_id, "watch-1");
+                assert_eq!(u.found.len(), 1);
+                assert_eq!(u.removed, vec![corner(3)]);
+                assert!(!u.full);
+            }
+            _ => panic!("Decoding failed"),
+        }
+        let directive = ActionDirective {
+            action: Some(unwatch),
+            ..Default::default()
+        };
+        let decoded = ActionDirective::decode(&directive.encode_to_vec()[..]).unwrap();
+        assert!(matches!(
+            decoded.action,
+            Some(Action::UnwatchBlocks(UnwatchBlocksAction { ref watch_id })) if watch_id == "watch-1"
+        ));
+
+        println!("✓ WatchBlocksAction and BlockWatchUpdate serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_smelting() {
+        use npc_society::v1::{
+            action_directive::Action, action_result::Result as ResultType, ActionDirective,
+            BlockPosition, ItemStack, SmeltAction, SmeltResult, StationOutputObservation,
+            StationStopReason, StationType,
+        };
+
+        let furnace = BlockPosition {
+            world: "world".to_string(),
+            x: 4,
+            y: 64,
+            z: 9,
+            ..Default::default()
+        };
+        let smelt = ActionDirective {
+            directive_id: "smelt-1".to_string(),
+            npc_id: "smith".to_string(),
+            action: Some(Action::Smelt(SmeltAction {
+                furnace_position: Some(furnace.clone()),
+                input_item: "minecraft:raw_iron".to_string(),
+                fuel_item: "minecraft:coal".to_string(),
+                count: 16,
+            })),
+            ..Default::default()
+        };
+        let loaded = ActionResult {
+            directive_id: "smelt-1".to_string(),
+            npc_id: "smith".to_string(),
+            success: true,
+            result: Some(ResultType::SmeltResult(SmeltResult {
+                items_loaded: 16,
+                fuel_loaded: 2,
+                ready_at_tick: 27_200,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let done = ClientMessage {
+            message: Some(ClientMsg::StationOutput(StationOutputObservation {
+                npc_id: "smith".to_string(),
+                directive_id: "smelt-1".to_string(),
+                station_position: Some(furnace),
+                station_type: StationType::Furnace as i32,
+                reason: StationStopReason::Ready as i32,
+                output: vec![ItemStack {
+                    item_type: "minecraft:iron_ingot".to_string(),
+                    quantity: 16,
+                    ..Default::default()
+                }],
+                server_tick: 27_210,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        let decoded = ActionDirective::decode(&smelt.encode_to_vec()[..]).unwrap();
+        assert!(matches!(decoded.action, Some(Action::Smelt(s)) if s.count == 16));
+        let decoded = ActionResult::decode(&loaded.encode_to_vec()[..]).unwrap();
+        match decoded.result {
+            Some(ResultType::SmeltResult(r)) => assert_eq!(r.ready_at_tick, 27_200),
+            _ => panic!("Expected SmeltResult"),
+        }
+        let decoded = ClientMessage::decode(&done.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ClientMsg::StationOutput(output)) => {
+                assert_eq!(output.reason(), StationStopReason::Ready);
+                assert_eq!(output.station_type(), StationType::Furnace);
+                assert_eq!(output.output[0].quantity, 16);
+            }
+            _ => panic!("Decoding failed"),
+        }
+
+        println!("✓ SmeltAction and StationOutputObservation serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_animal_husbandry() {
+        use npc_society::v1::{
+            action_directive::Action, action_result::Result as ResultType, ActionDirective,
+            AnimalState, BreedAnimalsAction, BreedAnimalsResult, EntitySnapshot, WorldTick,
+        };
+
+        let tick = ClientMessage {
+            message: Some(ClientMsg::WorldTick(WorldTick {
+                nearby_entities: vec![EntitySnapshot {
+                    entity_uuid: "cow-1".to_string(),
+                    entity_type: "minecraft:cow".to_string(),
+                    animal: Some(AnimalState {
+                        breed_cooldown_ticks: 1200,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let breed = ActionDirective {
+            directive_id: "breed-1".to_string(),
+            npc_id: "rancher".to_string(),
+            action: Some(Action::BreedAnimals(BreedAnimalsAction {
+                first_uuid: "cow-1".to_string(),
+                second_uuid: "cow-2".to_string(),
+                food_item: "minecraft:wheat".to_string(),
+            })),
+            ..Default::default()
+        };
+        let bred = ActionResult {
+            directive_id: "breed-1".to_string(),
+            success: true,
+            result: Some(ResultType::BreedAnimalsResult(BreedAnimalsResult {
+                baby_uuid: "calf-1".to_string(),
+                food_used: 2,
+            })),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        let decoded = ClientMessage::decode(&tick.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ClientMsg::WorldTick(tick)) => {
+                let animal = tick.nearby_entities[0].animal.as_ref().unwrap();
+                assert_eq!(animal.breed_cooldown_ticks, 1200);
+                assert!(!animal.baby);
+            }
+            _ => panic!("Decoding failed"),
+        }
+        let decoded = ActionDirective::decode(&breed.encode_to_vec()[..]).unwrap();
+        assert!(matches!(decoded.action, Some(Action::BreedAnimals(b)) if b.second_uuid == "cow-2"));
+        let decoded = ActionResult::decode(&bred.encode_to_vec()[..]).unwrap();
+        match decoded.result {
+            Some(ResultType::BreedAnimalsResult(r)) => assert_eq!(r.baby_uuid, "calf-1"),
+            _ => panic!("Expected BreedAnimalsResult"),
+        }
+
+        println!("✓ Animal husbandry messages serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_ride_and_drive() {
+        use npc_society::v1::{
+            action_directive::Action, action_result::Result as ResultType, ActionDirective,
+            ActionErrorCode, Position, RideAndDriveAction, RideAndDriveResult,
+        };
+
+        let target = Position {
+            world: "world".to_string(),
+            x: 400.0,
+            y: 63.0,
+            z: -80.0,
+            ..Default::default()
+        };
+        let ride = ActionDirective {
+            directive_id: "ride-1".to_string(),
+            npc_id: "courier".to_string(),
+            action: Some(Action::RideAndDrive(RideAndDriveAction {
+                target: Some(target.clone()),
+                speed: 0.8,
+                dismount: true,
+            })),
+            ..Default::default()
+        };
+        let arrived = ActionResult {
+            directive_id: "ride-1".to_string(),
+            success: true,
+            result: Some(ResultType::RideAndDriveResult(RideAndDriveResult {
+                final_position: Some(target),
+                reached_destination: true,
+                mounted: false,
+            })),
+            ..Default::default()
+        };
+        let on_foot = ActionResult {
+            directive_id: "ride-2".to_string(),
+            success: false,
+            error_code: ActionErrorCode::NotMounted as i32,
+            ..Default::default()
+        };
+
+        use prost::Message;
+        let decoded = ActionDirective::decode(&ride.encode_to_vec()[..]).unwrap();
+        assert!(matches!(decoded.action, Some(Action::RideAndDrive(r)) if r.dismount));
+        let decoded = ActionResult::decode(&arrived.encode_to_vec()[..]).unwrap();
+        match decoded.result {
+            Some(ResultType::RideAndDriveResult(r)) => {
+                assert!(r.reached_destination && !r.mounted)
+            }
+            _ => panic!("Expected RideAndDriveResult"),
+        }
+        let decoded = ActionResult::decode(&on_foot.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.error_code(), ActionErrorCode::NotMounted);
+
+        println!("✓ RideAndDriveAction serializes correctly");
+    }
+
+    #[tokio::test]
+    async fn test_typed_events() {
+        use npc_society::v1::{
+            event_observation::Payload, BlockPosition, DeathEvent, EventObservation, EventType,
+            ExplosionEvent, RaidEvent, RaidEventType,
+        };
+
+        let explosion = EventObservation {
+            npc_id: "guard".to_string(),
+            event_type: EventType::Explosion as i32,
+            payload: Some(Payload::Explosion(ExplosionEvent {
+                source_type: "minecraft:creeper".to_string(),
+                power: 3.0,
+                destroyed_blocks: vec![BlockPosition { x: 10, y: 64, z: -3, ..Default::default() }],
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let death = EventObservation {
+            npc_id: "guard".to_string(),
+            event_type: EventType::Death as i32,
+            payload: Some(Payload::Death(DeathEvent {
+                entity_type: "minecraft:player".to_string(),
+                cause: "lava".to_string(),
+                death_message: "Steve tried to swim in lava".to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let raid = EventObservation {
+            npc_id: "guard".to_string(),
+            event_type: EventType::Raid as i32,
+            payload: Some(Payload::Raid(RaidEvent {
+                event_type: RaidEventType::Wave as i32,
+                wave: 2,
+                total_waves: 5,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        let decoded = EventObservation::decode(&explosion.encode_to_vec()[..]).unwrap();
+        match decoded.payload {
+            Some(Payload::Explosion(e)) => assert_eq!(e.destroyed_blocks.len(), 1),
+            _ => panic!("Expected ExplosionEvent"),
+        }
+        let decoded = EventObservation::decode(&death.encode_to_vec()[..]).unwrap();
+        assert!(matches!(decoded.payload, Some(Payload::Death(d)) if d.cause == "lava"));
+        let decoded = EventObservation::decode(&raid.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.event_type(), EventType::Raid);
+        assert!(matches!(decoded.payload, Some(Payload::Raid(r)) if r.event_type() == RaidEventType::Wave));
+
+        println!("✓ Typed event payloads serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_events() {
+        use npc_society::v1::{
+            server_message::Message as ServerMsg, EventType, ServerMessage, SubscribeEvents,
+        };
+
+        let msg = ServerMessage {
+            message: Some(ServerMsg::SubscribeEvents(SubscribeEvents {
+                event_types: vec![EventType::Block as i32, EventType::Combat as i32],
+                npc_ids: vec!["miner_01".to_string()],
+            })),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        let decoded = ServerMessage::decode(&msg.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ServerMsg::SubscribeEvents(s)) => {
+                assert_eq!(s.event_types().collect::<Vec<_>>(), [EventType::Block, EventType::Combat]);
+                assert_eq!(s.npc_ids, ["miner_01"]);
+            }
+            _ => panic!("Expected SubscribeEvents"),
+        }
+
+        println!("✓ SubscribeEvents serializes correctly");
+    }
+
+    #[tokio::test]
+    async fn test_npc_state_backup() {
+        use npc_society::v1::{
+            server_message::Message as ServerMsg, ExportNpcStateResponse, ItemStack, NpcSnapshot,
+            NpcStateSnapshot, Relationship, RestoreNpcState, ServerMessage,
+        };
+
+        let backup = ExportNpcStateResponse {
+            npcs: vec![NpcStateSnapshot {
+                npc_id: "miner_01".to_string(),
+                server_id: "survival".to_string(),
+                exported_at_ms: 1_700_000_000_000,
+                npc: Some(NpcSnapshot {
+                    npc_id: "miner_01".to_string(),
+                    xp_level: 12,
+                    ..Default::default()
+                }),
+                relationships: vec![Relationship {
+                    player_uuid: "069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string(),
+                    score: -30.0,
+                    updated_at_ms: 1_699_999_000_000,
+                }],
+                memory: [("last_seen_ore".to_string(), "diamond".to_string())].into(),
+                ..Default::default()
+            }],
+        };
+        let restore = ServerMessage {
+            message: Some(ServerMsg::RestoreNpcState(RestoreNpcState {
+                npc_id: "miner_01".to_string(),
+                inventory: vec![ItemStack {
+                    item_type: "minecraft:torch".to_string(),
+                    quantity: 32,
+                    ..Default::default()
+                }],
+                replace_inventory: true,
+                xp_level: Some(12),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        let decoded = ExportNpcStateResponse::decode(&backup.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded, backup);
+        assert_eq!(decoded.npcs[0].memory["last_seen_ore"], "diamond");
+        let decoded = ServerMessage::decode(&restore.encode_to_vec()[..]).unwrap();
+        match decoded.message {
+            Some(ServerMsg::RestoreNpcState(r)) => {
+                assert!(r.replace_inventory);
+                assert_eq!(r.xp_level, Some(12));
+                assert!(r.equipment.is_none());
+            }
+            _ => panic!("Expected RestoreNpcState"),
+        }
+
+        println!("✓ NpcStateSnapshot and RestoreNpcState serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_list_servers() {
+        use npc_society::v1::{
+            GetNpcStateRequest, GetSessionInfoRequest, GetSessionInfoResponse, Hello,
+            ListServersResponse,
+        };
+
+        let session = |server_id: &str, connected| GetSessionInfoResponse {
+            connected,
+            hello: Some(Hello {
+                server_id: server_id.to_string(),
+                ..Default::default()
+            }),
+            messages_received: 120,
+            ..Default::default()
+        };
+        let servers = ListServersResponse {
+            servers: vec![session("creative", false), session("survival", true)],
+        };
+        let npc = GetNpcStateRequest {
+            npc_id: "miner_01".to_string(),
+            server_id: "survival".to_string(),
+        };
+
+        use prost::Message;
+        let decoded = ListServersResponse::decode(&servers.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded, servers);
+        assert_eq!(decoded.servers[1].hello.as_ref().unwrap().server_id, "survival");
+        assert_eq!(GetNpcStateRequest::decode(&npc.encode_to_vec()[..]).unwrap(), npc);
+        // Older admin tools send no server_id
+        let legacy = GetSessionInfoRequest::decode(&[][..]).unwrap();
+        assert!(legacy.server_id.is_empty());
+
+        println!("✓ ListServersResponse and server_id requests serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_npc_transfer() {
+        use npc_society::v1::{
+            server_message::Message as ServerMsg, FinishNpcTransfer, NpcStateSnapshot,
+            NpcTransferStage, NpcTransferUpdate, Position, PrepareNpcTransfer, ServerMessage,
+            SpawnTransferredNpc, TransferNpcResponse,
+        };
+
+        let prepare = ServerMessage {
+            message: Some(ServerMsg::PrepareNpcTransfer(PrepareNpcTransfer {
+                transfer_id: "transfer-1".to_string(),
+                npc_id: "miner_01".to_string(),
+                target_server_id: "survival".to_string(),
+            })),
+            ..Default::default()
+        };
+        let spawn = ServerMessage {
+            message: Some(ServerMsg::SpawnTransferredNpc(SpawnTransferredNpc {
+                transfer_id: "transfer-1".to_string(),
+                source_server_id: "lobby".to_string(),
+                snapshot: Some(Box::new(NpcStateSnapshot {
+                    npc_id: "miner_01".to_string(),
+                    server_id: "lobby".to_string(),
+                    ..Default::default()
+                })),
+                spawn_position: Some(Position {
+                    world: "world".to_string(),
+                    x: 100.5,
+                    y: 64.0,
+                    z: -20.5,
+                    ..Default::default()
+                }),
+            })),
+            ..Default::default()
+        };
+        let finish = ServerMessage {
+            message: Some(ServerMsg::FinishNpcTransfer(FinishNpcTransfer {
+                transfer_id: "transfer-1".to_string(),
+                npc_id: "miner_01".to_string(),
+                commit: true,
+            })),
+            ..Default::default()
+        };
+        let update = ClientMessage {
+            message: Some(ClientMsg::NpcTransferUpdate(NpcTransferUpdate {
+                transfer_id: "transfer-1".to_string(),
+                npc_id: "miner_01".to_string(),
+                stage: NpcTransferStage::Spawned as i32,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let response = TransferNpcResponse {
+            transfer_id: "transfer-1".to_string(),
+            stage: NpcTransferStage::RolledBack as i32,
+            error_message: "target failed: no room".to_string(),
+        };
+
+        use prost::Message;
+        for msg in [&prepare, &spawn, &finish] {
+            assert_eq!(&ServerMessage::decode(&msg.encode_to_vec()[..]).unwrap(), msg);
+        }
+        let decoded = ClientMessage::decode(&update.encode_to_vec()[..]).unwrap();
+        let Some(ClientMsg::NpcTransferUpdate(decoded)) = decoded.message else {
+            panic!("Expected NpcTransferUpdate");
+        };
+        assert_eq!(decoded.stage(), NpcTransferStage::Spawned);
+        let decoded = TransferNpcResponse::decode(&response.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.stage(), NpcTransferStage::RolledBack);
+
+        println!("✓ NPC transfer messages serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_message_timing() {
+        use npc_society::v1::{
+            server_message::Message as ServerMsg, GetSessionInfoResponse, MessageLatency,
+            MessageTiming, ServerMessage, SpeakDirective,
+        };
+
+        // A plugin that never heard of timing sends none; both still decode
+        let tick = ClientMessage {
+            message: Some(ClientMsg::WorldTick(Default::default())),
+            timing: Some(MessageTiming {
+                sent_at_ms: 1_700_000_001_000,
+                echo_sent_at_ms: 1_700_000_000_000,
+                echo_received_at_ms: 1_700_000_000_990,
+                ..Default::default()
+            }),
+        };
+        let speak = ServerMessage {
+            message: Some(ServerMsg::SpeakDirective(SpeakDirective {
+                npc_id: "miner_01".to_string(),
+                text: "On my way".to_string(),
+                ..Default::default()
+            })),
+            timing: Some(MessageTiming {
+                sent_at_ms: 1_700_000_000_000,
+                deadline_ms: 1_700_000_010_000,
+                ..Default::default()
+            }),
+        };
+        let info = GetSessionInfoResponse {
+            connected: true,
+            latencies: vec![MessageLatency {
+                message_type: "world_tick".to_string(),
+                inbound: true,
+                count: 1_200,
+                p50_ms: 5.0,
+                p99_ms: 50.0,
+                max_ms: 180.0,
+                over_budget: 3,
+                budget_ms: 100.0,
+                expired: 0,
+            }],
+            clock_offset_ms: -15,
+            ..Default::default()
+        };
+
+        use prost::Message;
+        assert_eq!(ClientMessage::decode(&tick.encode_to_vec()[..]).unwrap(), tick);
+        assert_eq!(ServerMessage::decode(&speak.encode_to_vec()[..]).unwrap(), speak);
+        assert_eq!(GetSessionInfoResponse::decode(&info.encode_to_vec()[..]).unwrap(), info);
+        let untimed = ClientMessage {
+            timing: None,
+            ..tick.clone()
+        };
+        let decoded = ClientMessage::decode(&untimed.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.timing, None);
+
+        println!("✓ Message timing serializes correctly");
+    }
+
+    #[tokio::test]
+    async fn test_send_directive() {
+        use npc_society::v1::{
+            action_directive::Action, ActionDirective, ActionResult, GetNpcStateResponse,
+            SendDirectiveRequest, SendDirectiveResponse, StopAction,
+        };
+
+        let request = SendDirectiveRequest {
+            directive: Some(ActionDirective {
+                npc_id: "miner_01".to_string(),
+                action: Some(Action::Stop(StopAction { cancel_pending: true })),
+                ..Default::default()
+            }),
+            server_id: "survival".to_string(),
+        };
+        let response = SendDirectiveResponse {
+            directive_id: "dir-42".to_string(),
+        };
+        let state = GetNpcStateResponse {
+            server_tick: 1200,
+            last_result: Some(ActionResult {
+                directive_id: "dir-42".to_string(),
+                npc_id: "miner_01".to_string(),
+                success: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        let decoded = SendDirectiveRequest::decode(&request.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded, request);
+        assert!(decoded.directive.unwrap().directive_id.is_empty());
+        assert_eq!(SendDirectiveResponse::decode(&response.encode_to_vec()[..]).unwrap(), response);
+        let decoded = GetNpcStateResponse::decode(&state.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.last_result.unwrap().directive_id, "dir-42");
+        // Before v1.2 there was no last_result
+        let legacy = GetNpcStateResponse { last_result: None, ..state };
+        assert_eq!(GetNpcStateResponse::decode(&legacy.encode_to_vec()[..]).unwrap().last_result, None);
+
+        println!("✓ SendDirective and GetNpcStateResponse.last_result serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_chat_directive() {
+        use npc_society::v1::{server_message::Message as ServerMsg, ChatDirective, ServerMessage};
+
+        let chat = ServerMessage::from(ChatDirective {
+            player_uuids: vec!["550e8400-e29b-41d4-a716-446655440000".to_string()],
+            text: "!npc goto <x> <y> <z>: walk to a position\n!npc freeze: stop".to_string(),
+            npc_id: String::new(),
+            locale: "en_us".to_string(),
+        });
+
+        use prost::Message;
+        let decoded = ServerMessage::decode(&chat.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded, chat);
+        let Some(ServerMsg::Chat(decoded)) = decoded.message else {
+            panic!("Expected ChatDirective");
+        };
+        assert_eq!(decoded.text.lines().count(), 2);
+        assert!(decoded.npc_id.is_empty());
+        assert_eq!(decoded.locale, "en_us");
+
+        println!("✓ ChatDirective serializes correctly");
+    }
+
+    #[tokio::test]
+    async fn test_freeze_and_resume() {
+        use npc_society::v1::{
+            server_message::Message as ServerMsg, FreezeNpcDirective, NpcSnapshot,
+            ResumeNpcDirective, ServerMessage,
+        };
+
+        let freeze = ServerMessage::from(FreezeNpcDirective {
+            npc_id: "miner_01".to_string(),
+            reason: "cutscene".to_string(),
+        });
+        let resume = ServerMessage::from(ResumeNpcDirective {
+            npc_id: "miner_01".to_string(),
+            discard_held: true,
+        });
+        let npc = NpcSnapshot {
+            npc_id: "miner_01".to_string(),
+            frozen: true,
+            freeze_reason: "cutscene".to_string(),
+            ..Default::default()
+        };
+
+        use prost::Message;
+        for msg in [&freeze, &resume] {
+            assert_eq!(&ServerMessage::decode(&msg.encode_to_vec()[..]).unwrap(), msg);
+        }
+        assert!(matches!(freeze.message, Some(ServerMsg::FreezeNpc(_))));
+        let decoded = NpcSnapshot::decode(&npc.encode_to_vec()[..]).unwrap();
+        assert!(decoded.frozen);
+        assert_eq!(decoded.freeze_reason, "cutscene");
+        // Plugins before v1.2 never report a freeze
+        let legacy = NpcSnapshot { frozen: false, freeze_reason: String::new(), ..npc };
+        assert!(!NpcSnapshot::decode(&legacy.encode_to_vec()[..]).unwrap().frozen);
+
+        println!("✓ FreezeNpcDirective, ResumeNpcDirective and NpcSnapshot.frozen serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_choreography() {
+        use npc_society::v1::{
+            action_directive::Action, choreography_step::Step,
+            client_message::Message as ClientMsg, ActionDirective, ChoreographyDirective,
+            ChoreographyResult, ChoreographyStep, ChoreographyStepResult, ServerMessage,
+            SpeakDirective, StopAction,
+        };
+
+        let scene = ServerMessage::from(ChoreographyDirective {
+            directive_id: "scene-1".to_string(),
+            steps: vec![
+                ChoreographyStep {
+                    at_ms: 0,
+                    step: Some(Step::Action(ActionDirective {
+                        npc_id: "guard".to_string(),
+                        action: Some(Action::Stop(StopAction::default())),
+                        ..Default::default()
+                    })),
+                    after_previous: false,
+                },
+                ChoreographyStep {
+                    at_ms: 1500,
+                    step: Some(Step::Speak(SpeakDirective {
+                        npc_id: "guard".to_string(),
+                        text: "Halt!".to_string(),
+                        ..Default::default()
+                    })),
+                    after_previous: true,
+                },
+            ],
+            start_at_ms: 0,
+            abort_on_failure: true,
+        });
+        let result = ClientMessage::from(ChoreographyResult {
+            directive_id: "scene-1".to_string(),
+            success: false,
+            aborted: true,
+            started_at_ms: 1_000,
+            finished_at_ms: 1_200,
+            steps: vec![ChoreographyStepResult {
+                npc_id: "guard".to_string(),
+                success: false,
+                error_message: "npc despawned".to_string(),
+                started_at_ms: 1_000,
+                finished_at_ms: 1_200,
+            }],
+        });
+
+        use prost::Message;
+        assert_eq!(ServerMessage::decode(&scene.encode_to_vec()[..]).unwrap(), scene);
+        let decoded = ClientMessage::decode(&result.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded, result);
+        let Some(ClientMsg::ChoreographyResult(decoded)) = decoded.message else {
+            panic!("expected a ChoreographyResult");
+        };
+        assert!(decoded.aborted);
+        assert_eq!(decoded.steps[0].error_message, "npc despawned");
+
+        println!("✓ ChoreographyDirective and ChoreographyResult serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_dialogue_options() {
+        use npc_society::v1::{
+            server_message::Message as ServerMsg, DialogueChoiceObservation, DialogueOption,
+            DialogueOptionsDirective, ServerMessage,
+        };
+
+        let prompt = ServerMessage::from(DialogueOptionsDirective {
+            prompt_id: "prompt-1".to_string(),
+            npc_id: "smith".to_string(),
+            player_uuid: "069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string(),
+            text: "What can I do for you?".to_string(),
+            options: vec![
+                DialogueOption {
+                    option_id: "buy".to_string(),
+                    label: "I'd like to buy".to_string(),
+                    tooltip: "Opens the shop".to_string(),
+                },
+                DialogueOption {
+                    option_id: "bye".to_string(),
+                    label: "Goodbye".to_string(),
+                    ..Default::default()
+                },
+            ],
+            expires_at_ms: 1_700_000_060_000,
+        });
+        let choice = ClientMessage::from(DialogueChoiceObservation {
+            prompt_id: "prompt-1".to_string(),
+            npc_id: "smith".to_string(),
+            player_uuid: "069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string(),
+            option_id: "buy".to_string(),
+            timestamp_ms: 1_700_000_005_000,
+        });
+
+        use prost::Message;
+        let decoded = ServerMessage::decode(&prompt.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded, prompt);
+        assert!(matches!(decoded.message, Some(ServerMsg::DialogueOptions(ref m)) if m.options.len() == 2));
+        assert_eq!(ClientMessage::decode(&choice.encode_to_vec()[..]).unwrap(), choice);
+
+        println!("✓ DialogueOptionsDirective and DialogueChoiceObservation serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_shop() {
+        use npc_society::v1::{
+            client_message::Message as ClientMsg, ItemStack, ServerMessage, ShopDefinition,
+            ShopListing, ShopTradeObservation, ShopTradeSide,
+        };
+
+        let shop = ServerMessage::from(ShopDefinition {
+            shop_id: "miner_01-shop".to_string(),
+            npc_id: "miner_01".to_string(),
+            title: "Mining supplies".to_string(),
+            listings: vec![ShopListing {
+                listing_id: "diamond".to_string(),
+                item: Some(ItemStack {
+                    item_type: "minecraft:diamond".to_string(),
+                    quantity: 1,
+                    ..Default::default()
+                }),
+                buy_price: 500,
+                sell_price: 0,
+                stock: 3,
+            }],
+            currency: String::new(),
+            open_for_player_uuids: vec!["069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string()],
+        });
+        let trade = ClientMessage::from(ShopTradeObservation {
+            shop_id: "miner_01-shop".to_string(),
+            npc_id: "miner_01".to_string(),
+            player_uuid: "069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string(),
+            listing_id: "diamond".to_string(),
+            side: ShopTradeSide::Buy as i32,
+            count: 2,
+            total_price: 1000,
+            success: true,
+            stock: 1,
+            npc_balance: 1000,
+            ..Default::default()
+        });
+
+        use prost::Message;
+        assert_eq!(ServerMessage::decode(&shop.encode_to_vec()[..]).unwrap(), shop);
+        let decoded = ClientMessage::decode(&trade.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded, trade);
+        let Some(ClientMsg::ShopTrade(decoded)) = decoded.message else {
+            panic!("expected a ShopTradeObservation");
+        };
+        assert_eq!(decoded.side(), ShopTradeSide::Buy);
+
+        println!("✓ ShopDefinition and ShopTradeObservation serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_displays() {
+        use npc_society::v1::{
+            show_display_directive::Display, BossBarColor, BossBarDisplay, HologramDisplay,
+            RemoveDisplayDirective, ServerMessage, ShowDisplayDirective,
+        };
+
+        let hologram = ServerMessage::from(ShowDisplayDirective {
+            display_id: "stall".to_string(),
+            npc_id: "smith".to_string(),
+            range: 16.0,
+            display: Some(Display::Hologram(HologramDisplay {
+                lines: vec!["Smithy".to_string(), "Iron sword: 50".to_string()],
+                offset_y: 0.5,
+                ..Default::default()
+            })),
+            ..Default::default()
+        });
+        let bar = ServerMessage::from(ShowDisplayDirective {
+            display_id: "quest".to_string(),
+            npc_id: "smith".to_string(),
+            player_uuids: vec!["069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string()],
+            display: Some(Display::BossBar(BossBarDisplay {
+                title: "Iron for the smith".to_string(),
+                progress: 0.25,
+                color: BossBarColor::Yellow as i32,
+                segments: 10,
+            })),
+            ..Default::default()
+        });
+        let remove = ServerMessage::from(RemoveDisplayDirective {
+            display_id: "stall".to_string(),
+            npc_id: "smith".to_string(),
+        });
+
+        use prost::Message;
+        for msg in [&hologram, &bar, &remove] {
+            assert_eq!(&ServerMessage::decode(&msg.encode_to_vec()[..]).unwrap(), msg);
+        }
+
+        println!("✓ ShowDisplayDirective and RemoveDisplayDirective serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_effects() {
+        use npc_society::v1::{PlayParticleDirective, PlaySoundDirective, Position, ServerMessage};
+
+        let particle = ServerMessage::from(PlayParticleDirective {
+            particle: "minecraft:heart".to_string(),
+            position: Some(Position {
+                world: "world".to_string(),
+                x: 10.5,
+                y: 64.0,
+                z: -3.5,
+                ..Default::default()
+            }),
+            count: 7,
+            offset_x: 0.5,
+            offset_y: 0.5,
+            offset_z: 0.5,
+            ..Default::default()
+        });
+        let sound = ServerMessage::from(PlaySoundDirective {
+            sound: "minecraft:block.anvil.use".to_string(),
+            npc_id: "smith".to_string(),
+            volume: 0.8,
+            pitch: 1.2,
+            category: "blocks".to_string(),
+            ..Default::default()
+        });
+
+        use prost::Message;
+        for msg in [&particle, &sound] {
+            assert_eq!(&ServerMessage::decode(&msg.encode_to_vec()[..]).unwrap(), msg);
+        }
+
+        println!("✓ PlayParticleDirective and PlaySoundDirective serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_world_control() {
+        use npc_society::v1::{
+            Hello, HelloAck, RejectionCode, ServerMessage, SetTimeDirective, SetWeatherDirective,
+            Weather,
+        };
+
+        let hello = Hello {
+            protocol_version: "1".to_string(),
+            world_control_available: true,
+            ..Default::default()
+        };
+        let ack = HelloAck {
+            protocol_version: "1".to_string(),
+            world_control: true,
+            ..Default::default()
+        };
+        let time = ServerMessage::from(SetTimeDirective {
+            directive_id: "dir-1".to_string(),
+            world: "world".to_string(),
+            time_of_day: 13_000,
+            reason: "quest: the long night".to_string(),
+            ..Default::default()
+        });
+        let storm = ServerMessage::from(SetWeatherDirective {
+            directive_id: "dir-2".to_string(),
+            npc_id: "wizard".to_string(),
+            weather: Weather::Thunder as i32,
+            duration_ticks: 6_000,
+            reason: "wizard summoned a storm".to_string(),
+            ..Default::default()
+        });
+
+        use prost::Message;
+        assert!(Hello::decode(&hello.encode_to_vec()[..]).unwrap().world_control_available);
+        assert!(HelloAck::decode(&ack.encode_to_vec()[..]).unwrap().world_control);
+        for msg in [&time, &storm] {
+            assert_eq!(&ServerMessage::decode(&msg.encode_to_vec()[..]).unwrap(), msg);
+        }
+        assert_eq!(RejectionCode::try_from(4), Ok(RejectionCode::NotPermitted));
+
+        println!("✓ World control handshake, SetTimeDirective and SetWeatherDirective serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_audio_routing() {
+        use npc_society::v1::{Hello, ServerMessage, SpeakDirective, SpeechDelivery};
+
+        let hello = Hello {
+            supported_deliveries: vec![
+                SpeechDelivery::Spatial as i32,
+                SpeechDelivery::Group as i32,
+                SpeechDelivery::Global as i32,
+            ],
+            ..Default::default()
+        };
+        let group = ServerMessage::from(SpeakDirective {
+            npc_id: "captain".to_string(),
+            text: "Form up at the gate".to_string(),
+            delivery: SpeechDelivery::Group as i32,
+            group: "guards".to_string(),
+            stream_id: "s-1".to_string(),
+            ..Default::default()
+        });
+        let shout = ServerMessage::from(SpeakDirective {
+            npc_id: "captain".to_string(),
+            text: "Raiders!".to_string(),
+            delivery: SpeechDelivery::Spatial as i32,
+            range: 64.0,
+            ..Default::default()
+        });
+
+        use prost::Message;
+        let decoded = Hello::decode(&hello.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.supported_deliveries().count(), 3);
+        for msg in [&group, &shout] {
+            assert_eq!(&ServerMessage::decode(&msg.encode_to_vec()[..]).unwrap(), msg);
+        }
+
+        println!("✓ Voice routing modes serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_audio_buffer_status() {
+        use npc_society::v1::{client_message::Message as ClientMsg, AudioBufferStatus};
+        use npc_society_example::validate::Validate;
+
+        let status = ClientMessage::from(AudioBufferStatus {
+            npc_id: "herald".to_string(),
+            stream_id: "s-1".to_string(),
+            buffered_ms: 340,
+            played_ms: 1_200,
+            underruns: 1,
+            timestamp_ms: 1_700_000_000_000,
+        });
+        assert!(status.validate().is_ok());
+
+        use prost::Message;
+        let decoded = ClientMessage::decode(&status.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded, status);
+        assert!(matches!(decoded.message, Some(ClientMsg::AudioBuffer(_))));
+
+        println!("✓ AudioBufferStatus serializes correctly");
+    }
+
+    #[tokio::test]
+    async fn test_audio_assets() {
+        use npc_society::v1::{
+            server_message::Message as ServerMsg, AudioAssetRef, ServerMessage, SpeakDirective,
+        };
+        use npc_society_example::assets::AudioAssets;
+        use npc_society_example::validate::Validate;
+
+        let mut assets = AudioAssets::new();
+        assert!(assets.add("welcome", &[0.2; 16_000], 16_000));
+        let speak = SpeakDirective {
+            npc_id: "innkeeper".to_string(),
+            text: "Welcome!".to_string(),
+            directive_id: "d-1".to_string(),
+            ..Default::default()
+        };
+        let messages = assets.play("welcome", speak).unwrap();
+        assert_eq!(messages.len(), 2);
+
+        use prost::Message;
+        for msg in &messages {
+            assert!(msg.validate().is_ok());
+            assert_eq!(&ServerMessage::decode(&msg.encode_to_vec()[..]).unwrap(), msg);
+        }
+        let Some(ServerMsg::RegisterAudioAsset(register)) = &messages[0].message else {
+            panic!("expected a RegisterAudioAsset");
+        };
+        assert_eq!(register.pcm_data.len(), 96_000);
+        assert_eq!(register.duration_ms, 1_000);
+        assert_eq!(
+            assets.cached(),
+            vec![AudioAssetRef {
+                asset_id: "welcome".to_string(),
+                hash: register.hash.clone(),
+            }]
+        );
+
+        println!("✓ RegisterAudioAsset and PlayAudioAssetDirective serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_play_music() {
+        use npc_society::v1::{NoteInstrument, ServerMessage};
+        use npc_society_example::music;
+        use npc_society_example::validate::Validate;
+
+        let mut song = music::song("bard", "Ballad", "F#4 A4+C#5:2 R B4", 90, NoteInstrument::Harp)
+            .unwrap();
+        song.repeat = true;
+        let song = ServerMessage::from(song);
+        assert!(song.validate().is_ok());
+        assert!(ServerMessage::from(music::stop("bard")).validate().is_ok());
+
+        use prost::Message;
+        assert_eq!(ServerMessage::decode(&song.encode_to_vec()[..]).unwrap(), song);
+
+        println!("✓ PlayMusicDirective serializes correctly");
+    }
+
+    #[tokio::test]
+    async fn test_formation() {
+        use npc_society::v1::{FormationDirective, ServerMessage};
+        use npc_society_example::formation;
+        use npc_society_example::validate::Validate;
+
+        let guards = vec!["guard_1".to_string(), "guard_2".to_string()];
+        let escort = formation::escort("escort-p1", "p1", &guards, 3.0);
+        assert!(escort.validate().is_ok());
+        assert!(formation::disband("escort-p1").validate().is_ok());
+
+        let twice = FormationDirective {
+            members: [escort.members.clone(), escort.members.clone()].concat(),
+            ..escort.clone()
+        };
+        assert!(twice.validate().is_err());
+        let leaderless = FormationDirective { leader: None, ..escort.clone() };
+        assert!(leaderless.validate().is_err());
+
+        use prost::Message;
+        let escort = ServerMessage::from(escort);
+        assert_eq!(ServerMessage::decode(&escort.encode_to_vec()[..]).unwrap(), escort);
+
+        println!("✓ FormationDirective serializes correctly");
+    }
+
+    #[tokio::test]
+    async fn test_guard_zone() {
+        use npc_society::v1::{
+            BlockPosition, GuardZoneDirective, IntruderObservation, IntruderTrigger, ServerMessage,
+        };
+        use npc_society_example::guard::{self, IntruderPolicy, Response};
+        use npc_society_example::validate::Validate;
+
+        let corner = |x| BlockPosition {
+            world: "world".to_string(),
+            x,
+            y: 64,
+            z: x,
+            ..Default::default()
+        };
+        let zone = guard::zone("guard", "gate", corner(0), corner(16), &["owner".to_string()]);
+        assert!(zone.validate().is_ok());
+        assert!(guard::lift("guard", "gate").validate().is_ok());
+        let half = GuardZoneDirective { to: None, ..zone.clone() };
+        assert!(half.validate().is_err());
+
+        use prost::Message;
+        let zone = ServerMessage::from(zone);
+        assert_eq!(ServerMessage::decode(&zone.encode_to_vec()[..]).unwrap(), zone);
+
+        let spotted = ClientMessage::from(IntruderObservation {
+            npc_id: "guard".to_string(),
+            zone_id: "gate".to_string(),
+            trigger: IntruderTrigger::Spotted as i32,
+            player_uuid: "p1".to_string(),
+            intruders: 1,
+            ..Default::default()
+        });
+        assert!(spotted.validate().is_ok());
+        let decoded = ClientMessage::decode(&spotted.encode_to_vec()[..]).unwrap();
+        let Some(ClientMsg::Intruder(observation)) = &decoded.message else {
+            panic!("Expected IntruderObservation");
+        };
+        let mut policy = IntruderPolicy::default();
+        assert_eq!(policy.decide(observation), Some(Response::Warn));
+
+        println!("✓ GuardZoneDirective and IntruderObservation serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_claim_container() {
+        use npc_society::v1::{
+            ActionErrorCode, BlockPosition, ClaimContainerDirective, ContainerAccess,
+            ContainerAccessObservation, ItemStack, ServerMessage,
+        };
+        use npc_society_example::storage;
+        use npc_society_example::validate::Validate;
+
+        let chest = BlockPosition {
+            world: "world".to_string(),
+            x: 100,
+            y: 64,
+            z: -200,
+            ..Default::default()
+        };
+        let owner = ["owner".to_string()];
+        let claim = storage::claim("miner", chest.clone(), "Miner's Chest", true, &owner);
+        assert!(claim.validate().is_ok());
+        assert!(storage::release("miner", chest.clone()).validate().is_ok());
+        let nowhere = ClaimContainerDirective { position: None, ..claim.clone() };
+        assert!(nowhere.validate().is_err());
+
+        use prost::Message;
+        let claim = ServerMessage::from(claim);
+        assert_eq!(ServerMessage::decode(&claim.encode_to_vec()[..]).unwrap(), claim);
+
+        let took = ClientMessage::from(ContainerAccessObservation {
+            npc_id: "miner".to_string(),
+            position: Some(chest),
+            container_name: "Miner's Chest".to_string(),
+            access: ContainerAccess::Took as i32,
+            player_uuid: "p1".to_string(),
+            items: vec![ItemStack {
+                item_type: "minecraft:diamond".to_string(),
+                quantity: 4,
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        assert!(took.validate().is_ok());
+        let decoded = ClientMessage::decode(&took.encode_to_vec()[..]).unwrap();
+        let Some(ClientMsg::ContainerAccess(access)) = &decoded.message else {
+            panic!("Expected ContainerAccessObservation");
+        };
+        assert_eq!(storage::stolen(access)[0].quantity, 4);
+
+        let locked = ActionResult {
+            directive_id: "deposit-1".to_string(),
+            npc_id: "farmer".to_string(),
+            error_code: ActionErrorCode::ContainerLocked as i32,
+            ..Default::default()
+        };
+        let decoded = ActionResult::decode(&locked.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.error_code(), ActionErrorCode::ContainerLocked);
+
+        println!("✓ ClaimContainerDirective and ContainerAccessObservation serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_landmarks() {
+        use npc_society::v1::{Landmark, LandmarkList, Position, RegisterLandmark, ServerMessage};
+        use npc_society_example::landmarks::{self, Landmarks};
+        use npc_society_example::validate::Validate;
+
+        let square = Position {
+            world: "world".to_string(),
+            x: 12.5,
+            y: 70.0,
+            z: -3.5,
+            ..Default::default()
+        };
+        let register = landmarks::register("town_square", square.clone(), "The fountain");
+        assert!(register.validate().is_ok());
+        assert!(landmarks::remove("town_square").validate().is_ok());
+        assert!(RegisterLandmark::default().validate().is_err());
+
+        use prost::Message;
+        let list = ServerMessage::from(landmarks::list());
+        assert!(list.validate().is_ok());
+        assert_eq!(ServerMessage::decode(&list.encode_to_vec()[..]).unwrap(), list);
+
+        let answer = ClientMessage::from(LandmarkList {
+            request_id: landmarks::REQUEST_ID.to_string(),
+            landmarks: vec![Landmark {
+                name: "town_square".to_string(),
+                position: Some(square),
+                set_by: "daemon".to_string(),
+                ..Default::default()
+            }],
+            complete: true,
+            ..Default::default()
+        });
+        assert!(answer.validate().is_ok());
+        let decoded = ClientMessage::decode(&answer.encode_to_vec()[..]).unwrap();
+        let Some(ClientMsg::LandmarkList(list)) = &decoded.message else {
+            panic!("Expected LandmarkList");
+        };
+        let mut cache = Landmarks::default();
+        cache.ingest(list);
+        assert_eq!(cache.block("town_square").unwrap().z, -4);
+
+        println!("✓ RegisterLandmark, ListLandmarks and LandmarkList serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_voice_consent() {
+        use npc_society::v1::{
+            RequestVoiceCapture, ServerMessage, StopVoiceCapture, VoiceConsent,
+            VoiceConsentObservation,
+        };
+        use npc_society_example::consent::VoiceConsents;
+        use npc_society_example::validate::Validate;
+
+        use prost::Message;
+        let granted = ClientMessage::from(VoiceConsentObservation {
+            player_uuid: "player-123".to_string(),
+            consent: VoiceConsent::Granted as i32,
+            timestamp_ms: 1_700_000_000_000,
+        });
+        assert!(granted.validate().is_ok());
+        let decoded = ClientMessage::decode(&granted.encode_to_vec()[..]).unwrap();
+        let Some(ClientMsg::VoiceConsent(observation)) = &decoded.message else {
+            panic!("Expected VoiceConsentObservation");
+        };
+        assert!(VoiceConsentObservation::default().validate().is_err());
+
+        let mut consents = VoiceConsents::default();
+        consents.on_hello(&Hello {
+            voice_consent_required: true,
+            voice_capture_on_request: true,
+            ..Default::default()
+        });
+        consents.observe(observation);
+        let request = consents
+            .request("npc_guide_01", ["player-123", "player-456"], 60_000, 0)
+            .unwrap();
+        assert_eq!(request.player_uuids, vec!["player-123".to_string()]);
+
+        let request = ServerMessage::from(request);
+        assert!(request.validate().is_ok());
+        assert_eq!(ServerMessage::decode(&request.encode_to_vec()[..]).unwrap(), request);
+        let stop = ServerMessage::from(consents.stop("npc_guide_01"));
+        assert!(stop.validate().is_ok());
+        assert!(RequestVoiceCapture::default().validate().is_err());
+        assert!(StopVoiceCapture::default().validate().is_err());
+
+        println!("✓ VoiceConsentObservation, RequestVoiceCapture and StopVoiceCapture serialize correctly");
+    }
+
+    #[tokio::test]
+    async fn test_moderation_quarantine() {
+        use npc_society::v1::{
+            server_message::Message as ServerMsg, ModerationCategory, ModerationFlag,
+            QuarantineNpcDirective, ServerMessage,
+        };
+        use npc_society_example::moderation::Moderation;
+        use npc_society_example::outbound::Priority;
+        use npc_society_example::validate::Validate;
+
+        use prost::Message;
+        let flag = ClientMessage::from(ModerationFlag {
+            flag_id: "flag-1".to_string(),
+            npc_id: "npc_guide_01".to_string(),
+            reporter_uuid: "player-123".to_string(),
+            by_moderator: true,
+            category: ModerationCategory::Harassment as i32,
+            directive_id: "speak-42".to_string(),
+            excerpt: "Nobody wants you here".to_string(),
+            note: String::new(),
+            timestamp_ms: 1_700_000_000_000,
+        });
+        assert!(flag.validate().is_ok());
+        let decoded = ClientMessage::decode(&flag.encode_to_vec()[..]).unwrap();
+        let Some(ClientMsg::ModerationFlag(flag)) = &decoded.message else {
+            panic!("Expected ModerationFlag");
+        };
+        assert!(ModerationFlag::default().validate().is_err());
+
+        let mut moderation = Moderation::default();
+        let quarantine = ServerMessage::from(moderation.on_flag(flag).unwrap());
+        assert!(quarantine.validate().is_ok());
+        assert_eq!(Priority::of(&quarantine), Priority::Control);
+        let decoded = ServerMessage::decode(&quarantine.encode_to_vec()[..]).unwrap();
+        let Some(ServerMsg::QuarantineNpc(directive)) = &decoded.message else {
+            panic!("Expected QuarantineNpcDirective");
+        };
+        assert!(directive.quarantined);
+        assert_eq!(directive.flag_ids, vec!["flag-1".to_string()]);
+        assert!(moderation.is_quarantined("npc_guide_01"));
+        assert!(QuarantineNpcDirective::default().validate().is_err());
+
+        println!("✓ ModerationFlag and QuarantineNpcDirective serialize correctly");
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn test_json_fixture() {
+        use npc_society::v1::{Dimension, NpcSnapshot, WorldTick};
+
+        // Omitted fields take their defaults, like on the wire
+        let fixture = r#"{
+            "message": {
+                "world_tick": {
+                    "server_tick": 20,
+                    "npcs": [{"npc_id": "miner_01", "position": {"world": "world", "x": 4.5, "dimension": 1}}]
+                }
+            }
+        }"#;
+        let msg: ClientMessage = serde_json::from_str(fixture).unwrap();
+        let Some(ClientMsg::WorldTick(WorldTick { server_tick, npcs, .. })) = &msg.message else {
+            panic!("Expected WorldTick");
+        };
+        assert_eq!(*server_tick, 20);
+        let NpcSnapshot { npc_id, position, .. } = &npcs[0];
+        assert_eq!(npc_id, "miner_01");
+        assert_eq!(position.as_ref().unwrap().dimension(), Dimension::Overworld);
+
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(serde_json::from_str::<ClientMessage>(&json).unwrap(), msg);
+
+        println!("✓ ClientMessage round-trips through JSON");
+    }
+
+    #[cfg(feature = "scenario")]
+    #[test]
+    fn test_scenarios() {
+        use npc_society_example::scenario::Scenario;
+
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios");
+        let mut files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        files.sort();
+        assert!(!files.is_empty());
+        // The daemon as main() builds it, minus TTS and the stores
+        let service = || crate::ExampleNpcSocietyService {
+            chat: std::sync::Arc::new(crate::chat_pipeline()),
+            lines: std::sync::Arc::new(crate::miner_lines()),
+            commands: std::sync::Arc::new(npc_society_example::commands::CommandRouter::admin()),
+            ..Default::default()
+        };
+        for file in files {
+            let scenario = Scenario::load(&file).unwrap();
+            scenario.run(&service()).assert();
+            println!("✓ Scenario {:?} passes", scenario.name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_audio_stream() {
+        use npc_society::v1::{ActionDirective, AudioChunk, AudioStreamHello, HelloAck};
+        use npc_society_example::connection::ConnectionContext;
+        use npc_society_example::outbound::{self, Priority, QueueConfig};
+        use npc_society_example::validate::Validate;
+        use tokio_stream::StreamExt;
+
+        use prost::Message;
+        let cx = ConnectionContext::new("10.0.0.2:41000", 1_700_000_000_000);
+        cx.record_hello(&Hello {
+            server_id: "survival".to_string(),
+            audio_stream_available: true,
+            ..Default::default()
+        });
+        cx.record_ack(&HelloAck {
+            audio_stream_token: crate::audio_stream_token(&cx),
+            ..Default::default()
+        });
+        assert!(cx.capabilities().audio_stream);
+
+        // The plugin opens ConnectAudio with the token it was given
+        let token = cx.ack().unwrap().audio_stream_token.clone();
+        let opening = ClientMessage::from(AudioStreamHello {
+            server_id: "survival".to_string(),
+            token,
+        });
+        assert!(opening.validate().is_ok());
+        let decoded = ClientMessage::decode(&opening.encode_to_vec()[..]).unwrap();
+        let Some(ClientMsg::AudioStreamHello(hello)) = &decoded.message else {
+            panic!("Expected AudioStreamHello");
+        };
+        assert!(cx.accepts_audio_stream(hello));
+        assert!(AudioStreamHello::default().validate().is_err());
+        // Tokens are not guessable from another connection's
+        assert_ne!(crate::audio_stream_token(&cx), hello.token);
+
+        // Handlers keep sending through one handle; audio takes the
+        // second stream, directives the first
+        let (tx, mut rx) = outbound::queue(QueueConfig::default());
+        let mut audio = tx.audio_stream().unwrap();
+        tx.send(AudioChunk {
+            npc_id: "npc_guide_01".to_string(),
+            stream_id: "stream-1".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        tx.send(ActionDirective {
+            directive_id: "dir-1".to_string(),
+            npc_id: "npc_guide_01".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(Priority::of(&rx.try_next().unwrap()), Priority::Directive);
+        assert!(rx.try_next().is_none());
+        assert_eq!(Priority::of(&audio.next().await.unwrap()), Priority::Audio);
+
+        println!("✓ AudioStreamHello pairs a ConnectAudio stream with its connection");
+    }
+
+    #[tokio::test]
+    async fn test_world_bootstrap() {
+        use npc_society::v1::{
+            server_message::Message as ServerMsg, EnvironmentState, Landmark, NpcSnapshot, Position,
+            WorldBootstrap,
+        };
+        use npc_society_example::connection::ConnectionContext;
+        use npc_society_example::events::{self, ClientEvent};
+        use npc_society_example::outbound::{self, QueueConfig};
+        use npc_society_example::validate::Validate;
+
+        use prost::Message;
+        let chest = Position {
+            world: "world".to_string(),
+            x: 100.5,
+            y: 64.0,
+            z: -199.5,
+            ..Default::default()
+        };
+        let bootstrap = ClientMessage::from(WorldBootstrap {
+            npcs: vec![NpcSnapshot {
+                npc_id: "npc_miner_01".to_string(),
+                position: Some(chest.clone()),
+                health_norm: 1.0,
+                ..Default::default()
+            }],
+            landmarks: vec![Landmark {
+                name: crate::MINERS_CHEST.to_string(),
+                position: Some(chest),
+                ..Default::default()
+            }],
+            environments: vec![EnvironmentState {
+                world: "world".to_string(),
+                time_of_day: 18_000,
+                ..Default::default()
+            }],
+            server_tick: 1200,
+            timestamp_ms: 1_700_000_000_000,
+            ..Default::default()
+        });
+        assert!(bootstrap.validate().is_ok());
+        assert_eq!(ClientMessage::decode(&bootstrap.encode_to_vec()[..]).unwrap(), bootstrap);
+        assert!(ClientMessage::from(WorldBootstrap {
+            npcs: vec![NpcSnapshot::default()],
+            ..Default::default()
+        })
+        .validate()
+        .is_err());
+
+        // A plugin that bootstraps is not asked for its landmarks
+        let service = crate::ExampleNpcSocietyService::default();
+        let cx = ConnectionContext::new("10.0.0.2:41000", 1_700_000_000_000);
+        let (tx, mut rx) = outbound::queue(QueueConfig::default());
+        let hello = ClientMessage::from(Hello {
+            server_id: "survival".to_string(),
+            world_bootstrap_available: true,
+            ..Default::default()
+        });
+        for message in [hello, bootstrap] {
+            events::dispatch(&service, ClientEvent::try_from(message).unwrap(), &cx, &tx);
+        }
+        while let Some(message) = rx.try_next() {
+            assert!(!matches!(message.message, Some(ServerMsg::ListLandmarks(_))));
+        }
+
+        // The world model starts from it before the first WorldTick
+        let state = service.state.lock().unwrap();
+        assert!(state.landmarks.lock().unwrap().block(crate::MINERS_CHEST).is_some());
+        assert!(state.world.npc("npc_miner_01").is_some());
+        assert_eq!(state.world.environment("world").unwrap().time_of_day, 18_000);
+
+        println!("✓ WorldBootstrap fills the world model and replaces ListLandmarks");
+    }
+
+    #[test]
+    fn test_debug_path() {
+        use npc_society::v1::{
+            server_message::Message as ServerMsg, ChatObservation, DebugPathDirective,
+            DebugPathStyle, PlayerSnapshot, ServerMessage,
+        };
+        use npc_society_example::commands::CommandRouter;
+        use npc_society_example::outbound::Priority;
+        use npc_society_example::validate::Validate;
+
+        use prost::Message;
+        let chat = ChatObservation {
+            npc_id: "npc_guide_01".to_string(),
+            player_uuid: "player-123".to_string(),
+            player_name: "Steve".to_string(),
+            message: "!npc path on".to_string(),
+            ..Default::default()
+        };
+        let op = PlayerSnapshot {
+            player_uuid: "player-123".to_string(),
+            op: true,
+            ..Default::default()
+        };
+        let sent = CommandRouter::admin()
+            .dispatch(&chat, Some(&op), None)
+            .unwrap();
+        let debug_path = &sent[0];
+        assert!(debug_path.validate().is_ok());
+        assert_eq!(Priority::of(debug_path), Priority::Directive);
+        let decoded = ServerMessage::decode(&debug_path.encode_to_vec()[..]).unwrap();
+        let Some(ServerMsg::DebugPath(directive)) = &decoded.message else {
+            panic!("Expected DebugPathDirective");
+        };
+        assert_eq!(directive.viewer_uuid, "player-123");
+        assert_eq!(directive.style(), DebugPathStyle::Particles);
+        assert!(DebugPathDirective::default().validate().is_err());
+
+        println!("✓ DebugPathDirective serializes correctly");
+    }
+
+    #[test]
+    fn test_probe_self_check() {
+        use npc_society::v1::{server_message::Message as ServerMsg, ServerMessage};
+        use npc_society_example::outbound::Priority;
+        use npc_society_example::probe::{self, ProbeConfig, SelfTest};
+        use npc_society_example::validate::Validate;
+
+        use prost::Message;
+        let mut test = SelfTest::new("probe-1", ProbeConfig::default());
+        let probes = test.start(1_700_000_000_000);
+        assert_eq!(probes.len(), 8);
+        for probe in probes {
+            let msg = ServerMessage::from(probe);
+            assert!(msg.validate().is_ok());
+            assert_eq!(Priority::of(&msg), Priority::Control);
+
+            // The plugin echoes what it decoded, 5 ms later on its clock
+            let decoded = ServerMessage::decode(&msg.encode_to_vec()[..]).unwrap();
+            let Some(ServerMsg::Probe(probe)) = &decoded.message else {
+                panic!("Expected Probe");
+            };
+            let arrived_at_ms = probe.sent_at_ms + 5;
+            let reply = ClientMessage::from(probe::reply(probe, arrived_at_ms, arrived_at_ms));
+            assert!(reply.validate().is_ok());
+            let decoded = ClientMessage::decode(&reply.encode_to_vec()[..]).unwrap();
+            let Some(ClientMsg::ProbeReply(reply)) = &decoded.message else {
+                panic!("Expected ProbeReply");
+            };
+            assert!(test.on_reply(reply, 1_700_000_000_010));
+        }
+
+        let report = test.finish(1_700_000_000_010).unwrap();
+        assert!(report.passed, "{}", report.failure);
+        assert_eq!((report.sent, report.replied), (8, 8));
+        assert_eq!(report.round_trip_p50_ms, 10.0);
+
+        println!("✓ Probe round trips pass the self-check");
+    }
+
+    #[test]
+    fn test_chunk_load_parking() {
+        use npc_society::v1::{
+            client_message::Message as ClientMsg, server_message::Message as ServerMsg,
+            ChunkLoadObservation, ClientMessage, KeepChunkLoadedDirective, ServerMessage,
+        };
+        use npc_society_example::outbound::Priority;
+        use npc_society_example::validate::Validate;
+
+        use prost::Message;
+        let unload = ChunkLoadObservation {
+            npc_id: "npc_miner_01".to_string(),
+            loaded: false,
+            world: "world".to_string(),
+            chunk_x: 62,
+            chunk_z: -13,
+            parked_directive_ids: vec!["dir-7".to_string(), "dir-8".to_string()],
+            timestamp_ms: 1_700_000_000_000,
+        };
+        let msg: ClientMessage = unload.clone().into();
+        assert!(msg.validate().is_ok());
+        let decoded = ClientMessage::decode(&msg.encode_to_vec()[..]).unwrap();
+        let Some(ClientMsg::ChunkLoad(observation)) = decoded.message else {
+            panic!("Expected ChunkLoadObservation");
+        };
+        assert_eq!(observation, unload);
+        assert!(ChunkLoadObservation::default().validate().is_err());
+
+        let keep = KeepChunkLoadedDirective {
+            directive_id: "dir-9".to_string(),
+            npc_id: "npc_miner_01".to_string(),
+            keep_loaded: true,
+            radius_chunks: 1,
+            duration_ms: 600_000,
+            reason: "guarding the mine".to_string(),
+        };
+        let msg: ServerMessage = keep.clone().into();
+        assert!(msg.validate().is_ok());
+        assert_eq!(Priority::of(&msg), Priority::Directive);
+        let decoded = ServerMessage::decode(&msg.encode_to_vec()[..]).unwrap();
+        assert_eq!(decoded.message, Some(ServerMsg::KeepChunkLoaded(keep.clone())));
+        let negative = KeepChunkLoadedDirective {
+            radius_chunks: -1,
+            ..keep
+        };
+        assert!(negative.validate().is_err());
+
+        println!("✓ Chunk load observations and KeepChunkLoadedDirective serialize correctly");
+    }
 }
@@ -0,0 +1,238 @@
+//! Jitter buffer for sequenced audio (AudioChunk / VoicePcmFrame).
+//!
+//! Frames can arrive out of order or duplicated. `AudioStreamAssembler`
+//! releases them strictly in `sequence` order per stream, skips gaps that
+//! outlive the jitter window, and ends a stream after its final frame.
+
+use std::collections::{BTreeMap, HashMap};
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+/// One frame of a sequenced audio stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioFrame {
+    /// Sequence number within the stream
+    pub sequence: u64,
+    /// Audio payload (format as negotiated in HelloAck), shared with the
+    /// message it came from
+    pub pcm_data: Bytes,
+    /// Whether this is the last frame of the stream
+    pub is_final: bool,
+}
+
+/// What happened to a frame passed to [`AudioStreamAssembler::push`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushOutcome {
+    /// The frame (and possibly buffered successors) was released in order
+    Released(usize),
+    /// The frame is waiting for an earlier sequence number
+    Buffered,
+    /// A frame with this sequence number is already buffered
+    Duplicate,
+    /// The sequence was already released or skipped, or the stream has ended
+    Late,
+}
+
+#[derive(Debug, Default)]
+struct StreamState {
+    next_sequence: u64,
+    pending: BTreeMap<u64, AudioFrame>,
+    finished: bool,
+    subscriber: Option<mpsc::UnboundedSender<AudioFrame>>,
+}
+
+/// Reorders audio frames per stream id.
+///
+/// Released frames go to the stream's subscriber if one was registered
+/// with [`subscribe`](Self::subscribe), otherwise they are returned from
+/// [`push`](Self::push).
+#[derive(Debug)]
+pub struct AudioStreamAssembler {
+    max_buffered: usize,
+    streams: HashMap<String, StreamState>,
+}
+
+impl AudioStreamAssembler {
+    /// Create an assembler that waits for at most `max_buffered` frames
+    /// behind a gap before treating the missing frames as lost.
+    pub fn new(max_buffered: usize) -> Self {
+        Self {
+            max_buffered: max_buffered.max(1),
+            streams: HashMap::new(),
+        }
+    }
+
+    /// Deliver released frames of `stream_id` to a `Stream` instead of
+    /// returning them from `push`. The stream ends after the final frame.
+    pub fn subscribe(&mut self, stream_id: &str) -> UnboundedReceiverStream<AudioFrame> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let state = self.streams.entry(stream_id.to_string()).or_default();
+        if !state.finished {
+            state.subscriber = Some(tx);
+        }
+        UnboundedReceiverStream::new(rx)
+    }
+
+    /// Add a frame to `stream_id`, releasing every frame that is now in order.
+    pub fn push(&mut self, stream_id: &str, frame: AudioFrame) -> (PushOutcome, Vec<AudioFrame>) {
+        let max_buffered = self.max_buffered;
+        let state = self.streams.entry(stream_id.to_string()).or_default();
+
+        if state.finished || frame.sequence < state.next_sequence {
+            return (PushOutcome::Late, Vec::new());
+        }
+        if state.pending.contains_key(&frame.sequence) {
+            return (PushOutcome::Duplicate, Vec::new());
+        }
+        state.pending.insert(frame.sequence, frame);
+
+        // Give up on a gap once the window behind it is full
+        if state.pending.len() > max_buffered {
+            if let Some(&first) = state.pending.keys().next() {
+                state.next_sequence = first;
+            }
+        }
+
+        let released = Self::release(state, false);
+        let outcome = match released.len() {
+            0 => PushOutcome::Buffered,
+            n => PushOutcome::Released(n),
+        };
+        (outcome, Self::deliver(state, released))
+    }
+
+    /// Release everything buffered for `stream_id`, skipping any gaps.
+    /// Use when a stream has been idle too long to wait for missing frames.
+    pub fn flush(&mut self, stream_id: &str) -> Vec<AudioFrame> {
+        match self.streams.get_mut(stream_id) {
+            Some(state) => {
+                let released = Self::release(state, true);
+                Self::deliver(state, released)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Forget a stream, dropping any buffered frames.
+    pub fn remove(&mut self, stream_id: &str) {
+        self.streams.remove(stream_id);
+    }
+
+    /// Number of streams currently tracked
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Whether no streams are tracked
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    fn release(state: &mut StreamState, skip_gaps: bool) -> Vec<AudioFrame> {
+        let mut released = Vec::new();
+        while let Some(entry) = state.pending.first_entry() {
+            if *entry.key() != state.next_sequence && !skip_gaps {
+                break;
+            }
+            let frame = entry.remove();
+            state.next_sequence = frame.sequence + 1;
+            let is_final = frame.is_final;
+            released.push(frame);
+            if is_final {
+                state.finished = true;
+                state.pending.clear();
+                break;
+            }
+        }
+        released
+    }
+
+    fn deliver(state: &mut StreamState, released: Vec<AudioFrame>) -> Vec<AudioFrame> {
+        let Some(tx) = state.subscriber.as_ref() else {
+            return released;
+        };
+        for frame in released {
+            let _ = tx.send(frame);
+        }
+        if state.finished {
+            state.subscriber = None;
+        }
+        Vec::new()
+    }
+}
+
+impl Default for AudioStreamAssembler {
+    /// Five frames (100ms of 20ms frames) of jitter tolerance
+    fn default() -> Self {
+        Self::new(5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_stream::StreamExt;
+
+    fn frame(sequence: u64, is_final: bool) -> AudioFrame {
+        AudioFrame {
+            sequence,
+            pcm_data: Bytes::from(vec![sequence as u8]),
+            is_final,
+        }
+    }
+
+    fn sequences(frames: &[AudioFrame]) -> Vec<u64> {
+        frames.iter().map(|f| f.sequence).collect()
+    }
+
+    #[test]
+    fn test_reorders_and_drops_duplicates() {
+        let mut assembler = AudioStreamAssembler::new(4);
+
+        assert_eq!(assembler.push("s", frame(1, false)).0, PushOutcome::Buffered);
+        assert_eq!(assembler.push("s", frame(1, false)).0, PushOutcome::Duplicate);
+
+        let (outcome, released) = assembler.push("s", frame(0, false));
+        assert_eq!(outcome, PushOutcome::Released(2));
+        assert_eq!(sequences(&released), vec![0, 1]);
+
+        assert_eq!(assembler.push("s", frame(0, false)).0, PushOutcome::Late);
+    }
+
+    #[test]
+    fn test_skips_gap_when_window_is_full() {
+        let mut assembler = AudioStreamAssembler::new(2);
+        assembler.push("s", frame(0, false));
+
+        // Sequence 1 is lost
+        assert_eq!(assembler.push("s", frame(2, false)).0, PushOutcome::Buffered);
+        assert_eq!(assembler.push("s", frame(3, false)).0, PushOutcome::Buffered);
+        let (_, released) = assembler.push("s", frame(4, false));
+        assert_eq!(sequences(&released), vec![2, 3, 4]);
+
+        assert_eq!(assembler.push("s", frame(1, false)).0, PushOutcome::Late);
+    }
+
+    #[test]
+    fn test_final_frame_ends_stream() {
+        let mut assembler = AudioStreamAssembler::default();
+        let (_, released) = assembler.push("s", frame(0, true));
+        assert_eq!(sequences(&released), vec![0]);
+        assert_eq!(assembler.push("s", frame(1, false)).0, PushOutcome::Late);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_yields_ordered_stream() {
+        let mut assembler = AudioStreamAssembler::default();
+        let stream = assembler.subscribe("s");
+
+        assert!(assembler.push("s", frame(2, true)).1.is_empty());
+        assert!(assembler.push("s", frame(0, false)).1.is_empty());
+        assert!(assembler.push("s", frame(1, false)).1.is_empty());
+
+        let frames: Vec<AudioFrame> = stream.collect().await;
+        assert_eq!(sequences(&frames), vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,182 @@
+//! Named places (`Landmark`, v1.2+).
+//!
+//! Coordinates written into a daemon, like a chest at (100, 64, -200),
+//! break as soon as the map changes. Landmarks live in the plugin, where
+//! staff can also move them in game, and reach daemons in the
+//! WorldBootstrap or as LandmarkLists: the answer to a ListLandmarks sent
+//! after the handshake, then a push per change. [`Landmarks`] caches them so behavior trees look places up by
+//! name; [`register`], [`remove`] and [`list`] build the messages.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::dimension;
+use crate::npc_society::v1::{
+    BlockPosition, Landmark, LandmarkList, ListLandmarks, Position, RegisterLandmark,
+    WorldBootstrap,
+};
+
+/// `request_id` of the ListLandmarks [`list`] builds
+pub const REQUEST_ID: &str = "landmarks";
+
+/// Landmarks of one server, by name
+#[derive(Debug, Default)]
+pub struct Landmarks {
+    by_name: BTreeMap<String, Landmark>,
+}
+
+/// [`Landmarks`] shared between the connection and behavior trees
+pub type SharedLandmarks = Arc<Mutex<Landmarks>>;
+
+impl Landmarks {
+    /// Apply a LandmarkList; a complete one replaces everything cached
+    pub fn ingest(&mut self, list: &LandmarkList) {
+        if list.complete {
+            self.by_name.clear();
+        }
+        for name in &list.removed {
+            self.by_name.remove(name);
+        }
+        for landmark in &list.landmarks {
+            self.by_name.insert(landmark.name.clone(), landmark.clone());
+        }
+    }
+
+    /// Replace everything cached with the landmarks of a WorldBootstrap
+    pub fn ingest_bootstrap(&mut self, bootstrap: &WorldBootstrap) {
+        self.ingest(&LandmarkList {
+            landmarks: bootstrap.landmarks.clone(),
+            complete: true,
+            ..Default::default()
+        });
+    }
+
+    /// The landmark called `name`
+    pub fn get(&self, name: &str) -> Option<&Landmark> {
+        self.by_name.get(name)
+    }
+
+    /// Where the landmark called `name` is
+    pub fn position(&self, name: &str) -> Option<&Position> {
+        self.get(name)?.position.as_ref()
+    }
+
+    /// The block the landmark called `name` is in, e.g. a chest
+    pub fn block(&self, name: &str) -> Option<BlockPosition> {
+        self.position(name).map(dimension::block_at)
+    }
+
+    /// Landmarks tagged `tag`, by name
+    pub fn tagged<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'a Landmark> + 'a {
+        self.by_name
+            .values()
+            .filter(move |landmark| landmark.tags.iter().any(|t| t == tag))
+    }
+
+    /// Number of landmarks cached
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    /// Whether no landmarks are cached
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+}
+
+/// Set the landmark `name` at `position`, replacing one of that name
+pub fn register(name: &str, position: Position, description: &str) -> RegisterLandmark {
+    RegisterLandmark {
+        landmark: Some(Landmark {
+            name: name.to_string(),
+            position: Some(position),
+            description: description.to_string(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Remove the landmark `name`
+pub fn remove(name: &str) -> RegisterLandmark {
+    RegisterLandmark {
+        landmark: Some(Landmark {
+            name: name.to_string(),
+            ..Default::default()
+        }),
+        remove: true,
+        ..Default::default()
+    }
+}
+
+/// Ask for every landmark; the answer is complete
+pub fn list() -> ListLandmarks {
+    ListLandmarks {
+        request_id: REQUEST_ID.to_string(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn landmark(name: &str, x: f64, tags: &[&str]) -> Landmark {
+        Landmark {
+            name: name.to_string(),
+            position: Some(Position {
+                world: "world".to_string(),
+                x,
+                y: 64.5,
+                z: -0.5,
+                ..Default::default()
+            }),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_ingest() {
+        let mut landmarks = Landmarks::default();
+        landmarks.ingest(&LandmarkList {
+            request_id: REQUEST_ID.to_string(),
+            landmarks: vec![
+                landmark("town_square", 0.0, &[]),
+                landmark("bakery", 10.0, &["shop"]),
+                landmark("smithy", 20.0, &["shop"]),
+            ],
+            complete: true,
+            ..Default::default()
+        });
+        let block = landmarks.block("bakery").unwrap();
+        assert_eq!((block.x, block.y, block.z), (10, 64, -1));
+        let shops: Vec<_> = landmarks.tagged("shop").map(|l| l.name.as_str()).collect();
+        assert_eq!(shops, ["bakery", "smithy"]);
+
+        // Pushes move and remove single landmarks
+        landmarks.ingest(&LandmarkList {
+            landmarks: vec![landmark("bakery", 15.0, &["shop"])],
+            removed: vec!["smithy".to_string()],
+            ..Default::default()
+        });
+        assert_eq!(landmarks.position("bakery").unwrap().x, 15.0);
+        assert!(landmarks.get("smithy").is_none());
+        assert_eq!(landmarks.len(), 2);
+
+        // A complete list drops what it lacks
+        landmarks.ingest(&LandmarkList {
+            landmarks: vec![landmark("town_square", 0.0, &[])],
+            complete: true,
+            ..Default::default()
+        });
+        assert_eq!(landmarks.len(), 1);
+
+        landmarks.ingest_bootstrap(&WorldBootstrap {
+            landmarks: vec![landmark("bakery", 10.0, &["shop"])],
+            ..Default::default()
+        });
+        assert!(landmarks.get("town_square").is_none());
+        assert_eq!(landmarks.len(), 1);
+    }
+}
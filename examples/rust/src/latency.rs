@@ -0,0 +1,519 @@
+//! Message timing, clock sync and latency budgets.
+//!
+//! Both ends stamp every envelope with a [`MessageTiming`]: when it was
+//! sent, an optional deadline, and an echo of the last message received
+//! from the peer. Each echo gives the four timestamps of an NTP exchange,
+//! so [`ClockSync`] can estimate the offset between the plugin's and the
+//! daemon's clocks. With the offset, every message's `sent_at_ms` turns
+//! into a one-way delay:
+//!
+//! - plugin -> daemon: measured for every message received
+//! - daemon -> plugin: sampled, from the messages the plugin echoes
+//!
+//! [`LatencyTracker`] keeps one histogram per message type and direction,
+//! flags messages slower than their [`LatencyBudgets`] entry, and reports
+//! everything as [`MessageLatency`] for GetSessionInfo:
+//!
+//! ```ignore
+//! let arrival = tracker.on_received(msg.timing.as_ref(), event.message_type(), now_ms());
+//! if arrival.expired {
+//!     return; // nobody is waiting for this anymore
+//! }
+//! // ... and for every message put on the wire:
+//! tracker.stamp(&mut reply, now_ms());
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::events::ServerEvent;
+use crate::npc_society::v1::{MessageLatency, MessageTiming, ServerMessage};
+
+/// Exchanges kept by [`ClockSync`]; the one with the shortest round trip wins
+const CLOCK_SAMPLES: usize = 16;
+
+/// Sent messages remembered to attribute the plugin's echoes
+const SENT_HISTORY: usize = 256;
+
+/// Upper bounds of the histogram buckets in milliseconds; the last bucket
+/// takes everything slower
+const BUCKETS_MS: [i64; 14] = [
+    1, 2, 5, 10, 20, 50, 100, 200, 500, 1_000, 2_000, 5_000, 10_000, 30_000,
+];
+
+/// Offset between the local clock and the peer's, from echoed timestamps
+#[derive(Debug, Clone, Default)]
+pub struct ClockSync {
+    /// Recent (round trip, offset) pairs, oldest first
+    samples: VecDeque<(i64, i64)>,
+}
+
+impl ClockSync {
+    /// Add one exchange:
+    ///
+    /// - `t0`: we sent a message (local clock)
+    /// - `t1`: the peer received it (peer clock)
+    /// - `t2`: the peer sent its reply (peer clock)
+    /// - `t3`: we received the reply (local clock)
+    pub fn observe(&mut self, t0: i64, t1: i64, t2: i64, t3: i64) {
+        let round_trip = (t3 - t0) - (t2 - t1);
+        if round_trip < 0 {
+            // Clock stepped mid-exchange; the sample means nothing
+            return;
+        }
+        let offset = ((t1 - t0) + (t2 - t3)) / 2;
+        if self.samples.len() == CLOCK_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((round_trip, offset));
+    }
+
+    /// Best sample: the shortest round trip has the least queueing in it
+    fn best(&self) -> Option<(i64, i64)> {
+        self.samples
+            .iter()
+            .copied()
+            .min_by_key(|&(round_trip, _)| round_trip)
+    }
+
+    /// Peer clock minus local clock, once an exchange completed
+    pub fn offset_ms(&self) -> Option<i64> {
+        self.best().map(|(_, offset)| offset)
+    }
+
+    /// Round trip of the best sample, without the time the peer held it
+    pub fn round_trip_ms(&self) -> Option<i64> {
+        self.best().map(|(round_trip, _)| round_trip)
+    }
+}
+
+/// Delays of one message type in one direction
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    buckets: [u64; BUCKETS_MS.len() + 1],
+    count: u64,
+    max_ms: i64,
+    over_budget: u64,
+    expired: u64,
+}
+
+impl LatencyHistogram {
+    /// Add one delay (negative delays from clock error count as 0)
+    pub fn record(&mut self, delay_ms: i64) {
+        let delay_ms = delay_ms.max(0);
+        let bucket = BUCKETS_MS
+            .iter()
+            .position(|&bound| delay_ms <= bound)
+            .unwrap_or(BUCKETS_MS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.max_ms = self.max_ms.max(delay_ms);
+    }
+
+    /// Upper bound of the bucket holding quantile `q` (0..=1), capped at
+    /// the slowest delay seen
+    pub fn percentile(&self, q: f64) -> i64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((q * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let bound = BUCKETS_MS.get(bucket).copied().unwrap_or(self.max_ms);
+                return bound.min(self.max_ms);
+            }
+        }
+        self.max_ms
+    }
+
+    /// Delays recorded
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// Per message type budgets for the one-way delay, keyed by oneof field
+/// name (`world_tick`, `speak_directive`, ...)
+#[derive(Debug, Clone)]
+pub struct LatencyBudgets {
+    budgets: HashMap<String, i64>,
+}
+
+impl Default for LatencyBudgets {
+    /// Tight budgets for what players hear and see move, none elsewhere
+    fn default() -> Self {
+        let mut budgets = Self::none();
+        budgets.set("world_tick", 100);
+        budgets.set("chat_observation", 250);
+        budgets.set("voice_pcm_frame", 60);
+        budgets.set("action_directive", 100);
+        budgets.set("speak_directive", 250);
+        budgets.set("audio_chunk", 60);
+        budgets.set("stop_speaking", 60);
+        budgets
+    }
+}
+
+impl LatencyBudgets {
+    /// No budgets at all
+    pub fn none() -> Self {
+        Self {
+            budgets: HashMap::new(),
+        }
+    }
+
+    /// Budget `message_type` at `budget_ms` (0 removes it)
+    pub fn set(&mut self, message_type: &str, budget_ms: i64) {
+        if budget_ms > 0 {
+            self.budgets.insert(message_type.to_string(), budget_ms);
+        } else {
+            self.budgets.remove(message_type);
+        }
+    }
+
+    /// Budget of `message_type`, if any
+    pub fn get(&self, message_type: &str) -> Option<i64> {
+        self.budgets.get(message_type).copied()
+    }
+}
+
+/// `WorldTick` -> `world_tick`
+fn snake_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Histogram plus the type's budget, looked up once
+#[derive(Debug, Clone, Default)]
+struct TypeLatency {
+    message_type: String,
+    budget_ms: Option<i64>,
+    histogram: LatencyHistogram,
+}
+
+/// What [`LatencyTracker::on_received`] learned about a message
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Arrival {
+    /// One-way delay, once the clocks are synced
+    pub delay_ms: Option<i64>,
+    /// Slower than the type's budget
+    pub over_budget: bool,
+    /// Its deadline passed before it arrived
+    pub expired: bool,
+}
+
+/// Timing of one connection: clock sync, echoes and delay histograms
+#[derive(Debug, Clone, Default)]
+pub struct LatencyTracker {
+    budgets: LatencyBudgets,
+    clock: ClockSync,
+    /// sent_at_ms of the peer's latest message and when it arrived
+    last_received: Option<(i64, i64)>,
+    /// (sent_at_ms, message type) of recent messages, oldest first
+    sent: VecDeque<(i64, &'static str)>,
+    /// plugin -> daemon, keyed by prost variant name
+    inbound: HashMap<&'static str, TypeLatency>,
+    /// daemon -> plugin, keyed by prost variant name
+    outbound: HashMap<&'static str, TypeLatency>,
+}
+
+impl LatencyTracker {
+    pub fn new(budgets: LatencyBudgets) -> Self {
+        Self {
+            budgets,
+            ..Default::default()
+        }
+    }
+
+    fn entry<'a>(
+        table: &'a mut HashMap<&'static str, TypeLatency>,
+        budgets: &LatencyBudgets,
+        message_type: &'static str,
+    ) -> &'a mut TypeLatency {
+        table.entry(message_type).or_insert_with(|| {
+            let name = snake_case(message_type);
+            TypeLatency {
+                budget_ms: budgets.get(&name),
+                message_type: name,
+                histogram: LatencyHistogram::default(),
+            }
+        })
+    }
+
+    /// Account for a message from the peer, received at `now_ms`.
+    /// `message_type` is the oneof variant name, as returned by
+    /// `ClientEvent::message_type`.
+    pub fn on_received(
+        &mut self,
+        timing: Option<&MessageTiming>,
+        message_type: &'static str,
+        now_ms: i64,
+    ) -> Arrival {
+        let Some(timing) = timing.filter(|t| t.sent_at_ms != 0) else {
+            return Arrival::default();
+        };
+        self.last_received = Some((timing.sent_at_ms, now_ms));
+
+        if timing.echo_sent_at_ms != 0 {
+            self.clock.observe(
+                timing.echo_sent_at_ms,
+                timing.echo_received_at_ms,
+                timing.sent_at_ms,
+                now_ms,
+            );
+            self.on_echo(timing.echo_sent_at_ms, timing.echo_received_at_ms);
+        }
+
+        let expired = timing.deadline_ms != 0 && now_ms > self.to_local(timing.deadline_ms);
+        let delay_ms = self
+            .clock
+            .offset_ms()
+            .map(|offset| now_ms - (timing.sent_at_ms - offset));
+        let mut over_budget = false;
+        if let Some(delay_ms) = delay_ms {
+            let entry = Self::entry(&mut self.inbound, &self.budgets, message_type);
+            entry.histogram.record(delay_ms);
+            over_budget = entry.budget_ms.is_some_and(|budget| delay_ms > budget);
+            if over_budget {
+                entry.histogram.over_budget += 1;
+            }
+        }
+        if expired {
+            let entry = Self::entry(&mut self.inbound, &self.budgets, message_type);
+            entry.histogram.expired += 1;
+        }
+        Arrival {
+            delay_ms,
+            over_budget,
+            expired,
+        }
+    }
+
+    /// The peer received our message sent at `sent_at_ms` at
+    /// `received_at_ms` on its clock
+    fn on_echo(&mut self, sent_at_ms: i64, received_at_ms: i64) {
+        let Some(offset) = self.clock.offset_ms() else {
+            return;
+        };
+        let Some(index) = self.sent.iter().position(|&(at, _)| at == sent_at_ms) else {
+            return;
+        };
+        // Older messages were either echoed already or never will be
+        let (_, message_type) = self.sent[index];
+        self.sent.drain(..=index);
+        let delay_ms = received_at_ms - offset - sent_at_ms;
+        let entry = Self::entry(&mut self.outbound, &self.budgets, message_type);
+        entry.histogram.record(delay_ms);
+        if entry.budget_ms.is_some_and(|budget| delay_ms > budget) {
+            entry.histogram.over_budget += 1;
+        }
+    }
+
+    /// Stamp `msg` as sent at `now_ms`, echoing the peer's latest message.
+    /// Keeps a deadline the producer set (see [`with_deadline`]).
+    pub fn stamp(&mut self, msg: &mut ServerMessage, now_ms: i64) {
+        let timing = msg.timing.get_or_insert_with(Default::default);
+        timing.sent_at_ms = now_ms;
+        if let Some((sent_at_ms, received_at_ms)) = self.last_received {
+            timing.echo_sent_at_ms = sent_at_ms;
+            timing.echo_received_at_ms = received_at_ms;
+        }
+        if self.sent.len() == SENT_HISTORY {
+            self.sent.pop_front();
+        }
+        self.sent.push_back((now_ms, server_message_type(msg)));
+    }
+
+    /// Count `msg` as dropped because its deadline passed before sending
+    pub fn on_expired(&mut self, msg: &ServerMessage) {
+        let entry = Self::entry(&mut self.outbound, &self.budgets, server_message_type(msg));
+        entry.histogram.expired += 1;
+    }
+
+    /// Convert a time on the peer's clock to ours (unchanged until synced)
+    pub fn to_local(&self, peer_ms: i64) -> i64 {
+        peer_ms - self.clock.offset_ms().unwrap_or(0)
+    }
+
+    /// Peer clock minus local clock (0 until synced)
+    pub fn offset_ms(&self) -> i64 {
+        self.clock.offset_ms().unwrap_or(0)
+    }
+
+    /// The clock estimate
+    pub fn clock(&self) -> &ClockSync {
+        &self.clock
+    }
+
+    /// Histograms of both directions, inbound first, by type
+    pub fn stats(&self) -> Vec<MessageLatency> {
+        let mut stats: Vec<_> = [(true, &self.inbound), (false, &self.outbound)]
+            .into_iter()
+            .flat_map(|(inbound, table)| {
+                table.values().map(move |t| MessageLatency {
+                    message_type: t.message_type.clone(),
+                    inbound,
+                    count: t.histogram.count,
+                    p50_ms: t.histogram.percentile(0.5) as f64,
+                    p99_ms: t.histogram.percentile(0.99) as f64,
+                    max_ms: t.histogram.max_ms as f64,
+                    over_budget: t.histogram.over_budget,
+                    budget_ms: t.budget_ms.unwrap_or(0) as f64,
+                    expired: t.histogram.expired,
+                })
+            })
+            .collect();
+        stats.sort_by(|a, b| {
+            b.inbound
+                .cmp(&a.inbound)
+                .then_with(|| a.message_type.cmp(&b.message_type))
+        });
+        stats
+    }
+}
+
+/// Prost variant name of a ServerMessage's payload
+fn server_message_type(msg: &ServerMessage) -> &'static str {
+    ServerEvent::type_of(msg).unwrap_or("None")
+}
+
+/// `msg` with a deadline on the local clock: it is dropped instead of sent
+/// once `deadline_ms` has passed, and the plugin may skip it after that
+pub fn with_deadline(msg: impl Into<ServerMessage>, deadline_ms: i64) -> ServerMessage {
+    let mut msg = msg.into();
+    msg.timing.get_or_insert_with(Default::default).deadline_ms = deadline_ms;
+    msg
+}
+
+/// Whether `msg`'s deadline (local clock) is before `now_ms`
+pub fn is_expired(msg: &ServerMessage, now_ms: i64) -> bool {
+    msg.timing
+        .as_ref()
+        .is_some_and(|t| t.deadline_ms != 0 && now_ms > t.deadline_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{AudioChunk, SpeakDirective};
+
+    fn timing(sent_at_ms: i64, echo: Option<(i64, i64)>) -> MessageTiming {
+        let (echo_sent_at_ms, echo_received_at_ms) = echo.unwrap_or_default();
+        MessageTiming {
+            sent_at_ms,
+            echo_sent_at_ms,
+            echo_received_at_ms,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_clock_sync_and_delays() {
+        // The plugin's clock runs 1000ms ahead; every hop takes 20ms
+        let mut tracker = LatencyTracker::new(LatencyBudgets::default());
+        let hello = timing(11_000, None);
+        let arrival = tracker.on_received(Some(&hello), "Hello", 10_020);
+        assert_eq!(arrival.delay_ms, None);
+
+        let mut ack = ServerMessage::from(SpeakDirective::default());
+        tracker.stamp(&mut ack, 10_030);
+        let stamp = ack.timing.unwrap();
+        assert_eq!(
+            (stamp.echo_sent_at_ms, stamp.echo_received_at_ms),
+            (11_000, 10_020)
+        );
+
+        // The plugin got it at 11_050 its time and answers 5ms later
+        let tick = timing(11_055, Some((10_030, 11_050)));
+        let arrival = tracker.on_received(Some(&tick), "WorldTick", 10_075);
+        assert_eq!(tracker.offset_ms(), 1_000);
+        assert_eq!(tracker.clock().round_trip_ms(), Some(40));
+        assert_eq!(arrival.delay_ms, Some(20));
+        assert!(!arrival.over_budget);
+        assert_eq!(tracker.to_local(11_075), 10_075);
+
+        // A slow chat blows its 250ms budget
+        let chat = timing(11_100, None);
+        let arrival = tracker.on_received(Some(&chat), "ChatObservation", 10_400);
+        assert_eq!(arrival.delay_ms, Some(300));
+        assert!(arrival.over_budget);
+
+        let stats = tracker.stats();
+        let types: Vec<_> = stats
+            .iter()
+            .map(|s| (s.message_type.as_str(), s.inbound))
+            .collect();
+        assert_eq!(
+            types,
+            [
+                ("chat_observation", true),
+                ("world_tick", true),
+                ("speak_directive", false),
+            ]
+        );
+        assert_eq!(stats[0].over_budget, 1);
+        assert_eq!(stats[0].budget_ms, 250.0);
+        assert_eq!(stats[2].max_ms, 20.0);
+    }
+
+    #[test]
+    fn test_deadlines() {
+        let mut tracker = LatencyTracker::default();
+        let late = MessageTiming {
+            sent_at_ms: 1_000,
+            deadline_ms: 1_500,
+            ..Default::default()
+        };
+        assert!(
+            tracker
+                .on_received(Some(&late), "ChatObservation", 2_000)
+                .expired
+        );
+        assert!(!tracker.on_received(None, "ChatObservation", 2_000).expired);
+
+        let chunk = with_deadline(AudioChunk::default(), 5_000);
+        assert!(!is_expired(&chunk, 5_000));
+        assert!(is_expired(&chunk, 5_001));
+        tracker.on_expired(&chunk);
+        let mut stamped = chunk.clone();
+        tracker.stamp(&mut stamped, 4_000);
+        assert_eq!(stamped.timing.unwrap().deadline_ms, 5_000);
+
+        let expired: Vec<_> = tracker
+            .stats()
+            .into_iter()
+            .map(|s| (s.message_type, s.expired))
+            .collect();
+        assert_eq!(
+            expired,
+            [
+                ("chat_observation".to_string(), 1),
+                ("audio_chunk".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_histogram_percentiles() {
+        let mut histogram = LatencyHistogram::default();
+        for delay in 1..=100 {
+            histogram.record(delay);
+        }
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.percentile(0.5), 50);
+        assert_eq!(histogram.percentile(0.99), 100);
+        histogram.record(45_000);
+        assert_eq!(histogram.percentile(1.0), 45_000);
+    }
+}
@@ -0,0 +1,397 @@
+//! NPC ownership across daemon replicas.
+//!
+//! One daemon process is a single point of failure for every NPC it
+//! drives. Several replicas can instead serve the same servers, each
+//! plugin connected to all of them, as long as every NPC still has exactly
+//! one brain: a replica only acts for NPCs it holds a [`Lease`] on.
+//!
+//! [`LeaseManager`] claims the NPCs its replica sees, renews its leases
+//! well before they run out and takes over NPCs whose owner stopped
+//! renewing, so the NPCs of a replica that dies move to the others within
+//! one `ttl_ms`. A replica that cannot reach the store stops acting once
+//! its leases run out, before anyone else may take them.
+//!
+//! Leases live in a [`LeaseStore`] all replicas share: `RedisLeases`
+//! (feature `lease-redis`) across processes, or [`MemoryLeases`] for
+//! replicas in one process and tests. Every change of owner bumps the
+//! lease's `epoch`, a fencing token that tells a stale owner apart.
+//!
+//! Stores block on the network. Run [`LeaseManager::sync`] on a thread of
+//! its own rather than on a connection's task, and hand its
+//! [`LeaseChanges`] and [`leases`](LeaseManager::leases) to the
+//! connections.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "lease-redis")]
+use std::time::Duration;
+
+/// Who drives an NPC, until when
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    /// NPC the lease is for
+    pub npc_id: String,
+    /// Replica holding it
+    pub owner: String,
+    /// Incremented whenever the NPC changes owner
+    pub epoch: u64,
+    /// When it runs out unless renewed (Unix ms)
+    pub expires_at_ms: i64,
+}
+
+/// Lease table shared by the replicas
+pub trait LeaseStore: Send {
+    /// Error of the underlying store
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Take `npc_id` for `owner` if it is free or expired, or extend it by
+    /// `ttl_ms` if `owner` holds it already. Returns the lease as it now
+    /// stands, which belongs to another replica if that one holds it.
+    fn acquire(
+        &mut self,
+        npc_id: &str,
+        owner: &str,
+        ttl_ms: i64,
+        now_ms: i64,
+    ) -> Result<Lease, Self::Error>;
+
+    /// Give up `npc_id` if `owner` holds it
+    fn release(&mut self, npc_id: &str, owner: &str) -> Result<(), Self::Error>;
+}
+
+/// Leases in memory; clones share the table
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLeases {
+    leases: Arc<Mutex<HashMap<String, Lease>>>,
+    epochs: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl LeaseStore for MemoryLeases {
+    type Error = Infallible;
+
+    fn acquire(
+        &mut self,
+        npc_id: &str,
+        owner: &str,
+        ttl_ms: i64,
+        now_ms: i64,
+    ) -> Result<Lease, Infallible> {
+        let mut leases = self.leases.lock().unwrap();
+        if let Some(lease) = leases.get_mut(npc_id) {
+            if lease.owner == owner {
+                lease.expires_at_ms = now_ms + ttl_ms;
+            }
+            if lease.owner == owner || lease.expires_at_ms > now_ms {
+                return Ok(lease.clone());
+            }
+        }
+        let mut epochs = self.epochs.lock().unwrap();
+        let epoch = epochs.entry(npc_id.to_string()).or_default();
+        *epoch += 1;
+        let lease = Lease {
+            npc_id: npc_id.to_string(),
+            owner: owner.to_string(),
+            epoch: *epoch,
+            expires_at_ms: now_ms + ttl_ms,
+        };
+        leases.insert(npc_id.to_string(), lease.clone());
+        Ok(lease)
+    }
+
+    fn release(&mut self, npc_id: &str, owner: &str) -> Result<(), Infallible> {
+        let mut leases = self.leases.lock().unwrap();
+        if leases.get(npc_id).is_some_and(|lease| lease.owner == owner) {
+            leases.remove(npc_id);
+        }
+        Ok(())
+    }
+}
+
+/// Leases in Redis, one key per NPC that expires with the lease
+#[cfg(feature = "lease-redis")]
+pub struct RedisLeases {
+    connection: redis::Connection,
+    prefix: String,
+}
+
+/// Value of a lease key: "<owner>\n<epoch>"
+#[cfg(feature = "lease-redis")]
+const ACQUIRE_SCRIPT: &str = r"
+local held = redis.call('GET', KEYS[1])
+if held and string.sub(held, 1, #ARGV[1] + 1) == ARGV[1] .. '\n' then
+  redis.call('PEXPIRE', KEYS[1], ARGV[2])
+elseif not held then
+  held = ARGV[1] .. '\n' .. redis.call('INCR', KEYS[2])
+  redis.call('SET', KEYS[1], held, 'PX', ARGV[2])
+end
+return {held, redis.call('PTTL', KEYS[1])}
+";
+
+#[cfg(feature = "lease-redis")]
+const RELEASE_SCRIPT: &str = r"
+local held = redis.call('GET', KEYS[1])
+if held and string.sub(held, 1, #ARGV[1] + 1) == ARGV[1] .. '\n' then
+  redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+#[cfg(feature = "lease-redis")]
+impl RedisLeases {
+    /// Connect to `url` (e.g. `redis://127.0.0.1/`); keys start with
+    /// `prefix`, so several societies can share one Redis. Connecting and
+    /// every command give up after `timeout`, so an unreachable Redis
+    /// fails a sync instead of hanging it.
+    pub fn open(url: &str, prefix: &str, timeout: Duration) -> redis::RedisResult<Self> {
+        let connection = redis::Client::open(url)?.get_connection_with_timeout(timeout)?;
+        connection.set_read_timeout(Some(timeout))?;
+        connection.set_write_timeout(Some(timeout))?;
+        Ok(Self {
+            connection,
+            prefix: prefix.to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "lease-redis")]
+impl LeaseStore for RedisLeases {
+    type Error = redis::RedisError;
+
+    fn acquire(
+        &mut self,
+        npc_id: &str,
+        owner: &str,
+        ttl_ms: i64,
+        now_ms: i64,
+    ) -> redis::RedisResult<Lease> {
+        // Redis keeps time, so expiry does not depend on replica clocks
+        let (held, pttl): (String, i64) = redis::Script::new(ACQUIRE_SCRIPT)
+            .key(format!("{}:lease:{}", self.prefix, npc_id))
+            .key(format!("{}:epoch:{}", self.prefix, npc_id))
+            .arg(owner)
+            .arg(ttl_ms)
+            .invoke(&mut self.connection)?;
+        let (holder, epoch) = held
+            .rsplit_once('\n')
+            .and_then(|(holder, epoch)| Some((holder, epoch.parse().ok()?)))
+            .ok_or_else(|| {
+                redis::RedisError::from((
+                    redis::ErrorKind::TypeError,
+                    "malformed lease",
+                    held.clone(),
+                ))
+            })?;
+        Ok(Lease {
+            npc_id: npc_id.to_string(),
+            owner: holder.to_string(),
+            epoch,
+            expires_at_ms: now_ms + pttl.max(0),
+        })
+    }
+
+    fn release(&mut self, npc_id: &str, owner: &str) -> redis::RedisResult<()> {
+        redis::Script::new(RELEASE_SCRIPT)
+            .key(format!("{}:lease:{}", self.prefix, npc_id))
+            .arg(owner)
+            .invoke(&mut self.connection)
+    }
+}
+
+/// How long leases last and how often they are renewed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeaseConfig {
+    /// Lifetime of a lease; also how long NPCs of a dead replica go
+    /// without a brain, and how long an NPC unseen is kept
+    pub ttl_ms: i64,
+    /// Time between renewals; well below `ttl_ms` so one slow renewal does
+    /// not lose the NPCs
+    pub renew_every_ms: i64,
+}
+
+impl Default for LeaseConfig {
+    fn default() -> Self {
+        Self {
+            ttl_ms: 10_000,
+            renew_every_ms: 3_000,
+        }
+    }
+}
+
+/// NPCs whose owner changed in a [`LeaseManager::sync`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LeaseChanges {
+    /// NPCs this replica drives from now on
+    pub gained: Vec<String>,
+    /// NPCs this replica must stop driving
+    pub lost: Vec<String>,
+}
+
+/// One replica's leases
+#[derive(Debug)]
+pub struct LeaseManager<S> {
+    replica_id: String,
+    config: LeaseConfig,
+    store: S,
+    owned: HashMap<String, Lease>,
+    /// When each NPC was last reported by a plugin
+    seen: HashMap<String, i64>,
+    last_sync_ms: Option<i64>,
+}
+
+impl<S: LeaseStore> LeaseManager<S> {
+    /// A manager claiming NPCs for `replica_id`, which must be unique
+    /// among the replicas
+    pub fn new(replica_id: &str, config: LeaseConfig, store: S) -> Self {
+        Self {
+            replica_id: replica_id.to_string(),
+            config,
+            store,
+            owned: HashMap::new(),
+            seen: HashMap::new(),
+            last_sync_ms: None,
+        }
+    }
+
+    /// This replica's id
+    pub fn replica_id(&self) -> &str {
+        &self.replica_id
+    }
+
+    /// Whether this replica may drive `npc_id` at `now_ms`
+    pub fn owns(&self, npc_id: &str, now_ms: i64) -> bool {
+        self.owned
+            .get(npc_id)
+            .is_some_and(|lease| lease.expires_at_ms > now_ms)
+    }
+
+    /// The lease this replica holds on `npc_id`
+    pub fn lease(&self, npc_id: &str) -> Option<&Lease> {
+        self.owned.get(npc_id)
+    }
+
+    /// Every lease this replica holds, including ones that ran out since
+    /// the last sync
+    pub fn leases(&self) -> impl Iterator<Item = &Lease> {
+        self.owned.values()
+    }
+
+    /// Note the NPCs a plugin reported and, at most every
+    /// `renew_every_ms`, claim or renew a lease on every NPC seen within
+    /// `ttl_ms`. NPCs not seen for longer are no longer renewed.
+    pub fn sync<'a>(
+        &mut self,
+        seen: impl IntoIterator<Item = &'a str>,
+        now_ms: i64,
+    ) -> Result<LeaseChanges, S::Error> {
+        for npc_id in seen {
+            self.seen.insert(npc_id.to_string(), now_ms);
+        }
+        let mut changes = LeaseChanges::default();
+        if self
+            .last_sync_ms
+            .is_some_and(|last| now_ms - last < self.config.renew_every_ms)
+        {
+            return Ok(changes);
+        }
+        self.last_sync_ms = Some(now_ms);
+
+        let ttl_ms = self.config.ttl_ms;
+        self.seen.retain(|_, at| now_ms - *at < ttl_ms);
+        let stale: Vec<String> = self
+            .owned
+            .keys()
+            .filter(|npc_id| !self.seen.contains_key(*npc_id))
+            .cloned()
+            .collect();
+        for npc_id in stale {
+            self.owned.remove(&npc_id);
+            changes.lost.push(npc_id);
+        }
+
+        let mut npc_ids: Vec<&String> = self.seen.keys().collect();
+        npc_ids.sort();
+        for npc_id in npc_ids {
+            let lease = self
+                .store
+                .acquire(npc_id, &self.replica_id, ttl_ms, now_ms)?;
+            let was_owned = self.owned.contains_key(npc_id);
+            if lease.owner == self.replica_id {
+                if !was_owned {
+                    changes.gained.push(npc_id.clone());
+                }
+                self.owned.insert(npc_id.clone(), lease);
+            } else if was_owned {
+                self.owned.remove(npc_id);
+                changes.lost.push(npc_id.clone());
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Give up every lease, e.g. on shutdown, so other replicas take the
+    /// NPCs over right away instead of after `ttl_ms`
+    pub fn release_all(&mut self) -> Result<Vec<String>, S::Error> {
+        let mut released = Vec::new();
+        for (npc_id, _) in self.owned.drain() {
+            self.store.release(&npc_id, &self.replica_id)?;
+            released.push(npc_id);
+        }
+        released.sort();
+        Ok(released)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failover() {
+        let leases = MemoryLeases::default();
+        let config = LeaseConfig::default();
+        let mut a = LeaseManager::new("a", config, leases.clone());
+        let mut b = LeaseManager::new("b", config, leases);
+
+        let changes = a.sync(["miner", "farmer"], 0).unwrap();
+        assert_eq!(changes.gained, ["farmer", "miner"]);
+        assert_eq!(a.leases().count(), 2);
+        assert!(b.sync(["miner", "farmer"], 100).unwrap().gained.is_empty());
+        assert!(!b.owns("miner", 100));
+        assert_eq!(b.leases().count(), 0);
+
+        // Renewals within the interval are skipped
+        a.sync(["miner", "farmer"], 1_000).unwrap();
+        assert_eq!(a.lease("miner").unwrap().expires_at_ms, 10_000);
+        a.sync(["miner", "farmer"], 3_000).unwrap();
+        assert_eq!(a.lease("miner").unwrap().expires_at_ms, 13_000);
+
+        // a dies: b takes over once the leases run out, and a would know
+        // its leases are no longer good
+        assert!(b.sync(["miner"], 12_000).unwrap().gained.is_empty());
+        let changes = b.sync(["miner", "farmer"], 15_000).unwrap();
+        assert_eq!(changes.gained, ["farmer", "miner"]);
+        assert_eq!(b.lease("miner").unwrap().epoch, 2);
+        assert!(!a.owns("miner", 15_000));
+
+        // a comes back and learns it lost them
+        let changes = a.sync(["miner", "farmer"], 15_500).unwrap();
+        assert_eq!(changes.lost, ["farmer", "miner"]);
+    }
+
+    #[test]
+    fn test_unseen_and_release() {
+        let leases = MemoryLeases::default();
+        let mut a = LeaseManager::new("a", LeaseConfig::default(), leases.clone());
+        let mut b = LeaseManager::new("b", LeaseConfig::default(), leases);
+        a.sync(["miner", "farmer"], 0).unwrap();
+
+        // The farmer left the server; its lease is not renewed
+        a.sync(["miner"], 6_000).unwrap();
+        let changes = a.sync(["miner"], 10_000).unwrap();
+        assert_eq!(changes.lost, ["farmer"]);
+
+        assert_eq!(a.release_all().unwrap(), ["miner"]);
+        assert_eq!(b.sync(["miner"], 10_100).unwrap().gained, ["miner"]);
+    }
+}
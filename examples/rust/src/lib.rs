@@ -0,0 +1,135 @@
+//! Reusable helpers for NPC Society daemons.
+//!
+//! The example server in `main.rs` uses most of these; they have no
+//! dependency on the server itself so they can be copied into a real daemon.
+
+// The generated proto code, from the npc-society-proto crate
+pub mod npc_society {
+    pub use npc_society_proto::v1;
+}
+
+pub mod actor;
+pub mod agent;
+#[cfg(feature = "voice")]
+pub mod asr;
+#[cfg(feature = "audio")]
+pub mod assets;
+#[cfg(feature = "audio")]
+pub mod audio;
+pub mod behavior;
+pub mod block_pattern;
+pub mod budget;
+pub mod builders;
+pub mod chat;
+pub mod chunking;
+pub mod clock;
+pub mod codegen;
+pub mod commands;
+pub mod compat;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod config;
+pub mod context;
+pub mod connection;
+pub mod consent;
+pub mod conversation;
+pub mod crafting;
+#[cfg(feature = "dashboard")]
+pub mod dashboard;
+pub mod dimension;
+pub mod display;
+pub mod effects;
+pub mod emotion;
+pub mod equipment;
+pub mod events;
+pub mod formation;
+pub mod fsm;
+pub mod game_event;
+pub mod geom;
+pub mod guard;
+pub mod guardrail;
+#[cfg(feature = "simulator")]
+pub mod golden;
+pub mod http2;
+pub mod husbandry;
+pub mod ids;
+#[cfg(feature = "voice")]
+pub mod jitter;
+pub mod landmarks;
+#[cfg(feature = "metrics")]
+pub mod latency;
+pub mod lease;
+pub mod lifecycle;
+#[cfg(feature = "simulator")]
+pub mod loadgen;
+pub mod locale;
+#[cfg(feature = "voice")]
+pub mod mixer;
+pub mod moderation;
+pub mod movement;
+pub mod music;
+pub mod npc_state;
+pub mod outbound;
+#[cfg(feature = "audio")]
+pub mod pacing;
+pub mod path;
+#[cfg(feature = "planner")]
+pub mod planner;
+#[cfg(feature = "persistence")]
+pub mod persistence;
+pub mod players;
+pub mod policy;
+pub mod pool;
+pub mod probe;
+#[cfg(feature = "npc-profiles")]
+pub mod profiles;
+pub mod prompts;
+pub mod region;
+#[cfg(feature = "audio")]
+pub mod reply_cache;
+#[cfg(feature = "persistence")]
+pub mod replay;
+pub mod reputation;
+pub mod retry;
+pub mod routing;
+pub mod sanitize;
+#[cfg(feature = "scenario")]
+pub mod scenario;
+pub mod schedule;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod servers;
+pub mod shop;
+#[cfg(feature = "simulator")]
+pub mod sim;
+pub mod speech_rate;
+pub mod stations;
+pub mod storage;
+#[cfg(feature = "metrics")]
+pub mod tap;
+pub mod tasks;
+#[cfg(feature = "tensors")]
+pub mod tensors;
+pub mod throttle;
+pub mod time;
+#[cfg(feature = "simulator")]
+pub mod training;
+#[cfg(feature = "metrics")]
+pub mod top;
+#[cfg(feature = "persistence")]
+pub mod transcripts;
+pub mod transfer;
+#[cfg(feature = "audio")]
+pub mod tts;
+pub mod types;
+pub mod utility;
+#[cfg(feature = "voice")]
+pub mod vad;
+pub mod validate;
+#[cfg(feature = "voice")]
+pub mod voice_commands;
+pub mod watch;
+pub mod watchdog;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+pub mod world_model;
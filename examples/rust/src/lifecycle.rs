@@ -0,0 +1,257 @@
+//! Startup and shutdown of a daemon.
+//!
+//! A [`Lifecycle`] moves through [`Stage`]s once: `Starting` while state is
+//! rebuilt and the listener binds, `Ready` once plugins may connect,
+//! `Draining` after SIGTERM or Ctrl-C ([`shutdown_signal`]) while
+//! directives already sent finish, and `Stopped` when the streams close.
+//! [`Lifecycle::report_health`] mirrors the stage to the standard gRPC
+//! health service, so a load balancer or orchestrator stops routing plugins
+//! to a replica as soon as it starts draining.
+//!
+//! Draining refuses new directives and new Connect streams but keeps the
+//! existing streams open, so ActionResults for what is in flight still
+//! arrive. [`Lifecycle::drain`] waits for those until a grace period runs
+//! out; streams wrapped with [`Lifecycle::until_stopped`] then end, which
+//! lets the gRPC server shut down instead of waiting on them forever.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tokio_stream::wrappers::WatchStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::server::NamedService;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+
+/// How often [`Lifecycle::drain`] checks what is still in flight
+const DRAIN_POLL: Duration = Duration::from_millis(100);
+
+/// Where a daemon is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// Rebuilding state and binding the listener
+    Starting,
+    /// Serving plugins
+    Ready,
+    /// Letting in-flight work finish; nothing new is started
+    Draining,
+    /// Streams closed
+    Stopped,
+}
+
+/// Outcome of [`Lifecycle::drain`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrainReport {
+    /// How long draining took
+    pub waited: Duration,
+    /// Work still in flight when it ended (0 = drained cleanly)
+    pub in_flight: usize,
+}
+
+/// Stage of a daemon, shared by everything that has to react to it
+#[derive(Debug, Clone)]
+pub struct Lifecycle {
+    stage: Arc<watch::Sender<Stage>>,
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lifecycle {
+    /// A daemon that is starting
+    pub fn new() -> Self {
+        Self {
+            stage: Arc::new(watch::Sender::new(Stage::Starting)),
+        }
+    }
+
+    /// Current stage
+    pub fn stage(&self) -> Stage {
+        *self.stage.borrow()
+    }
+
+    /// Whether new work should be refused
+    pub fn is_draining(&self) -> bool {
+        self.stage() >= Stage::Draining
+    }
+
+    /// Follow the stage as it changes
+    pub fn subscribe(&self) -> watch::Receiver<Stage> {
+        self.stage.subscribe()
+    }
+
+    /// Move on to `stage`; stages never go back
+    fn advance(&self, stage: Stage) {
+        self.stage.send_if_modified(|current| {
+            let advanced = stage > *current;
+            if advanced {
+                *current = stage;
+            }
+            advanced
+        });
+    }
+
+    /// Startup is done: accept plugins
+    pub fn ready(&self) {
+        self.advance(Stage::Ready);
+    }
+
+    /// Start draining without waiting for it
+    pub fn begin_drain(&self) {
+        self.advance(Stage::Draining);
+    }
+
+    /// Close the streams
+    pub fn stop(&self) {
+        self.advance(Stage::Stopped);
+    }
+
+    /// Wait until the stage is at least `stage`
+    pub async fn reached(&self, stage: Stage) {
+        let mut receiver = self.subscribe();
+        // The sender lives in self, so this cannot fail
+        let _ = receiver.wait_for(|current| *current >= stage).await;
+    }
+
+    /// Drain: refuse new work, then wait until `in_flight` reports nothing
+    /// left or `grace` has passed, and stop
+    pub async fn drain(
+        &self,
+        grace: Duration,
+        mut in_flight: impl FnMut() -> usize,
+    ) -> DrainReport {
+        self.begin_drain();
+        let start = Instant::now();
+        let mut remaining = in_flight();
+        while remaining > 0 && start.elapsed() < grace {
+            tokio::time::sleep(DRAIN_POLL.min(grace - start.elapsed())).await;
+            remaining = in_flight();
+        }
+        self.stop();
+        DrainReport {
+            waited: start.elapsed(),
+            in_flight: remaining,
+        }
+    }
+
+    /// `stream`, ending once the daemon has stopped
+    pub fn until_stopped<S>(&self, stream: S) -> impl Stream<Item = S::Item>
+    where
+        S: Stream + Send + 'static,
+        S::Item: Send,
+    {
+        let stopped = WatchStream::new(self.subscribe())
+            .filter(|stage| *stage == Stage::Stopped)
+            .map(|_| None);
+        stream
+            .map(Some)
+            .merge(stopped)
+            .take_while(Option::is_some)
+            .map(|item| item.expect("taken while Some"))
+    }
+
+    /// Report service `S` as SERVING while ready and NOT_SERVING otherwise
+    /// on `reporter`'s health service, until the daemon stops
+    pub fn report_health<S: NamedService>(
+        &self,
+        mut reporter: HealthReporter,
+    ) -> tokio::task::JoinHandle<()> {
+        let mut receiver = self.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let stage = *receiver.borrow_and_update();
+                reporter
+                    .set_service_status(S::NAME, serving_status(stage))
+                    .await;
+                if stage == Stage::Stopped || receiver.changed().await.is_err() {
+                    break;
+                }
+            }
+        })
+    }
+}
+
+/// Resolves on SIGTERM (Unix) or Ctrl-C
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+                return;
+            }
+            Err(error) => tracing::warn!(%error, "Cannot listen for SIGTERM, only Ctrl-C"),
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Health status of `stage` as the gRPC health service reports it
+pub fn serving_status(stage: Stage) -> ServingStatus {
+    match stage {
+        Stage::Ready => ServingStatus::Serving,
+        _ => ServingStatus::NotServing,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain_waits_for_in_flight() {
+        let lifecycle = Lifecycle::new();
+        lifecycle.ready();
+        assert!(!lifecycle.is_draining());
+
+        let in_flight = Arc::new(AtomicUsize::new(2));
+        let finishing = in_flight.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            finishing.store(0, Ordering::Relaxed);
+        });
+        let report = lifecycle
+            .drain(Duration::from_secs(5), || in_flight.load(Ordering::Relaxed))
+            .await;
+        assert_eq!(report.in_flight, 0);
+        assert!(report.waited < Duration::from_secs(1));
+        assert_eq!(lifecycle.stage(), Stage::Stopped);
+
+        // Stages never go back
+        lifecycle.ready();
+        assert_eq!(lifecycle.stage(), Stage::Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_drain_gives_up_after_grace() {
+        let lifecycle = Lifecycle::new();
+        let report = lifecycle.drain(Duration::from_millis(200), || 3).await;
+        assert_eq!(report.in_flight, 3);
+        assert!(report.waited >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_streams_end_when_stopped() {
+        let lifecycle = Lifecycle::new();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut stream = Box::pin(
+            lifecycle.until_stopped(tokio_stream::wrappers::UnboundedReceiverStream::new(rx)),
+        );
+        tx.send(1).unwrap();
+        assert_eq!(stream.next().await, Some(1));
+        lifecycle.stop();
+        assert_eq!(stream.next().await, None);
+        assert_eq!(serving_status(lifecycle.stage()), ServingStatus::NotServing);
+    }
+}
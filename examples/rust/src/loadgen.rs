@@ -0,0 +1,376 @@
+//! Synthetic plugin traffic for load tests.
+//!
+//! [`SyntheticServer`] plays one Minecraft server: a Hello, WorldTicks
+//! with `npcs` NPCs wandering around `players` players, chat aimed at
+//! random NPCs and a voice frame every 20 ms per speaking player. The
+//! `loadgen` binary streams that from many servers at once and measures
+//! with [`LatencyRecorder`] how long the daemon takes to answer each chat.
+//!
+//! Everything is deterministic for a given seed, so two runs against two
+//! daemon builds send the same traffic.
+
+use std::collections::{HashMap, VecDeque};
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::npc_society::v1::{
+    client_message::Message as ClientMsg, ChatObservation, ClientMessage, Hello, NpcSnapshot,
+    PcmFormat, PlayerSnapshot, Position, VoicePcmFrame, WorldTick,
+};
+
+/// Sample rate of the synthetic voice frames
+pub const VOICE_SAMPLE_RATE_HZ: i32 = 48_000;
+/// Length of one voice frame, as Simple Voice Chat sends them
+pub const VOICE_FRAME_MS: u64 = 20;
+
+/// What one synthetic server sends
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoadProfile {
+    /// NPCs in every WorldTick
+    pub npcs: usize,
+    /// Players in every WorldTick
+    pub players: usize,
+    /// WorldTicks per second
+    pub tick_hz: f64,
+    /// ChatObservations per minute, spread over the NPCs
+    pub chats_per_minute: f64,
+    /// Players streaming voice frames at any time
+    pub voice_streams: usize,
+}
+
+impl Default for LoadProfile {
+    /// A busy server: 100 NPCs, 20 players, 10 ticks a second, a chat
+    /// every two seconds and two players talking
+    fn default() -> Self {
+        Self {
+            npcs: 100,
+            players: 20,
+            tick_hz: 10.0,
+            chats_per_minute: 30.0,
+            voice_streams: 2,
+        }
+    }
+}
+
+impl LoadProfile {
+    /// Time between WorldTicks
+    pub fn tick_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.tick_hz.max(0.001))
+    }
+
+    /// Time between chats, `None` for no chat
+    pub fn chat_interval(&self) -> Option<Duration> {
+        (self.chats_per_minute > 0.0).then(|| Duration::from_secs_f64(60.0 / self.chats_per_minute))
+    }
+}
+
+/// One fake plugin's messages
+#[derive(Debug)]
+pub struct SyntheticServer {
+    server_id: String,
+    profile: LoadProfile,
+    rng: u64,
+    server_tick: i64,
+    voice_sequence: u64,
+    /// One frame of tone, shared by every voice frame
+    voice_pcm: Bytes,
+}
+
+impl SyntheticServer {
+    /// A server named `server_id`; `seed` picks its chat targets
+    pub fn new(server_id: &str, profile: LoadProfile, seed: u64) -> Self {
+        let samples = (VOICE_SAMPLE_RATE_HZ as u64 * VOICE_FRAME_MS / 1_000) as usize;
+        // A 220 Hz tone loud enough that voice activity detection hears
+        // speech, so the daemon's whole voice path is exercised
+        let voice_pcm = (0..samples)
+            .flat_map(|i| {
+                let t = i as f32 / VOICE_SAMPLE_RATE_HZ as f32;
+                (((TAU * 220.0 * t).sin() * 8_000.0) as i16).to_le_bytes()
+            })
+            .collect::<Vec<u8>>();
+        Self {
+            server_id: server_id.to_string(),
+            profile,
+            rng: seed.max(1),
+            server_tick: 0,
+            voice_sequence: 0,
+            voice_pcm: Bytes::from(voice_pcm),
+        }
+    }
+
+    /// This server's id, as in its Hello
+    pub fn server_id(&self) -> &str {
+        &self.server_id
+    }
+
+    /// Id of NPC `i`, unique across servers
+    pub fn npc_id(&self, i: usize) -> String {
+        format!("{}_npc_{}", self.server_id, i)
+    }
+
+    fn player_uuid(&self, i: usize) -> String {
+        format!("{}-player-{}", self.server_id, i)
+    }
+
+    /// xorshift64: fast, and the same sequence for the same seed
+    fn next(&mut self, bound: usize) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng % bound.max(1) as u64) as usize
+    }
+
+    /// The handshake
+    pub fn hello(&self) -> ClientMessage {
+        message(ClientMsg::Hello(Hello {
+            plugin_version: "loadgen".to_string(),
+            protocol_version: "1".to_string(),
+            server_id: self.server_id.clone(),
+            minecraft_version: "1.21.1".to_string(),
+            voice_available: self.profile.voice_streams > 0,
+            server_name: self.server_id.clone(),
+            supported_audio_formats: vec![PcmFormat::S16le as i32],
+            ..Default::default()
+        }))
+    }
+
+    /// The next WorldTick; NPCs walk in circles so positions change
+    pub fn world_tick(&mut self, now_ms: i64) -> ClientMessage {
+        self.server_tick += (20.0 / self.profile.tick_hz.max(0.001)).round().max(1.0) as i64;
+        let phase = self.server_tick as f64 / 200.0;
+        let position = |i: usize, radius: f64| {
+            let angle = phase + i as f64;
+            Some(Position {
+                world: "world".to_string(),
+                x: (i % 50) as f64 * 16.0 + angle.cos() * radius,
+                y: 64.0,
+                z: (i / 50) as f64 * 16.0 + angle.sin() * radius,
+                ..Default::default()
+            })
+        };
+        let npcs = (0..self.profile.npcs)
+            .map(|i| NpcSnapshot {
+                npc_id: self.npc_id(i),
+                position: position(i, 4.0),
+                health_norm: 1.0,
+                hunger_norm: 0.8,
+                ..Default::default()
+            })
+            .collect();
+        let nearby_players = (0..self.profile.players)
+            .map(|i| PlayerSnapshot {
+                player_uuid: self.player_uuid(i),
+                player_name: format!("Player{}", i),
+                position: position(i, 8.0),
+                health_norm: 1.0,
+                game_mode: "survival".to_string(),
+                ..Default::default()
+            })
+            .collect();
+        message(ClientMsg::WorldTick(WorldTick {
+            server_tick: self.server_tick,
+            timestamp_ms: now_ms,
+            npcs,
+            nearby_players,
+            mspt: 25.0,
+            ..Default::default()
+        }))
+    }
+
+    /// A chat from a random player to a random NPC
+    pub fn chat(&mut self, now_ms: i64) -> ClientMessage {
+        let npc = self.next(self.profile.npcs);
+        let player = self.next(self.profile.players);
+        message(ClientMsg::ChatObservation(ChatObservation {
+            npc_id: self.npc_id(npc),
+            player_uuid: self.player_uuid(player),
+            player_name: format!("Player{}", player),
+            message: "Have you found any diamonds?".to_string(),
+            timestamp_ms: now_ms,
+            distance: 3.0,
+            player_locale: "en_us".to_string(),
+        }))
+    }
+
+    /// One frame per speaking player, each talking to the NPC with its
+    /// number
+    pub fn voice_frames(&mut self, now_ms: i64) -> Vec<ClientMessage> {
+        self.voice_sequence += 1;
+        (0..self.profile.voice_streams.min(self.profile.players))
+            .map(|i| {
+                message(ClientMsg::VoicePcmFrame(VoicePcmFrame {
+                    npc_id: self.npc_id(i % self.profile.npcs.max(1)),
+                    player_uuid: self.player_uuid(i),
+                    pcm_data: self.voice_pcm.clone(),
+                    sequence: self.voice_sequence,
+                    timestamp_ms: now_ms,
+                    sample_rate_hz: VOICE_SAMPLE_RATE_HZ,
+                    format: PcmFormat::S16le as i32,
+                }))
+            })
+            .collect()
+    }
+}
+
+fn message(message: ClientMsg) -> ClientMessage {
+    ClientMessage {
+        message: Some(message),
+        ..Default::default()
+    }
+}
+
+/// Matches replies to the requests that caused them, per NPC, first in
+/// first out
+#[derive(Debug)]
+pub struct RoundTrips<T> {
+    waiting: HashMap<String, VecDeque<T>>,
+}
+
+impl<T> Default for RoundTrips<T> {
+    fn default() -> Self {
+        Self {
+            waiting: HashMap::new(),
+        }
+    }
+}
+
+impl<T> RoundTrips<T> {
+    /// A request for `npc_id` went out at `sent`
+    pub fn sent(&mut self, npc_id: &str, sent: T) {
+        self.waiting
+            .entry(npc_id.to_string())
+            .or_default()
+            .push_back(sent);
+    }
+
+    /// A reply for `npc_id` arrived; when its oldest request went out
+    pub fn answered(&mut self, npc_id: &str) -> Option<T> {
+        let waiting = self.waiting.get_mut(npc_id)?;
+        let sent = waiting.pop_front();
+        if waiting.is_empty() {
+            self.waiting.remove(npc_id);
+        }
+        sent
+    }
+
+    /// Requests still without a reply
+    pub fn unanswered(&self) -> usize {
+        self.waiting.values().map(VecDeque::len).sum()
+    }
+}
+
+/// Latency percentiles of a run
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencySummary {
+    /// Samples recorded
+    pub count: usize,
+    /// Median
+    pub p50: Duration,
+    /// 90th percentile
+    pub p90: Duration,
+    /// 99th percentile
+    pub p99: Duration,
+    /// Slowest sample
+    pub max: Duration,
+}
+
+/// Collects latency samples
+#[derive(Debug, Default)]
+pub struct LatencyRecorder {
+    samples: Vec<Duration>,
+}
+
+impl LatencyRecorder {
+    /// Add a sample
+    pub fn record(&mut self, latency: Duration) {
+        self.samples.push(latency);
+    }
+
+    /// Add every sample of `other`, e.g. to sum up several connections
+    pub fn merge(&mut self, other: &LatencyRecorder) {
+        self.samples.extend_from_slice(&other.samples);
+    }
+
+    /// Percentiles of the samples so far (nearest rank)
+    pub fn summary(&self) -> LatencySummary {
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        let Some(&max) = sorted.last() else {
+            return LatencySummary::default();
+        };
+        let rank =
+            |p: f64| sorted[((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len()) - 1];
+        LatencySummary {
+            count: sorted.len(),
+            p50: rank(0.50),
+            p90: rank(0.90),
+            p99: rank(0.99),
+            max,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_synthetic_server() {
+        let profile = LoadProfile {
+            npcs: 3,
+            players: 2,
+            voice_streams: 1,
+            ..Default::default()
+        };
+        let mut server = SyntheticServer::new("survival", profile, 7);
+        let Some(ClientMsg::WorldTick(tick)) = server.world_tick(1_000).message else {
+            panic!("Expected WorldTick");
+        };
+        assert_eq!(tick.npcs.len(), 3);
+        assert_eq!(tick.nearby_players.len(), 2);
+        assert_eq!(tick.server_tick, 2);
+        assert_eq!(tick.npcs[2].npc_id, "survival_npc_2");
+
+        let Some(ClientMsg::ChatObservation(chat)) = server.chat(1_000).message else {
+            panic!("Expected ChatObservation");
+        };
+        assert!(chat.npc_id.starts_with("survival_npc_"));
+        // Same seed, same traffic
+        let mut again = SyntheticServer::new("survival", profile, 7);
+        assert_eq!(
+            again.chat(1_000).message,
+            Some(ClientMsg::ChatObservation(chat))
+        );
+
+        let frames = server.voice_frames(1_020);
+        assert_eq!(frames.len(), 1);
+        let Some(ClientMsg::VoicePcmFrame(frame)) = &frames[0].message else {
+            panic!("Expected VoicePcmFrame");
+        };
+        // 20 ms of 16-bit mono at 48 kHz
+        assert_eq!(frame.pcm_data.len(), 1_920);
+    }
+
+    #[test]
+    fn test_latency() {
+        let mut trips = RoundTrips::default();
+        trips.sent("miner", 10);
+        trips.sent("miner", 20);
+        assert_eq!(trips.answered("miner"), Some(10));
+        assert_eq!(trips.answered("farmer"), None);
+        assert_eq!(trips.unanswered(), 1);
+
+        let mut latencies = LatencyRecorder::default();
+        assert_eq!(latencies.summary().count, 0);
+        for ms in 1..=100 {
+            latencies.record(Duration::from_millis(ms));
+        }
+        let summary = latencies.summary();
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.p50, Duration::from_millis(50));
+        assert_eq!(summary.p99, Duration::from_millis(99));
+        assert_eq!(summary.max, Duration::from_millis(100));
+    }
+}
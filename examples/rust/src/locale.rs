@@ -0,0 +1,337 @@
+//! One logical line in each player's language.
+//!
+//! Plugins from v1.2 report each player's client language in
+//! `PlayerSnapshot.locale` and `ChatObservation.player_locale`, in
+//! Minecraft's lowercase form (`en_us`, `pt_br`), and `SpeakDirective` /
+//! `ChatDirective` carry the `locale` their text is in. A [`Catalog`] holds
+//! the translations of each line by key, with `{name}` placeholders, and
+//! renders a key for a locale, falling back to another region of the same
+//! language and then to the catalog's default locale:
+//!
+//! ```ignore
+//! let catalog = Catalog::new("en_us")
+//!     .with("en_us", "greeting", "Hello, {player}!")
+//!     .with("de_de", "greeting", "Hallo, {player}!");
+//! let copies = catalog.speak_to(&speak, "greeting", &[("player", "Steve")], &nearby, || cx.next_directive_id(npc));
+//! ```
+//!
+//! [`Catalog::speak_to`] and [`Catalog::chat_to`] send a line to several
+//! players as one directive per language, each addressed to its players.
+//! Translations can also be loaded from `.lang`-style files, one per
+//! locale, with [`Catalog::load_str`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::npc_society::v1::{ChatDirective, PlayerSnapshot, SpeakDirective};
+
+/// Locale assumed when a player's is unknown
+pub const DEFAULT_LOCALE: &str = "en_us";
+
+/// `locale` in Minecraft's form: "en-US" and "EN_us" become "en_us"
+pub fn normalize(locale: &str) -> String {
+    locale.trim().replace('-', "_").to_lowercase()
+}
+
+/// Language part of a locale: "en" for "en_us"
+pub fn language(locale: &str) -> &str {
+    locale.split('_').next().unwrap_or_default()
+}
+
+/// A catalog file that cannot be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogError {
+    /// 1-based line number
+    pub line: usize,
+    /// What is wrong with it
+    pub reason: String,
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "catalog line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for CatalogError {}
+
+/// Translations of a daemon's lines, by key and locale
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    default_locale: String,
+    /// key -> locale -> template
+    lines: HashMap<String, HashMap<String, String>>,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self::new(DEFAULT_LOCALE)
+    }
+}
+
+impl Catalog {
+    /// An empty catalog whose fallback language is `default_locale`
+    pub fn new(default_locale: &str) -> Self {
+        Self {
+            default_locale: normalize(default_locale),
+            lines: HashMap::new(),
+        }
+    }
+
+    /// Locale used when neither a player's locale nor its language has a
+    /// translation
+    pub fn default_locale(&self) -> &str {
+        &self.default_locale
+    }
+
+    /// Add `key`'s translation for `locale`
+    pub fn insert(&mut self, locale: &str, key: &str, template: &str) {
+        self.lines
+            .entry(key.to_string())
+            .or_default()
+            .insert(normalize(locale), template.to_string());
+    }
+
+    /// `self` with `key`'s translation for `locale`
+    pub fn with(mut self, locale: &str, key: &str, template: &str) -> Self {
+        self.insert(locale, key, template);
+        self
+    }
+
+    /// Add the translations of one `.lang`-style file for `locale`:
+    /// `key = template` lines, blank lines and `#` comments
+    pub fn load_str(&mut self, locale: &str, text: &str) -> Result<(), CatalogError> {
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |reason: &str| CatalogError {
+                line: index + 1,
+                reason: reason.to_string(),
+            };
+            let (key, template) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected key = text"))?;
+            let key = key.trim();
+            if key.is_empty() {
+                return Err(error("empty key"));
+            }
+            self.insert(locale, key, template.trim());
+        }
+        Ok(())
+    }
+
+    /// The locale `key` is rendered in for a player whose client uses
+    /// `locale`: the locale itself, another region of its language (the
+    /// language's own region first, `de_de` for `de_at`), or the default.
+    /// None if the key has no translation in any of them.
+    pub fn resolve(&self, key: &str, locale: &str) -> Option<&str> {
+        let translations = self.lines.get(key)?;
+        let locale = normalize(locale);
+        let lang = language(&locale);
+        let same_language = || {
+            let home = format!("{lang}_{lang}");
+            translations
+                .get_key_value(&home)
+                .map(|(l, _)| l)
+                .or_else(|| {
+                    translations
+                        .keys()
+                        .filter(|l| !lang.is_empty() && language(l) == lang)
+                        .min()
+                })
+        };
+        translations
+            .get_key_value(&locale)
+            .map(|(l, _)| l)
+            .or_else(same_language)
+            .or_else(|| {
+                translations
+                    .get_key_value(&self.default_locale)
+                    .map(|(l, _)| l)
+            })
+            .map(String::as_str)
+    }
+
+    /// `key` for a player using `locale`, with each `{name}` in `args`
+    /// filled in. Unknown placeholders stay as they are.
+    pub fn render(&self, key: &str, locale: &str, args: &[(&str, &str)]) -> Option<Rendered> {
+        let locale = self.resolve(key, locale)?;
+        let mut text = self.lines[key][locale].clone();
+        for (name, value) in args {
+            text = text.replace(&format!("{{{name}}}"), value);
+        }
+        Some(Rendered {
+            locale: locale.to_string(),
+            text,
+        })
+    }
+
+    /// `players` grouped by the locale `key` renders in for them, with the
+    /// rendered text; empty if the key is unknown
+    pub fn group<'a>(
+        &self,
+        key: &str,
+        args: &[(&str, &str)],
+        players: impl IntoIterator<Item = &'a PlayerSnapshot>,
+    ) -> Vec<(Rendered, Vec<String>)> {
+        let mut groups: BTreeMap<String, (Rendered, Vec<String>)> = BTreeMap::new();
+        for player in players {
+            let Some(rendered) = self.render(key, &player.locale, args) else {
+                return Vec::new();
+            };
+            groups
+                .entry(rendered.locale.clone())
+                .or_insert_with(|| (rendered, Vec::new()))
+                .1
+                .push(player.player_uuid.clone());
+        }
+        groups.into_values().collect()
+    }
+
+    /// `speak` with `key`'s text, once per language among `players` and
+    /// addressed to them. Each copy gets a directive id from `next_id`; a
+    /// `stream_id` is cleared, since every language needs its own audio.
+    pub fn speak_to<'a>(
+        &self,
+        speak: &SpeakDirective,
+        key: &str,
+        args: &[(&str, &str)],
+        players: impl IntoIterator<Item = &'a PlayerSnapshot>,
+        mut next_id: impl FnMut() -> String,
+    ) -> Vec<SpeakDirective> {
+        self.group(key, args, players)
+            .into_iter()
+            .map(|(rendered, targets)| SpeakDirective {
+                text: rendered.text,
+                locale: rendered.locale,
+                target_player_uuids: targets,
+                directive_id: next_id(),
+                stream_id: String::new(),
+                ..speak.clone()
+            })
+            .collect()
+    }
+
+    /// A chat message with `key`'s text from `npc_id` (empty = the server),
+    /// once per language among `players`
+    pub fn chat_to<'a>(
+        &self,
+        npc_id: &str,
+        key: &str,
+        args: &[(&str, &str)],
+        players: impl IntoIterator<Item = &'a PlayerSnapshot>,
+    ) -> Vec<ChatDirective> {
+        self.group(key, args, players)
+            .into_iter()
+            .map(|(rendered, player_uuids)| ChatDirective {
+                player_uuids,
+                text: rendered.text,
+                npc_id: npc_id.to_string(),
+                locale: rendered.locale,
+            })
+            .collect()
+    }
+}
+
+/// A line rendered for one locale
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rendered {
+    /// Locale of the translation used
+    pub locale: String,
+    /// The text, placeholders filled in
+    pub text: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> Catalog {
+        let mut catalog = Catalog::new("en_us")
+            .with("en_us", "greeting", "Hello, {player}!")
+            .with("de_de", "greeting", "Hallo, {player}!");
+        catalog
+            .load_str(
+                "pt_BR",
+                "# Portuguese\ngreeting = Olá, {player}!\n\nfarewell = Tchau",
+            )
+            .unwrap();
+        catalog
+    }
+
+    fn player(uuid: &str, locale: &str) -> PlayerSnapshot {
+        PlayerSnapshot {
+            player_uuid: uuid.to_string(),
+            locale: locale.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render_with_fallbacks() {
+        let catalog = catalog();
+        let hello = |locale| {
+            catalog
+                .render("greeting", locale, &[("player", "Steve")])
+                .unwrap()
+        };
+        assert_eq!(hello("de_de").text, "Hallo, Steve!");
+        // Another region of the same language, then the default
+        assert_eq!(hello("de_AT").locale, "de_de");
+        assert_eq!(hello("pt_pt").text, "Olá, Steve!");
+        assert_eq!(hello("fr_fr").locale, "en_us");
+        assert_eq!(hello("").locale, "en_us");
+        // Only Portuguese says goodbye; the rest have nothing to fall back to
+        assert_eq!(
+            catalog.render("farewell", "pt_br", &[]).unwrap().text,
+            "Tchau"
+        );
+        assert_eq!(catalog.render("farewell", "en_us", &[]), None);
+
+        let error = Catalog::default()
+            .load_str("en_us", "ok = fine\nbroken")
+            .unwrap_err();
+        assert_eq!(error.line, 2);
+    }
+
+    #[test]
+    fn test_one_directive_per_language() {
+        let players = [
+            player("a", "de_de"),
+            player("b", "en_gb"),
+            player("c", "de_at"),
+            player("d", ""),
+        ];
+        let template = SpeakDirective {
+            npc_id: "smith".to_string(),
+            stream_id: "s".to_string(),
+            ..Default::default()
+        };
+        let mut n = 0;
+        let copies = catalog().speak_to(
+            &template,
+            "greeting",
+            &[("player", "all")],
+            &players,
+            || {
+                n += 1;
+                format!("dir-{n}")
+            },
+        );
+        assert_eq!(copies.len(), 2);
+        assert_eq!(copies[0].locale, "de_de");
+        assert_eq!(copies[0].target_player_uuids, ["a", "c"]);
+        assert_eq!(copies[1].text, "Hello, all!");
+        assert_eq!(copies[1].target_player_uuids, ["b", "d"]);
+        assert_ne!(copies[0].directive_id, copies[1].directive_id);
+        assert!(copies.iter().all(|copy| copy.stream_id.is_empty()));
+
+        let chats = catalog().chat_to("smith", "greeting", &[("player", "all")], &players);
+        assert_eq!(chats[0].text, "Hallo, all!");
+        assert!(catalog()
+            .chat_to("smith", "missing", &[], &players)
+            .is_empty());
+    }
+}
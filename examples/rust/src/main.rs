@@ -9,340 +9,3074 @@
 #[cfg(test)]
 mod integration_test;
 
+use std::collections::{HashMap, HashSet};
 use std::pin::Pin;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use tokio::sync::mpsc;
-use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio_stream::{Stream, StreamExt};
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::server::TcpIncoming;
 use tonic::{transport::Server, Request, Response, Status, Streaming};
 use tracing::{info, warn, error, debug, Level};
 
-// Include the generated proto code from build.rs
-pub mod npc_society {
-    pub mod v1 {
-        tonic::include_proto!("npc_society.v1");
-    }
-}
+use npc_society_example::asr::{self, AsrProvider, VoiceTranscription};
+use npc_society_example::assets::AudioAssets;
+use npc_society_example::audio;
+use npc_society_example::behavior::{action, condition, selector, sequence, BehaviorTree};
+use npc_society_example::budget::{Call, SpendLedger};
+use npc_society_example::builders::Buildable;
+use npc_society_example::chat::{
+    ChatPipeline, IntentStage, KeywordIntents, LanguageDetector, ProfanityFilter,
+};
+use npc_society_example::chunking::{MessageLimits, ResultAssembler};
+use npc_society_example::clock::TickClock;
+use npc_society_example::commands::CommandRouter;
+#[cfg(feature = "compression")]
+use npc_society_example::compression::Compression;
+use npc_society_example::config::DaemonConfig;
+use npc_society_example::connection::ConnectionContext;
+use npc_society_example::consent::VoiceConsents;
+use npc_society_example::conversation::{
+    ConversationConfig, ConversationManager, ConversationTracker, Speaker, SpeakerEvent,
+};
+#[cfg(feature = "persistence")]
+use npc_society_example::conversation::Turn;
+#[cfg(feature = "dashboard")]
+use npc_society_example::dashboard::Dashboard;
+#[cfg(feature = "websocket")]
+use npc_society_example::websocket::WebSocketConnect;
+use npc_society_example::dimension::{self, World};
+use npc_society_example::display::{self, Displays};
+use npc_society_example::effects;
+use npc_society_example::emotion::{self, Spoken, Tone};
+use npc_society_example::equipment;
+use npc_society_example::events::{self, ClientEvent, NpcSocietyHandler};
+use npc_society_example::formation;
+use npc_society_example::game_event::GameEvent;
+use npc_society_example::guard::{self, IntruderPolicy};
+use npc_society_example::guardrail::{GuardChain, StayInCharacter};
+use npc_society_example::ids::DirectiveIdFactory;
+use npc_society_example::landmarks::{self, SharedLandmarks};
+use npc_society_example::latency::{self, LatencyTracker};
+use npc_society_example::locale::Catalog;
+use npc_society_example::mixer::MixerConfig;
+use npc_society_example::moderation::{self, Moderation, ModerationHooks, ModerationNotice};
+use npc_society_example::movement;
+use npc_society_example::music;
+#[cfg(feature = "lease-redis")]
+use npc_society_example::lease::{Lease, LeaseChanges, LeaseConfig, LeaseManager, RedisLeases};
+use npc_society_example::lifecycle::{self, Lifecycle};
+use npc_society_example::npc_society;
+use npc_society_example::npc_state;
+use npc_society_example::outbound::{self, Outbound, OutboundMonitor, QueueConfig};
+use npc_society_example::pacing::PacingConfig;
+#[cfg(feature = "persistence")]
+use npc_society_example::persistence::{SqliteStore, Store};
+#[cfg(feature = "npc-profiles")]
+use npc_society_example::profiles;
+use npc_society_example::players;
+use npc_society_example::policy::{self, ActionPolicy};
+use npc_society_example::probe::SelfTest;
+use npc_society_example::prompts::{self, DialoguePrompts};
+#[cfg(feature = "persistence")]
+use npc_society_example::replay::{self, EventRecorder, ReplayConfig};
+use npc_society_example::reputation::Reputation;
+#[cfg(feature = "persistence")]
+use npc_society_example::transcripts::{Channel, TranscriptConfig, TranscriptEntry, TranscriptLog};
+use npc_society_example::retry::{self, RetryDecision, RetryPolicy, RetryTracker};
+use npc_society_example::routing;
+use npc_society_example::sanitize::OutputFilter;
+use npc_society_example::speech_rate::{Admission, SpeechGovernor, SpeechRate};
+use npc_society_example::throttle::LoadThrottle;
+use npc_society_example::time::{now_ms, Timed};
+use npc_society_example::transfer::{Outgoing, Transfer, TransferCoordinator};
+use npc_society_example::tts::{self, SpeechRegistry, TtsProvider};
+use npc_society_example::types::{NpcId, PlayerUuid, StreamId};
+use npc_society_example::validate::{SequenceTracker, Validate};
+use npc_society_example::voice_commands::{Follow, GoHome, GoTo, Stop, VoiceGrammar};
+use npc_society_example::servers::{ResolveError, ServerRegistry};
+use npc_society_example::shop::{self, Shops};
+use npc_society_example::sim::{SimConfig, Simulation, Trace};
+use npc_society_example::stations::StationJobs;
+use npc_society_example::storage;
+use npc_society_example::tap::{CaptureWriter, LogTap, Tap};
+use npc_society_example::watch::BlockWatches;
+use npc_society_example::watchdog::{DirectiveWatchdog, OrphanStage};
+use npc_society_example::world_model::WorldModel;
 
 use npc_society::v1::{
     npc_society_service_server::{NpcSocietyService, NpcSocietyServiceServer},
     action_directive::Action,
     action_result::Result as ActionResultType,
-    ActionDirective, AudioChunk, ClientMessage, ServerMessage, SpeakDirective,
     client_message::Message as ClientMsg,
     server_message::Message as ServerMsg,
+    ActionDirective, ActionResult, ClientMessage, ServerMessage, SpeakDirective, WorldTick, Hello,
+    HelloAck, PcmFormat, SpeechDelivery, StopSpeaking, SubscribeEvents, EventType, Emotion,
+    // Observations
+    ChatObservation, EventObservation, VoicePcmFrame, SpeakResult, SpeechInterrupted, NpcMessage,
+    QuestUpdate, TransactionObservation, ChangeDimensionObservation, BlockWatchUpdate,
+    StationOutputObservation, CombatPolicyObservation, SetCombatPolicyDirective, CombatStance,
+    TargetFilter, DirectiveRejected, DirectiveAck, NpcTransferUpdate, NpcTransferStage,
+    ChoreographyResult, DialogueChoiceObservation, DialogueOptionsDirective, ShopDefinition,
+    ShopTradeObservation, QuestStatus, ShowDisplayDirective, SetWeatherDirective, Weather,
+    AudioBufferStatus, RejectionCode, NoteInstrument, IntruderObservation, AttackAction,
+    GiveItemAction, ContainerAccessObservation, LandmarkList, VoiceConsentObservation,
+    ModerationFlag, WorldBootstrap, ProbeReply, ProbeReport, ChunkLoadObservation,
+    WriteBookAction, ToggleLeverAction, TransportItemsDirective, TransportProgress,
+    TransportItemsResult,
+    // Unary RPC types
+    GetSnapshotRequest, GetSnapshotResponse, ListNpcsRequest, ListNpcsResponse, ListServersRequest,
+    ListServersResponse,
+    GetNpcStateRequest, GetNpcStateResponse, ListPendingDirectivesRequest,
+    ListPendingDirectivesResponse, GetSessionInfoRequest, GetSessionInfoResponse,
+    PendingDirective, ExportNpcStateRequest, ExportNpcStateResponse, ImportNpcStateRequest,
+    ImportNpcStateResponse, NpcStateSnapshot, TransferNpcRequest, TransferNpcResponse,
+    SendDirectiveRequest, SendDirectiveResponse,
     // Action types
-    MoveAction, BreakBlockAction, ScanBlocksAction, DepositToChestAction,
+    MoveAction, BreakBlockAction, ScanBlocksAction, DepositToChestAction, ConsumeItemAction,
     // Common types
-    Position, BlockPosition,
+    Position, BlockPosition, ItemStack,
 };
 
-/// Counter for generating unique directive IDs
-static DIRECTIVE_COUNTER: AtomicU64 = AtomicU64::new(1);
+/// How far (in frames) a VoicePcmFrame may trail its stream before it is dropped
+const VOICE_REORDER_WINDOW: u64 = 50;
+
+/// How long an Opus voice decoder outlives its stream's last frame (ms)
+#[cfg(feature = "audio-opus")]
+const OPUS_DECODER_IDLE_MS: i64 = 60_000;
+
+/// How long after a chat its reply is still worth sending (ms)
+const CHAT_REPLY_DEADLINE_MS: i64 = 10_000;
+
+/// How long a player has to click a dialogue option (ms)
+const DIALOGUE_PROMPT_TTL_MS: i64 = 60_000;
+
+/// How long an NPC listens to a player's voice after they chatted (ms)
+const VOICE_CAPTURE_MS: i32 = 5 * 60 * 1000;
+
+/// How long a summoned storm lasts (ticks; 6000 = five minutes)
+const STORM_TICKS: i32 = 6_000;
+
+/// Blocks a guard zone reaches out from where the guard stood when posted
+const GUARD_ZONE_REACH: i32 = 16;
+
+/// What the miner plays when asked for a song (see `music::song`)
+const MINER_SONG: &str = "F#4 A4 B4 A4:2 F#4 E4 F#4:2 R A4 B4 C#5:2 B4 A4 F#4:3";
+
+/// Landmark of the chest the miners deposit their diamonds in
+const MINERS_CHEST: &str = "miners_chest";
+
+/// Landmark of the lever powering the mine's piston gate
+const MINE_GATE_LEVER: &str = "mine_gate_lever";
+
+/// Landmark of the village storehouse the miners' diamonds are hauled to
+const STOREHOUSE_CHEST: &str = "storehouse_chest";
+
+/// A fresh stream ID for audio
+fn next_stream_id(cx: &ConnectionContext) -> StreamId {
+    StreamId::new(cx.next_id("stream")).expect("generated stream ids are valid")
+}
+
+/// A HelloAck.audio_stream_token: unique like an id, and not guessable
+/// from the ids another server's connection was given
+fn audio_stream_token(cx: &ConnectionContext) -> String {
+    use std::hash::{BuildHasher, RandomState};
+    let salt = RandomState::new().hash_one(cx.connected_at_ms());
+    format!("{}:{:016x}", cx.next_id("audio"), salt)
+}
 
-/// Generate a unique directive ID
-fn next_directive_id() -> String {
-    format!("dir-{}", DIRECTIVE_COUNTER.fetch_add(1, Ordering::SeqCst))
+/// Charge a line's characters to the NPC's AI budget if it still pays for
+/// speech; past the downgrade threshold the subtitle has to do
+fn charge_tts(spend: &mut SpendLedger, speak: &SpeakDirective) -> bool {
+    if !spend.allows(&speak.npc_id, Call::Tts, now_ms()) {
+        debug!(npc_id = %speak.npc_id, "AI budget downgraded, sending subtitle only");
+        return false;
+    }
+    spend.record_tts(&speak.npc_id, speak.text.chars().count() as u64, now_ms());
+    true
 }
 
-/// Generate a unique stream ID for audio
-fn next_stream_id() -> String {
-    format!("stream-{}", DIRECTIVE_COUNTER.fetch_add(1, Ordering::SeqCst))
+/// This replica's NPC leases, as the lease thread last saw them. Only that
+/// thread talks to Redis, so a slow store never holds up a connection.
+#[cfg(feature = "lease-redis")]
+#[derive(Debug, Default)]
+struct LeaseState {
+    /// NPCs reported by plugins since the thread's last round
+    seen: HashSet<String>,
+    /// Leases held after the last round
+    owned: HashMap<String, Lease>,
+    /// Owner changes no connection has acted on yet
+    changes: LeaseChanges,
 }
 
-/// Example implementation of the NPC Society service.
+#[cfg(feature = "lease-redis")]
+type SharedLeases = Arc<Mutex<LeaseState>>;
+
+/// State of one Minecraft server, shared between its `Connect` stream and
+/// the unary RPCs.
 #[derive(Debug, Default)]
-pub struct ExampleNpcSocietyService;
+struct SharedState {
+    /// Whether a plugin is currently connected
+    connected: bool,
+    /// The current (or last) connection: peer, Hello, counters
+    connection: Option<Arc<ConnectionContext>>,
+    /// Most recent WorldTick, served by GetSnapshot
+    latest_tick: Option<WorldTick>,
+    /// Server tick rate and tick to wall time conversion
+    clock: TickClock,
+    /// Directive rate limit that tightens as the server's load rises
+    throttle: LoadThrottle,
+    /// How often each NPC may speak, so chatty NPCs don't flood players
+    speech_rate: SpeechGovernor,
+    /// What each NPC's TTS and ASR calls cost against its budget
+    spend: SpendLedger,
+    /// Imported NPCs to restore in the world once the plugin reports them
+    restores: Vec<NpcStateSnapshot>,
+    /// Directives awaiting an ActionResult, oldest first
+    pending: Vec<PendingDirective>,
+    /// Final ActionResult of each NPC's latest directive, for GetNpcState
+    last_results: HashMap<String, ActionResult>,
+    /// Blocks, entities and players seen so far
+    world: WorldModel,
+    /// How each NPC feels about each player
+    reputation: Reputation,
+    /// What each NPC may do; checked before a directive is sent
+    policy: ActionPolicy,
+    /// Retry policies and attempts of in-flight directives
+    retries: RetryTracker,
+    /// Deadlines for acks and results of directives in flight
+    watchdog: DirectiveWatchdog,
+    /// ActionResults split by the plugin, until all parts arrived
+    result_parts: ResultAssembler,
+    /// Current matches of the WatchBlocksActions in flight
+    watches: BlockWatches,
+    /// Furnaces and brewing stands loaded by NPCs, until they finish
+    stations: StationJobs,
+    /// Autonomy per NPC, driven by WorldTicks and ActionResults
+    behaviors: HashMap<String, BehaviorTree>,
+    /// NPCs sent a SetCombatPolicyDirective on the current connection
+    combat_policies: HashSet<String>,
+    /// What guards do about the intruders the plugin reports
+    intruders: IntruderPolicy,
+    /// Named places, cached from LandmarkLists; behavior trees hold clones
+    landmarks: SharedLandmarks,
+    /// Who consented to voice capture and which NPCs asked to hear whom
+    consents: VoiceConsents,
+    /// Flags per NPC and the NPCs quarantined pending review
+    moderation: Moderation,
+    /// Voice sessions per (npc_id, player_uuid) and recent utterances per NPC
+    conversations: ConversationTracker,
+    /// Opus decoder per (npc_id, player_uuid) voice stream, and when it
+    /// last decoded a frame
+    #[cfg(feature = "audio-opus")]
+    opus_voice: HashMap<(String, String), (audio::OpusDecoder, i64)>,
+    /// Dialogue with each player per NPC: chat, transcripts and replies
+    dialogues: ConversationManager,
+    /// Dialogue options shown to players and not answered yet
+    prompts: DialoguePrompts,
+    /// Shops the NPCs run, with their stock as last traded
+    shops: Shops,
+    /// Stock lines and what the plugin has cached of them
+    audio_assets: AudioAssets,
+    /// Holograms, boss bars and scoreboards the plugin shows
+    displays: Displays,
+    /// Sender of the current connection, for messages that do not answer
+    /// this server (NPC transfers)
+    tx: Option<Outbound>,
+    /// Clock sync and one-way delays of the current connection
+    latency: LatencyTracker,
+    /// Probes of the current connection awaiting replies
+    probe: Option<SelfTest>,
+    /// Latest finished self-check of the current connection
+    probe_report: Option<ProbeReport>,
+    /// Which received messages go into the NPC_DB event log
+    #[cfg(feature = "persistence")]
+    recorder: EventRecorder,
+}
+
+impl SharedState {
+    fn new(config: &DaemonConfig) -> Self {
+        let mut state = Self {
+            throttle: LoadThrottle::new(config.ticks),
+            spend: SpendLedger::new(config.budget),
+            ..Self::default()
+        };
+        // Moves and block breaks often fail transiently (blocked path);
+        // everything else is reported as-is
+        state.retries.set_policy("move", RetryPolicy::default());
+        state.retries.set_policy("break_block", RetryPolicy::default());
+        // Time and weather belong to every player on the server; the
+        // operator has to opt in
+        state.policy.allow_world_control(config.policy.world_control);
+        // One ASR feed per NPC, with the NPC's own voice taken out
+        if config.audio.voice_mix {
+            state.conversations = ConversationTracker::new(ConversationConfig {
+                mix: Some(MixerConfig::default()),
+                ..Default::default()
+            });
+        }
+        state
+    }
+
+    /// Turn an Opus voice frame into the S16LE [`ConversationTracker`]
+    /// segments; other frames pass through. None if it cannot be decoded.
+    #[cfg(feature = "audio-opus")]
+    fn decode_voice(&mut self, mut frame: VoicePcmFrame, now_ms: i64) -> Option<VoicePcmFrame> {
+        if frame.format() != PcmFormat::Opus {
+            return Some(frame);
+        }
+        let sample_rate = match frame.sample_rate_hz {
+            0 => audio::PROTOCOL_SAMPLE_RATE_HZ,
+            hz => hz as u32,
+        };
+        let key = (frame.npc_id.clone(), frame.player_uuid.clone());
+        // A new stream, or one the plugin renegotiated
+        if self.opus_voice.get(&key).is_none_or(|(decoder, _)| decoder.sample_rate_hz() != sample_rate) {
+            match audio::OpusDecoder::new(sample_rate) {
+                Ok(decoder) => {
+                    self.opus_voice.insert(key.clone(), (decoder, now_ms));
+                }
+                Err(error) => {
+                    warn!(sample_rate, %error, "No Opus decoder for voice stream");
+                    return None;
+                }
+            }
+        }
+        let (decoder, last_used_ms) = self.opus_voice.get_mut(&key)?;
+        *last_used_ms = now_ms;
+        match decoder.decode_frame(&frame.pcm_data) {
+            Ok(pcm) => {
+                frame.pcm_data = pcm.into();
+                frame.format = PcmFormat::S16le as i32;
+                Some(frame)
+            }
+            Err(error) => {
+                debug!(player_uuid = %frame.player_uuid, sequence = frame.sequence, %error, "Opus voice frame dropped");
+                None
+            }
+        }
+    }
+
+    /// Take over what the previous connection of the same server left:
+    /// everything but the connection itself
+    fn adopt(&mut self, previous: &mut SharedState) {
+        let previous = std::mem::take(previous);
+        *self = SharedState {
+            connected: self.connected,
+            connection: self.connection.take(),
+            tx: self.tx.take(),
+            latency: std::mem::take(&mut self.latency),
+            // Self-checks describe one connection
+            probe: None,
+            probe_report: None,
+            // Plugins do not keep combat policies across connections
+            combat_policies: HashSet::new(),
+            ..previous
+        };
+    }
+
+    /// GetSessionInfo's description of the connection
+    fn session_info(&self) -> GetSessionInfoResponse {
+        let cx = self.connection.as_deref();
+        GetSessionInfoResponse {
+            connected: self.connected,
+            hello: cx.and_then(ConnectionContext::hello).cloned(),
+            peer_address: cx.map(|cx| cx.peer_address().to_string()).unwrap_or_default(),
+            connected_at_ms: cx.map(ConnectionContext::connected_at_ms).unwrap_or_default(),
+            messages_received: cx.map(ConnectionContext::received).unwrap_or_default(),
+            outbound: cx.and_then(ConnectionContext::outbound).map(OutboundMonitor::stats),
+            latencies: self.latency.stats(),
+            clock_offset_ms: self.latency.offset_ms(),
+            probe: self.probe_report.clone(),
+        }
+    }
+
+    /// server_id from the most recent Hello (empty before handshake)
+    fn server_id(&self) -> &str {
+        self.connection.as_deref().map(ConnectionContext::server_id).unwrap_or_default()
+    }
+
+    /// Pending directives, optionally restricted to one NPC
+    fn pending_for(&self, npc_id: &str) -> Vec<PendingDirective> {
+        self.pending
+            .iter()
+            .filter(|p| {
+                npc_id.is_empty() || p.directive.as_ref().is_some_and(|d| d.npc_id == npc_id)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Example implementation of the NPC Society service. Each `Connect`
+/// stream runs on its own clone, whose `state` belongs to that connection's
+/// server once the Hello names it.
+#[derive(Default, Clone)]
+pub struct ExampleNpcSocietyService {
+    /// Settings of the deployment, from the config file, NPC_* variables
+    /// and flags
+    config: Arc<DaemonConfig>,
+    /// Startup and shutdown stage; draining refuses new directives
+    lifecycle: Lifecycle,
+    /// Directive, stream and prompt ids, unique across connections,
+    /// restarts and replicas
+    ids: Arc<DirectiveIdFactory>,
+    /// State of the connection's server (unused outside a connection)
+    state: Arc<Mutex<SharedState>>,
+    /// State of every server by server_id, for the unary RPCs
+    servers: Arc<ServerRegistry<SharedState>>,
+    /// Speech recognition backend (None = utterances are only logged)
+    asr: Option<Arc<dyn AsrProvider>>,
+    /// Speech synthesis backend (None = SpeakDirectives are subtitle-only)
+    tts: Option<Arc<dyn TtsProvider>>,
+    /// TTS streams still being sent, cancelled on barge-in
+    speech: SpeechRegistry,
+    /// Preprocessing of ChatObservations before on_chat acts on them
+    chat: Arc<ChatPipeline>,
+    /// The miner's lines in each language it speaks
+    lines: Arc<Catalog>,
+    /// Cleans NPC text (LLM output included) before it reaches players
+    output: Arc<OutputFilter>,
+    /// Last checks on every action and line, ahead of the output filter
+    guards: Arc<GuardChain>,
+    /// `!npc` admin commands in chat, handled before the pipeline
+    commands: Arc<CommandRouter>,
+    /// Spoken commands run without the LLM, e.g. "follow me"
+    voice_commands: Arc<VoiceGrammar>,
+    /// Per-NPC profiles from policy.profiles_dir, reloaded while running
+    #[cfg(feature = "npc-profiles")]
+    profiles: Option<profiles::SharedProfiles>,
+    /// gRPC compression from GRPC_COMPRESSION
+    #[cfg(feature = "compression")]
+    compression: Compression,
+    /// Directive log and dialogue history in the SQLite file NPC_DB
+    #[cfg(feature = "persistence")]
+    store: Option<Arc<Mutex<SqliteStore>>>,
+    /// Conversation transcripts for moderators under NPC_TRANSCRIPTS
+    #[cfg(feature = "persistence")]
+    transcripts: Option<Arc<Mutex<TranscriptLog>>>,
+    /// Which NPCs this replica drives, leased in LEASE_REDIS_URL
+    #[cfg(feature = "lease-redis")]
+    leases: Option<SharedLeases>,
+    /// NPCs moving between servers
+    transfers: Arc<Mutex<TransferCoordinator>>,
+    /// TransferNpc calls waiting for their transfer to end
+    transfer_waiters: Arc<Mutex<HashMap<String, oneshot::Sender<Transfer>>>>,
+    /// Observers of every message on every stream
+    tap: Tap,
+    /// Operators told of moderation flags and quarantines
+    moderation: ModerationHooks,
+    /// Web dashboard on DASHBOARD_ADDR, for what the tap does not see
+    #[cfg(feature = "dashboard")]
+    dashboard: Option<Dashboard>,
+}
 
 impl ExampleNpcSocietyService {
-    /// Process an incoming client message and return responses.
-    fn handle_client_message(&self, msg: ClientMessage, tx: &mpsc::Sender<ServerMessage>) {
-        match msg.message {
-            Some(ClientMsg::Hello(hello)) => {
-                // Example A: Log v1.1+ handshake fields
-                info!(
-                    plugin_version = %hello.plugin_version,
-                    protocol_version = %hello.protocol_version,
-                    server_id = %hello.server_id,
-                    minecraft_version = %hello.minecraft_version,
-                    voice_available = hello.voice_available,
-                    server_name = %hello.server_name,
-                    daemon_mode = %hello.daemon_mode,
-                    "Received Hello handshake"
-                );
-                
-                if hello.voice_available {
-                    info!("Voice chat is available - TTS audio will be sent");
+    /// TTS voice of an NPC: from its profile if it sets one
+    #[cfg_attr(not(feature = "npc-profiles"), allow(unused_variables))]
+    fn voice_id(&self, npc_id: &str) -> String {
+        #[cfg(feature = "npc-profiles")]
+        if let Some(profiles) = &self.profiles {
+            if let Some(profile) = profiles.lock().unwrap().get(npc_id) {
+                if !profile.voice_id.is_empty() {
+                    return profile.voice_id.clone();
                 }
             }
-            
-            Some(ClientMsg::WorldTick(tick)) => {
-                debug!(
-                    server_tick = tick.server_tick,
-                    npcs = tick.npcs.len(),
-                    players = tick.nearby_players.len(),
-                    "WorldTick received"
-                );
-                
-                // Example D: Mining perception loop
-                // Every 100 ticks, send a ScanBlocksAction to look for diamond ore
-                if tick.server_tick % 100 == 0 && !tick.npcs.is_empty() {
-                    let npc = &tick.npcs[0];
-                    let directive_id = next_directive_id();
+        }
+        "en-US-Neural2-D".to_string() // Example TTS voice
+    }
+    
+    /// How often an NPC may speak: from its profile if it sets a rate
+    #[cfg_attr(not(feature = "npc-profiles"), allow(unused_variables))]
+    fn speech_rate(&self, npc_id: &str) -> SpeechRate {
+        #[cfg(feature = "npc-profiles")]
+        if let Some(profiles) = &self.profiles {
+            if let Some(rate) = profiles.lock().unwrap().get(npc_id).and_then(|p| p.speech_rate) {
+                return rate;
+            }
+        }
+        SpeechRate::default()
+    }
+
+    /// Whether this replica drives `npc_id`: always, unless replicas share
+    /// the NPCs through leases
+    #[cfg_attr(not(feature = "lease-redis"), allow(unused_variables))]
+    fn owns(&self, npc_id: &str) -> bool {
+        #[cfg(feature = "lease-redis")]
+        if let Some(leases) = &self.leases {
+            let leases = leases.lock().unwrap();
+            return leases.owned.get(npc_id).is_some_and(|lease| lease.expires_at_ms > now_ms());
+        }
+        true
+    }
+    
+    /// Send a run of Probes, if the plugin answers them
+    fn start_probe(&self, cx: &ConnectionContext, tx: &Outbound) {
+        let config = self.config.probe;
+        if config.count == 0 || !cx.hello().is_some_and(|hello| hello.probe_available) {
+            return;
+        }
+        let mut test = SelfTest::new(&cx.next_id("probe"), config);
+        let probes = test.start(now_ms());
+        self.state.lock().unwrap().probe = Some(test);
+        for probe in probes {
+            if let Err(error) = tx.send(probe) {
+                warn!(%error, "Probe not sent");
+            }
+        }
+    }
+
+    /// Report the running self-check once it is done, and start the next
+    /// one probe.interval_ms later
+    fn check_probe(&self, cx: &ConnectionContext, tx: &Outbound) {
+        let now = now_ms();
+        let due = {
+            let mut state = self.state.lock().unwrap();
+            if let Some(report) = state.probe.as_ref().and_then(|test| test.finish(now)) {
+                if report.passed {
+                    info!(
+                        probes = report.sent,
+                        round_trip_p50_ms = report.round_trip_p50_ms,
+                        clock_offset_ms = report.clock_offset_ms,
+                        "Probe self-check passed"
+                    );
+                } else {
+                    warn!(
+                        failure = %report.failure,
+                        lost = report.lost,
+                        corrupted = report.corrupted,
+                        out_of_order = report.out_of_order,
+                        "Probe self-check failed"
+                    );
+                }
+                state.probe = None;
+                state.probe_report = Some(report);
+            }
+            let interval = self.config.probe.interval_ms;
+            state.probe.is_none()
+                && interval > 0
+                && state.probe_report.as_ref().is_some_and(|r| now - r.finished_at_ms >= interval)
+        };
+        if due {
+            self.start_probe(cx, tx);
+        }
+    }
+    
+    /// Run an `!npc` command typed near an NPC this replica drives
+    fn run_command(&self, chat: &ChatObservation, cx: &ConnectionContext, tx: &Outbound) {
+        if !self.owns(&chat.npc_id) {
+            return;
+        }
+        let messages = {
+            let state = self.state.lock().unwrap();
+            let tick = state.latest_tick.as_ref();
+            let player = tick
+                .into_iter()
+                .flat_map(|tick| &tick.nearby_players)
+                .find(|p| p.player_uuid == chat.player_uuid);
+            let npc = tick
+                .into_iter()
+                .flat_map(|tick| &tick.npcs)
+                .find(|npc| npc.npc_id == chat.npc_id);
+            self.commands.dispatch(chat, player, npc).unwrap_or_default()
+        };
+        info!(
+            npc_id = %chat.npc_id,
+            player_name = %chat.player_name,
+            command = %chat.message,
+            "Chat command"
+        );
+        for message in messages {
+            if let Some(ServerMsg::QuarantineNpc(quarantine)) = &message.message {
+                self.state.lock().unwrap().moderation.apply(quarantine);
+                self.moderation.notify(&ModerationNotice::Quarantine {
+                    server_id: cx.server_id(),
+                    directive: quarantine,
+                });
+            }
+            let result = match message.message {
+                Some(ServerMsg::ActionDirective(mut directive)) => {
+                    directive.directive_id = cx.next_directive_id(&directive.npc_id);
+                    self.send_directive(tx, directive)
+                        .map_err(|failed| failed.error_message)
+                }
+                Some(ServerMsg::DebugPath(_)) if !cx.capabilities().debug_path => {
+                    Err("the plugin cannot draw paths".to_string())
+                }
+                _ => tx.send(message).map_err(|e| e.to_string()),
+            };
+            if let Err(error) = result {
+                warn!(npc_id = %chat.npc_id, %error, "Chat command output not sent");
+            }
+        }
+    }
+    
+    /// Run `transcription` right away if it is a voice command, without
+    /// waiting for the LLM; returns whether it was one
+    fn run_voice_command(&self, transcription: &VoiceTranscription, tx: &Outbound) -> bool {
+        if !self.owns(&transcription.npc_id) {
+            return false;
+        }
+        let (recognized, cx) = {
+            let state = self.state.lock().unwrap();
+            if state.moderation.is_quarantined(&transcription.npc_id) {
+                return false;
+            }
+            let tick = state.latest_tick.as_ref();
+            let player = tick
+                .into_iter()
+                .flat_map(|tick| &tick.nearby_players)
+                .find(|p| p.player_uuid == transcription.player_uuid);
+            let npc = tick
+                .into_iter()
+                .flat_map(|tick| &tick.npcs)
+                .find(|npc| npc.npc_id == transcription.npc_id);
+            let landmarks = state.landmarks.lock().unwrap();
+            let recognized = self.voice_commands.dispatch(transcription, player, npc, Some(&landmarks));
+            (recognized, state.connection.clone())
+        };
+        let (Some(recognized), Some(cx)) = (recognized, cx) else {
+            return false;
+        };
+        info!(
+            npc_id = %transcription.npc_id,
+            player_uuid = %transcription.player_uuid,
+            command = %recognized.command,
+            "Voice command"
+        );
+        for message in recognized.messages {
+            let result = match message.message {
+                Some(ServerMsg::ActionDirective(mut directive)) => {
+                    directive.directive_id = cx.next_directive_id(&directive.npc_id);
+                    self.send_directive(tx, directive)
+                        .map_err(|failed| failed.error_message)
+                }
+                _ => tx.send(message).map_err(|e| e.to_string()),
+            };
+            if let Err(error) = result {
+                warn!(npc_id = %transcription.npc_id, %error, "Voice command output not sent");
+            }
+        }
+        true
+    }
+    
+    /// Report the NPCs in `tick` to the lease thread and act on what it
+    /// found since the last tick. NPCs lost to another replica drop their
+    /// behavior tree; the new owner starts its own.
+    #[cfg(feature = "lease-redis")]
+    fn sync_leases(&self, tick: &WorldTick) {
+        let Some(leases) = &self.leases else {
+            return;
+        };
+        let changes = {
+            let mut leases = leases.lock().unwrap();
+            leases.seen.extend(tick.npcs.iter().map(|npc| npc.npc_id.clone()));
+            std::mem::take(&mut leases.changes)
+        };
+        if !changes.gained.is_empty() {
+            info!(npcs = ?changes.gained, "Leased NPCs");
+        }
+        if !changes.lost.is_empty() {
+            warn!(npcs = ?changes.lost, "Lost NPC leases");
+            let mut state = self.state.lock().unwrap();
+            for npc_id in &changes.lost {
+                state.behaviors.remove(npc_id);
+            }
+        }
+    }
+    
+    /// The server a unary request means (`server_id`, or the only one)
+    fn server(&self, server_id: &str) -> Result<Arc<Mutex<SharedState>>, ResolveError> {
+        self.servers.resolve(server_id)
+    }
+
+    /// Write to the NPC_DB store, if there is one. A failed write is logged
+    /// and otherwise ignored: losing history must not stop the NPCs.
+    #[cfg(feature = "persistence")]
+    fn persist(&self, write: impl FnOnce(&mut SqliteStore) -> rusqlite::Result<()>) {
+        if let Some(store) = &self.store {
+            if let Err(e) = write(&mut store.lock().unwrap()) {
+                warn!(error = %e, "Failed to write to NPC_DB");
+            }
+        }
+    }
+    
+    /// Log utterances as they start and transcribe the ones that ended
+    fn on_speaker_events(&self, events: Vec<SpeakerEvent>, tx: &Outbound) {
+        for event in events {
+            match event {
+                SpeakerEvent::Started { npc_id, player_uuid } => {
+                    debug!(npc_id = %npc_id, player_uuid = %player_uuid, "Utterance started");
+                }
+                SpeakerEvent::Utterance { npc_id, player_uuid, audio: utterance } => {
+                    let duration_ms = utterance.len() as u64 * 1000 / audio::ASR_SAMPLE_RATE_HZ as u64;
+                    info!(
+                        npc_id = %npc_id,
+                        player_uuid = %player_uuid,
+                        duration_ms,
+                        "Utterance ended"
+                    );
                     
-                    let center = npc.position.as_ref().map(|p| BlockPosition {
-                        world: p.world.clone(),
-                        x: p.x as i32,
-                        y: p.y as i32,
-                        z: p.z as i32,
+                    // Nobody transcribes for an NPC whose AI budget is spent
+                    let asr = self.asr.clone().filter(|_| {
+                        let mut state = self.state.lock().unwrap();
+                        if !state.spend.allows(&npc_id, Call::Asr, now_ms()) {
+                            debug!(npc_id = %npc_id, "AI budget spent, not transcribing");
+                            return false;
+                        }
+                        state.spend.record_asr(&npc_id, duration_ms, now_ms());
+                        true
                     });
-                    
-                    if let Some(center) = center {
-                        let scan_action = ActionDirective {
-                            directive_id: directive_id.clone(),
-                            npc_id: npc.npc_id.clone(),
-                            priority: 5,
-                            action: Some(Action::ScanBlocks(ScanBlocksAction {
-                                center: Some(center),
-                                radius: 16,
-                                block_types: vec![
-                                    "minecraft:diamond_ore".to_string(),
-                                    "minecraft:deepslate_diamond_ore".to_string(),
-                                ],
-                                max_results: 10,
-                            })),
-                        };
-                        
-                        let _ = tx.blocking_send(ServerMessage {
-                            message: Some(ServerMsg::ActionDirective(scan_action)),
+                    if let Some(asr) = asr {
+                        let state = self.state.clone();
+                        let service = self.clone();
+                        let tx = tx.clone();
+                        #[cfg(feature = "dashboard")]
+                        let dashboard = self.dashboard.clone();
+                        tokio::spawn(async move {
+                            let transcripts = asr::transcribe_utterance(
+                                asr.as_ref(),
+                                &npc_id,
+                                &player_uuid,
+                                utterance,
+                            );
+                            tokio::pin!(transcripts);
+                            while let Some(result) = transcripts.next().await {
+                                match result {
+                                    Ok(t) if t.is_final => {
+                                        let context = {
+                                            let mut state = state.lock().unwrap();
+                                            let ids = (
+                                                NpcId::new(t.npc_id.as_str()),
+                                                PlayerUuid::new(t.player_uuid.as_str()),
+                                            );
+                                            if let (Ok(npc), Ok(player)) = ids {
+                                                state.conversations.record_utterance(&npc, &player, &t.text, now_ms());
+                                                state.dialogues.record(npc.as_str(), player.as_str(), Speaker::Player, &t.text, now_ms());
+                                            }
+                                            #[cfg(feature = "dashboard")]
+                                            if let Some(dashboard) = &dashboard {
+                                                dashboard.transcript(&t.npc_id, &t.player_uuid, &t.text, now_ms());
+                                            }
+                                            state.conversations.context(&t.npc_id, now_ms())
+                                        };
+                                        #[cfg(feature = "persistence")]
+                                        service.transcribe(TranscriptEntry {
+                                            timestamp_ms: now_ms(),
+                                            server_id: state.lock().unwrap().server_id().to_string(),
+                                            npc_id: t.npc_id.clone(),
+                                            channel: Channel::Voice,
+                                            speaker: t.player_uuid.clone(),
+                                            text: t.text.clone(),
+                                            participants: context.participants.clone(),
+                                        });
+                                        info!(
+                                            npc_id = %t.npc_id,
+                                            player_uuid = %t.player_uuid,
+                                            text = %t.text,
+                                            participants = ?context.participants,
+                                            "Voice transcription"
+                                        );
+                                        // "Stop!" cannot wait for the LLM
+                                        service.run_voice_command(&t, &tx);
+                                    }
+                                    Ok(_) => {}
+                                    Err(e) => warn!(error = %e, "ASR failed"),
+                                }
+                            }
+                            // In production: process transcriptions that were not
+                            // voice commands with the LLM, passing the conversation
+                            // context
                         });
-                        
-                        info!(directive_id = %directive_id, npc_id = %npc.npc_id, "Sent ScanBlocksAction");
                     }
                 }
-                
-                // Every 50 ticks, send a move directive
-                if tick.server_tick % 50 == 0 && !tick.npcs.is_empty() {
-                    let npc = &tick.npcs[0];
-                    let directive_id = next_directive_id();
-                    
-                    let directive = ActionDirective {
-                        directive_id: directive_id.clone(),
-                        npc_id: npc.npc_id.clone(),
-                        priority: 1,
-                        action: Some(Action::Move(MoveAction {
-                            target: Some(Position {
-                                world: "world".to_string(),
-                                x: npc.position.as_ref().map(|p| p.x + 5.0).unwrap_or(0.0),
-                                y: npc.position.as_ref().map(|p| p.y).unwrap_or(64.0),
-                                z: npc.position.as_ref().map(|p| p.z).unwrap_or(0.0),
-                                yaw: 0.0,
-                                pitch: 0.0,
-                            }),
-                            speed: 0.5,
-                            pathfind: true,
-                        })),
-                    };
-                    
-                    let _ = tx.blocking_send(ServerMessage {
-                        message: Some(ServerMsg::ActionDirective(directive)),
-                    });
-                    
-                    debug!(directive_id = %directive_id, "Sent MoveAction");
+            }
+        }
+    }
+    
+    /// Append to the NPC_TRANSCRIPTS log, if there is one; like
+    /// [`Self::persist`], a failed write is only logged
+    #[cfg(feature = "persistence")]
+    fn transcribe(&self, entry: TranscriptEntry) {
+        if let Some(transcripts) = &self.transcripts {
+            if let Err(e) = transcripts.lock().unwrap().append(&entry) {
+                warn!(error = %e, npc_id = %entry.npc_id, "Failed to write transcript");
+            }
+        }
+    }
+    
+    /// Send transfer messages to the servers they are for
+    fn route(&self, outgoing: Vec<Outgoing>) {
+        for Outgoing { server_id, message } in outgoing {
+            let tx = self.servers.get(&server_id).and_then(|s| s.lock().unwrap().tx.clone());
+            let Some(tx) = tx else {
+                // The transfer times out and rolls back
+                warn!(server_id = %server_id, "Transfer message not sent: server not connected");
+                continue;
+            };
+            if let Err(error) = tx.send(message) {
+                warn!(server_id = %server_id, %error, "Transfer message not sent");
+            }
+        }
+    }
+    
+    /// Move the daemon's state of NPCs whose transfer committed, and answer
+    /// the TransferNpc calls waiting on finished transfers
+    fn finish_transfers(&self) {
+        let finished = self.transfers.lock().unwrap().take_finished();
+        for transfer in finished {
+            if transfer.is_committed() {
+                info!(
+                    transfer_id = %transfer.transfer_id,
+                    npc_id = %transfer.npc_id,
+                    from = %transfer.source_server_id,
+                    to = %transfer.target_server_id,
+                    "NPC transferred"
+                );
+                // Directives the source still had for the NPC can never finish
+                if let Some(source) = self.servers.get(&transfer.source_server_id) {
+                    let mut source = source.lock().unwrap();
+                    let (dropped, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut source.pending)
+                        .into_iter()
+                        .partition(|p| p.directive.as_ref().is_some_and(|d| d.npc_id == transfer.npc_id));
+                    source.pending = kept;
+                    for directive in dropped.into_iter().filter_map(|p| p.directive) {
+                        source.retries.forget(&directive.directive_id);
+                        source.watchdog.finished(&directive.directive_id);
+                    }
+                    source.behaviors.remove(&transfer.npc_id);
+                }
+                if let (Some(target), Some(snapshot)) =
+                    (self.servers.get(&transfer.target_server_id), &transfer.snapshot)
+                {
+                    target.lock().unwrap().reputation.restore(&transfer.npc_id, &snapshot.relationships);
+                }
+            } else {
+                warn!(
+                    transfer_id = %transfer.transfer_id,
+                    npc_id = %transfer.npc_id,
+                    error = %transfer.error_message,
+                    "NPC transfer rolled back"
+                );
+            }
+            if let Some(waiter) = self.transfer_waiters.lock().unwrap().remove(&transfer.transfer_id) {
+                let _ = waiter.send(transfer);
+            }
+        }
+    }
+    
+    /// Have `chat`'s NPC guard the area around it against everyone but the
+    /// player who posted it
+    fn post_guard(&self, cx: &ConnectionContext, tx: &Outbound, chat: &ChatObservation) {
+        let center = {
+            let state = self.state.lock().unwrap();
+            state
+                .latest_tick
+                .iter()
+                .flat_map(|tick| &tick.npcs)
+                .find(|npc| npc.npc_id == chat.npc_id)
+                .and_then(|npc| npc.position.as_ref())
+                .map(dimension::block_at)
+        };
+        let Some(center) = center else {
+            info!(npc_id = %chat.npc_id, "Guard not posted: position unknown");
+            return;
+        };
+        let corner = |offset: i32| BlockPosition {
+            x: center.x + offset,
+            y: center.y + offset,
+            z: center.z + offset,
+            ..center.clone()
+        };
+        let mut zone = guard::zone(
+            &chat.npc_id,
+            &format!("post-{}", chat.npc_id),
+            corner(-GUARD_ZONE_REACH),
+            corner(GUARD_ZONE_REACH),
+            std::slice::from_ref(&chat.player_uuid),
+        );
+        zone.directive_id = cx.next_directive_id(&chat.npc_id);
+        if let Err(error) = tx.send(zone) {
+            warn!(npc_id = %chat.npc_id, %error, "GuardZoneDirective not sent");
+        }
+    }
+    
+    /// Hand the player who asked a torch from the NPC's own stock
+    fn give_torch(&self, cx: &ConnectionContext, tx: &Outbound, chat: &ChatObservation) {
+        let gift = PlayerUuid::try_from(chat).map_err(|e| e.to_string()).and_then(|player| {
+            GiveItemAction::builder()
+                .player_uuid(&player)
+                .item_type("minecraft:torch")
+                .count(1)
+                .from_inventory(true)
+                .build()
+                .map_err(|e| e.to_string())
+        });
+        let gift = match gift {
+            Ok(gift) => gift,
+            Err(error) => {
+                warn!(npc_id = %chat.npc_id, %error, "Torch not given");
+                return;
+            }
+        };
+        let directive = ActionDirective {
+            directive_id: cx.next_directive_id(&chat.npc_id),
+            npc_id: chat.npc_id.clone(),
+            action: Some(gift.into()),
+            ..Default::default()
+        };
+        if let Err(failed) = self.send_directive(tx, directive) {
+            warn!(npc_id = %chat.npc_id, error = %failed.error_message, "GiveItemAction not sent");
+        }
+    }
+    
+    /// Hand the player who asked a book of the mine's history, signed by
+    /// the NPC
+    fn hand_out_story(&self, cx: &ConnectionContext, tx: &Outbound, chat: &ChatObservation) {
+        let book = PlayerUuid::try_from(chat).map_err(|e| e.to_string()).and_then(|player| {
+            WriteBookAction::builder()
+                .title("The Old Mine")
+                .pages([
+                    "Before the village, the hill was hollow. The first miners \
+                     followed a seam of iron down until the lanterns went out.",
+                    "They came back with enough ore for every door in town, and \
+                     one pickaxe that never dulled. It is still down there.",
+                ])
+                .player_uuid(&player)
+                .build()
+                .map_err(|e| e.to_string())
+        });
+        let book = match book {
+            Ok(book) => book,
+            Err(error) => {
+                warn!(npc_id = %chat.npc_id, %error, "Story not handed out");
+                return;
+            }
+        };
+        let directive = ActionDirective {
+            directive_id: cx.next_directive_id(&chat.npc_id),
+            npc_id: chat.npc_id.clone(),
+            action: Some(book.into()),
+            ..Default::default()
+        };
+        if let Err(failed) = self.send_directive(tx, directive) {
+            warn!(npc_id = %chat.npc_id, error = %failed.error_message, "WriteBookAction not sent");
+        }
+    }
+    
+    /// Courier runs from the miners' chest to the storehouse until the
+    /// chest has no diamonds left; the plugin runs every leg itself
+    fn haul_diamonds(&self, cx: &ConnectionContext, tx: &Outbound, npc_id: &str) {
+        let (from, to) = {
+            let state = self.state.lock().unwrap();
+            let landmarks = state.landmarks.lock().unwrap();
+            (landmarks.block(MINERS_CHEST), landmarks.block(STOREHOUSE_CHEST))
+        };
+        let (Some(from), Some(to)) = (from, to) else {
+            info!(npc_id, "Diamonds not hauled: needs the {MINERS_CHEST} and {STOREHOUSE_CHEST} landmarks");
+            return;
+        };
+        let haul = TransportItemsDirective {
+            directive_id: cx.next_directive_id(npc_id),
+            npc_id: npc_id.to_string(),
+            from_container: Some(from),
+            to_container: Some(to),
+            item_types: vec!["minecraft:diamond".to_string()],
+            ..Default::default()
+        };
+        if let Err(error) = tx.send(haul) {
+            warn!(npc_id, %error, "TransportItemsDirective not sent");
+        }
+    }
+    
+    /// Let `npc_id` summon a thunderstorm over its world, if the policy
+    /// allows world control and the plugin granted it on this connection
+    fn summon_storm(&self, cx: &ConnectionContext, tx: &Outbound, npc_id: &str) {
+        let storm = ServerMessage::from(SetWeatherDirective {
+            directive_id: cx.next_directive_id(npc_id),
+            npc_id: npc_id.to_string(),
+            weather: Weather::Thunder as i32,
+            duration_ticks: STORM_TICKS,
+            reason: format!("{} summoned a storm", npc_id),
+            ..Default::default()
+        });
+        {
+            let state = self.state.lock().unwrap();
+            if let Err(error) = state.policy.check_world_control(&storm) {
+                info!(%error, "Storm not summoned");
+                return;
+            }
+            if !cx.hello().is_some_and(|hello| hello.world_control_available) {
+                info!(npc_id, "Storm not summoned: the plugin does not offer world control");
+                return;
+            }
+        }
+        if let Err(error) = tx.send(storm) {
+            warn!(npc_id, %error, "SetWeatherDirective not sent");
+            return;
+        }
+        
+        // Everyone gets the storm, so everyone hears who brought it
+        let mut announcement = SpeakDirective {
+            npc_id: npc_id.to_string(),
+            text: "The sky answers my call. Take shelter!".to_string(),
+            duration_ms: 4000,
+            directive_id: cx.next_directive_id(npc_id),
+            delivery: SpeechDelivery::Global as i32,
+            ..Default::default()
+        };
+        // Not a canonical emotion: plugins that know "ominous" can darken
+        // the subtitle, the rest show it neutral
+        announcement.set_tone(Tone::Custom("ominous".to_string()));
+        self.fit_routing(cx, &mut announcement);
+        if let Err(error) = tx.send(announcement) {
+            warn!(npc_id, %error, "Storm announcement not sent");
+        }
+    }
+    
+    /// Say a stock line to a player from a cached clip: synthesized and
+    /// uploaded the first time, played from the plugin's cache after that
+    fn bark(&self, cx: &ConnectionContext, tx: &Outbound, npc_id: &str, player_uuid: &str, asset_id: &'static str, text: &str) {
+        let mut speak = SpeakDirective {
+            npc_id: npc_id.to_string(),
+            text: text.to_string(),
+            directive_id: cx.next_directive_id(npc_id),
+            voice_id: self.voice_id(npc_id),
+            volume: 0.8,
+            target_player_uuids: vec![player_uuid.to_string()],
+            delivery: SpeechDelivery::Direct as i32,
+            ..Default::default()
+        };
+        self.fit_routing(cx, &mut speak);
+        let Some(tts) = self.tts.clone() else {
+            if let Err(error) = tx.send(speak) {
+                warn!(npc_id, %error, "SpeakDirective not sent");
+            }
+            return;
+        };
+        let state = self.state.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let synthesize = {
+                let mut state = state.lock().unwrap();
+                !state.audio_assets.contains(asset_id) && charge_tts(&mut state.spend, &speak)
+            };
+            if synthesize {
+                match tts::synthesize_speech(&*tts, &speak).await {
+                    Ok(clip) => {
+                        if !state.lock().unwrap().audio_assets.add(asset_id, &clip.samples, clip.sample_rate_hz) {
+                            warn!(asset_id, "Clip too long for an audio asset");
+                        }
+                    }
+                    Err(e) => warn!(asset_id, error = %e, "TTS failed, sending subtitle only"),
+                }
+            }
+            let messages = state.lock().unwrap().audio_assets.play(asset_id, speak.clone());
+            for message in messages.unwrap_or_else(|| vec![speak.into()]) {
+                if let Err(error) = tx.send(message) {
+                    warn!(asset_id, %error, "Stock line not sent");
+                    return;
+                }
+            }
+        });
+    }
+    
+    /// Fall back to a delivery the connected plugin can route
+    fn fit_routing(&self, cx: &ConnectionContext, speak: &mut SpeakDirective) {
+        let Some(hello) = cx.hello() else {
+            return;
+        };
+        if let Some(unsupported) = routing::fit(speak, hello) {
+            debug!(
+                npc_id = %speak.npc_id,
+                ?unsupported,
+                delivery = ?speak.delivery(),
+                "Plugin cannot route this delivery, falling back"
+            );
+        }
+    }
+    
+    /// Send an ActionDirective and track it until its ActionResult arrives.
+    /// Directives the NPC's policy or the land claims around it forbid,
+    /// directives aimed at another world, directives for an NPC in transfer,
+    /// and directives the outbound queue has no room for are not sent; the
+    /// returned failed ActionResult says why. Movement hints the plugin
+    /// cannot plan are dropped first.
+    fn send_directive(&self, tx: &Outbound, mut directive: ActionDirective) -> Result<(), Box<ActionResult>> {
+        if let Some(Action::Move(action)) = &mut directive.action {
+            let state = self.state.lock().unwrap();
+            if let Some(hello) = state.connection.as_ref().and_then(|cx| cx.hello()) {
+                let unsupported = movement::fit(action, hello);
+                if !unsupported.is_empty() {
+                    debug!(
+                        directive_id = %directive.directive_id,
+                        ?unsupported,
+                        "Plugin cannot plan these movement modes, dropping the hints"
+                    );
+                }
+            }
+        }
+        
+        let failed = |error: &dyn std::fmt::Display| Box::new(ActionResult {
+            directive_id: directive.directive_id.clone(),
+            npc_id: directive.npc_id.clone(),
+            success: false,
+            error_message: error.to_string(),
+            ..Default::default()
+        });
+        
+        if let Some(transfer) = self.transfers.lock().unwrap().active(&directive.npc_id) {
+            return Err(failed(&format!("NPC is in transfer {}", transfer.transfer_id)));
+        }
+        if self.lifecycle.is_draining() {
+            return Err(failed(&"daemon is shutting down"));
+        }
+        
+        let mut state = self.state.lock().unwrap();
+        let npc = state
+            .latest_tick
+            .iter()
+            .flat_map(|tick| &tick.npcs)
+            .find(|npc| npc.npc_id == directive.npc_id);
+        let allowed = state.policy.check(&directive).and_then(|_| match npc {
+            Some(npc) => {
+                policy::check_world(&directive, npc)?;
+                policy::check_claims(&directive, &npc.regions)
+            }
+            None => Ok(()),
+        });
+        if let Err(error) = allowed {
+            return Err(failed(&error));
+        }
+        // Held back directives fail like any other; behavior trees try
+        // again on a later tick, which is the back-off
+        if let Err(error) = state.throttle.acquire(&directive, now_ms()) {
+            return Err(failed(&error));
+        }
+        
+        if let Err(error) = tx.send(directive.clone()) {
+            return Err(failed(&error));
+        }
+        state.retries.track(&directive);
+        state.watchdog.sent(&directive, now_ms());
+        state.watches.start(&directive);
+        state.stations.start(&directive);
+        #[cfg(feature = "persistence")]
+        self.persist(|store| store.log_directive(state.server_id(), &directive, now_ms()));
+        state.pending.push(PendingDirective {
+            directive: Some(directive),
+            sent_at_ms: now_ms(),
+            ack: None,
+        });
+        Ok(())
+    }
+    
+    /// Resend a failed directive after its backoff. The retry tracker
+    /// already knows the new directive_id.
+    fn schedule_retry(&self, tx: &Outbound, directive: ActionDirective, after: Duration) {
+        {
+            let mut state = self.state.lock().unwrap();
+            let sent_at_ms = now_ms() + after.as_millis() as i64;
+            state.watchdog.sent(&directive, sent_at_ms);
+            state.pending.push(PendingDirective {
+                directive: Some(directive.clone()),
+                sent_at_ms,
+                ack: None,
+            });
+        }
+        
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(after).await;
+            let directive_id = directive.directive_id.clone();
+            if let Err(error) = tx.send_wait(directive).await {
+                warn!(directive_id = %directive_id, %error, "Retry not sent");
+            }
+        });
+    }
+
+    /// Show a message from the plugin to the wire tap
+    fn tap_inbound(&self, msg: &ClientMessage) {
+        if self.tap.is_empty() {
+            return;
+        }
+        let state = self.state.lock().unwrap();
+        self.tap.inbound(state.server_id(), msg, now_ms());
+    }
+
+    /// What goes on the wire of one of a connection's streams (Connect or
+    /// ConnectAudio), whose server state is `state`.
+    /// Last line of defence: never put a malformed message on the wire.
+    /// Messages nobody waits for anymore are dropped too; the rest are
+    /// stamped for the plugin's clock sync and latency tracking
+    fn wire(
+        &self,
+        messages: impl Stream<Item = ServerMessage> + Send + 'static,
+        state: Arc<Mutex<SharedState>>,
+    ) -> Pin<Box<dyn Stream<Item = Result<ServerMessage, Status>> + Send>> {
+        let mut sequences = SequenceTracker::new(0);
+        let tap = self.tap.clone();
+        let out_stream = messages.filter_map(move |mut msg| {
+            if let Err(e) = msg.validate().and_then(|_| sequences.check_server(&msg)) {
+                error!(error = %e, "Dropping invalid server message");
+                return None;
+            }
+            let now = now_ms();
+            let mut state = state.lock().unwrap();
+            if latency::is_expired(&msg, now) {
+                debug!(message = ?msg.message, "Dropping server message past its deadline");
+                state.latency.on_expired(&msg);
+                return None;
+            }
+            state.latency.stamp(&mut msg, now);
+            tap.outbound(state.server_id(), &msg, now);
+            Some(msg)
+        });
+        // Ends when shutdown has drained, so the server can stop
+        Box::pin(self.lifecycle.until_stopped(out_stream).map(Ok))
+    }
+
+    /// Process an incoming client message and return responses.
+    fn handle_client_message(&self, mut msg: ClientMessage, cx: &ConnectionContext, tx: &Outbound) {
+        cx.count_received();
+        #[cfg(feature = "persistence")]
+        {
+            let record = {
+                let mut state = self.state.lock().unwrap();
+                let record = state.recorder.should_record(&msg, now_ms());
+                record.then(|| state.server_id().to_string())
+            };
+            if let Some(server_id) = record {
+                self.persist(|store| store.append_event(&server_id, &msg, now_ms()));
+            }
+        }
+        
+        let timing = msg.timing.take();
+        let event = match ClientEvent::try_from(msg) {
+            Ok(event) => event,
+            Err(_) => {
+                warn!("Received empty client message");
+                return;
+            }
+        };
+        let message_type = event.message_type();
+        let arrival = self
+            .state
+            .lock()
+            .unwrap()
+            .latency
+            .on_received(timing.as_ref(), message_type, now_ms());
+        if arrival.over_budget {
+            warn!(message_type, delay_ms = ?arrival.delay_ms, "Message over its latency budget");
+        }
+        if arrival.expired {
+            debug!(message_type, npc_id = %event.npc_id(), "Dropping message past its deadline");
+            return;
+        }
+        events::dispatch(self, event, cx, tx);
+    }
+}
+
+/// The example's reaction to each client message
+impl NpcSocietyHandler for ExampleNpcSocietyService {
+    fn on_hello(&self, hello: Hello, cx: &ConnectionContext, tx: &Outbound) {
+        // Example A: Log v1.1+ handshake fields
+        info!(
+            plugin_version = %hello.plugin_version,
+            protocol_version = %hello.protocol_version,
+            server_id = %hello.server_id,
+            minecraft_version = %hello.minecraft_version,
+            voice_available = hello.voice_available,
+            voice_consent_required = hello.voice_consent_required,
+            voice_capture_on_request = hello.voice_capture_on_request,
+            server_name = %hello.server_name,
+            daemon_mode = %hello.daemon_mode,
+            supported_audio_formats = ?hello.supported_audio_formats,
+            world_control_available = hello.world_control_available,
+            probe_available = hello.probe_available,
+            debug_path_available = hello.debug_path_available,
+            keep_chunk_loaded_available = hello.keep_chunk_loaded_available,
+            supported_deliveries = ?hello.supported_deliveries,
+            supported_movement = ?hello.supported_movement,
+            supported_voice_sample_rates_hz = ?hello.supported_voice_sample_rates_hz,
+            cached_audio_assets = hello.cached_audio_assets.len(),
+            "Received Hello handshake"
+        );
+        
+        if hello.voice_available {
+            info!("Voice chat is available - TTS audio will be sent");
+        }
+        
+        // Complete negotiation. Voice comes as Opus where both sides can
+        // code it (decoded again before segmentation); TTS is always sent
+        // as raw PCM, which every plugin plays without a codec.
+        let opus = PcmFormat::Opus as i32;
+        let voice_format = if cfg!(feature = "audio-opus") && hello.supported_audio_formats.contains(&opus) {
+            PcmFormat::Opus
+        } else {
+            PcmFormat::S16le
+        };
+        let ack = HelloAck {
+            protocol_version: "1".to_string(),
+            daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+            voice_format: voice_format as i32,
+            playback_format: PcmFormat::S16le as i32,
+            // Ask for time and weather only if the policy allows them
+            world_control: hello.world_control_available
+                && self.state.lock().unwrap().policy.world_control(),
+            // Voice is only transcribed (and mixed) at 16kHz; anything
+            // more is bandwidth thrown away by the resampler
+            voice_sample_rate_hz: audio::negotiate_voice_rate(&hello, audio::ASR_SAMPLE_RATE_HZ),
+            // Voice and TTS audio get their own stream where the plugin can
+            // open one, so they never hold up directives and results
+            audio_stream_token: if hello.audio_stream_available {
+                audio_stream_token(cx)
+            } else {
+                String::new()
+            },
+        };
+        
+        cx.record_ack(&ack);
+        if let Err(error) = tx.send(ack) {
+            warn!(%error, "HelloAck not sent");
+        }
+        
+        // Only ask for the events on_event uses; on a busy server the
+        // rest would be most of the traffic
+        let subscription = SubscribeEvents::builder()
+            .event_types([EventType::Block, EventType::Combat, EventType::Explosion])
+            .build();
+        match subscription {
+            Ok(subscription) => {
+                if let Err(error) = tx.send(subscription) {
+                    warn!(%error, "SubscribeEvents not sent");
+                }
+            }
+            Err(error) => warn!(%error, "Invalid event subscription"),
+        }
+        
+        // Fill the landmark cache, unless the WorldBootstrap will; the
+        // plugin pushes changes from then on
+        if !hello.world_bootstrap_available {
+            if let Err(error) = tx.send(landmarks::list()) {
+                warn!(%error, "ListLandmarks not sent");
+            }
+        }
+        
+        // This connection's state becomes the server's state, with whatever
+        // an earlier connection of the same server left pending
+        self.servers.attach(&hello.server_id, &self.state, SharedState::adopt);
+        
+        // Resend whatever the last connection left unanswered, including
+        // retries it had scheduled. The plugin runs each directive_id once,
+        // so directives it already got are answered, not repeated.
+        let unanswered: Vec<ActionDirective> = {
+            let mut state = self.state.lock().unwrap();
+            // A restarted plugin may have lost clips it had
+            state.audio_assets.on_hello(&hello);
+            state.consents.on_hello(&hello);
+            state.pending.iter().filter_map(|p| p.directive.clone()).collect()
+        };
+        for directive in unanswered {
+            self.state.lock().unwrap().watchdog.sent(&directive, now_ms());
+            let directive_id = directive.directive_id.clone();
+            match tx.send(directive) {
+                Ok(()) => info!(directive_id = %directive_id, "Resent unanswered directive"),
+                Err(error) => warn!(directive_id = %directive_id, %error, "Directive not resent"),
+            }
+        }
+        
+        // The plugin dropped its displays with the last connection
+        let displays: Vec<ShowDisplayDirective> =
+            self.state.lock().unwrap().displays.all().cloned().collect();
+        for display in displays {
+            if let Err(error) = tx.send(display) {
+                warn!(%error, "Display not resent");
+            }
+        }
+        
+        // Check the connection end to end before relying on it
+        self.start_probe(cx, tx);
+    }
+    
+    fn on_world_bootstrap(&self, bootstrap: WorldBootstrap, _cx: &ConnectionContext, _tx: &Outbound) {
+        info!(
+            npcs = bootstrap.npcs.len(),
+            landmarks = bootstrap.landmarks.len(),
+            regions = bootstrap.regions.len(),
+            containers = bootstrap.containers.len(),
+            worlds = bootstrap.environments.len(),
+            "WorldBootstrap received"
+        );
+        let mut state = self.state.lock().unwrap();
+        state.landmarks.lock().unwrap().ingest_bootstrap(&bootstrap);
+        state.world.ingest_bootstrap(&bootstrap);
+    }
+    
+    fn on_probe_reply(&self, reply: ProbeReply, cx: &ConnectionContext, tx: &Outbound) {
+        let ours = self
+            .state
+            .lock()
+            .unwrap()
+            .probe
+            .as_mut()
+            .is_some_and(|test| test.on_reply(&reply, now_ms()));
+        if !ours {
+            debug!(nonce = %reply.nonce, "ProbeReply to no running self-check");
+        }
+        self.check_probe(cx, tx);
+    }
+    
+    fn on_chunk_load(&self, chunk: ChunkLoadObservation, _cx: &ConnectionContext, _tx: &Outbound) {
+        info!(
+            npc_id = %chunk.npc_id,
+            loaded = chunk.loaded,
+            world = %chunk.world,
+            chunk_x = chunk.chunk_x,
+            chunk_z = chunk.chunk_z,
+            parked = chunk.parked_directive_ids.len(),
+            "NPC chunk {}",
+            if chunk.loaded { "loaded" } else { "unloaded" }
+        );
+        // Parked directives run once the chunk loads: don't time them out
+        let mut state = self.state.lock().unwrap();
+        if chunk.loaded {
+            state.watchdog.unpark(&chunk.npc_id, now_ms());
+        } else {
+            state.watchdog.park(&chunk.npc_id);
+        }
+    }
+    
+    fn on_world_tick(&self, tick: WorldTick, cx: &ConnectionContext, tx: &Outbound) {
+        debug!(
+            server_tick = tick.server_tick,
+            npcs = tick.npcs.len(),
+            players = tick.nearby_players.len(),
+            mspt = tick.mspt,
+            loaded_chunks = tick.loaded_chunks,
+            entities = tick.entity_count,
+            "WorldTick received"
+        );
+        
+        #[cfg(feature = "lease-redis")]
+        self.sync_leases(&tick);
+        
+        // Probes that never got a reply count as lost by now
+        self.check_probe(cx, tx);
+        
+        // Transfers whose servers went quiet roll back
+        let expired = self.transfers.lock().unwrap().expire(now_ms());
+        self.route(expired);
+        self.finish_transfers();
+        
+        let speaker_events = {
+            let mut state = self.state.lock().unwrap();
+            state.world.ingest_tick(&tick);
+            // Catches chunk loads whose ChunkLoadObservation was missed
+            for npc in &tick.npcs {
+                if npc.parked {
+                    state.watchdog.park(&npc.npc_id);
+                } else {
+                    state.watchdog.unpark(&npc.npc_id, now_ms());
+                }
+            }
+            state.reputation.observe_tick(&tick);
+            state.consents.observe_tick(&tick);
+            for quarantine in state.moderation.observe_tick(&tick) {
+                self.moderation.notify(&ModerationNotice::Quarantine {
+                    server_id: state.server_id(),
+                    directive: &quarantine,
+                });
+            }
+            let was_lagging = state.clock.is_lagging();
+            state.clock.observe(&tick);
+            state.throttle.observe(&tick);
+            if state.clock.is_lagging() && !was_lagging {
+                warn!(tps = state.clock.tps(), "Server is lagging, game time runs slow");
+            }
+            state.latest_tick = Some(tick.clone());
+            // Decoders of voice streams that went quiet
+            #[cfg(feature = "audio-opus")]
+            {
+                let now = now_ms();
+                state.opus_voice.retain(|_, (_, last_used_ms)| now - *last_used_ms <= OPUS_DECODER_IDLE_MS);
+            }
+            // Players who stopped talking without a silent frame
+            let speaker_events = state.conversations.prune(now_ms());
+            for session in state.dialogues.prune(now_ms()) {
+                debug!(
+                    npc_id = %session.npc_id,
+                    player_uuid = %session.player_uuid,
+                    turns = session.turns.len(),
+                    "Dialogue session ended"
+                );
+            }
+            state.prompts.prune(now_ms());
+            speaker_events
+        };
+        self.on_speaker_events(speaker_events, tx);
+        
+        // Held lines whose NPC may speak again go out as subtitles; their
+        // audio would only be later still
+        let due = self.state.lock().unwrap().speech_rate.release(now_ms());
+        for speak in due {
+            if let Err(error) = tx.send(speak) {
+                warn!(%error, "Held SpeakDirective not sent");
+            }
+        }
+        
+        // NPCs imported with ImportNpcState: put them back in the world
+        // once the plugin reports them, then resume what they were doing
+        let restores: Vec<NpcStateSnapshot> = {
+            let mut state = self.state.lock().unwrap();
+            let (ready, waiting) = std::mem::take(&mut state.restores)
+                .into_iter()
+                .partition(|r| tick.npcs.iter().any(|npc| npc.npc_id == r.npc_id));
+            state.restores = waiting;
+            ready
+        };
+        for snapshot in restores {
+            if let Err(error) = tx.send(npc_state::restore_directive(&snapshot)) {
+                warn!(npc_id = %snapshot.npc_id, %error, "RestoreNpcState not sent");
+                continue;
+            }
+            info!(npc_id = %snapshot.npc_id, exported_at_ms = snapshot.exported_at_ms, "Restoring NPC");
+            for directive in snapshot.active_directives.into_iter().filter_map(|p| p.directive) {
+                if let Err(failed) = self.send_directive(tx, directive) {
+                    warn!(
+                        directive_id = %failed.directive_id,
+                        error = %failed.error_message,
+                        "Restored directive not sent"
+                    );
+                }
+            }
+        }
+        
+        // Directives that got no ack or result in time: resend them once
+        // (the plugin runs each directive_id only once), then fail them
+        let orphans = self.state.lock().unwrap().watchdog.check(now_ms());
+        for orphan in orphans {
+            let directive = orphan.directive;
+            warn!(
+                directive_id = %directive.directive_id,
+                npc_id = %directive.npc_id,
+                stage = ?orphan.stage,
+                resends = orphan.resends,
+                resend = orphan.resend,
+                "Directive orphaned"
+            );
+            if orphan.resend {
+                if let Err(error) = tx.send(directive) {
+                    warn!(%error, "Orphaned directive not resent");
+                }
+                continue;
+            }
+            let error_message = match orphan.stage {
+                OrphanStage::Unacknowledged => "orphaned: never acknowledged",
+                OrphanStage::Unfinished => "orphaned: no result",
+            };
+            let result = ActionResult {
+                directive_id: directive.directive_id,
+                npc_id: directive.npc_id,
+                success: false,
+                error_message: error_message.to_string(),
+                ..Default::default()
+            };
+            self.on_action_result(result, cx, tx);
+        }
+        
+        // Let the plugin handle self-defense of NPCs new on this connection,
+        // so a miner fights back without a round-trip per hit
+        let new_npcs: Vec<NpcId> = {
+            let mut state = self.state.lock().unwrap();
+            tick.npcs
+                .iter()
+                .filter(|npc| state.combat_policies.insert(npc.npc_id.clone()))
+                .filter_map(|npc| NpcId::try_from(npc).ok())
+                .collect()
+        };
+        for npc_id in new_npcs {
+            let combat_policy = SetCombatPolicyDirective::builder()
+                .npc_id(&npc_id)
+                .stance(CombatStance::Defensive)
+                .flee_below(0.3)
+                .target_filters([
+                    TargetFilter {
+                        entity_type: "minecraft:creeper".to_string(),
+                        exclude: true,
+                    },
+                    TargetFilter {
+                        entity_type: "#minecraft:undead".to_string(),
+                        exclude: false,
+                    },
+                ])
+                .max_chase_distance(8.0)
+                .build();
+            match combat_policy {
+                Ok(combat_policy) => {
+                    if let Err(error) = tx.send(combat_policy) {
+                        warn!(%error, "SetCombatPolicyDirective not sent");
+                    }
+                }
+                Err(error) => warn!(%error, "Invalid combat policy"),
+            }
+        }
+        
+        // Example D: Mining perception loop, one behavior tree per NPC
+        // this replica drives. Frozen and parked NPCs would only hold the
+        // directives, so their trees wait for the resume or the chunk load.
+        let directives: Vec<ActionDirective> = {
+            let mut state = self.state.lock().unwrap();
+            let landmarks = state.landmarks.clone();
+            tick.npcs
+                .iter()
+                .filter(|npc| self.owns(&npc.npc_id) && !npc.frozen && !npc.parked)
+                .flat_map(|npc| {
+                    state
+                        .behaviors
+                        .entry(npc.npc_id.clone())
+                        .or_insert_with(|| {
+                            mining_tree(&npc.npc_id, landmarks.clone()).with_ids(self.ids.clone())
+                        })
+                        .on_world_tick(&tick)
+                })
+                .collect()
+        };
+        
+        for directive in directives {
+            debug!(
+                directive_id = %directive.directive_id,
+                npc_id = %directive.npc_id,
+                action = ?directive.action.as_ref().map(retry::action_kind),
+                "Sent behavior tree directive"
+            );
+            if let Err(result) = self.send_directive(tx, directive) {
+                warn!(
+                    directive_id = %result.directive_id,
+                    error = %result.error_message,
+                    "Directive not sent"
+                );
+                // Fail the leaf so the tree moves on instead of waiting forever
+                if let Some(tree) = self.state.lock().unwrap().behaviors.get_mut(&result.npc_id) {
+                    tree.on_action_result(&result);
+                }
+            }
+        }
+    }
+    
+    fn on_chat(&self, chat: ChatObservation, cx: &ConnectionContext, tx: &Outbound) {
+        if self.commands.is_command(&chat) {
+            self.run_command(&chat, cx, tx);
+            return;
+        }
+        let chat = match self.chat.run(chat) {
+            Ok(enriched) => {
+                let annotations = &enriched.annotations;
+                info!(
+                    npc_id = %enriched.chat.npc_id,
+                    player_name = %enriched.chat.player_name,
+                    message = %enriched.chat.message,
+                    language = ?annotations.language,
+                    intent = ?annotations.intent.as_ref().map(|i| &i.label),
+                    "Chat observation received"
+                );
+                enriched.chat
+            }
+            Err(dropped) => {
+                info!(%dropped, "Chat observation dropped");
+                return;
+            }
+        };
+        let (first_message, player) = {
+            let mut state = self.state.lock().unwrap();
+            state.reputation.observe_chat(&chat);
+            let player = state
+                .latest_tick
+                .iter()
+                .flat_map(|tick| &tick.nearby_players)
+                .find(|p| p.player_uuid == chat.player_uuid)
+                .cloned();
+            (state.dialogues.on_chat(&chat).player_turns() == 1, player)
+        };
+        // Every replica keeps up reputation and dialogue, so whichever
+        // takes over the NPC knows the conversation; only the owner answers
+        if !self.owns(&chat.npc_id) {
+            debug!(npc_id = %chat.npc_id, "Chat for an NPC another replica drives");
+            return;
+        }
+        let privileged = player.as_ref().is_some_and(players::is_privileged);
+        #[cfg(feature = "persistence")]
+        self.persist(|store| store.append_turn(&chat.npc_id, &chat.player_uuid, &Turn {
+            speaker: Speaker::Player,
+            text: chat.message.clone(),
+            timestamp_ms: chat.timestamp_ms,
+        }));
+        #[cfg(feature = "persistence")]
+        self.transcribe(TranscriptEntry {
+            timestamp_ms: chat.timestamp_ms,
+            server_id: cx.server_id().to_string(),
+            npc_id: chat.npc_id.clone(),
+            channel: Channel::Chat,
+            speaker: chat.player_uuid.clone(),
+            text: chat.message.clone(),
+            participants: vec![chat.player_uuid.clone()],
+        });
+        // A quarantined NPC says nothing until a moderator releases it
+        if self.state.lock().unwrap().moderation.is_quarantined(&chat.npc_id) {
+            debug!(npc_id = %chat.npc_id, "Chat for a quarantined NPC");
+            return;
+        }
+        
+        // Example E: Send SpeakDirective with correlation fields + audio
+        let directive_id = cx.next_directive_id(&chat.npc_id);
+        let stream_id = next_stream_id(cx);
+        
+        // Greet once per session; in production, hand the session's turns
+        // to the LLM instead
+        // Staff get a status report rather than an offer of help
+        // Answer in the player's language (v1.2+ player_locale)
+        let key = if first_message && privileged {
+            "status"
+        } else if first_message {
+            "greeting"
+        } else {
+            "progress"
+        };
+        let reply = self.lines
+            .render(key, &chat.player_locale, &[("player", &chat.player_name)])
+            .expect("every miner line has an English translation");
+        
+        // Offer the usual requests as clickable replies rather than
+        // waiting for the player to guess the right words
+        if first_message && !privileged {
+            let prompt = DialogueOptionsDirective {
+                prompt_id: cx.next_id("prompt"),
+                npc_id: chat.npc_id.clone(),
+                player_uuid: chat.player_uuid.clone(),
+                options: vec![
+                    prompts::option("diamonds", "Where are the diamonds?"),
+                    prompts::option("follow", "Follow me"),
+                    prompts::option("trade", "What do you sell?"),
+                    prompts::option("bye", "Never mind"),
+                ],
+                expires_at_ms: now_ms() + DIALOGUE_PROMPT_TTL_MS,
+                ..Default::default()
+            };
+            self.state.lock().unwrap().prompts.offer(&prompt);
+            if let Err(error) = tx.send(prompt) {
+                warn!(npc_id = %chat.npc_id, %error, "DialogueOptionsDirective not sent");
+            }
+        }
+        
+        // Where voice is only forwarded on request, listen to the player
+        // for a while; players who did not consent are never asked for
+        let capture = {
+            let mut state = self.state.lock().unwrap();
+            if state.consents.on_request() {
+                let player = [&chat.player_uuid];
+                state.consents.request(&chat.npc_id, player, VOICE_CAPTURE_MS, now_ms())
+            } else {
+                None
+            }
+        };
+        if let Some(Err(error)) = capture.map(|capture| tx.send(capture)) {
+            warn!(npc_id = %chat.npc_id, %error, "RequestVoiceCapture not sent");
+        }
+        
+        // Staff can stage a storm for a story event
+        if privileged && chat.message.to_lowercase().contains("storm") {
+            self.summon_storm(cx, tx, &chat.npc_id);
+        }
+        
+        // The miner plays for anyone who asks; the plugin times the notes
+        if chat.message.to_lowercase().contains("song") {
+            match music::song(&chat.npc_id, "Miner's Tune", MINER_SONG, 140, NoteInstrument::Banjo) {
+                Ok(mut song) => {
+                    song.directive_id = cx.next_directive_id(&chat.npc_id);
+                    if let Err(error) = tx.send(song) {
+                        warn!(npc_id = %chat.npc_id, %error, "PlayMusicDirective not sent");
+                    }
+                }
+                Err(error) => warn!(%error, "Invalid song"),
+            }
+        }
+        
+        // The miner spares a torch for anyone who asks; what does not fit
+        // in the player's inventory lands at their feet
+        if chat.message.to_lowercase().contains("torch") {
+            self.give_torch(cx, tx, &chat);
+        }
+        
+        // Storytellers leave something behind: asking for a story gets a
+        // written book the player keeps
+        if chat.message.to_lowercase().contains("story") {
+            self.hand_out_story(cx, tx, &chat);
+        }
+        
+        // Staff can have the miner open or close the mine's gate. Setting
+        // the lever's state instead of flipping it keeps a resent
+        // directive from closing the gate again
+        let message = chat.message.to_lowercase();
+        if privileged && (message.contains("open the mine") || message.contains("close the mine")) {
+            let lever = self.state.lock().unwrap().landmarks.lock().unwrap().block(MINE_GATE_LEVER);
+            let toggle = lever.map(|lever| {
+                ToggleLeverAction::builder()
+                    .position(lever)
+                    .powered(message.contains("open the mine"))
+                    .build()
+            });
+            match toggle {
+                Some(Ok(toggle)) => {
+                    let directive = ActionDirective {
+                        directive_id: cx.next_directive_id(&chat.npc_id),
+                        npc_id: chat.npc_id.clone(),
+                        action: Some(toggle.into()),
+                        ..Default::default()
+                    };
+                    if let Err(failed) = self.send_directive(tx, directive) {
+                        warn!(npc_id = %chat.npc_id, error = %failed.error_message, "ToggleLeverAction not sent");
+                    }
+                }
+                Some(Err(error)) => warn!(npc_id = %chat.npc_id, %error, "Gate not toggled"),
+                None => info!(landmark = MINE_GATE_LEVER, "Gate not toggled: no such landmark"),
+            }
+        }
+        
+        // Staff can send the miner on courier runs, hauling the diamonds
+        // from its chest to the storehouse
+        if privileged && message.contains("haul") {
+            self.haul_diamonds(cx, tx, &chat.npc_id);
+        }
+        
+        // Staff can lock the miners' chest, keeping its key themselves
+        if privileged && chat.message.to_lowercase().contains("lock the chest") {
+            let chest = self.state.lock().unwrap().landmarks.lock().unwrap().block(MINERS_CHEST);
+            match chest {
+                Some(chest) => {
+                    let mut claim = storage::claim(
+                        &chat.npc_id,
+                        chest,
+                        "Miner's Chest",
+                        true,
+                        std::slice::from_ref(&chat.player_uuid),
+                    );
+                    claim.directive_id = cx.next_directive_id(&chat.npc_id);
+                    self.state.lock().unwrap().world.claim_container(&claim);
+                    if let Err(error) = tx.send(claim) {
+                        warn!(npc_id = %chat.npc_id, %error, "ClaimContainerDirective not sent");
+                    }
+                }
+                None => info!(landmark = MINERS_CHEST, "Chest not locked: no such landmark"),
+            }
+        }
+        
+        // Staff name places where they stand: "landmark miners_chest"
+        if let Some(name) = chat.message.strip_prefix("landmark ").map(str::trim) {
+            match player.as_ref().and_then(|p| p.position.clone()) {
+                Some(position) if privileged && !name.is_empty() => {
+                    let mut landmark = landmarks::register(name, position, "");
+                    landmark.directive_id = cx.next_directive_id(&chat.npc_id);
+                    if let Err(error) = tx.send(landmark) {
+                        warn!(%name, %error, "RegisterLandmark not sent");
+                    }
+                }
+                _ => debug!(%name, "Landmark not set"),
+            }
+        }
+        
+        // Staff can post an NPC as a guard where it stands
+        if privileged && chat.message.to_lowercase().contains("guard") {
+            self.post_guard(cx, tx, &chat);
+        }
+        
+        // A guard asked to escort keeps at the player's side until disbanded
+        if chat.message.to_lowercase().contains("escort") {
+            let formation_id = format!("escort-{}", chat.player_uuid);
+            let mut escort = formation::escort(&formation_id, &chat.player_uuid, std::slice::from_ref(&chat.npc_id), 3.0);
+            escort.directive_id = cx.next_directive_id(&chat.npc_id);
+            if let Err(error) = tx.send(escort) {
+                warn!(npc_id = %chat.npc_id, %error, "FormationDirective not sent");
+            }
+        }
+        
+        // Send SpeakDirective with v1.1+ correlation fields
+        let mut speak = SpeakDirective {
+            npc_id: chat.npc_id.clone(),
+            text: reply.text,
+            locale: reply.locale,
+            // v1.2+ canonical emotion, with its name for older plugins
+            emotion: emotion::name(Emotion::Happy).to_string(),
+            emotion_type: Emotion::Happy as i32,
+            duration_ms: 3000,
+            // v1.1+ fields for correlation
+            directive_id: directive_id.clone(),
+            voice_id: self.voice_id(&chat.npc_id),
+            volume: 0.8,
+            stream_id: stream_id.to_string(), // Must match AudioChunk.stream_id
+            // v1.2+ addressing: reply privately to the player who chatted
+            target_player_uuids: vec![chat.player_uuid.clone()],
+            delivery: SpeechDelivery::Direct as i32,
+            ..Default::default()
+        };
+        self.fit_routing(cx, &mut speak);
+        // Clean the text before TTS reads it; sending cleans it again,
+        // which changes nothing
+        if self.output.filter_speak(&mut speak).is_err() {
+            return;
+        }
+        // An NPC that talks too much waits its turn or stays quiet
+        let rate = self.speech_rate(&chat.npc_id);
+        let admission = {
+            let mut state = self.state.lock().unwrap();
+            state.speech_rate.set_rate(&chat.npc_id, rate);
+            state.speech_rate.submit(speak, now_ms())
+        };
+        let mut speak = match admission {
+            Admission::Speak(speak) => *speak,
+            Admission::Queued { queued } => {
+                debug!(npc_id = %chat.npc_id, queued, "Reply held back, NPC speaks too often");
+                return;
+            }
+            Admission::Dropped(muted) => {
+                info!(%muted, "Reply dropped");
+                return;
+            }
+        };
+        // A reply long after the chat answers nobody; chat.timestamp_ms is
+        // on the plugin's clock
+        let deadline_ms = {
+            let mut state = self.state.lock().unwrap();
+            state.dialogues.on_speak(&speak, now_ms());
+            state.latency.to_local(chat.timestamp_ms) + CHAT_REPLY_DEADLINE_MS
+        };
+        #[cfg(feature = "persistence")]
+        self.persist(|store| store.append_turn(&chat.npc_id, &chat.player_uuid, &Turn {
+            speaker: Speaker::Npc,
+            text: speak.text.clone(),
+            timestamp_ms: now_ms(),
+        }));
+        #[cfg(feature = "persistence")]
+        self.transcribe(TranscriptEntry {
+            timestamp_ms: now_ms(),
+            server_id: cx.server_id().to_string(),
+            npc_id: chat.npc_id.clone(),
+            channel: Channel::Reply,
+            speaker: chat.npc_id.clone(),
+            text: speak.text.clone(),
+            // A whisper is heard by its targets only
+            participants: if speak.target_player_uuids.is_empty() {
+                vec![chat.player_uuid.clone()]
+            } else {
+                speak.target_player_uuids.clone()
+            },
+        });
+        
+        let tts = self.tts.clone().filter(|_| charge_tts(&mut self.state.lock().unwrap().spend, &speak));
+        let Some(tts) = tts else {
+            if let Err(error) = tx.send(latency::with_deadline(speak, deadline_ms)) {
+                warn!(directive_id = %directive_id, %error, "SpeakDirective not sent");
+            }
+            return;
+        };
+        
+        // Synthesize first so the subtitle lasts as long as the audio,
+        // then send the SpeakDirective followed by its AudioChunks
+        let Ok(npc_id) = NpcId::try_from(&chat) else {
+            warn!(npc_id = %chat.npc_id, "Invalid npc_id, not speaking");
+            return;
+        };
+        let tx = tx.clone();
+        let speech = self.speech.start(&npc_id, &stream_id);
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            let synthesized = match tts::synthesize_speech(&*tts, &speak).await {
+                Ok(synthesized) => synthesized,
+                Err(e) => {
+                    warn!(directive_id = %directive_id, error = %e, "TTS failed, sending subtitle only");
+                    if let Err(error) = tx.send_wait(latency::with_deadline(speak, deadline_ms)).await {
+                        warn!(directive_id = %directive_id, %error, "SpeakDirective not sent");
+                    }
+                    return;
+                }
+            };
+            // Speech without its directive would be stray audio, so a late
+            // reply is dropped here rather than at the stream
+            if now_ms() > deadline_ms {
+                warn!(directive_id = %directive_id, "TTS finished past the reply deadline, not speaking");
+                return;
+            }
+            speak.set_duration(synthesized.duration());
+            let chunks = tts::chunk_speech(&speak, &synthesized);
+            
+            let visemes = tts::viseme_timeline(&speak, &synthesized);
+            
+            // The directive goes out ahead of queued audio; waiting here
+            // keeps its chunks from outrunning it
+            if let Err(error) = tx.send_wait(latency::with_deadline(speak, deadline_ms)).await {
+                warn!(directive_id = %directive_id, %error, "SpeakDirective not sent");
+                return;
+            }
+            {
+                // Players' microphones pick this up; the mixer takes it out
+                let mut state = state.lock().unwrap();
+                if state.conversations.is_mixing() {
+                    let reference = audio::resample(
+                        &synthesized.samples,
+                        synthesized.sample_rate_hz,
+                        audio::ASR_SAMPLE_RATE_HZ,
+                    );
+                    let start_ms = now_ms() + state.latency.offset_ms();
+                    state.conversations.play(&npc_id, &reference, start_ms);
+                }
+            }
+            
+            // Lip-sync cues go ahead of the audio they describe
+            if let Some(timeline) = visemes {
+                if tx.send_wait(timeline).await.is_err() {
+                    return;
+                }
+            }
+            
+            info!(
+                directive_id = %directive_id,
+                stream_id = %stream_id,
+                "Sent SpeakDirective with audio correlation"
+            );
+            
+            let mut sent = 0;
+            for chunk in chunks {
+                // Keep only a short lead over playback; the plugin should
+                // not have to hold the whole utterance
+                let wait_ms = speech.wait_ms(now_ms());
+                if wait_ms > 0 {
+                    tokio::time::sleep(Duration::from_millis(wait_ms as u64)).await;
+                }
+                // Stop early if the player barged in
+                if speech.is_cancelled() {
+                    break;
+                }
+                // Waiting for room also keeps a congested stream from
+                // dropping chunks
+                speech.sent(&chunk, now_ms());
+                if tx.send_wait(chunk).await.is_err() {
+                    break;
+                }
+                sent += 1;
+            }
+            
+            debug!(
+                stream_id = %stream_id,
+                chunks = sent,
+                cancelled = speech.is_cancelled(),
+                "Sent AudioChunks with correlation"
+            );
+        });
+    }
+    
+    fn on_action_result(&self, result: ActionResult, _cx: &ConnectionContext, tx: &Outbound) {
+        // Large results arrive in parts; act on the whole result only
+        let result = match self.state.lock().unwrap().result_parts.push(result) {
+            Ok(Some(result)) => result,
+            Ok(None) => return,
+            Err(e) => {
+                warn!(error = %e, "Dropping split ActionResult");
+                return;
+            }
+        };
+
+        {
+            let mut state = self.state.lock().unwrap();
+            state.watchdog.finished(&result.directive_id);
+            let before = state.pending.len();
+            state
+                .pending
+                .retain(|p| p.directive.as_ref().map(|d| &d.directive_id) != Some(&result.directive_id));
+            // A resent directive can be answered twice: once by the
+            // original result, once replayed
+            if result.replayed && state.pending.len() == before {
+                debug!(directive_id = %result.directive_id, "Ignoring replayed result already handled");
+                return;
+            }
+        }
+        #[cfg(feature = "persistence")]
+        {
+            let server_id = self.state.lock().unwrap().server_id().to_string();
+            self.persist(|store| store.log_result(&server_id, &result, now_ms()));
+        }
+        
+        {
+            let mut state = self.state.lock().unwrap();
+            state.world.ingest_action_result(&result, now_ms());
+            if state.watches.finish(&result) {
+                info!(watch_id = %result.directive_id, error = %result.error_message, "Block watch ended");
+            }
+            if let Some(job) = state.stations.loaded(&result) {
+                info!(
+                    directive_id = %job.directive_id,
+                    item = %job.input_item,
+                    count = job.count,
+                    ready_at_tick = job.ready_at_tick,
+                    "Station loaded"
+                );
+            }
+        }
+        
+        // Retry policy first: intermediate failures never reach the
+        // behavior tree, final results carry the original directive_id
+        let decision = self.state.lock().unwrap().retries.on_result(result.clone());
+        let result = match decision {
+            RetryDecision::Retry { directive, after } => {
+                warn!(
+                    directive_id = %result.directive_id,
+                    retry_id = %directive.directive_id,
+                    after_ms = after.as_millis() as u64,
+                    error = %result.error_message,
+                    "Action failed, retrying"
+                );
+                self.schedule_retry(tx, directive, after);
+                return;
+            }
+            RetryDecision::Exhausted { result, attempts } => {
+                warn!(directive_id = %result.directive_id, attempts, "Retries exhausted");
+                result
+            }
+            RetryDecision::Done(result) => result,
+            RetryDecision::Untracked => result,
+        };
+        self.state.lock().unwrap().last_results.insert(result.npc_id.clone(), result.clone());
+        
+        // Let players nearby see and hear what the NPC just did
+        for effect in effects::for_result(&result) {
+            if let Err(error) = tx.send(effect) {
+                warn!(%error, "Effect not played");
+            }
+        }
+        
+        // Let the NPC's behavior tree continue
+        if let Some(tree) = self.state.lock().unwrap().behaviors.get_mut(&result.npc_id) {
+            tree.on_action_result(&result);
+        }
+        
+        if result.success {
+            info!(
+                directive_id = %result.directive_id,
+                npc_id = %result.npc_id,
+                "Action completed successfully"
+            );
+            
+            match result.result {
+                Some(ActionResultType::ScanBlocksResult(scan)) => {
+                    info!(
+                        matches = scan.matches.len(),
+                        "ScanBlocksResult: found ore blocks"
+                    );
+                }
+                
+                Some(ActionResultType::BreakBlockResult(break_result)) => {
+                    info!(
+                        items = break_result.items_dropped.len(),
+                        "BreakBlockResult: picked up items"
+                    );
+                }
+                
+                Some(ActionResultType::RegionSnapshotResult(region)) => {
+                    info!(
+                        palette = region.palette.len(),
+                        runs = region.run_lengths.len(),
+                        "RegionSnapshotResult: terrain cached"
+                    );
+                }
+                
+                Some(ActionResultType::DepositToChestResult(deposit)) => {
+                    info!(
+                        deposited = deposit.deposited.len(),
+                        "DepositToChestResult: items stored"
+                    );
+                }
+                
+                Some(ActionResultType::GiveItemResult(gift)) => {
+                    info!(
+                        player_uuid = %gift.player_uuid,
+                        item = %gift.item_type,
+                        accepted = gift.accepted,
+                        dropped = gift.dropped,
+                        "GiveItemResult: items handed over"
+                    );
+                }
+                
+                Some(ActionResultType::WriteSignResult(sign)) => {
+                    info!(
+                        lines = sign.lines.len(),
+                        placed = sign.placed,
+                        "WriteSignResult: sign written"
+                    );
+                }
+                
+                Some(ActionResultType::WriteBookResult(book)) => {
+                    info!(
+                        title = %book.title,
+                        pages = book.pages,
+                        copies = book.copies,
+                        player_uuid = %book.player_uuid,
+                        dropped = book.dropped,
+                        "WriteBookResult: book written"
+                    );
+                }
+                
+                Some(ActionResultType::MarkMapResult(map)) => {
+                    info!(
+                        map_id = map.map_id,
+                        markers = map.markers,
+                        player_uuid = %map.player_uuid,
+                        dropped = map.dropped,
+                        "MarkMapResult: map drawn"
+                    );
+                }
+                
+                Some(ActionResultType::ToggleLeverResult(lever)) => {
+                    info!(
+                        powered = lever.powered,
+                        flipped = lever.flipped,
+                        "ToggleLeverResult: lever set"
+                    );
+                }
+                
+                Some(ActionResultType::PressButtonResult(button)) => {
+                    debug!(pressed_ticks = button.pressed_ticks, "PressButtonResult: button pressed");
+                }
+                
+                Some(ActionResultType::SetMechanismResult(mechanism)) => {
+                    debug!(
+                        block = %mechanism.block_type,
+                        clicks = mechanism.clicks,
+                        "SetMechanismResult: mechanism set"
+                    );
+                }
+                
+                Some(ActionResultType::ReadRedstonePowerResult(reading)) => {
+                    debug!(
+                        block = %reading.block_type,
+                        power = reading.power,
+                        output = reading.output,
+                        "ReadRedstonePowerResult: signal read"
+                    );
+                }
+                
+                Some(ActionResultType::MoveResult(move_result)) => {
+                    debug!(
+                        reached = move_result.reached_destination,
+                        path_length = move_result.path_length,
+                        nodes_expanded = move_result.nodes_expanded,
+                        partial_path = move_result.partial_path.len(),
+                        "MoveResult received"
+                    );
+                }
+                
+                _ => {}
+            }
+        } else {
+            // Example: Error case handling
+            warn!(
+                directive_id = %result.directive_id,
+                npc_id = %result.npc_id,
+                error = %result.error_message,
+                error_code = ?result.error_code(),
+                denied_by_region_id = %result.denied_by_region_id,
+                "Action failed"
+            );
+            
+            // Retries (if any) are used up: the behavior tree treats
+            // this as a failed node and starts over on the next tick
+        }
+    }
+    
+    fn on_event(&self, event: EventObservation, _cx: &ConnectionContext, _tx: &Outbound) {
+        debug!(
+            npc_id = %event.npc_id,
+            kind = GameEvent::of(&event).name(),
+            "Event observation received"
+        );
+        
+        // Keep the block cache current when blocks are broken, placed
+        // or blown up, and remember who started fights
+        let mut state = self.state.lock().unwrap();
+        state.world.ingest_event(&event);
+        state.reputation.observe_event(&event);
+    }
+    
+    fn on_voice_frame(&self, frame: VoicePcmFrame, _cx: &ConnectionContext, tx: &Outbound) {
+        debug!(
+            npc_id = %frame.npc_id,
+            player_uuid = %frame.player_uuid,
+            sequence = frame.sequence,
+            bytes = frame.pcm_data.len(),
+            sample_rate = frame.sample_rate_hz,
+            format = ?frame.format,
+            "Voice frame received"
+        );
+        
+        // Group frames per speaker: reorder, convert to 16kHz and
+        // segment utterances. Frames of players who withdrew consent may
+        // still be in flight; they are never transcribed.
+        let events = {
+            let mut state = self.state.lock().unwrap();
+            if !state.consents.admit(&frame, now_ms()) {
+                debug!(player_uuid = %frame.player_uuid, "Voice frame without consent dropped");
+                return;
+            }
+            #[cfg(feature = "audio-opus")]
+            let Some(frame) = state.decode_voice(frame, now_ms()) else {
+                return;
+            };
+            state.conversations.push_frame(frame, now_ms())
+        };
+        
+        self.on_speaker_events(events, tx);
+    }
+    
+    fn on_speak_result(&self, spoken: SpeakResult, _cx: &ConnectionContext, _tx: &Outbound) {
+        info!(
+            directive_id = %spoken.directive_id,
+            npc_id = %spoken.npc_id,
+            played_ms = spoken.played_ms,
+            listeners = spoken.listeners_count,
+            truncated = spoken.truncated,
+            "Speech finished"
+        );
+        self.state.lock().unwrap().audio_assets.finished(&spoken.directive_id);
+        
+        // In production: continue whatever was waiting on this line
+        // (e.g. walk away only after the NPC finished talking)
+    }
+    
+    fn on_speech_interrupted(
+        &self,
+        interrupted: SpeechInterrupted,
+        _cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+        info!(
+            npc_id = %interrupted.npc_id,
+            stream_id = %interrupted.stream_id,
+            player_uuid = %interrupted.player_uuid,
+            played_ms = interrupted.played_ms,
+            "Speech interrupted"
+        );
+        
+        // Let the player talk: stop streaming and tell the plugin to
+        // drop what it has buffered
+        // An empty stream_id means every stream of the NPC
+        let cancelled = match NpcId::try_from(&interrupted) {
+            Ok(npc) => self.speech.cancel(&npc, StreamId::try_from(&interrupted).ok().as_ref()),
+            Err(_) => Vec::new(),
+        };
+        debug!(streams = ?cancelled, "Cancelled TTS streams");
+        
+        // Also drops the stream's audio that is still queued
+        let stop = StopSpeaking {
+            npc_id: interrupted.npc_id,
+            stream_id: interrupted.stream_id,
+        };
+        if let Err(error) = tx.send(stop) {
+            warn!(%error, "StopSpeaking not sent");
+        }
+    }
+    
+    fn on_npc_message(&self, npc_message: NpcMessage, _cx: &ConnectionContext, _tx: &Outbound) {
+        info!(
+            message_id = %npc_message.message_id,
+            from = %npc_message.sender_npc_id,
+            to = %npc_message.recipient_npc_id,
+            topic = %npc_message.topic,
+            bytes = npc_message.payload.len(),
+            "NPC message"
+        );
+        
+        // In production: hand it to the recipient's agent (e.g. a
+        // "found_ore" message could send a miner to the position)
+    }
+    
+    fn on_quest_update(&self, update: QuestUpdate, _cx: &ConnectionContext, tx: &Outbound) {
+        info!(
+            quest_id = %update.quest_id,
+            npc_id = %update.npc_id,
+            player_uuid = %update.player_uuid,
+            status = ?update.status(),
+            "Quest update"
+        );
+        
+        // Track the quest in the player's sidebar while it runs
+        let display_id = format!("quest-{}-{}", update.quest_id, update.player_uuid);
+        let mut state = self.state.lock().unwrap();
+        let sent = match update.status() {
+            QuestStatus::Accepted | QuestStatus::InProgress => {
+                let lines = update.progress.iter().map(|p| {
+                    let done = if p.complete { " (done)" } else { "" };
+                    format!("{}: {}{}", p.objective_id, p.count, done)
+                });
+                let board = display::scoreboard(
+                    &display_id,
+                    &update.npc_id,
+                    &update.player_uuid,
+                    &update.quest_id,
+                    lines,
+                );
+                state.displays.show(board).map(|board| tx.send(board))
+            }
+            _ => state.displays.remove(&display_id).map(|remove| tx.send(remove)),
+        };
+        if let Some(Err(error)) = sent {
+            warn!(quest_id = %update.quest_id, %error, "Quest tracker not updated");
+        }
+        
+        // In production: thank the player on completion, offer the
+        // next quest in the chain, adjust the relationship, ...
+    }
+    
+    fn on_transaction(
+        &self,
+        transaction: TransactionObservation,
+        _cx: &ConnectionContext,
+        _tx: &Outbound,
+    ) {
+        if transaction.success {
+            info!(
+                directive_id = %transaction.directive_id,
+                npc_id = %transaction.npc_id,
+                player_uuid = %transaction.player_uuid,
+                direction = ?transaction.direction(),
+                amount = transaction.amount,
+                npc_balance = transaction.npc_balance,
+                "Transaction completed"
+            );
+        } else {
+            warn!(
+                directive_id = %transaction.directive_id,
+                npc_id = %transaction.npc_id,
+                error = %transaction.error_message,
+                "Transaction failed"
+            );
+        }
+        self.state.lock().unwrap().reputation.observe_transaction(&transaction);
+        
+        // In production: hand over the goods with a GiveItemAction once
+        // a charge went through, or tell the player they can't afford it
+    }
+    
+    fn on_change_dimension(
+        &self,
+        change: ChangeDimensionObservation,
+        _cx: &ConnectionContext,
+        _tx: &Outbound,
+    ) {
+        info!(
+            npc_id = %change.npc_id,
+            from = %change.from.as_ref().map(|p| World::of(p).to_string()).unwrap_or_default(),
+            to = %change.to.as_ref().map(|p| World::of(p).to_string()).unwrap_or_default(),
+            cause = ?change.cause(),
+            "NPC changed dimension"
+        );
+        
+        // Directives are checked against the NPC's world from now
+        // on, not from the next WorldTick
+        let mut state = self.state.lock().unwrap();
+        let npc = state
+            .latest_tick
+            .iter_mut()
+            .flat_map(|tick| &mut tick.npcs)
+            .find(|npc| npc.npc_id == change.npc_id);
+        if let Some(npc) = npc {
+            npc.position = change.to.clone();
+        }
+        
+        // In production: drop plans and memories tied to the old
+        // world (paths, known chests, scan results)
+    }
+
+    fn on_block_watch_update(&self, update: BlockWatchUpdate, _cx: &ConnectionContext, _tx: &Outbound) {
+        let mut state = self.state.lock().unwrap();
+        state.world.ingest_block_watch(&update, now_ms());
+        if !state.watches.apply(&update) {
+            warn!(watch_id = %update.directive_id, "BlockWatchUpdate for an unknown watch");
+            return;
+        }
+        info!(
+            watch_id = %update.directive_id,
+            npc_id = %update.npc_id,
+            found = update.found.len(),
+            removed = update.removed.len(),
+            tracked = state.watches.matches(&update.directive_id).count(),
+            "Watched blocks changed"
+        );
+    }
+
+    fn on_station_output(&self, output: StationOutputObservation, _cx: &ConnectionContext, _tx: &Outbound) {
+        let job = self.state.lock().unwrap().stations.finish(&output);
+        let produced: i32 = output.output.iter().map(|item| item.quantity).sum();
+        info!(
+            npc_id = %output.npc_id,
+            directive_id = %output.directive_id,
+            reason = ?output.reason(),
+            produced,
+            loaded = job.map(|j| j.count),
+            "Station finished"
+        );
+        
+        // In production: send the NPC back with a count-0 SmeltAction /
+        // BrewAction to collect, or refuel on OUT_OF_FUEL
+    }
+    
+    fn on_directive_rejected(&self, rejected: DirectiveRejected, cx: &ConnectionContext, tx: &Outbound) {
+        warn!(
+            directive_id = %rejected.directive_id,
+            npc_id = %rejected.npc_id,
+            code = ?rejected.code(),
+            detail = %rejected.detail,
+            "Directive rejected by the plugin"
+        );
+        if rejected.directive_id.is_empty() {
+            return;
+        }
+        
+        // A stock line whose clip the plugin lost: upload it and say it again
+        if rejected.code() == RejectionCode::AssetMissing {
+            let replay = self.state.lock().unwrap().audio_assets.on_rejected(&rejected);
+            for message in replay.unwrap_or_default() {
+                if let Err(error) = tx.send(message) {
+                    warn!(directive_id = %rejected.directive_id, %error, "Stock line not replayed");
+                    break;
+                }
+            }
+            return;
+        }
+        
+        // No ActionResult will come: fail the directive here so the behavior
+        // tree and anything else waiting on it moves on
+        let original = {
+            let mut state = self.state.lock().unwrap();
+            state
+                .pending
+                .retain(|p| p.directive.as_ref().map(|d| &d.directive_id) != Some(&rejected.directive_id));
+            state.watchdog.finished(&rejected.directive_id);
+            state.retries.forget(&rejected.directive_id)
+        };
+        let error_message = format!("rejected ({:?}): {}", rejected.code(), rejected.detail);
+        let result = ActionResult {
+            directive_id: original.map_or(rejected.directive_id, |d| d.directive_id),
+            npc_id: rejected.npc_id,
+            success: false,
+            error_message,
+            ..Default::default()
+        };
+        self.on_action_result(result, cx, tx);
+    }
+    
+    fn on_directive_ack(&self, ack: DirectiveAck, _cx: &ConnectionContext, _tx: &Outbound) {
+        debug!(
+            directive_id = %ack.directive_id,
+            npc_id = %ack.npc_id,
+            queued_position = ack.queued_position,
+            "Directive acknowledged"
+        );
+        
+        // Shown by GetNpcState / ListPendingDirectives: a pending directive
+        // without an ack never reached the plugin
+        let mut state = self.state.lock().unwrap();
+        state.watchdog.acked(&ack, now_ms());
+        let pending = state
+            .pending
+            .iter_mut()
+            .find(|p| p.directive.as_ref().is_some_and(|d| d.directive_id == ack.directive_id));
+        if let Some(pending) = pending {
+            pending.ack = Some(ack);
+        }
+    }
+    
+    fn on_combat_policy(&self, observation: CombatPolicyObservation, _cx: &ConnectionContext, _tx: &Outbound) {
+        info!(
+            npc_id = %observation.npc_id,
+            trigger = ?observation.trigger(),
+            target_uuid = %observation.target_uuid,
+            target_type = %observation.target_type,
+            health_norm = observation.health_norm,
+            "Combat policy acted"
+        );
+        
+        // In production: pause the NPC's behavior tree while it fights or
+        // flees, and resume it on DISENGAGED
+    }
+    
+    fn on_npc_transfer(&self, mut update: NpcTransferUpdate, _cx: &ConnectionContext, _tx: &Outbound) {
+        let server_id = {
+            let state = self.state.lock().unwrap();
+            // The plugin knows the NPC's body; the target also gets what
+            // the daemon knows of it
+            if update.stage() == NpcTransferStage::Prepared {
+                if let Some(snapshot) = update.snapshot.as_mut() {
+                    snapshot.npc_id = update.npc_id.clone();
+                    snapshot.server_id = state.server_id().to_string();
+                    snapshot.exported_at_ms = now_ms();
+                    snapshot.relationships = state.reputation.relationships(&update.npc_id);
+                }
+            }
+            state.server_id().to_string()
+        };
+        info!(
+            transfer_id = %update.transfer_id,
+            npc_id = %update.npc_id,
+            server_id = %server_id,
+            stage = ?update.stage(),
+            error = %update.error_message,
+            "NPC transfer update"
+        );
+        
+        let outgoing = self.transfers.lock().unwrap().on_update(&server_id, &update, now_ms());
+        self.route(outgoing);
+        self.finish_transfers();
+    }
+    
+    fn on_dialogue_choice(&self, choice: DialogueChoiceObservation, cx: &ConnectionContext, tx: &Outbound) {
+        let mut state = self.state.lock().unwrap();
+        let Some(choice) = state.prompts.choose(&choice) else {
+            debug!(prompt_id = %choice.prompt_id, "Choice for a prompt that is not open");
+            return;
+        };
+        let Some(option) = choice.option else {
+            debug!(prompt_id = %choice.prompt_id, "Dialogue prompt closed without a choice");
+            return;
+        };
+        info!(
+            npc_id = %choice.npc_id,
+            player_uuid = %choice.player_uuid,
+            option_id = %option.option_id,
+            "Dialogue option chosen"
+        );
+        
+        // The click stands for the player saying the label, so the
+        // session reads the same as if it had been typed. In production:
+        // follow the dialogue branch option_id names.
+        state.dialogues.record(
+            &choice.npc_id,
+            &choice.player_uuid,
+            Speaker::Player,
+            &option.label,
+            now_ms(),
+        );
+        if option.option_id == "trade" {
+            let shop = miner_shop(&choice.npc_id, &choice.player_uuid);
+            state.shops.define(&shop);
+            let sign = state.displays.show(shop_sign(&shop));
+            if let Err(error) = tx.send(shop) {
+                warn!(npc_id = %choice.npc_id, %error, "ShopDefinition not sent");
+            }
+            if let Some(Err(error)) = sign.map(|sign| tx.send(sign)) {
+                warn!(npc_id = %choice.npc_id, %error, "Shop sign not shown");
+            }
+        }
+        if option.option_id == "bye" {
+            drop(state);
+            self.bark(cx, tx, &choice.npc_id, &choice.player_uuid, "miner-farewell", "Stay out of trouble.");
+        }
+    }
+    
+    fn on_shop_trade(&self, trade: ShopTradeObservation, _cx: &ConnectionContext, tx: &Outbound) {
+        if trade.success {
+            info!(
+                shop_id = %trade.shop_id,
+                player_uuid = %trade.player_uuid,
+                listing_id = %trade.listing_id,
+                side = ?trade.side(),
+                count = trade.count,
+                total_price = trade.total_price,
+                stock = trade.stock,
+                "Shop trade"
+            );
+        } else {
+            debug!(
+                shop_id = %trade.shop_id,
+                listing_id = %trade.listing_id,
+                error = %trade.error_message,
+                "Shop trade failed"
+            );
+        }
+        let mut state = self.state.lock().unwrap();
+        state.reputation.observe_shop_trade(&trade);
+        state.shops.apply(&trade);
+        
+        // The miner digs up more; in production, restock from what the NPC
+        // actually carries
+        if shop::sold_out(&trade) {
+            if let Some(shop) = state.shops.restock(&trade.shop_id, &trade.listing_id, 3) {
+                if let Err(error) = tx.send(shop) {
+                    warn!(shop_id = %trade.shop_id, %error, "ShopDefinition not sent");
+                }
+            }
+        }
+        let sign = state.shops.shop(&trade.shop_id).map(shop_sign);
+        let sign = sign.and_then(|sign| state.displays.show(sign));
+        if let Some(Err(error)) = sign.map(|sign| tx.send(sign)) {
+            warn!(shop_id = %trade.shop_id, %error, "Shop sign not updated");
+        }
+    }
+    
+    fn on_landmark_list(&self, list: LandmarkList, _cx: &ConnectionContext, _tx: &Outbound) {
+        info!(
+            request_id = %list.request_id,
+            landmarks = list.landmarks.len(),
+            removed = list.removed.len(),
+            complete = list.complete,
+            "Landmarks"
+        );
+        self.state.lock().unwrap().landmarks.lock().unwrap().ingest(&list);
+    }
+    
+    fn on_voice_consent(
+        &self,
+        consent: VoiceConsentObservation,
+        _cx: &ConnectionContext,
+        tx: &Outbound,
+    ) {
+        info!(
+            player_uuid = %consent.player_uuid,
+            consent = ?consent.consent(),
+            "Voice consent changed"
+        );
+        // The plugin has already stopped forwarding the player; end the
+        // captures that named them so they are not resumed by accident
+        let stops = {
+            let mut state = self.state.lock().unwrap();
+            let stops = state.consents.observe(&consent);
+            // What was captured before is not transcribed either
+            if !state.consents.allows(&consent.player_uuid) {
+                if let Ok(player) = PlayerUuid::new(consent.player_uuid.as_str()) {
+                    state.conversations.forget_player(&player);
                 }
+                #[cfg(feature = "audio-opus")]
+                state.opus_voice.retain(|(_, player_uuid), _| *player_uuid != consent.player_uuid);
             }
-            
-            Some(ClientMsg::ChatObservation(chat)) => {
-                info!(
-                    npc_id = %chat.npc_id,
-                    player_name = %chat.player_name,
-                    message = %chat.message,
-                    "Chat observation received"
-                );
-                
-                // Example E: Send SpeakDirective with correlation fields + audio
-                let directive_id = next_directive_id();
-                let stream_id = next_stream_id();
-                
-                // Send SpeakDirective with v1.1+ correlation fields
-                let speak = SpeakDirective {
-                    npc_id: chat.npc_id.clone(),
-                    text: format!("Hello, {}! I'll help you find diamonds.", chat.player_name),
-                    emotion: "helpful".to_string(),
+            stops
+        };
+        for stop in stops {
+            let npc_id = stop.npc_id.clone();
+            if let Err(error) = tx.send(stop) {
+                warn!(npc_id = %npc_id, %error, "StopVoiceCapture not sent");
+            }
+        }
+    }
+    
+    fn on_moderation_flag(&self, flag: ModerationFlag, cx: &ConnectionContext, tx: &Outbound) {
+        self.moderation.notify(&ModerationNotice::Flagged {
+            server_id: cx.server_id(),
+            flag: &flag,
+        });
+        // Every replica keeps the flags; only the owner quarantines
+        let quarantine = self.state.lock().unwrap().moderation.on_flag(&flag);
+        let Some(quarantine) = quarantine.filter(|_| self.owns(&flag.npc_id)) else {
+            return;
+        };
+        self.moderation.notify(&ModerationNotice::Quarantine {
+            server_id: cx.server_id(),
+            directive: &quarantine,
+        });
+        if let Err(error) = tx.send(quarantine) {
+            warn!(npc_id = %flag.npc_id, %error, "QuarantineNpcDirective not sent");
+        }
+    }
+    
+    fn on_audio_buffer(&self, status: AudioBufferStatus, _cx: &ConnectionContext, _tx: &Outbound) {
+        if status.underruns > 0 {
+            debug!(
+                stream_id = %status.stream_id,
+                buffered_ms = status.buffered_ms,
+                underruns = status.underruns,
+                "Audio playback ran dry"
+            );
+        }
+        let local_ms = self.state.lock().unwrap().latency.to_local(status.timestamp_ms);
+        // Reports of a stream already sent in full need nothing
+        self.speech.report(&status, local_ms);
+    }
+    
+    fn on_intruder(&self, observation: IntruderObservation, cx: &ConnectionContext, tx: &Outbound) {
+        info!(
+            npc_id = %observation.npc_id,
+            zone_id = %observation.zone_id,
+            trigger = ?observation.trigger(),
+            player_uuid = %observation.player_uuid,
+            distance = observation.distance,
+            intruders = observation.intruders,
+            "Intruder"
+        );
+        
+        // The plugin only patrols; whether to warn, fight or get help is
+        // decided here
+        let response = self.state.lock().unwrap().intruders.decide(&observation);
+        let npc_id = observation.npc_id.clone();
+        match response {
+            Some(guard::Response::Warn) => {
+                let warning = SpeakDirective {
+                    npc_id: npc_id.clone(),
+                    text: format!("Halt, {}! You have no business here. Leave.", observation.player_name),
+                    emotion: emotion::name(Emotion::Angry).to_string(),
+                    emotion_type: Emotion::Angry as i32,
                     duration_ms: 3000,
-                    // v1.1+ fields for correlation
-                    directive_id: directive_id.clone(),
-                    voice_id: "en-US-Neural2-D".to_string(), // Example TTS voice
-                    volume: 0.8,
-                    stream_id: stream_id.clone(), // Must match AudioChunk.stream_id
+                    directive_id: cx.next_directive_id(&npc_id),
+                    target_player_uuids: vec![observation.player_uuid.clone()],
+                    ..Default::default()
                 };
-                
-                let _ = tx.blocking_send(ServerMessage {
-                    message: Some(ServerMsg::SpeakDirective(speak)),
-                });
-                
-                info!(
-                    directive_id = %directive_id,
-                    stream_id = %stream_id,
-                    "Sent SpeakDirective with audio correlation"
-                );
-                
-                // Send correlated AudioChunks (simulated TTS output)
-                for seq in 0..3 {
-                    let audio = AudioChunk {
-                        npc_id: chat.npc_id.clone(),
-                        stream_id: stream_id.clone(), // Matches SpeakDirective.stream_id
-                        pcm_data: vec![0u8; 960], // Dummy silence (20ms at 48kHz mono)
-                        sequence: seq,
-                        is_final: seq == 2,
-                        // v1.1+ optional correlation
-                        directive_id: directive_id.clone(),
-                    };
-                    
-                    let _ = tx.blocking_send(ServerMessage {
-                        message: Some(ServerMsg::AudioChunk(audio)),
-                    });
+                if let Err(error) = tx.send(warning) {
+                    warn!(%npc_id, %error, "Warning not sent");
                 }
-                
-                debug!(
-                    stream_id = %stream_id,
-                    chunks = 3,
-                    "Sent AudioChunks with correlation"
-                );
             }
-            
-            Some(ClientMsg::ActionResult(result)) => {
-                if result.success {
-                    info!(
-                        directive_id = %result.directive_id,
-                        npc_id = %result.npc_id,
-                        "Action completed successfully"
-                    );
-                    
-                    // Handle specific result types
-                    match result.result {
-                        Some(ActionResultType::ScanBlocksResult(scan)) => {
-                            // Example D: Process mining scan results
-                            info!(
-                                matches = scan.matches.len(),
-                                "ScanBlocksResult: found ore blocks"
-                            );
-                            
-                            // If we found ore, send a BreakBlockAction for the first one
-                            if let Some(first_match) = scan.matches.first() {
-                                let directive_id = next_directive_id();
-                                
-                                let break_action = ActionDirective {
-                                    directive_id: directive_id.clone(),
-                                    npc_id: result.npc_id.clone(),
-                                    priority: 10, // High priority
-                                    action: Some(Action::BreakBlock(BreakBlockAction {
-                                        position: first_match.position.clone(),
-                                    })),
-                                };
-                                
-                                let _ = tx.blocking_send(ServerMessage {
-                                    message: Some(ServerMsg::ActionDirective(break_action)),
-                                });
-                                
-                                info!(
-                                    directive_id = %directive_id,
-                                    block_type = %first_match.block_type,
-                                    "Sent BreakBlockAction for found ore"
-                                );
-                            }
-                        }
-                        
-                        Some(ActionResultType::BreakBlockResult(break_result)) => {
-                            // After breaking blocks, deposit to chest
-                            if !break_result.items_dropped.is_empty() {
-                                info!(
-                                    items = break_result.items_dropped.len(),
-                                    "BreakBlockResult: picked up items"
-                                );
-                                
-                                // Send DepositToChestAction
-                                let directive_id = next_directive_id();
-                                
-                                let deposit_action = ActionDirective {
-                                    directive_id: directive_id.clone(),
-                                    npc_id: result.npc_id.clone(),
-                                    priority: 5,
-                                    action: Some(Action::DepositToChest(DepositToChestAction {
-                                        chest_position: Some(BlockPosition {
-                                            world: "world".to_string(),
-                                            x: 100,
-                                            y: 64,
-                                            z: -200,
-                                        }),
-                                        item_types: vec!["minecraft:diamond".to_string()],
-                                        max_items: 64,
-                                    })),
-                                };
-                                
-                                let _ = tx.blocking_send(ServerMessage {
-                                    message: Some(ServerMsg::ActionDirective(deposit_action)),
-                                });
-                                
-                                info!(directive_id = %directive_id, "Sent DepositToChestAction");
-                            }
-                        }
-                        
-                        Some(ActionResultType::DepositToChestResult(deposit)) => {
-                            info!(
-                                deposited = deposit.deposited.len(),
-                                "DepositToChestResult: items stored"
-                            );
-                        }
-                        
-                        Some(ActionResultType::MoveResult(move_result)) => {
-                            debug!(
-                                reached = move_result.reached_destination,
-                                "MoveResult received"
-                            );
-                        }
-                        
-                        _ => {}
-                    }
-                } else {
-                    // Example: Error case handling
-                    warn!(
-                        directive_id = %result.directive_id,
-                        npc_id = %result.npc_id,
-                        error = %result.error_message,
-                        "Action failed"
-                    );
-                    
-                    // Could retry, fall back, or notify player
+            Some(guard::Response::Attack) => {
+                let attack = ActionDirective {
+                    directive_id: cx.next_directive_id(&npc_id),
+                    npc_id: npc_id.clone(),
+                    action: Some(Action::Attack(AttackAction {
+                        target_uuid: observation.player_uuid.clone(),
+                    })),
+                    ..Default::default()
+                };
+                if let Err(failed) = self.send_directive(tx, attack) {
+                    warn!(%npc_id, error = %failed.error_message, "Attack not sent");
                 }
             }
-            
-            Some(ClientMsg::EventObservation(event)) => {
-                debug!(
-                    npc_id = %event.npc_id,
-                    event_type = ?event.event_type,
-                    "Event observation received"
-                );
-            }
-            
-            Some(ClientMsg::VoicePcmFrame(frame)) => {
-                debug!(
-                    npc_id = %frame.npc_id,
-                    player_uuid = %frame.player_uuid,
-                    sequence = frame.sequence,
-                    bytes = frame.pcm_data.len(),
-                    sample_rate = frame.sample_rate_hz,
-                    format = ?frame.format,
-                    "Voice frame received"
-                );
-                // In production: buffer audio, run ASR, process with LLM
-            }
-            
-            None => {
-                warn!("Received empty client message");
+            Some(guard::Response::CallForHelp) => {
+                let alarm = NpcMessage {
+                    message_id: cx.next_id("message"),
+                    sender_npc_id: npc_id.clone(),
+                    topic: "intruders".to_string(),
+                    payload: serde_json::json!({
+                        "zone_id": observation.zone_id,
+                        "player_uuid": observation.player_uuid,
+                        "intruders": observation.intruders,
+                    })
+                    .to_string()
+                    .into_bytes(),
+                    ..Default::default()
+                };
+                if let Err(error) = tx.send(alarm) {
+                    warn!(%npc_id, %error, "Call for help not sent");
+                }
             }
+            None => {}
+        }
+    }
+    
+    fn on_container_access(&self, access: ContainerAccessObservation, cx: &ConnectionContext, tx: &Outbound) {
+        debug!(
+            npc_id = %access.npc_id,
+            container = %access.container_name,
+            access = ?access.access(),
+            player_uuid = %access.player_uuid,
+            allowed = access.allowed,
+            items = access.items.len(),
+            "Container access"
+        );
+        self.state.lock().unwrap().reputation.observe_container_access(&access);
+        
+        let stolen = storage::stolen(&access);
+        if stolen.is_empty() || !self.owns(&access.npc_id) {
+            return;
+        }
+        warn!(
+            npc_id = %access.npc_id,
+            container = %access.container_name,
+            player_uuid = %access.player_uuid,
+            items = ?stolen.iter().map(|i| (&i.item_type, i.quantity)).collect::<Vec<_>>(),
+            "Theft"
+        );
+        let shout = SpeakDirective {
+            npc_id: access.npc_id.clone(),
+            text: format!("Thief! {}, put that back!", access.player_name),
+            emotion: emotion::name(Emotion::Angry).to_string(),
+            emotion_type: Emotion::Angry as i32,
+            duration_ms: 3000,
+            directive_id: cx.next_directive_id(&access.npc_id),
+            ..Default::default()
+        };
+        if let Err(error) = tx.send(shout) {
+            warn!(npc_id = %access.npc_id, %error, "Theft not called out");
+        }
+    }
+    
+    fn on_choreography_result(&self, result: ChoreographyResult, _cx: &ConnectionContext, _tx: &Outbound) {
+        info!(
+            directive_id = %result.directive_id,
+            success = result.success,
+            aborted = result.aborted,
+            duration_ms = result.finished_at_ms - result.started_at_ms,
+            "Choreography finished"
+        );
+        
+        // The scene as a whole is one directive; say which lines went wrong
+        for step in result.steps.iter().filter(|step| !step.success) {
+            warn!(
+                directive_id = %result.directive_id,
+                npc_id = %step.npc_id,
+                started_at_ms = step.started_at_ms,
+                error = %step.error_message,
+                "Choreography step failed"
+            );
+        }
+    }
+    
+    fn on_transport_progress(&self, progress: TransportProgress, _cx: &ConnectionContext, _tx: &Outbound) {
+        debug!(
+            directive_id = %progress.directive_id,
+            npc_id = %progress.npc_id,
+            stage = ?progress.stage(),
+            finished = progress.finished,
+            trip = progress.trip,
+            carried = progress.carried,
+            delivered = progress.delivered,
+            "Courier leg {}",
+            if progress.finished { "finished" } else { "started" }
+        );
+    }
+    
+    fn on_transport_items_result(&self, result: TransportItemsResult, _cx: &ConnectionContext, _tx: &Outbound) {
+        let delivered: i32 = result.delivered.iter().map(|item| item.quantity).sum();
+        let carried: i32 = result.carried.iter().map(|item| item.quantity).sum();
+        if result.success {
+            info!(
+                directive_id = %result.directive_id,
+                npc_id = %result.npc_id,
+                trips = result.trips,
+                delivered,
+                "Courier run finished"
+            );
+            return;
+        }
+        // Like a scene, the run is one directive; the failed leg says where
+        let leg = result.legs.iter().rev().find(|leg| !leg.success);
+        warn!(
+            directive_id = %result.directive_id,
+            npc_id = %result.npc_id,
+            code = ?result.error_code(),
+            error = %result.error_message,
+            trips = result.trips,
+            failed_leg = leg.map_or("", |leg| leg.error_message.as_str()),
+            delivered,
+            carried,
+            "Courier run failed"
+        );
+    }
+}
+
+/// The miner's shop, opened for `player_uuid`: a few diamonds for sale,
+/// torches without limit, and coal bought from players
+fn miner_shop(npc_id: &str, player_uuid: &str) -> ShopDefinition {
+    let item = |item_type: &str, quantity| ItemStack {
+        item_type: item_type.to_string(),
+        quantity,
+        ..Default::default()
+    };
+    let mut diamonds = shop::listing("diamond", item("minecraft:diamond", 1), 500, 0);
+    diamonds.stock = 3;
+    ShopDefinition {
+        shop_id: format!("{}-shop", npc_id),
+        npc_id: npc_id.to_string(),
+        title: "Mining supplies".to_string(),
+        listings: vec![
+            diamonds,
+            shop::listing("torches", item("minecraft:torch", 16), 20, 0),
+            shop::listing("coal", item("minecraft:coal", 8), 0, 10),
+        ],
+        open_for_player_uuids: vec![player_uuid.to_string()],
+        ..Default::default()
+    }
+}
+
+/// Hologram above a shop's NPC with what it sells and what is left
+fn shop_sign(shop: &ShopDefinition) -> ShowDisplayDirective {
+    let offers = shop.listings.iter().filter(|l| l.buy_price > 0).map(|l| {
+        let item = l.item.as_ref().map_or("", |i| i.item_type.trim_start_matches("minecraft:"));
+        match l.stock {
+            shop::UNLIMITED => format!("{}: {}", item, l.buy_price),
+            stock => format!("{}: {} ({} left)", item, l.buy_price, stock),
         }
+    });
+    let lines = std::iter::once(shop.title.clone()).chain(offers);
+    display::hologram(&shop.shop_id, &shop.npc_id, lines)
+}
+
+/// Example D as a behavior tree: every 100 ticks eat if hungry, or else
+/// scan for diamond ore, break the first match if holding a pickaxe and
+/// deposit the drops in the [`MINERS_CHEST`] landmark; every 50 ticks
+/// otherwise, wander 5 blocks east. An NPC that mines all day would rather
+/// send one WatchBlocksAction and pick targets from `BlockWatches`.
+fn mining_tree(npc_id: &str, landmarks: SharedLandmarks) -> BehaviorTree {
+    let mine = sequence(vec![
+        condition(|bb| bb.server_tick % 100 == 0),
+        // Breaking ore by hand takes ages and drops nothing
+        condition(|bb| equipment::holding(&bb.npc, "minecraft:*_pickaxe")),
+        action("scan", 5, |bb| {
+            let p = bb.npc.position.as_ref()?;
+            let scan = ScanBlocksAction::builder()
+                .center(dimension::block_at(p))
+                .block_types(["#minecraft:diamond_ores"])
+                .build();
+            scan.ok().map(Action::from)
+        }),
+        action("break", 10, |bb| match &bb.result("scan")?.result {
+            Some(ActionResultType::ScanBlocksResult(scan)) => {
+                Some(Action::BreakBlock(BreakBlockAction {
+                    position: scan.matches.first()?.position.clone(),
+                }))
+            }
+            _ => None,
+        }),
+        condition(|bb| {
+            matches!(
+                bb.result("break").and_then(|r| r.result.as_ref()),
+                Some(ActionResultType::BreakBlockResult(b)) if !b.items_dropped.is_empty()
+            )
+        }),
+        // Without the landmark the miner keeps its diamonds
+        action("deposit", 5, move |_| {
+            let chest = landmarks.lock().unwrap().block(MINERS_CHEST)?;
+            let deposit = DepositToChestAction::builder()
+                .chest_position(chest)
+                .item_types(["minecraft:diamond"])
+                .build();
+            deposit.ok().map(Action::from)
+        }),
+    ]);
+    
+    let wander = sequence(vec![
+        condition(|bb| bb.server_tick % 50 == 0),
+        action("move", 1, |bb| {
+            // Stay in whatever world the NPC is in
+            let p = bb.npc.position.as_ref()?;
+            let target = Position {
+                x: p.x + 5.0,
+                yaw: 0.0,
+                pitch: 0.0,
+                ..p.clone()
+            };
+            MoveAction::builder().target(target).build().ok().map(Action::from)
+        }),
+    ]);
+    
+    let eat = sequence(vec![
+        condition(|bb| bb.server_tick % 100 == 0 && bb.npc.hunger_norm < 0.3),
+        action("eat", 8, |_| ConsumeItemAction::builder().build().ok().map(Action::from)),
+    ]);
+    
+    BehaviorTree::new(npc_id, selector(vec![eat, mine, wander]))
+}
+
+/// Example D in a seeded [`Simulation`]: every NPC runs `mining_tree` for
+/// 1200 ticks, a minute of game time
+fn simulate_mining(seed: u64) -> Trace {
+    let mut simulation = Simulation::new(SimConfig { seed, ..Default::default() });
+    for npc_id in simulation.npc_ids() {
+        simulation.attach(mining_tree(&npc_id, SharedLandmarks::default()));
     }
+    simulation.run(1200);
+    simulation.trace().clone()
 }
 
 #[tonic::async_trait]
@@ -358,22 +3092,55 @@ impl NpcSocietyService for ExampleNpcSocietyService {
             .map(|a| a.to_string())
             .unwrap_or_else(|| "unknown".to_string());
         
+        // The plugin retries; by then another replica is ready
+        if self.lifecycle.is_draining() {
+            return Err(Status::unavailable("daemon is shutting down"));
+        }
         info!(peer = %peer_addr, "New plugin connection");
-
+        
         let mut in_stream = request.into_inner();
         
-        // Channel for sending responses back to client
-        let (tx, rx) = mpsc::channel(128);
+        // Prioritized queue for responses: directives overtake audio, and
+        // anything dropped or refused is counted for GetSessionInfo
+        let (tx, rx) = outbound::queue(QueueConfig::default());
+        let tx = tx.with_filter(self.output.clone()).with_guards(self.guards.clone());
+        let cx = Arc::new(ConnectionContext::new(peer_addr.clone(), now_ms())
+            .with_outbound(tx.monitor())
+            .with_ids(self.ids.clone()));
         
-        // Spawn task to process incoming messages
-        let service = Arc::new(ExampleNpcSocietyService);
-        let tx_clone = tx.clone();
+        // Each connection has its own handler and state until the Hello
+        // says which server it is
+        let service = ExampleNpcSocietyService {
+            state: Arc::new(Mutex::new(SharedState {
+                connected: true,
+                connection: Some(cx.clone()),
+                tx: Some(tx.clone()),
+                ..SharedState::new(&self.config)
+            })),
+            ..self.clone()
+        };
         
+        let state = service.state.clone();
         tokio::spawn(async move {
+            // Voice frames may arrive slightly out of order; the jitter buffer
+            // handles that, so only flag frames far behind their stream
+            let mut sequences = SequenceTracker::new(VOICE_REORDER_WINDOW);
             while let Some(result) = in_stream.next().await {
                 match result {
                     Ok(msg) => {
-                        service.handle_client_message(msg, &tx_clone);
+                        service.tap_inbound(&msg);
+                        if let Err(e) = msg.validate().and_then(|_| sequences.check_client(&msg)) {
+                            warn!(error = %e, "Dropping invalid client message");
+                            continue;
+                        }
+                        service.handle_client_message(msg, &cx, &tx);
+                    }
+                    Err(e) if e.code() == tonic::Code::OutOfRange => {
+                        error!(
+                            error = %e,
+                            "Message over MAX_MESSAGE_BYTES; raise it or have the plugin split results"
+                        );
+                        break;
                     }
                     Err(e) => {
                         error!(error = %e, "Stream error");
@@ -381,12 +3148,579 @@ impl NpcSocietyService for ExampleNpcSocietyService {
                     }
                 }
             }
-            info!(peer = %peer_addr, "Connection closed");
+            {
+                let mut state = service.state.lock().unwrap();
+                state.connected = false;
+                state.tx = None;
+            }
+            info!(peer = %peer_addr, outbound = ?tx.stats(), "Connection closed");
+        });
+
+        #[cfg_attr(not(feature = "compression"), allow(unused_mut))]
+        let mut response = Response::new(self.wire(rx, state));
+        #[cfg(feature = "compression")]
+        self.compression.connect_response(&mut response);
+        Ok(response)
+    }
+
+    type ConnectAudioStream = Pin<Box<dyn Stream<Item = Result<ServerMessage, Status>> + Send>>;
+
+    async fn connect_audio(
+        &self,
+        request: Request<Streaming<ClientMessage>>,
+    ) -> Result<Response<Self::ConnectAudioStream>, Status> {
+        let mut in_stream = request.into_inner();
+        
+        // The first message says whose audio this is
+        let hello = match in_stream.message().await? {
+            Some(ClientMessage { message: Some(ClientMsg::AudioStreamHello(hello)), .. }) => hello,
+            _ => return Err(Status::invalid_argument("ConnectAudio must start with AudioStreamHello")),
+        };
+        let server = self.server(&hello.server_id)?;
+        let (cx, tx) = {
+            let state = server.lock().unwrap();
+            match (&state.connection, &state.tx) {
+                (Some(cx), Some(tx)) if cx.accepts_audio_stream(&hello) => (cx.clone(), tx.clone()),
+                _ => return Err(Status::permission_denied("audio_stream_token matches no connection")),
+            }
+        };
+        let Some(audio) = tx.audio_stream() else {
+            return Err(Status::already_exists("connection already has an audio stream"));
+        };
+        info!(server_id = %hello.server_id, peer = %cx.peer_address(), "Audio stream opened");
+        
+        // Voice frames are handled as if they came on Connect
+        let service = ExampleNpcSocietyService { state: server.clone(), ..self.clone() };
+        tokio::spawn(async move {
+            let mut sequences = SequenceTracker::new(VOICE_REORDER_WINDOW);
+            while let Some(result) = in_stream.next().await {
+                // Ends with the connection it belongs to
+                if tx.is_closed() {
+                    break;
+                }
+                match result {
+                    Ok(msg @ ClientMessage { message: Some(ClientMsg::VoicePcmFrame(_)), .. }) => {
+                        service.tap_inbound(&msg);
+                        if let Err(e) = msg.validate().and_then(|_| sequences.check_client(&msg)) {
+                            warn!(error = %e, "Dropping invalid voice frame");
+                            continue;
+                        }
+                        service.handle_client_message(msg, &cx, &tx);
+                    }
+                    Ok(msg) => warn!(
+                        message = ?ClientEvent::type_of(&msg),
+                        "Dropping a message other than VoicePcmFrame on the audio stream"
+                    ),
+                    Err(e) => {
+                        error!(error = %e, "Audio stream error");
+                        break;
+                    }
+                }
+            }
+            info!(server_id = %cx.server_id(), "Audio stream closed, audio back on Connect");
         });
+        
+        Ok(Response::new(self.wire(audio, server)))
+    }
+
+    async fn get_snapshot(
+        &self,
+        request: Request<GetSnapshotRequest>,
+    ) -> Result<Response<GetSnapshotResponse>, Status> {
+        let req = request.into_inner();
+        let server = self.server(&req.server_id)?;
+        let state = server.lock().unwrap();
+        
+        let Some(tick) = state.latest_tick.as_ref() else {
+            return Err(Status::unavailable("no WorldTick received yet"));
+        };
+        
+        let npcs = tick
+            .npcs
+            .iter()
+            .filter(|npc| req.npc_ids.is_empty() || req.npc_ids.contains(&npc.npc_id))
+            .cloned()
+            .collect();
+        
+        Ok(Response::new(GetSnapshotResponse {
+            server_id: state.server_id().to_string(),
+            server_tick: tick.server_tick,
+            timestamp_ms: tick.timestamp_ms,
+            npcs,
+            nearby_players: tick.nearby_players.clone(),
+        }))
+    }
+
+    async fn list_npcs(
+        &self,
+        request: Request<ListNpcsRequest>,
+    ) -> Result<Response<ListNpcsResponse>, Status> {
+        let req = request.into_inner();
+        let server = self.server(&req.server_id)?;
+        let state = server.lock().unwrap();
+        
+        
+        let npc_ids = state
+            .latest_tick
+            .iter()
+            .flat_map(|tick| tick.npcs.iter().map(|npc| npc.npc_id.clone()))
+            .collect();
+        
+        Ok(Response::new(ListNpcsResponse {
+            server_id: state.server_id().to_string(),
+            npc_ids,
+        }))
+    }
+
+    async fn get_npc_state(
+        &self,
+        request: Request<GetNpcStateRequest>,
+    ) -> Result<Response<GetNpcStateResponse>, Status> {
+        let req = request.into_inner();
+        let server = self.server(&req.server_id)?;
+        let state = server.lock().unwrap();
+        
+        let Some((tick, npc)) = state.latest_tick.as_ref().and_then(|tick| {
+            tick.npcs.iter().find(|npc| npc.npc_id == req.npc_id).map(|npc| (tick, npc))
+        }) else {
+            return Err(Status::not_found(format!("unknown npc_id '{}'", req.npc_id)));
+        };
+        
+        Ok(Response::new(GetNpcStateResponse {
+            npc: Some(npc.clone()),
+            server_tick: tick.server_tick,
+            pending_directives: state.pending_for(&req.npc_id),
+            last_result: state.last_results.get(&req.npc_id).cloned(),
+            spend: Some(state.spend.spend(&req.npc_id, now_ms())),
+        }))
+    }
+
+    async fn list_pending_directives(
+        &self,
+        request: Request<ListPendingDirectivesRequest>,
+    ) -> Result<Response<ListPendingDirectivesResponse>, Status> {
+        let req = request.into_inner();
+        let server = self.server(&req.server_id)?;
+        let state = server.lock().unwrap();
+        
+        Ok(Response::new(ListPendingDirectivesResponse {
+            directives: state.pending_for(&req.npc_id),
+        }))
+    }
+
+    async fn get_session_info(
+        &self,
+        request: Request<GetSessionInfoRequest>,
+    ) -> Result<Response<GetSessionInfoResponse>, Status> {
+        let req = request.into_inner();
+        let server = self.server(&req.server_id)?;
+        let state = server.lock().unwrap();
+        
+        Ok(Response::new(state.session_info()))
+    }
+
+    async fn list_servers(
+        &self,
+        _request: Request<ListServersRequest>,
+    ) -> Result<Response<ListServersResponse>, Status> {
+        let servers = self
+            .servers
+            .all()
+            .into_iter()
+            .map(|(_, state)| state.lock().unwrap().session_info())
+            .collect();
+        
+        Ok(Response::new(ListServersResponse { servers }))
+    }
+
+    async fn send_directive(
+        &self,
+        request: Request<SendDirectiveRequest>,
+    ) -> Result<Response<SendDirectiveResponse>, Status> {
+        let req = request.into_inner();
+        let Some(mut directive) = req.directive else {
+            return Err(Status::invalid_argument("directive is required"));
+        };
+        let server = self.server(&req.server_id)?;
+        let (Some(tx), Some(cx)) = ({
+            let state = server.lock().unwrap();
+            (state.tx.clone(), state.connection.clone())
+        }) else {
+            return Err(Status::unavailable("server not connected"));
+        };
+        if directive.directive_id.is_empty() {
+            directive.directive_id = cx.next_directive_id(&directive.npc_id);
+        }
+        ServerMessage::from(directive.clone())
+            .validate()
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        
+        // Sent as that server's connection would send it: same policy,
+        // throttle, retries and pending list
+        let connection = ExampleNpcSocietyService { state: server, ..self.clone() };
+        let directive_id = directive.directive_id.clone();
+        info!(directive_id = %directive_id, npc_id = %directive.npc_id, "Sending operator directive");
+        connection
+            .send_directive(&tx, directive)
+            .map_err(|result| Status::failed_precondition(result.error_message))?;
+        
+        Ok(Response::new(SendDirectiveResponse { directive_id }))
+    }
+
+    async fn export_npc_state(
+        &self,
+        request: Request<ExportNpcStateRequest>,
+    ) -> Result<Response<ExportNpcStateResponse>, Status> {
+        let req = request.into_inner();
+        let server = self.server(&req.server_id)?;
+        let state = server.lock().unwrap();
+        
+        let Some(tick) = state.latest_tick.as_ref() else {
+            return Err(Status::unavailable("no WorldTick received yet"));
+        };
+        
+        // This example keeps no memory or inventory of its own, so those
+        // stay empty
+        let npcs = tick
+            .npcs
+            .iter()
+            .filter(|npc| req.npc_ids.is_empty() || req.npc_ids.contains(&npc.npc_id))
+            .map(|npc| NpcStateSnapshot {
+                npc_id: npc.npc_id.clone(),
+                server_id: state.server_id().to_string(),
+                exported_at_ms: now_ms(),
+                npc: Some(npc.clone()),
+                relationships: state.reputation.relationships(&npc.npc_id),
+                active_directives: state.pending_for(&npc.npc_id),
+                ..Default::default()
+            })
+            .collect();
+        
+        Ok(Response::new(ExportNpcStateResponse { npcs }))
+    }
+
+    async fn import_npc_state(
+        &self,
+        request: Request<ImportNpcStateRequest>,
+    ) -> Result<Response<ImportNpcStateResponse>, Status> {
+        let req = request.into_inner();
+        if req.npcs.iter().any(|snapshot| snapshot.npc_id.is_empty()) {
+            return Err(Status::invalid_argument("NpcStateSnapshot.npc_id is required"));
+        }
+        let server = self.server(&req.server_id)?;
+        let mut state = server.lock().unwrap();
+        
+        let mut npc_ids = Vec::new();
+        for snapshot in req.npcs {
+            state.reputation.restore(&snapshot.npc_id, &snapshot.relationships);
+            npc_ids.push(snapshot.npc_id.clone());
+            if req.restore_in_world {
+                state.restores.retain(|r| r.npc_id != snapshot.npc_id);
+                state.restores.push(snapshot);
+            }
+        }
+        info!(npcs = ?npc_ids, restore_in_world = req.restore_in_world, "Imported NPC state");
+        
+        Ok(Response::new(ImportNpcStateResponse { npc_ids }))
+    }
+
+    async fn transfer_npc(
+        &self,
+        request: Request<TransferNpcRequest>,
+    ) -> Result<Response<TransferNpcResponse>, Status> {
+        let req = request.into_inner();
+        if req.npc_id.is_empty() {
+            return Err(Status::invalid_argument("npc_id is required"));
+        }
+        // Both plugins take part in the handoff, so both must be connected
+        for server_id in [&req.source_server_id, &req.target_server_id] {
+            let connected = self
+                .servers
+                .get(server_id)
+                .is_some_and(|server| server.lock().unwrap().tx.is_some());
+            if !connected {
+                return Err(ResolveError::Unknown(server_id.clone()).into());
+            }
+        }
+        
+        let (done_tx, done_rx) = oneshot::channel();
+        let (transfer_id, prepare) = self.transfers.lock().unwrap().begin(
+            &req.npc_id,
+            &req.source_server_id,
+            &req.target_server_id,
+            req.spawn_position,
+            now_ms(),
+        )?;
+        self.transfer_waiters.lock().unwrap().insert(transfer_id.clone(), done_tx);
+        info!(
+            transfer_id = %transfer_id,
+            npc_id = %req.npc_id,
+            from = %req.source_server_id,
+            to = %req.target_server_id,
+            "Transferring NPC"
+        );
+        self.route(vec![prepare]);
+        
+        // Every transfer ends, at the latest when it times out
+        let transfer = done_rx
+            .await
+            .map_err(|_| Status::aborted("transfer abandoned"))?;
+        
+        Ok(Response::new(TransferNpcResponse {
+            transfer_id,
+            stage: transfer.stage as i32,
+            error_message: transfer.error_message,
+        }))
+    }
+}
+
+/// ASR backend selected by the environment, if any
+fn asr_from_env() -> Option<Arc<dyn AsrProvider>> {
+    #[cfg(feature = "asr-whisper")]
+    if let Ok(url) = std::env::var("WHISPER_URL") {
+        info!(url = %url, "Transcribing voice with whisper.cpp server");
+        return Some(Arc::new(asr::WhisperServerAsr::new(&url)));
+    }
+    None
+}
+
+/// Profiles from policy.profiles_dir, checked for edits every 2 seconds
+#[cfg(feature = "npc-profiles")]
+fn profiles_from_config(config: &DaemonConfig) -> Option<profiles::SharedProfiles> {
+    let dir = config.policy.profiles_dir.clone()?;
+    info!(dir = %dir.display(), "Loading NPC profiles");
+    let store = Arc::new(Mutex::new(profiles::ProfileStore::new(dir)));
+    profiles::spawn_watcher(store.clone(), Duration::from_secs(2));
+    Some(store)
+}
+
+/// Encodings from GRPC_COMPRESSION (default zstd,gzip; `none` disables),
+/// Connect stream compressed if GRPC_COMPRESS_CONNECT=1
+#[cfg(feature = "compression")]
+fn compression_from_env() -> Result<Compression, Box<dyn std::error::Error>> {
+    let mut compression = match std::env::var("GRPC_COMPRESSION") {
+        Ok(names) => Compression::from_names(&names)?,
+        Err(_) => Compression::default(),
+    };
+    compression.compress_connect = std::env::var("GRPC_COMPRESS_CONNECT").is_ok_and(|v| v == "1");
+    info!(encodings = ?compression.encodings, connect = compression.compress_connect, "gRPC compression");
+    Ok(compression)
+}
+
+/// SQLite store at NPC_DB, if set
+#[cfg(feature = "persistence")]
+fn store_from_env() -> Result<Option<Arc<Mutex<SqliteStore>>>, Box<dyn std::error::Error>> {
+    let Ok(path) = std::env::var("NPC_DB") else {
+        return Ok(None);
+    };
+    info!(path = %path, "Persisting directive log and dialogue");
+    Ok(Some(Arc::new(Mutex::new(SqliteStore::open(path)?))))
+}
+
+/// Conversation transcripts under NPC_TRANSCRIPTS, if set
+#[cfg(feature = "persistence")]
+fn transcripts_from_env() -> Option<Arc<Mutex<TranscriptLog>>> {
+    let dir = std::env::var("NPC_TRANSCRIPTS").ok()?;
+    info!(dir = %dir, "Writing conversation transcripts");
+    Some(Arc::new(Mutex::new(TranscriptLog::new(TranscriptConfig::new(dir)))))
+}
+
+/// Name of this replica in NPC leases and directive ids: DAEMON_REPLICA_ID,
+/// or the process id
+fn replica_id() -> String {
+    std::env::var("DAEMON_REPLICA_ID").unwrap_or_else(|_| format!("replica-{}", std::process::id()))
+}
+
+/// NPC leases in LEASE_REDIS_URL, held as [`replica_id`], so replicas
+/// connected to the same servers split the NPCs
+#[cfg(feature = "lease-redis")]
+fn leases_from_env() -> Result<Option<SharedLeases>, Box<dyn std::error::Error>> {
+    let Ok(url) = std::env::var("LEASE_REDIS_URL") else {
+        return Ok(None);
+    };
+    let replica_id = replica_id();
+    info!(url = %url, replica_id = %replica_id, "Leasing NPCs");
+    let config = LeaseConfig::default();
+    // A lease must not outlive a hung command
+    let timeout = Duration::from_millis(config.renew_every_ms as u64);
+    let store = RedisLeases::open(&url, "npc-society", timeout)?;
+    let leases = SharedLeases::default();
+    let manager = LeaseManager::new(&replica_id, config, store);
+    std::thread::spawn({
+        let leases = leases.clone();
+        move || renew_leases(manager, config, &leases)
+    });
+    Ok(Some(leases))
+}
+
+/// The lease thread: every `renew_every_ms`, claim and renew leases on the
+/// NPCs the connections reported, off their tasks and without holding
+/// `leases` while Redis answers
+#[cfg(feature = "lease-redis")]
+fn renew_leases(mut manager: LeaseManager<RedisLeases>, config: LeaseConfig, leases: &Mutex<LeaseState>) {
+    loop {
+        let seen = std::mem::take(&mut leases.lock().unwrap().seen);
+        let synced = manager.sync(seen.iter().map(String::as_str), now_ms());
+        {
+            let mut leases = leases.lock().unwrap();
+            match synced {
+                Ok(changes) => {
+                    leases.changes.gained.extend(changes.gained);
+                    leases.changes.lost.extend(changes.lost);
+                }
+                // Leases run out unless a later round gets through
+                Err(e) => warn!(error = %e, "Failed to renew NPC leases"),
+            }
+            leases.owned = manager
+                .leases()
+                .map(|lease| (lease.npc_id.clone(), lease.clone()))
+                .collect();
+        }
+        std::thread::sleep(Duration::from_millis(config.renew_every_ms as u64));
+    }
+}
+
+/// Wire tap from TAP_LOG (log every message) and TAP_CAPTURE (append
+/// every message to that capture file)
+fn tap_from_env() -> Result<Tap, Box<dyn std::error::Error>> {
+    let tap = Tap::default();
+    if std::env::var_os("TAP_LOG").is_some() {
+        tap.register(LogTap);
+    }
+    if let Ok(path) = std::env::var("TAP_CAPTURE") {
+        info!(path = %path, "Capturing every message");
+        // Unbuffered: a daemon that is killed keeps its capture
+        tap.register(CaptureWriter::new(std::fs::File::create(&path)?)?);
+    }
+    Ok(tap)
+}
+
+/// Operators hear of flags and quarantines in the log; register a notifier
+/// here that pages whoever is on call or posts to the moderators' channel
+fn moderation_hooks() -> ModerationHooks {
+    let hooks = ModerationHooks::default();
+    hooks.register(|notice: &ModerationNotice<'_>| match notice {
+        ModerationNotice::Flagged { server_id, flag } => warn!(
+            server_id,
+            npc_id = %flag.npc_id,
+            flag_id = %flag.flag_id,
+            reporter_uuid = %flag.reporter_uuid,
+            by_moderator = flag.by_moderator,
+            category = %moderation::category_name(flag.category()),
+            excerpt = %flag.excerpt,
+            "NPC flagged"
+        ),
+        ModerationNotice::Quarantine { server_id, directive } if directive.quarantined => warn!(
+            server_id,
+            npc_id = %directive.npc_id,
+            reason = %directive.reason,
+            flag_ids = ?directive.flag_ids,
+            "NPC quarantined pending review"
+        ),
+        ModerationNotice::Quarantine { server_id, directive } => info!(
+            server_id,
+            npc_id = %directive.npc_id,
+            reason = %directive.reason,
+            "NPC released from quarantine"
+        ),
+    });
+    hooks
+}
+
+/// Web dashboard on DASHBOARD_ADDR (e.g. 127.0.0.1:8080), fed by the
+/// wire tap
+#[cfg(feature = "dashboard")]
+fn dashboard_from_env(tap: &Tap) -> Result<Option<Dashboard>, Box<dyn std::error::Error>> {
+    let Ok(addr) = std::env::var("DASHBOARD_ADDR") else {
+        return Ok(None);
+    };
+    let addr: std::net::SocketAddr = addr.parse()?;
+    let dashboard = Dashboard::new();
+    tap.register(dashboard.clone());
+    info!(address = %addr, "Serving the dashboard");
+    let server = dashboard.clone();
+    tokio::spawn(async move {
+        if let Err(e) = server.serve(addr).await {
+            error!(error = %e, "Dashboard stopped");
+        }
+    });
+    Ok(Some(dashboard))
+}
+
+/// The miner's replies in English, German and Spanish; a player in any
+/// other language hears English
+fn miner_lines() -> Catalog {
+    Catalog::new("en_us")
+        .with("en_us", "status", "Hello, {player}. Mining as usual, nothing to report.")
+        .with("en_us", "greeting", "Hello, {player}! I'll help you find diamonds.")
+        .with("en_us", "progress", "Still on it, {player}. Diamonds are deep, below Y=0.")
+        .with("de_de", "status", "Hallo, {player}. Es wird wie immer gegraben, nichts zu melden.")
+        .with("de_de", "greeting", "Hallo, {player}! Ich helfe dir, Diamanten zu finden.")
+        .with("de_de", "progress", "Bin dran, {player}. Diamanten liegen tief, unter Y=0.")
+        .with("es_es", "status", "Hola, {player}. Minando como siempre, nada que informar.")
+        .with("es_es", "greeting", "¡Hola, {player}! Te ayudaré a encontrar diamantes.")
+        .with("es_es", "progress", "Sigo en ello, {player}. Los diamantes están hondo, bajo Y=0.")
+}
 
-        let out_stream = ReceiverStream::new(rx);
-        Ok(Response::new(Box::pin(out_stream.map(Ok)) as Self::ConnectStream))
+/// Language, profanity and intent annotations for chat; swap the keyword
+/// intents for a model-backed IntentClassifier in production
+fn chat_pipeline() -> ChatPipeline {
+    ChatPipeline::default()
+        .stage(LanguageDetector::default())
+        .stage(ProfanityFilter::new(["darn", "heck"]))
+        .stage(IntentStage(
+            KeywordIntents::default()
+                .intent("mine", ["mine", "dig", "diamond", "diamonds", "ore"])
+                .intent("trade", ["buy", "sell", "trade", "price"])
+                .intent("greet", ["hello", "hi", "hey"]),
+        ))
+}
+
+/// The gRPC server with the [http2] settings, serving TLS if tls.cert and
+/// tls.key are set
+#[cfg(feature = "tls")]
+fn tls_server(config: &DaemonConfig) -> Result<Server, Box<dyn std::error::Error>> {
+    use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+    let (Some(cert), Some(key)) = (&config.tls.cert, &config.tls.key) else {
+        return Ok(config.http2.server(Server::builder()));
+    };
+    let mut tls = ServerTlsConfig::new().identity(Identity::from_pem(std::fs::read(cert)?, std::fs::read(key)?));
+    if let Some(ca) = &config.tls.client_ca {
+        tls = tls.client_ca_root(Certificate::from_pem(std::fs::read(ca)?));
+    }
+    info!(cert = %cert.display(), client_certificates = config.tls.client_ca.is_some(), "Serving TLS");
+    Ok(config.http2.server(Server::builder()).tls_config(tls)?)
+}
+
+/// The gRPC server with the [http2] settings; TLS needs the tls feature
+#[cfg(not(feature = "tls"))]
+fn tls_server(config: &DaemonConfig) -> Result<Server, Box<dyn std::error::Error>> {
+    if config.tls.is_enabled() {
+        return Err("tls.cert is set but the server was built without --features tls".into());
     }
+    Ok(config.http2.server(Server::builder()))
+}
+
+/// Connect and ConnectAudio over WebSocket on WEBSOCKET_ADDR (e.g.
+/// 127.0.0.1:8081), handled by the same service as gRPC and behind the
+/// same auth tokens
+#[cfg(feature = "websocket")]
+fn websocket_from_env(service: ExampleNpcSocietyService, config: &DaemonConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let Ok(addr) = std::env::var("WEBSOCKET_ADDR") else {
+        return Ok(());
+    };
+    let addr: std::net::SocketAddr = addr.parse()?;
+    let ws = WebSocketConnect::new(service)
+        .with_interceptor(config.auth.interceptor())
+        .with_max_message_bytes(config.max_message_bytes);
+    info!(address = %addr, "Serving Connect over WebSocket");
+    tokio::spawn(async move {
+        if let Err(e) = ws.serve(addr).await {
+            error!(error = %e, "WebSocket transport stopped");
+        }
+    });
+    Ok(())
 }
 
 #[tokio::main]
@@ -396,22 +3730,177 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with_max_level(Level::INFO)
         .init();
 
-    let port = std::env::var("PORT")
-        .ok()
-        .and_then(|p| p.parse().ok())
-        .unwrap_or(50051);
-    
-    let addr = format!("0.0.0.0:{}", port).parse()?;
-    let service = ExampleNpcSocietyService::default();
+    // SIMULATE=<seed> prints Example D's trace in a seeded simulation
+    // instead of serving; the same seed prints the same trace
+    if let Some(seed) = std::env::var("SIMULATE").ok().and_then(|s| s.parse().ok()) {
+        print!("{}", simulate_mining(seed));
+        return Ok(());
+    }
+
+    let config = DaemonConfig::from_process()?;
+    let addr = config.listen;
+    let tap = tap_from_env()?;
+    let moderation = moderation_hooks();
+    #[cfg(feature = "dashboard")]
+    let dashboard = dashboard_from_env(&tap)?;
+    let service = ExampleNpcSocietyService {
+        asr: asr_from_env(),
+        // Replace with a real engine; SilenceTts only exercises playback
+        tts: Some(Arc::new(tts::SilenceTts)),
+        speech: SpeechRegistry::with_pacing(PacingConfig {
+            lead_ms: config.audio.pacing_lead_ms,
+            max_lead_ms: config.audio.pacing_max_lead_ms,
+            ..Default::default()
+        }),
+        chat: Arc::new(chat_pipeline()),
+        lines: Arc::new(miner_lines()),
+        output: Arc::new(OutputFilter::new(config.output.clone())),
+        // Whatever writes the miner's lines, it never admits to being one
+        guards: Arc::new(GuardChain::new().with(StayInCharacter::default())),
+        commands: Arc::new(CommandRouter::admin()),
+        // The miners live by their chest
+        voice_commands: Arc::new(
+            VoiceGrammar::new()
+                .command(Follow)
+                .command(Stop)
+                .command(GoHome::to(MINERS_CHEST))
+                .command(GoTo),
+        ),
+        #[cfg(feature = "npc-profiles")]
+        profiles: profiles_from_config(&config),
+        #[cfg(feature = "compression")]
+        compression: compression_from_env()?,
+        #[cfg(feature = "persistence")]
+        store: store_from_env()?,
+        #[cfg(feature = "persistence")]
+        transcripts: transcripts_from_env(),
+        #[cfg(feature = "lease-redis")]
+        leases: leases_from_env()?,
+        tap,
+        moderation,
+        #[cfg(feature = "dashboard")]
+        dashboard,
+        ids: Arc::new(DirectiveIdFactory::starting_now(&replica_id())),
+        config: Arc::new(config),
+        ..Default::default()
+    };
+    // After a crash, pick up where the last run left off: unfinished
+    // directives are resent after each server's next Hello
+    #[cfg(feature = "persistence")]
+    if let Some(store) = &service.store {
+        let mut store = store.lock().unwrap();
+        for server_id in store.event_servers()? {
+            let replayed = replay::rebuild(&mut *store, &server_id, ReplayConfig::default(), now_ms())?;
+            info!(
+                server_id = %server_id,
+                events = replayed.events,
+                pending = replayed.pending.len(),
+                "Rebuilt state from NPC_DB"
+            );
+            service.servers.insert(&server_id, SharedState {
+                world: replayed.world,
+                reputation: replayed.reputation,
+                dialogues: replayed.dialogues,
+                latest_tick: replayed.latest_tick,
+                pending: replayed.pending,
+                ..SharedState::new(&service.config)
+            });
+        }
+    }
 
     info!("=== NPC Society Protocol Example Server ===");
     info!(address = %addr, "gRPC server starting");
     info!("Demonstrating: mining loop, audio correlation, error handling");
 
-    Server::builder()
-        .add_service(NpcSocietyServiceServer::new(service))
-        .serve(addr)
+    // NOT_SERVING on grpc.health.v1 until bound, and again once draining
+    let (health_reporter, health) = tonic_health::server::health_reporter();
+    let lifecycle = service.lifecycle.clone();
+    lifecycle.report_health::<NpcSocietyServiceServer<ExampleNpcSocietyService>>(health_reporter);
+    let servers = service.servers.clone();
+    #[cfg(feature = "persistence")]
+    let store = service.store.clone();
+
+    #[cfg(feature = "compression")]
+    let compression = service.compression.clone();
+    let config = service.config.clone();
+    let limits = MessageLimits {
+        max_decoding_bytes: config.max_message_bytes,
+        ..MessageLimits::default()
+    };
+    #[cfg(feature = "websocket")]
+    websocket_from_env(service.clone(), &config)?;
+    let server = limits.server(NpcSocietyServiceServer::new(service));
+    #[cfg(feature = "compression")]
+    let server = compression.server(server);
+    // Without auth.tokens every call passes
+    let server = InterceptedService::new(server, config.auth.interceptor());
+    // Browsers call the unary RPCs over gRPC-Web on the same port
+    #[cfg(feature = "grpc-web")]
+    let server = tonic_web::enable(server);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    lifecycle.ready();
+    let grace = Duration::from_millis(config.shutdown.grace_ms);
+    let drained = {
+        let servers = servers.clone();
+        async move {
+            lifecycle::shutdown_signal().await;
+            info!(grace_ms = grace.as_millis() as u64, "Shutting down: finishing in-flight directives");
+            let report = lifecycle
+                .drain(grace, || {
+                    // A disconnected server cannot answer; its directives
+                    // are reported below
+                    servers
+                        .all()
+                        .iter()
+                        .map(|(_, state)| state.lock().unwrap())
+                        .filter(|state| state.connected)
+                        .map(|state| state.pending.len())
+                        .sum()
+                })
+                .await;
+            info!(waited_ms = report.waited.as_millis() as u64, unfinished = report.in_flight, "Drained");
+        }
+    };
+    let incoming = TcpIncoming::from_listener(listener, config.http2.tcp_nodelay, None).map_err(|e| e as Box<dyn std::error::Error>)?;
+    #[cfg_attr(feature = "grpc-web", allow(unused_mut))]
+    let mut builder = tls_server(&config)?;
+    #[cfg(feature = "grpc-web")]
+    let mut builder = builder.accept_http1(true);
+    builder
+        .add_service(health)
+        .add_service(server)
+        .serve_with_incoming_shutdown(incoming, drained)
         .await?;
 
+    #[cfg(feature = "persistence")]
+    if let Some(store) = store {
+        if let Err(e) = store.lock().unwrap().checkpoint() {
+            warn!(error = %e, "Failed to flush NPC_DB");
+        }
+    }
+    report_directives(&servers);
     Ok(())
 }
+
+/// Log, per server, the directives still awaiting a result at shutdown.
+/// With NPC_DB they are resent after that server's next Hello.
+fn report_directives(servers: &ServerRegistry<SharedState>) {
+    for (server_id, state) in servers.all() {
+        let state = state.lock().unwrap();
+        for pending in &state.pending {
+            let Some(directive) = &pending.directive else {
+                continue;
+            };
+            warn!(
+                server_id = %server_id,
+                directive_id = %directive.directive_id,
+                npc_id = %directive.npc_id,
+                acked = pending.ack.is_some(),
+                age_ms = now_ms() - pending.sent_at_ms,
+                "Directive unfinished at shutdown"
+            );
+        }
+        info!(server_id = %server_id, unfinished = state.pending.len(), "Final directive state");
+    }
+}
@@ -0,0 +1,384 @@
+//! Mixing several players' voices into one ASR feed per NPC.
+//!
+//! When players talk over each other near an NPC, transcribing each
+//! stream on its own loses the exchange, and feeding the raw streams to
+//! ASR one after another produces garbage. [`VoiceMixer`] lines up the
+//! speakers' ordered 16kHz frames (see [`crate::conversation`]), sums them
+//! with a gain per speaker and segments the mix into utterances, each
+//! attributed to whoever was loudest in it.
+//!
+//! Players' microphones also pick up the NPC's own voice from their
+//! speakers. Tell the mixer what the NPC is saying with
+//! [`VoiceMixer::play`] and mixed frames that are not clearly louder than
+//! it are attenuated (echo suppression, not cancellation: a player
+//! talking over the NPC still gets through).
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::conversation::SpeakerEvent;
+use crate::vad::{self, EnergyVad, VadConfig, VadEvent};
+
+/// Length of one reference frame of NPC audio, matching voice frames
+const FRAME_MS: i64 = 20;
+
+/// Tuning for [`VoiceMixer`]. Frame counts assume 20ms frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MixerConfig {
+    /// Frames a speaker may fall behind before the mix goes on without them
+    pub max_lag_frames: usize,
+    /// Gain of a mixed frame taken for echo (0.0 = mute)
+    pub echo_attenuation: f32,
+    /// A frame this many times louder than the NPC's own voice is a player
+    /// talking over it, and kept
+    pub double_talk_ratio: f32,
+    /// How long after the NPC's audio played its echo may still arrive
+    pub echo_tail_ms: i64,
+    /// Utterance segmentation of the mix
+    pub vad: VadConfig,
+}
+
+impl Default for MixerConfig {
+    fn default() -> Self {
+        Self {
+            max_lag_frames: 5, // 100ms
+            echo_attenuation: 0.1,
+            double_talk_ratio: 2.0,
+            echo_tail_ms: 300,
+            vad: VadConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Lane {
+    /// (timestamp_ms, samples) not mixed yet, oldest first
+    frames: VecDeque<(i64, Vec<f32>)>,
+    /// Mixed frames since this speaker last had one in the mix
+    idle: usize,
+}
+
+#[derive(Debug, Default)]
+struct NpcMix {
+    lanes: HashMap<String, Lane>,
+    /// (start_ms, rms) of the NPC's own audio, oldest first
+    reference: VecDeque<(i64, f32)>,
+    vad: EnergyVad,
+    /// Energy per speaker in the mix since the utterance began
+    energy: HashMap<String, f32>,
+}
+
+/// Mixes the voices around each NPC into one utterance stream.
+#[derive(Debug, Default)]
+pub struct VoiceMixer {
+    config: MixerConfig,
+    gains: HashMap<(String, String), f32>,
+    npcs: HashMap<String, NpcMix>,
+}
+
+impl VoiceMixer {
+    /// Create a mixer with the given tuning
+    pub fn new(config: MixerConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Scale `player_uuid`'s voice near `npc_id` (default 1.0), e.g. to
+    /// quieten a player whose microphone is far too loud
+    pub fn set_gain(&mut self, npc_id: &str, player_uuid: &str, gain: f32) {
+        self.gains
+            .insert((npc_id.to_string(), player_uuid.to_string()), gain.max(0.0));
+    }
+
+    /// Record audio the NPC plays from `start_ms` (on the voice frames'
+    /// clock) as the echo reference; 16kHz mono f32
+    pub fn play(&mut self, npc_id: &str, samples: &[f32], start_ms: i64) {
+        let frame_len = vad_frame_len();
+        let npc = self.npc(npc_id);
+        for (i, frame) in samples.chunks(frame_len).enumerate() {
+            npc.reference
+                .push_back((start_ms + i as i64 * FRAME_MS, vad::rms(frame)));
+        }
+    }
+
+    /// Add one ordered 16kHz frame of `player_uuid` near `npc_id`, taken
+    /// at `timestamp_ms`, and mix whatever is ready
+    pub fn push(
+        &mut self,
+        npc_id: &str,
+        player_uuid: &str,
+        samples: Vec<f32>,
+        timestamp_ms: i64,
+    ) -> Vec<SpeakerEvent> {
+        let config = self.config;
+        let gains = &self.gains;
+        let npc = self
+            .npcs
+            .entry(npc_id.to_string())
+            .or_insert_with(|| NpcMix {
+                vad: EnergyVad::new(config.vad),
+                ..NpcMix::default()
+            });
+        npc.lanes
+            .entry(player_uuid.to_string())
+            .or_insert_with(|| Lane {
+                frames: VecDeque::new(),
+                idle: 0,
+            })
+            .frames
+            .push_back((timestamp_ms, samples));
+
+        let mut events = Vec::new();
+        while npc.ready(config.max_lag_frames) {
+            let (timestamp_ms, frame, levels) = npc.mix_frame(npc_id, gains);
+            let frame = npc.suppress_echo(frame, timestamp_ms, &config);
+            events.extend(npc.segment(npc_id, &frame, levels));
+        }
+        events
+    }
+
+    /// Drop `player_uuid`'s frames near `npc_id` (e.g. the player left)
+    pub fn remove(&mut self, npc_id: &str, player_uuid: &str) {
+        if let Some(npc) = self.npcs.get_mut(npc_id) {
+            npc.lanes.remove(player_uuid);
+        }
+    }
+
+    /// Drop `player_uuid`'s frames near every NPC and discard any mixed
+    /// utterance their voice is already part of (e.g. they withdrew
+    /// consent); the others' share of it is lost with it
+    pub fn forget(&mut self, player_uuid: &str) {
+        let vad_config = self.config.vad;
+        for npc in self.npcs.values_mut() {
+            npc.lanes.remove(player_uuid);
+            if npc.energy.remove(player_uuid).is_some() {
+                npc.vad = EnergyVad::new(vad_config);
+                npc.energy.clear();
+            }
+        }
+    }
+
+    fn npc(&mut self, npc_id: &str) -> &mut NpcMix {
+        let vad_config = self.config.vad;
+        self.npcs
+            .entry(npc_id.to_string())
+            .or_insert_with(|| NpcMix {
+                vad: EnergyVad::new(vad_config),
+                ..NpcMix::default()
+            })
+    }
+}
+
+impl NpcMix {
+    /// Whether a frame can be mixed: every speaker still talking has one
+    /// queued, or someone is so far ahead that the rest are left behind
+    fn ready(&self, max_lag_frames: usize) -> bool {
+        let longest = self
+            .lanes
+            .values()
+            .map(|l| l.frames.len())
+            .max()
+            .unwrap_or(0);
+        if longest == 0 {
+            return false;
+        }
+        longest > max_lag_frames
+            || self
+                .lanes
+                .values()
+                .all(|l| !l.frames.is_empty() || l.idle >= max_lag_frames)
+    }
+
+    /// Sum the earliest queued frame of every speaker whose next frame was
+    /// taken at about the same time; returns its time, the mix and each
+    /// speaker's energy in it
+    fn mix_frame(
+        &mut self,
+        npc_id: &str,
+        gains: &HashMap<(String, String), f32>,
+    ) -> (i64, Vec<f32>, Vec<(String, f32)>) {
+        let timestamp_ms = self
+            .lanes
+            .values()
+            .filter_map(|l| l.frames.front().map(|(at_ms, _)| *at_ms))
+            .min()
+            .unwrap_or_default();
+        let mut mixed: Vec<f32> = Vec::new();
+        let mut levels = Vec::new();
+        for (player_uuid, lane) in &mut self.lanes {
+            let Some((at_ms, _)) = lane.frames.front() else {
+                lane.idle += 1;
+                continue;
+            };
+            // A later frame waits for its turn
+            if *at_ms >= timestamp_ms + FRAME_MS / 2 {
+                continue;
+            }
+            let Some((_, samples)) = lane.frames.pop_front() else {
+                continue;
+            };
+            lane.idle = 0;
+            let gain = gains
+                .get(&(npc_id.to_string(), player_uuid.clone()))
+                .copied()
+                .unwrap_or(1.0);
+            if mixed.len() < samples.len() {
+                mixed.resize(samples.len(), 0.0);
+            }
+            let mut energy = 0.0;
+            for (out, sample) in mixed.iter_mut().zip(&samples) {
+                let sample = sample * gain;
+                energy += sample * sample;
+                *out += sample;
+            }
+            levels.push((player_uuid.clone(), energy));
+        }
+        // Keep the sum within range rather than clipping it
+        let peak = mixed.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        if peak > 1.0 {
+            mixed.iter_mut().for_each(|s| *s /= peak);
+        }
+        (timestamp_ms, mixed, levels)
+    }
+
+    /// Attenuate a frame that is probably the NPC's own voice coming back
+    fn suppress_echo(
+        &mut self,
+        mut frame: Vec<f32>,
+        timestamp_ms: i64,
+        config: &MixerConfig,
+    ) -> Vec<f32> {
+        let oldest = timestamp_ms - config.echo_tail_ms;
+        while self
+            .reference
+            .front()
+            .is_some_and(|(start_ms, _)| start_ms + FRAME_MS < oldest)
+        {
+            self.reference.pop_front();
+        }
+        let reference = self
+            .reference
+            .iter()
+            .take_while(|(start_ms, _)| *start_ms <= timestamp_ms)
+            .fold(0.0f32, |loudest, (_, rms)| loudest.max(*rms));
+        if reference > 0.0 && vad::rms(&frame) < reference * config.double_talk_ratio {
+            frame.iter_mut().for_each(|s| *s *= config.echo_attenuation);
+        }
+        frame
+    }
+
+    /// Run the mix through the VAD, crediting each utterance to its
+    /// loudest speaker
+    fn segment(
+        &mut self,
+        npc_id: &str,
+        frame: &[f32],
+        levels: Vec<(String, f32)>,
+    ) -> Vec<SpeakerEvent> {
+        let loudest_now = levels
+            .iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(player_uuid, _)| player_uuid.clone())
+            .unwrap_or_default();
+        for (player_uuid, energy) in levels {
+            *self.energy.entry(player_uuid).or_default() += energy;
+        }
+        match self.vad.process(frame) {
+            Some(VadEvent::UtteranceStart) => vec![SpeakerEvent::Started {
+                npc_id: npc_id.to_string(),
+                player_uuid: loudest_now,
+            }],
+            Some(VadEvent::UtteranceEnd { audio }) => {
+                let player_uuid = self
+                    .energy
+                    .drain()
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(player_uuid, _)| player_uuid)
+                    .unwrap_or_default();
+                vec![SpeakerEvent::Utterance {
+                    npc_id: npc_id.to_string(),
+                    player_uuid,
+                    audio,
+                }]
+            }
+            None => {
+                // Energy before an utterance starts belongs to no utterance,
+                // except the onset the VAD keeps
+                if !self.vad.is_speaking() && vad::rms(frame) < f32::EPSILON {
+                    self.energy.clear();
+                }
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Samples in one 20ms frame at the ASR rate
+fn vad_frame_len() -> usize {
+    (crate::audio::ASR_SAMPLE_RATE_HZ as i64 * FRAME_MS / 1000) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: usize = 320;
+
+    fn mixer() -> VoiceMixer {
+        VoiceMixer::new(MixerConfig {
+            vad: VadConfig {
+                min_speech_frames: 1,
+                hangover_frames: 2,
+                ..VadConfig::default()
+            },
+            ..MixerConfig::default()
+        })
+    }
+
+    fn utterance(events: &[SpeakerEvent]) -> Option<(&str, &[f32])> {
+        events.iter().find_map(|e| match e {
+            SpeakerEvent::Utterance {
+                player_uuid, audio, ..
+            } => Some((player_uuid.as_str(), audio.as_slice())),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_overlapping_speakers_mix() {
+        let mut mixer = mixer();
+        mixer.set_gain("npc", "bob", 0.5);
+        let mut events = Vec::new();
+        for i in 0..5 {
+            let (t, level) = (1_000 + i * 20, if i < 3 { 0.4 } else { 0.0 });
+            events.extend(mixer.push("npc", "alice", vec![level; FRAME], t));
+            events.extend(mixer.push("npc", "bob", vec![level; FRAME], t));
+        }
+        assert!(
+            matches!(&events[0], SpeakerEvent::Started { player_uuid, .. } if player_uuid == "alice")
+        );
+        let (player_uuid, audio) = utterance(&events).expect("utterance ended");
+        // Louder before Bob's gain, so Alice is credited
+        assert_eq!(player_uuid, "alice");
+        // Bob's first frame came too late to join Alice's; after that
+        // frames taken at the same time are mixed together
+        assert_eq!(audio.len(), 6 * FRAME);
+        assert!((audio[FRAME] - 0.2).abs() < 1e-6);
+        assert!((audio[2 * FRAME] - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_echo_is_suppressed() {
+        let mut mixer = mixer();
+        mixer.play("npc", &[0.3; FRAME * 3], 1_000);
+        // The NPC's voice coming back through a microphone, quieter
+        for i in 0..3 {
+            let events = mixer.push("npc", "alice", vec![0.2; FRAME], 1_000 + i * 20);
+            assert!(events.is_empty());
+        }
+        // A player talking over the NPC still gets through
+        let events = mixer.push("npc", "alice", vec![0.8; FRAME], 1_060);
+        assert!(matches!(&events[..], [SpeakerEvent::Started { .. }]));
+    }
+}
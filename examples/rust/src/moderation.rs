@@ -0,0 +1,371 @@
+//! Moderation flags and NPC quarantine (v1.2+).
+//!
+//! Players report what an NPC said or did with the plugin's report
+//! command, and moderators flag NPCs themselves; both arrive as
+//! ModerationFlags. [`Moderation`] keeps the flags per NPC and decides when
+//! an NPC is taken out of play: at once on a moderator's flag, or once
+//! enough different players flagged it within a window. A
+//! QuarantineNpcDirective silences and freezes just that NPC until a
+//! moderator releases it, so an incident no longer means stopping the
+//! daemon. The daemon should stop generating speech for a quarantined NPC
+//! as well ([`Moderation::is_quarantined`]); the plugin would reject it.
+//!
+//! Operators learn of every flag and quarantine through the
+//! [`ModerationNotifier`]s registered on [`ModerationHooks`], e.g. to page
+//! whoever is on call:
+//!
+//! ```ignore
+//! hooks.register(|notice: &ModerationNotice<'_>| warn!(?notice, "Moderation"));
+//! // ...
+//! hooks.notify(&ModerationNotice::Flagged { server_id, flag: &flag });
+//! if let Some(quarantine) = moderation.on_flag(&flag) {
+//!     hooks.notify(&ModerationNotice::Quarantine { server_id, directive: &quarantine });
+//!     tx.send(quarantine)?;
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use crate::npc_society::v1::{
+    ModerationCategory, ModerationFlag, QuarantineNpcDirective, WorldTick,
+};
+
+/// When flags quarantine an NPC
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModerationConfig {
+    /// Different players whose flags within `window_ms` quarantine the NPC
+    /// (0 = players' flags never do)
+    pub player_flags: usize,
+    /// How long a player's flag counts towards `player_flags`
+    pub window_ms: i64,
+    /// A moderator's flag quarantines the NPC at once
+    pub moderator_quarantines: bool,
+}
+
+impl Default for ModerationConfig {
+    /// Three players within ten minutes, or one moderator
+    fn default() -> Self {
+        Self {
+            player_flags: 3,
+            window_ms: 10 * 60 * 1000,
+            moderator_quarantines: true,
+        }
+    }
+}
+
+/// Flags and quarantines of one server's NPCs
+#[derive(Debug, Clone, Default)]
+pub struct Moderation {
+    config: ModerationConfig,
+    /// Flags per NPC: those in the window, or all since the quarantine
+    flags: HashMap<String, Vec<ModerationFlag>>,
+    /// The QuarantineNpcDirective in effect per quarantined NPC
+    quarantined: HashMap<String, QuarantineNpcDirective>,
+}
+
+impl Moderation {
+    /// No flags and no NPC quarantined
+    pub fn new(config: ModerationConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// When flags quarantine an NPC
+    pub fn config(&self) -> &ModerationConfig {
+        &self.config
+    }
+
+    /// Take a flag; returns the QuarantineNpcDirective to send if it
+    /// quarantines the NPC. Flags of a quarantined NPC are kept for review
+    /// and repeated flags (same flag_id) are ignored.
+    pub fn on_flag(&mut self, flag: &ModerationFlag) -> Option<QuarantineNpcDirective> {
+        let npc_id = &flag.npc_id;
+        let flags = self.flags.entry(npc_id.clone()).or_default();
+        if flags.iter().any(|f| f.flag_id == flag.flag_id) {
+            return None;
+        }
+        flags.push(flag.clone());
+        if let Some(quarantine) = self.quarantined.get_mut(npc_id) {
+            quarantine.flag_ids.push(flag.flag_id.clone());
+            return None;
+        }
+
+        let since_ms = flag.timestamp_ms - self.config.window_ms;
+        flags.retain(|f| f.timestamp_ms > since_ms);
+        let reason = if flag.by_moderator && self.config.moderator_quarantines {
+            format!("flagged by a moderator: {}", category_name(flag.category()))
+        } else {
+            let reporters: HashSet<&str> = flags
+                .iter()
+                .filter(|f| !f.by_moderator)
+                .map(|f| f.reporter_uuid.as_str())
+                .collect();
+            if self.config.player_flags == 0 || reporters.len() < self.config.player_flags {
+                return None;
+            }
+            format!("flagged by {} players", reporters.len())
+        };
+        let quarantine = QuarantineNpcDirective {
+            npc_id: npc_id.clone(),
+            quarantined: true,
+            reason,
+            flag_ids: flags.iter().map(|f| f.flag_id.clone()).collect(),
+        };
+        self.quarantined.insert(npc_id.clone(), quarantine.clone());
+        Some(quarantine)
+    }
+
+    /// Quarantine the NPC by hand, with its recent flags
+    pub fn quarantine(&mut self, npc_id: &str, reason: &str) -> QuarantineNpcDirective {
+        let quarantine = QuarantineNpcDirective {
+            npc_id: npc_id.to_string(),
+            quarantined: true,
+            reason: reason.to_string(),
+            flag_ids: self
+                .flags(npc_id)
+                .iter()
+                .map(|f| f.flag_id.clone())
+                .collect(),
+        };
+        self.apply(&quarantine);
+        quarantine
+    }
+
+    /// Release the NPC, forgetting its flags
+    pub fn release(&mut self, npc_id: &str, reason: &str) -> QuarantineNpcDirective {
+        let release = QuarantineNpcDirective {
+            npc_id: npc_id.to_string(),
+            quarantined: false,
+            reason: reason.to_string(),
+            flag_ids: Vec::new(),
+        };
+        self.apply(&release);
+        release
+    }
+
+    /// Take a QuarantineNpcDirective sent some other way, e.g. by a chat
+    /// command
+    pub fn apply(&mut self, directive: &QuarantineNpcDirective) {
+        if directive.quarantined {
+            self.quarantined
+                .insert(directive.npc_id.clone(), directive.clone());
+        } else {
+            self.quarantined.remove(&directive.npc_id);
+            self.flags.remove(&directive.npc_id);
+        }
+    }
+
+    /// Take the quarantines the plugin kept, e.g. across a daemon restart;
+    /// returns the ones not known before
+    pub fn observe_tick(&mut self, tick: &WorldTick) -> Vec<QuarantineNpcDirective> {
+        let mut learned = Vec::new();
+        for npc in &tick.npcs {
+            if !npc.quarantined || self.quarantined.contains_key(&npc.npc_id) {
+                continue;
+            }
+            let quarantine = QuarantineNpcDirective {
+                npc_id: npc.npc_id.clone(),
+                quarantined: true,
+                reason: "quarantined before this daemon connected".to_string(),
+                flag_ids: Vec::new(),
+            };
+            self.quarantined
+                .insert(npc.npc_id.clone(), quarantine.clone());
+            learned.push(quarantine);
+        }
+        learned
+    }
+
+    /// Whether the NPC is quarantined
+    pub fn is_quarantined(&self, npc_id: &str) -> bool {
+        self.quarantined.contains_key(npc_id)
+    }
+
+    /// Quarantines in effect, in no particular order
+    pub fn quarantined(&self) -> impl Iterator<Item = &QuarantineNpcDirective> {
+        self.quarantined.values()
+    }
+
+    /// Flags of the NPC kept for review, oldest first
+    pub fn flags(&self, npc_id: &str) -> &[ModerationFlag] {
+        self.flags
+            .get(npc_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+/// `MODERATION_CATEGORY_UNSAFE` as "unsafe"
+pub fn category_name(category: ModerationCategory) -> String {
+    match category {
+        ModerationCategory::Unspecified => "other".to_string(),
+        category => category
+            .as_str_name()
+            .trim_start_matches("MODERATION_CATEGORY_")
+            .to_lowercase(),
+    }
+}
+
+/// Something operators should know about
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModerationNotice<'a> {
+    /// A player or moderator flagged an NPC
+    Flagged {
+        server_id: &'a str,
+        flag: &'a ModerationFlag,
+    },
+    /// An NPC was quarantined or released (`directive.quarantined`)
+    Quarantine {
+        server_id: &'a str,
+        directive: &'a QuarantineNpcDirective,
+    },
+}
+
+/// Receives every [`ModerationNotice`]
+pub trait ModerationNotifier: Send + Sync {
+    fn notify(&self, notice: &ModerationNotice<'_>);
+}
+
+impl<F: Fn(&ModerationNotice<'_>) + Send + Sync> ModerationNotifier for F {
+    fn notify(&self, notice: &ModerationNotice<'_>) {
+        self(notice)
+    }
+}
+
+/// Notifiers shared by every connection; cheap to clone
+#[derive(Clone, Default)]
+pub struct ModerationHooks {
+    notifiers: Arc<RwLock<Vec<Arc<dyn ModerationNotifier>>>>,
+}
+
+impl fmt::Debug for ModerationHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ModerationHooks")
+            .field("notifiers", &self.notifiers.read().unwrap().len())
+            .finish()
+    }
+}
+
+impl ModerationHooks {
+    /// Start passing notices to `notifier`
+    pub fn register(&self, notifier: impl ModerationNotifier + 'static) {
+        self.notifiers.write().unwrap().push(Arc::new(notifier));
+    }
+
+    /// Pass `notice` to every notifier
+    pub fn notify(&self, notice: &ModerationNotice<'_>) {
+        for notifier in self.notifiers.read().unwrap().iter() {
+            notifier.notify(notice);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::NpcSnapshot;
+    use std::sync::Mutex;
+
+    fn flag(flag_id: &str, reporter: &str, timestamp_ms: i64) -> ModerationFlag {
+        ModerationFlag {
+            flag_id: flag_id.to_string(),
+            npc_id: "guide_01".to_string(),
+            reporter_uuid: reporter.to_string(),
+            category: ModerationCategory::Harassment as i32,
+            timestamp_ms,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_players_quarantine_within_window() {
+        let mut moderation = Moderation::default();
+        assert_eq!(moderation.on_flag(&flag("f1", "alice", 0)), None);
+        // The same player twice, and a repeated flag, count once
+        assert_eq!(moderation.on_flag(&flag("f2", "alice", 1_000)), None);
+        assert_eq!(moderation.on_flag(&flag("f2", "alice", 1_000)), None);
+        // Bob's flag comes after Alice's first left the window
+        assert_eq!(moderation.on_flag(&flag("f3", "bob", 601_000)), None);
+        assert_eq!(moderation.flags("guide_01").len(), 1);
+        assert_eq!(moderation.on_flag(&flag("f4", "carol", 602_000)), None);
+
+        let quarantine = moderation.on_flag(&flag("f5", "dave", 603_000)).unwrap();
+        assert!(quarantine.quarantined);
+        assert_eq!(quarantine.reason, "flagged by 3 players");
+        assert_eq!(quarantine.flag_ids, ["f3", "f4", "f5"]);
+        assert!(moderation.is_quarantined("guide_01"));
+
+        // Later flags are kept for review without another directive
+        assert_eq!(moderation.on_flag(&flag("f6", "erin", 604_000)), None);
+        let kept = moderation.quarantined().next().unwrap();
+        assert_eq!(kept.flag_ids.len(), 4);
+
+        let release = moderation.release("guide_01", "reviewed");
+        assert!(!release.quarantined);
+        assert!(!moderation.is_quarantined("guide_01"));
+        assert!(moderation.flags("guide_01").is_empty());
+    }
+
+    #[test]
+    fn test_moderator_flag_and_notifiers() {
+        let mut moderation = Moderation::new(ModerationConfig {
+            player_flags: 0,
+            ..Default::default()
+        });
+        let hooks = ModerationHooks::default();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        hooks.register(move |notice: &ModerationNotice<'_>| {
+            let line = match notice {
+                ModerationNotice::Flagged { flag, .. } => format!("flag {}", flag.flag_id),
+                ModerationNotice::Quarantine { directive, .. } => directive.reason.clone(),
+            };
+            sink.lock().unwrap().push(line);
+        });
+
+        assert_eq!(moderation.on_flag(&flag("f1", "alice", 0)), None);
+        let by_moderator = ModerationFlag {
+            by_moderator: true,
+            category: ModerationCategory::Unsafe as i32,
+            ..flag("f2", "mod", 1_000)
+        };
+        hooks.notify(&ModerationNotice::Flagged {
+            server_id: "survival",
+            flag: &by_moderator,
+        });
+        let quarantine = moderation.on_flag(&by_moderator).unwrap();
+        hooks.notify(&ModerationNotice::Quarantine {
+            server_id: "survival",
+            directive: &quarantine,
+        });
+        assert_eq!(
+            *seen.lock().unwrap(),
+            ["flag f2", "flagged by a moderator: unsafe"]
+        );
+
+        // A quarantine the plugin kept is learned once
+        let tick = WorldTick {
+            npcs: vec![
+                NpcSnapshot {
+                    npc_id: "guide_01".to_string(),
+                    quarantined: true,
+                    ..Default::default()
+                },
+                NpcSnapshot {
+                    npc_id: "guard_01".to_string(),
+                    quarantined: true,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let learned = moderation.observe_tick(&tick);
+        assert_eq!(learned.len(), 1);
+        assert_eq!(learned[0].npc_id, "guard_01");
+        assert!(moderation.observe_tick(&tick).is_empty());
+    }
+}
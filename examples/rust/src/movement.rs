@@ -0,0 +1,97 @@
+//! Movement modes for `MoveAction` (v1.2+).
+//!
+//! Besides walking, a plugin's pathfinder may swim, climb ladders and
+//! vines, or jump gaps; it lists what it can in `Hello.supported_movement`.
+//! `MoveAction` hints (`allow_swim`, `allow_climb`, `allow_parkour_jumps`)
+//! ask for a mode, and [`fit`] drops the ones the plugin cannot plan. A
+//! plugin that cannot swim gets `avoid_water` instead, so its path goes
+//! around water rather than stopping at the shore.
+
+use crate::npc_society::v1::{Hello, MoveAction, MovementMode};
+
+/// Movement modes the plugin can plan; none (walking only) if it listed none
+pub fn supported(hello: &Hello) -> Vec<MovementMode> {
+    hello
+        .supported_movement()
+        .filter(|mode| *mode != MovementMode::Unspecified)
+        .collect()
+}
+
+/// Make `action` plannable by the plugin that sent `hello`: hints for
+/// modes the plugin lacks are cleared. Returns the modes `action` asked
+/// for that it lacks.
+pub fn fit(action: &mut MoveAction, hello: &Hello) -> Vec<MovementMode> {
+    let supported = supported(hello);
+    let mut unsupported = Vec::new();
+    let hints = [
+        (MovementMode::Swim, &mut action.allow_swim),
+        (MovementMode::Climb, &mut action.allow_climb),
+        (MovementMode::Parkour, &mut action.allow_parkour_jumps),
+    ];
+    for (mode, hint) in hints {
+        if !supported.contains(&mode) && hint.take() == Some(true) {
+            unsupported.push(mode);
+        }
+    }
+    if !supported.contains(&MovementMode::Swim) {
+        action.avoid_water = true;
+    }
+    unsupported
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hello(modes: &[MovementMode]) -> Hello {
+        Hello {
+            supported_movement: modes.iter().map(|m| *m as i32).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fit() {
+        let everything = MoveAction {
+            allow_swim: Some(true),
+            allow_climb: Some(true),
+            allow_parkour_jumps: Some(false),
+            ..Default::default()
+        };
+
+        let mut action = everything.clone();
+        assert!(fit(
+            &mut action,
+            &hello(&[MovementMode::Swim, MovementMode::Climb])
+        )
+        .is_empty());
+        assert_eq!(
+            action,
+            MoveAction {
+                allow_parkour_jumps: None,
+                ..everything.clone()
+            }
+        );
+
+        // Walking only: the hints go, and water is avoided
+        let mut action = everything.clone();
+        assert_eq!(
+            fit(&mut action, &Hello::default()),
+            [MovementMode::Swim, MovementMode::Climb]
+        );
+        assert_eq!(
+            (
+                action.allow_swim,
+                action.allow_climb,
+                action.allow_parkour_jumps
+            ),
+            (None, None, None)
+        );
+        assert!(action.avoid_water);
+
+        let mut action = MoveAction::default();
+        assert!(fit(&mut action, &hello(&[MovementMode::Swim])).is_empty());
+        assert!(!action.avoid_water);
+        assert_eq!(supported(&hello(&[MovementMode::Unspecified])), []);
+    }
+}
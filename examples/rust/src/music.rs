@@ -0,0 +1,161 @@
+//! Note block songs (`PlayMusicDirective`, v1.2+).
+//!
+//! The plugin times the notes itself, so a song is one message however
+//! long it plays. [`song`] builds one from a melody written as note names,
+//! which keeps a bard's repertoire readable in code or config:
+//!
+//! ```text
+//! F#4 A4 C#5:2 R A4+C#5:2
+//! ```
+//!
+//! Each token lasts one beat unless `:beats` says otherwise (`:0.5` for a
+//! half beat); `R` is a rest and `+` joins notes into a chord. Notes span
+//! the note block range, F#3 to F#5, with `#` and `b` for accidentals.
+
+use std::fmt;
+
+use crate::npc_society::v1::{MusicNote, NoteInstrument, PlayMusicDirective};
+
+/// Highest note block pitch (F#5)
+pub const MAX_PITCH: i32 = 24;
+
+/// Most notes in one PlayMusicDirective
+pub const MAX_SONG_NOTES: usize = 2048;
+
+/// A melody token that could not be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct MelodyError {
+    /// The offending token
+    pub token: String,
+    /// What is wrong with it
+    pub reason: &'static str,
+}
+
+impl fmt::Display for MelodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid note {:?}: {}", self.token, self.reason)
+    }
+}
+
+impl std::error::Error for MelodyError {}
+
+/// Note block pitch of a note name such as "F#4" (12) or "Bb3" (4)
+pub fn pitch(note: &str) -> Result<i32, &'static str> {
+    let mut chars = note.chars();
+    let semitone = match chars.next().map(|c| c.to_ascii_uppercase()) {
+        Some('C') => 0,
+        Some('D') => 2,
+        Some('E') => 4,
+        Some('F') => 5,
+        Some('G') => 7,
+        Some('A') => 9,
+        Some('B') => 11,
+        _ => return Err("expected a note name A-G"),
+    };
+    let rest = chars.as_str();
+    let (semitone, octave) = match rest.strip_prefix('#') {
+        Some(octave) => (semitone + 1, octave),
+        None => match rest.strip_prefix('b') {
+            Some(octave) => (semitone - 1, octave),
+            None => (semitone, rest),
+        },
+    };
+    let octave: i32 = octave.parse().map_err(|_| "expected an octave number")?;
+    // F#3 is the lowest note block note
+    let pitch = octave * 12 + semitone - (3 * 12 + 6);
+    if !(0..=MAX_PITCH).contains(&pitch) {
+        return Err("outside the note block range F#3-F#5");
+    }
+    Ok(pitch)
+}
+
+/// A song of `melody` played by `npc_id` on `instrument` at `bpm` beats
+/// per minute
+pub fn song(
+    npc_id: impl Into<String>,
+    title: impl Into<String>,
+    melody: &str,
+    bpm: u32,
+    instrument: NoteInstrument,
+) -> Result<PlayMusicDirective, MelodyError> {
+    let beat_ms = 60_000.0 / bpm.max(1) as f64;
+    let mut notes = Vec::new();
+    let mut at_ms = 0.0f64;
+    for token in melody.split_whitespace() {
+        let error = |reason| MelodyError {
+            token: token.to_string(),
+            reason,
+        };
+        let (chord, beats) = match token.split_once(':') {
+            Some((chord, beats)) => match beats.parse::<f64>() {
+                Ok(beats) if beats > 0.0 => (chord, beats),
+                _ => return Err(error("beats must be a positive number")),
+            },
+            None => (token, 1.0),
+        };
+        if chord != "R" {
+            for note in chord.split('+') {
+                notes.push(MusicNote {
+                    at_ms: at_ms.round() as i64,
+                    instrument: instrument as i32,
+                    pitch: pitch(note).map_err(error)?,
+                });
+            }
+        }
+        at_ms += beat_ms * beats;
+    }
+    Ok(PlayMusicDirective {
+        npc_id: npc_id.into(),
+        title: title.into(),
+        notes,
+        length_ms: at_ms.round() as i64,
+        ..Default::default()
+    })
+}
+
+/// Stop whatever `npc_id` is playing
+pub fn stop(npc_id: impl Into<String>) -> PlayMusicDirective {
+    PlayMusicDirective {
+        npc_id: npc_id.into(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pitch() {
+        assert_eq!(pitch("F#3"), Ok(0));
+        assert_eq!(pitch("F#4"), Ok(12));
+        assert_eq!(pitch("Bb3"), Ok(4));
+        assert_eq!(pitch("F#5"), Ok(MAX_PITCH));
+        assert!(pitch("F3").is_err());
+        assert!(pitch("H4").is_err());
+        assert!(pitch("C").is_err());
+    }
+
+    #[test]
+    fn test_song() {
+        let tavern = song(
+            "bard",
+            "Tavern",
+            "F#4 A4:0.5 R C#5+A4:2",
+            120,
+            NoteInstrument::Flute,
+        )
+        .unwrap();
+        let timed: Vec<(i64, i32)> = tavern.notes.iter().map(|n| (n.at_ms, n.pitch)).collect();
+        assert_eq!(timed, [(0, 12), (500, 15), (1_250, 19), (1_250, 15)]);
+        assert_eq!(tavern.length_ms, 2_250);
+        assert!(tavern
+            .notes
+            .iter()
+            .all(|n| n.instrument() == NoteInstrument::Flute));
+
+        let err = song("bard", "", "F#4 G9", 120, NoteInstrument::Harp).unwrap_err();
+        assert_eq!(err.token, "G9");
+        assert!(song("bard", "", "F#4:0", 120, NoteInstrument::Harp).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! NPC backups.
+//!
+//! A world reset or a move to another server should not cost NPCs their
+//! memories. `ExportNpcState` hands out an [`NpcStateSnapshot`] per NPC;
+//! [`save`] writes them to disk in the same encoding as the RPC response,
+//! and [`load`] reads them back for `ImportNpcState`. With the `serde`
+//! feature, [`save_json`] and [`load_json`] write an editable JSON file
+//! instead.
+//!
+//! [`restore_directive`] turns a snapshot into the RestoreNpcState that
+//! puts the NPC back in the world.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use prost::Message;
+
+use crate::npc_society::v1::{ExportNpcStateResponse, NpcStateSnapshot, RestoreNpcState};
+
+/// The RestoreNpcState that puts the NPC back where and as it was
+pub fn restore_directive(snapshot: &NpcStateSnapshot) -> RestoreNpcState {
+    let npc = snapshot.npc.clone().unwrap_or_default();
+    RestoreNpcState {
+        npc_id: snapshot.npc_id.clone(),
+        position: npc.position,
+        health_norm: npc.health_norm,
+        equipment: npc.equipment.map(Box::new),
+        inventory: snapshot.inventory.clone(),
+        // Only a snapshot that knew the inventory may clear it
+        replace_inventory: !snapshot.inventory.is_empty(),
+        xp_level: snapshot.npc.as_ref().map(|n| n.xp_level),
+        xp_progress: npc.xp_progress,
+    }
+}
+
+/// Write `npcs` to `path`, replacing the file only once the new one is
+/// complete
+pub fn save(path: impl AsRef<Path>, npcs: &[NpcStateSnapshot]) -> io::Result<()> {
+    let backup = ExportNpcStateResponse {
+        npcs: npcs.to_vec(),
+    };
+    write_atomic(path.as_ref(), &backup.encode_to_vec())
+}
+
+/// Read snapshots written by [`save`]
+pub fn load(path: impl AsRef<Path>) -> io::Result<Vec<NpcStateSnapshot>> {
+    let bytes = fs::read(path)?;
+    ExportNpcStateResponse::decode(&bytes[..])
+        .map(|backup| backup.npcs)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Write `npcs` to `path` as JSON
+#[cfg(feature = "serde")]
+pub fn save_json(path: impl AsRef<Path>, npcs: &[NpcStateSnapshot]) -> io::Result<()> {
+    let backup = ExportNpcStateResponse {
+        npcs: npcs.to_vec(),
+    };
+    write_atomic(path.as_ref(), &serde_json::to_vec_pretty(&backup)?)
+}
+
+/// Read snapshots written by [`save_json`]
+#[cfg(feature = "serde")]
+pub fn load_json(path: impl AsRef<Path>) -> io::Result<Vec<NpcStateSnapshot>> {
+    let backup: ExportNpcStateResponse = serde_json::from_slice(&fs::read(path)?)?;
+    Ok(backup.npcs)
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    fs::write(&partial, bytes)?;
+    fs::rename(&partial, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{ItemStack, NpcSnapshot, Position, Relationship};
+
+    fn snapshot() -> NpcStateSnapshot {
+        NpcStateSnapshot {
+            npc_id: "miner".to_string(),
+            server_id: "survival-1".to_string(),
+            exported_at_ms: 1_000,
+            npc: Some(NpcSnapshot {
+                npc_id: "miner".to_string(),
+                position: Some(Position {
+                    world: "world".to_string(),
+                    x: 12.5,
+                    y: 40.0,
+                    z: -3.5,
+                    ..Default::default()
+                }),
+                health_norm: 0.8,
+                xp_level: 7,
+                ..Default::default()
+            }),
+            inventory: vec![ItemStack {
+                item_type: "minecraft:iron_ore".to_string(),
+                quantity: 23,
+                ..Default::default()
+            }],
+            relationships: vec![Relationship {
+                player_uuid: "069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string(),
+                score: 40.0,
+                updated_at_ms: 900,
+            }],
+            memory: [("home".to_string(), "the mine at 12 40 -3".to_string())].into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_restore_directive() {
+        let restore = restore_directive(&snapshot());
+        assert_eq!(restore.npc_id, "miner");
+        assert_eq!(restore.position.unwrap().x, 12.5);
+        assert_eq!(restore.xp_level, Some(7));
+        assert!(restore.replace_inventory);
+
+        // Nothing known: nothing changed
+        let unknown = restore_directive(&NpcStateSnapshot {
+            npc_id: "miner".to_string(),
+            ..Default::default()
+        });
+        assert_eq!((unknown.position, unknown.xp_level), (None, None));
+        assert!(!unknown.replace_inventory);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("npc-state-{}.pb", std::process::id()));
+        save(&path, &[snapshot()]).unwrap();
+        assert_eq!(load(&path).unwrap(), [snapshot()]);
+
+        fs::write(&path, b"not a backup").unwrap();
+        assert_eq!(load(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,759 @@
+//! Prioritized outbound queue.
+//!
+//! A single bounded channel lets a burst of audio delay directives behind
+//! it, and `let _ = tx.send(..)` hides every message that never made it.
+//! [`queue`] keeps one bounded queue per [`Priority`] and always sends the
+//! highest class first. When a class is full:
+//!
+//! - control and directives are refused with [`SendError::Full`]
+//! - audio and background messages push out the oldest queued message of
+//!   their class, which is stale by now
+//!
+//! A StopSpeaking also drops the queued audio it cancels. Every drop and
+//! refusal is counted in [`Outbound::stats`] and logged.
+//!
+//! Producers that can wait (a TTS task streaming chunks) should use
+//! [`Outbound::send_wait`], which waits for room instead of dropping.
+//!
+//! With [`Outbound::with_filter`], the text of every SpeakDirective and
+//! ChatDirective is cleaned by an [`OutputFilter`] before it is queued;
+//! what the filter rejects fails with [`SendError::Rejected`]. With
+//! [`Outbound::with_guards`], a [`GuardChain`] inspects every action and
+//! speech first; what it vetoes fails with [`SendError::Vetoed`].
+//!
+//! Priorities only order what is still queued: once on the wire, audio
+//! still shares the stream's flow-control window with directives. When
+//! the plugin opens a ConnectAudio stream, [`Outbound::audio_stream`]
+//! hands the audio class to an [`AudioReceiver`] for it. Producers keep
+//! sending through the same `Outbound`; audio goes back to the main stream
+//! when the `AudioReceiver` is dropped.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use tokio::sync::{mpsc, Notify};
+use tokio_stream::Stream;
+use tracing::{debug, warn};
+
+use crate::guardrail::GuardChain;
+use crate::npc_society::v1::{
+    server_message::Message as ServerMsg, OutboundClassStats, OutboundQueueStats, ServerMessage,
+    StopSpeaking,
+};
+use crate::sanitize::OutputFilter;
+
+/// Message class, highest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Priority {
+    /// HelloAck, StopSpeaking and SubscribeEvents
+    Control,
+    /// Action, speak, quest and currency directives
+    Directive,
+    /// AudioChunk and VisemeTimeline
+    Audio,
+    /// NpcMessage, particles and sounds
+    Background,
+}
+
+impl Priority {
+    const ALL: [Priority; 4] = [
+        Priority::Control,
+        Priority::Directive,
+        Priority::Audio,
+        Priority::Background,
+    ];
+
+    /// Class of `msg` (an empty message counts as background)
+    pub fn of(msg: &ServerMessage) -> Self {
+        match &msg.message {
+            Some(
+                ServerMsg::HelloAck(_)
+                | ServerMsg::StopSpeaking(_)
+                | ServerMsg::SubscribeEvents(_)
+                | ServerMsg::ListLandmarks(_)
+                // Withdrawn consent has to take effect at once
+                | ServerMsg::StopVoiceCapture(_)
+                // An NPC saying harmful things must be silenced first
+                | ServerMsg::QuarantineNpc(_)
+                // Probes measure the connection, not the directive queue
+                | ServerMsg::Probe(_)
+                | ServerMsg::FreezeNpc(_)
+                | ServerMsg::ResumeNpc(_),
+            ) => Priority::Control,
+            Some(
+                ServerMsg::ActionDirective(_)
+                | ServerMsg::SpeakDirective(_)
+                | ServerMsg::QuestOffer(_)
+                | ServerMsg::TransferCurrency(_)
+                | ServerMsg::SetCombatPolicy(_)
+                | ServerMsg::RestoreNpcState(_)
+                | ServerMsg::PrepareNpcTransfer(_)
+                | ServerMsg::SpawnTransferredNpc(_)
+                | ServerMsg::FinishNpcTransfer(_)
+                | ServerMsg::Chat(_)
+                | ServerMsg::Choreography(_)
+                | ServerMsg::TransportItems(_)
+                | ServerMsg::DialogueOptions(_)
+                | ServerMsg::Shop(_)
+                | ServerMsg::ShowDisplay(_)
+                | ServerMsg::RemoveDisplay(_)
+                | ServerMsg::SetTime(_)
+                | ServerMsg::SetWeather(_)
+                | ServerMsg::Formation(_)
+                | ServerMsg::GuardZone(_)
+                | ServerMsg::ClaimContainer(_)
+                | ServerMsg::RegisterLandmark(_)
+                | ServerMsg::RequestVoiceCapture(_)
+                | ServerMsg::DebugPath(_)
+                | ServerMsg::KeepChunkLoaded(_)
+                // Not droppable like streamed audio: a lost upload or
+                // play would silence the NPC
+                | ServerMsg::RegisterAudioAsset(_)
+                | ServerMsg::PlayAudioAsset(_),
+            ) => Priority::Directive,
+            Some(ServerMsg::AudioChunk(_) | ServerMsg::VisemeTimeline(_)) => Priority::Audio,
+            Some(
+                ServerMsg::NpcMessage(_)
+                    | ServerMsg::PlayParticle(_)
+                    | ServerMsg::PlaySound(_)
+                    | ServerMsg::PlayMusic(_),
+            )
+            | None => Priority::Background,
+        }
+    }
+
+    /// Whether stale messages of this class may be dropped to make room
+    fn droppable(self) -> bool {
+        matches!(self, Priority::Audio | Priority::Background)
+    }
+}
+
+/// Capacity of each class's queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueConfig {
+    /// HelloAck, StopSpeaking and SubscribeEvents
+    pub control: usize,
+    /// Directives
+    pub directives: usize,
+    /// Audio chunks and viseme timelines (~20ms of speech per chunk)
+    pub audio: usize,
+    /// NPC messages
+    pub background: usize,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            control: 32,
+            directives: 256,
+            audio: 512,
+            background: 64,
+        }
+    }
+}
+
+impl QueueConfig {
+    fn capacity(&self, priority: Priority) -> usize {
+        let capacity = match priority {
+            Priority::Control => self.control,
+            Priority::Directive => self.directives,
+            Priority::Audio => self.audio,
+            Priority::Background => self.background,
+        };
+        capacity.max(1)
+    }
+}
+
+/// Why a message was not queued
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError {
+    /// The class's queue is full and its messages are never dropped
+    Full(Priority),
+    /// The connection is gone
+    Closed,
+    /// The output filter refused the message's text
+    Rejected,
+    /// A guardrail vetoed the message
+    Vetoed,
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(priority) => write!(f, "outbound {:?} queue is full", priority),
+            Self::Closed => write!(f, "outbound stream is closed"),
+            Self::Rejected => write!(f, "outbound text rejected by the output filter"),
+            Self::Vetoed => write!(f, "outbound message vetoed by a guardrail"),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+#[derive(Debug, Default)]
+struct Queues {
+    config: QueueConfig,
+    messages: [VecDeque<ServerMessage>; 4],
+    stats: [OutboundClassStats; 4],
+    /// An AudioReceiver takes the audio class
+    audio_split: bool,
+    /// The OutboundReceiver was dropped
+    receiver_gone: bool,
+}
+
+impl Queues {
+    fn push(&mut self, msg: ServerMessage) -> Result<(), SendError> {
+        let priority = Priority::of(&msg);
+        let class = priority as usize;
+        if let Some(ServerMsg::StopSpeaking(stop)) = &msg.message {
+            self.drop_cancelled_audio(stop);
+        }
+        if self.messages[class].len() >= self.config.capacity(priority) {
+            if !priority.droppable() {
+                self.stats[class].rejected += 1;
+                return Err(SendError::Full(priority));
+            }
+            self.messages[class].pop_front();
+            self.stats[class].dropped += 1;
+            debug!(?priority, "Outbound queue full, dropped oldest message");
+        }
+        self.messages[class].push_back(msg);
+        let depth = self.messages[class].len() as u32;
+        let stats = &mut self.stats[class];
+        stats.depth = depth;
+        stats.high_water = stats.high_water.max(depth);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<ServerMessage> {
+        let audio = Priority::Audio as usize;
+        let class = (0..self.messages.len())
+            .filter(|&class| !(self.audio_split && class == audio))
+            .find(|&class| !self.messages[class].is_empty())?;
+        self.pop_class(class)
+    }
+
+    fn pop_audio(&mut self) -> Option<ServerMessage> {
+        self.pop_class(Priority::Audio as usize)
+    }
+
+    fn pop_class(&mut self, class: usize) -> Option<ServerMessage> {
+        let msg = self.messages[class].pop_front();
+        self.stats[class].depth = self.messages[class].len() as u32;
+        self.stats[class].sent += 1;
+        msg
+    }
+
+    fn has_room(&self, priority: Priority) -> bool {
+        self.messages[priority as usize].len() < self.config.capacity(priority)
+    }
+
+    /// Audio for a stream that was just stopped would only be discarded by
+    /// the plugin
+    fn drop_cancelled_audio(&mut self, stop: &StopSpeaking) {
+        let audio = Priority::Audio as usize;
+        let before = self.messages[audio].len();
+        self.messages[audio].retain(|msg| {
+            let (npc_id, stream_id) = match &msg.message {
+                Some(ServerMsg::AudioChunk(c)) => (&c.npc_id, &c.stream_id),
+                Some(ServerMsg::VisemeTimeline(v)) => (&v.npc_id, &v.stream_id),
+                _ => return true,
+            };
+            *npc_id != stop.npc_id || !(stop.stream_id.is_empty() || *stream_id == stop.stream_id)
+        });
+        let dropped = before - self.messages[audio].len();
+        if dropped > 0 {
+            self.stats[audio].dropped += dropped as u64;
+            self.stats[audio].depth = self.messages[audio].len() as u32;
+            debug!(npc_id = %stop.npc_id, dropped, "Dropped queued audio of stopped stream");
+        }
+    }
+}
+
+struct Shared {
+    queues: Mutex<Queues>,
+    /// Woken when a message is popped
+    space: Notify,
+    /// Receiving end of `Outbound::audio_wake` while no AudioReceiver has it
+    audio_wake: Mutex<Option<mpsc::Receiver<()>>>,
+}
+
+/// Sending half of the queue. Cheap to clone; the stream ends once every
+/// clone is dropped and the queue is drained.
+#[derive(Clone)]
+pub struct Outbound {
+    shared: Arc<Shared>,
+    /// Wakes the receiver; at most one wakeup is ever pending
+    wake: mpsc::Sender<()>,
+    /// Wakes the AudioReceiver, if there is one
+    audio_wake: mpsc::Sender<()>,
+    /// Cleans NPC text before it is queued
+    filter: Option<Arc<OutputFilter>>,
+    /// Inspects actions and speech before the filter
+    guards: Option<Arc<GuardChain>>,
+}
+
+/// Receiving half of the queue: a stream of messages in priority order
+pub struct OutboundReceiver {
+    shared: Arc<Shared>,
+    wake: mpsc::Receiver<()>,
+    /// Ends the AudioReceiver with this stream
+    audio_wake: mpsc::WeakSender<()>,
+}
+
+/// Receiving half for the audio class alone, see [`Outbound::audio_stream`].
+/// Ends when the [`OutboundReceiver`] is dropped, or like it once every
+/// sender is gone; dropping it sends audio to the main stream again.
+pub struct AudioReceiver {
+    shared: Arc<Shared>,
+    wake: Option<mpsc::Receiver<()>>,
+    /// Wakes the main receiver for the audio left behind
+    main_wake: mpsc::WeakSender<()>,
+}
+
+/// Create a queue with the given per-class capacities
+pub fn queue(config: QueueConfig) -> (Outbound, OutboundReceiver) {
+    let (audio_wake_tx, audio_wake_rx) = mpsc::channel(1);
+    let shared = Arc::new(Shared {
+        queues: Mutex::new(Queues {
+            config,
+            ..Default::default()
+        }),
+        space: Notify::new(),
+        audio_wake: Mutex::new(Some(audio_wake_rx)),
+    });
+    let (wake_tx, wake_rx) = mpsc::channel(1);
+    let audio_wake_weak = audio_wake_tx.downgrade();
+    (
+        Outbound {
+            shared: shared.clone(),
+            wake: wake_tx,
+            audio_wake: audio_wake_tx,
+            filter: None,
+            guards: None,
+        },
+        OutboundReceiver {
+            shared,
+            wake: wake_rx,
+            audio_wake: audio_wake_weak,
+        },
+    )
+}
+
+impl Outbound {
+    /// Queue `msg` without waiting. Audio and background messages always
+    /// succeed (possibly pushing out an older one); control and directives
+    /// fail when their queue is full.
+    pub fn send(&self, msg: impl Into<ServerMessage>) -> Result<(), SendError> {
+        if self.wake.is_closed() {
+            return Err(SendError::Closed);
+        }
+        let mut msg = msg.into();
+        // The chain and the filter log what they stop
+        if let Some(guards) = &self.guards {
+            guards.inspect_message(&mut msg).map_err(|_| SendError::Vetoed)?;
+        }
+        if let Some(filter) = &self.filter {
+            filter.filter(&mut msg).map_err(|_| SendError::Rejected)?;
+        }
+        let audio = Priority::of(&msg) == Priority::Audio;
+        let (result, split) = {
+            let mut queues = self.shared.queues.lock().unwrap();
+            (queues.push(msg), audio && queues.audio_split)
+        };
+        match result {
+            Ok(()) => {
+                // Full means a wakeup is already pending
+                let wake = if split { &self.audio_wake } else { &self.wake };
+                let _ = wake.try_send(());
+            }
+            Err(error) => warn!(%error, "Outbound message refused"),
+        }
+        result
+    }
+
+    /// Queue `msg`, waiting until its class has room. Fails only if the
+    /// connection closes.
+    pub async fn send_wait(&self, msg: impl Into<ServerMessage>) -> Result<(), SendError> {
+        let msg = msg.into();
+        let priority = Priority::of(&msg);
+        loop {
+            let space = self.shared.space.notified();
+            tokio::pin!(space);
+            // Register before checking so a pop in between is not missed
+            space.as_mut().enable();
+            if self.wake.is_closed() {
+                return Err(SendError::Closed);
+            }
+            if self.shared.queues.lock().unwrap().has_room(priority) {
+                return self.send(msg);
+            }
+            tokio::select! {
+                _ = space => {}
+                _ = self.wake.closed() => return Err(SendError::Closed),
+            }
+        }
+    }
+
+    /// Clean the text of every SpeakDirective and ChatDirective sent
+    /// through this handle and its clones with `filter`
+    pub fn with_filter(mut self, filter: Arc<OutputFilter>) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Run `guards` over every action and speech sent through this handle
+    /// and its clones
+    pub fn with_guards(mut self, guards: Arc<GuardChain>) -> Self {
+        self.guards = Some(guards);
+        self
+    }
+
+    /// Whether the receiver is gone
+    pub fn is_closed(&self) -> bool {
+        self.wake.is_closed()
+    }
+
+    /// Take the audio class (AudioChunk and VisemeTimeline) off the main
+    /// stream, for a ConnectAudio stream; None if one already has it.
+    /// Audio already queued goes out on the new stream.
+    pub fn audio_stream(&self) -> Option<AudioReceiver> {
+        let wake = self.shared.audio_wake.lock().unwrap().take()?;
+        self.shared.queues.lock().unwrap().audio_split = true;
+        let _ = self.audio_wake.try_send(());
+        Some(AudioReceiver {
+            shared: self.shared.clone(),
+            wake: Some(wake),
+            main_wake: self.wake.downgrade(),
+        })
+    }
+
+    /// Whether an [`AudioReceiver`] has the audio class
+    pub fn has_audio_stream(&self) -> bool {
+        self.shared.queues.lock().unwrap().audio_split
+    }
+
+    /// Counters per class
+    pub fn stats(&self) -> OutboundQueueStats {
+        stats(&self.shared)
+    }
+
+    /// Read-only handle for the counters that, unlike a clone, does not
+    /// keep the stream open
+    pub fn monitor(&self) -> OutboundMonitor {
+        OutboundMonitor(self.shared.clone())
+    }
+}
+
+impl fmt::Debug for Outbound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Outbound")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl OutboundReceiver {
+    /// Counters per class
+    pub fn stats(&self) -> OutboundQueueStats {
+        stats(&self.shared)
+    }
+
+    /// The next message if one is queued, without waiting (e.g. to drain
+    /// the queue in a synchronous test)
+    pub fn try_next(&mut self) -> Option<ServerMessage> {
+        let popped = self.shared.queues.lock().unwrap().pop();
+        if popped.is_some() {
+            self.shared.space.notify_waiters();
+        }
+        popped
+    }
+}
+
+/// Counters of a queue, see [`Outbound::monitor`]
+#[derive(Clone)]
+pub struct OutboundMonitor(Arc<Shared>);
+
+impl OutboundMonitor {
+    /// Counters per class
+    pub fn stats(&self) -> OutboundQueueStats {
+        stats(&self.0)
+    }
+}
+
+impl fmt::Debug for OutboundMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OutboundMonitor").field(&self.stats()).finish()
+    }
+}
+
+fn stats(shared: &Shared) -> OutboundQueueStats {
+    let queues = shared.queues.lock().unwrap();
+    let [control, directives, audio, background] = Priority::ALL.map(|p| queues.stats[p as usize]);
+    OutboundQueueStats {
+        control: Some(control),
+        directives: Some(directives),
+        audio: Some(audio),
+        background: Some(background),
+    }
+}
+
+impl Stream for OutboundReceiver {
+    type Item = ServerMessage;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ServerMessage>> {
+        let this = self.get_mut();
+        loop {
+            let popped = this.shared.queues.lock().unwrap().pop();
+            if let Some(msg) = popped {
+                this.shared.space.notify_waiters();
+                return Poll::Ready(Some(msg));
+            }
+            match this.wake.poll_recv(cx) {
+                Poll::Ready(Some(())) => continue,
+                // Every sender is gone and the queue is empty
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl Stream for AudioReceiver {
+    type Item = ServerMessage;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ServerMessage>> {
+        let this = self.get_mut();
+        let Some(wake) = this.wake.as_mut() else {
+            return Poll::Ready(None);
+        };
+        loop {
+            let popped = {
+                let mut queues = this.shared.queues.lock().unwrap();
+                if queues.receiver_gone {
+                    return Poll::Ready(None);
+                }
+                queues.pop_audio()
+            };
+            if let Some(msg) = popped {
+                this.shared.space.notify_waiters();
+                return Poll::Ready(Some(msg));
+            }
+            match wake.poll_recv(cx) {
+                Poll::Ready(Some(())) => continue,
+                // Every sender is gone and the audio queue is empty
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl Drop for OutboundReceiver {
+    fn drop(&mut self) {
+        self.shared.queues.lock().unwrap().receiver_gone = true;
+        if let Some(audio_wake) = self.audio_wake.upgrade() {
+            let _ = audio_wake.try_send(());
+        }
+    }
+}
+
+impl Drop for AudioReceiver {
+    fn drop(&mut self) {
+        self.shared.queues.lock().unwrap().audio_split = false;
+        *self.shared.audio_wake.lock().unwrap() = self.wake.take();
+        if let Some(main_wake) = self.main_wake.upgrade() {
+            let _ = main_wake.try_send(());
+        }
+    }
+}
+
+impl fmt::Debug for AudioReceiver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AudioReceiver")
+            .field("stats", &stats(&self.shared).audio)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guardrail::StayInCharacter;
+    use crate::npc_society::v1::{
+        ActionDirective, AudioChunk, ChatDirective, NpcMessage, SpeakDirective,
+    };
+    use tokio_stream::StreamExt;
+
+    fn chunk(stream_id: &str, sequence: u64) -> AudioChunk {
+        AudioChunk {
+            npc_id: "bard".to_string(),
+            stream_id: stream_id.to_string(),
+            sequence,
+            ..Default::default()
+        }
+    }
+
+    fn directive(id: &str) -> ActionDirective {
+        ActionDirective {
+            directive_id: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_priorities_and_pressure() {
+        let (tx, mut rx) = queue(QueueConfig {
+            directives: 1,
+            audio: 2,
+            ..Default::default()
+        });
+        for sequence in 0..3 {
+            tx.send(chunk("s1", sequence)).unwrap();
+        }
+        tx.send(NpcMessage::default()).unwrap();
+        tx.send(directive("d1")).unwrap();
+        assert_eq!(
+            tx.send(directive("d2")),
+            Err(SendError::Full(Priority::Directive))
+        );
+
+        // Directives overtake audio; the oldest chunk was pushed out
+        assert_eq!(Priority::of(&rx.next().await.unwrap()), Priority::Directive);
+        let sequences: Vec<u64> = [rx.next().await.unwrap(), rx.next().await.unwrap()]
+            .into_iter()
+            .map(|msg| match msg.message {
+                Some(ServerMsg::AudioChunk(c)) => c.sequence,
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(sequences, [1, 2]);
+
+        let stats = tx.stats();
+        let directives = stats.directives.unwrap();
+        assert_eq!((directives.sent, directives.rejected), (1, 1));
+        let audio = stats.audio.unwrap();
+        assert_eq!((audio.dropped, audio.high_water, audio.depth), (1, 2, 0));
+        assert_eq!(stats.background.unwrap().depth, 1);
+
+        drop(tx);
+        assert_eq!(
+            Priority::of(&rx.next().await.unwrap()),
+            Priority::Background
+        );
+        assert!(rx.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stop_speaking_and_send_wait() {
+        let (tx, mut rx) = queue(QueueConfig {
+            audio: 1,
+            ..Default::default()
+        });
+        tx.send(chunk("s1", 0)).unwrap();
+        tx.send(StopSpeaking {
+            npc_id: "bard".to_string(),
+            stream_id: "s1".to_string(),
+        })
+        .unwrap();
+        assert_eq!(tx.stats().audio.unwrap().dropped, 1);
+
+        // Waits for the receiver instead of dropping
+        tx.send(chunk("s2", 0)).unwrap();
+        let sender = tx.clone();
+        let waiting = tokio::spawn(async move { sender.send_wait(chunk("s2", 1)).await });
+        assert_eq!(Priority::of(&rx.next().await.unwrap()), Priority::Control);
+        assert_eq!(Priority::of(&rx.next().await.unwrap()), Priority::Audio);
+        waiting.await.unwrap().unwrap();
+        assert_eq!(tx.stats().audio.unwrap().dropped, 1);
+
+        drop(rx);
+        assert_eq!(tx.send(chunk("s2", 2)), Err(SendError::Closed));
+        assert_eq!(tx.send_wait(directive("d")).await, Err(SendError::Closed));
+    }
+
+    #[tokio::test]
+    async fn test_audio_stream() {
+        let (tx, mut rx) = queue(QueueConfig::default());
+        tx.send(chunk("s1", 0)).unwrap();
+        let mut audio = tx.audio_stream().unwrap();
+        assert!(tx.has_audio_stream());
+        assert!(tx.audio_stream().is_none());
+
+        // Audio, queued before or after, goes to the audio stream only
+        tx.send(chunk("s1", 1)).unwrap();
+        tx.send(directive("d1")).unwrap();
+        assert_eq!(Priority::of(&rx.next().await.unwrap()), Priority::Directive);
+        assert_eq!(Priority::of(&audio.next().await.unwrap()), Priority::Audio);
+        assert_eq!(Priority::of(&audio.next().await.unwrap()), Priority::Audio);
+        assert!(rx.try_next().is_none());
+
+        // Once it closes, audio is back on the main stream
+        tx.send(chunk("s1", 2)).unwrap();
+        drop(audio);
+        assert!(!tx.has_audio_stream());
+        assert_eq!(Priority::of(&rx.next().await.unwrap()), Priority::Audio);
+        tx.send(chunk("s1", 3)).unwrap();
+        assert_eq!(Priority::of(&rx.next().await.unwrap()), Priority::Audio);
+
+        // A new one may take over; it ends with the senders, or with the
+        // main stream
+        let mut audio = tx.audio_stream().unwrap();
+        let sender = tx.clone();
+        drop(tx);
+        let ended = tokio::spawn(async move { audio.next().await });
+        drop(rx);
+        assert!(ended.await.unwrap().is_none());
+        let mut audio = sender.audio_stream().unwrap();
+        drop(sender);
+        assert!(audio.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_output_filter() {
+        let (tx, mut rx) = queue(QueueConfig::default());
+        let tx = tx.with_filter(Arc::new(OutputFilter::default()));
+        tx.send(SpeakDirective {
+            npc_id: "bard".to_string(),
+            text: "&4Hello\nthere".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            tx.send(ChatDirective {
+                text: "\u{7}".to_string(),
+                ..Default::default()
+            }),
+            Err(SendError::Rejected)
+        );
+        match rx.next().await.unwrap().message {
+            Some(ServerMsg::SpeakDirective(speak)) => {
+                assert_eq!(speak.text, "&\u{200B}4Hello there")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_guards() {
+        let (tx, mut rx) = queue(QueueConfig::default());
+        let guards = GuardChain::new().with(StayInCharacter::default());
+        let tx = tx.with_guards(Arc::new(guards));
+        assert_eq!(
+            tx.send(SpeakDirective {
+                npc_id: "bard".to_string(),
+                text: "I'm an AI and cannot sing.".to_string(),
+                ..Default::default()
+            }),
+            Err(SendError::Vetoed)
+        );
+        tx.send(directive("d")).unwrap();
+        match rx.next().await.unwrap().message {
+            Some(ServerMsg::ActionDirective(d)) => assert_eq!(d.directive_id, "d"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
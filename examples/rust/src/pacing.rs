@@ -0,0 +1,170 @@
+//! Real-time pacing of outbound `AudioChunk` streams (v1.2+).
+//!
+//! TTS produces a whole utterance at once, but the plugin plays it at
+//! 1x; sending it all in one burst leaves seconds of PCM sitting in the
+//! plugin's buffer. An [`AudioPacer`] lets a stream run ahead of playback
+//! by a lead of a few hundred milliseconds and holds the rest back. Until
+//! the plugin reports an `AudioBufferStatus`, playback is assumed to start
+//! with the first chunk; each report re-anchors the estimate, and an
+//! underrun lengthens the lead.
+
+use crate::audio;
+use crate::npc_society::v1::{AudioBufferStatus, AudioChunk, PcmFormat};
+
+/// Playback time of one Opus packet, as cut by [`crate::tts::chunk_speech`]
+const OPUS_PACKET_MS: i64 = 20;
+
+/// Tuning for [`AudioPacer`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PacingConfig {
+    /// Audio sent ahead of playback
+    pub lead_ms: i64,
+    /// Longest lead underruns may grow it to
+    pub max_lead_ms: i64,
+    /// Lead added for every underrun the plugin reports
+    pub underrun_step_ms: i64,
+}
+
+impl Default for PacingConfig {
+    fn default() -> Self {
+        Self {
+            lead_ms: 300,
+            max_lead_ms: 1_000,
+            underrun_step_ms: 100,
+        }
+    }
+}
+
+/// Paces one stream. Ask [`wait_ms`](Self::wait_ms) before each chunk and
+/// report it with [`sent`](Self::sent) once it went out.
+#[derive(Debug, Clone)]
+pub struct AudioPacer {
+    config: PacingConfig,
+    lead_ms: i64,
+    /// Audio sent so far
+    sent_ms: i64,
+    /// (local time, played_ms) of the latest known playback position
+    anchor: Option<(i64, i64)>,
+    underruns: i32,
+}
+
+impl AudioPacer {
+    /// Create a pacer for a new stream
+    pub fn new(config: PacingConfig) -> Self {
+        Self {
+            config,
+            lead_ms: config.lead_ms,
+            sent_ms: 0,
+            anchor: None,
+            underruns: 0,
+        }
+    }
+
+    /// Current lead, grown by underruns
+    pub fn lead_ms(&self) -> i64 {
+        self.lead_ms
+    }
+
+    /// Estimated playback position at `now_ms`
+    pub fn played_ms(&self, now_ms: i64) -> i64 {
+        match self.anchor {
+            Some((at_ms, played_ms)) => (played_ms + now_ms - at_ms).clamp(0, self.sent_ms),
+            None => 0,
+        }
+    }
+
+    /// Estimated audio the plugin has queued at `now_ms`
+    pub fn buffered_ms(&self, now_ms: i64) -> i64 {
+        self.sent_ms - self.played_ms(now_ms)
+    }
+
+    /// How long to wait before sending the next chunk (0 = send now)
+    pub fn wait_ms(&self, now_ms: i64) -> i64 {
+        (self.buffered_ms(now_ms) - self.lead_ms).max(0)
+    }
+
+    /// Record a chunk of `duration_ms` sent at `now_ms`
+    pub fn sent(&mut self, duration_ms: i64, now_ms: i64) {
+        // Playback starts with the first chunk until the plugin says
+        // otherwise
+        self.anchor.get_or_insert((now_ms, 0));
+        self.sent_ms += duration_ms;
+    }
+
+    /// Take the plugin's report, received at `local_ms` on our clock
+    pub fn on_status(&mut self, status: &AudioBufferStatus, local_ms: i64) {
+        self.anchor = Some((local_ms, status.played_ms));
+        if status.underruns > self.underruns {
+            let grown = self.config.underrun_step_ms * (status.underruns - self.underruns) as i64;
+            self.lead_ms = (self.lead_ms + grown).min(self.config.max_lead_ms);
+            self.underruns = status.underruns;
+        }
+    }
+}
+
+/// Playback time of a chunk: its PCM length, or one packet for Opus
+pub fn chunk_duration_ms(chunk: &AudioChunk) -> i64 {
+    match chunk.format() {
+        PcmFormat::Opus => OPUS_PACKET_MS,
+        _ => chunk.pcm_data.len() as i64 / 2 * 1000 / audio::PROTOCOL_SAMPLE_RATE_HZ as i64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pacing() {
+        let mut pacer = AudioPacer::new(PacingConfig::default());
+        // The lead goes out at once, then one chunk per 20ms
+        let mut now = 0;
+        let mut burst = 0;
+        while pacer.wait_ms(now) == 0 {
+            pacer.sent(20, now);
+            burst += 1;
+        }
+        assert_eq!(burst, 16);
+        assert_eq!(pacer.wait_ms(now), 20);
+        now += 20;
+        assert_eq!(pacer.wait_ms(now), 0);
+
+        // Playback started late: the plugin has more queued than estimated
+        pacer.on_status(
+            &AudioBufferStatus {
+                buffered_ms: 320,
+                played_ms: 0,
+                ..Default::default()
+            },
+            now,
+        );
+        assert_eq!(pacer.wait_ms(now), 20);
+
+        // Ran dry twice: send further ahead
+        pacer.on_status(
+            &AudioBufferStatus {
+                played_ms: 320,
+                underruns: 2,
+                ..Default::default()
+            },
+            now,
+        );
+        assert_eq!(pacer.lead_ms(), 500);
+        assert_eq!(pacer.wait_ms(now), 0);
+    }
+
+    #[test]
+    fn test_chunk_duration() {
+        let pcm = AudioChunk {
+            pcm_data: vec![0; crate::tts::FRAME_SAMPLES * 2].into(),
+            ..Default::default()
+        };
+        assert_eq!(chunk_duration_ms(&pcm), 20);
+        let opus = AudioChunk {
+            pcm_data: vec![0; 40].into(),
+            format: PcmFormat::Opus as i32,
+            ..Default::default()
+        };
+        assert_eq!(chunk_duration_ms(&opus), 20);
+    }
+}
@@ -0,0 +1,315 @@
+//! Path feasibility over the cached world.
+//!
+//! Before sending a `MoveAction`, [`plan_path`] runs a bounded A* over the
+//! blocks in a [`WorldModel`] to tell whether the target can be walked to
+//! (send `pathfind = true`), needs a tunnel (break the returned blocks
+//! first), or is out of reach. Blocks the daemon has never seen are
+//! guessed according to [`PathLimits::unknown`]; block types are judged by
+//! the properties scans and region snapshots reported, if any, and by
+//! [`is_passable`] otherwise.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::dimension::same_world;
+use crate::npc_society::v1::BlockPosition;
+use crate::world_model::WorldModel;
+
+/// How to treat blocks missing from the world model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unknown {
+    /// Assume air (surface, open caves)
+    Open,
+    /// Assume stone (underground)
+    Solid,
+}
+
+/// Search bounds and costs
+#[derive(Debug, Clone, Copy)]
+pub struct PathLimits {
+    /// Give up after expanding this many positions
+    pub max_nodes: usize,
+    /// Allow breaking solid blocks on the way
+    pub allow_digging: bool,
+    /// Extra cost per block broken, in steps
+    pub dig_cost: u32,
+    /// Guess for blocks the world model doesn't know
+    pub unknown: Unknown,
+}
+
+impl Default for PathLimits {
+    fn default() -> Self {
+        Self {
+            max_nodes: 4_096,
+            allow_digging: true,
+            dig_cost: 5,
+            unknown: Unknown::Open,
+        }
+    }
+}
+
+/// Result of [`plan_path`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reachability {
+    /// Walkable without breaking anything; `path` lists the feet positions
+    Walkable { path: Vec<BlockPosition> },
+    /// Reachable after breaking `dig`, in walking order
+    Dig {
+        path: Vec<BlockPosition>,
+        dig: Vec<BlockPosition>,
+    },
+    /// No path within [`PathLimits::max_nodes`] (or a different world)
+    Unreachable,
+}
+
+/// Block types an NPC can stand in, for types without reported properties
+pub fn is_passable(block_type: &str) -> bool {
+    matches!(
+        block_type,
+        "minecraft:air"
+            | "minecraft:cave_air"
+            | "minecraft:void_air"
+            | "minecraft:short_grass"
+            | "minecraft:tall_grass"
+            | "minecraft:torch"
+            | "minecraft:wall_torch"
+            | "minecraft:snow"
+    )
+}
+
+type Cell = (i32, i32, i32);
+
+/// Find a path for an NPC standing at `from` (feet block) to any position
+/// within `reach` blocks of `to`, e.g. `4.0` to open a chest.
+pub fn plan_path(
+    world: &WorldModel,
+    from: &BlockPosition,
+    to: &BlockPosition,
+    reach: f64,
+    limits: &PathLimits,
+) -> Reachability {
+    if !same_world(from, to) {
+        return Reachability::Unreachable;
+    }
+    let grid = Grid {
+        world,
+        name: &from.world,
+        unknown: limits.unknown,
+    };
+    let goal = (to.x, to.y, to.z);
+    let heuristic = |c: Cell| {
+        let d = (c.0 - goal.0).abs().max((c.1 - goal.1).abs()).max((c.2 - goal.2).abs());
+        (d as f64 - reach).max(0.0) as u32
+    };
+
+    let start = (from.x, from.y, from.z);
+    let mut open = BinaryHeap::new();
+    let mut cost: HashMap<Cell, u32> = HashMap::from([(start, 0)]);
+    let mut came_from: HashMap<Cell, Cell> = HashMap::new();
+    open.push(Reverse((heuristic(start), 0, start)));
+
+    let mut expanded = 0;
+    while let Some(Reverse((_, g, cell))) = open.pop() {
+        if g > cost[&cell] {
+            continue;
+        }
+        if distance(cell, goal) <= reach {
+            return grid.reconstruct(&came_from, cell);
+        }
+        expanded += 1;
+        if expanded > limits.max_nodes {
+            break;
+        }
+
+        for (dx, dz) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            for dy in [0, 1, -1] {
+                let next = (cell.0 + dx, cell.1 + dy, cell.2 + dz);
+                let Some(digs) = grid.step_digs(cell, next) else {
+                    continue;
+                };
+                if digs > 0 && !limits.allow_digging {
+                    continue;
+                }
+                let g = g + 1 + digs * limits.dig_cost;
+                let better = match cost.get(&next) {
+                    Some(&known) => g < known,
+                    None => true,
+                };
+                if better {
+                    cost.insert(next, g);
+                    came_from.insert(next, cell);
+                    open.push(Reverse((g + heuristic(next), g, next)));
+                }
+            }
+        }
+    }
+    Reachability::Unreachable
+}
+
+fn distance(a: Cell, b: Cell) -> f64 {
+    let (dx, dy, dz) = ((a.0 - b.0) as f64, (a.1 - b.1) as f64, (a.2 - b.2) as f64);
+    (dx * dx + dy * dy + dz * dz).sqrt()
+}
+
+struct Grid<'a> {
+    world: &'a WorldModel,
+    name: &'a str,
+    unknown: Unknown,
+}
+
+impl Grid<'_> {
+    fn position(&self, (x, y, z): Cell) -> BlockPosition {
+        BlockPosition {
+            world: self.name.to_string(),
+            x,
+            y,
+            z,
+            ..Default::default()
+        }
+    }
+
+    fn solid(&self, cell: Cell) -> bool {
+        match self.world.block(&self.position(cell)) {
+            Some(block) => !self.world.is_passable(&block.block_type),
+            None => self.unknown == Unknown::Solid,
+        }
+    }
+
+    /// Cells that must be clear to step from `from` to `to`: feet and head
+    /// at the target, plus headroom above `from` when climbing (or above
+    /// `to` when dropping).
+    fn body(from: Cell, to: Cell) -> Vec<Cell> {
+        let mut cells = vec![to, (to.0, to.1 + 1, to.2)];
+        match to.1 - from.1 {
+            1 => cells.push((from.0, from.1 + 2, from.2)),
+            -1 => cells.push((to.0, to.1 + 2, to.2)),
+            _ => {}
+        }
+        cells
+    }
+
+    fn unbreakable(&self, cell: Cell) -> bool {
+        self.world
+            .block(&self.position(cell))
+            .is_some_and(|b| self.world.is_unbreakable(&b.block_type))
+    }
+
+    /// Number of blocks to break for the step, or None if there is nothing
+    /// to stand on or the way is unbreakable. Unknown floors count as ground.
+    fn step_digs(&self, from: Cell, to: Cell) -> Option<u32> {
+        let floor = (to.0, to.1 - 1, to.2);
+        if self
+            .world
+            .block(&self.position(floor))
+            .is_some_and(|b| self.world.is_passable(&b.block_type))
+        {
+            return None;
+        }
+        let body = Self::body(from, to);
+        if body.iter().any(|&c| self.unbreakable(c)) {
+            return None;
+        }
+        Some(body.into_iter().filter(|&c| self.solid(c)).count() as u32)
+    }
+
+    fn reconstruct(&self, came_from: &HashMap<Cell, Cell>, end: Cell) -> Reachability {
+        let mut cells = vec![end];
+        while let Some(&prev) = came_from.get(cells.last().unwrap()) {
+            cells.push(prev);
+        }
+        cells.reverse();
+
+        let mut dig = Vec::new();
+        for pair in cells.windows(2) {
+            for cell in Self::body(pair[0], pair[1]) {
+                if self.solid(cell) {
+                    dig.push(self.position(cell));
+                }
+            }
+        }
+        let path = cells.into_iter().map(|c| self.position(c)).collect();
+        if dig.is_empty() {
+            Reachability::Walkable { path }
+        } else {
+            Reachability::Dig { path, dig }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_model::AIR;
+
+    fn pos(x: i32, y: i32, z: i32) -> BlockPosition {
+        BlockPosition {
+            world: "world".to_string(),
+            x,
+            y,
+            z,
+            ..Default::default()
+        }
+    }
+
+    /// A 1x2 corridor along +x from 0 to 5 at y=10, walled with stone
+    fn corridor(blocked_at: Option<i32>) -> WorldModel {
+        let mut world = WorldModel::default();
+        for x in -1..=6 {
+            for y in 9..=12 {
+                for z in -1..=1 {
+                    let open = (0..=5).contains(&x) && z == 0 && (y == 10 || y == 11);
+                    world.set_block(&pos(x, y, z), if open { AIR } else { "minecraft:stone" }, 0);
+                }
+            }
+        }
+        if let Some(x) = blocked_at {
+            world.set_block(&pos(x, 10, 0), "minecraft:stone", 0);
+            world.set_block(&pos(x, 11, 0), "minecraft:stone", 0);
+        }
+        world
+    }
+
+    #[test]
+    fn test_walkable_corridor() {
+        let world = corridor(None);
+        let limits = PathLimits {
+            unknown: Unknown::Solid,
+            ..PathLimits::default()
+        };
+        match plan_path(&world, &pos(0, 10, 0), &pos(5, 10, 0), 0.0, &limits) {
+            Reachability::Walkable { path } => {
+                assert_eq!(path.len(), 6);
+                assert_eq!(path.last(), Some(&pos(5, 10, 0)));
+            }
+            other => panic!("expected Walkable, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_blocked_corridor_needs_digging() {
+        let world = corridor(Some(3));
+        let limits = PathLimits {
+            unknown: Unknown::Solid,
+            ..PathLimits::default()
+        };
+        match plan_path(&world, &pos(0, 10, 0), &pos(5, 10, 0), 0.0, &limits) {
+            Reachability::Dig { dig, .. } => assert_eq!(dig, vec![pos(3, 10, 0), pos(3, 11, 0)]),
+            other => panic!("expected Dig, got {other:?}"),
+        }
+
+        // Stopping in front of the wall is enough to reach something behind it
+        assert!(matches!(
+            plan_path(&world, &pos(0, 10, 0), &pos(3, 10, 0), 1.0, &limits),
+            Reachability::Walkable { .. }
+        ));
+
+        let no_digging = PathLimits {
+            allow_digging: false,
+            ..limits
+        };
+        assert_eq!(
+            plan_path(&world, &pos(0, 10, 0), &pos(5, 10, 0), 0.0, &no_digging),
+            Reachability::Unreachable
+        );
+    }
+}
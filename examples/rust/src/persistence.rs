@@ -0,0 +1,591 @@
+//! Durable daemon state (feature `persistence`).
+//!
+//! Directive logs, NPC memory, dialogue history and reputation scores all
+//! live in memory in the other modules and are gone after a restart.
+//! [`Store`] is the storage they need, and [`SqliteStore`] implements it on
+//! a single SQLite file. A daemon that already runs Postgres implements
+//! `Store` on top of it instead; nothing else depends on SQLite.
+//!
+//! Protocol messages are stored as their protobuf encoding, so new fields
+//! survive a round trip through an older daemon.
+//!
+//! `Store` is synchronous. Calls take well under a millisecond on a local
+//! file, but a busy daemon should move them off the stream's task
+//! (`tokio::task::spawn_blocking`).
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use prost::Message;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::conversation::{Speaker, Turn};
+use crate::npc_society::v1::{ActionDirective, ActionResult, ClientMessage, Relationship};
+
+/// A sent directive and, once it arrived, its result
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectiveRecord {
+    /// The directive as sent
+    pub directive: ActionDirective,
+    /// Unix timestamp in milliseconds when it was sent
+    pub sent_at_ms: i64,
+    /// Its final ActionResult (None while in flight)
+    pub result: Option<ActionResult>,
+    /// Unix timestamp in milliseconds when the result arrived
+    pub finished_at_ms: Option<i64>,
+}
+
+/// A ClientMessage as it was received, for replay
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoggedEvent {
+    /// Position in the log; increases with every append
+    pub seq: i64,
+    /// Unix timestamp in milliseconds when the daemon received it
+    pub received_at_ms: i64,
+    /// The message
+    pub message: ClientMessage,
+}
+
+/// Storage for daemon state that should outlive the process.
+pub trait Store: Send {
+    /// Error of the backing database
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Log a directive sent to `server_id` at `sent_at_ms`. Logging it
+    /// again (a resend) keeps the first send time.
+    fn log_directive(
+        &mut self,
+        server_id: &str,
+        directive: &ActionDirective,
+        sent_at_ms: i64,
+    ) -> Result<(), Self::Error>;
+
+    /// Attach `result` from `server_id` to its directive; results of
+    /// unlogged directives are ignored
+    fn log_result(
+        &mut self,
+        server_id: &str,
+        result: &ActionResult,
+        received_at_ms: i64,
+    ) -> Result<(), Self::Error>;
+
+    /// Directives sent to `server_id` at or after `since_ms` that have no
+    /// result yet, oldest first
+    fn unfinished_directives(
+        &self,
+        server_id: &str,
+        since_ms: i64,
+    ) -> Result<Vec<DirectiveRecord>, Self::Error>;
+
+    /// The `limit` most recent directives of `npc_id`, newest first
+    fn directive_log(
+        &self,
+        npc_id: &str,
+        limit: usize,
+    ) -> Result<Vec<DirectiveRecord>, Self::Error>;
+
+    /// Set `key` in the memory of `npc_id`
+    fn remember(
+        &mut self,
+        npc_id: &str,
+        key: &str,
+        value: &str,
+        now_ms: i64,
+    ) -> Result<(), Self::Error>;
+
+    /// Remove `key` from the memory of `npc_id`
+    fn forget(&mut self, npc_id: &str, key: &str) -> Result<(), Self::Error>;
+
+    /// Everything `npc_id` remembers, by key
+    fn memory(&self, npc_id: &str) -> Result<BTreeMap<String, String>, Self::Error>;
+
+    /// Append a turn to the dialogue between `npc_id` and `player_uuid`
+    fn append_turn(
+        &mut self,
+        npc_id: &str,
+        player_uuid: &str,
+        turn: &Turn,
+    ) -> Result<(), Self::Error>;
+
+    /// The `limit` most recent turns of that dialogue, oldest first
+    fn dialogue(
+        &self,
+        npc_id: &str,
+        player_uuid: &str,
+        limit: usize,
+    ) -> Result<Vec<Turn>, Self::Error>;
+
+    /// Replace the reputation scores of `npc_id`
+    fn save_relationships(
+        &mut self,
+        npc_id: &str,
+        relationships: &[Relationship],
+    ) -> Result<(), Self::Error>;
+
+    /// Reputation scores of `npc_id`, by player_uuid
+    fn relationships(&self, npc_id: &str) -> Result<Vec<Relationship>, Self::Error>;
+
+    /// Append a message received from `server_id` to the event log
+    fn append_event(
+        &mut self,
+        server_id: &str,
+        message: &ClientMessage,
+        received_at_ms: i64,
+    ) -> Result<(), Self::Error>;
+
+    /// Up to `limit` messages logged from `server_id` after `after_seq`,
+    /// oldest first
+    fn events(
+        &self,
+        server_id: &str,
+        after_seq: i64,
+        limit: usize,
+    ) -> Result<Vec<LoggedEvent>, Self::Error>;
+
+    /// Servers with messages in the event log, sorted
+    fn event_servers(&self) -> Result<Vec<String>, Self::Error>;
+
+    /// Drop logged messages received before `before_ms`; returns how many
+    fn prune_events(&mut self, before_ms: i64) -> Result<usize, Self::Error>;
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS directives (
+        server_id TEXT NOT NULL,
+        directive_id TEXT NOT NULL,
+        npc_id TEXT NOT NULL,
+        directive BLOB NOT NULL,
+        sent_at_ms INTEGER NOT NULL,
+        result BLOB,
+        finished_at_ms INTEGER,
+        PRIMARY KEY (server_id, directive_id)
+    );
+    CREATE INDEX IF NOT EXISTS directives_by_npc ON directives (npc_id, sent_at_ms);
+    CREATE TABLE IF NOT EXISTS memory (
+        npc_id TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        updated_at_ms INTEGER NOT NULL,
+        PRIMARY KEY (npc_id, key)
+    );
+    CREATE TABLE IF NOT EXISTS turns (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        npc_id TEXT NOT NULL,
+        player_uuid TEXT NOT NULL,
+        from_npc INTEGER NOT NULL,
+        text TEXT NOT NULL,
+        timestamp_ms INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS turns_by_dialogue ON turns (npc_id, player_uuid, id);
+    CREATE TABLE IF NOT EXISTS relationships (
+        npc_id TEXT NOT NULL,
+        player_uuid TEXT NOT NULL,
+        score REAL NOT NULL,
+        updated_at_ms INTEGER NOT NULL,
+        PRIMARY KEY (npc_id, player_uuid)
+    );
+    CREATE TABLE IF NOT EXISTS events (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        server_id TEXT NOT NULL,
+        received_at_ms INTEGER NOT NULL,
+        message BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS events_by_time ON events (received_at_ms);
+    CREATE INDEX IF NOT EXISTS events_by_server ON events (server_id, seq);
+";
+
+/// [`Store`] on an SQLite database
+#[derive(Debug)]
+pub struct SqliteStore {
+    db: Connection,
+}
+
+impl SqliteStore {
+    /// Open (or create) the database at `path`
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    /// A database that lives only as long as the store, for tests
+    pub fn in_memory() -> rusqlite::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    /// Whether a directive with `directive_id` was ever sent to `server_id`,
+    /// e.g. to skip re-running one after a restart
+    pub fn is_logged(&self, server_id: &str, directive_id: &str) -> rusqlite::Result<bool> {
+        self.db
+            .query_row(
+                "SELECT 1 FROM directives WHERE server_id = ?1 AND directive_id = ?2",
+                params![server_id, directive_id],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|found| found.is_some())
+    }
+
+    /// Write the WAL back into the database file, e.g. before shutting
+    /// down, so the file alone holds everything
+    pub fn checkpoint(&self) -> rusqlite::Result<()> {
+        self.db
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+    }
+
+    fn init(db: Connection) -> rusqlite::Result<Self> {
+        db.pragma_update(None, "journal_mode", "WAL")?;
+        db.execute_batch(SCHEMA)?;
+        Ok(Self { db })
+    }
+}
+
+fn decode<M: Message + Default>(bytes: Vec<u8>) -> rusqlite::Result<M> {
+    M::decode(&bytes[..]).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Blob, Box::new(e))
+    })
+}
+
+impl Store for SqliteStore {
+    type Error = rusqlite::Error;
+
+    fn log_directive(
+        &mut self,
+        server_id: &str,
+        directive: &ActionDirective,
+        sent_at_ms: i64,
+    ) -> rusqlite::Result<()> {
+        self.db.execute(
+            "INSERT INTO directives (server_id, directive_id, npc_id, directive, sent_at_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (server_id, directive_id) DO NOTHING",
+            params![
+                server_id,
+                directive.directive_id,
+                directive.npc_id,
+                directive.encode_to_vec(),
+                sent_at_ms
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn log_result(
+        &mut self,
+        server_id: &str,
+        result: &ActionResult,
+        received_at_ms: i64,
+    ) -> rusqlite::Result<()> {
+        self.db.execute(
+            "UPDATE directives SET result = ?3, finished_at_ms = ?4
+             WHERE server_id = ?1 AND directive_id = ?2",
+            params![
+                server_id,
+                result.directive_id,
+                result.encode_to_vec(),
+                received_at_ms
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn directive_log(&self, npc_id: &str, limit: usize) -> rusqlite::Result<Vec<DirectiveRecord>> {
+        let mut query = self.db.prepare(
+            "SELECT directive, sent_at_ms, result, finished_at_ms FROM directives
+             WHERE npc_id = ?1 ORDER BY sent_at_ms DESC, rowid DESC LIMIT ?2",
+        )?;
+        let rows = query.query_map(params![npc_id, limit as i64], |row| {
+            Ok(DirectiveRecord {
+                directive: decode(row.get(0)?)?,
+                sent_at_ms: row.get(1)?,
+                result: row.get::<_, Option<Vec<u8>>>(2)?.map(decode).transpose()?,
+                finished_at_ms: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    fn remember(
+        &mut self,
+        npc_id: &str,
+        key: &str,
+        value: &str,
+        now_ms: i64,
+    ) -> rusqlite::Result<()> {
+        self.db.execute(
+            "INSERT INTO memory (npc_id, key, value, updated_at_ms) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (npc_id, key) DO UPDATE SET value = ?3, updated_at_ms = ?4",
+            params![npc_id, key, value, now_ms],
+        )?;
+        Ok(())
+    }
+
+    fn forget(&mut self, npc_id: &str, key: &str) -> rusqlite::Result<()> {
+        self.db.execute(
+            "DELETE FROM memory WHERE npc_id = ?1 AND key = ?2",
+            params![npc_id, key],
+        )?;
+        Ok(())
+    }
+
+    fn memory(&self, npc_id: &str) -> rusqlite::Result<BTreeMap<String, String>> {
+        let mut query = self
+            .db
+            .prepare("SELECT key, value FROM memory WHERE npc_id = ?1")?;
+        let rows = query.query_map(params![npc_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    fn append_turn(
+        &mut self,
+        npc_id: &str,
+        player_uuid: &str,
+        turn: &Turn,
+    ) -> rusqlite::Result<()> {
+        self.db.execute(
+            "INSERT INTO turns (npc_id, player_uuid, from_npc, text, timestamp_ms) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![npc_id, player_uuid, turn.speaker == Speaker::Npc, turn.text, turn.timestamp_ms],
+        )?;
+        Ok(())
+    }
+
+    fn dialogue(
+        &self,
+        npc_id: &str,
+        player_uuid: &str,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<Turn>> {
+        let mut query = self.db.prepare(
+            "SELECT from_npc, text, timestamp_ms FROM turns
+             WHERE npc_id = ?1 AND player_uuid = ?2 ORDER BY id DESC LIMIT ?3",
+        )?;
+        let rows = query.query_map(params![npc_id, player_uuid, limit as i64], |row| {
+            Ok(Turn {
+                speaker: if row.get(0)? {
+                    Speaker::Npc
+                } else {
+                    Speaker::Player
+                },
+                text: row.get(1)?,
+                timestamp_ms: row.get(2)?,
+            })
+        })?;
+        let mut turns = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+        turns.reverse();
+        Ok(turns)
+    }
+
+    fn save_relationships(
+        &mut self,
+        npc_id: &str,
+        relationships: &[Relationship],
+    ) -> rusqlite::Result<()> {
+        let tx = self.db.transaction()?;
+        tx.execute(
+            "DELETE FROM relationships WHERE npc_id = ?1",
+            params![npc_id],
+        )?;
+        for r in relationships {
+            tx.execute(
+                "INSERT INTO relationships (npc_id, player_uuid, score, updated_at_ms) VALUES (?1, ?2, ?3, ?4)",
+                params![npc_id, r.player_uuid, r.score, r.updated_at_ms],
+            )?;
+        }
+        tx.commit()
+    }
+
+    fn relationships(&self, npc_id: &str) -> rusqlite::Result<Vec<Relationship>> {
+        let mut query = self.db.prepare(
+            "SELECT player_uuid, score, updated_at_ms FROM relationships
+             WHERE npc_id = ?1 ORDER BY player_uuid",
+        )?;
+        let rows = query.query_map(params![npc_id], |row| {
+            Ok(Relationship {
+                player_uuid: row.get(0)?,
+                score: row.get(1)?,
+                updated_at_ms: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    fn unfinished_directives(
+        &self,
+        server_id: &str,
+        since_ms: i64,
+    ) -> rusqlite::Result<Vec<DirectiveRecord>> {
+        let mut query = self.db.prepare(
+            "SELECT directive, sent_at_ms FROM directives
+             WHERE server_id = ?1 AND result IS NULL AND sent_at_ms >= ?2
+             ORDER BY sent_at_ms, rowid",
+        )?;
+        let rows = query.query_map(params![server_id, since_ms], |row| {
+            Ok(DirectiveRecord {
+                directive: decode(row.get(0)?)?,
+                sent_at_ms: row.get(1)?,
+                result: None,
+                finished_at_ms: None,
+            })
+        })?;
+        rows.collect()
+    }
+
+    fn append_event(
+        &mut self,
+        server_id: &str,
+        message: &ClientMessage,
+        received_at_ms: i64,
+    ) -> rusqlite::Result<()> {
+        self.db.execute(
+            "INSERT INTO events (server_id, received_at_ms, message) VALUES (?1, ?2, ?3)",
+            params![server_id, received_at_ms, message.encode_to_vec()],
+        )?;
+        Ok(())
+    }
+
+    fn events(
+        &self,
+        server_id: &str,
+        after_seq: i64,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<LoggedEvent>> {
+        let mut query = self.db.prepare(
+            "SELECT seq, received_at_ms, message FROM events
+             WHERE server_id = ?1 AND seq > ?2 ORDER BY seq LIMIT ?3",
+        )?;
+        let rows = query.query_map(params![server_id, after_seq, limit as i64], |row| {
+            Ok(LoggedEvent {
+                seq: row.get(0)?,
+                received_at_ms: row.get(1)?,
+                message: decode(row.get(2)?)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    fn event_servers(&self) -> rusqlite::Result<Vec<String>> {
+        let mut query = self
+            .db
+            .prepare("SELECT DISTINCT server_id FROM events ORDER BY server_id")?;
+        let rows = query.query_map([], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    fn prune_events(&mut self, before_ms: i64) -> rusqlite::Result<usize> {
+        self.db.execute(
+            "DELETE FROM events WHERE received_at_ms < ?1",
+            params![before_ms],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{action_directive::Action, MoveAction};
+
+    #[test]
+    fn test_directive_log() {
+        let mut store = SqliteStore::in_memory().unwrap();
+        let directive = ActionDirective {
+            directive_id: "move-1".to_string(),
+            npc_id: "miner".to_string(),
+            action: Some(Action::Move(MoveAction::default())),
+            ..Default::default()
+        };
+        store.log_directive("survival", &directive, 1_000).unwrap();
+        store.log_directive("survival", &directive, 5_000).unwrap();
+        store
+            .log_directive(
+                "survival",
+                &ActionDirective {
+                    directive_id: "move-2".to_string(),
+                    ..directive.clone()
+                },
+                2_000,
+            )
+            .unwrap();
+        store
+            .log_result(
+                "survival",
+                &ActionResult {
+                    directive_id: "move-1".to_string(),
+                    success: true,
+                    ..Default::default()
+                },
+                1_500,
+            )
+            .unwrap();
+
+        let log = store.directive_log("miner", 10).unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].directive.directive_id, "move-2");
+        assert_eq!(log[0].result, None);
+        assert_eq!(log[1].directive, directive);
+        assert_eq!(log[1].sent_at_ms, 1_000);
+        assert!(log[1].result.as_ref().unwrap().success);
+        assert_eq!(log[1].finished_at_ms, Some(1_500));
+        assert!(store.is_logged("survival", "move-1").unwrap());
+        assert!(!store.is_logged("survival", "move-3").unwrap());
+        assert!(!store.is_logged("creative", "move-1").unwrap());
+        let unfinished = store.unfinished_directives("survival", 0).unwrap();
+        assert_eq!(unfinished.len(), 1);
+        assert_eq!(unfinished[0].directive.directive_id, "move-2");
+        assert!(store
+            .unfinished_directives("survival", 3_000)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_memory_dialogue_and_relationships() {
+        let path = std::env::temp_dir().join(format!("npc-store-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let mut store = SqliteStore::open(&path).unwrap();
+            store.remember("miner", "home", "12 40 -3", 0).unwrap();
+            store.remember("miner", "home", "80 12 5", 1).unwrap();
+            store.remember("miner", "pet", "Rex", 1).unwrap();
+            store.forget("miner", "pet").unwrap();
+            for (i, (speaker, text)) in [
+                (Speaker::Player, "hi"),
+                (Speaker::Npc, "hello"),
+                (Speaker::Player, "ore?"),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let turn = Turn {
+                    speaker,
+                    text: text.to_string(),
+                    timestamp_ms: i as i64,
+                };
+                store.append_turn("miner", "steve", &turn).unwrap();
+            }
+            let scores = [Relationship {
+                player_uuid: "steve".to_string(),
+                score: 25.0,
+                updated_at_ms: 3,
+            }];
+            store.save_relationships("miner", &scores).unwrap();
+        }
+
+        // Everything survives reopening
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(
+            store.memory("miner").unwrap(),
+            [("home".to_string(), "80 12 5".to_string())].into()
+        );
+        let dialogue = store.dialogue("miner", "steve", 2).unwrap();
+        assert_eq!(dialogue.len(), 2);
+        assert_eq!(
+            (dialogue[0].speaker, dialogue[0].text.as_str()),
+            (Speaker::Npc, "hello")
+        );
+        assert_eq!(dialogue[1].text, "ore?");
+        assert!(store.dialogue("miner", "alex", 10).unwrap().is_empty());
+        assert_eq!(store.relationships("miner").unwrap()[0].score, 25.0);
+        drop(store);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+}
@@ -0,0 +1,578 @@
+//! Goal-oriented action planning (`--features planner`).
+//!
+//! Where a [`BehaviorTree`](crate::behavior::BehaviorTree) spells out the
+//! steps, a [`GoapAgent`] is told what it wants ([`Goal::have_item`]) and
+//! what each action does ([`ActionModel`]: preconditions and effects over a
+//! [`WorldState`] of named facts) and searches for the cheapest sequence of
+//! actions that gets there. Each step becomes an `ActionDirective`; the
+//! agent waits for its `ActionResult`, applies the step's effects when it
+//! succeeds and plans again from what it knows when it fails, when the next
+//! step's preconditions no longer hold or when a higher goal comes up.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::behavior::Blackboard;
+use crate::ids::DirectiveIdFactory;
+use crate::npc_society::v1::{action_directive::Action, ActionDirective, ActionResult, WorldTick};
+
+/// States the search may expand before giving up
+pub const MAX_PLAN_NODES: usize = 4096;
+/// Longest plan the search considers
+pub const MAX_PLAN_LENGTH: usize = 16;
+
+/// Named integer facts about the world, e.g. `item:minecraft:diamond = 2`.
+/// Facts that were never set read as 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct WorldState(BTreeMap<String, i64>);
+
+impl WorldState {
+    /// Key of the fact counting `item_type` in the NPC's inventory
+    pub fn item_key(item_type: &str) -> String {
+        format!("item:{item_type}")
+    }
+
+    /// Value of `key`, 0 if unset
+    pub fn get(&self, key: &str) -> i64 {
+        self.0.get(key).copied().unwrap_or(0)
+    }
+
+    /// Set `key` to `value`
+    pub fn set(&mut self, key: &str, value: i64) {
+        self.0.insert(key.to_string(), value);
+    }
+
+    /// Builder form of [`WorldState::set`]
+    pub fn with(mut self, key: &str, value: i64) -> Self {
+        self.set(key, value);
+        self
+    }
+
+    /// Builder form of setting the count of `item_type`
+    pub fn with_item(self, item_type: &str, count: i64) -> Self {
+        self.with(&Self::item_key(item_type), count)
+    }
+
+    /// Overwrite this state's facts with those in `other`
+    pub fn merge(&mut self, other: WorldState) {
+        self.0.extend(other.0);
+    }
+}
+
+/// A fact an action needs or a goal wants
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    /// The fact is at least the value
+    AtLeast(String, i64),
+    /// The fact is exactly the value
+    Equals(String, i64),
+}
+
+impl Condition {
+    /// Whether the condition holds in `state`
+    pub fn holds(&self, state: &WorldState) -> bool {
+        match self {
+            Condition::AtLeast(key, value) => state.get(key) >= *value,
+            Condition::Equals(key, value) => state.get(key) == *value,
+        }
+    }
+}
+
+/// How an action changes a fact
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Effect {
+    /// Set the fact to the value
+    Set(String, i64),
+    /// Add the value (negative to consume)
+    Add(String, i64),
+}
+
+impl Effect {
+    fn apply(&self, state: &mut WorldState) {
+        match self {
+            Effect::Set(key, value) => state.set(key, *value),
+            Effect::Add(key, value) => state.set(key, state.get(key) + value),
+        }
+    }
+}
+
+/// What the NPC wants: every condition holding at once
+#[derive(Debug, Clone)]
+pub struct Goal {
+    /// Name for logs
+    pub name: String,
+    /// Conditions that must all hold
+    pub conditions: Vec<Condition>,
+}
+
+impl Goal {
+    /// A goal without conditions yet
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            conditions: Vec::new(),
+        }
+    }
+
+    /// Also require `condition`
+    pub fn require(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    /// Have at least `count` of `item_type`
+    pub fn have_item(item_type: &str, count: i64) -> Self {
+        Self::new(&format!("have {count} {item_type}"))
+            .require(Condition::AtLeast(WorldState::item_key(item_type), count))
+    }
+
+    /// Whether the goal holds in `state`
+    pub fn is_met(&self, state: &WorldState) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.holds(state))
+    }
+}
+
+/// Makes the protocol action for a step from what the agent knows
+type MakeAction = Box<dyn Fn(&Blackboard) -> Option<Action> + Send + Sync>;
+
+/// An action the planner may use: when it applies, what it changes and how
+/// to send it.
+pub struct ActionModel {
+    /// Name of the step in plans and on the blackboard
+    pub name: String,
+    /// Search cost; plans minimise the sum
+    pub cost: u32,
+    /// Directive priority
+    pub priority: i32,
+    /// Facts that must hold before the action
+    pub preconditions: Vec<Condition>,
+    /// How the action changes the facts when it succeeds
+    pub effects: Vec<Effect>,
+    make: MakeAction,
+}
+
+impl ActionModel {
+    /// An action costing `cost`, sent as the action returned by `make`
+    /// (None fails the step)
+    pub fn new(
+        name: &str,
+        cost: u32,
+        make: impl Fn(&Blackboard) -> Option<Action> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            cost,
+            priority: 1,
+            preconditions: Vec::new(),
+            effects: Vec::new(),
+            make: Box::new(make),
+        }
+    }
+
+    /// Only usable while `condition` holds
+    pub fn requires(mut self, condition: Condition) -> Self {
+        self.preconditions.push(condition);
+        self
+    }
+
+    /// Changes the world by `effect` when it succeeds
+    pub fn effect(mut self, effect: Effect) -> Self {
+        self.effects.push(effect);
+        self
+    }
+
+    /// Send with directive priority `priority` (default 1)
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Whether the preconditions hold in `state`
+    pub fn applies(&self, state: &WorldState) -> bool {
+        self.preconditions
+            .iter()
+            .all(|condition| condition.holds(state))
+    }
+
+    fn apply(&self, state: &mut WorldState) {
+        for effect in &self.effects {
+            effect.apply(state);
+        }
+    }
+}
+
+impl fmt::Debug for ActionModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ActionModel")
+            .field("name", &self.name)
+            .field("cost", &self.cost)
+            .field("preconditions", &self.preconditions)
+            .field("effects", &self.effects)
+            .finish()
+    }
+}
+
+/// A sequence of actions reaching a goal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    /// Names of the actions, in order
+    pub steps: Vec<String>,
+    /// Sum of their costs
+    pub cost: u32,
+}
+
+/// Cheapest sequence of `models` that takes `state` to `goal`, by
+/// uniform-cost search. None if there is none within [`MAX_PLAN_LENGTH`]
+/// steps and [`MAX_PLAN_NODES`] expanded states. An already met goal gives
+/// an empty plan.
+pub fn plan(state: &WorldState, goal: &Goal, models: &[ActionModel]) -> Option<Plan> {
+    search(state, goal, models).map(|steps| Plan {
+        cost: steps.iter().map(|&i| models[i].cost).sum(),
+        steps: steps.iter().map(|&i| models[i].name.clone()).collect(),
+    })
+}
+
+/// [`plan`], as indices into `models`
+fn search(state: &WorldState, goal: &Goal, models: &[ActionModel]) -> Option<Vec<usize>> {
+    struct SearchNode {
+        state: WorldState,
+        // (parent node, model index)
+        via: Option<(usize, usize)>,
+        depth: usize,
+    }
+
+    let mut nodes = vec![SearchNode {
+        state: state.clone(),
+        via: None,
+        depth: 0,
+    }];
+    let mut open = BinaryHeap::from([Reverse((0u32, 0usize))]);
+    let mut best: HashMap<WorldState, u32> = HashMap::from([(state.clone(), 0)]);
+    let mut expanded = 0;
+
+    while let Some(Reverse((cost, index))) = open.pop() {
+        if best.get(&nodes[index].state).is_some_and(|&b| cost > b) {
+            continue;
+        }
+        if goal.is_met(&nodes[index].state) {
+            let mut steps = Vec::new();
+            let mut at = index;
+            while let Some((parent, model)) = nodes[at].via {
+                steps.push(model);
+                at = parent;
+            }
+            steps.reverse();
+            return Some(steps);
+        }
+        if nodes[index].depth >= MAX_PLAN_LENGTH {
+            continue;
+        }
+        expanded += 1;
+        if expanded > MAX_PLAN_NODES {
+            return None;
+        }
+        for (i, model) in models.iter().enumerate() {
+            if !model.applies(&nodes[index].state) {
+                continue;
+            }
+            let mut next = nodes[index].state.clone();
+            model.apply(&mut next);
+            let next_cost = cost + model.cost;
+            if best.get(&next).is_some_and(|&b| b <= next_cost) {
+                continue;
+            }
+            best.insert(next.clone(), next_cost);
+            nodes.push(SearchNode {
+                state: next,
+                via: Some((index, i)),
+                depth: nodes[index].depth + 1,
+            });
+            open.push(Reverse((next_cost, nodes.len() - 1)));
+        }
+    }
+    None
+}
+
+/// Plans and runs actions for one NPC towards its goals.
+pub struct GoapAgent {
+    models: Vec<ActionModel>,
+    goals: Vec<Goal>,
+    sense: Box<dyn Fn(&Blackboard) -> WorldState + Send + Sync>,
+    blackboard: Blackboard,
+    state: WorldState,
+    // Goal the plan is for, and the model indices still to run
+    pursuing: Option<usize>,
+    plan: VecDeque<usize>,
+    // Directive sent for the current step, and its model index
+    waiting: Option<(String, usize)>,
+    counter: u64,
+    ids: Option<Arc<DirectiveIdFactory>>,
+}
+
+impl GoapAgent {
+    /// An agent for `npc_id` planning with `models`. Every WorldTick, the
+    /// facts returned by `sense` overwrite what the agent believes; facts
+    /// `sense` leaves out keep the value the last successful effects gave.
+    pub fn new(
+        npc_id: &str,
+        models: Vec<ActionModel>,
+        sense: impl Fn(&Blackboard) -> WorldState + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            models,
+            goals: Vec::new(),
+            sense: Box::new(sense),
+            blackboard: Blackboard::for_npc(npc_id),
+            state: WorldState::default(),
+            pursuing: None,
+            plan: VecDeque::new(),
+            waiting: None,
+            counter: 0,
+            ids: None,
+        }
+    }
+
+    /// Add a goal. Goals are in priority order: the agent works on the
+    /// first one that isn't met.
+    pub fn goal(mut self, goal: Goal) -> Self {
+        self.goals.push(goal);
+        self
+    }
+
+    /// Take directive ids from `ids` instead of the agent's own counter
+    pub fn with_ids(mut self, ids: Arc<DirectiveIdFactory>) -> Self {
+        self.ids = Some(ids);
+        self
+    }
+
+    /// What the agent believes about the world
+    pub fn state(&self) -> &WorldState {
+        &self.state
+    }
+
+    /// The blackboard handed to `sense` and the action models
+    pub fn blackboard(&self) -> &Blackboard {
+        &self.blackboard
+    }
+
+    /// Name of the goal being worked on
+    pub fn current_goal(&self) -> Option<&str> {
+        self.pursuing.map(|i| self.goals[i].name.as_str())
+    }
+
+    /// Steps of the current plan not yet sent
+    pub fn remaining(&self) -> Vec<&str> {
+        self.plan
+            .iter()
+            .map(|&i| self.models[i].name.as_str())
+            .collect()
+    }
+
+    /// Refresh from `tick` and send the next step of the plan, planning
+    /// first if needed. Returns at most one directive; directive_ids are
+    /// `<npc_id>-goap-<n>`, or come from the factory given to
+    /// [`GoapAgent::with_ids`].
+    pub fn on_world_tick(&mut self, tick: &WorldTick) -> Vec<ActionDirective> {
+        if !self.blackboard.refresh(tick) {
+            return Vec::new();
+        }
+        self.state.merge((self.sense)(&self.blackboard));
+        if self.waiting.is_some() {
+            return Vec::new();
+        }
+
+        let Some(goal) = self.goals.iter().position(|goal| !goal.is_met(&self.state)) else {
+            self.pursuing = None;
+            self.plan.clear();
+            return Vec::new();
+        };
+        let stale = self.pursuing != Some(goal)
+            || self
+                .plan
+                .front()
+                .is_none_or(|&i| !self.models[i].applies(&self.state));
+        if stale {
+            self.pursuing = Some(goal);
+            self.blackboard.clear_results();
+            self.plan = search(&self.state, &self.goals[goal], &self.models)
+                .unwrap_or_default()
+                .into();
+        }
+
+        let Some(step) = self.plan.pop_front() else {
+            // No plan; try again next tick with fresh facts
+            return Vec::new();
+        };
+        let model = &self.models[step];
+        let Some(action) = (model.make)(&self.blackboard) else {
+            self.plan.clear();
+            return Vec::new();
+        };
+        let priority = model.priority;
+        let directive_id = self.next_id();
+        self.waiting = Some((directive_id.clone(), step));
+        vec![ActionDirective {
+            directive_id,
+            npc_id: self.blackboard.npc.npc_id.clone(),
+            priority,
+            action: Some(action),
+            ..Default::default()
+        }]
+    }
+
+    /// Deliver an ActionResult. Returns false if the agent wasn't waiting
+    /// for it. A failed step drops the rest of the plan.
+    pub fn on_action_result(&mut self, result: &ActionResult) -> bool {
+        match &self.waiting {
+            Some((id, step)) if *id == result.directive_id => {
+                let model = &self.models[*step];
+                if result.success {
+                    model.apply(&mut self.state);
+                } else {
+                    self.plan.clear();
+                }
+                self.blackboard.record(model.name.clone(), result);
+                self.waiting = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn next_id(&mut self) -> String {
+        let npc_id = &self.blackboard.npc.npc_id;
+        if let Some(ids) = &self.ids {
+            return ids.next_for(npc_id, "goap");
+        }
+        self.counter += 1;
+        format!("{}-goap-{}", npc_id, self.counter)
+    }
+}
+
+impl fmt::Debug for GoapAgent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GoapAgent")
+            .field("npc_id", &self.blackboard.npc.npc_id)
+            .field("goal", &self.current_goal())
+            .field("plan", &self.remaining())
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{CraftAction, NpcSnapshot, ScanBlocksAction};
+
+    const DIAMOND: &str = "minecraft:diamond";
+    const PICKAXE: &str = "minecraft:iron_pickaxe";
+    const IRON: &str = "minecraft:iron_ingot";
+
+    fn item(item_type: &str) -> String {
+        WorldState::item_key(item_type)
+    }
+
+    fn models() -> Vec<ActionModel> {
+        vec![
+            ActionModel::new("buy_diamond", 20, |_| None).effect(Effect::Add(item(DIAMOND), 1)),
+            ActionModel::new("craft_pickaxe", 2, |_| {
+                Some(Action::Craft(CraftAction {
+                    item_type: PICKAXE.to_string(),
+                    count: 1,
+                    ..Default::default()
+                }))
+            })
+            .requires(Condition::AtLeast(item(IRON), 3))
+            .effect(Effect::Add(item(IRON), -3))
+            .effect(Effect::Add(item(PICKAXE), 1)),
+            ActionModel::new("mine_diamond", 4, |_| {
+                Some(Action::ScanBlocks(ScanBlocksAction::default()))
+            })
+            .requires(Condition::AtLeast(item(PICKAXE), 1))
+            .effect(Effect::Add(item(DIAMOND), 1)),
+        ]
+    }
+
+    fn tick() -> WorldTick {
+        WorldTick {
+            npcs: vec![NpcSnapshot {
+                npc_id: "miner".to_string(),
+                ..NpcSnapshot::default()
+            }],
+            ..WorldTick::default()
+        }
+    }
+
+    fn result(directive_id: &str, success: bool) -> ActionResult {
+        ActionResult {
+            directive_id: directive_id.to_string(),
+            npc_id: "miner".to_string(),
+            success,
+            ..ActionResult::default()
+        }
+    }
+
+    #[test]
+    fn test_plan_is_cheapest() {
+        let goal = Goal::have_item(DIAMOND, 3);
+        let state = WorldState::default().with_item(IRON, 3);
+        let found = plan(&state, &goal, &models()).unwrap();
+        assert_eq!(
+            found.steps,
+            [
+                "craft_pickaxe",
+                "mine_diamond",
+                "mine_diamond",
+                "mine_diamond"
+            ]
+        );
+        assert_eq!(found.cost, 14);
+
+        // Without iron, buying is the only way
+        let found = plan(&WorldState::default(), &goal, &models()).unwrap();
+        assert_eq!(found.steps, ["buy_diamond"; 3]);
+
+        assert_eq!(
+            plan(&state, &Goal::have_item(DIAMOND, 0), &models())
+                .unwrap()
+                .steps
+                .len(),
+            0
+        );
+        assert!(plan(&state, &Goal::have_item("minecraft:stone", 1), &models()).is_none());
+    }
+
+    #[test]
+    fn test_agent_replans_on_failure() {
+        let mut agent = GoapAgent::new("miner", models(), |_| {
+            WorldState::default().with_item(IRON, 3)
+        })
+        .goal(Goal::have_item(DIAMOND, 2));
+
+        let sent = agent.on_world_tick(&tick());
+        assert!(matches!(sent[0].action, Some(Action::Craft(_))));
+        assert_eq!(agent.remaining(), ["mine_diamond", "mine_diamond"]);
+        // One step at a time
+        assert!(agent.on_world_tick(&tick()).is_empty());
+        assert!(!agent.on_action_result(&result("unknown", true)));
+
+        assert!(agent.on_action_result(&result(&sent[0].directive_id, true)));
+        assert_eq!(agent.state().get(&item(PICKAXE)), 1);
+        let sent = agent.on_world_tick(&tick());
+        assert!(matches!(sent[0].action, Some(Action::ScanBlocks(_))));
+
+        // A failed step drops the plan; the next tick plans again from what
+        // the agent believes
+        agent.on_action_result(&result(&sent[0].directive_id, false));
+        assert!(agent.remaining().is_empty());
+        let sent = agent.on_world_tick(&tick());
+        assert!(matches!(sent[0].action, Some(Action::ScanBlocks(_))));
+        assert_eq!(agent.remaining(), ["mine_diamond"]);
+        assert_eq!(agent.current_goal(), Some("have 2 minecraft:diamond"));
+    }
+}
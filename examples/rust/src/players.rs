@@ -0,0 +1,118 @@
+//! Who a player is, from `WorldTick.nearby_players`.
+//!
+//! An NPC should treat a creative-mode admin differently from a new
+//! survival player, and a player holding emeralds differently from one
+//! holding a sword. `PlayerSnapshot` carries the game mode, operator
+//! status, the permission nodes the plugin is configured to report, and
+//! equipment (v1.2+); these helpers read them. Plugins older than v1.2
+//! only report `held_item`, which [`main_hand`] falls back to.
+
+use crate::equipment;
+use crate::npc_society::v1::{EquipmentSlot, PlayerSnapshot};
+
+/// `PlayerSnapshot.game_mode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameMode {
+    /// `survival`
+    Survival,
+    /// `creative`
+    Creative,
+    /// `adventure`
+    Adventure,
+    /// `spectator`
+    Spectator,
+    /// Missing or unrecognized
+    Unknown,
+}
+
+/// The player's game mode
+pub fn game_mode(player: &PlayerSnapshot) -> GameMode {
+    match player.game_mode.to_ascii_lowercase().as_str() {
+        "survival" => GameMode::Survival,
+        "creative" => GameMode::Creative,
+        "adventure" => GameMode::Adventure,
+        "spectator" => GameMode::Spectator,
+        _ => GameMode::Unknown,
+    }
+}
+
+/// Whether the player is staff rather than a regular player: an operator,
+/// or in creative or spectator mode
+pub fn is_privileged(player: &PlayerSnapshot) -> bool {
+    player.op || matches!(game_mode(player), GameMode::Creative | GameMode::Spectator)
+}
+
+/// Whether the player has permission `node`. Only nodes the plugin is
+/// configured to report can be checked; others are always false.
+pub fn has_permission(player: &PlayerSnapshot, node: &str) -> bool {
+    player.permissions.iter().any(|p| p == node)
+}
+
+/// Item type in the player's main hand (None if the hand is empty)
+pub fn main_hand(player: &PlayerSnapshot) -> Option<&str> {
+    let held = player
+        .equipment
+        .as_ref()
+        .and_then(|e| equipment::item(e, EquipmentSlot::MainHand))
+        .map(|i| i.item_type.as_str())
+        .unwrap_or(&player.held_item);
+    Some(held).filter(|h| !h.is_empty())
+}
+
+/// Number of armor slots (head, chest, legs, feet) the player fills
+pub fn armor_pieces(player: &PlayerSnapshot) -> usize {
+    let Some(worn) = &player.equipment else {
+        return 0;
+    };
+    [
+        EquipmentSlot::Head,
+        EquipmentSlot::Chest,
+        EquipmentSlot::Legs,
+        EquipmentSlot::Feet,
+    ]
+    .into_iter()
+    .filter(|&slot| equipment::item(worn, slot).is_some())
+    .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{Equipment, ItemStack};
+
+    #[test]
+    fn test_profile() {
+        let newcomer = PlayerSnapshot {
+            game_mode: "survival".to_string(),
+            held_item: "minecraft:emerald".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(game_mode(&newcomer), GameMode::Survival);
+        assert!(!is_privileged(&newcomer));
+        assert_eq!(main_hand(&newcomer), Some("minecraft:emerald"));
+        assert_eq!(armor_pieces(&newcomer), 0);
+
+        let admin = PlayerSnapshot {
+            game_mode: "CREATIVE".to_string(),
+            permissions: vec!["npcsociety.vip".to_string()],
+            equipment: Some(Equipment {
+                head: Some(ItemStack {
+                    item_type: "minecraft:netherite_helmet".to_string(),
+                    quantity: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(is_privileged(&admin));
+        assert!(has_permission(&admin, "npcsociety.vip"));
+        assert!(!has_permission(&admin, "npcsociety.admin"));
+        assert_eq!(main_hand(&admin), None);
+        assert_eq!(armor_pieces(&admin), 1);
+        assert!(is_privileged(&PlayerSnapshot {
+            op: true,
+            ..Default::default()
+        }));
+    }
+}
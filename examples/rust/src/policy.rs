@@ -0,0 +1,471 @@
+//! Permission checks for outgoing directives.
+//!
+//! An [`ActionPolicy`] holds an [`NpcPolicy`] per NPC (plus a default):
+//! which action kinds the NPC may use at all, and which kinds are confined
+//! to [`Region`]s ("farmers may only break blocks inside the farm"). Run
+//! [`ActionPolicy::check`] before a directive goes on the wire so a
+//! runaway LLM-driven daemon cannot grief the server; a rejection comes
+//! back as a [`PolicyError`] saying what rule was broken.
+//!
+//! [`check_world`] rejects directives aimed at a world the NPC is not in,
+//! and [`check_claims`] does the same for the land claims the plugin reports
+//! in `NpcSnapshot.regions`, so directives that a protection plugin would
+//! deny are caught before they are sent.
+//!
+//! Time and weather changes (`SetTimeDirective`, `SetWeatherDirective`)
+//! affect every player, so they are denied until
+//! [`ActionPolicy::allow_world_control`] turns them on;
+//! [`ActionPolicy::check_world_control`] applies that.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::dimension::{block_at, same_world, World};
+use crate::npc_society::v1::{
+    action_directive::Action, interact_action::Target, look_action,
+    server_message::Message as ServerMsg, ActionDirective, BlockPosition, NpcSnapshot,
+    RegionClaim, ServerMessage,
+};
+use crate::retry::action_kind;
+
+/// Axis-aligned box of blocks in one world, bounds inclusive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    /// Name used in errors, e.g. "farm"
+    pub name: String,
+    /// World the region is in
+    pub world: String,
+    /// One corner (x, y, z)
+    pub min: (i32, i32, i32),
+    /// The opposite corner (x, y, z)
+    pub max: (i32, i32, i32),
+}
+
+impl Region {
+    /// Region spanning the two corners, in any order
+    pub fn new(name: &str, world: &str, a: (i32, i32, i32), b: (i32, i32, i32)) -> Self {
+        Self {
+            name: name.to_string(),
+            world: world.to_string(),
+            min: (a.0.min(b.0), a.1.min(b.1), a.2.min(b.2)),
+            max: (a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)),
+        }
+    }
+
+    /// Whether the block is inside the region
+    pub fn contains(&self, p: &BlockPosition) -> bool {
+        p.world == self.world
+            && (self.min.0..=self.max.0).contains(&p.x)
+            && (self.min.1..=self.max.1).contains(&p.y)
+            && (self.min.2..=self.max.2).contains(&p.z)
+    }
+}
+
+/// Rules for one NPC
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NpcPolicy {
+    /// Action kinds (see [`action_kind`]) the NPC may use; None = all
+    pub allowed: Option<Vec<String>>,
+    /// Action kind -> regions its target must lie in (any of them)
+    pub confined: HashMap<String, Vec<Region>>,
+}
+
+impl NpcPolicy {
+    /// Only allow these action kinds
+    pub fn allow(mut self, kinds: &[&str]) -> Self {
+        self.allowed = Some(kinds.iter().map(|k| k.to_string()).collect());
+        self
+    }
+
+    /// Require actions of `kind` to target a block inside `region` (or
+    /// another region confined to the same kind). Actions without a
+    /// target position (attack, look, stop, ...) are not affected.
+    pub fn confine(mut self, kind: &str, region: Region) -> Self {
+        self.confined.entry(kind.to_string()).or_default().push(region);
+        self
+    }
+}
+
+/// Why a directive was rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyViolation {
+    /// The directive has no action
+    MissingAction,
+    /// The NPC may not use this kind of action
+    ActionNotAllowed,
+    /// The action targets a block outside every region it is confined to
+    OutsideRegion {
+        /// Where the action pointed
+        target: Box<BlockPosition>,
+        /// Names of the regions it would have to be in
+        regions: Vec<String>,
+    },
+    /// The action targets another world than the one the NPC is in
+    WrongWorld {
+        /// World of the NPC
+        npc_world: String,
+        /// World the action points at
+        target_world: String,
+    },
+    /// The action targets a block in a land claim that forbids it
+    Protected {
+        /// region_id of the claim
+        region_id: String,
+    },
+    /// Time or weather change while world control is off
+    WorldControlDenied,
+}
+
+/// A rejected directive
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyError {
+    /// directive_id of the rejected directive
+    pub directive_id: String,
+    /// NPC it was for
+    pub npc_id: String,
+    /// [`action_kind`] of its action ("" if none)
+    pub action_kind: &'static str,
+    /// Which rule it broke
+    pub violation: PolicyViolation,
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "policy rejected {} for {}: ", self.directive_id, self.npc_id)?;
+        match &self.violation {
+            PolicyViolation::MissingAction => write!(f, "no action"),
+            PolicyViolation::ActionNotAllowed => write!(f, "{} not allowed", self.action_kind),
+            PolicyViolation::OutsideRegion { target, regions } => write!(
+                f,
+                "{} at {} {},{},{} outside {}",
+                self.action_kind,
+                target.world,
+                target.x,
+                target.y,
+                target.z,
+                regions.join(", ")
+            ),
+            PolicyViolation::WrongWorld {
+                npc_world,
+                target_world,
+            } => write!(f, "{} targets {} but NPC is in {}", self.action_kind, target_world, npc_world),
+            PolicyViolation::Protected { region_id } => {
+                write!(f, "{} denied by protected region {}", self.action_kind, region_id)
+            }
+            PolicyViolation::WorldControlDenied => {
+                write!(f, "{} denied, world control is off", self.action_kind)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+/// Policies for every NPC.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActionPolicy {
+    default: NpcPolicy,
+    per_npc: HashMap<String, NpcPolicy>,
+    world_control: bool,
+}
+
+impl ActionPolicy {
+    /// Use `policy` for NPCs without their own
+    pub fn set_default(&mut self, policy: NpcPolicy) {
+        self.default = policy;
+    }
+
+    /// Use `policy` for `npc_id`
+    pub fn set_policy(&mut self, npc_id: &str, policy: NpcPolicy) {
+        self.per_npc.insert(npc_id.to_string(), policy);
+    }
+
+    /// Policy that applies to `npc_id`
+    pub fn policy(&self, npc_id: &str) -> &NpcPolicy {
+        self.per_npc.get(npc_id).unwrap_or(&self.default)
+    }
+
+    /// Allow or deny time and weather changes (denied by default)
+    pub fn allow_world_control(&mut self, allowed: bool) {
+        self.world_control = allowed;
+    }
+
+    /// Whether time and weather changes are allowed
+    pub fn world_control(&self) -> bool {
+        self.world_control
+    }
+
+    /// Reject a time or weather change while world control is off; other
+    /// messages pass
+    pub fn check_world_control(&self, message: &ServerMessage) -> Result<(), PolicyError> {
+        let (directive_id, npc_id, action_kind) = match &message.message {
+            Some(ServerMsg::SetTime(m)) => (&m.directive_id, &m.npc_id, "set_time"),
+            Some(ServerMsg::SetWeather(m)) => (&m.directive_id, &m.npc_id, "set_weather"),
+            _ => return Ok(()),
+        };
+        if self.world_control {
+            return Ok(());
+        }
+        Err(PolicyError {
+            directive_id: directive_id.clone(),
+            npc_id: npc_id.clone(),
+            action_kind,
+            violation: PolicyViolation::WorldControlDenied,
+        })
+    }
+
+    /// Check a directive against its NPC's policy
+    pub fn check(&self, directive: &ActionDirective) -> Result<(), PolicyError> {
+        let error = |action_kind, violation| PolicyError {
+            directive_id: directive.directive_id.clone(),
+            npc_id: directive.npc_id.clone(),
+            action_kind,
+            violation,
+        };
+        let Some(action) = &directive.action else {
+            return Err(error("", PolicyViolation::MissingAction));
+        };
+        let kind = action_kind(action);
+        let policy = self.policy(&directive.npc_id);
+
+        if let Some(allowed) = &policy.allowed {
+            if !allowed.iter().any(|a| a == kind) {
+                return Err(error(kind, PolicyViolation::ActionNotAllowed));
+            }
+        }
+        if let (Some(regions), Some(target)) = (policy.confined.get(kind), target_block(action)) {
+            if !regions.iter().any(|r| r.contains(&target)) {
+                let regions = regions.iter().map(|r| r.name.clone()).collect();
+                return Err(error(kind, PolicyViolation::OutsideRegion { target: Box::new(target), regions }));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that a directive targets the world `npc` is currently in.
+/// Passes if either position is unknown.
+pub fn check_world(directive: &ActionDirective, npc: &NpcSnapshot) -> Result<(), PolicyError> {
+    let (Some(action), Some(here)) = (&directive.action, &npc.position) else {
+        return Ok(());
+    };
+    let target = match action {
+        Action::Look(look) => match &look.target {
+            Some(look_action::Target::Position(p)) => Some(World::of(p)),
+            _ => None,
+        },
+        _ => target_block(action).map(|b| World::of(&b)),
+    };
+    match target {
+        Some(target) if !same_world(here, &target) => Err(PolicyError {
+            directive_id: directive.directive_id.clone(),
+            npc_id: directive.npc_id.clone(),
+            action_kind: action_kind(action),
+            violation: PolicyViolation::WrongWorld {
+                npc_world: World::of(here).to_string(),
+                target_world: target.to_string(),
+            },
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Check a directive against the land claims around its NPC (see
+/// `NpcSnapshot.regions`): building needs `build_allowed`, using blocks
+/// needs `interact_allowed`. Other actions are not checked.
+pub fn check_claims(directive: &ActionDirective, claims: &[RegionClaim]) -> Result<(), PolicyError> {
+    let Some(action) = &directive.action else {
+        return Ok(());
+    };
+    let Some(target) = target_block(action) else {
+        return Ok(());
+    };
+    let denied = claims.iter().find(|claim| {
+        let allowed = match action {
+            Action::BreakBlock(_) | Action::PlaceBlock(_) => claim.build_allowed,
+            Action::Interact(_) | Action::DepositToChest(_) => claim.interact_allowed,
+            _ => true,
+        };
+        !allowed && claim_contains(claim, &target)
+    });
+    match denied {
+        Some(claim) => Err(PolicyError {
+            directive_id: directive.directive_id.clone(),
+            npc_id: directive.npc_id.clone(),
+            action_kind: action_kind(action),
+            violation: PolicyViolation::Protected {
+                region_id: claim.region_id.clone(),
+            },
+        }),
+        None => Ok(()),
+    }
+}
+
+fn claim_contains(claim: &RegionClaim, p: &BlockPosition) -> bool {
+    let (Some(min), Some(max)) = (&claim.min, &claim.max) else {
+        return false;
+    };
+    p.world == min.world
+        && (min.x..=max.x).contains(&p.x)
+        && (min.y..=max.y).contains(&p.y)
+        && (min.z..=max.z).contains(&p.z)
+}
+
+/// The block an action is aimed at, if it has one
+pub fn target_block(action: &Action) -> Option<BlockPosition> {
+    match action {
+        Action::Move(m) => m.target.as_ref().map(block_at),
+        Action::RideAndDrive(r) => r.target.as_ref().map(block_at),
+        Action::BreakBlock(b) => b.position.clone(),
+        Action::PlaceBlock(p) => p.position.clone(),
+        Action::Interact(i) => match &i.target {
+            Some(Target::Block(block)) => Some(block.clone()),
+            _ => None,
+        },
+        Action::ScanBlocks(s) => s.center.clone(),
+        Action::RegionSnapshot(r) => r.from.clone(),
+        Action::WatchBlocks(w) => w.from.clone(),
+        Action::Smelt(s) => s.furnace_position.clone(),
+        Action::Brew(b) => b.stand_position.clone(),
+        Action::DepositToChest(d) => d.chest_position.clone(),
+        Action::EnchantItem(e) => e.table_position.clone(),
+        Action::RepairItem(r) => r.anvil_position.clone(),
+        Action::Craft(c) => c.table_position.clone(),
+        Action::WriteSign(w) => w.position.clone(),
+        Action::ToggleLever(t) => t.position.clone(),
+        Action::PressButton(p) => p.position.clone(),
+        Action::SetMechanism(s) => s.position.clone(),
+        Action::ReadRedstonePower(r) => r.position.clone(),
+        Action::Attack(_)
+        | Action::Inventory(_)
+        | Action::Look(_)
+        | Action::Stop(_)
+        | Action::RaycastLook(_)
+        | Action::CheckLineOfSight(_)
+        | Action::UnwatchBlocks(_)
+        | Action::BreedAnimals(_)
+        | Action::TameAnimal(_)
+        | Action::Shear(_)
+        | Action::Milk(_)
+        | Action::EquipArmor(_)
+        | Action::ConsumeItem(_)
+        | Action::GiveItem(_)
+        | Action::WriteBook(_)
+        | Action::MarkMap(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{AttackAction, BreakBlockAction};
+
+    fn directive(action: Action) -> ActionDirective {
+        ActionDirective {
+            directive_id: "d".to_string(),
+            npc_id: "farmer".to_string(),
+            priority: 1,
+            action: Some(action),
+            ..Default::default()
+        }
+    }
+
+    fn break_at(x: i32) -> Action {
+        Action::BreakBlock(BreakBlockAction {
+            position: Some(BlockPosition {
+                world: "world".to_string(),
+                x,
+                y: 64,
+                z: 0,
+                ..Default::default()
+            }),
+        })
+    }
+
+    #[test]
+    fn test_allowlist_and_regions() {
+        let mut policy = ActionPolicy::default();
+        policy.set_policy(
+            "farmer",
+            NpcPolicy::default()
+                .allow(&["move", "break_block"])
+                .confine("break_block", Region::new("farm", "world", (10, 60, 10), (0, 70, -10))),
+        );
+
+        assert_eq!(policy.check(&directive(break_at(5))), Ok(()));
+        let err = policy.check(&directive(break_at(50))).unwrap_err();
+        assert!(matches!(&err.violation, PolicyViolation::OutsideRegion { regions, .. } if regions == &["farm"]));
+        assert_eq!(err.to_string(), "policy rejected d for farmer: break_block at world 50,64,0 outside farm");
+
+        let attack = directive(Action::Attack(AttackAction::default()));
+        assert_eq!(policy.check(&attack).unwrap_err().violation, PolicyViolation::ActionNotAllowed);
+
+        // Other NPCs fall back to the (permissive) default
+        let guard = ActionDirective {
+            npc_id: "guard".to_string(),
+            ..attack
+        };
+        assert_eq!(policy.check(&guard), Ok(()));
+    }
+
+    #[test]
+    fn test_claims() {
+        let corner = |x, y, z| {
+            Some(BlockPosition {
+                world: "world".to_string(),
+                x,
+                y,
+                z,
+                ..Default::default()
+            })
+        };
+        let claims = [RegionClaim {
+            region_id: "spawn".to_string(),
+            min: corner(0, 0, -5),
+            max: corner(10, 255, 5),
+            interact_allowed: true,
+            ..Default::default()
+        }];
+
+        let err = check_claims(&directive(break_at(5)), &claims).unwrap_err();
+        assert_eq!(err.to_string(), "policy rejected d for farmer: break_block denied by protected region spawn");
+        assert_eq!(check_claims(&directive(break_at(50)), &claims), Ok(()));
+        let mut allowed = claims.clone();
+        allowed[0].build_allowed = true;
+        assert_eq!(check_claims(&directive(break_at(5)), &allowed), Ok(()));
+    }
+
+    #[test]
+    fn test_world() {
+        let npc = |world: &str| NpcSnapshot {
+            position: Some(crate::npc_society::v1::Position {
+                world: world.to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(check_world(&directive(break_at(5)), &npc("world")), Ok(()));
+        let err = check_world(&directive(break_at(5)), &npc("world_nether")).unwrap_err();
+        assert_eq!(err.to_string(), "policy rejected d for farmer: break_block targets world but NPC is in world_nether");
+        assert_eq!(check_world(&directive(break_at(5)), &NpcSnapshot::default()), Ok(()));
+    }
+
+    #[test]
+    fn test_world_control() {
+        use crate::npc_society::v1::{SetWeatherDirective, Weather};
+
+        let storm = ServerMessage::from(SetWeatherDirective {
+            directive_id: "w".to_string(),
+            npc_id: "wizard".to_string(),
+            weather: Weather::Thunder as i32,
+            ..Default::default()
+        });
+        let mut policy = ActionPolicy::default();
+        let err = policy.check_world_control(&storm).unwrap_err();
+        assert_eq!(err.to_string(), "policy rejected w for wizard: set_weather denied, world control is off");
+        assert_eq!(policy.check_world_control(&directive(break_at(5)).into()), Ok(()));
+
+        policy.allow_world_control(true);
+        assert_eq!(policy.check_world_control(&storm), Ok(()));
+    }
+}
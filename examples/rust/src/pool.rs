@@ -0,0 +1,281 @@
+//! Reusable decoding for WorldTicks.
+//!
+//! A WorldTick with hundreds of NPCs arrives 20 times a second, and plain
+//! decoding allocates every snapshot, every string in it and every list
+//! anew each time. [`TickPool`] decodes into ticks and snapshots handed
+//! back with [`TickPool::recycle`] instead: their strings and lists keep
+//! their capacity, so a warmed-up pool mostly reuses memory. Nested
+//! messages (positions) are still decoded afresh.
+//!
+//! tonic's generated server decodes with its own codec; use the pool where
+//! the daemon decodes ClientMessages itself (a custom codec, recorded
+//! sessions, another transport). `cargo bench --bench tick_decode`
+//! compares it with plain decoding.
+
+use prost::{DecodeError, Message};
+
+use crate::npc_society::v1::{
+    client_message::Message as ClientMsg, ClientMessage, EntitySnapshot, NpcSnapshot,
+    PlayerSnapshot, WorldTick,
+};
+
+/// `ClientMessage.world_tick`
+const WORLD_TICK: u64 = 2;
+/// `WorldTick.npcs`
+const NPCS: u64 = 3;
+/// `WorldTick.nearby_players`
+const PLAYERS: u64 = 4;
+/// `WorldTick.nearby_entities`
+const ENTITIES: u64 = 5;
+/// Length-delimited wire type
+const LEN: u64 = 2;
+
+/// Spare ticks kept for reuse; one or two are in flight at a time
+const MAX_SPARE_TICKS: usize = 4;
+
+/// Decodes WorldTicks into recycled allocations.
+#[derive(Debug)]
+pub struct TickPool {
+    max_spare: usize,
+    ticks: Vec<WorldTick>,
+    npcs: Vec<NpcSnapshot>,
+    players: Vec<PlayerSnapshot>,
+    entities: Vec<EntitySnapshot>,
+}
+
+impl Default for TickPool {
+    fn default() -> Self {
+        Self::new(4096)
+    }
+}
+
+impl TickPool {
+    /// Keep at most `max_spare` snapshots of each kind for reuse
+    pub fn new(max_spare: usize) -> Self {
+        Self {
+            max_spare,
+            ticks: Vec::new(),
+            npcs: Vec::new(),
+            players: Vec::new(),
+            entities: Vec::new(),
+        }
+    }
+
+    /// Decode a ClientMessage, reusing pooled memory if it is a WorldTick.
+    /// Same result as `ClientMessage::decode`.
+    pub fn decode(&mut self, buf: &[u8]) -> Result<ClientMessage, DecodeError> {
+        if let Some(tick) = self.try_decode_client_tick(buf) {
+            return Ok(ClientMessage {
+                message: Some(ClientMsg::WorldTick(tick)),
+                ..Default::default()
+            });
+        }
+        ClientMessage::decode(buf)
+    }
+
+    /// Decode a bare WorldTick. Same result as `WorldTick::decode`.
+    pub fn decode_tick(&mut self, buf: &[u8]) -> Result<WorldTick, DecodeError> {
+        let mut tick = self.ticks.pop().unwrap_or_default();
+        match self.merge_tick(&mut tick, buf) {
+            Some(()) => Ok(tick),
+            None => {
+                self.recycle_tick(tick);
+                WorldTick::decode(buf)
+            }
+        }
+    }
+
+    /// Hand a message back once done with it. Only WorldTicks are kept.
+    pub fn recycle(&mut self, msg: ClientMessage) {
+        if let Some(ClientMsg::WorldTick(tick)) = msg.message {
+            self.recycle_tick(tick);
+        }
+    }
+
+    /// Hand a tick back once done with it
+    pub fn recycle_tick(&mut self, mut tick: WorldTick) {
+        keep(&mut self.npcs, &mut tick.npcs, self.max_spare);
+        keep(&mut self.players, &mut tick.nearby_players, self.max_spare);
+        keep(
+            &mut self.entities,
+            &mut tick.nearby_entities,
+            self.max_spare,
+        );
+        if self.ticks.len() < MAX_SPARE_TICKS {
+            tick.clear();
+            self.ticks.push(tick);
+        }
+    }
+
+    /// Snapshots currently waiting for reuse
+    pub fn spare(&self) -> usize {
+        self.npcs.len() + self.players.len() + self.entities.len()
+    }
+
+    /// The tick if `buf` holds nothing but `world_tick`; anything else (or
+    /// malformed input) is left to prost
+    fn try_decode_client_tick(&mut self, mut buf: &[u8]) -> Option<WorldTick> {
+        let mut tick = self.ticks.pop().unwrap_or_default();
+        while !buf.is_empty() {
+            let merged = match next_field(&mut buf) {
+                Some(field) if field.number == WORLD_TICK && field.wire_type == LEN => {
+                    self.merge_tick(&mut tick, field.payload)
+                }
+                _ => None,
+            };
+            if merged.is_none() {
+                self.recycle_tick(tick);
+                return None;
+            }
+        }
+        Some(tick)
+    }
+
+    fn merge_tick(&mut self, tick: &mut WorldTick, mut buf: &[u8]) -> Option<()> {
+        while !buf.is_empty() {
+            let field = next_field(&mut buf)?;
+            match (field.number, field.wire_type) {
+                (NPCS, LEN) => tick.npcs.push(reuse(&mut self.npcs, field.payload)?),
+                (PLAYERS, LEN) => tick
+                    .nearby_players
+                    .push(reuse(&mut self.players, field.payload)?),
+                (ENTITIES, LEN) => tick
+                    .nearby_entities
+                    .push(reuse(&mut self.entities, field.payload)?),
+                _ => tick.merge(field.raw).ok()?,
+            }
+        }
+        Some(())
+    }
+}
+
+/// Clear `items` into `spare`, up to `max` spares
+fn keep<M: Message>(spare: &mut Vec<M>, items: &mut Vec<M>, max: usize) {
+    let room = max.saturating_sub(spare.len());
+    spare.extend(items.drain(..).take(room).map(|mut item| {
+        item.clear();
+        item
+    }));
+    items.clear();
+}
+
+/// Decode `payload` into a cleared spare, or a new message
+fn reuse<M: Message + Default>(spare: &mut Vec<M>, payload: &[u8]) -> Option<M> {
+    let mut item = spare.pop().unwrap_or_default();
+    item.merge(payload).ok()?;
+    Some(item)
+}
+
+struct Field<'a> {
+    number: u64,
+    wire_type: u64,
+    /// Value (without the length prefix for length-delimited fields)
+    payload: &'a [u8],
+    /// Key and value as on the wire
+    raw: &'a [u8],
+}
+
+/// Split the next field off `buf`; None if malformed
+fn next_field<'a>(buf: &mut &'a [u8]) -> Option<Field<'a>> {
+    let start = *buf;
+    let key = varint(buf)?;
+    let payload = match key & 7 {
+        0 => {
+            let value = *buf;
+            varint(buf)?;
+            &value[..value.len() - buf.len()]
+        }
+        1 => take(buf, 8)?,
+        LEN => {
+            let len = usize::try_from(varint(buf)?).ok()?;
+            take(buf, len)?
+        }
+        5 => take(buf, 4)?,
+        // Groups are not used by the protocol
+        _ => return None,
+    };
+    Some(Field {
+        number: key >> 3,
+        wire_type: key & 7,
+        payload,
+        raw: &start[..start.len() - buf.len()],
+    })
+}
+
+fn varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for (i, &byte) in buf.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte < 0x80 {
+            *buf = &buf[i + 1..];
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if buf.len() < len {
+        return None;
+    }
+    let (value, rest) = buf.split_at(len);
+    *buf = rest;
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{ChatObservation, Position};
+
+    fn tick(server_tick: i64, npcs: usize, with_positions: bool) -> ClientMessage {
+        ClientMessage {
+            message: Some(ClientMsg::WorldTick(WorldTick {
+                server_tick,
+                npcs: (0..npcs)
+                    .map(|i| NpcSnapshot {
+                        npc_id: format!("npc_{}", i),
+                        health_norm: 0.5,
+                        position: with_positions.then(|| Position {
+                            world: "world".to_string(),
+                            x: i as f64,
+                            ..Default::default()
+                        }),
+                        balance: with_positions.then_some(10),
+                        ..Default::default()
+                    })
+                    .collect(),
+                nearby_players: vec![PlayerSnapshot {
+                    player_name: "Steve".to_string(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_matches_plain_decoding() {
+        let mut pool = TickPool::default();
+        // Fields set in one tick must not leak into the next
+        for msg in [tick(1, 3, true), tick(2, 5, false), tick(3, 1, true)] {
+            let bytes = msg.encode_to_vec();
+            let decoded = pool.decode(&bytes).unwrap();
+            assert_eq!(decoded, ClientMessage::decode(&bytes[..]).unwrap());
+            pool.recycle(decoded);
+        }
+        assert_eq!(pool.spare(), 5 + 1);
+
+        let chat = ClientMessage {
+            message: Some(ClientMsg::ChatObservation(ChatObservation {
+                message: "hi".to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        assert_eq!(pool.decode(&chat.encode_to_vec()).unwrap(), chat);
+        let truncated = &tick(4, 2, true).encode_to_vec()[..20];
+        assert!(pool.decode(truncated).is_err());
+    }
+}
@@ -0,0 +1,265 @@
+//! Live self-checks of a plugin connection.
+//!
+//! After a plugin or daemon upgrade, a canary wants to know that the
+//! stream still works end to end, not just that Hello went through. A
+//! [`SelfTest`] sends a run of [`Probe`]s, each with a nonce, a sequence
+//! number and a payload, and checks the ProbeReplies the plugin echoes:
+//!
+//! - integrity: every reply carries its Probe's sequence and payload
+//!   unchanged
+//! - ordering: replies come back in the order the Probes were sent
+//! - loss: every Probe is answered within [`ProbeConfig::timeout_ms`]
+//! - clock skew: the plugin's clock, estimated NTP-style from the reply
+//!   with the shortest round trip, is within
+//!   [`ProbeConfig::max_clock_skew_ms`] of the daemon's
+//!
+//! The outcome is a [`ProbeReport`], which the example daemon serves in
+//! `GetSessionInfoResponse.probe`:
+//!
+//! ```ignore
+//! let mut test = SelfTest::new(&cx.next_id("probe"), ProbeConfig::default());
+//! for probe in test.start(now_ms()) {
+//!     tx.send(probe)?;
+//! }
+//! // on every ProbeReply:
+//! test.on_reply(&reply, now_ms());
+//! // and now and then, for Probes that never get one:
+//! if let Some(report) = test.finish(now_ms()) {
+//!     // ...
+//! }
+//! ```
+
+use crate::npc_society::v1::{Probe, ProbeReply, ProbeReport};
+
+/// Size and pass marks of a run of Probes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeConfig {
+    /// Probes per run (0 = no self-checks)
+    pub count: u32,
+    /// Bytes of each Probe's payload
+    pub payload_bytes: usize,
+    /// How long a Probe may go unanswered before it counts as lost
+    pub timeout_ms: i64,
+    /// Largest clock offset between plugin and daemon that passes
+    pub max_clock_skew_ms: i64,
+    /// Time between the end of a run and the next (0 = only after Hello)
+    pub interval_ms: i64,
+}
+
+impl Default for ProbeConfig {
+    /// 8 Probes of 4 KiB after Hello, 5 s to answer, clocks within 1 s
+    fn default() -> Self {
+        Self {
+            count: 8,
+            payload_bytes: 4096,
+            timeout_ms: 5_000,
+            max_clock_skew_ms: 1_000,
+            interval_ms: 0,
+        }
+    }
+}
+
+/// One run of Probes and what came back
+#[derive(Debug, Clone)]
+pub struct SelfTest {
+    config: ProbeConfig,
+    run_id: String,
+    started_at_ms: i64,
+    /// Probes sent, by sequence
+    probes: Vec<Probe>,
+    /// Whether each Probe got a reply, right or not
+    answered: Vec<bool>,
+    /// Round trips of the replies that matched, in milliseconds
+    round_trips: Vec<i64>,
+    corrupted: u32,
+    out_of_order: u32,
+    /// Highest sequence answered so far
+    last_sequence: Option<u32>,
+    /// (round trip, clock offset) of the fastest matching reply
+    fastest: Option<(i64, i64)>,
+}
+
+/// Payload of Probe `sequence` of a run: bytes from a xorshift generator,
+/// so a flipped or dropped byte anywhere shows
+fn payload(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed | 1;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+impl SelfTest {
+    /// A run whose nonces start with `run_id`, unique per run
+    pub fn new(run_id: &str, config: ProbeConfig) -> Self {
+        Self {
+            config,
+            run_id: run_id.to_string(),
+            started_at_ms: 0,
+            probes: Vec::new(),
+            answered: Vec::new(),
+            round_trips: Vec::new(),
+            corrupted: 0,
+            out_of_order: 0,
+            last_sequence: None,
+            fastest: None,
+        }
+    }
+
+    /// The Probes to send, in order
+    pub fn start(&mut self, now_ms: i64) -> Vec<Probe> {
+        self.started_at_ms = now_ms;
+        self.probes = (0..self.config.count)
+            .map(|sequence| Probe {
+                nonce: format!("{}-{}", self.run_id, sequence),
+                sequence,
+                payload: payload(
+                    now_ms as u64 ^ ((sequence as u64) << 32),
+                    self.config.payload_bytes,
+                ),
+                sent_at_ms: now_ms,
+            })
+            .collect();
+        self.answered = vec![false; self.probes.len()];
+        self.probes.clone()
+    }
+
+    /// Check a reply; false if it answers no Probe of this run, e.g. one
+    /// of an earlier run that timed out
+    pub fn on_reply(&mut self, reply: &ProbeReply, now_ms: i64) -> bool {
+        let Some(index) = self.probes.iter().position(|p| p.nonce == reply.nonce) else {
+            return false;
+        };
+        let probe = &self.probes[index];
+        if std::mem::replace(&mut self.answered[index], true)
+            || reply.sequence != probe.sequence
+            || reply.payload != probe.payload
+            || reply.probe_sent_at_ms != probe.sent_at_ms
+        {
+            self.corrupted += 1;
+            return true;
+        }
+        match self.last_sequence {
+            Some(last) if probe.sequence < last => self.out_of_order += 1,
+            _ => self.last_sequence = Some(probe.sequence),
+        }
+        let held = (reply.sent_at_ms - reply.received_at_ms).max(0);
+        let round_trip = (now_ms - probe.sent_at_ms - held).max(0);
+        self.round_trips.push(round_trip);
+        // The shortest round trip has the least queueing in its offset
+        let offset = ((reply.received_at_ms - probe.sent_at_ms) + (reply.sent_at_ms - now_ms)) / 2;
+        if self.fastest.is_none_or(|(fastest, _)| round_trip < fastest) {
+            self.fastest = Some((round_trip, offset));
+        }
+        true
+    }
+
+    /// Whether every Probe was answered or the rest timed out
+    pub fn is_done(&self, now_ms: i64) -> bool {
+        self.answered.iter().all(|&answered| answered)
+            || now_ms - self.started_at_ms >= self.config.timeout_ms
+    }
+
+    /// The report once [`SelfTest::is_done`]
+    pub fn finish(&self, now_ms: i64) -> Option<ProbeReport> {
+        if !self.is_done(now_ms) {
+            return None;
+        }
+        let sent = self.probes.len() as u32;
+        let lost = self.answered.iter().filter(|&&answered| !answered).count() as u32;
+        let mut round_trips = self.round_trips.clone();
+        round_trips.sort_unstable();
+        let clock_offset_ms = self.fastest.map_or(0, |(_, offset)| offset);
+        let failure = if lost > 0 {
+            format!("{} of {} probes lost", lost, sent)
+        } else if self.corrupted > 0 {
+            format!("{} of {} replies corrupted", self.corrupted, sent)
+        } else if self.out_of_order > 0 {
+            format!("{} replies out of order", self.out_of_order)
+        } else if clock_offset_ms.abs() > self.config.max_clock_skew_ms {
+            format!(
+                "clocks {} ms apart, at most {} allowed",
+                clock_offset_ms, self.config.max_clock_skew_ms
+            )
+        } else {
+            String::new()
+        };
+        Some(ProbeReport {
+            started_at_ms: self.started_at_ms,
+            finished_at_ms: now_ms,
+            sent,
+            replied: round_trips.len() as u32,
+            corrupted: self.corrupted,
+            out_of_order: self.out_of_order,
+            lost,
+            round_trip_p50_ms: round_trips.get(round_trips.len() / 2).copied().unwrap_or(0) as f64,
+            round_trip_max_ms: round_trips.last().copied().unwrap_or(0) as f64,
+            clock_offset_ms,
+            passed: failure.is_empty(),
+            failure,
+        })
+    }
+}
+
+/// What a plugin does with a Probe: echo it, `received_at_ms` and
+/// `sent_at_ms` on its own clock
+pub fn reply(probe: &Probe, received_at_ms: i64, sent_at_ms: i64) -> ProbeReply {
+    ProbeReply {
+        nonce: probe.nonce.clone(),
+        sequence: probe.sequence,
+        payload: probe.payload.clone(),
+        probe_sent_at_ms: probe.sent_at_ms,
+        received_at_ms,
+        sent_at_ms,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_test() {
+        let config = ProbeConfig {
+            count: 4,
+            ..Default::default()
+        };
+        // A healthy plugin 300 ms ahead, 20 ms away
+        let mut test = SelfTest::new("probe-1", config);
+        let earlier = test.start(1_000);
+        assert_eq!(earlier.len(), 4);
+        assert_ne!(earlier[0].payload, earlier[1].payload);
+        for probe in &earlier {
+            assert!(test.on_reply(&reply(probe, 1_310, 1_311), 1_021));
+        }
+        let report = test.finish(1_021).unwrap();
+        assert!(report.passed, "{}", report.failure);
+        assert_eq!(report.replied, 4);
+        assert_eq!(report.round_trip_p50_ms, 20.0);
+        assert_eq!(report.clock_offset_ms, 300);
+
+        // A plugin that mangles a payload, swaps two replies, repeats one
+        // and drops one
+        let mut test = SelfTest::new("probe-2", config);
+        let probes = test.start(2_000);
+        let mut mangled = reply(&probes[0], 2_010, 2_010);
+        mangled.payload[7] ^= 0x20;
+        assert!(test.on_reply(&mangled, 2_020));
+        assert!(test.on_reply(&reply(&probes[2], 2_010, 2_010), 2_020));
+        assert!(test.on_reply(&reply(&probes[1], 2_010, 2_010), 2_020));
+        assert!(test.on_reply(&reply(&probes[1], 2_010, 2_010), 2_020));
+        // Replies to an earlier run are not this run's
+        assert!(!test.on_reply(&reply(&earlier[3], 2_010, 2_010), 2_020));
+        assert!(test.finish(2_020).is_none());
+        let report = test.finish(7_000).unwrap();
+        assert!(!report.passed);
+        assert_eq!(report.lost, 1);
+        assert_eq!(report.corrupted, 2);
+        assert_eq!(report.out_of_order, 1);
+        assert_eq!(report.failure, "1 of 4 probes lost");
+    }
+}
@@ -0,0 +1,338 @@
+//! Per-NPC profiles loaded from TOML files (feature `npc-profiles`).
+//!
+//! A [`ProfileStore`] reads one `<npc_id>.toml` per NPC from a directory:
+//!
+//! ```toml
+//! persona = "Grumpy baker who secretly loves visitors"
+//! voice_id = "en-US-Neural2-D"
+//! home = { world = "world", x = 100.5, y = 64, z = -20 }
+//! allowed_actions = ["move", "interact", "stop"]   # omit to allow everything
+//!
+//! [speech]                     # omit for the daemon's default rate
+//! min_interval_ms = 5000
+//! burst = 2
+//! max_per_minute = 6
+//! overflow = "queue"           # or "drop"
+//! max_queued = 3
+//! max_wait_ms = 10000
+//!
+//! [[routine]]
+//! at = "06:00"
+//! label = "bakery"
+//! move_to = { world = "world", x = 110, y = 64, z = -18 }
+//! ```
+//!
+//! Routine entries use the same format as [`Scheduler::from_toml`], minus
+//! the `npc_id`. Unset `[speech]` keys keep the values of
+//! [`SpeechRate::default`]. [`ProfileStore::refresh`] picks up added, edited and
+//! deleted files; [`spawn_watcher`] calls it periodically so edits apply
+//! while the daemon runs.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+
+use crate::npc_society::v1::{action_directive::Action, Position};
+use crate::retry::action_kind;
+use crate::schedule::toml_format::{Coords, Slot};
+use crate::schedule::{Routine, Scheduler};
+use crate::speech_rate::{Overflow, SpeechRate};
+
+/// Everything configurable about one NPC
+#[derive(Debug, Clone, PartialEq)]
+pub struct NpcProfile {
+    /// File stem of the profile
+    pub npc_id: String,
+    /// Character description for the LLM prompt
+    pub persona: String,
+    /// TTS voice (empty = daemon default)
+    pub voice_id: String,
+    /// Where the NPC lives
+    pub home: Option<Position>,
+    /// Action kinds (see [`action_kind`]) the NPC may perform; None = all
+    pub allowed_actions: Option<Vec<String>>,
+    /// Daily routine
+    pub routine: Routine,
+    /// How often the NPC may speak; None = the daemon's default
+    pub speech_rate: Option<SpeechRate>,
+}
+
+impl NpcProfile {
+    /// Whether the profile permits `action`
+    pub fn allows(&self, action: &Action) -> bool {
+        match &self.allowed_actions {
+            Some(allowed) => allowed.iter().any(|a| a == action_kind(action)),
+            None => true,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ProfileFile {
+    #[serde(default)]
+    persona: String,
+    #[serde(default)]
+    voice_id: String,
+    home: Option<Coords>,
+    allowed_actions: Option<Vec<String>>,
+    #[serde(default)]
+    routine: Vec<Slot>,
+    speech: Option<SpeechFile>,
+}
+
+#[derive(Deserialize)]
+struct SpeechFile {
+    min_interval_ms: Option<i64>,
+    burst: Option<u32>,
+    max_per_minute: Option<u32>,
+    overflow: Option<String>,
+    max_queued: Option<usize>,
+    max_wait_ms: Option<i64>,
+}
+
+impl SpeechFile {
+    fn into_rate(self) -> Result<SpeechRate, String> {
+        let default = SpeechRate::default();
+        let overflow = match self.overflow.as_deref() {
+            None | Some("drop") => Overflow::Drop,
+            Some("queue") => Overflow::Queue {
+                max_queued: self.max_queued.unwrap_or(3),
+                max_wait_ms: self.max_wait_ms.unwrap_or(10_000),
+            },
+            Some(other) => return Err(format!("speech.overflow: expected drop or queue, not {other:?}")),
+        };
+        Ok(SpeechRate {
+            min_interval_ms: self.min_interval_ms.unwrap_or(default.min_interval_ms),
+            burst: self.burst.unwrap_or(default.burst),
+            max_per_minute: self.max_per_minute.unwrap_or(default.max_per_minute),
+            overflow,
+        })
+    }
+}
+
+/// Error reading a profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileError(pub String);
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid profile: {}", self.0)
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+/// What [`ProfileStore::refresh`] changed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProfileChange {
+    /// A profile was added or edited
+    Loaded(String),
+    /// A profile file was deleted
+    Removed(String),
+    /// A profile file changed but could not be read; the previous version stays
+    Invalid { npc_id: String, error: ProfileError },
+}
+
+/// Profiles of all NPCs, kept in sync with a directory.
+#[derive(Debug)]
+pub struct ProfileStore {
+    dir: PathBuf,
+    profiles: HashMap<String, NpcProfile>,
+    modified: HashMap<String, SystemTime>,
+}
+
+/// A [`ProfileStore`] shared with a watcher task
+pub type SharedProfiles = Arc<Mutex<ProfileStore>>;
+
+impl ProfileStore {
+    /// Empty store for `dir`; call [`ProfileStore::refresh`] to load it
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            profiles: HashMap::new(),
+            modified: HashMap::new(),
+        }
+    }
+
+    /// Profile of `npc_id`, if it has one
+    pub fn get(&self, npc_id: &str) -> Option<&NpcProfile> {
+        self.profiles.get(npc_id)
+    }
+
+    /// All loaded profiles
+    pub fn iter(&self) -> impl Iterator<Item = &NpcProfile> {
+        self.profiles.values()
+    }
+
+    /// Give every profiled NPC its routine
+    pub fn apply_routines(&self, scheduler: &mut Scheduler) {
+        for profile in self.profiles.values() {
+            scheduler.set_routine(&profile.npc_id, profile.routine.clone());
+        }
+    }
+
+    /// Reload files that were added or modified since the last call and
+    /// forget deleted ones. Fails only if the directory can't be read.
+    pub fn refresh(&mut self) -> Result<Vec<ProfileChange>, ProfileError> {
+        let entries = std::fs::read_dir(&self.dir)
+            .map_err(|e| ProfileError(format!("{}: {}", self.dir.display(), e)))?;
+
+        let mut changes = Vec::new();
+        let mut seen = Vec::new();
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+                continue;
+            }
+            let Some(npc_id) = path.file_stem().and_then(|s| s.to_str()).map(str::to_string) else {
+                continue;
+            };
+            seen.push(npc_id.clone());
+
+            let Ok(modified) = std::fs::metadata(&path).and_then(|m| m.modified()) else {
+                continue;
+            };
+            if self.modified.get(&npc_id) == Some(&modified) {
+                continue;
+            }
+            self.modified.insert(npc_id.clone(), modified);
+
+            match load(&npc_id, &path) {
+                Ok(profile) => {
+                    self.profiles.insert(npc_id.clone(), profile);
+                    changes.push(ProfileChange::Loaded(npc_id));
+                }
+                Err(error) => changes.push(ProfileChange::Invalid { npc_id, error }),
+            }
+        }
+
+        let removed: Vec<String> = self
+            .modified
+            .keys()
+            .filter(|npc_id| !seen.contains(npc_id))
+            .cloned()
+            .collect();
+        for npc_id in removed {
+            self.modified.remove(&npc_id);
+            self.profiles.remove(&npc_id);
+            changes.push(ProfileChange::Removed(npc_id));
+        }
+        Ok(changes)
+    }
+}
+
+fn load(npc_id: &str, path: &Path) -> Result<NpcProfile, ProfileError> {
+    let source = std::fs::read_to_string(path).map_err(|e| ProfileError(format!("{}: {}", path.display(), e)))?;
+    let file: ProfileFile =
+        toml::from_str(&source).map_err(|e| ProfileError(format!("{}: {}", path.display(), e)))?;
+    let routine = file
+        .routine
+        .into_iter()
+        .map(|slot| slot.into_entry(npc_id))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| ProfileError(format!("{}: {}", path.display(), e.0)))?;
+    let speech_rate = file
+        .speech
+        .map(SpeechFile::into_rate)
+        .transpose()
+        .map_err(|e| ProfileError(format!("{}: {}", path.display(), e)))?;
+    Ok(NpcProfile {
+        npc_id: npc_id.to_string(),
+        persona: file.persona,
+        voice_id: file.voice_id,
+        home: file.home.map(Position::from),
+        allowed_actions: file.allowed_actions,
+        routine: Routine::new(routine),
+        speech_rate,
+    })
+}
+
+/// Refresh `store` every `every` in a background task, logging changes
+pub fn spawn_watcher(store: SharedProfiles, every: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(every);
+        loop {
+            interval.tick().await;
+            let result = store.lock().unwrap().refresh();
+            match result {
+                Ok(changes) => {
+                    for change in changes {
+                        match change {
+                            ProfileChange::Loaded(npc_id) => tracing::info!(%npc_id, "Profile loaded"),
+                            ProfileChange::Removed(npc_id) => tracing::info!(%npc_id, "Profile removed"),
+                            ProfileChange::Invalid { npc_id, error } => {
+                                tracing::warn!(%npc_id, %error, "Keeping previous profile")
+                            }
+                        }
+                    }
+                }
+                Err(error) => tracing::warn!(%error, "Could not read profiles"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{BreakBlockAction, MoveAction};
+
+    #[test]
+    fn test_refresh_tracks_files() {
+        let dir = std::env::temp_dir().join(format!("npc-profiles-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let baker = dir.join("baker.toml");
+        std::fs::write(
+            &baker,
+            r#"
+            voice_id = "warm"
+            allowed_actions = ["move"]
+            home = { world = "world", x = 1.5, y = 64, z = 2 }
+
+            [speech]
+            overflow = "queue"
+            max_queued = 1
+
+            [[routine]]
+            at = "06:00"
+            idle = true
+            "#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a profile").unwrap();
+
+        let mut store = ProfileStore::new(&dir);
+        assert_eq!(store.refresh().unwrap(), vec![ProfileChange::Loaded("baker".to_string())]);
+        let profile = store.get("baker").unwrap();
+        assert_eq!(profile.voice_id, "warm");
+        assert_eq!(profile.home.as_ref().unwrap().x, 1.5);
+        assert_eq!(profile.routine.entries().len(), 1);
+        let speech_rate = profile.speech_rate.unwrap();
+        assert_eq!(speech_rate.burst, SpeechRate::default().burst);
+        assert_eq!(
+            speech_rate.overflow,
+            Overflow::Queue {
+                max_queued: 1,
+                max_wait_ms: 10_000
+            }
+        );
+        assert!(profile.allows(&Action::Move(MoveAction::default())));
+        assert!(!profile.allows(&Action::BreakBlock(BreakBlockAction::default())));
+        assert!(store.refresh().unwrap().is_empty());
+
+        // A broken edit keeps the old profile
+        std::fs::write(&baker, "voice_id = ").unwrap();
+        store.modified.clear();
+        assert!(matches!(&store.refresh().unwrap()[..], [ProfileChange::Invalid { .. }]));
+        assert_eq!(store.get("baker").unwrap().voice_id, "warm");
+
+        std::fs::remove_file(&baker).unwrap();
+        assert_eq!(store.refresh().unwrap(), vec![ProfileChange::Removed("baker".to_string())]);
+        assert!(store.get("baker").is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+//! Clickable dialogue choices (`DialogueOptionsDirective`, v1.2+).
+//!
+//! Instead of making players type exact phrases, offer the branches of a
+//! dialogue as options; the plugin renders them as clickable chat
+//! components or a GUI and reports the pick in a
+//! [`DialogueChoiceObservation`]. [`DialoguePrompts`] keeps the prompts
+//! that are open: record each one sent with [`DialoguePrompts::offer`],
+//! and [`DialoguePrompts::choose`] turns the observation back into the
+//! option the player clicked.
+
+use std::collections::HashMap;
+
+use crate::npc_society::v1::{DialogueChoiceObservation, DialogueOption, DialogueOptionsDirective};
+
+/// An option without a tooltip
+pub fn option(option_id: impl Into<String>, label: impl Into<String>) -> DialogueOption {
+    DialogueOption {
+        option_id: option_id.into(),
+        label: label.into(),
+        ..Default::default()
+    }
+}
+
+/// How a prompt was answered
+#[derive(Debug, Clone, PartialEq)]
+pub struct Choice {
+    pub prompt_id: String,
+    pub npc_id: String,
+    pub player_uuid: String,
+    /// The clicked option; None if the prompt expired or was replaced
+    pub option: Option<DialogueOption>,
+}
+
+/// Open prompts, by prompt_id
+#[derive(Debug, Default)]
+pub struct DialoguePrompts {
+    prompts: HashMap<String, DialogueOptionsDirective>,
+}
+
+impl DialoguePrompts {
+    /// Remember a prompt sent to the plugin. The plugin drops the player's
+    /// previous prompt from the same NPC, so it is forgotten here too;
+    /// returns its prompt_id.
+    pub fn offer(&mut self, prompt: &DialogueOptionsDirective) -> Option<String> {
+        let replaced = self
+            .open(&prompt.npc_id, &prompt.player_uuid)
+            .map(|p| p.prompt_id.clone());
+        if let Some(prompt_id) = &replaced {
+            self.prompts.remove(prompt_id);
+        }
+        self.prompts
+            .insert(prompt.prompt_id.clone(), prompt.clone());
+        replaced
+    }
+
+    /// Close the prompt `choice` answers. None if it is not open (never
+    /// offered, already answered or pruned), or if the choice names an
+    /// option the prompt did not offer; such a prompt stays open.
+    pub fn choose(&mut self, choice: &DialogueChoiceObservation) -> Option<Choice> {
+        let prompt = self.prompts.get(&choice.prompt_id)?;
+        if prompt.npc_id != choice.npc_id || prompt.player_uuid != choice.player_uuid {
+            return None;
+        }
+        let option = if choice.option_id.is_empty() {
+            None
+        } else {
+            let option = prompt
+                .options
+                .iter()
+                .find(|o| o.option_id == choice.option_id)?;
+            Some(option.clone())
+        };
+        let prompt = self.prompts.remove(&choice.prompt_id)?;
+        Some(Choice {
+            prompt_id: prompt.prompt_id,
+            npc_id: prompt.npc_id,
+            player_uuid: prompt.player_uuid,
+            option,
+        })
+    }
+
+    /// The open prompt `npc_id` showed `player_uuid`, if any
+    pub fn open(&self, npc_id: &str, player_uuid: &str) -> Option<&DialogueOptionsDirective> {
+        self.prompts
+            .values()
+            .find(|p| p.npc_id == npc_id && p.player_uuid == player_uuid)
+    }
+
+    /// Forget prompts that expired by `now_ms` and return them. The plugin
+    /// reports those as closed, but not if the connection dropped first.
+    pub fn prune(&mut self, now_ms: i64) -> Vec<DialogueOptionsDirective> {
+        let expired: Vec<String> = self
+            .prompts
+            .values()
+            .filter(|p| p.expires_at_ms > 0 && p.expires_at_ms <= now_ms)
+            .map(|p| p.prompt_id.clone())
+            .collect();
+        expired
+            .iter()
+            .filter_map(|prompt_id| self.prompts.remove(prompt_id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompt(prompt_id: &str, expires_at_ms: i64) -> DialogueOptionsDirective {
+        DialogueOptionsDirective {
+            prompt_id: prompt_id.to_string(),
+            npc_id: "smith".to_string(),
+            player_uuid: "p1".to_string(),
+            options: vec![option("buy", "I'd like to buy"), option("bye", "Goodbye")],
+            expires_at_ms,
+            ..Default::default()
+        }
+    }
+
+    fn choice(prompt_id: &str, option_id: &str) -> DialogueChoiceObservation {
+        DialogueChoiceObservation {
+            prompt_id: prompt_id.to_string(),
+            npc_id: "smith".to_string(),
+            player_uuid: "p1".to_string(),
+            option_id: option_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_offer_and_choose() {
+        let mut prompts = DialoguePrompts::default();
+        assert_eq!(prompts.offer(&prompt("q1", 0)), None);
+        // A newer prompt replaces the open one
+        assert_eq!(prompts.offer(&prompt("q2", 0)).as_deref(), Some("q1"));
+        assert!(prompts.choose(&choice("q1", "buy")).is_none());
+
+        assert!(prompts.choose(&choice("q2", "steal")).is_none());
+        let picked = prompts.choose(&choice("q2", "buy")).unwrap();
+        assert_eq!(picked.option.unwrap().label, "I'd like to buy");
+        // Answered once only
+        assert!(prompts.choose(&choice("q2", "buy")).is_none());
+        assert!(prompts.open("smith", "p1").is_none());
+
+        prompts.offer(&prompt("q3", 0));
+        assert_eq!(prompts.choose(&choice("q3", "")).unwrap().option, None);
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut prompts = DialoguePrompts::default();
+        prompts.offer(&prompt("q1", 1_000));
+        assert!(prompts.prune(999).is_empty());
+        assert_eq!(prompts.prune(1_000)[0].prompt_id, "q1");
+        assert!(prompts.choose(&choice("q1", "buy")).is_none());
+    }
+}
@@ -0,0 +1,428 @@
+//! Full-terrain snapshots from RegionSnapshot results.
+//!
+//! A `RegionSnapshotResult` is a palette plus run-length encoded cuboid.
+//! [`Region::decode`] expands it into one palette index per block so
+//! planners can ask "what is at (x, y, z)" (and, if requested, how
+//! passable and how bright it is) in constant time, and
+//! [`crate::world_model::WorldModel`] caches every known block from it.
+//! [`Region::encode`] does the reverse for plugin bridges and tests.
+
+use std::fmt;
+
+use crate::npc_society::v1::{BlockPosition, BlockProperties, RegionSnapshotResult};
+
+/// Largest volume decoded (256^3 blocks); larger sizes are rejected
+/// rather than allocated
+pub const MAX_REGION_VOLUME: usize = 256 * 256 * 256;
+
+/// Palette entry of blocks in unloaded chunks
+pub const UNKNOWN: &str = "";
+
+/// Why a RegionSnapshotResult could not be decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegionError {
+    /// A size is negative or the volume exceeds [`MAX_REGION_VOLUME`]
+    InvalidSize {
+        size_x: i32,
+        size_y: i32,
+        size_z: i32,
+    },
+    /// Run values and run lengths differ in number
+    RunMismatch { indices: usize, lengths: usize },
+    /// A run refers past the end of the palette
+    PaletteIndex(u32),
+    /// The runs cover a different number of blocks than the cuboid
+    BlockCount { expected: usize, actual: usize },
+    /// `palette_properties` is neither empty nor one per palette entry
+    PropertiesMismatch { palette: usize, properties: usize },
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSize {
+                size_x,
+                size_y,
+                size_z,
+            } => write!(f, "invalid region size {}x{}x{}", size_x, size_y, size_z),
+            Self::RunMismatch { indices, lengths } => write!(
+                f,
+                "{} run palette indices but {} run lengths",
+                indices, lengths
+            ),
+            Self::PaletteIndex(index) => write!(f, "palette index {} out of range", index),
+            Self::BlockCount { expected, actual } => {
+                write!(f, "runs cover {} blocks, expected {}", actual, expected)
+            }
+            Self::PropertiesMismatch {
+                palette,
+                properties,
+            } => write!(
+                f,
+                "{} palette properties for {} palette entries",
+                properties, palette
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RegionError {}
+
+/// A decoded cuboid of blocks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    min: BlockPosition,
+    size: [i32; 3],
+    palette: Vec<String>,
+    /// Properties per palette entry, or empty
+    properties: Vec<BlockProperties>,
+    /// Palette index per block, x fastest, then z, then y
+    blocks: Vec<u32>,
+    /// Light level per block, or empty
+    light: Vec<u8>,
+}
+
+impl Region {
+    /// A cuboid at `min` of `size` (x, y, z) blocks, all unknown
+    pub fn new(min: BlockPosition, size: [i32; 3]) -> Result<Self, RegionError> {
+        let volume = volume(size)?;
+        Ok(Self {
+            min,
+            size,
+            palette: vec![UNKNOWN.to_string()],
+            properties: Vec::new(),
+            blocks: vec![0; volume],
+            light: Vec::new(),
+        })
+    }
+
+    /// Expand a RegionSnapshotResult
+    pub fn decode(result: &RegionSnapshotResult) -> Result<Self, RegionError> {
+        let size = [result.size_x, result.size_y, result.size_z];
+        let volume = volume(size)?;
+        let blocks = expand(&result.run_palette_indices, &result.run_lengths, volume)?;
+        if let Some(&index) = result
+            .run_palette_indices
+            .iter()
+            .find(|&&i| i as usize >= result.palette.len())
+        {
+            return Err(RegionError::PaletteIndex(index));
+        }
+        let properties = &result.palette_properties;
+        if !properties.is_empty() && properties.len() != result.palette.len() {
+            return Err(RegionError::PropertiesMismatch {
+                palette: result.palette.len(),
+                properties: properties.len(),
+            });
+        }
+        let light = if result.light_run_lengths.is_empty() {
+            Vec::new()
+        } else {
+            expand(&result.light_run_levels, &result.light_run_lengths, volume)?
+                .into_iter()
+                .map(|level| level.min(15) as u8)
+                .collect()
+        };
+
+        Ok(Self {
+            min: result.min.clone().unwrap_or_default(),
+            size,
+            palette: result.palette.clone(),
+            properties: properties.clone(),
+            blocks,
+            light,
+        })
+    }
+
+    /// Palette and run-length encode the region
+    pub fn encode(&self) -> RegionSnapshotResult {
+        let (run_palette_indices, run_lengths) = compress(self.blocks.iter().copied());
+        let (light_run_levels, light_run_lengths) =
+            compress(self.light.iter().map(|&level| level as u32));
+        RegionSnapshotResult {
+            min: Some(self.min.clone()),
+            size_x: self.size[0],
+            size_y: self.size[1],
+            size_z: self.size[2],
+            palette: self.palette.clone(),
+            run_palette_indices,
+            run_lengths,
+            palette_properties: self.properties.clone(),
+            light_run_levels,
+            light_run_lengths,
+        }
+    }
+
+    /// Minimum corner
+    pub fn min(&self) -> &BlockPosition {
+        &self.min
+    }
+
+    /// Extent along x, y and z
+    pub fn size(&self) -> [i32; 3] {
+        self.size
+    }
+
+    /// Whether `position` lies inside the cuboid
+    pub fn contains(&self, position: &BlockPosition) -> bool {
+        self.offset(position).is_some()
+    }
+
+    /// Block type at `position`; None outside the cuboid or in unloaded chunks
+    pub fn get(&self, position: &BlockPosition) -> Option<&str> {
+        let block_type = &self.palette[self.blocks[self.offset(position)?] as usize];
+        (block_type != UNKNOWN).then_some(block_type.as_str())
+    }
+
+    /// Properties of the block at `position`, if the snapshot has them
+    pub fn properties(&self, position: &BlockPosition) -> Option<&BlockProperties> {
+        self.properties
+            .get(self.blocks[self.offset(position)?] as usize)
+    }
+
+    /// Light level (0-15) at `position`, if the snapshot has it
+    pub fn light_level(&self, position: &BlockPosition) -> Option<u8> {
+        self.light.get(self.offset(position)?).copied()
+    }
+
+    /// Known block types with their properties
+    pub fn palette_properties(&self) -> impl Iterator<Item = (&str, &BlockProperties)> + '_ {
+        self.palette
+            .iter()
+            .map(String::as_str)
+            .zip(&self.properties)
+            .filter(|(block_type, _)| *block_type != UNKNOWN)
+    }
+
+    /// Set the block at `position`; false if it lies outside the cuboid
+    pub fn set(&mut self, position: &BlockPosition, block_type: &str) -> bool {
+        let Some(offset) = self.offset(position) else {
+            return false;
+        };
+        self.blocks[offset] = self.palette_index(block_type) as u32;
+        true
+    }
+
+    /// Set the properties of every block of `block_type`
+    pub fn set_properties(&mut self, block_type: &str, properties: BlockProperties) {
+        let index = self.palette_index(block_type);
+        self.properties
+            .resize(self.palette.len(), BlockProperties::default());
+        self.properties[index] = properties;
+    }
+
+    /// Set the light level at `position`; false if it lies outside the cuboid
+    pub fn set_light_level(&mut self, position: &BlockPosition, level: u8) -> bool {
+        let Some(offset) = self.offset(position) else {
+            return false;
+        };
+        self.light.resize(self.blocks.len(), 0);
+        self.light[offset] = level.min(15);
+        true
+    }
+
+    fn palette_index(&mut self, block_type: &str) -> usize {
+        if let Some(index) = self.palette.iter().position(|p| p == block_type) {
+            return index;
+        }
+        self.palette.push(block_type.to_string());
+        if !self.properties.is_empty() {
+            self.properties.push(BlockProperties::default());
+        }
+        self.palette.len() - 1
+    }
+
+    /// Every block outside unloaded chunks, with its position
+    pub fn blocks(&self) -> impl Iterator<Item = (BlockPosition, &str)> + '_ {
+        let [size_x, _, size_z] = self.size;
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, &index)| self.palette[index as usize] != UNKNOWN)
+            .map(move |(i, &index)| {
+                let i = i as i32;
+                let position = BlockPosition {
+                    x: self.min.x + i % size_x,
+                    y: self.min.y + i / (size_x * size_z),
+                    z: self.min.z + i / size_x % size_z,
+                    ..self.min.clone()
+                };
+                (position, self.palette[index as usize].as_str())
+            })
+    }
+
+    fn offset(&self, p: &BlockPosition) -> Option<usize> {
+        let [size_x, size_y, size_z] = self.size;
+        let (x, y, z) = (p.x - self.min.x, p.y - self.min.y, p.z - self.min.z);
+        let inside = p.world == self.min.world
+            && (0..size_x).contains(&x)
+            && (0..size_y).contains(&y)
+            && (0..size_z).contains(&z);
+        inside.then(|| ((y * size_z + z) * size_x + x) as usize)
+    }
+}
+
+/// One value per block from runs, which must cover exactly `volume` blocks
+fn expand(values: &[u32], lengths: &[u32], volume: usize) -> Result<Vec<u32>, RegionError> {
+    if values.len() != lengths.len() {
+        return Err(RegionError::RunMismatch {
+            indices: values.len(),
+            lengths: lengths.len(),
+        });
+    }
+    let actual = lengths.iter().map(|&l| l as usize).sum();
+    if actual != volume {
+        return Err(RegionError::BlockCount {
+            expected: volume,
+            actual,
+        });
+    }
+    let mut expanded = Vec::with_capacity(volume);
+    for (&value, &length) in values.iter().zip(lengths) {
+        expanded.resize(expanded.len() + length as usize, value);
+    }
+    Ok(expanded)
+}
+
+/// Runs of equal values: (values, lengths)
+fn compress(values: impl Iterator<Item = u32>) -> (Vec<u32>, Vec<u32>) {
+    let (mut runs, mut lengths) = (Vec::new(), Vec::<u32>::new());
+    for value in values {
+        match (runs.last(), lengths.last_mut()) {
+            (Some(&last), Some(length)) if last == value => *length += 1,
+            _ => {
+                runs.push(value);
+                lengths.push(1);
+            }
+        }
+    }
+    (runs, lengths)
+}
+
+fn volume([size_x, size_y, size_z]: [i32; 3]) -> Result<usize, RegionError> {
+    let invalid = RegionError::InvalidSize {
+        size_x,
+        size_y,
+        size_z,
+    };
+    if size_x < 0 || size_y < 0 || size_z < 0 {
+        return Err(invalid);
+    }
+    (size_x as usize)
+        .checked_mul(size_y as usize)
+        .and_then(|v| v.checked_mul(size_z as usize))
+        .filter(|&v| v <= MAX_REGION_VOLUME)
+        .ok_or(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: i32, y: i32, z: i32) -> BlockPosition {
+        BlockPosition {
+            world: "world".to_string(),
+            x,
+            y,
+            z,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        // A 4x3x2 cuboid: stone floor, air above, one ore
+        let mut region = Region::new(at(10, 60, -5), [4, 3, 2]).unwrap();
+        for x in 10..14 {
+            for z in -5..-3 {
+                region.set(&at(x, 60, z), "minecraft:stone");
+                region.set(&at(x, 61, z), "minecraft:air");
+            }
+        }
+        region.set(&at(12, 60, -4), "minecraft:diamond_ore");
+
+        let encoded = region.encode();
+        assert_eq!(encoded.run_lengths.iter().sum::<u32>(), 24);
+        assert!(encoded.run_lengths.len() < 24);
+
+        let decoded = Region::decode(&encoded).unwrap();
+        assert_eq!(decoded.get(&at(12, 60, -4)), Some("minecraft:diamond_ore"));
+        assert_eq!(decoded.get(&at(11, 61, -5)), Some("minecraft:air"));
+        // Top layer is unloaded, the rest is outside
+        assert_eq!(decoded.get(&at(11, 62, -5)), None);
+        assert_eq!(decoded.get(&at(14, 60, -5)), None);
+        assert_eq!(decoded.blocks().count(), 16);
+        assert!(decoded
+            .blocks()
+            .any(|(p, block)| p == at(12, 60, -4) && block == "minecraft:diamond_ore"));
+        assert!(decoded.properties(&at(12, 60, -4)).is_none());
+        assert!(decoded.light_level(&at(12, 60, -4)).is_none());
+    }
+
+    #[test]
+    fn test_properties_and_light() {
+        let mut region = Region::new(at(0, 0, 0), [2, 2, 1]).unwrap();
+        region.set(&at(0, 0, 0), "minecraft:stone");
+        region.set(&at(1, 0, 0), "minecraft:water");
+        region.set_properties(
+            "minecraft:stone",
+            BlockProperties {
+                solid: true,
+                hardness: 1.5,
+                preferred_tool: "pickaxe".to_string(),
+                ..Default::default()
+            },
+        );
+        region.set_properties(
+            "minecraft:water",
+            BlockProperties {
+                passable: true,
+                liquid: true,
+                hardness: 100.0,
+                ..Default::default()
+            },
+        );
+        region.set(&at(0, 1, 0), "minecraft:air");
+        region.set_light_level(&at(0, 1, 0), 14);
+
+        let decoded = Region::decode(&region.encode()).unwrap();
+        assert!(decoded.properties(&at(1, 0, 0)).unwrap().liquid);
+        assert_eq!(
+            decoded.properties(&at(0, 0, 0)).unwrap().preferred_tool,
+            "pickaxe"
+        );
+        // Added after set_properties: default properties
+        assert!(!decoded.properties(&at(0, 1, 0)).unwrap().solid);
+        assert_eq!(decoded.light_level(&at(0, 1, 0)), Some(14));
+        assert_eq!(decoded.light_level(&at(0, 0, 0)), Some(0));
+        assert_eq!(decoded.palette_properties().count(), 3);
+
+        let mut encoded = region.encode();
+        encoded.palette_properties.pop();
+        assert!(matches!(
+            Region::decode(&encoded),
+            Err(RegionError::PropertiesMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let mut encoded = Region::new(at(0, 0, 0), [2, 2, 2]).unwrap().encode();
+        encoded.run_lengths[0] = 9;
+        assert_eq!(
+            Region::decode(&encoded),
+            Err(RegionError::BlockCount {
+                expected: 8,
+                actual: 9
+            })
+        );
+        encoded.run_lengths[0] = 8;
+        encoded.run_palette_indices[0] = 3;
+        assert_eq!(Region::decode(&encoded), Err(RegionError::PaletteIndex(3)));
+        encoded.size_x = 1 << 30;
+        assert!(matches!(
+            Region::decode(&encoded),
+            Err(RegionError::InvalidSize { .. })
+        ));
+    }
+}
@@ -0,0 +1,282 @@
+//! Rebuilding daemon state from the event log (feature `persistence`).
+//!
+//! A daemon that records what the plugin sends with [`EventRecorder`] can
+//! crash and come back knowing what it knew: [`rebuild`] replays a server's
+//! log into a fresh [`WorldModel`], [`Reputation`] and [`ConversationManager`],
+//! and picks up the directives that were still waiting for a result, so
+//! they are resent after the next Hello instead of abandoned mid-task.
+//!
+//! Only messages that change that state are recorded, and WorldTicks at
+//! most once per `tick_interval_ms`: a tick mostly repeats the one before,
+//! and the last one before the crash is what matters.
+
+use crate::conversation::ConversationManager;
+use crate::npc_society::v1::{client_message::Message, ClientMessage, PendingDirective, WorldTick};
+use crate::persistence::Store;
+use crate::reputation::Reputation;
+use crate::world_model::WorldModel;
+
+/// Messages read from the store at a time during replay
+const REPLAY_BATCH: usize = 1_000;
+
+/// What to record and for how long
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayConfig {
+    /// Minimum time between recorded WorldTicks
+    pub tick_interval_ms: i64,
+    /// Messages and unfinished directives older than this are not replayed
+    /// (and pruned by [`rebuild`])
+    pub retention_ms: i64,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            tick_interval_ms: 1_000,
+            retention_ms: 60 * 60_000,
+        }
+    }
+}
+
+/// Decides which received messages go into the event log
+#[derive(Debug, Default)]
+pub struct EventRecorder {
+    config: ReplayConfig,
+    last_tick_ms: Option<i64>,
+}
+
+impl EventRecorder {
+    /// A recorder with `config`
+    pub fn new(config: ReplayConfig) -> Self {
+        Self {
+            config,
+            last_tick_ms: None,
+        }
+    }
+
+    /// Whether `message`, received at `now_ms`, should be appended to the
+    /// log
+    pub fn should_record(&mut self, message: &ClientMessage, now_ms: i64) -> bool {
+        match &message.message {
+            Some(Message::WorldTick(_)) => {
+                let due = self
+                    .last_tick_ms
+                    .is_none_or(|last| now_ms - last >= self.config.tick_interval_ms);
+                if due {
+                    self.last_tick_ms = Some(now_ms);
+                }
+                due
+            }
+            Some(
+                Message::ChatObservation(_)
+                | Message::EventObservation(_)
+                | Message::ActionResult(_)
+                | Message::TransactionObservation(_)
+                | Message::BlockWatchUpdate(_),
+            ) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Daemon state rebuilt from the event log
+#[derive(Debug, Default)]
+pub struct Replayed {
+    /// Blocks, entities and players seen
+    pub world: WorldModel,
+    /// How each NPC feels about each player
+    pub reputation: Reputation,
+    /// Dialogue sessions still live (player turns only; NPC replies are in
+    /// [`Store::dialogue`])
+    pub dialogues: ConversationManager,
+    /// The last recorded WorldTick
+    pub latest_tick: Option<WorldTick>,
+    /// Directives sent but never answered, oldest first
+    pub pending: Vec<PendingDirective>,
+    /// Messages replayed
+    pub events: usize,
+}
+
+impl Replayed {
+    /// Apply one logged message, as the daemon did when it arrived
+    pub fn apply(&mut self, message: &ClientMessage, received_at_ms: i64) {
+        self.events += 1;
+        match &message.message {
+            Some(Message::WorldBootstrap(bootstrap)) => {
+                self.world.ingest_bootstrap(bootstrap);
+            }
+            Some(Message::WorldTick(tick)) => {
+                self.world.ingest_tick(tick);
+                self.reputation.observe_tick(tick);
+                self.latest_tick = Some(tick.clone());
+            }
+            Some(Message::ChatObservation(chat)) => {
+                self.reputation.observe_chat(chat);
+                self.dialogues.on_chat(chat);
+            }
+            Some(Message::EventObservation(event)) => {
+                self.world.ingest_event(event);
+                self.reputation.observe_event(event);
+            }
+            Some(Message::ActionResult(result)) => {
+                self.world.ingest_action_result(result, received_at_ms);
+            }
+            Some(Message::TransactionObservation(transaction)) => {
+                self.reputation.observe_transaction(transaction);
+            }
+            Some(Message::BlockWatchUpdate(update)) => {
+                self.world.ingest_block_watch(update, received_at_ms);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Prune the log to `config.retention_ms` before `now_ms`, then replay what
+/// is left of `server_id`'s messages ([`Store::event_servers`] lists the
+/// servers to rebuild)
+pub fn rebuild<S: Store>(
+    store: &mut S,
+    server_id: &str,
+    config: ReplayConfig,
+    now_ms: i64,
+) -> Result<Replayed, S::Error> {
+    let cutoff = now_ms - config.retention_ms;
+    store.prune_events(cutoff)?;
+
+    let mut replayed = Replayed::default();
+    let mut after_seq = 0;
+    loop {
+        let batch = store.events(server_id, after_seq, REPLAY_BATCH)?;
+        let Some(last) = batch.last() else {
+            break;
+        };
+        after_seq = last.seq;
+        for event in &batch {
+            replayed.apply(&event.message, event.received_at_ms);
+        }
+    }
+    replayed.dialogues.prune(now_ms);
+    replayed.pending = store
+        .unfinished_directives(server_id, cutoff)?
+        .into_iter()
+        .map(|record| PendingDirective {
+            directive: Some(record.directive),
+            sent_at_ms: record.sent_at_ms,
+            ack: None,
+        })
+        .collect();
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{
+        action_directive::Action, ActionDirective, ActionResult, ChatObservation, MoveAction,
+        NpcSnapshot,
+    };
+    use crate::persistence::SqliteStore;
+
+    fn message(message: Message) -> ClientMessage {
+        ClientMessage {
+            message: Some(message),
+            ..Default::default()
+        }
+    }
+
+    fn tick(server_tick: i64) -> ClientMessage {
+        message(Message::WorldTick(WorldTick {
+            server_tick,
+            npcs: vec![NpcSnapshot {
+                npc_id: "miner".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn test_recorder_thins_ticks() {
+        let mut recorder = EventRecorder::default();
+        assert!(recorder.should_record(&tick(1), 0));
+        assert!(!recorder.should_record(&tick(2), 50));
+        assert!(recorder.should_record(&tick(21), 1_000));
+        assert!(
+            !recorder.should_record(&message(Message::VoicePcmFrame(Default::default())), 1_000)
+        );
+        assert!(recorder.should_record(
+            &message(Message::ChatObservation(Default::default())),
+            1_000
+        ));
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let now = 2 * 60 * 60_000;
+        let mut store = SqliteStore::in_memory().unwrap();
+        // Too old to replay
+        store.append_event("survival", &tick(1), 0).unwrap();
+        store
+            .append_event("survival", &tick(2_000), now - 5_000)
+            .unwrap();
+        let chat = ChatObservation {
+            npc_id: "miner".to_string(),
+            player_uuid: "steve".to_string(),
+            message: "any diamonds?".to_string(),
+            timestamp_ms: now - 4_000,
+            ..Default::default()
+        };
+        store
+            .append_event(
+                "survival",
+                &message(Message::ChatObservation(chat)),
+                now - 4_000,
+            )
+            .unwrap();
+
+        let directive = |id: &str| ActionDirective {
+            directive_id: id.to_string(),
+            npc_id: "miner".to_string(),
+            action: Some(Action::Move(MoveAction::default())),
+            ..Default::default()
+        };
+        store
+            .log_directive("survival", &directive("move-1"), now - 3_000)
+            .unwrap();
+        store
+            .log_directive("survival", &directive("move-2"), now - 2_000)
+            .unwrap();
+        let result = ActionResult {
+            directive_id: "move-1".to_string(),
+            success: true,
+            ..Default::default()
+        };
+        store.log_result("survival", &result, now - 1_000).unwrap();
+        store
+            .append_event(
+                "survival",
+                &message(Message::ActionResult(result)),
+                now - 1_000,
+            )
+            .unwrap();
+
+        // Another server's tick stays with that server
+        store
+            .append_event("creative", &tick(9_000), now - 500)
+            .unwrap();
+
+        let replayed = rebuild(&mut store, "survival", ReplayConfig::default(), now).unwrap();
+        assert_eq!(replayed.events, 3);
+        assert_eq!(replayed.latest_tick.unwrap().server_tick, 2_000);
+        assert!(replayed.dialogues.session("miner", "steve", now).is_some());
+        assert_eq!(replayed.pending.len(), 1);
+        assert_eq!(
+            replayed.pending[0].directive.as_ref().unwrap().directive_id,
+            "move-2"
+        );
+        // The old tick is gone for good
+        assert_eq!(store.events("survival", 0, 10).unwrap().len(), 3);
+        assert_eq!(store.event_servers().unwrap(), ["creative", "survival"]);
+    }
+}
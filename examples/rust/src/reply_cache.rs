@@ -0,0 +1,318 @@
+//! Canned replies for questions NPCs hear over and over.
+//!
+//! Five hundred players saying "hi" to the same shopkeeper is five hundred
+//! identical LLM completions and TTS syntheses. A [`ReplyCache`] remembers
+//! the reply to a [`ReplyKey`] (the NPC's persona, what the player said
+//! and a hash of whatever else the reply depends on) and keeps its speech
+//! as an audio asset, so the next player gets the same line from the
+//! plugin's asset cache without an LLM or TTS call:
+//!
+//! ```ignore
+//! let key = ReplyKey::new(&profile.persona, &chat.message, &mood);
+//! let reply = match cache.lookup(&key, now_ms) {
+//!     Some(reply) => reply,
+//!     None => cache.store(key, llm.reply(&prompt).await?, now_ms),
+//! };
+//! if !assets.contains(reply.asset_id()) {
+//!     let clip = tts.synthesize(&reply.text).await?;
+//!     assets.add(reply.asset_id(), &clip.samples, clip.sample_rate_hz);
+//! }
+//! for message in reply.speak(speak, &mut assets) {
+//!     tx.send(message)?;
+//! }
+//! ```
+//!
+//! Only replies that mean the same to every player belong here: greetings
+//! and FAQ answers, not lines with the player's name or the state of a
+//! quest in them unless that goes into the context.
+
+use std::collections::HashMap;
+
+use crate::assets::AudioAssets;
+use crate::npc_society::v1::{ServerMessage, SpeakDirective};
+
+/// How many replies a [`ReplyCache`] keeps and for how long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplyCacheConfig {
+    /// Replies kept; the least recently used goes first
+    pub capacity: usize,
+    /// How long a reply is reused after it was stored
+    pub ttl_ms: i64,
+}
+
+impl Default for ReplyCacheConfig {
+    /// 256 replies for an hour each
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            ttl_ms: 60 * 60 * 1000,
+        }
+    }
+}
+
+/// What a reply depends on
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReplyKey {
+    persona: u64,
+    utterance: String,
+    context: u64,
+}
+
+impl ReplyKey {
+    /// Key of a reply by an NPC of `persona` to `utterance`; `context` is
+    /// anything else the reply depends on (time of day, attitude, ...)
+    pub fn new(persona: &str, utterance: &str, context: &str) -> Self {
+        Self {
+            persona: fnv(persona.as_bytes()),
+            utterance: normalize(utterance),
+            context: fnv(context.as_bytes()),
+        }
+    }
+
+    /// The utterance as it is matched: lowercase, without punctuation
+    pub fn utterance(&self) -> &str {
+        &self.utterance
+    }
+
+    /// Asset ID of the reply's speech; the same in every process
+    fn asset_id(&self) -> String {
+        let mut bytes = Vec::with_capacity(16 + self.utterance.len());
+        bytes.extend(self.persona.to_le_bytes());
+        bytes.extend(self.context.to_le_bytes());
+        bytes.extend(self.utterance.as_bytes());
+        format!("reply-{:016x}", fnv(&bytes))
+    }
+}
+
+/// A reply to reuse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedReply {
+    /// What the NPC says
+    pub text: String,
+    asset_id: String,
+}
+
+impl CachedReply {
+    /// Audio asset the reply's speech is kept as
+    pub fn asset_id(&self) -> &str {
+        &self.asset_id
+    }
+
+    /// Messages that say the reply with `speak`'s routing: the cached clip
+    /// if `assets` has it, otherwise `speak` as a subtitle
+    pub fn speak(&self, mut speak: SpeakDirective, assets: &mut AudioAssets) -> Vec<ServerMessage> {
+        speak.text = self.text.clone();
+        if assets.contains(&self.asset_id) {
+            if let Some(messages) = assets.play(&self.asset_id, speak.clone()) {
+                return messages;
+            }
+        }
+        vec![speak.into()]
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    reply: CachedReply,
+    stored_at_ms: i64,
+    used_at_ms: i64,
+}
+
+/// Replies by key, with hit counts
+#[derive(Debug, Clone, Default)]
+pub struct ReplyCache {
+    config: ReplyCacheConfig,
+    entries: HashMap<ReplyKey, Entry>,
+    hits: u64,
+    misses: u64,
+}
+
+impl ReplyCache {
+    /// Create an empty cache
+    pub fn new(config: ReplyCacheConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// The stored reply to `key`, unless it expired
+    pub fn lookup(&mut self, key: &ReplyKey, now_ms: i64) -> Option<CachedReply> {
+        let ttl_ms = self.config.ttl_ms;
+        match self.entries.get_mut(key) {
+            Some(entry) if now_ms - entry.stored_at_ms < ttl_ms => {
+                entry.used_at_ms = now_ms;
+                self.hits += 1;
+                Some(entry.reply.clone())
+            }
+            Some(_) => {
+                self.entries.remove(key);
+                self.misses += 1;
+                None
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Store `text` as the reply to `key`, making room if the cache is full
+    pub fn store(&mut self, key: ReplyKey, text: impl Into<String>, now_ms: i64) -> CachedReply {
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.config.capacity {
+            let ttl_ms = self.config.ttl_ms;
+            self.entries
+                .retain(|_, entry| now_ms - entry.stored_at_ms < ttl_ms);
+            if self.entries.len() >= self.config.capacity {
+                let oldest = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.used_at_ms)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    self.entries.remove(&oldest);
+                }
+            }
+        }
+        let reply = CachedReply {
+            text: text.into(),
+            asset_id: key.asset_id(),
+        };
+        self.entries.insert(
+            key,
+            Entry {
+                reply: reply.clone(),
+                stored_at_ms: now_ms,
+                used_at_ms: now_ms,
+            },
+        );
+        reply
+    }
+
+    /// Forget every reply, e.g. after an NPC's persona changed
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Replies stored
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no reply is stored
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Lookups answered from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Lookups that found nothing to reuse
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
+
+/// Lowercase words without punctuation, single-spaced: "Hi there!!" and
+/// "hi  there" are the same question
+fn normalize(utterance: &str) -> String {
+    utterance
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// FNV-1a; stable across builds, unlike std's hasher
+fn fnv(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::server_message::Message as ServerMsg;
+
+    #[test]
+    fn test_lookup_and_store() {
+        let mut cache = ReplyCache::new(ReplyCacheConfig {
+            capacity: 2,
+            ttl_ms: 10_000,
+        });
+        let hello = ReplyKey::new("Grumpy baker", "Hello there!", "morning");
+        assert_eq!(hello.utterance(), "hello there");
+        assert_eq!(cache.lookup(&hello, 0), None);
+        cache.store(hello.clone(), "We're closed.", 0);
+
+        // Case, punctuation and spacing don't make a new question
+        let again = ReplyKey::new("Grumpy baker", "  hello, THERE ", "morning");
+        assert_eq!(again, hello);
+        let reply = cache.lookup(&again, 1_000).unwrap();
+        assert_eq!(reply.text, "We're closed.");
+        assert!(reply.asset_id().starts_with("reply-"));
+
+        // Another persona or context is another reply
+        assert_eq!(
+            cache.lookup(&ReplyKey::new("Baker", "hello there", "morning"), 1_000),
+            None
+        );
+        assert_eq!(
+            cache.lookup(
+                &ReplyKey::new("Grumpy baker", "hello there", "night"),
+                1_000
+            ),
+            None
+        );
+        assert_eq!((cache.hits(), cache.misses()), (1, 3));
+
+        // Full: the least recently used reply goes
+        let bread = ReplyKey::new("Grumpy baker", "any bread?", "morning");
+        let cake = ReplyKey::new("Grumpy baker", "any cake?", "morning");
+        cache.store(bread.clone(), "No.", 2_000);
+        cache.lookup(&hello, 3_000);
+        cache.store(cake.clone(), "Maybe.", 4_000);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.lookup(&bread, 4_000).is_none());
+        assert!(cache.lookup(&cake, 4_000).is_some());
+
+        // Expired replies are not reused
+        assert!(cache.lookup(&hello, 10_000).is_none());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_speak_from_asset() {
+        let mut cache = ReplyCache::default();
+        let mut assets = AudioAssets::new();
+        let reply = cache.store(ReplyKey::new("Guard", "hi", ""), "Move along.", 0);
+        let speak = SpeakDirective {
+            npc_id: "guard_01".to_string(),
+            directive_id: "guard_01-speak-1".to_string(),
+            ..Default::default()
+        };
+
+        // No clip yet: a plain subtitle
+        let messages = reply.speak(speak.clone(), &mut assets);
+        assert_eq!(messages.len(), 1);
+        let Some(ServerMsg::SpeakDirective(subtitle)) = &messages[0].message else {
+            panic!("Expected a SpeakDirective, got {messages:?}");
+        };
+        assert_eq!(subtitle.text, "Move along.");
+
+        // With the clip: uploaded once, then played from the plugin's cache
+        assert!(assets.add(reply.asset_id(), &[0.0; 1600], 16_000));
+        let messages = reply.speak(speak, &mut assets);
+        let Some(ServerMsg::PlayAudioAsset(play)) =
+            messages.last().and_then(|m| m.message.as_ref())
+        else {
+            panic!("Expected a play, got {messages:?}");
+        };
+        assert_eq!(play.asset_id, reply.asset_id());
+        assert_eq!(play.speak.as_ref().unwrap().text, "Move along.");
+    }
+}
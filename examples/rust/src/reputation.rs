@@ -0,0 +1,416 @@
+//! NPC-player relationships.
+//!
+//! [`Reputation`] keeps a score from -100 (hated) to 100 (beloved) per
+//! (NPC, player) pair and updates it from observations according to
+//! [`ReputationRules`]: attacks near or on the NPC, trades and gifts via
+//! the economy, theft from its storage, and chat through an optional
+//! sentiment hook. Scores travel between daemons (or into a memory store)
+//! as `NpcMessage`s, and [`player_nearby`] exposes them to behavior trees,
+//! so "the guard remembers you punched him" is a condition node.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Map, Value};
+
+use crate::behavior::{condition, Node};
+use crate::npc_society::v1::{
+    event_observation::Payload, ChatObservation, ContainerAccessObservation, EventObservation,
+    NpcMessage, Relationship, ShopTradeObservation, TransactionObservation, TransferDirection,
+    WorldTick,
+};
+use crate::storage;
+use crate::types::{NpcId, PlayerUuid};
+
+/// `NpcMessage.topic` of [`Reputation::sync_message`]
+pub const TOPIC_SYNC: &str = "reputation.sync";
+
+/// Lowest possible score
+pub const MIN_SCORE: f32 = -100.0;
+/// Highest possible score
+pub const MAX_SCORE: f32 = 100.0;
+
+/// How observations change scores
+#[derive(Debug, Clone, Copy)]
+pub struct ReputationRules {
+    /// Player hit the NPC
+    pub attacked_npc: f32,
+    /// Player hit something else the NPC saw
+    pub attacked_nearby: f32,
+    /// Added when that hit killed the target
+    pub killed: f32,
+    /// Completed trade initiated by the NPC (TransferCurrencyDirective) or
+    /// in its shop
+    pub trade: f32,
+    /// Per 100 currency units a player gave the NPC unprompted
+    pub gift_per_100: f32,
+    /// Player took items from the NPC's claimed container without
+    /// permission, or broke it
+    pub theft: f32,
+    /// Multiplies the sentiment of a chat message
+    pub chat_weight: f32,
+    /// Sentiment of a chat message from -1.0 to 1.0; chat is ignored without it
+    pub sentiment: Option<fn(&str) -> f32>,
+}
+
+impl Default for ReputationRules {
+    fn default() -> Self {
+        Self {
+            attacked_npc: -25.0,
+            attacked_nearby: -5.0,
+            killed: -15.0,
+            trade: 2.0,
+            gift_per_100: 1.0,
+            theft: -20.0,
+            chat_weight: 3.0,
+            sentiment: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Entry {
+    score: f32,
+    updated_at_ms: i64,
+}
+
+/// Relationship scores of every NPC with every player it has met.
+#[derive(Debug, Default)]
+pub struct Reputation {
+    rules: ReputationRules,
+    /// npc_id -> player_uuid -> entry
+    scores: HashMap<String, HashMap<String, Entry>>,
+    /// npc_id -> entity UUID, from WorldTicks
+    entities: HashMap<String, String>,
+}
+
+/// A [`Reputation`] shared between the connection and behavior trees
+pub type SharedReputation = Arc<Mutex<Reputation>>;
+
+impl Reputation {
+    /// Create with the given rules
+    pub fn new(rules: ReputationRules) -> Self {
+        Self {
+            rules,
+            ..Self::default()
+        }
+    }
+
+    /// Score of `player` with `npc` (0 for strangers)
+    pub fn score(&self, npc: &NpcId, player: &PlayerUuid) -> f32 {
+        self.lookup(npc.as_str(), player.as_str())
+    }
+
+    /// Change a score by `delta`, clamped to [`MIN_SCORE`]..=[`MAX_SCORE`]
+    pub fn adjust(&mut self, npc: &NpcId, player: &PlayerUuid, delta: f32, now_ms: i64) {
+        self.apply(npc.as_str(), player.as_str(), delta, now_ms);
+    }
+
+    // Observations carry plain strings; attackers are not necessarily
+    // players, so these skip the id checks
+    fn lookup(&self, npc_id: &str, player_uuid: &str) -> f32 {
+        self.scores
+            .get(npc_id)
+            .and_then(|players| players.get(player_uuid))
+            .map_or(0.0, |e| e.score)
+    }
+
+    fn apply(&mut self, npc_id: &str, player_uuid: &str, delta: f32, now_ms: i64) {
+        let entry = self
+            .scores
+            .entry(npc_id.to_string())
+            .or_default()
+            .entry(player_uuid.to_string())
+            .or_insert(Entry {
+                score: 0.0,
+                updated_at_ms: now_ms,
+            });
+        entry.score = (entry.score + delta).clamp(MIN_SCORE, MAX_SCORE);
+        entry.updated_at_ms = now_ms;
+    }
+
+    /// Learn the NPCs' entity UUIDs, so attacks on them can be told apart
+    pub fn observe_tick(&mut self, tick: &WorldTick) {
+        for npc in &tick.npcs {
+            self.entities.insert(npc.npc_id.clone(), npc.entity_uuid.clone());
+        }
+    }
+
+    /// Apply combat seen by an NPC. Attackers that are not players simply
+    /// never show up in [`Reputation::score`] lookups.
+    pub fn observe_event(&mut self, event: &EventObservation) {
+        let Some(Payload::Combat(combat)) = &event.payload else {
+            return;
+        };
+        let on_npc = self.entities.get(&event.npc_id) == Some(&combat.target_uuid);
+        let mut delta = if on_npc {
+            self.rules.attacked_npc
+        } else {
+            self.rules.attacked_nearby
+        };
+        if combat.target_killed {
+            delta += self.rules.killed;
+        }
+        self.apply(&event.npc_id, &combat.attacker_uuid, delta, event.timestamp_ms);
+    }
+
+    /// Apply a completed trade or gift
+    pub fn observe_transaction(&mut self, transaction: &TransactionObservation) {
+        if !transaction.success {
+            return;
+        }
+        let delta = if !transaction.directive_id.is_empty() {
+            self.rules.trade
+        } else if transaction.direction() == TransferDirection::PlayerToNpc {
+            self.rules.gift_per_100 * transaction.amount as f32 / 100.0
+        } else {
+            return;
+        };
+        self.apply(&transaction.npc_id, &transaction.player_uuid, delta, transaction.timestamp_ms);
+    }
+
+    /// Apply a completed trade in the NPC's shop GUI
+    pub fn observe_shop_trade(&mut self, trade: &ShopTradeObservation) {
+        if trade.success {
+            self.apply(&trade.npc_id, &trade.player_uuid, self.rules.trade, trade.timestamp_ms);
+        }
+    }
+
+    /// Apply a theft from the NPC's storage
+    pub fn observe_container_access(&mut self, access: &ContainerAccessObservation) {
+        if !storage::stolen(access).is_empty() {
+            self.apply(&access.npc_id, &access.player_uuid, self.rules.theft, access.timestamp_ms);
+        }
+    }
+
+    /// Apply the sentiment of a chat message, if a hook is configured
+    pub fn observe_chat(&mut self, chat: &ChatObservation) {
+        if let Some(sentiment) = self.rules.sentiment {
+            let delta = sentiment(&chat.message).clamp(-1.0, 1.0) * self.rules.chat_weight;
+            self.apply(&chat.npc_id, &chat.player_uuid, delta, chat.timestamp_ms);
+        }
+    }
+
+    /// Everyone `npc_id` has a score with, for an NpcStateSnapshot
+    pub fn relationships(&self, npc_id: &str) -> Vec<Relationship> {
+        let mut relationships: Vec<Relationship> = self
+            .scores
+            .get(npc_id)
+            .into_iter()
+            .flatten()
+            .map(|(player, e)| Relationship {
+                player_uuid: player.clone(),
+                score: e.score,
+                updated_at_ms: e.updated_at_ms,
+            })
+            .collect();
+        relationships.sort_by(|a, b| a.player_uuid.cmp(&b.player_uuid));
+        relationships
+    }
+
+    /// Replace the scores of `npc_id` with imported ones
+    pub fn restore(&mut self, npc_id: &str, relationships: &[Relationship]) {
+        let players = relationships
+            .iter()
+            .map(|r| {
+                let entry = Entry {
+                    score: r.score.clamp(MIN_SCORE, MAX_SCORE),
+                    updated_at_ms: r.updated_at_ms,
+                };
+                (r.player_uuid.clone(), entry)
+            })
+            .collect();
+        self.scores.insert(npc_id.to_string(), players);
+    }
+
+    /// Scores of `npc_id` as a broadcast NpcMessage, to persist them or
+    /// share them with other daemons
+    pub fn sync_message(&self, npc_id: &str, now_ms: i64) -> NpcMessage {
+        let scores: Map<String, Value> = self
+            .scores
+            .get(npc_id)
+            .into_iter()
+            .flatten()
+            .map(|(player, e)| (player.clone(), json!([e.score, e.updated_at_ms])))
+            .collect();
+        NpcMessage {
+            message_id: format!("{}-reputation-{}", npc_id, now_ms),
+            sender_npc_id: npc_id.to_string(),
+            topic: TOPIC_SYNC.to_string(),
+            payload: Value::Object(scores).to_string().into_bytes(),
+            timestamp_ms: now_ms,
+            ..Default::default()
+        }
+    }
+
+    /// Merge scores from a [`Reputation::sync_message`]; the most recently
+    /// updated score wins. Returns false for other topics.
+    pub fn on_message(&mut self, message: &NpcMessage) -> bool {
+        if message.topic != TOPIC_SYNC {
+            return false;
+        }
+        let Ok(Value::Object(scores)) = serde_json::from_slice(&message.payload) else {
+            return false;
+        };
+        let players = self.scores.entry(message.sender_npc_id.clone()).or_default();
+        for (player, value) in scores {
+            let (Some(score), Some(updated_at_ms)) = (value[0].as_f64(), value[1].as_i64()) else {
+                continue;
+            };
+            let incoming = Entry {
+                score: score as f32,
+                updated_at_ms,
+            };
+            match players.get(&player) {
+                Some(current) if current.updated_at_ms >= updated_at_ms => {}
+                _ => {
+                    players.insert(player, incoming);
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Behavior-tree condition: succeeds if a player in the latest WorldTick
+/// has a score with this NPC for which `check` returns true, e.g.
+/// `player_nearby(rep, |score| score <= -50.0)` for a guard.
+pub fn player_nearby(reputation: SharedReputation, check: fn(f32) -> bool) -> Node {
+    condition(move |bb| {
+        let reputation = reputation.lock().unwrap();
+        bb.nearby_players
+            .iter()
+            .any(|p| check(reputation.lookup(&bb.npc.npc_id, &p.player_uuid)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::behavior::{action, sequence, BehaviorTree};
+    use crate::npc_society::v1::{
+        action_directive::Action, CombatEvent, ContainerAccess, ItemStack, NpcSnapshot, PlayerSnapshot,
+        StopAction,
+    };
+
+    const PLAYER: &str = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+
+    fn guard() -> NpcId {
+        NpcId::new("guard").unwrap()
+    }
+
+    fn player() -> PlayerUuid {
+        PlayerUuid::new(PLAYER).unwrap()
+    }
+
+    fn punch(target: &str, killed: bool, at: i64) -> EventObservation {
+        EventObservation {
+            npc_id: "guard".to_string(),
+            timestamp_ms: at,
+            payload: Some(Payload::Combat(CombatEvent {
+                attacker_uuid: PLAYER.to_string(),
+                target_uuid: target.to_string(),
+                target_killed: killed,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    fn tick() -> WorldTick {
+        WorldTick {
+            npcs: vec![NpcSnapshot {
+                npc_id: "guard".to_string(),
+                entity_uuid: "guard-entity".to_string(),
+                ..Default::default()
+            }],
+            nearby_players: vec![PlayerSnapshot {
+                player_uuid: PLAYER.to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rules_and_sync() {
+        let mut rep = Reputation::new(ReputationRules {
+            sentiment: Some(|text| if text.contains("thanks") { 1.0 } else { 0.0 }),
+            ..ReputationRules::default()
+        });
+        rep.observe_tick(&tick());
+
+        rep.observe_event(&punch("cow", true, 1));
+        assert_eq!(rep.score(&guard(), &player()), -20.0);
+        rep.observe_event(&punch("guard-entity", false, 2));
+        assert_eq!(rep.score(&guard(), &player()), -45.0);
+        rep.observe_chat(&ChatObservation {
+            npc_id: "guard".to_string(),
+            player_uuid: PLAYER.to_string(),
+            message: "thanks!".to_string(),
+            timestamp_ms: 3,
+            ..Default::default()
+        });
+        assert_eq!(rep.score(&guard(), &player()), -42.0);
+        rep.observe_transaction(&TransactionObservation {
+            npc_id: "guard".to_string(),
+            player_uuid: PLAYER.to_string(),
+            direction: TransferDirection::PlayerToNpc as i32,
+            amount: 500,
+            success: true,
+            timestamp_ms: 4,
+            ..Default::default()
+        });
+        assert_eq!(rep.score(&guard(), &player()), -37.0);
+
+        // Another daemon with an older score takes ours; ours keeps ours
+        let mut other = Reputation::default();
+        other.adjust(&guard(), &player(), 10.0, 0);
+        assert!(other.on_message(&rep.sync_message("guard", 5)));
+        assert_eq!(other.score(&guard(), &player()), -37.0);
+        assert!(rep.on_message(&Reputation::default().sync_message("guard", 6)));
+        assert_eq!(rep.score(&guard(), &player()), -37.0);
+    }
+
+    #[test]
+    fn test_theft() {
+        let mut rep = Reputation::default();
+        let access = |access: ContainerAccess, allowed| ContainerAccessObservation {
+            npc_id: "guard".to_string(),
+            player_uuid: PLAYER.to_string(),
+            access: access as i32,
+            allowed,
+            items: vec![ItemStack {
+                item_type: "minecraft:bread".to_string(),
+                quantity: 1,
+                ..Default::default()
+            }],
+            timestamp_ms: 1,
+            ..Default::default()
+        };
+
+        // Taking with permission is not theft
+        rep.observe_container_access(&access(ContainerAccess::Took, true));
+        assert_eq!(rep.score(&guard(), &player()), 0.0);
+        rep.observe_container_access(&access(ContainerAccess::Took, false));
+        assert_eq!(rep.score(&guard(), &player()), -20.0);
+        rep.observe_container_access(&access(ContainerAccess::Broke, false));
+        assert_eq!(rep.score(&guard(), &player()), -40.0);
+    }
+
+    #[test]
+    fn test_condition_node() {
+        let rep: SharedReputation = Arc::default();
+        let mut tree = BehaviorTree::new(
+            "guard",
+            sequence(vec![
+                player_nearby(rep.clone(), |score| score <= -20.0),
+                action("confront", 5, |_| Some(Action::Stop(StopAction::default()))),
+            ]),
+        );
+
+        assert!(tree.on_world_tick(&tick()).is_empty());
+        rep.lock().unwrap().adjust(&guard(), &player(), -30.0, 0);
+        assert_eq!(tree.on_world_tick(&tick()).len(), 1);
+    }
+}
@@ -0,0 +1,328 @@
+//! Retry policies for failed ActionDirectives.
+//!
+//! [`RetryTracker`] sits between the connection and the rest of the daemon:
+//! track each directive as it is sent, and pass every `ActionResult`
+//! through [`RetryTracker::on_result`]. Failures the policy considers
+//! retryable come back as a [`RetryDecision::Retry`] with the directive to
+//! resend; everything else is handed on with the original directive_id, so
+//! callers waiting on that id (e.g. [`crate::behavior`]) never see the
+//! intermediate attempts.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::npc_society::v1::{
+    action_directive::Action, ActionDirective, ActionErrorCode, ActionResult,
+};
+
+/// How often and how fast to retry a failed directive
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total attempts including the first one (1 = never retry)
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles with every further retry
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay
+    pub max_backoff: Duration,
+    /// Whether a failed result is worth retrying
+    pub retryable: fn(&ActionResult) -> bool,
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry` (1-based)
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u32.checked_shl(retry.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, 500ms then 1s apart, retrying every failure except
+    /// `ACTION_ERROR_CODE_PRECONDITION` (e.g. a protected region),
+    /// `ACTION_ERROR_CODE_NOT_MOUNTED`, `ACTION_ERROR_CODE_DOOR_LOCKED`,
+    /// `ACTION_ERROR_CODE_CONTAINER_LOCKED` and
+    /// `ACTION_ERROR_CODE_CHUNK_UNLOADED` (the directive asked to fail
+    /// rather than wait for the chunk)
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(5),
+            retryable: |r| {
+                !matches!(
+                    r.error_code(),
+                    ActionErrorCode::Precondition
+                        | ActionErrorCode::NotMounted
+                        | ActionErrorCode::DoorLocked
+                        | ActionErrorCode::ContainerLocked
+                        | ActionErrorCode::ChunkUnloaded
+                )
+            },
+        }
+    }
+}
+
+/// What to do with an ActionResult
+#[derive(Debug, Clone, PartialEq)]
+pub enum RetryDecision {
+    /// The directive is not tracked; handle the result as usual
+    Untracked,
+    /// Final outcome (success or non-retryable failure), with the original directive_id
+    Done(ActionResult),
+    /// Resend `directive` after `after`
+    Retry {
+        directive: ActionDirective,
+        after: Duration,
+    },
+    /// Every attempt failed; `result` is the last failure, with the original directive_id
+    Exhausted { result: ActionResult, attempts: u32 },
+}
+
+#[derive(Debug)]
+struct Attempt {
+    original: ActionDirective,
+    attempt: u32,
+    policy: RetryPolicy,
+}
+
+/// Tracks in-flight directives and their retry policies.
+#[derive(Debug, Default)]
+pub struct RetryTracker {
+    default_policy: Option<RetryPolicy>,
+    policies: HashMap<&'static str, RetryPolicy>,
+    /// Keyed by the directive_id of the current attempt
+    in_flight: HashMap<String, Attempt>,
+}
+
+impl RetryTracker {
+    /// Create a tracker; `default_policy` applies to action types without
+    /// their own policy (None = don't retry them).
+    pub fn new(default_policy: Option<RetryPolicy>) -> Self {
+        Self {
+            default_policy,
+            ..Self::default()
+        }
+    }
+
+    /// Use `policy` for every directive of `action_kind` (see [`action_kind`])
+    pub fn set_policy(&mut self, action_kind: &'static str, policy: RetryPolicy) {
+        self.policies.insert(action_kind, policy);
+    }
+
+    /// Track a directive with the policy for its action type, if any
+    pub fn track(&mut self, directive: &ActionDirective) {
+        let kind = directive.action.as_ref().map(action_kind).unwrap_or_default();
+        if let Some(policy) = self.policies.get(kind).copied().or(self.default_policy) {
+            self.track_with(directive, policy);
+        }
+    }
+
+    /// Track a directive with an explicit policy
+    pub fn track_with(&mut self, directive: &ActionDirective, policy: RetryPolicy) {
+        self.in_flight.insert(
+            directive.directive_id.clone(),
+            Attempt {
+                original: directive.clone(),
+                attempt: 1,
+                policy,
+            },
+        );
+    }
+
+    /// Stop tracking a directive that will get no result (e.g. the plugin
+    /// rejected it); returns the original directive
+    pub fn forget(&mut self, directive_id: &str) -> Option<ActionDirective> {
+        self.in_flight.remove(directive_id).map(|a| a.original)
+    }
+
+    /// Number of directives awaiting a result
+    pub fn len(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Whether no directives are awaiting a result
+    pub fn is_empty(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+
+    /// Decide what to do with `result`.
+    pub fn on_result(&mut self, mut result: ActionResult) -> RetryDecision {
+        let Some(mut attempt) = self.in_flight.remove(&result.directive_id) else {
+            return RetryDecision::Untracked;
+        };
+        result.directive_id = attempt.original.directive_id.clone();
+
+        if result.success || !(attempt.policy.retryable)(&result) {
+            return RetryDecision::Done(result);
+        }
+        if attempt.attempt >= attempt.policy.max_attempts {
+            return RetryDecision::Exhausted {
+                result,
+                attempts: attempt.attempt,
+            };
+        }
+
+        let after = attempt.policy.backoff(attempt.attempt);
+        attempt.attempt += 1;
+        let directive = ActionDirective {
+            directive_id: format!("{}-retry{}", attempt.original.directive_id, attempt.attempt - 1),
+            ..attempt.original.clone()
+        };
+        self.in_flight.insert(directive.directive_id.clone(), attempt);
+        RetryDecision::Retry { directive, after }
+    }
+}
+
+/// Stable name of an action type, e.g. for [`RetryTracker::set_policy`] or logging
+pub fn action_kind(action: &Action) -> &'static str {
+    match action {
+        Action::Move(_) => "move",
+        Action::BreakBlock(_) => "break_block",
+        Action::PlaceBlock(_) => "place_block",
+        Action::Attack(_) => "attack",
+        Action::Interact(_) => "interact",
+        Action::Inventory(_) => "inventory",
+        Action::Look(_) => "look",
+        Action::Stop(_) => "stop",
+        Action::ScanBlocks(_) => "scan_blocks",
+        Action::RaycastLook(_) => "raycast_look",
+        Action::DepositToChest(_) => "deposit_to_chest",
+        Action::RegionSnapshot(_) => "region_snapshot",
+        Action::WatchBlocks(_) => "watch_blocks",
+        Action::UnwatchBlocks(_) => "unwatch_blocks",
+        Action::Smelt(_) => "smelt",
+        Action::Brew(_) => "brew",
+        Action::BreedAnimals(_) => "breed_animals",
+        Action::TameAnimal(_) => "tame_animal",
+        Action::Shear(_) => "shear",
+        Action::Milk(_) => "milk",
+        Action::RideAndDrive(_) => "ride_and_drive",
+        Action::EquipArmor(_) => "equip_armor",
+        Action::EnchantItem(_) => "enchant_item",
+        Action::RepairItem(_) => "repair_item",
+        Action::ConsumeItem(_) => "consume_item",
+        Action::Craft(_) => "craft",
+        Action::CheckLineOfSight(_) => "check_line_of_sight",
+        Action::GiveItem(_) => "give_item",
+        Action::WriteSign(_) => "write_sign",
+        Action::WriteBook(_) => "write_book",
+        Action::MarkMap(_) => "mark_map",
+        Action::ToggleLever(_) => "toggle_lever",
+        Action::PressButton(_) => "press_button",
+        Action::SetMechanism(_) => "set_mechanism",
+        Action::ReadRedstonePower(_) => "read_redstone_power",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::MoveAction;
+
+    fn directive() -> ActionDirective {
+        ActionDirective {
+            directive_id: "dir-1".to_string(),
+            npc_id: "npc".to_string(),
+            priority: 1,
+            action: Some(Action::Move(MoveAction::default())),
+            ..Default::default()
+        }
+    }
+
+    fn result(directive_id: &str, success: bool, error: &str) -> ActionResult {
+        ActionResult {
+            directive_id: directive_id.to_string(),
+            npc_id: "npc".to_string(),
+            success,
+            error_message: error.to_string(),
+            ..ActionResult::default()
+        }
+    }
+
+    #[test]
+    fn test_retries_until_exhausted() {
+        let mut tracker = RetryTracker::new(Some(RetryPolicy::default()));
+        tracker.track(&directive());
+
+        let RetryDecision::Retry { directive: retry, after } = tracker.on_result(result("dir-1", false, "stuck"))
+        else {
+            panic!("expected a retry");
+        };
+        assert_eq!(retry.directive_id, "dir-1-retry1");
+        assert_eq!(after, Duration::from_millis(500));
+
+        let RetryDecision::Retry { directive: retry, after } = tracker.on_result(result(&retry.directive_id, false, "stuck"))
+        else {
+            panic!("expected a retry");
+        };
+        assert_eq!(after, Duration::from_secs(1));
+
+        match tracker.on_result(result(&retry.directive_id, false, "stuck")) {
+            RetryDecision::Exhausted { result, attempts } => {
+                assert_eq!(result.directive_id, "dir-1");
+                assert_eq!(attempts, 3);
+            }
+            other => panic!("expected Exhausted, got {other:?}"),
+        }
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn test_success_and_non_retryable_failures_are_done() {
+        let mut tracker = RetryTracker::default();
+        tracker.track(&directive());
+        // No policy for moves
+        assert!(tracker.is_empty());
+
+        tracker.set_policy(
+            "move",
+            RetryPolicy {
+                retryable: |r| r.error_message != "no path",
+                ..RetryPolicy::default()
+            },
+        );
+        tracker.track(&directive());
+        assert!(matches!(
+            tracker.on_result(result("dir-1", false, "no path")),
+            RetryDecision::Done(r) if r.directive_id == "dir-1"
+        ));
+
+        tracker.track(&directive());
+        let RetryDecision::Retry { directive: retry, .. } = tracker.on_result(result("dir-1", false, "stuck")) else {
+            panic!("expected a retry");
+        };
+        assert!(matches!(
+            tracker.on_result(result(&retry.directive_id, true, "")),
+            RetryDecision::Done(r) if r.directive_id == "dir-1" && r.success
+        ));
+        assert_eq!(tracker.on_result(result("other", true, "")), RetryDecision::Untracked);
+
+        // A rejected retry is forgotten under its own id
+        tracker.track(&directive());
+        let RetryDecision::Retry { directive: retry, .. } = tracker.on_result(result("dir-1", false, "stuck")) else {
+            panic!("expected a retry");
+        };
+        assert_eq!(tracker.forget(&retry.directive_id).unwrap().directive_id, "dir-1");
+        assert!(tracker.is_empty());
+
+        // The default policy gives up right away on failures a retry cannot fix
+        tracker.set_policy("move", RetryPolicy::default());
+        for code in [
+            ActionErrorCode::Precondition,
+            ActionErrorCode::NotMounted,
+            ActionErrorCode::DoorLocked,
+            ActionErrorCode::ContainerLocked,
+            ActionErrorCode::ChunkUnloaded,
+        ] {
+            tracker.track(&directive());
+            let failed = ActionResult {
+                error_code: code as i32,
+                ..result("dir-1", false, "cannot be retried")
+            };
+            assert!(
+                matches!(tracker.on_result(failed), RetryDecision::Done(r) if !r.success),
+                "{code:?} was retried"
+            );
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Voice routing for `SpeakDirective` (v1.2+).
+//!
+//! `SpeakDirective.delivery` picks one of Simple Voice Chat's channels:
+//! proximity (`SPATIAL`, out to `range` blocks), a whisper (`DIRECT`), a
+//! voice chat group (`GROUP`) or the whole server (`GLOBAL`). Plugins list
+//! what they can route in `Hello.supported_deliveries`; [`fit`] rewrites a
+//! directive the plugin could not route into the closest mode it can.
+
+use crate::npc_society::v1::{Hello, SpeakDirective, SpeechDelivery};
+
+/// Delivery modes the plugin can route; SPATIAL and DIRECT if it listed none
+pub fn supported(hello: &Hello) -> Vec<SpeechDelivery> {
+    if hello.supported_deliveries.is_empty() {
+        return vec![SpeechDelivery::Spatial, SpeechDelivery::Direct];
+    }
+    hello.supported_deliveries().collect()
+}
+
+/// Make `speak` routable by the plugin that sent `hello`. An unsupported
+/// group or global delivery falls back to a whisper when the directive
+/// names its listeners, so private speech stays private, and to spatial
+/// audio otherwise. Returns the delivery that was replaced, if any.
+pub fn fit(speak: &mut SpeakDirective, hello: &Hello) -> Option<SpeechDelivery> {
+    let delivery = match speak.delivery() {
+        SpeechDelivery::Unspecified => SpeechDelivery::Spatial,
+        delivery => delivery,
+    };
+    let supported = supported(hello);
+    if supported.contains(&delivery) {
+        return None;
+    }
+    let fallback = if speak.target_player_uuids.is_empty() {
+        SpeechDelivery::Spatial
+    } else {
+        SpeechDelivery::Direct
+    };
+    speak.set_delivery(fallback);
+    speak.group.clear();
+    Some(delivery)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group_speech(targets: &[&str]) -> SpeakDirective {
+        SpeakDirective {
+            npc_id: "herald".to_string(),
+            text: "The gates close at dusk".to_string(),
+            delivery: SpeechDelivery::Group as i32,
+            group: "guards".to_string(),
+            target_player_uuids: targets.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fit() {
+        let plain = Hello::default();
+        let mut speak = group_speech(&[]);
+        assert_eq!(fit(&mut speak, &plain), Some(SpeechDelivery::Group));
+        assert_eq!((speak.delivery(), speak.group.as_str()), (SpeechDelivery::Spatial, ""));
+
+        let mut whisper = group_speech(&["p1"]);
+        fit(&mut whisper, &plain);
+        assert_eq!(whisper.delivery(), SpeechDelivery::Direct);
+
+        let voice_chat = Hello {
+            supported_deliveries: vec![
+                SpeechDelivery::Spatial as i32,
+                SpeechDelivery::Direct as i32,
+                SpeechDelivery::Group as i32,
+            ],
+            ..Default::default()
+        };
+        let mut speak = group_speech(&[]);
+        assert_eq!(fit(&mut speak, &voice_chat), None);
+        assert_eq!(speak.group, "guards");
+        assert_eq!(fit(&mut SpeakDirective::default(), &plain), None);
+    }
+}
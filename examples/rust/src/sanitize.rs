@@ -0,0 +1,403 @@
+//! Cleaning up NPC text before it reaches players.
+//!
+//! Whatever a daemon puts into `SpeakDirective.text` or
+//! `ChatDirective.text` ends up in players' chat and subtitles as it is,
+//! and text an LLM wrote can be anything: a thousand characters, newlines
+//! and control characters, `§`/`&` color codes or MiniMessage tags that
+//! the plugin renders as formatting (or click events). An [`OutputFilter`]
+//! cleans every such text in order:
+//!
+//! 1. control characters: newlines and tabs become spaces, the rest
+//!    (and bidirectional overrides) are removed, runs of whitespace
+//!    collapse to one space
+//! 2. profanity: words of the [`ProfanityFilter`] are masked, or the
+//!    message is rejected
+//! 3. chat formatting: the syntax the plugin renders ([`ChatFormat`]) is
+//!    escaped, so the text shows literally
+//! 4. length: text over `max_chars` is cut at a word and ends in "…", or
+//!    the message is rejected
+//!
+//! Cleaning is idempotent, so a text may pass the filter twice: once
+//! before TTS, so the audio says what the subtitle shows, and once on
+//! send. [`Outbound::with_filter`](crate::outbound::Outbound::with_filter)
+//! runs it on every message queued. A message the filter rejects is not
+//! sent; each rejection is logged, counted and handed to the observers
+//! registered with [`OutputFilter::on_reject`].
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tracing::warn;
+
+use crate::chat::ProfanityFilter;
+use crate::npc_society::v1::server_message::Message as ServerMsg;
+use crate::npc_society::v1::{ChatDirective, ServerMessage, SpeakDirective};
+
+/// Marks the end of a text that was cut short
+const ELLIPSIS: char = '…';
+
+/// Formatting syntax the plugin interprets in NPC text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChatFormat {
+    /// Text is shown as it is; only `§` codes are removed
+    Plain,
+    /// `§` and `&` color codes (`&c`, `&l`, `&#ff0000`)
+    #[default]
+    Legacy,
+    /// MiniMessage tags (`<red>`, `<click:run_command:...>`), plus `§`
+    MiniMessage,
+}
+
+impl FromStr for ChatFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "plain" => Ok(Self::Plain),
+            "legacy" => Ok(Self::Legacy),
+            "minimessage" => Ok(Self::MiniMessage),
+            _ => Err("expected plain, legacy or minimessage".to_string()),
+        }
+    }
+}
+
+/// What the filter does to outgoing text
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputConfig {
+    /// Longest text in characters
+    pub max_chars: usize,
+    /// Cut longer text at a word; false rejects it
+    pub truncate: bool,
+    /// Remove control characters and collapse whitespace
+    pub strip_control: bool,
+    /// Formatting syntax to escape
+    pub format: ChatFormat,
+    /// Lowercase words to mask (empty = no profanity filter)
+    pub profanity: Vec<String>,
+    /// Reject text with a listed word instead of masking it
+    pub reject_profanity: bool,
+}
+
+impl Default for OutputConfig {
+    /// 256 characters, truncated; control characters and legacy color
+    /// codes removed; no profanity list
+    fn default() -> Self {
+        Self {
+            max_chars: 256,
+            truncate: true,
+            strip_control: true,
+            format: ChatFormat::Legacy,
+            profanity: Vec::new(),
+            reject_profanity: false,
+        }
+    }
+}
+
+/// A directive the filter refused to send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputRejected {
+    /// "SpeakDirective" or "ChatDirective"
+    pub kind: &'static str,
+    /// Speaking NPC (empty for chat from the server)
+    pub npc_id: String,
+    /// Directive id of a SpeakDirective
+    pub directive_id: String,
+    /// Why
+    pub reason: String,
+}
+
+impl fmt::Display for OutputRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} rejected: {}", self.kind, self.reason)
+    }
+}
+
+impl std::error::Error for OutputRejected {}
+
+type Observer = Box<dyn Fn(&OutputRejected) + Send + Sync>;
+
+/// Cleans the text of SpeakDirectives and ChatDirectives
+pub struct OutputFilter {
+    config: OutputConfig,
+    profanity: Option<ProfanityFilter>,
+    rejected: AtomicU64,
+    observers: Vec<Observer>,
+}
+
+impl fmt::Debug for OutputFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutputFilter")
+            .field("config", &self.config)
+            .field("rejected", &self.rejected())
+            .finish()
+    }
+}
+
+impl Default for OutputFilter {
+    fn default() -> Self {
+        Self::new(OutputConfig::default())
+    }
+}
+
+impl OutputFilter {
+    /// A filter doing what `config` says
+    pub fn new(config: OutputConfig) -> Self {
+        let profanity = (!config.profanity.is_empty()).then(|| ProfanityFilter {
+            drop: config.reject_profanity,
+            ..ProfanityFilter::new(config.profanity.iter().map(String::as_str))
+        });
+        Self {
+            config,
+            profanity,
+            rejected: AtomicU64::new(0),
+            observers: Vec::new(),
+        }
+    }
+
+    /// Also call `observer` for every rejection; it runs on the sending
+    /// task, so keep it quick
+    pub fn on_reject(mut self, observer: impl Fn(&OutputRejected) + Send + Sync + 'static) -> Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    /// What the filter does
+    pub fn config(&self) -> &OutputConfig {
+        &self.config
+    }
+
+    /// Directives rejected so far
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// `text` cleaned, or why it cannot be sent. Empty text stays empty.
+    pub fn clean(&self, text: &str) -> Result<String, String> {
+        let mut text = if self.config.strip_control {
+            strip_control(text)
+        } else {
+            text.to_string()
+        };
+        if let Some(profanity) = &self.profanity {
+            text = profanity.filter(&text)?;
+        }
+        text = escape(&text, self.config.format);
+        if text.chars().count() > self.config.max_chars {
+            if !self.config.truncate {
+                return Err(format!("longer than {} characters", self.config.max_chars));
+            }
+            text = truncate(&text, self.config.max_chars);
+        }
+        Ok(text)
+    }
+
+    /// Clean `speak.text`
+    pub fn filter_speak(&self, speak: &mut SpeakDirective) -> Result<(), OutputRejected> {
+        match self.clean_nonempty(&speak.text) {
+            Ok(text) => {
+                speak.text = text;
+                Ok(())
+            }
+            Err(reason) => Err(self.reject(OutputRejected {
+                kind: "SpeakDirective",
+                npc_id: speak.npc_id.clone(),
+                directive_id: speak.directive_id.clone(),
+                reason,
+            })),
+        }
+    }
+
+    /// Clean `chat.text`
+    pub fn filter_chat(&self, chat: &mut ChatDirective) -> Result<(), OutputRejected> {
+        match self.clean_nonempty(&chat.text) {
+            Ok(text) => {
+                chat.text = text;
+                Ok(())
+            }
+            Err(reason) => Err(self.reject(OutputRejected {
+                kind: "ChatDirective",
+                npc_id: chat.npc_id.clone(),
+                directive_id: String::new(),
+                reason,
+            })),
+        }
+    }
+
+    /// Clean the text of `msg` if it has any player-visible text
+    pub fn filter(&self, msg: &mut ServerMessage) -> Result<(), OutputRejected> {
+        match &mut msg.message {
+            Some(ServerMsg::SpeakDirective(speak)) => self.filter_speak(speak),
+            Some(ServerMsg::Chat(chat)) => self.filter_chat(chat),
+            _ => Ok(()),
+        }
+    }
+
+    /// [`OutputFilter::clean`], rejecting text that had something to say
+    /// and has nothing left
+    fn clean_nonempty(&self, text: &str) -> Result<String, String> {
+        let cleaned = self.clean(text)?;
+        if cleaned.is_empty() && !text.is_empty() {
+            return Err("nothing left after cleaning".to_string());
+        }
+        Ok(cleaned)
+    }
+
+    fn reject(&self, rejected: OutputRejected) -> OutputRejected {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            npc_id = %rejected.npc_id,
+            directive_id = %rejected.directive_id,
+            reason = %rejected.reason,
+            "{} rejected by the output filter",
+            rejected.kind
+        );
+        for observer in &self.observers {
+            observer(&rejected);
+        }
+        rejected
+    }
+}
+
+/// Bidirectional overrides and isolates, which reorder the rest of a line
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+/// `text` on one line without control characters
+fn strip_control(text: &str) -> String {
+    let mut clean = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !clean.is_empty() && !clean.ends_with(' ') {
+                clean.push(' ');
+            }
+        } else if !c.is_control() && !is_bidi_control(c) {
+            clean.push(c);
+        }
+    }
+    clean.truncate(clean.trim_end().len());
+    clean
+}
+
+/// Whether `c` after `&` makes a legacy color or style code
+fn is_legacy_code(c: char) -> bool {
+    matches!(c.to_ascii_lowercase(), '0'..='9' | 'a'..='f' | 'k'..='o' | 'r' | 'x' | '#')
+}
+
+/// `text` with `format`'s syntax escaped. `§` is never typed by players,
+/// so its codes are removed whatever the format.
+fn escape(text: &str, format: ChatFormat) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (format, c) {
+            (_, '§') => {
+                chars.next();
+            }
+            // A zero-width space keeps `&c` from being read as a code
+            (ChatFormat::Legacy, '&') if chars.peek().is_some_and(|&n| is_legacy_code(n)) => {
+                escaped.push_str("&\u{200B}");
+            }
+            (ChatFormat::MiniMessage, '<') if !escaped.ends_with('\\') => {
+                escaped.push_str("\\<");
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The first `max_chars` characters of `text`, cut at the last space if
+/// there is one, ending in "…"
+fn truncate(text: &str, max_chars: usize) -> String {
+    let keep = max_chars.saturating_sub(1);
+    let end = text.char_indices().nth(keep).map_or(text.len(), |(i, _)| i);
+    let cut = &text[..end];
+    let cut = match cut.rfind(' ') {
+        Some(space) if space > 0 => &cut[..space],
+        _ => cut,
+    };
+    let mut truncated = cut.trim_end().to_string();
+    truncated.push(ELLIPSIS);
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_clean() {
+        let filter = OutputFilter::default();
+        assert_eq!(
+            filter.clean("Hi\n\n\tthere\u{7}\u{202E}!  ").unwrap(),
+            "Hi there!"
+        );
+        assert_eq!(
+            filter.clean("§4&cRed & bold&l").unwrap(),
+            "&\u{200B}cRed & bold&\u{200B}l"
+        );
+        let mini = OutputFilter::new(OutputConfig {
+            format: ChatFormat::MiniMessage,
+            ..Default::default()
+        });
+        assert_eq!(
+            mini.clean("<click:run_command:/op me>x").unwrap(),
+            "\\<click:run_command:/op me>x"
+        );
+
+        let long = "word ".repeat(100);
+        let cut = filter.clean(&long).unwrap();
+        assert!(cut.chars().count() <= 256);
+        assert!(cut.ends_with("word…"));
+
+        // A second pass changes nothing
+        for text in [long.as_str(), "&c <b> \\<i> a\u{0}b", "§§"] {
+            for filter in [&filter, &mini] {
+                let once = filter.clean(text).unwrap();
+                assert_eq!(filter.clean(&once).unwrap(), once);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejections() {
+        let seen = Arc::new(AtomicU64::new(0));
+        let counter = seen.clone();
+        let filter = OutputFilter::new(OutputConfig {
+            max_chars: 10,
+            truncate: false,
+            profanity: vec!["heck".to_string()],
+            ..Default::default()
+        })
+        .on_reject(move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+
+        let mut speak = SpeakDirective {
+            npc_id: "miner".to_string(),
+            directive_id: "d1".to_string(),
+            text: "What the heck".to_string(),
+            ..Default::default()
+        };
+        assert!(filter.filter_speak(&mut speak).is_err());
+        speak.text = "Oh, heck!".to_string();
+        filter.filter_speak(&mut speak).unwrap();
+        assert_eq!(speak.text, "Oh, ****!");
+
+        let mut chat = ServerMessage::from(ChatDirective {
+            text: "\u{1b}\u{7}".to_string(),
+            ..Default::default()
+        });
+        let rejected = filter.filter(&mut chat).unwrap_err();
+        assert_eq!(rejected.kind, "ChatDirective");
+        assert_eq!(filter.rejected(), 2);
+        assert_eq!(seen.load(Ordering::Relaxed), 2);
+
+        // Audio-only speech has no text to clean
+        speak.text.clear();
+        assert!(filter.filter_speak(&mut speak).is_ok());
+    }
+}
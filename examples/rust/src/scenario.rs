@@ -0,0 +1,654 @@
+//! Declarative end-to-end tests on the simulator (feature `scenario`).
+//!
+//! A [`Scenario`] is a TOML file: the NPCs and players in the world, what
+//! the players do and when, and what the daemon is expected to send back.
+//! [`Scenario::run`] connects a [`NpcSocietyHandler`] to a [`Simulation`]
+//! of that world, plays the steps on the virtual clock, feeds directives
+//! back so their results arrive, and checks the expectations against
+//! everything the handler sent:
+//!
+//! ```toml
+//! name = "miner greets a visitor"
+//! ticks = 60
+//!
+//! [[npc]]
+//! npc_id = "miner_1"
+//! at = { x = 0, y = 64, z = 0 }
+//!
+//! [[player]]
+//! name = "Steve"
+//! at = { x = 3, y = 64, z = 0 }
+//!
+//! [[step]]
+//! tick = 5
+//! player = "Steve"
+//! chat = "hello there"
+//!
+//! [[expect]]
+//! message = "SpeakDirective"
+//! npc = "miner_1"
+//! contains = "Steve"
+//! before_tick = 10
+//!
+//! [[expect]]
+//! message = "QuarantineNpc"
+//! at_most = 0
+//! ```
+//!
+//! A step does exactly one of `chat` (heard by `npc`, or every NPC within
+//! [`HEARING_BLOCKS`]), `move_to` or `talk_frames` (voice frames to `npc`,
+//! one per tick). `message` is a ServerMessage oneof variant as
+//! [`ServerEvent::VARIANTS`] names it; the other keys narrow it down and
+//! an expectation holds `at_least` once (1 unless `at_most` is 0):
+//!
+//! ```ignore
+//! let scenario = Scenario::load("scenarios/greeting.toml")?;
+//! scenario.run(&MyHandler::default()).assert();
+//! ```
+//!
+//! Runs are as deterministic as the simulator: the same file gives the same
+//! outcome on every machine.
+
+use std::fmt;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::connection::ConnectionContext;
+use crate::events::{self, ClientEvent, NpcSocietyHandler, ServerEvent};
+use crate::geom::distance;
+use crate::golden;
+use crate::npc_society::v1::{
+    server_message::Message as ServerMsg, ChatObservation, NpcSnapshot, PlayerSnapshot, Position,
+    ServerMessage,
+};
+use crate::outbound::{self, QueueConfig};
+use crate::sim::{SimConfig, Simulation};
+
+/// How far away an NPC hears a chat that names no NPC
+pub const HEARING_BLOCKS: f64 = 16.0;
+
+/// Why a scenario file is unusable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScenarioError(pub String);
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid scenario: {}", self.0)
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+/// A world, a script and what the daemon should do
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    /// Shown when the scenario fails
+    pub name: String,
+    /// Seeds action durations and outcomes
+    #[serde(default = "default_seed")]
+    pub seed: u64,
+    /// Ticks to run
+    pub ticks: i64,
+    /// Share of actions that fail, 0.0-1.0
+    #[serde(default)]
+    pub failure_rate: f64,
+    /// The NPCs; no others are generated
+    #[serde(default, rename = "npc")]
+    pub npcs: Vec<NpcSpec>,
+    /// The players; no others are generated
+    #[serde(default, rename = "player")]
+    pub players: Vec<PlayerSpec>,
+    /// What the players do, in any order
+    #[serde(default, rename = "step")]
+    pub steps: Vec<Step>,
+    /// What the daemon has to send
+    #[serde(default, rename = "expect")]
+    pub expectations: Vec<Expectation>,
+}
+
+fn default_seed() -> u64 {
+    1
+}
+
+/// A position in TOML; the world defaults to `world`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Place {
+    #[serde(default = "default_world")]
+    pub world: String,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+fn default_world() -> String {
+    "world".to_string()
+}
+
+impl From<&Place> for Position {
+    fn from(place: &Place) -> Self {
+        Position {
+            world: place.world.clone(),
+            x: place.x,
+            y: place.y,
+            z: place.z,
+            ..Default::default()
+        }
+    }
+}
+
+/// An `[[npc]]`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NpcSpec {
+    pub npc_id: String,
+    pub at: Place,
+}
+
+/// A `[[player]]`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlayerSpec {
+    /// Display name, and how steps refer to the player
+    pub name: String,
+    /// player_uuid; generated from the player's place in the file if unset
+    pub uuid: Option<String>,
+    pub at: Place,
+    #[serde(default)]
+    pub op: bool,
+    /// Permission nodes reported in PlayerSnapshot.permissions
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    /// Client language, e.g. `de_de`
+    #[serde(default)]
+    pub locale: String,
+}
+
+/// A `[[step]]`: one thing a player does at a tick
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    /// Tick it happens at, from 1
+    pub tick: i64,
+    /// `name` of the player
+    pub player: String,
+    /// NPC that hears the chat or voice
+    pub npc: Option<String>,
+    /// Say this in chat
+    pub chat: Option<String>,
+    /// Walk (teleport) here
+    pub move_to: Option<Place>,
+    /// Talk to `npc` for this many ticks
+    pub talk_frames: Option<u64>,
+}
+
+/// An `[[expect]]`: messages the daemon has to send
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expectation {
+    /// ServerMessage oneof variant, e.g. `SpeakDirective` or `Chat`
+    pub message: String,
+    /// The message names this npc_id
+    pub npc: Option<String>,
+    /// The message, as Debug prints it, contains this text
+    pub contains: Option<String>,
+    /// Sent at this tick or later
+    pub after_tick: Option<i64>,
+    /// Sent before this tick
+    pub before_tick: Option<i64>,
+    /// Fewest matching messages (default 1, or 0 with `at_most = 0`)
+    pub at_least: Option<usize>,
+    /// Most matching messages
+    pub at_most: Option<usize>,
+}
+
+impl Expectation {
+    fn matches(&self, sent: &Sent) -> bool {
+        if ServerEvent::type_of(&sent.message) != Some(self.message.as_str()) {
+            return false;
+        }
+        if self.after_tick.is_some_and(|after| sent.tick < after)
+            || self.before_tick.is_some_and(|before| sent.tick >= before)
+        {
+            return false;
+        }
+        let text = format!("{:?}", sent.message);
+        self.npc
+            .as_ref()
+            .is_none_or(|npc| text.contains(&format!("npc_id: {:?}", npc)))
+            && self
+                .contains
+                .as_ref()
+                .is_none_or(|needle| text.contains(needle.as_str()))
+    }
+
+    fn bounds(&self) -> (usize, Option<usize>) {
+        let at_least = self
+            .at_least
+            .unwrap_or(if self.at_most == Some(0) { 0 } else { 1 });
+        (at_least, self.at_most)
+    }
+
+    fn describe(&self) -> String {
+        let mut parts = vec![self.message.clone()];
+        if let Some(npc) = &self.npc {
+            parts.push(format!("for {}", npc));
+        }
+        if let Some(needle) = &self.contains {
+            parts.push(format!("containing {:?}", needle));
+        }
+        match (self.after_tick, self.before_tick) {
+            (Some(after), Some(before)) => parts.push(format!("in ticks {}..{}", after, before)),
+            (Some(after), None) => parts.push(format!("from tick {}", after)),
+            (None, Some(before)) => parts.push(format!("before tick {}", before)),
+            (None, None) => {}
+        }
+        parts.join(" ")
+    }
+}
+
+/// A message the handler sent, with the tick it was sent at
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sent {
+    pub tick: i64,
+    pub message: ServerMessage,
+}
+
+/// What a run sent and which expectations it broke
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub name: String,
+    pub sent: Vec<Sent>,
+    /// One line per broken expectation
+    pub failures: Vec<String>,
+}
+
+impl Outcome {
+    /// Whether every expectation held
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Panic with the broken expectations and everything sent, as
+    /// [`golden::render`] shows it, unless the run passed
+    pub fn assert(&self) {
+        if self.passed() {
+            return;
+        }
+        let messages: Vec<ServerMessage> = self.sent.iter().map(|s| s.message.clone()).collect();
+        panic!(
+            "scenario {:?} failed:\n  {}\n\nsent:\n{}",
+            self.name,
+            self.failures.join("\n  "),
+            golden::render(&messages)
+        );
+    }
+}
+
+impl Scenario {
+    /// Parse and check a scenario
+    pub fn from_toml(source: &str) -> Result<Self, ScenarioError> {
+        let scenario: Scenario =
+            toml::from_str(source).map_err(|e| ScenarioError(e.to_string()))?;
+        scenario.check()?;
+        Ok(scenario)
+    }
+
+    /// Read a scenario file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ScenarioError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| ScenarioError(format!("{}: {}", path.display(), e)))?;
+        Self::from_toml(&source)
+    }
+
+    fn check(&self) -> Result<(), ScenarioError> {
+        let error = |message: String| Err(ScenarioError(format!("{}: {}", self.name, message)));
+        if self.ticks < 1 {
+            return error("ticks must be at least 1".to_string());
+        }
+        for step in &self.steps {
+            let at = format!("step at tick {}", step.tick);
+            if !(1..=self.ticks).contains(&step.tick) {
+                return error(format!("{}: tick outside 1..={}", at, self.ticks));
+            }
+            if !self.players.iter().any(|p| p.name == step.player) {
+                return error(format!("{}: no player {:?}", at, step.player));
+            }
+            if let Some(npc) = &step.npc {
+                if !self.npcs.iter().any(|n| &n.npc_id == npc) {
+                    return error(format!("{}: no npc {:?}", at, npc));
+                }
+            }
+            let actions = [
+                step.chat.is_some(),
+                step.move_to.is_some(),
+                step.talk_frames.is_some(),
+            ];
+            if actions.iter().filter(|a| **a).count() != 1 {
+                return error(format!(
+                    "{}: need exactly one of chat, move_to, talk_frames",
+                    at
+                ));
+            }
+            if step.talk_frames.is_some() && step.npc.is_none() {
+                return error(format!("{}: talk_frames needs an npc", at));
+            }
+        }
+        for expectation in &self.expectations {
+            if !ServerEvent::VARIANTS
+                .iter()
+                .any(|(_, field)| *field == expectation.message)
+            {
+                return error(format!("unknown message {:?}", expectation.message));
+            }
+            let (at_least, at_most) = expectation.bounds();
+            if at_most.is_some_and(|most| most < at_least) {
+                return error(format!(
+                    "{}: at_most below at_least",
+                    expectation.describe()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn player_uuid(&self, index: usize) -> String {
+        self.players[index]
+            .uuid
+            .clone()
+            .unwrap_or_else(|| format!("00000000-0000-4000-a000-{:012}", index))
+    }
+
+    /// The world at tick 0: only the scenario's NPCs and players
+    pub fn simulation(&self) -> Simulation {
+        let mut simulation = Simulation::new(SimConfig {
+            seed: self.seed,
+            npcs: 0,
+            players: 0,
+            failure_rate: self.failure_rate,
+            ..Default::default()
+        });
+        for npc in &self.npcs {
+            simulation.add_npc(NpcSnapshot {
+                npc_id: npc.npc_id.clone(),
+                entity_uuid: format!("scenario-{}", npc.npc_id),
+                position: Some((&npc.at).into()),
+                health_norm: 1.0,
+                hunger_norm: 1.0,
+                ..Default::default()
+            });
+        }
+        for (i, player) in self.players.iter().enumerate() {
+            simulation.add_player(PlayerSnapshot {
+                player_uuid: self.player_uuid(i),
+                player_name: player.name.clone(),
+                position: Some((&player.at).into()),
+                health_norm: 1.0,
+                game_mode: "survival".to_string(),
+                op: player.op,
+                permissions: player.permissions.clone(),
+                locale: player.locale.clone(),
+                ..Default::default()
+            });
+        }
+        simulation
+    }
+
+    /// Run the scenario against `handler` and check what it sent
+    pub fn run<H: NpcSocietyHandler + ?Sized>(&self, handler: &H) -> Outcome {
+        let mut simulation = self.simulation();
+        let (tx, mut rx) = outbound::queue(QueueConfig::default());
+        let cx = ConnectionContext::new("scenario", 0);
+        let mut sent = Vec::new();
+        let mut session = vec![simulation.hello()];
+        for tick in 0..=self.ticks {
+            for msg in session {
+                if let Ok(event) = ClientEvent::try_from(msg) {
+                    events::dispatch(handler, event, &cx, &tx);
+                }
+            }
+            while let Some(message) = rx.try_next() {
+                if let Some(ServerMsg::ActionDirective(directive)) = &message.message {
+                    simulation.send(directive.clone());
+                }
+                sent.push(Sent { tick, message });
+            }
+            if tick == self.ticks {
+                break;
+            }
+            for step in self.steps.iter().filter(|s| s.tick == tick + 1) {
+                self.play(&mut simulation, step);
+            }
+            session = simulation.step();
+        }
+        Outcome {
+            name: self.name.clone(),
+            failures: self.failures(&sent),
+            sent,
+        }
+    }
+
+    /// Hand `step` to the simulation, to happen on its next step
+    fn play(&self, simulation: &mut Simulation, step: &Step) {
+        let Some(index) = self.players.iter().position(|p| p.name == step.player) else {
+            return;
+        };
+        let player_uuid = self.player_uuid(index);
+        if let Some(place) = &step.move_to {
+            simulation.move_player(&player_uuid, place.into());
+        }
+        if let (Some(frames), Some(npc)) = (step.talk_frames, &step.npc) {
+            simulation.talk(index, npc, frames);
+        }
+        let Some(message) = &step.chat else {
+            return;
+        };
+        let Some(player) = simulation
+            .players()
+            .iter()
+            .find(|p| p.player_uuid == player_uuid)
+            .cloned()
+        else {
+            return;
+        };
+        let timestamp_ms = simulation.clock().now_ms();
+        let hearers: Vec<(String, f64)> = simulation
+            .npcs()
+            .iter()
+            .filter_map(|npc| {
+                let blocks = match (&npc.position, &player.position) {
+                    (Some(a), Some(b)) if a.world == b.world => distance(a, b),
+                    _ => f64::INFINITY,
+                };
+                let heard = match &step.npc {
+                    Some(npc_id) => *npc_id == npc.npc_id,
+                    None => blocks <= HEARING_BLOCKS,
+                };
+                heard.then(|| (npc.npc_id.clone(), blocks))
+            })
+            .collect();
+        for (npc_id, blocks) in hearers {
+            simulation.emit(ChatObservation {
+                npc_id,
+                player_uuid: player.player_uuid.clone(),
+                player_name: player.player_name.clone(),
+                message: message.clone(),
+                timestamp_ms,
+                distance: if blocks.is_finite() {
+                    blocks as f32
+                } else {
+                    0.0
+                },
+                player_locale: player.locale.clone(),
+            });
+        }
+    }
+
+    /// One line per expectation `sent` breaks
+    pub fn failures(&self, sent: &[Sent]) -> Vec<String> {
+        let mut failures = Vec::new();
+        for expectation in &self.expectations {
+            let count = sent.iter().filter(|s| expectation.matches(s)).count();
+            let (at_least, at_most) = expectation.bounds();
+            if count < at_least || at_most.is_some_and(|most| count > most) {
+                let wanted = match at_most {
+                    Some(most) if most == at_least => format!("{}", most),
+                    Some(most) => format!("{}..={}", at_least, most),
+                    None => format!("at least {}", at_least),
+                };
+                failures.push(format!(
+                    "expected {} {}, got {}",
+                    wanted,
+                    expectation.describe(),
+                    count
+                ));
+            }
+        }
+        failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{ChatObservation, SpeakDirective, VoicePcmFrame};
+    use crate::outbound::Outbound;
+    use std::sync::Mutex;
+
+    /// Greets whoever chats and counts voice frames
+    #[derive(Default)]
+    struct Greeter {
+        frames: Mutex<usize>,
+    }
+
+    impl NpcSocietyHandler for Greeter {
+        fn on_chat(&self, chat: ChatObservation, _cx: &ConnectionContext, tx: &Outbound) {
+            let speak = SpeakDirective {
+                npc_id: chat.npc_id,
+                text: format!("Hello, {}!", chat.player_name),
+                ..Default::default()
+            };
+            tx.send(speak).unwrap();
+        }
+
+        fn on_voice_frame(&self, _frame: VoicePcmFrame, _cx: &ConnectionContext, _tx: &Outbound) {
+            *self.frames.lock().unwrap() += 1;
+        }
+    }
+
+    const SCENARIO: &str = r#"
+        name = "greeting"
+        ticks = 20
+
+        [[npc]]
+        npc_id = "miner_1"
+        at = { x = 0, y = 64, z = 0 }
+
+        [[npc]]
+        npc_id = "miner_2"
+        at = { x = 100, y = 64, z = 0 }
+
+        [[player]]
+        name = "Steve"
+        at = { x = 3, y = 64, z = 0 }
+
+        [[step]]
+        tick = 5
+        player = "Steve"
+        chat = "hello"
+
+        [[step]]
+        tick = 10
+        player = "Steve"
+        move_to = { x = 98, y = 64, z = 0 }
+
+        [[step]]
+        tick = 12
+        player = "Steve"
+        chat = "anyone here?"
+
+        [[step]]
+        tick = 15
+        player = "Steve"
+        npc = "miner_2"
+        talk_frames = 3
+
+        [[expect]]
+        message = "SpeakDirective"
+        npc = "miner_1"
+        contains = "Hello, Steve!"
+        after_tick = 5
+        before_tick = 6
+
+        [[expect]]
+        message = "SpeakDirective"
+        npc = "miner_2"
+        after_tick = 12
+        at_most = 1
+
+        [[expect]]
+        message = "QuarantineNpc"
+        at_most = 0
+    "#;
+
+    #[test]
+    fn test_run_scenario() {
+        let scenario = Scenario::from_toml(SCENARIO).unwrap();
+        let greeter = Greeter::default();
+        let outcome = scenario.run(&greeter);
+        outcome.assert();
+        // Only the NPC in hearing range answered each chat
+        assert_eq!(outcome.sent.len(), 2);
+        assert_eq!(outcome.sent[0].tick, 5);
+        assert_eq!(*greeter.frames.lock().unwrap(), 3);
+
+        let broken = Scenario {
+            expectations: vec![Expectation {
+                message: "SpeakDirective".to_string(),
+                npc: Some("miner_1".to_string()),
+                contains: None,
+                after_tick: None,
+                before_tick: None,
+                at_least: Some(2),
+                at_most: None,
+            }],
+            ..scenario
+        };
+        assert_eq!(
+            broken.run(&Greeter::default()).failures,
+            ["expected at least 2 SpeakDirective for miner_1, got 1"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_scenarios() {
+        let world = r#"
+            name = "broken"
+            ticks = 10
+            [[npc]]
+            npc_id = "miner_1"
+            at = { x = 0, y = 64, z = 0 }
+            [[player]]
+            name = "Steve"
+            at = { x = 0, y = 64, z = 0 }
+        "#;
+        let with = |extra: &str| Scenario::from_toml(&format!("{}{}", world, extra));
+
+        assert!(with("").is_ok());
+        let error = with("[[step]]\ntick = 3\nplayer = \"Alex\"\nchat = \"hi\"").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid scenario: broken: step at tick 3: no player \"Alex\""
+        );
+        assert!(with("[[step]]\ntick = 3\nplayer = \"Steve\"").is_err());
+        assert!(with("[[step]]\ntick = 11\nplayer = \"Steve\"\nchat = \"hi\"").is_err());
+        assert!(with("[[step]]\ntick = 3\nplayer = \"Steve\"\ntalk_frames = 5").is_err());
+        assert!(with("[[expect]]\nmessage = \"SpeakDirectiv\"").is_err());
+        assert!(with("[[expect]]\nmessage = \"Chat\"\nat_least = 2\nat_most = 1").is_err());
+        // Typos are errors rather than ignored keys
+        assert!(with("[[expect]]\nmessage = \"Chat\"\nbefore = 3").is_err());
+    }
+}
@@ -0,0 +1,402 @@
+//! Daily routines.
+//!
+//! A [`Routine`] lists what an NPC does from which time of day ("06:00 go
+//! to the farm, 22:00 go to bed"). [`Scheduler`] reads the in-game clock
+//! from `WorldTick.environment` and sends the directive for an entry once
+//! when its slot begins, including the slot that is current when the
+//! scheduler first sees an NPC.
+//!
+//! With the `schedule-toml` feature, routines can be loaded from TOML (see
+//! [`Scheduler::from_toml`]).
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::npc_society::v1::{
+    action_directive::Action, interact_action::Target, ActionDirective, BlockPosition,
+    InteractAction, MoveAction, Position, StopAction, WorldTick,
+};
+
+/// Minecraft ticks per in-game day
+pub const TICKS_PER_DAY: i64 = 24_000;
+
+/// Wall-clock style time of day (minutes since midnight)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClockTime(u16);
+
+impl ClockTime {
+    /// `hour` 0-23, `minute` 0-59
+    pub fn new(hour: u16, minute: u16) -> Option<Self> {
+        (hour < 24 && minute < 60).then_some(Self(hour * 60 + minute))
+    }
+
+    /// Parse `HH:MM`
+    pub fn parse(s: &str) -> Option<Self> {
+        let (hour, minute) = s.split_once(':')?;
+        Self::new(hour.parse().ok()?, minute.parse().ok()?)
+    }
+
+    /// Convert Minecraft time of day (0 = 06:00)
+    pub fn from_time_of_day(ticks: i64) -> Self {
+        let since_midnight = (ticks + 6_000).rem_euclid(TICKS_PER_DAY);
+        Self((since_midnight * 1_440 / TICKS_PER_DAY) as u16)
+    }
+
+    /// Minutes since midnight
+    pub fn minutes(self) -> u16 {
+        self.0
+    }
+}
+
+impl fmt::Display for ClockTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+/// What an NPC does during a routine slot
+#[derive(Debug, Clone, PartialEq)]
+pub enum Activity {
+    /// Walk somewhere (pathfinding)
+    MoveTo(Position),
+    /// Use a block, e.g. a bed to sleep or a door
+    Interact(BlockPosition),
+    /// Stop and stay put
+    Idle,
+}
+
+impl Activity {
+    fn to_action(&self) -> Action {
+        match self {
+            Activity::MoveTo(target) => Action::Move(MoveAction {
+                target: Some(target.clone()),
+                speed: 0.5,
+                pathfind: true,
+                ..Default::default()
+            }),
+            Activity::Interact(block) => Action::Interact(InteractAction {
+                target: Some(Target::Block(block.clone())),
+                main_hand: true,
+            }),
+            Activity::Idle => Action::Stop(StopAction { cancel_pending: true }),
+        }
+    }
+}
+
+/// One slot of a routine
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutineEntry {
+    /// When the slot starts
+    pub at: ClockTime,
+    /// Name for logs, e.g. "farm"
+    pub label: String,
+    /// What to do
+    pub activity: Activity,
+}
+
+/// A daily routine; each entry lasts until the next one starts.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Routine {
+    entries: Vec<RoutineEntry>,
+}
+
+impl Routine {
+    /// Build a routine from entries in any order
+    pub fn new(mut entries: Vec<RoutineEntry>) -> Self {
+        entries.sort_by_key(|e| e.at);
+        Self { entries }
+    }
+
+    /// Entries sorted by start time
+    pub fn entries(&self) -> &[RoutineEntry] {
+        &self.entries
+    }
+
+    /// Index of the entry active at `now`, and whether it started the
+    /// previous day (before the first entry, the last one still runs).
+    fn slot(&self, now: ClockTime) -> Option<(usize, bool)> {
+        match self.entries.iter().rposition(|e| e.at <= now) {
+            Some(index) => Some((index, false)),
+            None if self.entries.is_empty() => None,
+            None => Some((self.entries.len() - 1, true)),
+        }
+    }
+
+    /// The entry active at `now`
+    pub fn current(&self, now: ClockTime) -> Option<&RoutineEntry> {
+        self.slot(now).map(|(index, _)| &self.entries[index])
+    }
+}
+
+/// Routines for many NPCs, driven by the WorldTick clock.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    routines: HashMap<String, Routine>,
+    /// (day, entry index) of the last slot started per NPC
+    started: HashMap<String, (i64, usize)>,
+    counter: u64,
+}
+
+impl Scheduler {
+    /// Give `npc_id` a routine, replacing any previous one
+    pub fn set_routine(&mut self, npc_id: &str, routine: Routine) {
+        self.routines.insert(npc_id.to_string(), routine);
+        self.started.remove(npc_id);
+    }
+
+    /// Routine of `npc_id`, if any
+    pub fn routine(&self, npc_id: &str) -> Option<&Routine> {
+        self.routines.get(npc_id)
+    }
+
+    /// Directives for routine slots that began since the last tick.
+    /// Does nothing for ticks without `environment`.
+    pub fn on_world_tick(&mut self, tick: &WorldTick) -> Vec<ActionDirective> {
+        let Some(env) = tick.environment.as_ref() else {
+            return Vec::new();
+        };
+        let now = ClockTime::from_time_of_day(env.time_of_day);
+
+        let mut directives = Vec::new();
+        for npc in &tick.npcs {
+            let Some(routine) = self.routines.get(&npc.npc_id) else {
+                continue;
+            };
+            let Some((index, from_yesterday)) = routine.slot(now) else {
+                continue;
+            };
+            let slot = (env.day - i64::from(from_yesterday), index);
+            if self.started.get(&npc.npc_id) == Some(&slot) {
+                continue;
+            }
+            self.started.insert(npc.npc_id.clone(), slot);
+
+            self.counter += 1;
+            directives.push(ActionDirective {
+                directive_id: format!("{}-sched-{}", npc.npc_id, self.counter),
+                npc_id: npc.npc_id.clone(),
+                priority: 1,
+                action: Some(routine.entries[index].activity.to_action()),
+                ..Default::default()
+            });
+        }
+        directives
+    }
+}
+
+/// Error loading routines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleError(pub String);
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid schedule: {}", self.0)
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+#[cfg(feature = "schedule-toml")]
+pub(crate) mod toml_format {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct File {
+        #[serde(default)]
+        routine: Vec<Entry>,
+    }
+
+    #[derive(Deserialize)]
+    struct Entry {
+        npc_id: String,
+        #[serde(flatten)]
+        slot: Slot,
+    }
+
+    /// One `[[routine]]` table without the npc_id
+    #[derive(Deserialize)]
+    pub(crate) struct Slot {
+        at: String,
+        #[serde(default)]
+        label: String,
+        move_to: Option<Coords>,
+        interact: Option<Coords>,
+        #[serde(default)]
+        idle: bool,
+    }
+
+    #[derive(Deserialize)]
+    pub(crate) struct Coords {
+        world: String,
+        x: f64,
+        y: f64,
+        z: f64,
+    }
+
+    impl From<Coords> for Position {
+        fn from(c: Coords) -> Self {
+            Position {
+                world: c.world,
+                x: c.x,
+                y: c.y,
+                z: c.z,
+                ..Default::default()
+            }
+        }
+    }
+
+    impl Slot {
+        /// `owner` names the NPC in error messages
+        pub(crate) fn into_entry(self, owner: &str) -> Result<RoutineEntry, ScheduleError> {
+            let at = ClockTime::parse(&self.at)
+                .ok_or_else(|| ScheduleError(format!("bad time '{}' for {}", self.at, owner)))?;
+            let activity = match (self.move_to, self.interact, self.idle) {
+                (Some(c), None, false) => Activity::MoveTo(c.into()),
+                (None, Some(c), false) => Activity::Interact(BlockPosition {
+                    world: c.world,
+                    x: c.x.floor() as i32,
+                    y: c.y.floor() as i32,
+                    z: c.z.floor() as i32,
+                    ..Default::default()
+                }),
+                (None, None, true) => Activity::Idle,
+                _ => {
+                    return Err(ScheduleError(format!(
+                        "{} at {}: need exactly one of move_to, interact, idle",
+                        owner, self.at
+                    )))
+                }
+            };
+            Ok(RoutineEntry {
+                at,
+                label: self.label,
+                activity,
+            })
+        }
+    }
+
+    impl Scheduler {
+        /// Load routines from TOML:
+        ///
+        /// ```toml
+        /// [[routine]]
+        /// npc_id = "baker"
+        /// at = "06:00"
+        /// label = "farm"
+        /// move_to = { world = "world", x = 100.5, y = 64, z = -20 }
+        ///
+        /// [[routine]]
+        /// npc_id = "baker"
+        /// at = "22:00"
+        /// label = "sleep"
+        /// interact = { world = "world", x = 98, y = 64, z = -25 }
+        /// ```
+        ///
+        /// Each entry needs exactly one of `move_to`, `interact` or `idle = true`.
+        pub fn from_toml(source: &str) -> Result<Self, ScheduleError> {
+            let file: File = toml::from_str(source).map_err(|e| ScheduleError(e.to_string()))?;
+
+            let mut entries: HashMap<String, Vec<RoutineEntry>> = HashMap::new();
+            for entry in file.routine {
+                let routine_entry = entry.slot.into_entry(&entry.npc_id)?;
+                entries.entry(entry.npc_id).or_default().push(routine_entry);
+            }
+
+            let mut scheduler = Scheduler::default();
+            for (npc_id, entries) in entries {
+                scheduler.set_routine(&npc_id, Routine::new(entries));
+            }
+            Ok(scheduler)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{EnvironmentState, NpcSnapshot};
+
+    fn entry(at: &str, label: &str, activity: Activity) -> RoutineEntry {
+        RoutineEntry {
+            at: ClockTime::parse(at).unwrap(),
+            label: label.to_string(),
+            activity,
+        }
+    }
+
+    fn tick(day: i64, time_of_day: i64) -> WorldTick {
+        WorldTick {
+            npcs: vec![NpcSnapshot {
+                npc_id: "baker".to_string(),
+                ..Default::default()
+            }],
+            environment: Some(EnvironmentState {
+                day,
+                time_of_day,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_clock_conversion() {
+        assert_eq!(ClockTime::from_time_of_day(0).to_string(), "06:00");
+        assert_eq!(ClockTime::from_time_of_day(6_000).to_string(), "12:00");
+        assert_eq!(ClockTime::from_time_of_day(18_000).to_string(), "00:00");
+        assert_eq!(ClockTime::parse("25:00"), None);
+    }
+
+    #[test]
+    fn test_scheduler_starts_each_slot_once() {
+        let mut scheduler = Scheduler::default();
+        scheduler.set_routine(
+            "baker",
+            Routine::new(vec![
+                entry("22:00", "sleep", Activity::Idle),
+                entry("06:00", "farm", Activity::MoveTo(Position::default())),
+            ]),
+        );
+
+        // 03:00 on day 1: still sleeping since 22:00 on day 0
+        let sent = scheduler.on_world_tick(&tick(1, 21_000));
+        assert!(matches!(sent[0].action, Some(Action::Stop(_))));
+        assert!(scheduler.on_world_tick(&tick(1, 22_000)).is_empty());
+
+        // 06:00 -> farm, once
+        let sent = scheduler.on_world_tick(&tick(1, 0));
+        assert!(matches!(sent[0].action, Some(Action::Move(_))));
+        assert!(scheduler.on_world_tick(&tick(1, 1_000)).is_empty());
+
+        // Ticks without a clock are ignored
+        assert!(scheduler.on_world_tick(&WorldTick::default()).is_empty());
+    }
+
+    #[cfg(feature = "schedule-toml")]
+    #[test]
+    fn test_from_toml() {
+        let scheduler = Scheduler::from_toml(
+            r#"
+            [[routine]]
+            npc_id = "baker"
+            at = "12:00"
+            label = "tavern"
+            move_to = { world = "world", x = 1.5, y = 64, z = 2 }
+
+            [[routine]]
+            npc_id = "baker"
+            at = "22:00"
+            interact = { world = "world", x = 3, y = 64, z = 4 }
+            "#,
+        )
+        .unwrap();
+
+        let routine = scheduler.routine("baker").unwrap();
+        assert_eq!(routine.entries().len(), 2);
+        assert_eq!(routine.current(ClockTime::parse("13:00").unwrap()).unwrap().label, "tavern");
+
+        assert!(Scheduler::from_toml("[[routine]]\nnpc_id = \"x\"\nat = \"06:00\"").is_err());
+    }
+}
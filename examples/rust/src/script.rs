@@ -0,0 +1,353 @@
+//! NPC behavior written in Rhai (feature `scripting`).
+//!
+//! [`ScriptedNpc`] is an [`NpcActor`] whose logic lives in a `.rhai` file,
+//! so server admins can change what an NPC does without recompiling the
+//! daemon. The file is reloaded whenever it changes on disk; if the new
+//! version does not compile, the old one keeps running.
+//!
+//! A script defines any of these functions:
+//!
+//! ```rhai
+//! fn on_tick(npc) { }       // npc: #{ npc_id, world, x, y, z, health, in_combat, activity, server_tick }
+//! fn on_chat(chat) { }      // chat: #{ player_uuid, player_name, message }
+//! fn on_result(result) { }  // result: #{ directive_id, success, error }
+//! fn on_event(event) { }    // event: #{ event_type, kind }
+//! ```
+//!
+//! and reacts by calling `move_to(x, y, z)`, `break_block(x, y, z)`,
+//! `look_at(x, y, z)`, `stop()` or `say(text)`, which act on the script's
+//! own NPC in its current world. `this` is a map that keeps its contents
+//! between calls (and across reloads):
+//!
+//! ```rhai
+//! fn on_tick(npc) {
+//!     this.ticks = (this.ticks ?? 0) + 1;
+//!     if this.ticks % 100 == 0 { move_to(npc.x + 5, npc.y, npc.z); }
+//! }
+//! ```
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+
+use crate::actor::{NpcActor, NpcEvent};
+use crate::game_event::GameEvent;
+use crate::npc_society::v1::{
+    action_directive::Action, look_action, server_message::Message as ServerMsg, ActionDirective,
+    BlockPosition, BreakBlockAction, LookAction, MoveAction, Position, ServerMessage,
+    SpeakDirective, StopAction,
+};
+
+/// Error loading or running a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError(pub String);
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "script error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// What a script asked for during one call
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    MoveTo(f64, f64, f64),
+    BreakBlock(f64, f64, f64),
+    LookAt(f64, f64, f64),
+    Stop,
+    Say(String),
+}
+
+fn number(value: &Dynamic) -> f64 {
+    value
+        .as_float()
+        .or_else(|_| value.as_int().map(|i| i as f64))
+        .unwrap_or_default()
+}
+
+/// An NPC driven by a hot-reloaded Rhai script.
+pub struct ScriptedNpc {
+    npc_id: String,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    engine: Engine,
+    ast: AST,
+    this: Dynamic,
+    commands: Arc<Mutex<Vec<Command>>>,
+    world: String,
+    counter: u64,
+}
+
+impl ScriptedNpc {
+    /// Load the script at `path` for `npc_id`
+    pub fn load(npc_id: &str, path: impl AsRef<Path>) -> Result<Self, ScriptError> {
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = Engine::new();
+        let push = |commands: &Arc<Mutex<Vec<Command>>>| {
+            let commands = commands.clone();
+            move |command: Command| commands.lock().unwrap().push(command)
+        };
+        let send = push(&commands);
+        engine.register_fn("move_to", move |x: Dynamic, y: Dynamic, z: Dynamic| {
+            send(Command::MoveTo(number(&x), number(&y), number(&z)))
+        });
+        let send = push(&commands);
+        engine.register_fn("break_block", move |x: Dynamic, y: Dynamic, z: Dynamic| {
+            send(Command::BreakBlock(number(&x), number(&y), number(&z)))
+        });
+        let send = push(&commands);
+        engine.register_fn("look_at", move |x: Dynamic, y: Dynamic, z: Dynamic| {
+            send(Command::LookAt(number(&x), number(&y), number(&z)))
+        });
+        let send = push(&commands);
+        engine.register_fn("stop", move || send(Command::Stop));
+        let send = push(&commands);
+        engine.register_fn("say", move |text: &str| send(Command::Say(text.to_string())));
+
+        let mut npc = Self {
+            npc_id: npc_id.to_string(),
+            path: path.as_ref().to_path_buf(),
+            modified: None,
+            engine,
+            ast: AST::empty(),
+            this: Dynamic::from_map(Map::new()),
+            commands,
+            world: String::new(),
+            counter: 0,
+        };
+        npc.reload()?;
+        Ok(npc)
+    }
+
+    /// Recompile the script if the file changed since it was last loaded.
+    /// On error the previous version stays active.
+    pub fn reload(&mut self) -> Result<bool, ScriptError> {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .map_err(|e| ScriptError(format!("{}: {}", self.path.display(), e)))?;
+        if self.modified == Some(modified) {
+            return Ok(false);
+        }
+        self.modified = Some(modified);
+
+        let ast = self
+            .engine
+            .compile_file(self.path.clone())
+            .map_err(|e| ScriptError(format!("{}: {}", self.path.display(), e)))?;
+        // Run top-level statements once; handlers are called without them
+        self.engine
+            .run_ast(&ast)
+            .map_err(|e| ScriptError(format!("{}: {}", self.path.display(), e)))?;
+        self.commands.lock().unwrap().clear();
+        self.ast = ast;
+        Ok(true)
+    }
+
+    /// Call `name(arg)` if the script defines it and turn what it asked
+    /// for into messages
+    fn call(&mut self, name: &str, arg: Map) -> Result<Vec<ServerMessage>, ScriptError> {
+        if !self.ast.iter_functions().any(|f| f.name == name && f.params.len() == 1) {
+            return Ok(Vec::new());
+        }
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.this);
+        let outcome = self
+            .engine
+            .call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &self.ast, name, (arg,));
+        let commands = std::mem::take(&mut *self.commands.lock().unwrap());
+        if let Err(e) = outcome {
+            return Err(ScriptError(format!("{}: {}", name, e)));
+        }
+        Ok(commands.into_iter().map(|c| self.message(c)).collect())
+    }
+
+    fn message(&mut self, command: Command) -> ServerMessage {
+        let position = |x, y, z| Position {
+            world: self.world.clone(),
+            x,
+            y,
+            z,
+            ..Default::default()
+        };
+        let action = match command {
+            Command::Say(text) => {
+                return ServerMessage {
+                    message: Some(ServerMsg::SpeakDirective(SpeakDirective {
+                        npc_id: self.npc_id.clone(),
+                        text,
+                        ..Default::default()
+                    })),
+                    ..Default::default()
+                }
+            }
+            Command::MoveTo(x, y, z) => Action::Move(MoveAction {
+                target: Some(position(x, y, z)),
+                speed: 0.5,
+                pathfind: true,
+                ..Default::default()
+            }),
+            Command::BreakBlock(x, y, z) => Action::BreakBlock(BreakBlockAction {
+                position: Some(BlockPosition {
+                    world: self.world.clone(),
+                    x: x.floor() as i32,
+                    y: y.floor() as i32,
+                    z: z.floor() as i32,
+                    ..Default::default()
+                }),
+            }),
+            Command::LookAt(x, y, z) => Action::Look(LookAction {
+                target: Some(look_action::Target::Position(position(x, y, z))),
+            }),
+            Command::Stop => Action::Stop(StopAction { cancel_pending: true }),
+        };
+        self.counter += 1;
+        ServerMessage {
+            message: Some(ServerMsg::ActionDirective(ActionDirective {
+                directive_id: format!("{}-script-{}", self.npc_id, self.counter),
+                npc_id: self.npc_id.clone(),
+                priority: 1,
+                action: Some(action),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+}
+
+impl NpcActor for ScriptedNpc {
+    fn handle(&mut self, event: NpcEvent) -> Vec<ServerMessage> {
+        if let Err(e) = self.reload() {
+            tracing::warn!(npc_id = %self.npc_id, error = %e, "Keeping previous script");
+        }
+
+        let mut arg = Map::new();
+        let name = match event {
+            NpcEvent::Tick { npc, tick } => {
+                let p = npc.position.unwrap_or_default();
+                self.world = p.world.clone();
+                arg.insert("npc_id".into(), npc.npc_id.into());
+                arg.insert("world".into(), p.world.into());
+                arg.insert("x".into(), p.x.into());
+                arg.insert("y".into(), p.y.into());
+                arg.insert("z".into(), p.z.into());
+                arg.insert("health".into(), f64::from(npc.health_norm).into());
+                arg.insert("in_combat".into(), npc.in_combat.into());
+                arg.insert("activity".into(), npc.current_activity.into());
+                arg.insert("server_tick".into(), tick.server_tick.into());
+                "on_tick"
+            }
+            NpcEvent::Chat(chat) => {
+                arg.insert("player_uuid".into(), chat.player_uuid.into());
+                arg.insert("player_name".into(), chat.player_name.into());
+                arg.insert("message".into(), chat.message.into());
+                "on_chat"
+            }
+            NpcEvent::ActionResult(result) => {
+                arg.insert("directive_id".into(), result.directive_id.into());
+                arg.insert("success".into(), result.success.into());
+                arg.insert("error".into(), result.error_message.into());
+                "on_result"
+            }
+            NpcEvent::Event(event) => {
+                arg.insert("event_type".into(), event.event_type().as_str_name().into());
+                arg.insert("kind".into(), GameEvent::of(&event).name().into());
+                "on_event"
+            }
+            _ => return Vec::new(),
+        };
+
+        self.call(name, arg).unwrap_or_else(|e| {
+            tracing::warn!(npc_id = %self.npc_id, error = %e, "Script failed");
+            Vec::new()
+        })
+    }
+}
+
+impl fmt::Debug for ScriptedNpc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScriptedNpc")
+            .field("npc_id", &self.npc_id)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{ChatObservation, NpcSnapshot, WorldTick};
+
+    fn tick() -> NpcEvent {
+        let npc = NpcSnapshot {
+            npc_id: "bard".to_string(),
+            position: Some(Position {
+                world: "world".to_string(),
+                x: 10.0,
+                y: 64.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        NpcEvent::Tick {
+            npc: Box::new(npc),
+            tick: Arc::new(WorldTick::default()),
+        }
+    }
+
+    #[test]
+    fn test_script_handlers_and_reload() {
+        let path = std::env::temp_dir().join(format!("npc-script-{}.rhai", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+            fn on_tick(npc) {
+                this.ticks = (this.ticks ?? 0) + 1;
+                if this.ticks == 2 { move_to(npc.x + 5, npc.y, npc.z); }
+            }
+            fn on_chat(chat) { say("Hello, " + chat.player_name); }
+            "#,
+        )
+        .unwrap();
+        let mut bard = ScriptedNpc::load("bard", &path).unwrap();
+
+        assert!(bard.handle(tick()).is_empty());
+        match &bard.handle(tick())[..] {
+            [ServerMessage {
+                message: Some(ServerMsg::ActionDirective(directive)),
+                ..
+            }] => match &directive.action {
+                Some(Action::Move(m)) => assert_eq!(m.target.as_ref().unwrap().x, 15.0),
+                other => panic!("expected a move, got {other:?}"),
+            },
+            other => panic!("expected one directive, got {other:?}"),
+        }
+
+        let chat = NpcEvent::Chat(ChatObservation {
+            player_name: "Alex".to_string(),
+            ..Default::default()
+        });
+        match &bard.handle(chat.clone())[..] {
+            [ServerMessage {
+                message: Some(ServerMsg::SpeakDirective(speak)),
+                ..
+            }] => assert_eq!(speak.text, "Hello, Alex"),
+            other => panic!("expected speech, got {other:?}"),
+        }
+
+        // A broken edit keeps the old script; removing a handler works
+        std::fs::write(&path, "fn on_chat(chat) { say(").unwrap();
+        bard.modified = None;
+        assert!(bard.reload().is_err());
+        assert_eq!(bard.handle(chat.clone()).len(), 1);
+        std::fs::write(&path, "fn on_tick(npc) { stop(); }").unwrap();
+        bard.modified = None;
+        assert!(bard.reload().unwrap());
+        assert!(bard.handle(chat).is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
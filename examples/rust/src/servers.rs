@@ -0,0 +1,167 @@
+//! Several Minecraft servers per daemon.
+//!
+//! Each plugin connection says in its Hello which server it speaks for.
+//! [`ServerRegistry`] keeps one state per `server_id`, so NPCs with the
+//! same id on two servers never share a world model or pending directives,
+//! and admin RPCs pick a server by the `server_id` in their request.
+//!
+//! A connection starts with a fresh state and [`ServerRegistry::attach`]es
+//! it after the Hello; a server that reconnects takes over what its last
+//! connection left behind.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Why a request's `server_id` did not pick a server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    /// No server with that id has connected
+    Unknown(String),
+    /// No server has connected at all
+    NoServers,
+    /// `server_id` was empty but several servers are known
+    Ambiguous(Vec<String>),
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::Unknown(id) => write!(f, "server '{}' is not connected", id),
+            ResolveError::NoServers => write!(f, "no server has connected yet"),
+            ResolveError::Ambiguous(ids) => {
+                write!(
+                    f,
+                    "several servers are connected ({}); set server_id",
+                    ids.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+/// `NOT_FOUND`, or `INVALID_ARGUMENT` when `server_id` must be set
+impl From<ResolveError> for tonic::Status {
+    fn from(e: ResolveError) -> Self {
+        match e {
+            ResolveError::Ambiguous(_) => tonic::Status::invalid_argument(e.to_string()),
+            _ => tonic::Status::not_found(e.to_string()),
+        }
+    }
+}
+
+/// State per `server_id`
+#[derive(Debug)]
+pub struct ServerRegistry<S> {
+    servers: Mutex<BTreeMap<String, Arc<Mutex<S>>>>,
+}
+
+impl<S> Default for ServerRegistry<S> {
+    fn default() -> Self {
+        Self {
+            servers: Mutex::default(),
+        }
+    }
+}
+
+impl<S> ServerRegistry<S> {
+    /// Make `state` the state of `server_id`. If an earlier connection of
+    /// the same server left a state, `adopt` receives the new state and the
+    /// old one to carry over whatever should survive the reconnect.
+    pub fn attach(
+        &self,
+        server_id: &str,
+        state: &Arc<Mutex<S>>,
+        adopt: impl FnOnce(&mut S, &mut S),
+    ) {
+        let previous = self
+            .servers
+            .lock()
+            .unwrap()
+            .insert(server_id.to_string(), state.clone());
+        if let Some(previous) = previous.filter(|p| !Arc::ptr_eq(p, state)) {
+            adopt(&mut state.lock().unwrap(), &mut previous.lock().unwrap());
+        }
+    }
+
+    /// Add the state of a server that has not connected yet, e.g. rebuilt
+    /// from storage; a later [`attach`](Self::attach) adopts it
+    pub fn insert(&self, server_id: &str, state: S) {
+        self.servers
+            .lock()
+            .unwrap()
+            .insert(server_id.to_string(), Arc::new(Mutex::new(state)));
+    }
+
+    /// The state of `server_id`, if it is known
+    pub fn get(&self, server_id: &str) -> Option<Arc<Mutex<S>>> {
+        self.servers.lock().unwrap().get(server_id).cloned()
+    }
+
+    /// The server a request means: `server_id`, or the only known server
+    /// if it is empty
+    pub fn resolve(&self, server_id: &str) -> Result<Arc<Mutex<S>>, ResolveError> {
+        let servers = self.servers.lock().unwrap();
+        if !server_id.is_empty() {
+            return servers
+                .get(server_id)
+                .cloned()
+                .ok_or_else(|| ResolveError::Unknown(server_id.to_string()));
+        }
+        let mut all = servers.values();
+        match (all.next(), all.next()) {
+            (Some(only), None) => Ok(only.clone()),
+            (None, _) => Err(ResolveError::NoServers),
+            (Some(_), Some(_)) => Err(ResolveError::Ambiguous(servers.keys().cloned().collect())),
+        }
+    }
+
+    /// Known server ids, sorted
+    pub fn server_ids(&self) -> Vec<String> {
+        self.servers.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// The state of every known server, sorted by id
+    pub fn all(&self) -> Vec<(String, Arc<Mutex<S>>)> {
+        self.servers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, state)| (id.clone(), state.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attach_and_resolve() {
+        let servers = ServerRegistry::<Vec<&str>>::default();
+        assert_eq!(servers.resolve("").unwrap_err(), ResolveError::NoServers);
+
+        let survival = Arc::new(Mutex::new(vec!["dir-1"]));
+        servers.attach("survival", &survival, |_, _| unreachable!());
+        assert!(Arc::ptr_eq(&servers.resolve("").unwrap(), &survival));
+        assert_eq!(
+            servers.resolve("creative").unwrap_err(),
+            ResolveError::Unknown("creative".to_string())
+        );
+
+        servers.insert("creative", vec![]);
+        assert_eq!(
+            servers.resolve("").unwrap_err(),
+            ResolveError::Ambiguous(vec!["creative".to_string(), "survival".to_string()])
+        );
+
+        // Reconnect: the new connection takes over the pending directives
+        let reconnected = Arc::new(Mutex::new(vec![]));
+        servers.attach("survival", &reconnected, |new, old| new.append(old));
+        assert_eq!(*servers.get("survival").unwrap().lock().unwrap(), ["dir-1"]);
+        assert!(survival.lock().unwrap().is_empty());
+        assert_eq!(servers.server_ids(), ["creative", "survival"]);
+    }
+}
@@ -0,0 +1,194 @@
+//! NPC shops (`ShopDefinition`, v1.2+).
+//!
+//! The plugin runs the chest GUI, takes payment and moves the items; the
+//! daemon only decides what is on offer. [`Shops`] keeps the definitions
+//! sent and follows their stock through [`ShopTradeObservation`]s, so
+//! restocking or repricing is an edit of the last definition:
+//!
+//! ```ignore
+//! shops.define(&shop);
+//! tx.send(shop)?;
+//! // later, on a ShopTradeObservation
+//! shops.apply(&trade);
+//! if let Some(restocked) = shops.restock(&trade.shop_id, &trade.listing_id, 10) {
+//!     tx.send(restocked)?;
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use crate::npc_society::v1::{
+    ItemStack, ShopDefinition, ShopListing, ShopTradeObservation, ShopTradeSide,
+};
+
+/// Stock of a listing that never runs out
+pub const UNLIMITED: i32 = -1;
+
+/// A listing with unlimited stock; a price of 0 leaves that side out
+pub fn listing(
+    listing_id: impl Into<String>,
+    item: ItemStack,
+    buy_price: i64,
+    sell_price: i64,
+) -> ShopListing {
+    ShopListing {
+        listing_id: listing_id.into(),
+        item: Some(item),
+        buy_price,
+        sell_price,
+        stock: UNLIMITED,
+    }
+}
+
+/// Shops set up on the plugin, by shop_id
+#[derive(Debug, Default)]
+pub struct Shops {
+    shops: HashMap<String, ShopDefinition>,
+}
+
+impl Shops {
+    /// Remember a definition sent to the plugin; one without listings
+    /// closes the shop
+    pub fn define(&mut self, shop: &ShopDefinition) {
+        if shop.listings.is_empty() {
+            self.shops.remove(&shop.shop_id);
+        } else {
+            let shop = ShopDefinition {
+                open_for_player_uuids: Vec::new(),
+                ..shop.clone()
+            };
+            self.shops.insert(shop.shop_id.clone(), shop);
+        }
+    }
+
+    /// Take a trade's stock over; false if the shop or listing is unknown
+    pub fn apply(&mut self, trade: &ShopTradeObservation) -> bool {
+        let listing = self.shops.get_mut(&trade.shop_id).and_then(|shop| {
+            shop.listings
+                .iter_mut()
+                .find(|l| l.listing_id == trade.listing_id)
+        });
+        let Some(listing) = listing else {
+            return false;
+        };
+        // Failed trades report the stock too, e.g. "out of stock"
+        listing.stock = trade.stock;
+        true
+    }
+
+    /// The shop with this id, as last defined and traded
+    pub fn shop(&self, shop_id: &str) -> Option<&ShopDefinition> {
+        self.shops.get(shop_id)
+    }
+
+    /// Ids of the shops `npc_id` runs
+    pub fn shops_of<'a>(&'a self, npc_id: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.shops
+            .values()
+            .filter(move |shop| shop.npc_id == npc_id)
+            .map(|shop| shop.shop_id.as_str())
+    }
+
+    /// Set a listing's stock and return the definition to send again; None
+    /// if the shop or listing is unknown. A trade still in flight when the
+    /// plugin gets it is overwritten, so restock when the listing sold out
+    /// rather than on a timer.
+    pub fn restock(
+        &mut self,
+        shop_id: &str,
+        listing_id: &str,
+        stock: i32,
+    ) -> Option<ShopDefinition> {
+        self.update(shop_id, listing_id, |listing| listing.stock = stock)
+    }
+
+    /// Change a listing's prices and return the definition to send again
+    pub fn reprice(
+        &mut self,
+        shop_id: &str,
+        listing_id: &str,
+        buy_price: i64,
+        sell_price: i64,
+    ) -> Option<ShopDefinition> {
+        self.update(shop_id, listing_id, |listing| {
+            listing.buy_price = buy_price;
+            listing.sell_price = sell_price;
+        })
+    }
+
+    fn update(
+        &mut self,
+        shop_id: &str,
+        listing_id: &str,
+        change: impl FnOnce(&mut ShopListing),
+    ) -> Option<ShopDefinition> {
+        let shop = self.shops.get_mut(shop_id)?;
+        let listing = shop
+            .listings
+            .iter_mut()
+            .find(|l| l.listing_id == listing_id)?;
+        change(listing);
+        Some(shop.clone())
+    }
+}
+
+/// Whether a successful trade left the listing without stock to buy
+pub fn sold_out(trade: &ShopTradeObservation) -> bool {
+    trade.success && trade.side() == ShopTradeSide::Buy && trade.stock == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(item_type: &str, quantity: i32) -> ItemStack {
+        ItemStack {
+            item_type: item_type.to_string(),
+            quantity,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_trades_and_restock() {
+        let mut shops = Shops::default();
+        let mut torches = listing("torches", item("minecraft:torch", 16), 20, 0);
+        torches.stock = 1;
+        shops.define(&ShopDefinition {
+            shop_id: "miner-shop".to_string(),
+            npc_id: "miner".to_string(),
+            listings: vec![torches, listing("coal", item("minecraft:coal", 8), 0, 10)],
+            open_for_player_uuids: vec!["p1".to_string()],
+            ..Default::default()
+        });
+        assert_eq!(shops.shops_of("miner").collect::<Vec<_>>(), ["miner-shop"]);
+        // Resending must not pop the GUI up again
+        assert!(shops
+            .shop("miner-shop")
+            .unwrap()
+            .open_for_player_uuids
+            .is_empty());
+
+        let trade = ShopTradeObservation {
+            shop_id: "miner-shop".to_string(),
+            npc_id: "miner".to_string(),
+            listing_id: "torches".to_string(),
+            side: ShopTradeSide::Buy as i32,
+            count: 1,
+            success: true,
+            stock: 0,
+            ..Default::default()
+        };
+        assert!(shops.apply(&trade));
+        assert!(sold_out(&trade));
+        let restocked = shops.restock("miner-shop", "torches", 5).unwrap();
+        assert_eq!(restocked.listings[0].stock, 5);
+        assert!(shops.reprice("miner-shop", "missing", 1, 1).is_none());
+
+        shops.define(&ShopDefinition {
+            shop_id: "miner-shop".to_string(),
+            ..Default::default()
+        });
+        assert!(!shops.apply(&trade));
+    }
+}
@@ -0,0 +1,1382 @@
+//! Deterministic plugin simulation for reproducible tests.
+//!
+//! A [`Simulation`] stands in for a Minecraft server. Its NPCs and players
+//! come from a seed, its [`VirtualClock`] moves only when the test calls
+//! [`Simulation::step`], and each directive finishes after a seeded number
+//! of ticks with a seeded outcome. Behavior trees attached with
+//! [`Simulation::attach`] are ticked on every WorldTick and get their
+//! results, and everything that happens goes into a [`Trace`]. Nothing
+//! reads the wall clock or iterates a hash map, so the same seed gives the
+//! same trace on every run and machine:
+//!
+//! ```ignore
+//! let mut sim = Simulation::new(SimConfig { seed: 42, ..Default::default() });
+//! let npc_id = sim.npc_ids()[0].clone();
+//! sim.attach(BehaviorTree::new(&npc_id, root));
+//! sim.run(200);
+//! assert_eq!(sim.trace().to_string(), include_str!("miner.trace"));
+//! ```
+//!
+//! Directives from a daemon under test go in with [`Simulation::send`];
+//! `step` returns the messages the plugin would send back.
+//!
+//! Robustness code (retries, resends after a reconnect, reassembly) needs
+//! things to go wrong. [`Simulation::inject`] schedules a [`Fault`] for a
+//! tick: dropped messages, late or duplicated ActionResults, voice frames
+//! out of order or a broken stream. Faults draw on the same seed, so a
+//! failing run can be replayed exactly:
+//!
+//! ```ignore
+//! sim.inject(100, Fault::Disconnect);
+//! sim.inject(140, Fault::Reconnect);
+//! sim.inject(200, Fault::DelayResults { ticks: 60, count: 3 });
+//! ```
+//!
+//! Planners can price an action before sending it: [`Simulation::preview`]
+//! answers a directive with the PlanEstimate a dry run would get, without
+//! running it or drawing on the seed.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use bytes::Bytes;
+
+use crate::behavior::BehaviorTree;
+use crate::clock::NOMINAL_TPS;
+use crate::dimension::same_world;
+use crate::events::ClientEvent;
+use crate::geom::{direction, distance, Vec3};
+use crate::loadgen::VOICE_SAMPLE_RATE_HZ;
+use crate::npc_society::v1::{
+    action_directive::Action, action_result::Result as ActionResultType,
+    check_line_of_sight_action::Target, client_message::Message as ClientMsg, ActionDirective,
+    ActionErrorCode, ActionResult, CheckLineOfSightResult, ClientMessage, Equipment,
+    GiveItemResult, Hello, ItemStack, MoveAction, MoveProgress, MoveResult, MovementMode,
+    NpcSnapshot, PcmFormat, PlanEstimate, PlayerSnapshot, Position, VoicePcmFrame, WorldTick,
+    WriteBookResult,
+};
+use crate::retry::action_kind;
+
+/// Unix milliseconds at tick 0 of every simulation
+pub const EPOCH_MS: i64 = 1_700_000_000_000;
+
+/// Blocks a simulated NPC walks per second, whatever the move's speed
+pub const WALK_BLOCKS_PER_SECOND: f64 = 4.3;
+
+/// How close a simulated NPC has to be to break or place a block
+pub const REACH_BLOCKS: f64 = 4.5;
+
+/// How far a simulated NPC sees when a CheckLineOfSightAction sets no limit
+pub const SIGHT_BLOCKS: f64 = 64.0;
+
+/// Half the angle of a simulated NPC's field of view, in degrees
+pub const FIELD_OF_VIEW_HALF_DEGREES: f64 = 60.0;
+
+/// Shape of a simulated server
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimConfig {
+    /// Seeds world generation, action durations and outcomes
+    pub seed: u64,
+    /// NPCs in the world
+    pub npcs: usize,
+    /// Players in the world
+    pub players: usize,
+    /// Fewest and most ticks an action takes
+    pub action_ticks: (i64, i64),
+    /// Share of actions that fail, 0.0-1.0
+    pub failure_rate: f64,
+    /// NPCs and players spawn within this many blocks of 0,0
+    pub spawn_radius: f64,
+}
+
+impl Default for SimConfig {
+    /// Three NPCs, two players, actions of 5-40 ticks of which 10% fail
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            npcs: 3,
+            players: 2,
+            action_ticks: (5, 40),
+            failure_rate: 0.1,
+            spawn_radius: 32.0,
+        }
+    }
+}
+
+/// Server time that only moves when told to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtualClock {
+    server_tick: i64,
+}
+
+impl VirtualClock {
+    /// Current server tick
+    pub fn server_tick(&self) -> i64 {
+        self.server_tick
+    }
+
+    /// Unix milliseconds of the current tick at [`NOMINAL_TPS`], counted
+    /// from [`EPOCH_MS`]
+    pub fn now_ms(&self) -> i64 {
+        EPOCH_MS + (self.server_tick as f64 * 1_000.0 / NOMINAL_TPS) as i64
+    }
+
+    /// Move `ticks` ticks forward
+    pub fn advance(&mut self, ticks: i64) {
+        self.server_tick += ticks.max(0);
+    }
+}
+
+/// xorshift64: small, and the same sequence for the same seed everywhere
+#[derive(Debug, Clone)]
+pub struct SimRng(u64);
+
+impl SimRng {
+    /// A generator for `seed`; every seed, 0 included, is usable
+    pub fn new(seed: u64) -> Self {
+        Self((seed ^ 0x9E37_79B9_7F4A_7C15).max(1))
+    }
+
+    /// The next 64 random bits
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform in 0.0..1.0
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `low..=high`
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        let span = (high - low).max(0) as u64 + 1;
+        low + (self.next_u64() % span) as i64
+    }
+}
+
+/// Something that happened in a simulation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// A directive reached the plugin
+    Directive {
+        tick: i64,
+        npc_id: String,
+        directive_id: String,
+        action: &'static str,
+    },
+    /// A directive finished
+    Result {
+        tick: i64,
+        npc_id: String,
+        directive_id: String,
+        success: bool,
+    },
+    /// A finished directive was resent and its result sent again
+    Replayed {
+        tick: i64,
+        npc_id: String,
+        directive_id: String,
+    },
+    /// A dry run was answered with an estimate
+    DryRun {
+        tick: i64,
+        npc_id: String,
+        directive_id: String,
+        action: &'static str,
+    },
+    /// An injected fault took effect
+    Fault { tick: i64, what: String },
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Directive {
+                tick,
+                npc_id,
+                directive_id,
+                action,
+            } => write!(
+                f,
+                "{} {} directive {} {}",
+                tick, npc_id, directive_id, action
+            ),
+            Self::Result {
+                tick,
+                npc_id,
+                directive_id,
+                success,
+            } => {
+                let outcome = if *success { "ok" } else { "failed" };
+                write!(f, "{} {} result {} {}", tick, npc_id, directive_id, outcome)
+            }
+            Self::Replayed {
+                tick,
+                npc_id,
+                directive_id,
+            } => write!(f, "{} {} replayed {}", tick, npc_id, directive_id),
+            Self::DryRun {
+                tick,
+                npc_id,
+                directive_id,
+                action,
+            } => write!(f, "{} {} dry_run {} {}", tick, npc_id, directive_id, action),
+            Self::Fault { tick, what } => write!(f, "{} fault {}", tick, what),
+        }
+    }
+}
+
+/// Everything that happened in a simulation, in order. Displays one event
+/// per line, for golden files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace(pub Vec<TraceEvent>);
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for event in &self.0 {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+/// A fault for [`Simulation::inject`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Lose the next `count` messages of `message_type`, a ClientMessage
+    /// oneof variant as [`ClientEvent::message_type`] names it, e.g.
+    /// `"ActionResult"`
+    Drop { message_type: String, count: usize },
+    /// Hold the next `count` ActionResults back for `ticks` more ticks
+    DelayResults { ticks: i64, count: usize },
+    /// Send the next `count` ActionResults twice
+    DuplicateResults { count: usize },
+    /// Send voice frames in shuffled groups of `window`; 0 or 1 sends them
+    /// in order again
+    ReorderAudio { window: usize },
+    /// Break the stream. Directives keep running, but nothing the plugin
+    /// sends arrives and directives sent meanwhile are lost.
+    Disconnect,
+    /// Connect again, starting with a Hello
+    Reconnect,
+}
+
+#[derive(Debug, Default)]
+struct Faults {
+    /// Faults not in effect yet, with the tick they start at
+    scheduled: Vec<(i64, Fault)>,
+    /// `(message_type, count)` still to drop
+    drops: Vec<(String, usize)>,
+    /// `(ticks, count)` of results still to delay
+    delay: Option<(i64, usize)>,
+    duplicates: usize,
+    reorder_window: usize,
+    held_audio: Vec<ClientMessage>,
+    disconnected: bool,
+    hello_due: bool,
+}
+
+#[derive(Debug)]
+struct Pending {
+    sent_tick: i64,
+    due_tick: i64,
+    directive: ActionDirective,
+    success: bool,
+    delayed: bool,
+}
+
+/// A player talking to an NPC, one voice frame per tick
+#[derive(Debug)]
+struct Talk {
+    npc_id: String,
+    player_uuid: String,
+    next_sequence: u64,
+    last_sequence: u64,
+}
+
+/// A seeded world on a virtual clock
+#[derive(Debug)]
+pub struct Simulation {
+    config: SimConfig,
+    rng: SimRng,
+    clock: VirtualClock,
+    npcs: Vec<NpcSnapshot>,
+    players: Vec<PlayerSnapshot>,
+    trees: BTreeMap<String, BehaviorTree>,
+    /// In the order the directives arrived
+    pending: Vec<Pending>,
+    /// Results of every directive run, by directive_id, for resends
+    completed: BTreeMap<String, ActionResult>,
+    /// Results of resent directives and dry runs, sent on the next step
+    replays: Vec<ActionResult>,
+    talks: Vec<Talk>,
+    /// Messages of the plugin's own, sent on the next step
+    emitted: Vec<ClientMessage>,
+    /// One tick of silence, shared by every voice frame
+    silence: Bytes,
+    faults: Faults,
+    trace: Trace,
+}
+
+impl Simulation {
+    /// Generate a world from `config.seed`, at tick 0
+    pub fn new(config: SimConfig) -> Self {
+        let mut rng = SimRng::new(config.seed);
+        let position = |rng: &mut SimRng| {
+            let mut coordinate = || (rng.unit() * 2.0 - 1.0) * config.spawn_radius;
+            Some(Position {
+                world: "world".to_string(),
+                x: coordinate().round(),
+                y: 64.0,
+                z: coordinate().round(),
+                ..Default::default()
+            })
+        };
+        let npcs = (0..config.npcs)
+            .map(|i| {
+                // Every other NPC carries a pickaxe, so mining trees have
+                // something to do
+                let main_hand = (i % 2 == 0).then(|| ItemStack {
+                    item_type: "minecraft:iron_pickaxe".to_string(),
+                    quantity: 1,
+                    ..Default::default()
+                });
+                NpcSnapshot {
+                    npc_id: format!("sim_npc_{}", i),
+                    entity_uuid: format!("00000000-0000-4000-8000-{:012}", i),
+                    position: position(&mut rng),
+                    health_norm: 1.0,
+                    hunger_norm: (0.2 + rng.unit() * 0.8) as f32,
+                    equipment: Some(Equipment {
+                        main_hand,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let players = (0..config.players)
+            .map(|i| PlayerSnapshot {
+                player_uuid: format!("00000000-0000-4000-9000-{:012}", i),
+                player_name: format!("SimPlayer{}", i),
+                position: position(&mut rng),
+                health_norm: 1.0,
+                game_mode: "survival".to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let samples_per_tick = (VOICE_SAMPLE_RATE_HZ as f64 / NOMINAL_TPS) as usize;
+        Self {
+            config,
+            rng,
+            clock: VirtualClock { server_tick: 0 },
+            npcs,
+            players,
+            trees: BTreeMap::new(),
+            pending: Vec::new(),
+            completed: BTreeMap::new(),
+            replays: Vec::new(),
+            talks: Vec::new(),
+            emitted: Vec::new(),
+            silence: Bytes::from(vec![0; samples_per_tick * 2]),
+            faults: Faults::default(),
+            trace: Trace::default(),
+        }
+    }
+
+    /// The virtual clock
+    pub fn clock(&self) -> &VirtualClock {
+        &self.clock
+    }
+
+    /// Ids of the generated NPCs, in order
+    pub fn npc_ids(&self) -> Vec<String> {
+        self.npcs.iter().map(|npc| npc.npc_id.clone()).collect()
+    }
+
+    /// Current state of the NPCs
+    pub fn npcs(&self) -> &[NpcSnapshot] {
+        &self.npcs
+    }
+
+    /// Current state of the players
+    pub fn players(&self) -> &[PlayerSnapshot] {
+        &self.players
+    }
+
+    /// Place an NPC besides the generated ones, replacing one with the same
+    /// npc_id
+    pub fn add_npc(&mut self, npc: NpcSnapshot) {
+        match self.npcs.iter_mut().find(|n| n.npc_id == npc.npc_id) {
+            Some(existing) => *existing = npc,
+            None => self.npcs.push(npc),
+        }
+    }
+
+    /// Place a player besides the generated ones, replacing one with the
+    /// same player_uuid
+    pub fn add_player(&mut self, player: PlayerSnapshot) {
+        match self
+            .players
+            .iter_mut()
+            .find(|p| p.player_uuid == player.player_uuid)
+        {
+            Some(existing) => *existing = player,
+            None => self.players.push(player),
+        }
+    }
+
+    /// Teleport a player; false if there is none with that uuid
+    pub fn move_player(&mut self, player_uuid: &str, position: Position) -> bool {
+        let Some(player) = self
+            .players
+            .iter_mut()
+            .find(|p| p.player_uuid == player_uuid)
+        else {
+            return false;
+        };
+        player.position = Some(position);
+        true
+    }
+
+    /// Send `message` as the plugin on the next step, before the WorldTick,
+    /// e.g. a ChatObservation of a scripted player
+    pub fn emit(&mut self, message: impl Into<ClientMessage>) {
+        self.emitted.push(message.into());
+    }
+
+    /// Whether the stream is up (see [`Fault::Disconnect`])
+    pub fn is_connected(&self) -> bool {
+        !self.faults.disconnected
+    }
+
+    /// The plugin's handshake, sent again after every [`Fault::Reconnect`]
+    pub fn hello(&self) -> ClientMessage {
+        Hello {
+            plugin_version: "sim".to_string(),
+            protocol_version: "1".to_string(),
+            server_id: "sim".to_string(),
+            minecraft_version: "1.21.1".to_string(),
+            voice_available: true,
+            server_name: "sim".to_string(),
+            supported_audio_formats: vec![PcmFormat::S16le as i32],
+            dry_run_available: true,
+            supported_movement: vec![
+                MovementMode::Swim as i32,
+                MovementMode::Climb as i32,
+                MovementMode::Parkour as i32,
+            ],
+            ..Default::default()
+        }
+        .into()
+    }
+
+    /// Tick `tree` with every WorldTick and deliver its results. Replaces
+    /// a tree attached to the same NPC earlier.
+    pub fn attach(&mut self, tree: BehaviorTree) {
+        let npc_id = tree.blackboard().npc.npc_id.clone();
+        self.trees.insert(npc_id, tree);
+    }
+
+    /// Inject `fault` from tick `at_tick` on (the next step, if that tick
+    /// has passed)
+    pub fn inject(&mut self, at_tick: i64, fault: Fault) {
+        self.faults.scheduled.push((at_tick, fault));
+    }
+
+    /// Player `player` talks to `npc_id` for `frames` ticks, one
+    /// VoicePcmFrame each
+    pub fn talk(&mut self, player: usize, npc_id: &str, frames: u64) {
+        let Some(player) = self.players.get(player) else {
+            return;
+        };
+        self.talks.push(Talk {
+            npc_id: npc_id.to_string(),
+            player_uuid: player.player_uuid.clone(),
+            next_sequence: 1,
+            last_sequence: frames,
+        });
+    }
+
+    /// A directive from a daemon; its result comes out of a later
+    /// [`Self::step`]. As on a real plugin, `directive_id` is an
+    /// idempotency key: a resent directive that finished is answered with
+    /// its result again (`replayed` set), one still running is ignored.
+    /// A `dry_run` directive is answered with [`Self::preview`] instead.
+    pub fn send(&mut self, directive: ActionDirective) {
+        let tick = self.clock.server_tick;
+        if self.faults.disconnected {
+            self.fault(format!("lost {}", directive.directive_id));
+            return;
+        }
+        if directive.dry_run {
+            self.trace.0.push(TraceEvent::DryRun {
+                tick,
+                npc_id: directive.npc_id.clone(),
+                directive_id: directive.directive_id.clone(),
+                action: directive.action.as_ref().map_or("none", action_kind),
+            });
+            let estimate = self.preview(&directive);
+            self.replays.push(estimate);
+            return;
+        }
+        if let Some(result) = self.completed.get(&directive.directive_id) {
+            self.trace.0.push(TraceEvent::Replayed {
+                tick,
+                npc_id: result.npc_id.clone(),
+                directive_id: result.directive_id.clone(),
+            });
+            self.replays.push(ActionResult {
+                replayed: true,
+                ..result.clone()
+            });
+            return;
+        }
+        if self
+            .pending
+            .iter()
+            .any(|p| p.directive.directive_id == directive.directive_id)
+        {
+            return;
+        }
+        let action = directive.action.as_ref().map_or("none", action_kind);
+        self.trace.0.push(TraceEvent::Directive {
+            tick,
+            npc_id: directive.npc_id.clone(),
+            directive_id: directive.directive_id.clone(),
+            action,
+        });
+        let (low, high) = self.config.action_ticks;
+        let due_tick = tick + self.rng.range(low.max(1), high);
+        let success = self.rng.unit() >= self.config.failure_rate;
+        self.pending.push(Pending {
+            sent_tick: tick,
+            due_tick,
+            directive,
+            success,
+            delayed: false,
+        });
+    }
+
+    /// What `directive` would cost, as the ActionResult its dry run gets.
+    /// Nothing runs and the seed is not drawn on, so previews leave later
+    /// outcomes as they were. Moves walk a straight line at
+    /// [`WALK_BLOCKS_PER_SECOND`] and block actions walk to within
+    /// [`REACH_BLOCKS`]; every action takes the fewest ticks of
+    /// [`SimConfig::action_ticks`] on top.
+    pub fn preview(&self, directive: &ActionDirective) -> ActionResult {
+        let mut result = ActionResult {
+            directive_id: directive.directive_id.clone(),
+            npc_id: directive.npc_id.clone(),
+            ..Default::default()
+        };
+        let Some(from) = self
+            .npcs
+            .iter()
+            .find(|npc| npc.npc_id == directive.npc_id)
+            .and_then(|npc| npc.position.as_ref())
+        else {
+            result.error_message = "unknown NPC".to_string();
+            return result;
+        };
+        let mut estimate = PlanEstimate::default();
+        match &directive.action {
+            Some(Action::Move(action)) => {
+                let Some(target) = action.target.as_ref().filter(|t| same_world(from, *t)) else {
+                    result.error_code = ActionErrorCode::Precondition as i32;
+                    result.error_message = "target not in the NPC's world".to_string();
+                    return result;
+                };
+                estimate.path_length = distance(from, target);
+            }
+            Some(Action::BreakBlock(action)) => {
+                estimate.affected_blocks.extend(action.position.clone());
+            }
+            Some(Action::PlaceBlock(action)) => {
+                estimate.affected_blocks.extend(action.position.clone());
+            }
+            _ => {}
+        }
+        if let Some(block) = estimate.affected_blocks.first() {
+            estimate.path_length = (distance(from, block) - REACH_BLOCKS).max(0.0);
+        }
+        let walk_ms = estimate.path_length / WALK_BLOCKS_PER_SECOND * 1_000.0;
+        let work_ms = self.config.action_ticks.0.max(1) as f64 * 1_000.0 / NOMINAL_TPS;
+        estimate.duration_ms = (walk_ms + work_ms).round() as i64;
+        result.success = true;
+        result.estimate = Some(estimate);
+        result
+    }
+
+    /// Advance one tick: finish the directives due, send voice frames and a
+    /// WorldTick, and tick the attached trees with what arrived. Returns
+    /// what reached the daemon, in order.
+    pub fn step(&mut self) -> Vec<ClientMessage> {
+        self.clock.advance(1);
+        let now = self.clock.server_tick;
+        self.start_faults(now);
+
+        let mut sent = Vec::new();
+        if std::mem::take(&mut self.faults.hello_due) {
+            sent.push(self.hello());
+        }
+        sent.extend(self.replays.drain(..).map(ClientMessage::from));
+        sent.append(&mut self.emitted);
+
+        let (due, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition::<Vec<_>, _>(|p| p.due_tick <= now);
+        self.pending = pending;
+        for mut finished in due {
+            if let Some((ticks, count)) = self.faults.delay.filter(|_| !finished.delayed) {
+                self.fault(format!("delayed {}", finished.directive.directive_id));
+                finished.due_tick = now + ticks;
+                finished.delayed = true;
+                self.pending.push(finished);
+                self.faults.delay = (count > 1).then_some((ticks, count - 1));
+                continue;
+            }
+            let result = self.finish(finished);
+            if self.faults.duplicates > 0 {
+                self.faults.duplicates -= 1;
+                self.fault(format!("duplicated {}", result.directive_id));
+                sent.push(result.clone().into());
+            }
+            sent.push(result.into());
+        }
+        sent.extend(self.voice_frames());
+        sent.push(
+            WorldTick {
+                server_tick: now,
+                timestamp_ms: self.clock.now_ms(),
+                npcs: self.npc_snapshots(now),
+                nearby_players: self.players.clone(),
+                tps: NOMINAL_TPS as f32,
+                ..Default::default()
+            }
+            .into(),
+        );
+
+        let arrived = self.transmit(sent);
+        let mut directives = Vec::new();
+        for message in &arrived {
+            match &message.message {
+                Some(ClientMsg::ActionResult(result)) => {
+                    if let Some(tree) = self.trees.get_mut(&result.npc_id) {
+                        tree.on_action_result(result);
+                    }
+                }
+                Some(ClientMsg::WorldTick(tick)) => {
+                    directives.extend(self.trees.values_mut().flat_map(|t| t.on_world_tick(tick)));
+                }
+                _ => {}
+            }
+        }
+        for directive in directives {
+            self.send(directive);
+        }
+        arrived
+    }
+
+    /// [`Self::step`] `ticks` times; returns everything that reached the
+    /// daemon
+    pub fn run(&mut self, ticks: usize) -> Vec<ClientMessage> {
+        (0..ticks).flat_map(|_| self.step()).collect()
+    }
+
+    /// What happened so far
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    fn fault(&mut self, what: String) {
+        self.trace.0.push(TraceEvent::Fault {
+            tick: self.clock.server_tick,
+            what,
+        });
+    }
+
+    fn start_faults(&mut self, now: i64) {
+        let (starting, scheduled) = std::mem::take(&mut self.faults.scheduled)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(at, _)| *at <= now);
+        self.faults.scheduled = scheduled;
+        for (_, fault) in starting {
+            match fault {
+                Fault::Drop {
+                    message_type,
+                    count,
+                } => self.faults.drops.push((message_type, count)),
+                Fault::DelayResults { ticks, count } => {
+                    self.faults.delay = (count > 0).then_some((ticks, count))
+                }
+                Fault::DuplicateResults { count } => self.faults.duplicates += count,
+                Fault::ReorderAudio { window } => self.faults.reorder_window = window,
+                Fault::Disconnect if !self.faults.disconnected => {
+                    self.faults.disconnected = true;
+                    self.fault("disconnected".to_string());
+                }
+                Fault::Reconnect if self.faults.disconnected => {
+                    self.faults.disconnected = false;
+                    self.faults.hello_due = true;
+                    self.fault("reconnected".to_string());
+                }
+                Fault::Disconnect | Fault::Reconnect => {}
+            }
+        }
+    }
+
+    /// What of `sent` reaches the daemon
+    fn transmit(&mut self, sent: Vec<ClientMessage>) -> Vec<ClientMessage> {
+        if self.faults.disconnected {
+            return Vec::new();
+        }
+        let mut arrived = Vec::with_capacity(sent.len());
+        for message in sent {
+            let message_type = ClientEvent::type_of(&message).unwrap_or_default();
+            match self
+                .faults
+                .drops
+                .iter_mut()
+                .find(|(t, count)| t == message_type && *count > 0)
+            {
+                Some((_, count)) => {
+                    *count -= 1;
+                    self.fault(format!("dropped {}", message_type));
+                }
+                None => arrived.push(message),
+            }
+        }
+        self.faults.drops.retain(|(_, count)| *count > 0);
+        arrived
+    }
+
+    fn voice_frames(&mut self) -> Vec<ClientMessage> {
+        let now_ms = self.clock.now_ms();
+        let mut frames: Vec<ClientMessage> = Vec::new();
+        for talk in &mut self.talks {
+            frames.push(
+                VoicePcmFrame {
+                    npc_id: talk.npc_id.clone(),
+                    player_uuid: talk.player_uuid.clone(),
+                    pcm_data: self.silence.clone(),
+                    sequence: talk.next_sequence,
+                    timestamp_ms: now_ms,
+                    sample_rate_hz: VOICE_SAMPLE_RATE_HZ,
+                    format: PcmFormat::S16le as i32,
+                }
+                .into(),
+            );
+            talk.next_sequence += 1;
+        }
+        self.talks.retain(|t| t.next_sequence <= t.last_sequence);
+
+        let window = self.faults.reorder_window;
+        if window < 2 {
+            let mut held = std::mem::take(&mut self.faults.held_audio);
+            held.extend(frames);
+            return held;
+        }
+        self.faults.held_audio.extend(frames);
+        let held = self.faults.held_audio.len();
+        if held < window && !(self.talks.is_empty() && held > 0) {
+            return Vec::new();
+        }
+        let mut group = std::mem::take(&mut self.faults.held_audio);
+        for i in (1..group.len()).rev() {
+            group.swap(i, self.rng.range(0, i as i64) as usize);
+        }
+        self.fault(format!("reordered {} voice frames", group.len()));
+        group
+    }
+
+    /// The NPCs as a WorldTick shows them, with the MoveProgress of moves
+    /// underway. Moves cover their path evenly over their ticks.
+    fn npc_snapshots(&self, now: i64) -> Vec<NpcSnapshot> {
+        let mut npcs = self.npcs.clone();
+        for pending in self.pending.iter().filter(|p| !p.delayed) {
+            let Some(Action::Move(action)) = &pending.directive.action else {
+                continue;
+            };
+            let Some(npc) = npcs
+                .iter_mut()
+                .find(|npc| npc.npc_id == pending.directive.npc_id)
+            else {
+                continue;
+            };
+            let (path_length, nodes_expanded) = move_cost(npc.position.as_ref(), action);
+            let ticks_left = (pending.due_tick - now).max(0);
+            let ticks = (pending.due_tick - pending.sent_tick).max(1);
+            npc.move_progress = Some(MoveProgress {
+                directive_id: pending.directive.directive_id.clone(),
+                path_length,
+                remaining_length: path_length * ticks_left as f64 / ticks as f64,
+                nodes_expanded,
+                eta_ms: (ticks_left as f64 * 1_000.0 / NOMINAL_TPS) as i64,
+            });
+        }
+        npcs
+    }
+
+    fn finish(&mut self, finished: Pending) -> ActionResult {
+        let Pending {
+            directive, success, ..
+        } = finished;
+        let npc = self
+            .npcs
+            .iter_mut()
+            .find(|npc| npc.npc_id == directive.npc_id);
+        let success = success && npc.is_some();
+        let mut result = ActionResult {
+            directive_id: directive.directive_id.clone(),
+            npc_id: directive.npc_id.clone(),
+            success,
+            ..Default::default()
+        };
+        if !success {
+            result.error_message = "simulated failure".to_string();
+        } else if let Some(npc) = npc {
+            // Only what behavior trees look at changes: where the NPC is
+            // and how hungry
+            match &directive.action {
+                Some(Action::Move(action)) => {
+                    let (path_length, nodes_expanded) = move_cost(npc.position.as_ref(), action);
+                    npc.position = action.target.clone();
+                    result.result = Some(ActionResultType::MoveResult(MoveResult {
+                        final_position: action.target.clone(),
+                        reached_destination: true,
+                        path_length,
+                        nodes_expanded,
+                        ..Default::default()
+                    }));
+                }
+                Some(Action::ConsumeItem(_)) => npc.hunger_norm = 1.0,
+                Some(Action::CheckLineOfSight(action)) => {
+                    let target = match &action.target {
+                        Some(Target::Position(position)) => Some(position),
+                        Some(Target::EntityUuid(uuid)) => self
+                            .players
+                            .iter()
+                            .find(|player| player.player_uuid == *uuid)
+                            .and_then(|player| player.position.as_ref()),
+                        None => None,
+                    };
+                    match line_of_sight(npc.position.as_ref(), target, action.max_distance) {
+                        Some(sight) => {
+                            result.result = Some(ActionResultType::CheckLineOfSightResult(sight))
+                        }
+                        None => {
+                            result.success = false;
+                            result.error_message = "target not in the NPC's world".to_string();
+                        }
+                    }
+                }
+                Some(Action::GiveItem(action)) => {
+                    // Simulated inventories never fill up
+                    let player = self
+                        .players
+                        .iter()
+                        .find(|player| player.player_uuid == action.player_uuid)
+                        .and_then(|player| player.position.as_ref());
+                    match (npc.position.as_ref(), player) {
+                        (Some(from), Some(to)) if same_world(from, to) => {
+                            result.result = Some(ActionResultType::GiveItemResult(GiveItemResult {
+                                player_uuid: action.player_uuid.clone(),
+                                item_type: action.item_type.clone(),
+                                accepted: action.count,
+                                dropped: 0,
+                            }))
+                        }
+                        _ => {
+                            result.success = false;
+                            result.error_code = ActionErrorCode::Precondition as i32;
+                            result.error_message = "player not in the NPC's world".to_string();
+                        }
+                    }
+                }
+                Some(Action::WriteBook(action)) => {
+                    // Pages are never cut and inventories never fill up
+                    let copies = action.copies.max(1);
+                    let reachable = action.player_uuid.is_empty()
+                        || self
+                            .players
+                            .iter()
+                            .find(|player| player.player_uuid == action.player_uuid)
+                            .and_then(|player| player.position.as_ref())
+                            .zip(npc.position.as_ref())
+                            .is_some_and(|(to, from)| same_world(from, to));
+                    if reachable {
+                        result.result = Some(ActionResultType::WriteBookResult(WriteBookResult {
+                            title: action.title.clone(),
+                            pages: action.pages.len() as i32,
+                            copies,
+                            player_uuid: action.player_uuid.clone(),
+                            dropped: 0,
+                        }))
+                    } else {
+                        result.success = false;
+                        result.error_code = ActionErrorCode::Precondition as i32;
+                        result.error_message = "player not in the NPC's world".to_string();
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.trace.0.push(TraceEvent::Result {
+            tick: self.clock.server_tick,
+            npc_id: result.npc_id.clone(),
+            directive_id: result.directive_id.clone(),
+            success: result.success,
+        });
+        self.completed
+            .insert(result.directive_id.clone(), result.clone());
+        result
+    }
+}
+
+/// What a simulated NPC at `from` sees of `target`: the world has no
+/// blocks, so the target is visible within `max_distance` (0 =
+/// [`SIGHT_BLOCKS`]). None if either is unknown or they are in different
+/// worlds.
+fn line_of_sight(
+    from: Option<&Position>,
+    target: Option<&Position>,
+    max_distance: f32,
+) -> Option<CheckLineOfSightResult> {
+    let (from, target) = (from?, target?);
+    if !same_world(from, target) {
+        return None;
+    }
+    let range = if max_distance > 0.0 {
+        max_distance as f64
+    } else {
+        SIGHT_BLOCKS
+    };
+    let distance = distance(from, target);
+    let facing = Vec3::from_yaw_pitch(from.yaw, from.pitch);
+    let in_field_of_view = direction(from, target)
+        .is_none_or(|toward| facing.dot(toward) >= FIELD_OF_VIEW_HALF_DEGREES.to_radians().cos());
+    Some(CheckLineOfSightResult {
+        visible: distance <= range,
+        in_field_of_view,
+        distance: distance as f32,
+        ..Default::default()
+    })
+}
+
+/// Path length and pathfinder nodes of a simulated move from `from`: a
+/// straight line, one node per block when `pathfind` is set
+fn move_cost(from: Option<&Position>, action: &MoveAction) -> (f64, i32) {
+    let path_length = match (from, action.target.as_ref()) {
+        (Some(from), Some(target)) if same_world(from, target) => distance(from, target),
+        _ => 0.0,
+    };
+    let nodes_expanded = if action.pathfind {
+        path_length.ceil() as i32
+    } else {
+        0
+    };
+    (path_length, nodes_expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::behavior::{action, condition, sequence};
+    use crate::npc_society::v1::{
+        BlockPosition, BreakBlockAction, CheckLineOfSightAction, GiveItemAction, StopAction,
+        WriteBookAction,
+    };
+
+    fn wander(npc_id: &str) -> BehaviorTree {
+        let root = sequence(vec![
+            condition(|bb| bb.server_tick % 10 == 0),
+            action("move", 1, |bb| {
+                let p = bb.npc.position.as_ref()?;
+                Some(Action::Move(MoveAction {
+                    target: Some(Position {
+                        x: p.x + 1.0,
+                        ..p.clone()
+                    }),
+                    ..Default::default()
+                }))
+            }),
+        ]);
+        BehaviorTree::new(npc_id, root)
+    }
+
+    fn traced(seed: u64) -> (String, Vec<NpcSnapshot>) {
+        let mut sim = Simulation::new(SimConfig {
+            seed,
+            ..Default::default()
+        });
+        for npc_id in sim.npc_ids() {
+            sim.attach(wander(&npc_id));
+        }
+        sim.run(200);
+        (sim.trace().to_string(), sim.npcs().to_vec())
+    }
+
+    #[test]
+    fn test_same_seed_same_trace() {
+        let (trace, npcs) = traced(42);
+        assert!(trace.lines().count() > 20);
+        assert!(trace.contains("sim_npc_0 directive sim_npc_0-bt-1 move"));
+        assert!(trace.contains(" failed\n"));
+        assert_eq!(traced(42), (trace.clone(), npcs));
+        assert_ne!(traced(43).0, trace);
+    }
+
+    #[test]
+    fn test_virtual_clock_and_results() {
+        let mut sim = Simulation::new(SimConfig {
+            action_ticks: (3, 3),
+            failure_rate: 0.0,
+            ..Default::default()
+        });
+        assert_eq!(sim.clock().now_ms(), EPOCH_MS);
+        let npc = sim.npcs()[1].clone();
+        let target = Position {
+            x: 100.0,
+            ..npc.position.clone().unwrap()
+        };
+        let path_length = 100.0 - npc.position.as_ref().unwrap().x;
+        sim.send(ActionDirective {
+            directive_id: "d1".to_string(),
+            npc_id: npc.npc_id.clone(),
+            action: Some(Action::Move(MoveAction {
+                target: Some(target.clone()),
+                pathfind: true,
+                ..Default::default()
+            })),
+            ..Default::default()
+        });
+
+        // Only WorldTicks until the action's three ticks are up, showing
+        // the move's progress
+        for remaining in [2.0, 1.0] {
+            let sent = sim.step();
+            assert_eq!(sent.len(), 1);
+            let Some(ClientMsg::WorldTick(tick)) = &sent[0].message else {
+                panic!("Expected WorldTick");
+            };
+            let progress = tick.npcs[1].move_progress.as_ref().unwrap();
+            assert_eq!(progress.directive_id, "d1");
+            assert_eq!(progress.path_length, path_length);
+            assert_eq!(progress.remaining_length, path_length * remaining / 3.0);
+            assert_eq!(progress.eta_ms, remaining as i64 * 50);
+            assert!(tick.npcs[0].move_progress.is_none());
+        }
+        let sent = sim.step();
+        assert_eq!(sim.clock().server_tick(), 3);
+        assert_eq!(sim.clock().now_ms(), EPOCH_MS + 150);
+        let Some(ClientMsg::ActionResult(result)) = &sent[0].message else {
+            panic!("Expected ActionResult first");
+        };
+        assert!(result.success);
+        let Some(ActionResultType::MoveResult(moved)) = &result.result else {
+            panic!("Expected MoveResult");
+        };
+        assert_eq!(moved.path_length, path_length);
+        assert_eq!(moved.nodes_expanded, path_length.ceil() as i32);
+        assert!(moved.partial_path.is_empty());
+        let Some(ClientMsg::WorldTick(tick)) = &sent[1].message else {
+            panic!("Expected WorldTick");
+        };
+        assert_eq!(tick.npcs[1].position, Some(target));
+        assert!(tick.npcs[1].move_progress.is_none());
+    }
+
+    fn stop(directive_id: &str) -> ActionDirective {
+        ActionDirective {
+            directive_id: directive_id.to_string(),
+            npc_id: "sim_npc_0".to_string(),
+            action: Some(Action::Stop(StopAction::default())),
+            ..Default::default()
+        }
+    }
+
+    fn results(sent: &[ClientMessage]) -> Vec<(&str, bool)> {
+        sent.iter()
+            .filter_map(|m| match &m.message {
+                Some(ClientMsg::ActionResult(r)) => Some((r.directive_id.as_str(), r.replayed)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn exact() -> Simulation {
+        Simulation::new(SimConfig {
+            action_ticks: (2, 2),
+            failure_rate: 0.0,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_drop_delay_duplicate() {
+        let mut sim = exact();
+        sim.inject(
+            1,
+            Fault::Drop {
+                message_type: "ActionResult".to_string(),
+                count: 1,
+            },
+        );
+        sim.inject(3, Fault::DuplicateResults { count: 1 });
+        sim.inject(5, Fault::DelayResults { ticks: 5, count: 1 });
+
+        sim.send(stop("d1"));
+        assert!(results(&sim.run(2)).is_empty());
+        sim.send(stop("d2"));
+        assert_eq!(results(&sim.run(2)), vec![("d2", false), ("d2", false)]);
+        sim.send(stop("d3"));
+        assert!(results(&sim.run(6)).is_empty());
+        assert_eq!(results(&sim.step()), vec![("d3", false)]);
+
+        let trace = sim.trace().to_string();
+        assert!(trace.contains("2 sim_npc_0 result d1 ok\n2 fault dropped ActionResult\n"));
+        assert!(trace.contains("4 fault duplicated d2\n"));
+        assert!(trace.contains("6 fault delayed d3\n"));
+        assert!(trace.ends_with("11 sim_npc_0 result d3 ok\n"));
+    }
+
+    #[test]
+    fn test_disconnect_and_resend() {
+        let mut sim = exact();
+        sim.inject(2, Fault::Disconnect);
+        sim.inject(5, Fault::Reconnect);
+        sim.send(stop("d1"));
+
+        assert_eq!(sim.step().len(), 1);
+        // The result at tick 2 is lost with the stream
+        for _ in 2..5 {
+            assert!(sim.step().is_empty());
+            assert!(!sim.is_connected());
+        }
+        sim.send(stop("lost"));
+        let sent = sim.step();
+        assert!(matches!(sent[0].message, Some(ClientMsg::Hello(_))));
+
+        // Resent after the Hello: answered again, not run again
+        sim.send(stop("d1"));
+        sim.send(stop("d1"));
+        assert_eq!(results(&sim.step()), vec![("d1", true), ("d1", true)]);
+        let trace = sim.trace().to_string();
+        assert!(trace.contains("4 fault lost lost\n"));
+        assert_eq!(trace.matches("result d1").count(), 1);
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let mut sim = exact();
+        let npc = sim.npcs()[0].clone();
+        let from = npc.position.clone().unwrap();
+        let walk = |target: Position| ActionDirective {
+            directive_id: "plan".to_string(),
+            npc_id: npc.npc_id.clone(),
+            dry_run: true,
+            action: Some(Action::Move(MoveAction {
+                target: Some(target),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        let result = sim.preview(&walk(Position {
+            x: from.x + 43.0,
+            ..from.clone()
+        }));
+        assert!(result.success);
+        let estimate = result.estimate.unwrap();
+        assert_eq!(estimate.path_length, 43.0);
+        // 10s walking and the two ticks every action takes
+        assert_eq!(estimate.duration_ms, 10_100);
+        assert!(estimate.affected_blocks.is_empty());
+
+        let nether = sim.preview(&walk(Position {
+            world: "world_nether".to_string(),
+            ..from.clone()
+        }));
+        assert!(!nether.success);
+        assert_eq!(nether.error_code(), ActionErrorCode::Precondition);
+        assert!(nether.estimate.is_none());
+
+        let block = BlockPosition {
+            world: from.world.clone(),
+            x: from.x as i32 + 2,
+            y: from.y as i32,
+            z: from.z as i32,
+            ..Default::default()
+        };
+        let mut directive = stop("break");
+        directive.dry_run = true;
+        directive.action = Some(Action::BreakBlock(BreakBlockAction {
+            position: Some(block.clone()),
+        }));
+        let estimate = sim.preview(&directive).estimate.unwrap();
+        assert_eq!(estimate.path_length, 0.0);
+        assert_eq!(estimate.affected_blocks, [block]);
+
+        // Sent for real as a dry run: answered on the next step, nothing runs
+        sim.send(walk(Position {
+            x: from.x + 43.0,
+            ..from.clone()
+        }));
+        let sent = sim.step();
+        let Some(ClientMsg::ActionResult(result)) = &sent[0].message else {
+            panic!("Expected ActionResult first");
+        };
+        assert_eq!(result.estimate.as_ref().unwrap().path_length, 43.0);
+        sim.run(5);
+        assert_eq!(sim.npcs()[0].position, Some(from));
+        assert_eq!(sim.trace().to_string(), "0 sim_npc_0 dry_run plan move\n");
+    }
+
+    #[test]
+    fn test_line_of_sight() {
+        let mut sim = exact();
+        let from = sim.npcs()[0].position.clone().unwrap();
+        let forward = Vec3::from_yaw_pitch(from.yaw, from.pitch);
+        let at = |blocks: f64| Position {
+            x: from.x + forward.x * blocks,
+            y: from.y + forward.y * blocks,
+            z: from.z + forward.z * blocks,
+            ..from.clone()
+        };
+        let look = |directive_id: &str, target: Position, max_distance: f32| ActionDirective {
+            directive_id: directive_id.to_string(),
+            npc_id: "sim_npc_0".to_string(),
+            action: Some(Action::CheckLineOfSight(CheckLineOfSightAction {
+                target: Some(Target::Position(target)),
+                max_distance,
+            })),
+            ..Default::default()
+        };
+        sim.send(look("ahead", at(10.0), 0.0));
+        sim.send(look("behind", at(-10.0), 0.0));
+        sim.send(look("far", at(10.0), 5.0));
+        sim.send(look(
+            "nether",
+            Position {
+                world: "world_nether".to_string(),
+                ..from.clone()
+            },
+            0.0,
+        ));
+        let sent = sim.run(2);
+        let sights: BTreeMap<_, _> = sent
+            .iter()
+            .filter_map(|m| match &m.message {
+                Some(ClientMsg::ActionResult(r)) => Some((r.directive_id.as_str(), r)),
+                _ => None,
+            })
+            .map(|(id, r)| match &r.result {
+                Some(ActionResultType::CheckLineOfSightResult(sight)) => {
+                    (id, Some((sight.visible, sight.in_field_of_view)))
+                }
+                _ => (id, None),
+            })
+            .collect();
+        assert_eq!(sights["ahead"], Some((true, true)));
+        assert_eq!(sights["behind"], Some((true, false)));
+        assert_eq!(sights["far"], Some((false, true)));
+        assert_eq!(sights["nether"], None);
+    }
+
+    #[test]
+    fn test_give_item() {
+        let mut sim = exact();
+        let player_uuid = sim.players[0].player_uuid.clone();
+        let give = |directive_id: &str, player_uuid: &str| ActionDirective {
+            directive_id: directive_id.to_string(),
+            npc_id: "sim_npc_0".to_string(),
+            action: Some(Action::GiveItem(GiveItemAction {
+                player_uuid: player_uuid.to_string(),
+                item_type: "minecraft:emerald".to_string(),
+                count: 3,
+                from_inventory: false,
+            })),
+            ..Default::default()
+        };
+        sim.send(give("reward", &player_uuid));
+        sim.send(give("nobody", "00000000-0000-0000-0000-000000000000"));
+        let results: BTreeMap<_, _> = sim
+            .run(2)
+            .into_iter()
+            .filter_map(|m| match m.message {
+                Some(ClientMsg::ActionResult(r)) => Some((r.directive_id.clone(), r)),
+                _ => None,
+            })
+            .collect();
+        let Some(ActionResultType::GiveItemResult(gift)) = &results["reward"].result else {
+            panic!("no GiveItemResult");
+        };
+        assert_eq!((gift.accepted, gift.dropped), (3, 0));
+        assert_eq!(
+            results["nobody"].error_code(),
+            ActionErrorCode::Precondition
+        );
+    }
+
+    #[test]
+    fn test_write_book() {
+        let mut sim = exact();
+        let player_uuid = sim.players[0].player_uuid.clone();
+        let write = |directive_id: &str, player_uuid: &str| ActionDirective {
+            directive_id: directive_id.to_string(),
+            npc_id: "sim_npc_0".to_string(),
+            action: Some(Action::WriteBook(WriteBookAction {
+                title: "The Old Mine".to_string(),
+                pages: vec!["Long ago...".to_string(), "The end.".to_string()],
+                player_uuid: player_uuid.to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        sim.send(write("handout", &player_uuid));
+        sim.send(write("kept", ""));
+        sim.send(write("nobody", "00000000-0000-0000-0000-000000000000"));
+        let results: BTreeMap<_, _> = sim
+            .run(2)
+            .into_iter()
+            .filter_map(|m| match m.message {
+                Some(ClientMsg::ActionResult(r)) => Some((r.directive_id.clone(), r)),
+                _ => None,
+            })
+            .collect();
+        let Some(ActionResultType::WriteBookResult(book)) = &results["handout"].result else {
+            panic!("no WriteBookResult");
+        };
+        assert_eq!((book.pages, book.copies), (2, 1));
+        assert_eq!(book.player_uuid, player_uuid);
+        assert!(results["kept"].success);
+        assert_eq!(
+            results["nobody"].error_code(),
+            ActionErrorCode::Precondition
+        );
+    }
+
+    #[test]
+    fn test_reorder_audio() {
+        let mut sim = exact();
+        sim.inject(1, Fault::ReorderAudio { window: 3 });
+        sim.talk(0, "sim_npc_0", 6);
+        let sequences: Vec<u64> = sim
+            .run(8)
+            .iter()
+            .filter_map(|m| match &m.message {
+                Some(ClientMsg::VoicePcmFrame(f)) => Some(f.sequence),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sequences.len(), 6);
+        assert_ne!(sequences, vec![1, 2, 3, 4, 5, 6]);
+        let mut first: Vec<u64> = sequences[..3].to_vec();
+        first.sort_unstable();
+        assert_eq!(first, vec![1, 2, 3]);
+    }
+}
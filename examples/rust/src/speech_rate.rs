@@ -0,0 +1,368 @@
+//! How often each NPC may speak.
+//!
+//! An NPC driven by an LLM can talk itself into a loop: every line it says
+//! is heard by another NPC, which answers, and players get a wall of
+//! subtitles until they mute the plugin. A [`SpeechGovernor`] limits every
+//! NPC's SpeakDirectives with a [`SpeechRate`]:
+//!
+//! - `burst` lines may follow each other straight away; after that one line
+//!   every `min_interval_ms`
+//! - never more than `max_per_minute` lines in any 60 seconds
+//!
+//! A line over the limit is dropped or, with [`Overflow::Queue`], held
+//! until the NPC may speak again; [`SpeechGovernor::release`] hands out the
+//! held lines that are due, and drops those that waited too long to still
+//! make sense. Each NPC can have its own rate, e.g. from its profile: a
+//! town crier talks more than a guard.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use crate::npc_society::v1::SpeakDirective;
+
+/// Window of [`SpeechRate::max_per_minute`]
+const MINUTE_MS: i64 = 60_000;
+
+/// What happens to a line over the limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Say nothing
+    Drop,
+    /// Hold the line until the NPC may speak
+    Queue {
+        /// Lines held per NPC; a new line pushes out the oldest
+        max_queued: usize,
+        /// Held lines older than this are dropped
+        max_wait_ms: i64,
+    },
+}
+
+/// Limits on one NPC's speech
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeechRate {
+    /// Spacing of lines once a burst is used up (0 = no spacing)
+    pub min_interval_ms: i64,
+    /// Lines that may follow each other without spacing, at least 1
+    pub burst: u32,
+    /// Lines in any 60 seconds (0 = unlimited)
+    pub max_per_minute: u32,
+    /// Lines over the limit
+    pub overflow: Overflow,
+}
+
+impl Default for SpeechRate {
+    /// 3 lines at once, then one every 2 seconds, at most 12 a minute;
+    /// the rest are dropped
+    fn default() -> Self {
+        Self {
+            min_interval_ms: 2_000,
+            burst: 3,
+            max_per_minute: 12,
+            overflow: Overflow::Drop,
+        }
+    }
+}
+
+/// A line the governor would not let through now
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Muted {
+    /// The NPC that talks too much
+    pub npc_id: String,
+    /// When it may speak again (Unix ms)
+    pub retry_at_ms: i64,
+}
+
+impl fmt::Display for Muted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} speaks too often; next line allowed at {}",
+            self.npc_id, self.retry_at_ms
+        )
+    }
+}
+
+impl std::error::Error for Muted {}
+
+/// What [`SpeechGovernor::submit`] did with a line
+#[derive(Debug, Clone, PartialEq)]
+pub enum Admission {
+    /// Send it now
+    Speak(Box<SpeakDirective>),
+    /// Held; [`SpeechGovernor::release`] returns it when it is due
+    Queued {
+        /// Lines of the NPC now held, this one included
+        queued: usize,
+    },
+    /// Not sent
+    Dropped(Muted),
+}
+
+/// One NPC's recent speech
+#[derive(Debug, Clone)]
+struct Voice {
+    /// Lines left in the burst
+    tokens: f64,
+    refilled_at_ms: Option<i64>,
+    /// When the lines of the last minute were said, oldest first
+    spoken: VecDeque<i64>,
+    /// Held lines and when they were submitted
+    queue: VecDeque<(i64, SpeakDirective)>,
+}
+
+impl Voice {
+    fn new(rate: &SpeechRate) -> Self {
+        Self {
+            tokens: f64::from(rate.burst.max(1)),
+            refilled_at_ms: None,
+            spoken: VecDeque::new(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Take a line's allowance at `now_ms`, or say when there is one
+    fn speak(&mut self, rate: &SpeechRate, now_ms: i64) -> Result<(), i64> {
+        let burst = f64::from(rate.burst.max(1));
+        if rate.min_interval_ms > 0 {
+            if let Some(refilled_at_ms) = self.refilled_at_ms {
+                let elapsed = (now_ms - refilled_at_ms).max(0) as f64;
+                self.tokens += elapsed / rate.min_interval_ms as f64;
+            }
+        } else {
+            self.tokens = burst;
+        }
+        self.tokens = self.tokens.min(burst);
+        self.refilled_at_ms = Some(now_ms);
+        while self
+            .spoken
+            .front()
+            .is_some_and(|&at| at <= now_ms - MINUTE_MS)
+        {
+            self.spoken.pop_front();
+        }
+
+        let mut retry_at_ms = now_ms;
+        if self.tokens < 1.0 {
+            let wait = (1.0 - self.tokens) * rate.min_interval_ms as f64;
+            retry_at_ms = retry_at_ms.max(now_ms + wait.ceil() as i64);
+        }
+        let max = rate.max_per_minute as usize;
+        if max > 0 && self.spoken.len() >= max {
+            let oldest = self.spoken[self.spoken.len() - max];
+            retry_at_ms = retry_at_ms.max(oldest + MINUTE_MS);
+        }
+        if retry_at_ms > now_ms {
+            return Err(retry_at_ms);
+        }
+        self.tokens -= 1.0;
+        if max > 0 {
+            self.spoken.push_back(now_ms);
+            while self.spoken.len() > max {
+                self.spoken.pop_front();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Speech limits of every NPC
+#[derive(Debug, Clone, Default)]
+pub struct SpeechGovernor {
+    default: SpeechRate,
+    rates: HashMap<String, SpeechRate>,
+    voices: HashMap<String, Voice>,
+    dropped: u64,
+}
+
+impl SpeechGovernor {
+    /// Limit NPCs without a rate of their own to `default`
+    pub fn new(default: SpeechRate) -> Self {
+        Self {
+            default,
+            ..Self::default()
+        }
+    }
+
+    /// Limit `npc_id` to `rate` instead of the default. What it said
+    /// recently still counts.
+    pub fn set_rate(&mut self, npc_id: &str, rate: SpeechRate) {
+        self.rates.insert(npc_id.to_string(), rate);
+    }
+
+    /// Back to the default rate for `npc_id`
+    pub fn clear_rate(&mut self, npc_id: &str) {
+        self.rates.remove(npc_id);
+    }
+
+    /// The rate `npc_id` is limited to
+    pub fn rate(&self, npc_id: &str) -> &SpeechRate {
+        self.rates.get(npc_id).unwrap_or(&self.default)
+    }
+
+    /// Count a line of `npc_id` at `now_ms` if the rate allows one, for
+    /// speech that does not go through [`SpeechGovernor::submit`]
+    pub fn try_speak(&mut self, npc_id: &str, now_ms: i64) -> Result<(), Muted> {
+        let rate = *self.rate(npc_id);
+        self.voices
+            .entry(npc_id.to_string())
+            .or_insert_with(|| Voice::new(&rate))
+            .speak(&rate, now_ms)
+            .map_err(|retry_at_ms| Muted {
+                npc_id: npc_id.to_string(),
+                retry_at_ms,
+            })
+    }
+
+    /// Let `speak` through, hold it or drop it, as its NPC's rate says.
+    /// While lines of the NPC are held, new ones queue behind them.
+    pub fn submit(&mut self, speak: SpeakDirective, now_ms: i64) -> Admission {
+        let rate = *self.rate(&speak.npc_id);
+        let voice = self
+            .voices
+            .entry(speak.npc_id.clone())
+            .or_insert_with(|| Voice::new(&rate));
+        let retry_at_ms = match voice.queue.back() {
+            None => match voice.speak(&rate, now_ms) {
+                Ok(()) => return Admission::Speak(Box::new(speak)),
+                Err(retry_at_ms) => retry_at_ms,
+            },
+            Some(_) => now_ms,
+        };
+        match rate.overflow {
+            Overflow::Drop => {
+                self.dropped += 1;
+                Admission::Dropped(Muted {
+                    npc_id: speak.npc_id,
+                    retry_at_ms,
+                })
+            }
+            Overflow::Queue { max_queued, .. } => {
+                if voice.queue.len() >= max_queued.max(1) {
+                    voice.queue.pop_front();
+                    self.dropped += 1;
+                }
+                voice.queue.push_back((now_ms, speak));
+                Admission::Queued {
+                    queued: voice.queue.len(),
+                }
+            }
+        }
+    }
+
+    /// Held lines that may be said at `now_ms`, in the order they were
+    /// submitted per NPC. Lines held longer than `max_wait_ms` are dropped.
+    pub fn release(&mut self, now_ms: i64) -> Vec<SpeakDirective> {
+        let mut due = Vec::new();
+        for (npc_id, voice) in &mut self.voices {
+            let rate = *self.rates.get(npc_id).unwrap_or(&self.default);
+            let max_wait_ms = match rate.overflow {
+                Overflow::Queue { max_wait_ms, .. } => max_wait_ms,
+                // The rate changed since the lines were held
+                Overflow::Drop => 0,
+            };
+            while let Some((queued_at_ms, _)) = voice.queue.front() {
+                if now_ms - queued_at_ms > max_wait_ms {
+                    voice.queue.pop_front();
+                    self.dropped += 1;
+                    continue;
+                }
+                if voice.speak(&rate, now_ms).is_err() {
+                    break;
+                }
+                due.extend(voice.queue.pop_front().map(|(_, speak)| speak));
+            }
+        }
+        due
+    }
+
+    /// Lines of `npc_id` held now
+    pub fn queued(&self, npc_id: &str) -> usize {
+        self.voices.get(npc_id).map_or(0, |voice| voice.queue.len())
+    }
+
+    /// Lines dropped so far, over the limit or after waiting too long
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(npc_id: &str, text: &str) -> SpeakDirective {
+        SpeakDirective {
+            npc_id: npc_id.to_string(),
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_burst_interval_and_minute() {
+        let mut governor = SpeechGovernor::new(SpeechRate {
+            min_interval_ms: 1_000,
+            burst: 2,
+            max_per_minute: 4,
+            overflow: Overflow::Drop,
+        });
+        assert!(governor.try_speak("crier", 0).is_ok());
+        assert!(governor.try_speak("crier", 0).is_ok());
+        let muted = governor.try_speak("crier", 0).unwrap_err();
+        assert_eq!(muted.retry_at_ms, 1_000);
+        // Others have their own allowance
+        assert!(governor.try_speak("guard", 0).is_ok());
+
+        assert!(governor.try_speak("crier", 1_000).is_ok());
+        assert!(governor.try_speak("crier", 2_000).is_ok());
+        // Four lines within the minute: wait until the first is a minute old
+        let muted = governor.try_speak("crier", 30_000).unwrap_err();
+        assert_eq!(muted.retry_at_ms, 60_000);
+        assert!(governor.try_speak("crier", 60_000).is_ok());
+
+        let (admitted, dropped) = (
+            governor.submit(line("guard", "Halt!"), 0),
+            governor.submit(line("guard", "Halt!!"), 0),
+        );
+        assert!(matches!(admitted, Admission::Speak(_)));
+        assert!(matches!(dropped, Admission::Dropped(_)));
+        assert_eq!(governor.dropped(), 1);
+    }
+
+    #[test]
+    fn test_queue_per_npc() {
+        let mut governor = SpeechGovernor::default();
+        governor.set_rate(
+            "bard",
+            SpeechRate {
+                min_interval_ms: 1_000,
+                burst: 1,
+                max_per_minute: 0,
+                overflow: Overflow::Queue {
+                    max_queued: 2,
+                    max_wait_ms: 5_000,
+                },
+            },
+        );
+        assert!(matches!(
+            governor.submit(line("bard", "one"), 0),
+            Admission::Speak(_)
+        ));
+        for (text, queued) in [("two", 1), ("three", 2), ("four", 2)] {
+            assert_eq!(
+                governor.submit(line("bard", text), 0),
+                Admission::Queued { queued }
+            );
+        }
+        // "two" was pushed out by "four"
+        assert_eq!(governor.dropped(), 1);
+        assert!(governor.release(500).is_empty());
+        let texts = |lines: Vec<SpeakDirective>| -> Vec<String> {
+            lines.into_iter().map(|speak| speak.text).collect()
+        };
+        assert_eq!(texts(governor.release(1_000)), ["three"]);
+        // "four" waited too long
+        assert!(governor.release(6_000).is_empty());
+        assert_eq!((governor.queued("bard"), governor.dropped()), (0, 2));
+    }
+}
@@ -0,0 +1,159 @@
+//! Furnace and brewing stand jobs (`SmeltAction` / `BrewAction`, v1.2+).
+//!
+//! Loading a station completes at once; the output is reported by a
+//! `StationOutputObservation` minutes to hours of ticks later, long after
+//! the directive's ActionResult. [`StationJobs`] remembers loaded jobs so
+//! the observation can be matched to what was loaded, and lists jobs past
+//! their estimated tick (stations only run in loaded chunks, so estimates
+//! slip and observations can come late).
+
+use std::collections::HashMap;
+
+use crate::npc_society::v1::{
+    action_directive::Action, action_result::Result as ActionResultType, ActionDirective,
+    ActionResult, BlockPosition, StationOutputObservation,
+};
+
+/// A loaded station awaiting its output
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationJob {
+    /// directive_id of the SmeltAction or BrewAction
+    pub directive_id: String,
+    /// NPC that loaded the station
+    pub npc_id: String,
+    /// Station block
+    pub station: BlockPosition,
+    /// Item loaded (input or ingredient)
+    pub input_item: String,
+    /// Items or bottles loaded (as requested until the ActionResult)
+    pub count: i32,
+    /// Estimated server tick of completion (0 until the ActionResult)
+    pub ready_at_tick: i64,
+}
+
+/// Station jobs in flight, by directive_id
+#[derive(Debug, Default)]
+pub struct StationJobs {
+    jobs: HashMap<String, StationJob>,
+}
+
+impl StationJobs {
+    /// Track `directive` if it loads a station; returns whether it does
+    /// (collect-only directives, with a count of 0, do not)
+    pub fn start(&mut self, directive: &ActionDirective) -> bool {
+        let (station, input_item, count) = match &directive.action {
+            Some(Action::Smelt(s)) => (&s.furnace_position, &s.input_item, s.count),
+            Some(Action::Brew(b)) => (&b.stand_position, &b.ingredient_item, b.bottle_count),
+            _ => return false,
+        };
+        let Some(station) = station.clone().filter(|_| count > 0) else {
+            return false;
+        };
+        self.jobs.insert(
+            directive.directive_id.clone(),
+            StationJob {
+                directive_id: directive.directive_id.clone(),
+                npc_id: directive.npc_id.clone(),
+                station,
+                input_item: input_item.clone(),
+                count,
+                ready_at_tick: 0,
+            },
+        );
+        true
+    }
+
+    /// Record the loading result: a failed or empty load drops the job.
+    /// Returns the job if it is now waiting for its output.
+    pub fn loaded(&mut self, result: &ActionResult) -> Option<&StationJob> {
+        let (count, ready_at_tick) = match &result.result {
+            Some(ActionResultType::SmeltResult(s)) => (s.items_loaded, s.ready_at_tick),
+            Some(ActionResultType::BrewResult(b)) => (b.bottles_loaded, b.ready_at_tick),
+            _ => (0, 0),
+        };
+        if !result.success || count == 0 {
+            self.jobs.remove(&result.directive_id);
+            return None;
+        }
+        let job = self.jobs.get_mut(&result.directive_id)?;
+        job.count = count;
+        job.ready_at_tick = ready_at_tick;
+        Some(job)
+    }
+
+    /// The job an observation reports on, no longer tracked
+    pub fn finish(&mut self, output: &StationOutputObservation) -> Option<StationJob> {
+        self.jobs.remove(&output.directive_id)
+    }
+
+    /// Jobs of `npc_id`
+    pub fn jobs_of<'a>(&'a self, npc_id: &'a str) -> impl Iterator<Item = &'a StationJob> + 'a {
+        self.jobs.values().filter(move |j| j.npc_id == npc_id)
+    }
+
+    /// Loaded jobs still unreported `grace_ticks` after their estimate
+    pub fn overdue(&self, server_tick: i64, grace_ticks: i64) -> impl Iterator<Item = &StationJob> {
+        self.jobs
+            .values()
+            .filter(move |j| j.ready_at_tick > 0 && server_tick > j.ready_at_tick + grace_ticks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{SmeltAction, SmeltResult};
+
+    fn smelt(id: &str, count: i32) -> ActionDirective {
+        ActionDirective {
+            directive_id: id.to_string(),
+            npc_id: "smith".to_string(),
+            action: Some(Action::Smelt(SmeltAction {
+                furnace_position: Some(BlockPosition::default()),
+                input_item: "minecraft:raw_iron".to_string(),
+                count,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    fn loaded(id: &str, items_loaded: i32) -> ActionResult {
+        ActionResult {
+            directive_id: id.to_string(),
+            success: true,
+            result: Some(ActionResultType::SmeltResult(SmeltResult {
+                items_loaded,
+                ready_at_tick: 1000 + 200 * items_loaded as i64,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_jobs() {
+        let mut jobs = StationJobs::default();
+        assert!(!jobs.start(&smelt("collect", 0)));
+        assert!(jobs.start(&smelt("s1", 16)));
+        assert!(jobs.start(&smelt("s2", 8)));
+
+        // Only 12 raw iron in the inventory
+        assert_eq!(jobs.loaded(&loaded("s1", 12)).unwrap().count, 12);
+        assert!(jobs.loaded(&loaded("s2", 0)).is_none());
+        assert_eq!(jobs.jobs_of("smith").count(), 1);
+
+        assert_eq!(jobs.overdue(3500, 100).count(), 0);
+        assert_eq!(jobs.overdue(3501, 100).count(), 1);
+
+        let output = StationOutputObservation {
+            directive_id: "s1".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            jobs.finish(&output).unwrap().input_item,
+            "minecraft:raw_iron"
+        );
+        assert!(jobs.finish(&output).is_none());
+    }
+}
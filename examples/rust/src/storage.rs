@@ -0,0 +1,76 @@
+//! NPC-owned storage (`ClaimContainerDirective`, v1.2+).
+//!
+//! A shopkeeper's stock or a miner's haul sits in a chest any player can
+//! empty. A ClaimContainerDirective makes the container the NPC's: the
+//! plugin titles it, keeps it shut to everyone but the owner and allowed
+//! players when locked, and reports who opened it or took what with a
+//! ContainerAccessObservation. [`claim`] and [`release`] build the
+//! directives, [`stolen`] picks the thefts out of the observations.
+
+use crate::npc_society::v1::{
+    BlockPosition, ClaimContainerDirective, ContainerAccess, ContainerAccessObservation, ItemStack,
+};
+
+/// Claim the container at `position` for `npc_id`; a `locked` one opens
+/// only for the NPC and `allowed_players`
+pub fn claim(
+    npc_id: &str,
+    position: BlockPosition,
+    name: &str,
+    locked: bool,
+    allowed_players: &[String],
+) -> ClaimContainerDirective {
+    ClaimContainerDirective {
+        npc_id: npc_id.to_string(),
+        position: Some(position),
+        name: name.to_string(),
+        locked,
+        allowed_players: allowed_players.to_vec(),
+        ..Default::default()
+    }
+}
+
+/// End the claim of `npc_id` on the container at `position`
+pub fn release(npc_id: &str, position: BlockPosition) -> ClaimContainerDirective {
+    ClaimContainerDirective {
+        npc_id: npc_id.to_string(),
+        position: Some(position),
+        release: true,
+        ..Default::default()
+    }
+}
+
+/// The items a player without permission took out of the container or
+/// spilled by breaking it; empty for everything else
+pub fn stolen(access: &ContainerAccessObservation) -> &[ItemStack] {
+    match access.access() {
+        ContainerAccess::Took | ContainerAccess::Broke if !access.allowed => &access.items,
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stolen() {
+        let access = |access: ContainerAccess, allowed| ContainerAccessObservation {
+            npc_id: "miner".to_string(),
+            access: access as i32,
+            player_uuid: "p1".to_string(),
+            allowed,
+            items: vec![ItemStack {
+                item_type: "minecraft:diamond".to_string(),
+                quantity: 3,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(stolen(&access(ContainerAccess::Took, false))[0].quantity, 3);
+        assert_eq!(stolen(&access(ContainerAccess::Broke, false)).len(), 1);
+        assert!(stolen(&access(ContainerAccess::Took, true)).is_empty());
+        assert!(stolen(&access(ContainerAccess::Put, false)).is_empty());
+        assert!(stolen(&access(ContainerAccess::Denied, false)).is_empty());
+    }
+}
@@ -0,0 +1,519 @@
+//! Wire tap: observers of every message on the stream.
+//!
+//! Logging from inside handlers misses what never reaches one (invalid,
+//! late or empty messages) and shows nothing of what the daemon sends.
+//! A [`Tap`] sees every message in both directions as it crosses the
+//! stream, before validation on the way in and after stamping on the way
+//! out, and hands it to each registered [`TapObserver`]:
+//!
+//! - [`LogTap`]: one log line per message
+//! - [`CaptureWriter`]: appends to a capture file, read back with
+//!   [`CaptureReader`] (e.g. to replay a session into a test)
+//! - [`TapBroadcast`]: forwards to live subscribers, such as a tail command
+//!
+//! Any `Fn(&TapRecord)` is an observer too. Observers run on the stream's
+//! task, so they must be quick; hand anything slow to a channel.
+//!
+//! A capture file starts with [`CAPTURE_MAGIC`], followed by one record
+//! per message: direction (1 byte, 0 = inbound), timestamp (i64 Unix ms,
+//! little-endian), server_id (u16 little-endian length, then UTF-8) and
+//! the envelope, length-delimited.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use prost::Message;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::events::{ClientEvent, ServerEvent};
+use crate::npc_society::v1::{ClientMessage, ServerMessage};
+
+/// First bytes of a capture file
+pub const CAPTURE_MAGIC: &[u8; 8] = b"NPCTAP1\n";
+
+/// Which way a message went
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Plugin -> daemon
+    Inbound,
+    /// Daemon -> plugin
+    Outbound,
+}
+
+/// A message on the stream, borrowed from the tap's caller
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frame<'a> {
+    Client(&'a ClientMessage),
+    Server(&'a ServerMessage),
+}
+
+impl Frame<'_> {
+    pub fn direction(&self) -> Direction {
+        match self {
+            Frame::Client(_) => Direction::Inbound,
+            Frame::Server(_) => Direction::Outbound,
+        }
+    }
+
+    /// Oneof variant name, e.g. `WorldTick` ("None" if unset)
+    pub fn message_type(&self) -> &'static str {
+        match self {
+            Frame::Client(m) => ClientEvent::type_of(m),
+            Frame::Server(m) => ServerEvent::type_of(m),
+        }
+        .unwrap_or("None")
+    }
+
+    /// The envelope's protobuf encoding
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Frame::Client(m) => m.encode_to_vec(),
+            Frame::Server(m) => m.encode_to_vec(),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        match self {
+            Frame::Client(m) => m.encoded_len(),
+            Frame::Server(m) => m.encoded_len(),
+        }
+    }
+
+    fn to_envelope(self) -> Envelope {
+        match self {
+            Frame::Client(m) => Envelope::Client(m.clone()),
+            Frame::Server(m) => Envelope::Server(m.clone()),
+        }
+    }
+}
+
+/// One message as observers see it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TapRecord<'a> {
+    /// Server of the connection (empty before its Hello)
+    pub server_id: &'a str,
+    /// When the message crossed the stream (Unix ms)
+    pub timestamp_ms: i64,
+    /// Encoded envelope size, before gRPC framing and compression
+    pub size: usize,
+    pub frame: Frame<'a>,
+}
+
+impl TapRecord<'_> {
+    pub fn direction(&self) -> Direction {
+        self.frame.direction()
+    }
+
+    /// An owned copy, e.g. to send to another task
+    pub fn to_captured(self) -> Captured {
+        Captured {
+            server_id: self.server_id.to_string(),
+            timestamp_ms: self.timestamp_ms,
+            envelope: self.frame.to_envelope(),
+        }
+    }
+}
+
+/// Receives every message a [`Tap`] sees
+pub trait TapObserver: Send + Sync {
+    fn observe(&self, record: &TapRecord<'_>);
+}
+
+impl<F: Fn(&TapRecord<'_>) + Send + Sync> TapObserver for F {
+    fn observe(&self, record: &TapRecord<'_>) {
+        self(record)
+    }
+}
+
+type Observers = Vec<(TapId, Arc<dyn TapObserver>)>;
+
+/// Handle of a registered observer, for [`Tap::unregister`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TapId(u64);
+
+/// Observers shared by every connection; cheap to clone
+#[derive(Clone, Default)]
+pub struct Tap {
+    observers: Arc<RwLock<Observers>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for Tap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tap")
+            .field("observers", &self.observers.read().unwrap().len())
+            .finish()
+    }
+}
+
+impl Tap {
+    /// Start passing messages to `observer`
+    pub fn register(&self, observer: impl TapObserver + 'static) -> TapId {
+        let id = TapId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.observers
+            .write()
+            .unwrap()
+            .push((id, Arc::new(observer)));
+        id
+    }
+
+    /// Stop passing messages to the observer `id`; false if it is gone
+    pub fn unregister(&self, id: TapId) -> bool {
+        let mut observers = self.observers.write().unwrap();
+        let before = observers.len();
+        observers.retain(|(observer, _)| *observer != id);
+        observers.len() != before
+    }
+
+    /// Whether nobody is listening (the tap then costs a lock per message)
+    pub fn is_empty(&self) -> bool {
+        self.observers.read().unwrap().is_empty()
+    }
+
+    /// Pass a message received from `server_id` to every observer
+    pub fn inbound(&self, server_id: &str, message: &ClientMessage, now_ms: i64) {
+        self.observe(server_id, Frame::Client(message), now_ms);
+    }
+
+    /// Pass a message sent to `server_id` to every observer
+    pub fn outbound(&self, server_id: &str, message: &ServerMessage, now_ms: i64) {
+        self.observe(server_id, Frame::Server(message), now_ms);
+    }
+
+    fn observe(&self, server_id: &str, frame: Frame<'_>, now_ms: i64) {
+        let observers = self.observers.read().unwrap();
+        if observers.is_empty() {
+            return;
+        }
+        let record = TapRecord {
+            server_id,
+            timestamp_ms: now_ms,
+            size: frame.encoded_len(),
+            frame,
+        };
+        for (_, observer) in observers.iter() {
+            observer.observe(&record);
+        }
+    }
+}
+
+/// Logs one line per message
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogTap;
+
+impl TapObserver for LogTap {
+    fn observe(&self, record: &TapRecord<'_>) {
+        info!(
+            server_id = %record.server_id,
+            direction = ?record.direction(),
+            message_type = record.frame.message_type(),
+            size = record.size,
+            "Tap"
+        );
+    }
+}
+
+/// An owned envelope
+#[derive(Debug, Clone, PartialEq)]
+pub enum Envelope {
+    Client(ClientMessage),
+    Server(ServerMessage),
+}
+
+impl Envelope {
+    pub fn frame(&self) -> Frame<'_> {
+        match self {
+            Envelope::Client(m) => Frame::Client(m),
+            Envelope::Server(m) => Frame::Server(m),
+        }
+    }
+}
+
+/// A message read from a capture file or received from a [`TapBroadcast`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Captured {
+    pub server_id: String,
+    pub timestamp_ms: i64,
+    pub envelope: Envelope,
+}
+
+impl Captured {
+    /// The same view observers got
+    pub fn record(&self) -> TapRecord<'_> {
+        let frame = self.envelope.frame();
+        TapRecord {
+            server_id: &self.server_id,
+            timestamp_ms: self.timestamp_ms,
+            size: frame.encoded_len(),
+            frame,
+        }
+    }
+}
+
+/// Appends every message to a capture file (or any writer)
+#[derive(Debug)]
+pub struct CaptureWriter<W> {
+    out: Mutex<W>,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Start a capture in `out`, writing its header
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(CAPTURE_MAGIC)?;
+        Ok(Self {
+            out: Mutex::new(out),
+        })
+    }
+
+    /// Append one message
+    pub fn write(&self, record: &TapRecord<'_>) -> io::Result<()> {
+        let server_id = record.server_id.as_bytes();
+        let server_id = &server_id[..server_id.len().min(u16::MAX as usize)];
+        let body = match record.frame {
+            Frame::Client(m) => m.encode_length_delimited_to_vec(),
+            Frame::Server(m) => m.encode_length_delimited_to_vec(),
+        };
+        let mut buf = Vec::with_capacity(11 + server_id.len() + body.len());
+        buf.push(match record.direction() {
+            Direction::Inbound => 0,
+            Direction::Outbound => 1,
+        });
+        buf.extend_from_slice(&record.timestamp_ms.to_le_bytes());
+        buf.extend_from_slice(&(server_id.len() as u16).to_le_bytes());
+        buf.extend_from_slice(server_id);
+        buf.extend_from_slice(&body);
+        // One write per record keeps records whole if the process dies
+        self.out.lock().unwrap().write_all(&buf)
+    }
+
+    /// Flush buffered records
+    pub fn flush(&self) -> io::Result<()> {
+        self.out.lock().unwrap().flush()
+    }
+
+    /// The writer, e.g. to inspect an in-memory capture
+    pub fn into_inner(self) -> W {
+        self.out.into_inner().unwrap()
+    }
+}
+
+impl<W: Write + Send> TapObserver for CaptureWriter<W> {
+    fn observe(&self, record: &TapRecord<'_>) {
+        if let Err(e) = self.write(record) {
+            warn!(error = %e, "Capture write failed");
+        }
+    }
+}
+
+/// Reads a capture file back, one [`Captured`] at a time
+#[derive(Debug)]
+pub struct CaptureReader<R> {
+    input: R,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Open a capture, checking its header
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = [0; CAPTURE_MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        if &magic != CAPTURE_MAGIC {
+            return Err(invalid("not an NPC Society capture"));
+        }
+        Ok(Self { input })
+    }
+
+    fn read_record(&mut self, direction: u8) -> io::Result<Captured> {
+        let mut timestamp = [0; 8];
+        self.input.read_exact(&mut timestamp)?;
+        let mut len = [0; 2];
+        self.input.read_exact(&mut len)?;
+        let mut server_id = vec![0; u16::from_le_bytes(len) as usize];
+        self.input.read_exact(&mut server_id)?;
+        let server_id = String::from_utf8(server_id).map_err(|e| invalid(e.to_string()))?;
+
+        // Length prefix: a varint of at most 10 bytes
+        let mut body_len: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let mut byte = [0; 1];
+            self.input.read_exact(&mut byte)?;
+            body_len |= u64::from(byte[0] & 0x7f) << shift;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0; body_len as usize];
+        self.input.read_exact(&mut body)?;
+        let envelope = match direction {
+            0 => ClientMessage::decode(&body[..]).map(Envelope::Client),
+            1 => ServerMessage::decode(&body[..]).map(Envelope::Server),
+            other => return Err(invalid(format!("unknown direction {}", other))),
+        }
+        .map_err(|e| invalid(e.to_string()))?;
+        Ok(Captured {
+            server_id,
+            timestamp_ms: i64::from_le_bytes(timestamp),
+            envelope,
+        })
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<Captured>;
+
+    /// The next record; a capture cut off mid-record ends with an error
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut direction = [0; 1];
+        match self.input.read(&mut direction) {
+            Ok(0) => None,
+            Ok(_) => Some(self.read_record(direction[0])),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Forwards every message to live subscribers. Subscribers that fall
+/// behind by more than the channel's capacity miss messages (and learn
+/// how many from `RecvError::Lagged`) rather than slow the stream down.
+#[derive(Debug, Clone)]
+pub struct TapBroadcast {
+    tx: broadcast::Sender<Captured>,
+}
+
+impl TapBroadcast {
+    /// Keep up to `capacity` messages for slow subscribers
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+        Self { tx }
+    }
+
+    /// Receive every message from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Captured> {
+        self.tx.subscribe()
+    }
+}
+
+impl TapObserver for TapBroadcast {
+    fn observe(&self, record: &TapRecord<'_>) {
+        // Copying the message is the expensive part; skip it unwatched
+        if self.tx.receiver_count() > 0 {
+            let _ = self.tx.send(record.to_captured());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{ChatObservation, SpeakDirective};
+
+    fn chat() -> ClientMessage {
+        ChatObservation {
+            npc_id: "miner_01".to_string(),
+            message: "hello".to_string(),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    fn speak() -> ServerMessage {
+        SpeakDirective {
+            npc_id: "miner_01".to_string(),
+            text: "Hi!".to_string(),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_observers() {
+        let tap = Tap::default();
+        assert!(tap.is_empty());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let id = {
+            let seen = seen.clone();
+            tap.register(move |record: &TapRecord<'_>| {
+                seen.lock().unwrap().push((
+                    record.direction(),
+                    record.frame.message_type(),
+                    record.size,
+                ));
+            })
+        };
+
+        tap.inbound("lobby", &chat(), 1_000);
+        tap.outbound("lobby", &speak(), 1_001);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                (Direction::Inbound, "ChatObservation", chat().encoded_len()),
+                (Direction::Outbound, "SpeakDirective", speak().encoded_len()),
+            ]
+        );
+
+        assert!(tap.unregister(id));
+        assert!(!tap.unregister(id));
+        tap.inbound("lobby", &chat(), 1_002);
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_capture_roundtrip() {
+        let writer = CaptureWriter::new(Vec::new()).unwrap();
+        let chat = chat();
+        let inbound = TapRecord {
+            server_id: "lobby",
+            timestamp_ms: 1_000,
+            size: 0,
+            frame: Frame::Client(&chat),
+        };
+        writer.write(&inbound).unwrap();
+        let speak = speak();
+        writer
+            .write(&TapRecord {
+                server_id: "",
+                timestamp_ms: 1_001,
+                size: 0,
+                frame: Frame::Server(&speak),
+            })
+            .unwrap();
+        let bytes = writer.into_inner();
+
+        let captured: Vec<_> = CaptureReader::new(&bytes[..])
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(captured.len(), 2);
+        assert_eq!(captured[0], inbound.to_captured());
+        assert_eq!(captured[0].record().size, chat.encoded_len());
+        assert_eq!(captured[1].server_id, "");
+        assert_eq!(captured[1].envelope, Envelope::Server(speak));
+
+        // A capture cut off mid-record fails instead of ending quietly
+        let mut records = CaptureReader::new(&bytes[..bytes.len() - 1]).unwrap();
+        assert!(records.next().unwrap().is_ok());
+        assert!(records.next().unwrap().is_err());
+        assert!(CaptureReader::new(&b"not a capture"[..]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_broadcast() {
+        let tap = Tap::default();
+        let broadcast = TapBroadcast::new(16);
+        tap.register(broadcast.clone());
+        // Nobody subscribed yet: nothing is kept
+        tap.inbound("lobby", &chat(), 1_000);
+
+        let mut tail = broadcast.subscribe();
+        tap.outbound("lobby", &speak(), 1_001);
+        let captured = tail.recv().await.unwrap();
+        assert_eq!(captured.timestamp_ms, 1_001);
+        assert_eq!(captured.envelope, Envelope::Server(speak()));
+    }
+}
@@ -0,0 +1,263 @@
+//! Shared task board for multi-NPC work allocation.
+//!
+//! NPCs post tasks ("mine this vein", "guard this gate") and others claim
+//! them. Every [`TaskBoard`] operation returns the `NpcMessage` to send, and
+//! every board applies the messages it receives with
+//! [`TaskBoard::on_message`], so boards in different daemons converge on
+//! the same state. Concurrent claims are resolved the same way everywhere:
+//! the earliest claim wins, ties going to the smaller npc_id. A claimant
+//! should check [`TaskBoard::assignee`] again before committing to
+//! expensive work.
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use crate::npc_society::v1::{BlockPosition, NpcMessage};
+use crate::types::NpcId;
+
+/// `NpcMessage.topic` of a new task
+pub const TOPIC_POST: &str = "task.post";
+/// `NpcMessage.topic` of a claim
+pub const TOPIC_CLAIM: &str = "task.claim";
+/// `NpcMessage.topic` of a finished task
+pub const TOPIC_DONE: &str = "task.done";
+
+/// A unit of work any NPC may take on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Task {
+    /// Unique id, `<poster>-task-<n>`
+    pub task_id: String,
+    /// Application-defined kind, e.g. "mine" or "guard"
+    pub kind: String,
+    /// Where the work is, if anywhere in particular
+    pub position: Option<BlockPosition>,
+    /// NPC that posted the task
+    pub posted_by: String,
+    /// Whether the assignee reported it finished
+    pub done: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Claim {
+    at_ms: i64,
+    npc_id: String,
+}
+
+/// One replica of the shared task board.
+#[derive(Debug, Default)]
+pub struct TaskBoard {
+    tasks: HashMap<String, Task>,
+    /// Winning claim per task_id; may arrive before the task itself
+    claims: HashMap<String, Claim>,
+    counter: u64,
+}
+
+impl TaskBoard {
+    /// Post a task on behalf of `npc`; broadcast the returned message
+    pub fn post(
+        &mut self,
+        npc: &NpcId,
+        kind: &str,
+        position: Option<BlockPosition>,
+        now_ms: i64,
+    ) -> NpcMessage {
+        let npc_id = npc.as_str();
+        self.counter += 1;
+        let task = Task {
+            task_id: format!("{}-task-{}", npc_id, self.counter),
+            kind: kind.to_string(),
+            position,
+            posted_by: npc_id.to_string(),
+            done: false,
+        };
+        let payload = json!({
+            "task_id": task.task_id,
+            "kind": task.kind,
+            "position": task.position.as_ref().map(|p| json!({
+                "world": p.world, "x": p.x, "y": p.y, "z": p.z,
+            })),
+        });
+        self.tasks.insert(task.task_id.clone(), task);
+        self.message(npc_id, TOPIC_POST, payload, now_ms)
+    }
+
+    /// Claim an open task for `npc`. Returns None if the task is
+    /// unknown, finished or already claimed; otherwise broadcast the message.
+    pub fn claim(&mut self, task_id: &str, npc: &NpcId, now_ms: i64) -> Option<NpcMessage> {
+        let npc_id = npc.as_str();
+        let task = self.tasks.get(task_id)?;
+        if task.done || self.claims.contains_key(task_id) {
+            return None;
+        }
+        self.apply_claim(task_id, npc_id, now_ms);
+        // The plugin restamps timestamp_ms when relaying, so the claim time
+        // every board compares travels in the payload
+        let payload = json!({ "task_id": task_id, "at_ms": now_ms });
+        Some(self.message(npc_id, TOPIC_CLAIM, payload, now_ms))
+    }
+
+    /// Mark a task `npc` is assigned as finished; broadcast the message
+    pub fn complete(&mut self, task_id: &str, npc: &NpcId, now_ms: i64) -> Option<NpcMessage> {
+        let npc_id = npc.as_str();
+        if self.assignee(task_id) != Some(npc_id) {
+            return None;
+        }
+        self.tasks.get_mut(task_id)?.done = true;
+        Some(self.message(npc_id, TOPIC_DONE, json!({ "task_id": task_id }), now_ms))
+    }
+
+    /// Apply a board message from another NPC. Returns false for other
+    /// topics and malformed payloads.
+    pub fn on_message(&mut self, message: &NpcMessage) -> bool {
+        let Ok(payload) = serde_json::from_slice::<Value>(&message.payload) else {
+            return false;
+        };
+        let Some(task_id) = payload["task_id"].as_str() else {
+            return false;
+        };
+        match message.topic.as_str() {
+            TOPIC_POST => {
+                let position = payload["position"].as_object().map(|p| BlockPosition {
+                    world: p["world"].as_str().unwrap_or_default().to_string(),
+                    x: p["x"].as_i64().unwrap_or_default() as i32,
+                    y: p["y"].as_i64().unwrap_or_default() as i32,
+                    z: p["z"].as_i64().unwrap_or_default() as i32,
+                    ..Default::default()
+                });
+                self.tasks.entry(task_id.to_string()).or_insert_with(|| Task {
+                    task_id: task_id.to_string(),
+                    kind: payload["kind"].as_str().unwrap_or_default().to_string(),
+                    position,
+                    posted_by: message.sender_npc_id.clone(),
+                    done: false,
+                });
+            }
+            TOPIC_CLAIM => {
+                let at_ms = payload["at_ms"].as_i64().unwrap_or(message.timestamp_ms);
+                self.apply_claim(task_id, &message.sender_npc_id, at_ms);
+            }
+            TOPIC_DONE => {
+                if let Some(task) = self.tasks.get_mut(task_id) {
+                    task.done = true;
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// A task by id
+    pub fn task(&self, task_id: &str) -> Option<&Task> {
+        self.tasks.get(task_id)
+    }
+
+    /// NPC currently holding the task, if claimed
+    pub fn assignee(&self, task_id: &str) -> Option<&str> {
+        self.claims.get(task_id).map(|c| c.npc_id.as_str())
+    }
+
+    /// Unclaimed, unfinished tasks of `kind`
+    pub fn open_tasks<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = &'a Task> + 'a {
+        self.tasks
+            .values()
+            .filter(move |t| t.kind == kind && !t.done && !self.claims.contains_key(&t.task_id))
+    }
+
+    /// Unfinished tasks assigned to `npc_id`
+    pub fn assigned_to<'a>(&'a self, npc_id: &'a str) -> impl Iterator<Item = &'a Task> + 'a {
+        self.tasks
+            .values()
+            .filter(move |t| !t.done && self.assignee(&t.task_id) == Some(npc_id))
+    }
+
+    fn apply_claim(&mut self, task_id: &str, npc_id: &str, at_ms: i64) {
+        let claim = Claim {
+            at_ms,
+            npc_id: npc_id.to_string(),
+        };
+        match self.claims.get(task_id) {
+            Some(current) if *current <= claim => {}
+            _ => {
+                self.claims.insert(task_id.to_string(), claim);
+            }
+        }
+    }
+
+    fn message(&mut self, npc_id: &str, topic: &str, payload: Value, now_ms: i64) -> NpcMessage {
+        self.counter += 1;
+        NpcMessage {
+            message_id: format!("{}-board-{}", npc_id, self.counter),
+            sender_npc_id: npc_id.to_string(),
+            topic: topic.to_string(),
+            payload: payload.to_string().into_bytes(),
+            timestamp_ms: now_ms,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npc(id: &str) -> NpcId {
+        NpcId::new(id).unwrap()
+    }
+
+    #[test]
+    fn test_concurrent_claims_converge() {
+        let mut a = TaskBoard::default();
+        let mut b = TaskBoard::default();
+
+        let post = a.post(&npc("foreman"), "mine", None, 0);
+        assert!(b.on_message(&post));
+        let task_id = b.open_tasks("mine").next().unwrap().task_id.clone();
+
+        // Both miners claim before hearing from each other; the earlier wins
+        let late = a.claim(&task_id, &npc("miner2"), 20).unwrap();
+        let early = b.claim(&task_id, &npc("miner1"), 10).unwrap();
+        a.on_message(&early);
+        b.on_message(&late);
+        assert_eq!(a.assignee(&task_id), Some("miner1"));
+        assert_eq!(b.assignee(&task_id), Some("miner1"));
+        assert!(a.open_tasks("mine").next().is_none());
+
+        assert!(a.complete(&task_id, &npc("miner2"), 30).is_none());
+        let done = b.complete(&task_id, &npc("miner1"), 30).unwrap();
+        a.on_message(&done);
+        assert!(a.task(&task_id).unwrap().done);
+        assert!(a.assigned_to("miner1").next().is_none());
+    }
+
+    #[test]
+    fn test_claim_before_post_and_foreign_topics() {
+        let mut a = TaskBoard::default();
+        let mut b = TaskBoard::default();
+        let post = a.post(
+            &npc("foreman"),
+            "guard",
+            Some(BlockPosition {
+                world: "world".to_string(),
+                x: 1,
+                y: 64,
+                z: -3,
+                ..Default::default()
+            }),
+            0,
+        );
+        let claim = a.claim("foreman-task-1", &npc("guard1"), 5).unwrap();
+
+        // Relayed out of order
+        b.on_message(&claim);
+        b.on_message(&post);
+        assert_eq!(b.assignee("foreman-task-1"), Some("guard1"));
+        assert_eq!(b.task("foreman-task-1").unwrap().position.as_ref().unwrap().z, -3);
+
+        assert!(!b.on_message(&NpcMessage {
+            topic: "found_ore".to_string(),
+            payload: br#"{"task_id":"x"}"#.to_vec(),
+            ..Default::default()
+        }));
+    }
+}
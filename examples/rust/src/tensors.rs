@@ -0,0 +1,339 @@
+//! Fixed-shape features for learning-based daemons (`--features tensors`).
+//!
+//! A policy network wants the same shape of input every tick, while the
+//! plugin reports a varying number of players and entities and the blocks
+//! it happened to scan. [`FeatureExtractor::observe`] turns an NPC's
+//! snapshot and the [`WorldModel`] (fed with WorldTicks and scan results)
+//! into an [`Observation`] of `ndarray` arrays whose shapes depend only on
+//! the [`FeatureConfig`]:
+//!
+//! - `vitals` (`[VITALS]`): health, hunger, combat, experience, ...
+//! - `occupancy` (`[2, height, width, width]`): a grid of the blocks around
+//!   the NPC, channel 0 "known", channel 1 "solid"
+//! - `entities` (`[max_entities, ENTITY_FEATURES]`) and `players`
+//!   (`[max_players, PLAYER_FEATURES]`): the nearest first, zero-padded
+//!
+//! [`Observation::flatten`] concatenates them for models that take one
+//! vector. Every value is a finite `f32`, mostly in -1..1.
+
+use ndarray::{concatenate, Array1, Array2, Array4, Axis};
+
+use crate::dimension::block_at;
+use crate::geom::distance;
+use crate::npc_society::v1::{BlockPosition, NpcSnapshot, Position};
+use crate::world_model::WorldModel;
+
+/// Length of [`Observation::vitals`]
+pub const VITALS: usize = 8;
+/// Buckets entity types are hashed into
+pub const ENTITY_TYPE_BUCKETS: usize = 16;
+/// Features per row of [`Observation::entities`]: present, offset (3),
+/// distance, health and a one-hot type bucket
+pub const ENTITY_FEATURES: usize = 6 + ENTITY_TYPE_BUCKETS;
+/// Features per row of [`Observation::players`]: present, offset (3),
+/// distance, health, sneaking, sprinting
+pub const PLAYER_FEATURES: usize = 8;
+/// Experience level that maps to 1.0
+const MAX_XP_LEVEL: f32 = 30.0;
+
+/// Shapes of the features
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeatureConfig {
+    /// Blocks of the grid on each side of the NPC, horizontally
+    pub grid_radius: i32,
+    /// Blocks of the grid above and below the NPC's feet
+    pub grid_half_height: i32,
+    /// Rows of `entities`
+    pub max_entities: usize,
+    /// Rows of `players`
+    pub max_players: usize,
+    /// Entities and players further away are left out; offsets are
+    /// divided by this
+    pub sight_radius: f64,
+}
+
+impl Default for FeatureConfig {
+    /// A 9x5x9 grid, 8 entities and 4 players within 16 blocks
+    fn default() -> Self {
+        Self {
+            grid_radius: 4,
+            grid_half_height: 2,
+            max_entities: 8,
+            max_players: 4,
+            sight_radius: 16.0,
+        }
+    }
+}
+
+/// One NPC's features at one moment
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation {
+    /// `[VITALS]`
+    pub vitals: Array1<f32>,
+    /// `[2, height, width, width]`, indexed `[channel, y, x, z]`
+    pub occupancy: Array4<f32>,
+    /// `[max_entities, ENTITY_FEATURES]`
+    pub entities: Array2<f32>,
+    /// `[max_players, PLAYER_FEATURES]`
+    pub players: Array2<f32>,
+}
+
+impl Observation {
+    /// Every feature in one vector: vitals, occupancy, entities, players
+    pub fn flatten(&self) -> Array1<f32> {
+        let parts = [
+            self.vitals.view(),
+            self.occupancy
+                .view()
+                .into_shape_with_order(self.occupancy.len())
+                .unwrap(),
+            self.entities
+                .view()
+                .into_shape_with_order(self.entities.len())
+                .unwrap(),
+            self.players
+                .view()
+                .into_shape_with_order(self.players.len())
+                .unwrap(),
+        ];
+        concatenate(Axis(0), &parts).unwrap()
+    }
+}
+
+/// Turns snapshots and the world model into [`Observation`]s
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FeatureExtractor {
+    config: FeatureConfig,
+}
+
+impl FeatureExtractor {
+    /// An extractor of `config`'s shapes
+    pub fn new(config: FeatureConfig) -> Self {
+        Self { config }
+    }
+
+    /// Length of [`Observation::flatten`]
+    pub fn flat_len(&self) -> usize {
+        let (height, width) = self.grid_shape();
+        VITALS
+            + 2 * height * width * width
+            + self.config.max_entities * ENTITY_FEATURES
+            + self.config.max_players * PLAYER_FEATURES
+    }
+
+    /// Features of `npc`. An NPC without a position gets an unknown grid
+    /// and no entities or players.
+    pub fn observe(&self, npc: &NpcSnapshot, world: &WorldModel) -> Observation {
+        Observation {
+            vitals: vitals(npc),
+            occupancy: self.occupancy(npc.position.as_ref(), world),
+            entities: self.entities(npc.position.as_ref(), world),
+            players: self.players(npc.position.as_ref(), world),
+        }
+    }
+
+    fn grid_shape(&self) -> (usize, usize) {
+        let height = (2 * self.config.grid_half_height + 1).max(0) as usize;
+        let width = (2 * self.config.grid_radius + 1).max(0) as usize;
+        (height, width)
+    }
+
+    fn occupancy(&self, position: Option<&Position>, world: &WorldModel) -> Array4<f32> {
+        let (height, width) = self.grid_shape();
+        let mut grid = Array4::zeros((2, height, width, width));
+        let Some(position) = position else {
+            return grid;
+        };
+        let feet = block_at(position);
+        let (r, h) = (self.config.grid_radius, self.config.grid_half_height);
+        for dy in -h..=h {
+            for dx in -r..=r {
+                for dz in -r..=r {
+                    let at = BlockPosition {
+                        x: feet.x + dx,
+                        y: feet.y + dy,
+                        z: feet.z + dz,
+                        ..feet.clone()
+                    };
+                    let Some(block) = world.block(&at) else {
+                        continue;
+                    };
+                    let index = ((dy + h) as usize, (dx + r) as usize, (dz + r) as usize);
+                    grid[[0, index.0, index.1, index.2]] = 1.0;
+                    if !world.is_passable(&block.block_type) {
+                        grid[[1, index.0, index.1, index.2]] = 1.0;
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    fn entities(&self, position: Option<&Position>, world: &WorldModel) -> Array2<f32> {
+        let mut rows = Array2::zeros((self.config.max_entities, ENTITY_FEATURES));
+        let Some(position) = position else {
+            return rows;
+        };
+        let mut near: Vec<_> = world
+            .entities_within(position, self.config.sight_radius)
+            .into_iter()
+            .filter_map(|e| Some((e, e.position.as_ref()?)))
+            .collect();
+        near.sort_by(|a, b| distance(position, a.1).total_cmp(&distance(position, b.1)));
+        for (mut row, (entity, at)) in rows.rows_mut().into_iter().zip(near) {
+            self.relative(position, at, row.as_slice_mut().unwrap());
+            row[5] = entity.health_norm;
+            row[6 + bucket(&entity.entity_type)] = 1.0;
+        }
+        rows
+    }
+
+    fn players(&self, position: Option<&Position>, world: &WorldModel) -> Array2<f32> {
+        let mut rows = Array2::zeros((self.config.max_players, PLAYER_FEATURES));
+        let Some(position) = position else {
+            return rows;
+        };
+        let mut near: Vec<_> = world
+            .players_within(position, self.config.sight_radius)
+            .into_iter()
+            .filter_map(|p| Some((p, p.position.as_ref()?)))
+            .collect();
+        near.sort_by(|a, b| distance(position, a.1).total_cmp(&distance(position, b.1)));
+        for (mut row, (player, at)) in rows.rows_mut().into_iter().zip(near) {
+            self.relative(position, at, row.as_slice_mut().unwrap());
+            row[5] = player.health_norm;
+            row[6] = flag(player.sneaking);
+            row[7] = flag(player.sprinting);
+        }
+        rows
+    }
+
+    /// Present, offset and distance, scaled by the sight radius
+    fn relative(&self, from: &Position, to: &Position, row: &mut [f32]) {
+        let scale = self.config.sight_radius.max(f64::EPSILON);
+        row[0] = 1.0;
+        row[1] = ((to.x - from.x) / scale) as f32;
+        row[2] = ((to.y - from.y) / scale) as f32;
+        row[3] = ((to.z - from.z) / scale) as f32;
+        row[4] = (distance(from, to) / scale) as f32;
+    }
+}
+
+/// `[health, hunger, in_combat, frozen, moving, holding an item, xp level,
+/// xp progress]`
+pub fn vitals(npc: &NpcSnapshot) -> Array1<f32> {
+    Array1::from(vec![
+        npc.health_norm,
+        npc.hunger_norm,
+        flag(npc.in_combat),
+        flag(npc.frozen),
+        flag(npc.move_progress.is_some()),
+        flag(!npc.held_item.is_empty()),
+        (npc.xp_level as f32 / MAX_XP_LEVEL).min(1.0),
+        npc.xp_progress,
+    ])
+}
+
+fn flag(value: bool) -> f32 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Stable bucket of an entity type (FNV-1a), the same in every process
+fn bucket(entity_type: &str) -> usize {
+    let hash = entity_type
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    (hash % ENTITY_TYPE_BUCKETS as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{EntitySnapshot, PlayerSnapshot, WorldTick};
+
+    fn at(x: f64, y: f64, z: f64) -> Option<Position> {
+        Some(Position {
+            world: "world".to_string(),
+            x,
+            y,
+            z,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_observe() {
+        let npc = NpcSnapshot {
+            npc_id: "npc".to_string(),
+            position: at(0.5, 64.0, 0.5),
+            health_norm: 0.5,
+            in_combat: true,
+            ..Default::default()
+        };
+        let mut world = WorldModel::default();
+        world.ingest_tick(&WorldTick {
+            timestamp_ms: 1,
+            nearby_entities: vec![
+                EntitySnapshot {
+                    entity_uuid: "far".to_string(),
+                    entity_type: "minecraft:cow".to_string(),
+                    position: at(8.5, 64.0, 0.5),
+                    ..Default::default()
+                },
+                EntitySnapshot {
+                    entity_uuid: "near".to_string(),
+                    entity_type: "minecraft:zombie".to_string(),
+                    position: at(2.5, 64.0, 0.5),
+                    health_norm: 1.0,
+                    ..Default::default()
+                },
+            ],
+            nearby_players: vec![PlayerSnapshot {
+                player_uuid: "p".to_string(),
+                position: at(40.0, 64.0, 0.0),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        let stone = |x, y, z| BlockPosition {
+            world: "world".to_string(),
+            x,
+            y,
+            z,
+            ..Default::default()
+        };
+        world.set_block(&stone(0, 63, 0), "minecraft:stone", 1);
+        world.set_block(&stone(1, 64, 0), "minecraft:air", 1);
+
+        let extractor = FeatureExtractor::default();
+        let observation = extractor.observe(&npc, &world);
+        assert_eq!(observation.vitals[0], 0.5);
+        assert_eq!(observation.vitals[2], 1.0);
+
+        // Feet at the center of the grid: y index 2, x and z index 4
+        assert_eq!(observation.occupancy.shape(), &[2, 5, 9, 9]);
+        assert_eq!(observation.occupancy[[1, 1, 4, 4]], 1.0);
+        assert_eq!(observation.occupancy[[0, 2, 5, 4]], 1.0);
+        assert_eq!(observation.occupancy[[1, 2, 5, 4]], 0.0);
+        assert_eq!(observation.occupancy.sum(), 3.0);
+
+        // Nearest first, padded; the player is out of sight
+        let entities = &observation.entities;
+        assert_eq!(entities.shape(), &[8, ENTITY_FEATURES]);
+        assert_eq!(entities[[0, 1]], 2.0 / 16.0);
+        assert_eq!(entities[[0, 6 + bucket("minecraft:zombie")]], 1.0);
+        assert_eq!(entities[[1, 4]], 0.5);
+        assert_eq!(entities.row(2).sum(), 0.0);
+        assert_eq!(observation.players.sum(), 0.0);
+
+        assert_eq!(observation.flatten().len(), extractor.flat_len());
+        let unplaced = extractor.observe(&NpcSnapshot::default(), &world);
+        assert_eq!(unplaced.flatten().len(), extractor.flat_len());
+    }
+}
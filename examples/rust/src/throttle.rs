@@ -0,0 +1,209 @@
+//! Backing off when the server struggles.
+//!
+//! Every directive costs the server pathfinding, block updates and entity
+//! ticks. A daemon that keeps sending at full speed while the server runs
+//! at 12 TPS makes the lag worse for every player. [`LoadThrottle`] reads
+//! the load the plugin reports in each WorldTick (`mspt`, or `tps` from
+//! plugins that report only that) and lowers the rate of directives it lets
+//! through as the load rises, down to [`ThrottleConfig::min_share`] of the
+//! normal rate.
+//!
+//! `StopAction`s always pass: they only ever reduce the load.
+
+use std::fmt;
+
+use crate::npc_society::v1::{action_directive::Action, ActionDirective, WorldTick};
+
+/// How a [`LoadThrottle`] reacts to load
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThrottleConfig {
+    /// Directives per second on a healthy server; also the burst size
+    pub max_per_second: f64,
+    /// Share of `max_per_second` still allowed at full load, 0.0-1.0
+    pub min_share: f64,
+    /// Milliseconds per tick up to which the server counts as healthy
+    pub healthy_mspt: f64,
+    /// Milliseconds per tick at which the server counts as fully loaded
+    pub overloaded_mspt: f64,
+}
+
+impl Default for ThrottleConfig {
+    /// 20 directives per second, falling to 4 between 40 and 100 MSPT
+    fn default() -> Self {
+        Self {
+            max_per_second: 20.0,
+            min_share: 0.2,
+            healthy_mspt: 40.0,
+            overloaded_mspt: 100.0,
+        }
+    }
+}
+
+/// A directive held back because of server load
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Throttled {
+    /// Load when it was held back, 0.0-1.0
+    pub load: f64,
+    /// Directives per second currently allowed
+    pub rate: f64,
+}
+
+impl fmt::Display for Throttled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "server under load ({:.0}%), limited to {:.1} directives/s",
+            self.load * 100.0,
+            self.rate
+        )
+    }
+}
+
+impl std::error::Error for Throttled {}
+
+/// Token bucket whose refill rate follows the server's load.
+#[derive(Debug, Clone)]
+pub struct LoadThrottle {
+    config: ThrottleConfig,
+    load: f64,
+    tokens: f64,
+    refilled_at_ms: Option<i64>,
+}
+
+impl Default for LoadThrottle {
+    fn default() -> Self {
+        Self::new(ThrottleConfig::default())
+    }
+}
+
+impl LoadThrottle {
+    /// Create a throttle; the server counts as healthy until a WorldTick
+    /// says otherwise
+    pub fn new(config: ThrottleConfig) -> Self {
+        Self {
+            config,
+            load: 0.0,
+            tokens: config.max_per_second,
+            refilled_at_ms: None,
+        }
+    }
+
+    /// Update the load from a WorldTick. Ticks without `mspt` or `tps`
+    /// leave it unchanged.
+    pub fn observe(&mut self, tick: &WorldTick) {
+        let mspt = if tick.mspt > 0.0 {
+            f64::from(tick.mspt)
+        } else if tick.tps > 0.0 {
+            // Below 20 TPS ticks take longer than 50ms; at 20 all we know
+            // is that the server keeps up
+            if tick.tps < 19.5 {
+                1_000.0 / f64::from(tick.tps)
+            } else {
+                self.config.healthy_mspt
+            }
+        } else {
+            return;
+        };
+        let span = (self.config.overloaded_mspt - self.config.healthy_mspt).max(f64::EPSILON);
+        self.load = ((mspt - self.config.healthy_mspt) / span).clamp(0.0, 1.0);
+    }
+
+    /// Current load, 0.0 (healthy) to 1.0 (overloaded)
+    pub fn load(&self) -> f64 {
+        self.load
+    }
+
+    /// Directives per second currently allowed
+    pub fn rate(&self) -> f64 {
+        let share = 1.0 - self.load * (1.0 - self.config.min_share.clamp(0.0, 1.0));
+        self.config.max_per_second * share
+    }
+
+    /// Take a slot for `directive` at `now_ms`, or say why there is none
+    pub fn acquire(&mut self, directive: &ActionDirective, now_ms: i64) -> Result<(), Throttled> {
+        if matches!(directive.action, Some(Action::Stop(_))) {
+            return Ok(());
+        }
+        let rate = self.rate();
+        if let Some(refilled_at_ms) = self.refilled_at_ms {
+            let elapsed = (now_ms - refilled_at_ms).max(0) as f64 / 1_000.0;
+            self.tokens = (self.tokens + elapsed * rate).min(rate.max(1.0));
+        }
+        self.refilled_at_ms = Some(now_ms);
+        if self.tokens < 1.0 {
+            return Err(Throttled {
+                load: self.load,
+                rate,
+            });
+        }
+        self.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{MoveAction, StopAction};
+
+    fn directive(action: Action) -> ActionDirective {
+        ActionDirective {
+            directive_id: "d".to_string(),
+            npc_id: "miner".to_string(),
+            action: Some(action),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_backs_off_under_load() {
+        let mut throttle = LoadThrottle::default();
+        let walk = directive(Action::Move(MoveAction::default()));
+        for _ in 0..20 {
+            assert!(throttle.acquire(&walk, 0).is_ok());
+        }
+        assert!(throttle.acquire(&walk, 0).is_err());
+        // Refills at 20 per second
+        assert!(throttle.acquire(&walk, 50).is_ok());
+
+        // 85 MSPT: three quarters of the way to overloaded
+        throttle.observe(&WorldTick {
+            mspt: 85.0,
+            ..Default::default()
+        });
+        assert!((throttle.load() - 0.75).abs() < 1e-9);
+        assert!((throttle.rate() - 8.0).abs() < 1e-9);
+        assert!(throttle.acquire(&walk, 100).is_err());
+        assert!(throttle.acquire(&walk, 225).is_ok());
+
+        // Stops always go through
+        let stop = directive(Action::Stop(StopAction::default()));
+        assert!(throttle.acquire(&stop, 225).is_ok());
+    }
+
+    #[test]
+    fn test_load_from_tps() {
+        let mut throttle = LoadThrottle::default();
+        throttle.observe(&WorldTick {
+            tps: 10.0,
+            ..Default::default()
+        });
+        assert_eq!(throttle.load(), 1.0);
+        assert!((throttle.rate() - 4.0).abs() < 1e-9);
+
+        throttle.observe(&WorldTick {
+            tps: 20.0,
+            ..Default::default()
+        });
+        assert_eq!(throttle.load(), 0.0);
+
+        // Nothing reported: unchanged
+        throttle.observe(&WorldTick {
+            tps: 16.0,
+            ..Default::default()
+        });
+        let load = throttle.load();
+        throttle.observe(&WorldTick::default());
+        assert_eq!(throttle.load(), load);
+    }
+}
@@ -0,0 +1,181 @@
+//! `std::time` views of the protocol's millisecond fields.
+//!
+//! Every duration and timestamp on the wire is an integer number of
+//! milliseconds (`duration_ms`, `timestamp_ms`, `expires_at_ms`, ...):
+//! timestamps since the Unix epoch, durations as lengths. Writing seconds
+//! into one of them plays a 3 second line for 3 milliseconds, and nothing
+//! catches it. These helpers convert once, at the edge, so the rest of a
+//! daemon works with [`Duration`] and [`SystemTime`]:
+//!
+//! ```ignore
+//! use npc_society_example::time::{Timed, Timestamped};
+//!
+//! let age = SystemTime::now().duration_since(chat.timestamp()).unwrap_or_default();
+//! speak.set_duration(Duration::from_secs(3));
+//! ```
+//!
+//! [`Timestamped`] covers each message's own `timestamp_ms` (when the
+//! plugin saw or sent it) and [`Timed`] its length. Other fields convert
+//! with [`duration`], [`millis`], [`system_time`] and [`unix_ms`].
+//! Conversions saturate instead of wrapping: a negative length is zero and
+//! a length too long for the field is the field's maximum.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::npc_society::v1::{
+    AudioBufferStatus, ChangeDimensionObservation, ChatObservation, ChunkLoadObservation,
+    CombatPolicyObservation, ContainerAccessObservation, DialogueChoiceObservation,
+    EventObservation, IntruderObservation, LandmarkList, ModerationFlag, NpcMessage,
+    PlayMusicDirective, QuestUpdate, RegisterAudioAsset, ShopTradeObservation, SpeakDirective,
+    SpeechInterrupted, StationOutputObservation, TransactionObservation, TransportProgress,
+    VisemeCue, VoiceConsentObservation, VoicePcmFrame, WorldBootstrap, WorldTick,
+};
+
+/// A `*_ms` length as a [`Duration`]; negative lengths are zero
+pub fn duration(ms: i64) -> Duration {
+    Duration::from_millis(ms.max(0) as u64)
+}
+
+/// `duration` in whole milliseconds, for a `*_ms` length field
+pub fn millis(duration: Duration) -> i64 {
+    i64::try_from(duration.as_millis()).unwrap_or(i64::MAX)
+}
+
+/// A `*_ms` Unix timestamp as a [`SystemTime`]
+pub fn system_time(unix_ms: i64) -> SystemTime {
+    let offset = Duration::from_millis(unix_ms.unsigned_abs());
+    if unix_ms >= 0 {
+        UNIX_EPOCH + offset
+    } else {
+        UNIX_EPOCH - offset
+    }
+}
+
+/// `time` in milliseconds since the Unix epoch, for a `*_ms` timestamp field
+pub fn unix_ms(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => millis(since),
+        Err(before) => -millis(before.duration()),
+    }
+}
+
+/// The current time as a `*_ms` timestamp
+pub fn now_ms() -> i64 {
+    unix_ms(SystemTime::now())
+}
+
+/// A message stamped with when it was seen or sent (`timestamp_ms`)
+pub trait Timestamped {
+    /// The message's `timestamp_ms`
+    fn timestamp(&self) -> SystemTime;
+
+    /// Set the message's `timestamp_ms`
+    fn set_timestamp(&mut self, time: SystemTime);
+}
+
+/// A message that lasts for a while (`duration_ms`, `length_ms`)
+pub trait Timed {
+    /// How long it lasts
+    fn duration(&self) -> Duration;
+
+    /// Set how long it lasts
+    fn set_duration(&mut self, duration: Duration);
+}
+
+macro_rules! timestamped {
+    ($($message:ident),* $(,)?) => {
+        $(
+            impl Timestamped for $message {
+                fn timestamp(&self) -> SystemTime {
+                    system_time(self.timestamp_ms)
+                }
+
+                fn set_timestamp(&mut self, time: SystemTime) {
+                    self.timestamp_ms = unix_ms(time);
+                }
+            }
+        )*
+    };
+}
+
+timestamped!(
+    AudioBufferStatus,
+    ChangeDimensionObservation,
+    ChatObservation,
+    ChunkLoadObservation,
+    CombatPolicyObservation,
+    ContainerAccessObservation,
+    DialogueChoiceObservation,
+    EventObservation,
+    IntruderObservation,
+    LandmarkList,
+    ModerationFlag,
+    NpcMessage,
+    QuestUpdate,
+    ShopTradeObservation,
+    SpeechInterrupted,
+    StationOutputObservation,
+    TransactionObservation,
+    TransportProgress,
+    VoiceConsentObservation,
+    VoicePcmFrame,
+    WorldBootstrap,
+    WorldTick,
+);
+
+macro_rules! timed {
+    ($($message:ident . $field:ident: $ty:ty),* $(,)?) => {
+        $(
+            impl Timed for $message {
+                fn duration(&self) -> Duration {
+                    duration(i64::from(self.$field))
+                }
+
+                fn set_duration(&mut self, duration: Duration) {
+                    self.$field = <$ty>::try_from(millis(duration)).unwrap_or(<$ty>::MAX);
+                }
+            }
+        )*
+    };
+}
+
+timed!(
+    PlayMusicDirective.length_ms: i64,
+    RegisterAudioAsset.duration_ms: i64,
+    SpeakDirective.duration_ms: i32,
+    VisemeCue.duration_ms: i32,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(duration(1_500), Duration::from_millis(1_500));
+        assert_eq!(duration(-5), Duration::ZERO);
+        assert_eq!(millis(Duration::from_secs(3)), 3_000);
+        assert_eq!(millis(Duration::MAX), i64::MAX);
+
+        for ms in [0, 1_718_000_000_123, -86_400_000] {
+            assert_eq!(unix_ms(system_time(ms)), ms);
+        }
+        assert!(now_ms() > 1_700_000_000_000);
+    }
+
+    #[test]
+    fn test_message_fields() {
+        let mut speak = SpeakDirective::default();
+        speak.set_duration(Duration::from_secs(3));
+        assert_eq!(speak.duration_ms, 3_000);
+        // Too long for an int32 field: the longest it can hold
+        speak.set_duration(Duration::from_secs(u64::from(u32::MAX)));
+        assert_eq!(speak.duration_ms, i32::MAX);
+
+        let mut chat = ChatObservation::default();
+        let sent = UNIX_EPOCH + Duration::from_millis(1_718_000_000_000);
+        chat.set_timestamp(sent);
+        assert_eq!(chat.timestamp_ms, 1_718_000_000_000);
+        assert_eq!(chat.timestamp(), sent);
+    }
+}
@@ -0,0 +1,453 @@
+//! What the `npc-top` monitor shows, without the terminal.
+//!
+//! `npc-top` polls the daemon's admin RPCs once a second. [`Monitor`]
+//! turns `ListServers` into one [`ServerRow`] per server, with message
+//! rates from the counters' change since the previous poll, and
+//! [`npc_row`] turns a `GetNpcState` into an [`NpcRow`]. Directives the
+//! operator types (`<npc_id> <action> [args]`) are read by
+//! [`parse_directive`] and sent with `SendDirective`:
+//!
+//! ```text
+//! miner_01 move 120 64 -30
+//! miner_01 look 118 65 -30
+//! miner_01 break 118 63 -30
+//! miner_01 attack 5f1c0e3a-...
+//! miner_01 eat minecraft:bread
+//! miner_01 stop
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::builders::{BuildError, Buildable};
+use crate::npc_society::v1::{
+    action_directive::Action, ActionDirective, AttackAction, BlockPosition, BreakBlockAction,
+    ConsumeItemAction, GetNpcStateResponse, GetSessionInfoResponse, ListServersResponse,
+    LookAction, MoveAction, NpcSnapshot, OutboundQueueStats, Position, StopAction,
+};
+use crate::retry::action_kind;
+
+/// One line of the server table
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerRow {
+    pub server_id: String,
+    pub connected: bool,
+    pub peer_address: String,
+    /// ClientMessages per second since the previous poll
+    pub inbound_rate: f64,
+    /// ServerMessages per second since the previous poll
+    pub outbound_rate: f64,
+    /// Messages waiting in the outbound queue, all classes
+    pub queue_depth: u32,
+    /// Plugin clock minus daemon clock
+    pub clock_offset_ms: i64,
+}
+
+/// One line of the NPC table
+#[derive(Debug, Clone, PartialEq)]
+pub struct NpcRow {
+    pub server_id: String,
+    pub npc_id: String,
+    /// `world x y z`, rounded to blocks
+    pub position: String,
+    /// Oldest pending directive, e.g. `move dir-7` (`unacked` until the
+    /// plugin confirms it), after the reason if the NPC is frozen
+    pub current: String,
+    /// How the latest directive ended
+    pub last_result: String,
+    /// Directives pending for the NPC
+    pub queue_depth: usize,
+}
+
+/// Counters of one server at the previous poll
+#[derive(Debug, Clone, Copy)]
+struct Counters {
+    received: u64,
+    sent: u64,
+    at_ms: i64,
+}
+
+/// Server rows with rates; keeps the previous poll's counters
+#[derive(Debug, Default)]
+pub struct Monitor {
+    previous: BTreeMap<String, Counters>,
+}
+
+impl Monitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rows for a `ListServers` response polled at `now_ms`. Rates are 0
+    /// on the first poll and after a reconnect resets the counters.
+    pub fn servers(&mut self, list: &ListServersResponse, now_ms: i64) -> Vec<ServerRow> {
+        list.servers
+            .iter()
+            .map(|info| {
+                let server_id = server_id(info).to_string();
+                let counters = Counters {
+                    received: info.messages_received,
+                    sent: outbound_sent(info.outbound.as_ref()),
+                    at_ms: now_ms,
+                };
+                let (inbound_rate, outbound_rate) =
+                    match self.previous.insert(server_id.clone(), counters) {
+                        Some(before) if now_ms > before.at_ms => {
+                            let seconds = (now_ms - before.at_ms) as f64 / 1000.0;
+                            (
+                                counters.received.saturating_sub(before.received) as f64 / seconds,
+                                counters.sent.saturating_sub(before.sent) as f64 / seconds,
+                            )
+                        }
+                        _ => (0.0, 0.0),
+                    };
+                ServerRow {
+                    server_id,
+                    connected: info.connected,
+                    peer_address: info.peer_address.clone(),
+                    inbound_rate,
+                    outbound_rate,
+                    queue_depth: outbound_depth(info.outbound.as_ref()),
+                    clock_offset_ms: info.clock_offset_ms,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Server a session belongs to, from its Hello
+pub fn server_id(info: &GetSessionInfoResponse) -> &str {
+    info.hello
+        .as_ref()
+        .map_or("", |hello| hello.server_id.as_str())
+}
+
+fn classes(stats: Option<&OutboundQueueStats>) -> impl Iterator<Item = (u64, u32)> + '_ {
+    stats
+        .into_iter()
+        .flat_map(|s| [&s.control, &s.directives, &s.audio, &s.background])
+        .flatten()
+        .map(|class| (class.sent, class.depth))
+}
+
+fn outbound_sent(stats: Option<&OutboundQueueStats>) -> u64 {
+    classes(stats).map(|(sent, _)| sent).sum()
+}
+
+fn outbound_depth(stats: Option<&OutboundQueueStats>) -> u32 {
+    classes(stats).map(|(_, depth)| depth).sum()
+}
+
+/// Row for one NPC of `server_id`
+pub fn npc_row(server_id: &str, state: &GetNpcStateResponse) -> NpcRow {
+    let npc = state.npc.clone().unwrap_or_default();
+    let position = npc.position.as_ref().map_or_else(String::new, |p| {
+        format!(
+            "{} {:.0} {:.0} {:.0}",
+            p.world,
+            p.x.floor(),
+            p.y.floor(),
+            p.z.floor()
+        )
+    });
+    let current = state
+        .pending_directives
+        .first()
+        .and_then(|pending| {
+            let directive = pending.directive.as_ref()?;
+            let kind = directive.action.as_ref().map_or("none", action_kind);
+            let unacked = if pending.ack.is_none() {
+                " unacked"
+            } else {
+                ""
+            };
+            Some(format!("{} {}{}", kind, directive.directive_id, unacked))
+        })
+        .unwrap_or_default();
+    // Held directives stay pending until the resume
+    let current = match (npc.frozen, current.is_empty()) {
+        (false, _) => current,
+        (true, true) => format!("frozen: {}", npc.freeze_reason),
+        (true, false) => format!("frozen: {}, holding {}", npc.freeze_reason, current),
+    };
+    let last_result = state
+        .last_result
+        .as_ref()
+        .map(
+            |result| match (result.success, result.error_message.is_empty()) {
+                (true, _) => format!("{} ok", result.directive_id),
+                (false, true) => format!("{} failed", result.directive_id),
+                (false, false) => {
+                    format!("{} failed: {}", result.directive_id, result.error_message)
+                }
+            },
+        )
+        .unwrap_or_default();
+    NpcRow {
+        server_id: server_id.to_string(),
+        npc_id: npc.npc_id,
+        position,
+        current,
+        last_result,
+        queue_depth: state.pending_directives.len(),
+    }
+}
+
+/// A directive the operator typed could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectiveParseError(pub String);
+
+impl fmt::Display for DirectiveParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for DirectiveParseError {}
+
+impl From<BuildError> for DirectiveParseError {
+    fn from(e: BuildError) -> Self {
+        Self(e.0)
+    }
+}
+
+fn numbers<const N: usize>(args: &[&str]) -> Result<[f64; N], DirectiveParseError> {
+    let values: Vec<f64> = args
+        .iter()
+        .map(|a| {
+            a.parse()
+                .map_err(|_| DirectiveParseError(format!("not a number: {}", a)))
+        })
+        .collect::<Result<_, _>>()?;
+    values
+        .try_into()
+        .map_err(|_| DirectiveParseError(format!("expected {} numbers", N)))
+}
+
+/// Read `<npc_id> <action> [args]` (see the module docs). Coordinates are
+/// in the NPC's current world and dimension, taken from `npcs`. The directive_id is
+/// left empty for the daemon to assign.
+pub fn parse_directive(
+    line: &str,
+    npcs: &[NpcSnapshot],
+) -> Result<ActionDirective, DirectiveParseError> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let [npc_id, kind, args @ ..] = &words[..] else {
+        return Err(DirectiveParseError(
+            "usage: <npc_id> <action> [args]".to_string(),
+        ));
+    };
+    let here = npcs
+        .iter()
+        .find(|npc| npc.npc_id == *npc_id)
+        .and_then(|npc| npc.position.clone())
+        .unwrap_or_default();
+    let position = |args: &[&str]| {
+        numbers::<3>(args).map(|[x, y, z]| Position {
+            x,
+            y,
+            z,
+            ..here.clone()
+        })
+    };
+    let action: Action = match (*kind, args) {
+        ("move", _) => MoveAction::builder()
+            .target(position(args)?)
+            .build()?
+            .into(),
+        ("look", _) => LookAction::builder()
+            .position(position(args)?)
+            .build()?
+            .into(),
+        ("break", _) => {
+            let [x, y, z] = numbers::<3>(args)?;
+            let block = BlockPosition {
+                world: here.world.clone(),
+                x: x.floor() as i32,
+                y: y.floor() as i32,
+                z: z.floor() as i32,
+                dimension: here.dimension,
+            };
+            BreakBlockAction::builder().position(block).build()?.into()
+        }
+        ("attack", [uuid]) => AttackAction::builder().target_uuid(*uuid).build()?.into(),
+        ("eat", []) => ConsumeItemAction::builder().build()?.into(),
+        ("eat", [item]) => ConsumeItemAction::builder().item(*item).build()?.into(),
+        ("stop", []) => StopAction::builder().cancel_pending(true).build()?.into(),
+        ("attack" | "eat" | "stop", _) => {
+            return Err(DirectiveParseError(format!("wrong arguments for {}", kind)));
+        }
+        _ => {
+            return Err(DirectiveParseError(format!(
+                "unknown action {} (move, look, break, attack, eat, stop)",
+                kind
+            )))
+        }
+    };
+    Ok(ActionDirective {
+        npc_id: npc_id.to_string(),
+        priority: 1,
+        action: Some(action),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{
+        ActionResult, DirectiveAck, Hello, OutboundClassStats, PendingDirective,
+    };
+
+    fn session(received: u64, sent: u64) -> ListServersResponse {
+        ListServersResponse {
+            servers: vec![GetSessionInfoResponse {
+                connected: true,
+                hello: Some(Hello {
+                    server_id: "survival".to_string(),
+                    ..Default::default()
+                }),
+                messages_received: received,
+                outbound: Some(OutboundQueueStats {
+                    directives: Some(OutboundClassStats {
+                        depth: 2,
+                        sent,
+                        ..Default::default()
+                    }),
+                    audio: Some(OutboundClassStats {
+                        depth: 3,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn test_server_rates() {
+        let mut monitor = Monitor::new();
+        let first = monitor.servers(&session(100, 10), 1_000);
+        assert_eq!(first[0].server_id, "survival");
+        assert_eq!((first[0].inbound_rate, first[0].outbound_rate), (0.0, 0.0));
+        assert_eq!(first[0].queue_depth, 5);
+
+        let second = monitor.servers(&session(140, 15), 3_000);
+        assert_eq!(
+            (second[0].inbound_rate, second[0].outbound_rate),
+            (20.0, 2.5)
+        );
+        // A new connection starts counting from 0
+        let reconnected = monitor.servers(&session(4, 0), 4_000);
+        assert_eq!(reconnected[0].inbound_rate, 0.0);
+    }
+
+    #[test]
+    fn test_npc_row() {
+        let directive = parse_directive("miner_01 stop", &[]).unwrap();
+        let state = GetNpcStateResponse {
+            npc: Some(NpcSnapshot {
+                npc_id: "miner_01".to_string(),
+                position: Some(Position {
+                    world: "world".to_string(),
+                    x: 10.7,
+                    y: 64.0,
+                    z: -3.2,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            pending_directives: vec![PendingDirective {
+                directive: Some(ActionDirective {
+                    directive_id: "dir-8".to_string(),
+                    ..directive
+                }),
+                ack: None,
+                ..Default::default()
+            }],
+            last_result: Some(ActionResult {
+                directive_id: "dir-7".to_string(),
+                error_message: "path blocked".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let row = npc_row("survival", &state);
+        assert_eq!(row.position, "world 10 64 -4");
+        assert_eq!(row.current, "stop dir-8 unacked");
+        assert_eq!(row.last_result, "dir-7 failed: path blocked");
+        assert_eq!(row.queue_depth, 1);
+
+        let acked = PendingDirective {
+            ack: Some(DirectiveAck::default()),
+            ..state.pending_directives[0].clone()
+        };
+        let state = GetNpcStateResponse {
+            pending_directives: vec![acked],
+            ..state
+        };
+        assert_eq!(npc_row("survival", &state).current, "stop dir-8");
+
+        let frozen = GetNpcStateResponse {
+            npc: state.npc.clone().map(|npc| NpcSnapshot {
+                frozen: true,
+                freeze_reason: "cutscene".to_string(),
+                ..npc
+            }),
+            ..state
+        };
+        assert_eq!(
+            npc_row("survival", &frozen).current,
+            "frozen: cutscene, holding stop dir-8"
+        );
+    }
+
+    #[test]
+    fn test_parse_directive() {
+        let npcs = [NpcSnapshot {
+            npc_id: "miner_01".to_string(),
+            position: Some(Position {
+                world: "world_nether".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }];
+        let directive = parse_directive("miner_01 move 120 64 -30.5", &npcs).unwrap();
+        assert_eq!(directive.npc_id, "miner_01");
+        assert!(directive.directive_id.is_empty());
+        let Some(Action::Move(m)) = directive.action else {
+            panic!("Expected a move");
+        };
+        let target = m.target.unwrap();
+        assert_eq!((target.world.as_str(), target.z), ("world_nether", -30.5));
+
+        let Some(Action::BreakBlock(b)) = parse_directive("miner_01 break 1.5 63 -2.5", &npcs)
+            .unwrap()
+            .action
+        else {
+            panic!("Expected a break");
+        };
+        assert_eq!(b.position.map(|p| (p.x, p.z)), Some((1, -3)));
+        assert!(matches!(
+            parse_directive("guard eat", &npcs).unwrap().action,
+            Some(Action::ConsumeItem(_))
+        ));
+
+        for (line, error) in [
+            ("miner_01", "usage: <npc_id> <action> [args]"),
+            ("miner_01 move 1 2", "expected 3 numbers"),
+            ("miner_01 move 1 two 3", "not a number: two"),
+            ("miner_01 stop now", "wrong arguments for stop"),
+        ] {
+            assert_eq!(
+                parse_directive(line, &npcs),
+                Err(DirectiveParseError(error.to_string()))
+            );
+        }
+        assert!(parse_directive("miner_01 dance", &npcs)
+            .unwrap_err()
+            .0
+            .starts_with("unknown action"));
+    }
+}
@@ -0,0 +1,251 @@
+//! Offline training against the simulator.
+//!
+//! An [`Environment`] connects a [`NpcSocietyHandler`] to a [`Simulation`]
+//! without gRPC: every [`Environment::step`] advances the simulation one
+//! tick, dispatches what the plugin would send to the handler on the
+//! calling thread, feeds the handler's directives back in and returns both
+//! sides of the tick. Nothing waits on a clock, so a training loop runs as
+//! many ticks as the CPU allows, and the handler that was trained is the
+//! one the real server runs.
+//!
+//! ```ignore
+//! let mut env = Environment::new(Policy::default(), SimConfig::default());
+//! for episode in 0..10_000 {
+//!     env.reset(episode);
+//!     for _ in 0..2_000 {
+//!         let step = env.step();
+//!         let reward = score(&step, env.world());
+//!         env.handler().learn(reward);
+//!     }
+//! }
+//! ```
+//!
+//! The environment keeps a [`WorldModel`] of everything the plugin sent,
+//! for rewards and observations; behavior trees attached to the simulation
+//! with [`Simulation::attach`] run alongside the handler.
+
+use crate::connection::ConnectionContext;
+use crate::events::{self, ClientEvent, NpcSocietyHandler};
+use crate::npc_society::v1::{
+    client_message::Message as ClientMsg, server_message::Message as ServerMsg, ClientMessage,
+    ServerMessage,
+};
+use crate::outbound::{self, Outbound, OutboundReceiver, QueueConfig};
+use crate::sim::{SimConfig, Simulation};
+use crate::world_model::WorldModel;
+
+/// Both sides of one simulated tick
+#[derive(Debug, Clone, Default)]
+pub struct Step {
+    /// Server tick after the step
+    pub server_tick: i64,
+    /// What the plugin sent the handler, in order
+    pub received: Vec<ClientMessage>,
+    /// What the handler sent back, in the order the stream would carry it
+    pub sent: Vec<ServerMessage>,
+}
+
+/// A handler connected to a simulation, stepped synchronously.
+pub struct Environment<H> {
+    handler: H,
+    config: SimConfig,
+    simulation: Simulation,
+    world: WorldModel,
+    cx: ConnectionContext,
+    tx: Outbound,
+    rx: OutboundReceiver,
+    episode_ticks: u64,
+}
+
+impl<H: NpcSocietyHandler> Environment<H> {
+    /// Connect `handler` to a new simulation of `config`; the handler gets
+    /// the plugin's Hello right away
+    pub fn new(handler: H, config: SimConfig) -> Self {
+        let (tx, rx) = outbound::queue(QueueConfig::default());
+        let mut env = Self {
+            handler,
+            config,
+            simulation: Simulation::new(config),
+            world: WorldModel::default(),
+            cx: ConnectionContext::new("training", 0),
+            tx,
+            rx,
+            episode_ticks: 0,
+        };
+        env.connect();
+        env
+    }
+
+    /// Start a new episode: a fresh simulation of the same config seeded
+    /// with `seed`, a fresh connection and world model. The handler keeps
+    /// its state, so what it learned carries over.
+    pub fn reset(&mut self, seed: u64) -> Step {
+        self.config.seed = seed;
+        self.simulation = Simulation::new(self.config);
+        self.world = WorldModel::default();
+        self.episode_ticks = 0;
+        self.connect()
+    }
+
+    /// Advance one tick and let the handler answer
+    pub fn step(&mut self) -> Step {
+        let received = self.simulation.step();
+        self.episode_ticks += 1;
+        self.exchange(received)
+    }
+
+    /// Step until `done` returns true for a step or `max_ticks` ran out.
+    /// Returns the ticks stepped and whether `done` stopped the run.
+    pub fn run_until(
+        &mut self,
+        max_ticks: u64,
+        mut done: impl FnMut(&Step) -> bool,
+    ) -> (u64, bool) {
+        for ticks in 1..=max_ticks {
+            if done(&self.step()) {
+                return (ticks, true);
+            }
+        }
+        (max_ticks, false)
+    }
+
+    /// The handler under training
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// The handler, to deploy against the real plugin
+    pub fn into_handler(self) -> H {
+        self.handler
+    }
+
+    /// The simulation, e.g. to inject faults or attach behavior trees
+    pub fn simulation(&mut self) -> &mut Simulation {
+        &mut self.simulation
+    }
+
+    /// Everything the plugin reported this episode
+    pub fn world(&self) -> &WorldModel {
+        &self.world
+    }
+
+    /// Ticks stepped since the last reset
+    pub fn episode_ticks(&self) -> u64 {
+        self.episode_ticks
+    }
+
+    fn connect(&mut self) -> Step {
+        let hello = self.simulation.hello();
+        self.exchange(vec![hello])
+    }
+
+    fn exchange(&mut self, received: Vec<ClientMessage>) -> Step {
+        let now_ms = self.simulation.clock().now_ms();
+        for msg in &received {
+            match &msg.message {
+                // A Hello starts a new stream, like a reconnect would
+                Some(ClientMsg::Hello(_)) => self.cx = ConnectionContext::new("training", now_ms),
+                Some(ClientMsg::WorldBootstrap(bootstrap)) => {
+                    self.world.ingest_bootstrap(bootstrap)
+                }
+                Some(ClientMsg::WorldTick(tick)) => self.world.ingest_tick(tick),
+                Some(ClientMsg::ActionResult(result)) => {
+                    self.world.ingest_action_result(result, now_ms)
+                }
+                Some(ClientMsg::EventObservation(event)) => self.world.ingest_event(event),
+                Some(ClientMsg::BlockWatchUpdate(update)) => {
+                    self.world.ingest_block_watch(update, now_ms)
+                }
+                _ => {}
+            }
+            if let Ok(event) = ClientEvent::try_from(msg.clone()) {
+                events::dispatch(&self.handler, event, &self.cx, &self.tx);
+            }
+        }
+
+        let mut sent = Vec::new();
+        while let Some(msg) = self.rx.try_next() {
+            if let Some(ServerMsg::ActionDirective(directive)) = &msg.message {
+                self.simulation.send(directive.clone());
+            }
+            sent.push(msg);
+        }
+        Step {
+            server_tick: self.simulation.clock().server_tick(),
+            received,
+            sent,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+    use crate::npc_society::v1::{
+        action_directive::Action, ActionDirective, ActionResult, LookAction, WorldTick,
+    };
+
+    /// Sends one look per NPC and another once it finished, counting results
+    #[derive(Default)]
+    struct Looker {
+        sent: AtomicU64,
+        results: AtomicU64,
+    }
+
+    impl Looker {
+        fn look(&self, npc_id: &str, tx: &Outbound) {
+            let n = self.sent.fetch_add(1, Ordering::Relaxed);
+            tx.send(ActionDirective {
+                directive_id: format!("look-{n}"),
+                npc_id: npc_id.to_string(),
+                action: Some(Action::Look(LookAction::default())),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+    }
+
+    impl NpcSocietyHandler for Looker {
+        fn on_world_tick(&self, tick: WorldTick, _cx: &ConnectionContext, tx: &Outbound) {
+            if tick.server_tick == 1 {
+                for npc in &tick.npcs {
+                    self.look(&npc.npc_id, tx);
+                }
+            }
+        }
+
+        fn on_action_result(&self, result: ActionResult, _cx: &ConnectionContext, tx: &Outbound) {
+            self.results.fetch_add(1, Ordering::Relaxed);
+            self.look(&result.npc_id, tx);
+        }
+    }
+
+    #[test]
+    fn test_step_and_reset() {
+        let config = SimConfig {
+            seed: 3,
+            ..Default::default()
+        };
+        let mut env = Environment::new(Looker::default(), config);
+        let first = env.step();
+        assert_eq!(first.server_tick, 1);
+        assert_eq!(first.sent.len(), config.npcs);
+
+        let (ticks, done) = env.run_until(1_000, |step| !step.sent.is_empty());
+        assert!(done);
+        assert!(ticks <= config.action_ticks.1 as u64);
+        assert_eq!(env.episode_ticks(), ticks + 1);
+        let results = env.handler().results.load(Ordering::Relaxed);
+        assert!(results >= 1);
+
+        // The handler's state survives a reset; the world starts over
+        env.reset(4);
+        assert_eq!(env.episode_ticks(), 0);
+        assert_eq!(env.simulation().clock().server_tick(), 0);
+        assert_eq!(env.handler().results.load(Ordering::Relaxed), results);
+        env.run_until(200, |_| false);
+        assert!(env.into_handler().results.load(Ordering::Relaxed) > results);
+    }
+}
@@ -0,0 +1,434 @@
+//! Reviewable conversation logs per NPC (feature `persistence`).
+//!
+//! Moderators asked what an NPC told a player need a record they can read
+//! without a database client. A [`TranscriptLog`] appends every line of
+//! every conversation, whether typed chat, transcribed voice or the NPC's
+//! reply, to a JSON Lines file per NPC:
+//!
+//! ```text
+//! <dir>/<npc_id>/transcript.jsonl
+//! <dir>/<npc_id>/transcript-<first timestamp_ms>.jsonl   (rotated)
+//! ```
+//!
+//! Each line is one [`TranscriptEntry`]. The current file is rotated once
+//! it reaches [`TranscriptConfig::max_bytes`] or its first line is older
+//! than [`TranscriptConfig::max_age_ms`]; only the newest
+//! [`TranscriptConfig::keep`] rotated files are kept.
+//!
+//! The [`Store`] keeps the same dialogue as [`Turn`]s for the NPCs
+//! themselves; [`TranscriptEntry::turn`] and [`export`] convert between the
+//! two, so dialogue stored before the log was turned on can still be
+//! written out for review.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::conversation::{Speaker, Turn};
+use crate::persistence::Store;
+
+/// Name of the file being written in each NPC's directory
+const CURRENT: &str = "transcript.jsonl";
+
+/// Where transcripts go and when they rotate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscriptConfig {
+    /// Directory with one subdirectory per NPC
+    pub dir: PathBuf,
+    /// Size at which the current file is rotated
+    pub max_bytes: u64,
+    /// Age of the first line at which the current file is rotated
+    pub max_age_ms: i64,
+    /// Rotated files kept per NPC; older ones are deleted
+    pub keep: usize,
+}
+
+impl TranscriptConfig {
+    /// Daily files of at most 8 MiB under `dir`, kept for 30 rotations
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_bytes: 8 << 20,
+            max_age_ms: 24 * 60 * 60 * 1000,
+            keep: 30,
+        }
+    }
+}
+
+/// How a line reached the conversation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    /// A player typed it in chat
+    Chat,
+    /// A player said it, as transcribed by ASR
+    Voice,
+    /// The NPC said it
+    Reply,
+}
+
+impl Channel {
+    /// Name in the log
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Chat => "chat",
+            Self::Voice => "voice",
+            Self::Reply => "reply",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "chat" => Some(Self::Chat),
+            "voice" => Some(Self::Voice),
+            "reply" => Some(Self::Reply),
+            _ => None,
+        }
+    }
+}
+
+/// One line of a conversation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscriptEntry {
+    /// Unix timestamp in milliseconds
+    pub timestamp_ms: i64,
+    /// Server the conversation happened on
+    pub server_id: String,
+    /// The NPC
+    pub npc_id: String,
+    /// How the line was said
+    pub channel: Channel,
+    /// player_uuid of the player who said it, or the npc_id for replies
+    pub speaker: String,
+    /// What was said
+    pub text: String,
+    /// Players in the conversation at the time, including the speaker
+    pub participants: Vec<String>,
+}
+
+impl TranscriptEntry {
+    /// `turn` of the dialogue between `npc_id` and `player_uuid`; player
+    /// turns are logged as chat since a [`Turn`] does not say
+    pub fn from_turn(server_id: &str, npc_id: &str, player_uuid: &str, turn: &Turn) -> Self {
+        let (channel, speaker) = match turn.speaker {
+            Speaker::Player => (Channel::Chat, player_uuid),
+            Speaker::Npc => (Channel::Reply, npc_id),
+        };
+        Self {
+            timestamp_ms: turn.timestamp_ms,
+            server_id: server_id.to_string(),
+            npc_id: npc_id.to_string(),
+            channel,
+            speaker: speaker.to_string(),
+            text: turn.text.clone(),
+            participants: vec![player_uuid.to_string()],
+        }
+    }
+
+    /// The entry as a dialogue [`Turn`] for the [`Store`]
+    pub fn turn(&self) -> Turn {
+        Turn {
+            speaker: match self.channel {
+                Channel::Reply => Speaker::Npc,
+                Channel::Chat | Channel::Voice => Speaker::Player,
+            },
+            text: self.text.clone(),
+            timestamp_ms: self.timestamp_ms,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "timestamp_ms": self.timestamp_ms,
+            "server_id": self.server_id,
+            "npc_id": self.npc_id,
+            "channel": self.channel.as_str(),
+            "speaker": self.speaker,
+            "text": self.text,
+            "participants": self.participants,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let text = |key: &str| value.get(key)?.as_str().map(str::to_string);
+        Some(Self {
+            timestamp_ms: value.get("timestamp_ms")?.as_i64()?,
+            server_id: text("server_id")?,
+            npc_id: text("npc_id")?,
+            channel: Channel::parse(value.get("channel")?.as_str()?)?,
+            speaker: text("speaker")?,
+            text: text("text")?,
+            participants: value
+                .get("participants")?
+                .as_array()?
+                .iter()
+                .filter_map(|p| p.as_str().map(str::to_string))
+                .collect(),
+        })
+    }
+}
+
+/// The current file of one NPC
+#[derive(Debug, Clone, Copy)]
+struct Current {
+    bytes: u64,
+    first_ms: Option<i64>,
+}
+
+/// Per-NPC transcript files with rotation
+#[derive(Debug)]
+pub struct TranscriptLog {
+    config: TranscriptConfig,
+    current: HashMap<String, Current>,
+}
+
+impl TranscriptLog {
+    /// Write transcripts as `config` says; directories are created as
+    /// NPCs are first logged
+    pub fn new(config: TranscriptConfig) -> Self {
+        Self {
+            config,
+            current: HashMap::new(),
+        }
+    }
+
+    /// Where and when transcripts are written and rotated
+    pub fn config(&self) -> &TranscriptConfig {
+        &self.config
+    }
+
+    /// Directory of the NPC's files; characters that are not safe in a
+    /// file name become `_`
+    pub fn npc_dir(&self, npc_id: &str) -> PathBuf {
+        let name: String = npc_id
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+                _ => '_',
+            })
+            .collect();
+        self.config.dir.join(name.trim_start_matches('.'))
+    }
+
+    /// Append `entry` to its NPC's file, rotating first if it is due
+    pub fn append(&mut self, entry: &TranscriptEntry) -> io::Result<()> {
+        let dir = self.npc_dir(&entry.npc_id);
+        let mut line = entry.to_json().to_string();
+        line.push('\n');
+
+        let current = match self.current.get(&entry.npc_id) {
+            Some(current) => *current,
+            None => {
+                fs::create_dir_all(&dir)?;
+                scan(&dir.join(CURRENT))?
+            }
+        };
+        let due = current.bytes > 0
+            && (current.bytes + line.len() as u64 > self.config.max_bytes
+                || current
+                    .first_ms
+                    .is_some_and(|first| entry.timestamp_ms - first >= self.config.max_age_ms));
+        let current = if due {
+            self.rotate(&dir, current.first_ms.unwrap_or(entry.timestamp_ms))?;
+            Current {
+                bytes: 0,
+                first_ms: None,
+            }
+        } else {
+            current
+        };
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(CURRENT))?
+            .write_all(line.as_bytes())?;
+        self.current.insert(
+            entry.npc_id.clone(),
+            Current {
+                bytes: current.bytes + line.len() as u64,
+                first_ms: current.first_ms.or(Some(entry.timestamp_ms)),
+            },
+        );
+        Ok(())
+    }
+
+    /// Every entry of the NPC still on disk, oldest first
+    pub fn read(&self, npc_id: &str) -> io::Result<Vec<TranscriptEntry>> {
+        let dir = self.npc_dir(npc_id);
+        let mut files = rotated(&dir)?;
+        files.push(dir.join(CURRENT));
+        let mut entries = Vec::new();
+        for path in files {
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            for line in BufReader::new(file).lines() {
+                let value: Value = serde_json::from_str(&line?)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                entries.extend(TranscriptEntry::from_json(&value));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn rotate(&self, dir: &Path, first_ms: i64) -> io::Result<()> {
+        let rotated_name = format!("transcript-{:013}.jsonl", first_ms.max(0));
+        fs::rename(dir.join(CURRENT), dir.join(rotated_name))?;
+        let files = rotated(dir)?;
+        let excess = files.len().saturating_sub(self.config.keep);
+        for path in &files[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Size and first timestamp of an existing current file
+fn scan(path: &Path) -> io::Result<Current> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(Current {
+                bytes: 0,
+                first_ms: None,
+            })
+        }
+        Err(e) => return Err(e),
+    };
+    let bytes = file.metadata()?.len();
+    let mut first = String::new();
+    BufReader::new(file).read_line(&mut first)?;
+    let first_ms = serde_json::from_str::<Value>(&first)
+        .ok()
+        .and_then(|value| value.get("timestamp_ms")?.as_i64());
+    Ok(Current { bytes, first_ms })
+}
+
+/// Rotated files in `dir`, oldest first
+fn rotated(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        if name.starts_with("transcript-") && name.ends_with(".jsonl") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The `limit` most recent turns `store` holds of the dialogue between
+/// `npc_id` and `player_uuid`, as entries to append to a [`TranscriptLog`]
+pub fn export<S: Store>(
+    store: &S,
+    server_id: &str,
+    npc_id: &str,
+    player_uuid: &str,
+    limit: usize,
+) -> Result<Vec<TranscriptEntry>, S::Error> {
+    Ok(store
+        .dialogue(npc_id, player_uuid, limit)?
+        .iter()
+        .map(|turn| TranscriptEntry::from_turn(server_id, npc_id, player_uuid, turn))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::SqliteStore;
+
+    fn entry(timestamp_ms: i64, channel: Channel, text: &str) -> TranscriptEntry {
+        TranscriptEntry {
+            timestamp_ms,
+            server_id: "survival".to_string(),
+            npc_id: "miner/1".to_string(),
+            channel,
+            speaker: "p-1".to_string(),
+            text: text.to_string(),
+            participants: vec!["p-1".to_string(), "p-2".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_append_and_rotate() {
+        let dir = std::env::temp_dir().join(format!("npc-transcripts-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = TranscriptConfig {
+            max_bytes: 4096,
+            max_age_ms: 60_000,
+            keep: 2,
+            ..TranscriptConfig::new(&dir)
+        };
+        let mut log = TranscriptLog::new(config.clone());
+        let npc_dir = log.npc_dir("miner/1");
+        assert_eq!(npc_dir, dir.join("miner_1"));
+
+        log.append(&entry(0, Channel::Chat, "Where are the diamonds?"))
+            .unwrap();
+        log.append(&entry(1_000, Channel::Reply, "Deep down, friend."))
+            .unwrap();
+        log.append(&entry(2_000, Channel::Voice, "Show me"))
+            .unwrap();
+        let entries = log.read("miner/1").unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1].channel, Channel::Reply);
+        assert_eq!(entries[2], entry(2_000, Channel::Voice, "Show me"));
+
+        // A minute after the first line the file rotates, and only the
+        // newest two rotated files stay
+        log.append(&entry(60_000, Channel::Chat, "Thanks")).unwrap();
+        log.append(&entry(120_000, Channel::Chat, "Hello again"))
+            .unwrap();
+        // A restarted daemon picks up the current file where it was
+        let mut log = TranscriptLog::new(config);
+        log.append(&entry(180_000, Channel::Chat, "Still here"))
+            .unwrap();
+        assert_eq!(rotated(&npc_dir).unwrap().len(), 2);
+        let texts: Vec<String> = log
+            .read("miner/1")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.text)
+            .collect();
+        assert_eq!(texts, ["Thanks", "Hello again", "Still here"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_from_store() {
+        let mut store = SqliteStore::in_memory().unwrap();
+        let reply = entry(1_000, Channel::Reply, "Deep down, friend.");
+        store
+            .append_turn(
+                "miner/1",
+                "p-1",
+                &entry(0, Channel::Voice, "Diamonds?").turn(),
+            )
+            .unwrap();
+        store.append_turn("miner/1", "p-1", &reply.turn()).unwrap();
+
+        let exported = export(&store, "survival", "miner/1", "p-1", 10).unwrap();
+        assert_eq!(exported.len(), 2);
+        assert_eq!(exported[0].channel, Channel::Chat);
+        assert_eq!(exported[0].speaker, "p-1");
+        assert_eq!(exported[1].channel, Channel::Reply);
+        assert_eq!(exported[1].speaker, "miner/1");
+        assert_eq!(exported[1].text, reply.text);
+    }
+}
@@ -0,0 +1,429 @@
+//! Moving NPCs between servers.
+//!
+//! [`TransferCoordinator`] runs the daemon's side of the two-phase handoff
+//! described in the proto: [`begin`](TransferCoordinator::begin) asks the
+//! source to freeze the NPC, and every [`NpcTransferUpdate`] either side
+//! sends is passed to [`on_update`](TransferCoordinator::on_update), which
+//! answers with the messages to route next. The coordinator never sends
+//! anything itself; each [`Outgoing`] names the server it is for.
+//!
+//! Servers that go quiet are handled by [`expire`](TransferCoordinator::expire):
+//! a transfer not spawned within `timeout_ms` is rolled back, and one whose
+//! servers never confirm the outcome is dropped after another `timeout_ms`
+//! so the NPC can be transferred again.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::npc_society::v1::{
+    server_message::Message as ServerMsg, FinishNpcTransfer, NpcStateSnapshot, NpcTransferStage,
+    NpcTransferUpdate, Position, PrepareNpcTransfer, ServerMessage, SpawnTransferredNpc,
+};
+
+/// How long servers get for each phase of a transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferConfig {
+    /// Time to prepare and spawn before the transfer is rolled back, and
+    /// to confirm the outcome before it is given up
+    pub timeout_ms: i64,
+}
+
+impl Default for TransferConfig {
+    fn default() -> Self {
+        Self { timeout_ms: 30_000 }
+    }
+}
+
+/// Why a transfer could not start
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferError {
+    /// Source and target are the same server
+    SameServer(String),
+    /// The NPC is already being transferred, by this transfer
+    InProgress(String),
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransferError::SameServer(id) => {
+                write!(f, "source and target are both server '{}'", id)
+            }
+            TransferError::InProgress(id) => write!(f, "NPC is already in transfer {}", id),
+        }
+    }
+}
+
+impl std::error::Error for TransferError {}
+
+/// `INVALID_ARGUMENT`, or `FAILED_PRECONDITION` while the NPC is busy
+impl From<TransferError> for tonic::Status {
+    fn from(e: TransferError) -> Self {
+        match e {
+            TransferError::SameServer(_) => tonic::Status::invalid_argument(e.to_string()),
+            TransferError::InProgress(_) => tonic::Status::failed_precondition(e.to_string()),
+        }
+    }
+}
+
+/// A message for one server
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outgoing {
+    /// Server to send it to
+    pub server_id: String,
+    /// What to send
+    pub message: ServerMessage,
+}
+
+/// One NPC on its way between servers
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    /// Id in every message of the transfer
+    pub transfer_id: String,
+    /// NPC being moved
+    pub npc_id: String,
+    /// Server it comes from
+    pub source_server_id: String,
+    /// Server it goes to
+    pub target_server_id: String,
+    /// Where the target spawns it
+    pub spawn_position: Option<Position>,
+    /// How far it got: UNSPECIFIED while the source prepares, PREPARED
+    /// while the target spawns, then COMMITTED or ROLLED_BACK
+    pub stage: NpcTransferStage,
+    /// What the source reported when it froze the NPC
+    pub snapshot: Option<NpcStateSnapshot>,
+    /// Why it rolled back
+    pub error_message: String,
+    /// When it began
+    pub started_at_ms: i64,
+    decided_at_ms: i64,
+    source_done: bool,
+    target_done: bool,
+}
+
+impl Transfer {
+    /// Whether it was decided to commit or roll back
+    pub fn is_decided(&self) -> bool {
+        matches!(
+            self.stage,
+            NpcTransferStage::Committed | NpcTransferStage::RolledBack
+        )
+    }
+
+    /// Whether it committed
+    pub fn is_committed(&self) -> bool {
+        self.stage == NpcTransferStage::Committed
+    }
+
+    fn message(&self, server_id: &str, message: ServerMsg) -> Outgoing {
+        Outgoing {
+            server_id: server_id.to_string(),
+            message: ServerMessage {
+                message: Some(message),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Decide the outcome and tell both servers
+    fn finish(&mut self, commit: bool, error_message: &str, now_ms: i64) -> Vec<Outgoing> {
+        self.stage = if commit {
+            NpcTransferStage::Committed
+        } else {
+            NpcTransferStage::RolledBack
+        };
+        self.error_message = error_message.to_string();
+        self.decided_at_ms = now_ms;
+        let finish = FinishNpcTransfer {
+            transfer_id: self.transfer_id.clone(),
+            npc_id: self.npc_id.clone(),
+            commit,
+        };
+        vec![
+            self.message(
+                &self.source_server_id,
+                ServerMsg::FinishNpcTransfer(finish.clone()),
+            ),
+            self.message(&self.target_server_id, ServerMsg::FinishNpcTransfer(finish)),
+        ]
+    }
+}
+
+/// The transfers in flight
+#[derive(Debug, Default)]
+pub struct TransferCoordinator {
+    config: TransferConfig,
+    transfers: HashMap<String, Transfer>,
+    finished: Vec<Transfer>,
+    next_id: u64,
+}
+
+impl TransferCoordinator {
+    /// A coordinator with `config`
+    pub fn new(config: TransferConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Start moving `npc_id` from `source` to `target`; send the returned
+    /// PrepareNpcTransfer to the source
+    pub fn begin(
+        &mut self,
+        npc_id: &str,
+        source: &str,
+        target: &str,
+        spawn_position: Option<Position>,
+        now_ms: i64,
+    ) -> Result<(String, Outgoing), TransferError> {
+        if source == target {
+            return Err(TransferError::SameServer(source.to_string()));
+        }
+        if let Some(transfer) = self.active(npc_id) {
+            return Err(TransferError::InProgress(transfer.transfer_id.clone()));
+        }
+        self.next_id += 1;
+        let transfer = Transfer {
+            transfer_id: format!("transfer-{}-{}", now_ms, self.next_id),
+            npc_id: npc_id.to_string(),
+            source_server_id: source.to_string(),
+            target_server_id: target.to_string(),
+            spawn_position,
+            stage: NpcTransferStage::Unspecified,
+            snapshot: None,
+            error_message: String::new(),
+            started_at_ms: now_ms,
+            decided_at_ms: 0,
+            source_done: false,
+            target_done: false,
+        };
+        let prepare = transfer.message(
+            source,
+            ServerMsg::PrepareNpcTransfer(PrepareNpcTransfer {
+                transfer_id: transfer.transfer_id.clone(),
+                npc_id: npc_id.to_string(),
+                target_server_id: target.to_string(),
+            }),
+        );
+        let transfer_id = transfer.transfer_id.clone();
+        self.transfers.insert(transfer_id.clone(), transfer);
+        Ok((transfer_id, prepare))
+    }
+
+    /// Handle `update` from `server_id` and return what to send next.
+    /// Updates for unknown transfers, from the wrong server or out of order
+    /// are ignored.
+    pub fn on_update(
+        &mut self,
+        server_id: &str,
+        update: &NpcTransferUpdate,
+        now_ms: i64,
+    ) -> Vec<Outgoing> {
+        let Some(transfer) = self.transfers.get_mut(&update.transfer_id) else {
+            return Vec::new();
+        };
+        let from_source = server_id == transfer.source_server_id;
+        let from_target = server_id == transfer.target_server_id;
+        if !from_source && !from_target {
+            return Vec::new();
+        }
+        let outgoing = match update.stage() {
+            NpcTransferStage::Prepared
+                if from_source && transfer.stage == NpcTransferStage::Unspecified =>
+            {
+                transfer.stage = NpcTransferStage::Prepared;
+                transfer.snapshot = update.snapshot.as_deref().cloned();
+                let spawn = SpawnTransferredNpc {
+                    transfer_id: transfer.transfer_id.clone(),
+                    source_server_id: transfer.source_server_id.clone(),
+                    snapshot: transfer.snapshot.clone().map(Box::new),
+                    spawn_position: transfer.spawn_position.clone(),
+                };
+                vec![transfer.message(
+                    &transfer.target_server_id,
+                    ServerMsg::SpawnTransferredNpc(spawn),
+                )]
+            }
+            NpcTransferStage::Spawned
+                if from_target && transfer.stage == NpcTransferStage::Prepared =>
+            {
+                transfer.finish(true, "", now_ms)
+            }
+            NpcTransferStage::Failed if !transfer.is_decided() => {
+                let side = if from_source { "source" } else { "target" };
+                let error = format!("{} failed: {}", side, update.error_message);
+                transfer.finish(false, &error, now_ms)
+            }
+            NpcTransferStage::Committed | NpcTransferStage::RolledBack if transfer.is_decided() => {
+                transfer.source_done |= from_source;
+                transfer.target_done |= from_target;
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+        if transfer.source_done && transfer.target_done {
+            let transfer_id = update.transfer_id.clone();
+            self.done(&transfer_id);
+        }
+        outgoing
+    }
+
+    /// Roll back transfers that were not spawned in time, and give up on
+    /// decided ones whose servers never confirmed
+    pub fn expire(&mut self, now_ms: i64) -> Vec<Outgoing> {
+        let timeout_ms = self.config.timeout_ms;
+        let mut outgoing = Vec::new();
+        let mut given_up = Vec::new();
+        for transfer in self.transfers.values_mut() {
+            if !transfer.is_decided() {
+                if now_ms - transfer.started_at_ms >= timeout_ms {
+                    outgoing.extend(transfer.finish(false, "timed out", now_ms));
+                }
+            } else if now_ms - transfer.decided_at_ms >= timeout_ms {
+                given_up.push(transfer.transfer_id.clone());
+            }
+        }
+        for transfer_id in given_up {
+            self.done(&transfer_id);
+        }
+        outgoing
+    }
+
+    /// The transfer `npc_id` is in, if any
+    pub fn active(&self, npc_id: &str) -> Option<&Transfer> {
+        self.transfers.values().find(|t| t.npc_id == npc_id)
+    }
+
+    /// Transfer `transfer_id`, while in flight
+    pub fn get(&self, transfer_id: &str) -> Option<&Transfer> {
+        self.transfers.get(transfer_id)
+    }
+
+    /// Transfers that ended since the last call, oldest first
+    pub fn take_finished(&mut self) -> Vec<Transfer> {
+        std::mem::take(&mut self.finished)
+    }
+
+    fn done(&mut self, transfer_id: &str) {
+        if let Some(transfer) = self.transfers.remove(transfer_id) {
+            self.finished.push(transfer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(
+        transfer_id: &str,
+        stage: NpcTransferStage,
+        snapshot: Option<NpcStateSnapshot>,
+    ) -> NpcTransferUpdate {
+        NpcTransferUpdate {
+            transfer_id: transfer_id.to_string(),
+            npc_id: "miner".to_string(),
+            stage: stage as i32,
+            snapshot: snapshot.map(Box::new),
+            error_message: String::new(),
+        }
+    }
+
+    fn finish_commit(outgoing: &Outgoing) -> Option<bool> {
+        match &outgoing.message.message {
+            Some(ServerMsg::FinishNpcTransfer(finish)) => Some(finish.commit),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_commit() {
+        let mut transfers = TransferCoordinator::default();
+        let (id, prepare) = transfers
+            .begin("miner", "lobby", "survival", None, 0)
+            .unwrap();
+        assert_eq!(prepare.server_id, "lobby");
+        assert_eq!(
+            transfers.begin("miner", "lobby", "creative", None, 0),
+            Err(TransferError::InProgress(id.clone()))
+        );
+
+        // Only the source may report PREPARED
+        let snapshot = NpcStateSnapshot {
+            npc_id: "miner".to_string(),
+            ..Default::default()
+        };
+        let prepared = update(&id, NpcTransferStage::Prepared, Some(snapshot));
+        assert!(transfers.on_update("survival", &prepared, 10).is_empty());
+        let spawn = transfers.on_update("lobby", &prepared, 10);
+        assert_eq!(spawn.len(), 1);
+        assert_eq!(spawn[0].server_id, "survival");
+        assert!(matches!(
+            &spawn[0].message.message,
+            Some(ServerMsg::SpawnTransferredNpc(s)) if s.snapshot.as_ref().unwrap().npc_id == "miner"
+        ));
+
+        let spawned = update(&id, NpcTransferStage::Spawned, None);
+        let finish = transfers.on_update("survival", &spawned, 20);
+        assert_eq!(finish.len(), 2);
+        assert!(finish.iter().all(|o| finish_commit(o) == Some(true)));
+
+        let committed = update(&id, NpcTransferStage::Committed, None);
+        transfers.on_update("lobby", &committed, 30);
+        assert!(transfers.take_finished().is_empty());
+        transfers.on_update("survival", &committed, 30);
+        let finished = transfers.take_finished();
+        assert_eq!(finished.len(), 1);
+        assert!(finished[0].is_committed());
+        assert!(transfers.active("miner").is_none());
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut transfers = TransferCoordinator::new(TransferConfig { timeout_ms: 1_000 });
+        assert_eq!(
+            transfers.begin("miner", "lobby", "lobby", None, 0),
+            Err(TransferError::SameServer("lobby".to_string()))
+        );
+
+        // The target cannot spawn the NPC
+        let (id, _) = transfers
+            .begin("miner", "lobby", "survival", None, 0)
+            .unwrap();
+        let snapshot = Some(NpcStateSnapshot::default());
+        transfers.on_update(
+            "lobby",
+            &update(&id, NpcTransferStage::Prepared, snapshot),
+            10,
+        );
+        let mut failed = update(&id, NpcTransferStage::Failed, None);
+        failed.error_message = "no room".to_string();
+        let finish = transfers.on_update("survival", &failed, 20);
+        assert!(finish.iter().all(|o| finish_commit(o) == Some(false)));
+        assert_eq!(
+            transfers.get(&id).unwrap().error_message,
+            "target failed: no room"
+        );
+
+        // Only the source confirms; the transfer is given up after the timeout
+        let rolled_back = update(&id, NpcTransferStage::RolledBack, None);
+        transfers.on_update("lobby", &rolled_back, 30);
+        assert!(transfers.expire(500).is_empty());
+        assert!(transfers.active("miner").is_some());
+        transfers.expire(1_020);
+        assert_eq!(
+            transfers.take_finished()[0].stage,
+            NpcTransferStage::RolledBack
+        );
+
+        // The source never answers
+        let (id, _) = transfers
+            .begin("miner", "lobby", "survival", None, 2_000)
+            .unwrap();
+        let finish = transfers.expire(3_000);
+        assert_eq!(finish.len(), 2);
+        assert_eq!(transfers.get(&id).unwrap().error_message, "timed out");
+    }
+}
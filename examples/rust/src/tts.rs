@@ -0,0 +1,441 @@
+//! Pluggable speech synthesis.
+//!
+//! A [`TtsProvider`] turns `SpeakDirective` text into audio, and
+//! [`chunk_speech`] cuts that audio into the `AudioChunk` stream the plugin
+//! plays back: 20ms frames of 48kHz mono S16LE, numbered from 0, the last
+//! one marked `is_final`, each carrying the directive's `npc_id`,
+//! `stream_id` and `directive_id`. Engines that report mouth shapes can
+//! also drive lip-sync through [`viseme_timeline`].
+//!
+//! [`SpeechRegistry`] tracks streams still being sent so they can be cut
+//! short when a player barges in (`SpeechInterrupted` -> `StopSpeaking`),
+//! and paces each at about real time ([`crate::pacing`]) by the plugin's
+//! `AudioBufferStatus` reports.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::audio;
+use crate::emotion::{Spoken, StyleTable, TtsStyle};
+use crate::npc_society::v1::{
+    AudioBufferStatus, AudioChunk, PcmFormat, SpeakDirective, VisemeCue, VisemeTimeline,
+};
+use crate::pacing::{self, AudioPacer, PacingConfig};
+use crate::types::{NpcId, StreamId};
+
+/// Samples per AudioChunk: 20ms at 48kHz, the frame size Simple Voice Chat plays
+pub const FRAME_SAMPLES: usize = 960;
+
+/// Result of [`TtsProvider::synthesize`]
+pub type SynthesisFuture = Pin<Box<dyn Future<Output = Result<SynthesizedAudio, TtsError>> + Send>>;
+
+/// Mono audio produced by a [`TtsProvider`], at whatever rate the engine uses
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SynthesizedAudio {
+    /// Mono f32 samples
+    pub samples: Vec<f32>,
+    /// Sample rate of `samples`
+    pub sample_rate_hz: u32,
+    /// Mouth shapes with offsets from the start of `samples`, if the engine
+    /// reports them (empty otherwise)
+    pub visemes: Vec<VisemeCue>,
+}
+
+impl SynthesizedAudio {
+    /// Playback length, e.g. for `SpeakDirective.duration_ms`
+    pub fn duration_ms(&self) -> u64 {
+        if self.sample_rate_hz == 0 {
+            return 0;
+        }
+        self.samples.len() as u64 * 1000 / self.sample_rate_hz as u64
+    }
+
+    /// Playback length, e.g. for [`crate::time::Timed::set_duration`]
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms())
+    }
+}
+
+/// Error reported by a [`TtsProvider`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TtsError {
+    /// The backend could not be reached
+    Unavailable(String),
+    /// The backend rejected the request (e.g. unknown voice_id)
+    Backend(String),
+}
+
+impl fmt::Display for TtsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TtsError::Unavailable(msg) => write!(f, "TTS backend unavailable: {msg}"),
+            TtsError::Backend(msg) => write!(f, "TTS backend error: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for TtsError {}
+
+/// A speech synthesis backend.
+pub trait TtsProvider: Send + Sync {
+    /// Synthesize `text` with the given voice (empty = backend default).
+    fn synthesize(&self, text: &str, voice_id: &str) -> SynthesisFuture;
+
+    /// How this backend speaks each tone. Backends with named speaking
+    /// styles return their own table; the default only shifts pitch and rate.
+    fn styles(&self) -> StyleTable {
+        StyleTable::prosody()
+    }
+
+    /// Synthesize `text` in `style`. The default ignores the style.
+    fn synthesize_styled(&self, text: &str, voice_id: &str, style: &TtsStyle) -> SynthesisFuture {
+        let _ = style;
+        self.synthesize(text, voice_id)
+    }
+}
+
+/// Synthesize `speak`'s text in its voice and tone
+pub fn synthesize_speech(tts: &dyn TtsProvider, speak: &SpeakDirective) -> SynthesisFuture {
+    let style = tts.styles().style(&speak.tone());
+    tts.synthesize_styled(&speak.text, &speak.voice_id, &style)
+}
+
+/// Placeholder provider that "speaks" silence of a plausible length
+/// (300ms per word). Useful for exercising playback without a TTS engine.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SilenceTts;
+
+impl TtsProvider for SilenceTts {
+    fn synthesize(&self, text: &str, voice_id: &str) -> SynthesisFuture {
+        self.synthesize_styled(text, voice_id, &TtsStyle::default())
+    }
+
+    /// Faster speech is shorter silence
+    fn synthesize_styled(&self, text: &str, _voice_id: &str, style: &TtsStyle) -> SynthesisFuture {
+        let words = text.split_whitespace().count().max(1);
+        let seconds = words as f32 * 0.3 / style.rate.max(0.1);
+        let samples = vec![0.0; (seconds * audio::PROTOCOL_SAMPLE_RATE_HZ as f32) as usize];
+        Box::pin(async move {
+            Ok(SynthesizedAudio {
+                samples,
+                sample_rate_hz: audio::PROTOCOL_SAMPLE_RATE_HZ,
+                visemes: Vec::new(),
+            })
+        })
+    }
+}
+
+/// Cut synthesized audio into the AudioChunks for `speak`.
+///
+/// Audio is resampled to 48kHz S16LE and split into [`FRAME_SAMPLES`]
+/// frames; the last frame is padded with silence. At least one chunk is
+/// always returned so the stream is terminated by an `is_final` chunk.
+/// `speak.stream_id` must be set.
+pub fn chunk_speech(speak: &SpeakDirective, synthesized: &SynthesizedAudio) -> Vec<AudioChunk> {
+    debug_assert!(!speak.stream_id.is_empty(), "SpeakDirective.stream_id is required for audio");
+
+    let mut samples = audio::resample(
+        &synthesized.samples,
+        synthesized.sample_rate_hz,
+        audio::PROTOCOL_SAMPLE_RATE_HZ,
+    );
+    let padding = match samples.len() % FRAME_SAMPLES {
+        0 if !samples.is_empty() => 0,
+        partial => FRAME_SAMPLES - partial,
+    };
+    samples.resize(samples.len() + padding, 0.0);
+    let frames = samples.len() / FRAME_SAMPLES;
+
+    // Convert once; every chunk is a slice of the same buffer
+    let pcm = Bytes::from(audio::f32_to_s16le(&samples));
+    let frame_bytes = FRAME_SAMPLES * 2;
+    (0..frames)
+        .map(|i| AudioChunk {
+            npc_id: speak.npc_id.clone(),
+            stream_id: speak.stream_id.clone(),
+            pcm_data: pcm.slice(i * frame_bytes..(i + 1) * frame_bytes),
+            sequence: i as u64,
+            is_final: i + 1 == frames,
+            directive_id: speak.directive_id.clone(),
+            format: PcmFormat::S16le as i32,
+        })
+        .collect()
+}
+
+/// Lip-sync timeline for `speak`, or None if the engine reported no visemes.
+/// Send it before the stream's first AudioChunk.
+pub fn viseme_timeline(speak: &SpeakDirective, synthesized: &SynthesizedAudio) -> Option<VisemeTimeline> {
+    if synthesized.visemes.is_empty() {
+        return None;
+    }
+    Some(VisemeTimeline {
+        npc_id: speak.npc_id.clone(),
+        stream_id: speak.stream_id.clone(),
+        directive_id: speak.directive_id.clone(),
+        cues: synthesized.visemes.clone(),
+    })
+}
+
+#[derive(Debug)]
+struct ActiveSpeech {
+    npc_id: String,
+    cancelled: Arc<AtomicBool>,
+    pacer: Arc<Mutex<AudioPacer>>,
+}
+
+/// In-flight TTS streams, keyed by stream_id.
+///
+/// Cloning shares the registry. Register a stream with
+/// [`start`](Self::start) before sending its chunks and check
+/// [`SpeechHandle::is_cancelled`] and [`SpeechHandle::wait_ms`] between
+/// chunks.
+#[derive(Debug, Clone, Default)]
+pub struct SpeechRegistry {
+    streams: Arc<Mutex<HashMap<String, ActiveSpeech>>>,
+    pacing: PacingConfig,
+}
+
+impl SpeechRegistry {
+    /// Create a registry that paces streams with `pacing`
+    pub fn with_pacing(pacing: PacingConfig) -> Self {
+        Self {
+            pacing,
+            ..Self::default()
+        }
+    }
+
+    /// Register a stream; it is forgotten again when the handle is dropped.
+    pub fn start(&self, npc_id: &NpcId, stream_id: &StreamId) -> SpeechHandle {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let pacer = Arc::new(Mutex::new(AudioPacer::new(self.pacing)));
+        self.streams.lock().unwrap().insert(
+            stream_id.to_string(),
+            ActiveSpeech {
+                npc_id: npc_id.to_string(),
+                cancelled: cancelled.clone(),
+                pacer: pacer.clone(),
+            },
+        );
+        SpeechHandle {
+            stream_id: stream_id.to_string(),
+            cancelled,
+            pacer,
+            registry: self.clone(),
+        }
+    }
+
+    /// Pace the stream `status` reports on by it; `local_ms` is when it was
+    /// sent, on our clock. Returns false if the stream is no longer sent.
+    pub fn report(&self, status: &AudioBufferStatus, local_ms: i64) -> bool {
+        let streams = self.streams.lock().unwrap();
+        let Some(speech) = streams.get(&status.stream_id) else {
+            return false;
+        };
+        speech.pacer.lock().unwrap().on_status(status, local_ms);
+        true
+    }
+
+    /// Cancel `stream_id` of `npc_id`, or every stream of the NPC if
+    /// `stream_id` is None (an empty `StopSpeaking.stream_id`).
+    /// Returns the ids of the streams that were cancelled.
+    pub fn cancel(&self, npc_id: &NpcId, stream_id: Option<&StreamId>) -> Vec<String> {
+        let streams = self.streams.lock().unwrap();
+        streams
+            .iter()
+            .filter(|(id, speech)| {
+                *npc_id == speech.npc_id.as_str()
+                    && match stream_id {
+                        Some(stream_id) => *stream_id == id.as_str(),
+                        None => true,
+                    }
+            })
+            .map(|(id, speech)| {
+                speech.cancelled.store(true, Ordering::SeqCst);
+                id.clone()
+            })
+            .collect()
+    }
+
+    /// Whether `npc_id` has a stream in flight
+    pub fn is_speaking(&self, npc_id: &str) -> bool {
+        self.streams.lock().unwrap().values().any(|s| s.npc_id == npc_id)
+    }
+}
+
+/// Registration of one stream in a [`SpeechRegistry`]
+#[derive(Debug)]
+pub struct SpeechHandle {
+    stream_id: String,
+    cancelled: Arc<AtomicBool>,
+    pacer: Arc<Mutex<AudioPacer>>,
+    registry: SpeechRegistry,
+}
+
+impl SpeechHandle {
+    /// The registered stream
+    pub fn stream_id(&self) -> &str {
+        &self.stream_id
+    }
+
+    /// Whether the stream was cancelled; stop sending chunks if so
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// How long to wait at `now_ms` before sending the next chunk
+    pub fn wait_ms(&self, now_ms: i64) -> i64 {
+        self.pacer.lock().unwrap().wait_ms(now_ms)
+    }
+
+    /// Record `chunk` as sent at `now_ms`
+    pub fn sent(&self, chunk: &AudioChunk, now_ms: i64) {
+        self.pacer.lock().unwrap().sent(pacing::chunk_duration_ms(chunk), now_ms);
+    }
+}
+
+impl Drop for SpeechHandle {
+    fn drop(&mut self) {
+        let mut streams = self.registry.streams.lock().unwrap();
+        // The id may have been reused by a newer registration
+        if streams
+            .get(&self.stream_id)
+            .is_some_and(|s| Arc::ptr_eq(&s.cancelled, &self.cancelled))
+        {
+            streams.remove(&self.stream_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::Emotion;
+
+    fn speak() -> SpeakDirective {
+        SpeakDirective {
+            npc_id: "npc".to_string(),
+            text: "hi".to_string(),
+            directive_id: "dir-1".to_string(),
+            stream_id: "stream-1".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_chunks_are_sized_sequenced_and_correlated() {
+        // 2.5 frames at 24kHz -> 3 frames at 48kHz
+        let synthesized = SynthesizedAudio {
+            samples: vec![0.25; FRAME_SAMPLES * 5 / 4],
+            sample_rate_hz: 24_000,
+            ..Default::default()
+        };
+        let chunks = chunk_speech(&speak(), &synthesized);
+
+        assert_eq!(chunks.len(), 3);
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.sequence, i as u64);
+            assert_eq!(chunk.pcm_data.len(), FRAME_SAMPLES * 2);
+            assert_eq!(chunk.is_final, i == 2);
+            assert_eq!(chunk.stream_id, "stream-1");
+            assert_eq!(chunk.directive_id, "dir-1");
+            assert_eq!(chunk.npc_id, "npc");
+        }
+        // Chunks are views into one buffer, not copies
+        let first = chunks[0].pcm_data.as_ptr();
+        assert_eq!(chunks[1].pcm_data.as_ptr(), first.wrapping_add(FRAME_SAMPLES * 2));
+    }
+
+    #[test]
+    fn test_empty_audio_still_terminates_stream() {
+        let synthesized = SynthesizedAudio {
+            samples: Vec::new(),
+            sample_rate_hz: audio::PROTOCOL_SAMPLE_RATE_HZ,
+            ..Default::default()
+        };
+        let chunks = chunk_speech(&speak(), &synthesized);
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].is_final);
+        assert_eq!(viseme_timeline(&speak(), &synthesized), None);
+    }
+
+    #[test]
+    fn test_viseme_timeline_is_correlated() {
+        let cue = VisemeCue {
+            viseme: "AA".to_string(),
+            offset_ms: 0,
+            duration_ms: 120,
+        };
+        let synthesized = SynthesizedAudio {
+            visemes: vec![cue.clone()],
+            ..Default::default()
+        };
+        let timeline = viseme_timeline(&speak(), &synthesized).unwrap();
+        assert_eq!(timeline.stream_id, "stream-1");
+        assert_eq!(timeline.directive_id, "dir-1");
+        assert_eq!(timeline.cues, vec![cue]);
+    }
+
+    #[test]
+    fn test_cancel_speech() {
+        let registry = SpeechRegistry::default();
+        let npc = NpcId::new("npc").unwrap();
+        let stream = |id: &str| StreamId::new(id).unwrap();
+        let a = registry.start(&npc, &stream("a"));
+        let b = registry.start(&npc, &stream("b"));
+        let other = registry.start(&NpcId::new("other").unwrap(), &stream("c"));
+
+        assert_eq!(registry.cancel(&npc, Some(&stream("a"))), vec!["a".to_string()]);
+        assert!(a.is_cancelled() && !b.is_cancelled());
+
+        let mut cancelled = registry.cancel(&npc, None);
+        cancelled.sort();
+        assert_eq!(cancelled, vec!["a".to_string(), "b".to_string()]);
+        assert!(b.is_cancelled() && !other.is_cancelled());
+
+        drop((a, b));
+        assert!(!registry.is_speaking("npc"));
+        assert!(registry.is_speaking("other"));
+    }
+
+    #[test]
+    fn test_buffer_status_paces_stream() {
+        let registry = SpeechRegistry::default();
+        let speech = registry.start(&NpcId::new("npc").unwrap(), &StreamId::new("s").unwrap());
+        let chunk = AudioChunk {
+            pcm_data: vec![0; FRAME_SAMPLES * 2].into(),
+            ..Default::default()
+        };
+        for _ in 0..20 {
+            speech.sent(&chunk, 0);
+        }
+        assert_eq!(speech.wait_ms(0), 100);
+
+        let status = AudioBufferStatus {
+            stream_id: "s".to_string(),
+            played_ms: 200,
+            ..Default::default()
+        };
+        assert!(registry.report(&status, 0));
+        assert_eq!(speech.wait_ms(0), 0);
+        drop(speech);
+        assert!(!registry.report(&status, 0));
+    }
+
+    #[tokio::test]
+    async fn test_silence_tts_length() {
+        let synthesized = SilenceTts.synthesize("one two", "").await.unwrap();
+        assert_eq!(synthesized.duration_ms(), 600);
+
+        let mut speak = SpeakDirective { text: "one two".to_string(), ..Default::default() };
+        speak.set_tone(Emotion::Excited);
+        let excited = synthesize_speech(&SilenceTts, &speak).await.unwrap();
+        assert!(excited.duration_ms() < 600);
+    }
+}
@@ -0,0 +1,249 @@
+//! Typed identifiers.
+//!
+//! The generated messages use plain `String`s for every id, so nothing
+//! stops an NPC id from being passed where a player UUID is expected.
+//! [`NpcId`], [`PlayerUuid`], [`DirectiveId`] and [`StreamId`] are checked
+//! once when they are created (from a string with `new`/`TryFrom`, or from
+//! a message with `TryFrom<&Message>`) and turn back into the `String` the
+//! messages need with `From`.
+
+use std::borrow::Borrow;
+use std::fmt;
+
+use crate::npc_society::v1::{
+    ActionDirective, ActionResult, AudioBufferStatus, AudioChunk, BlockWatchUpdate,
+    ChangeDimensionObservation, ChatObservation, ChoreographyDirective, ChoreographyResult,
+    ChunkLoadObservation, ClaimContainerDirective, CombatPolicyObservation,
+    ContainerAccessObservation, DebugPathDirective, DialogueChoiceObservation,
+    DialogueOptionsDirective, DirectiveAck, DirectiveRejected, EventObservation, FinishNpcTransfer,
+    FormationDirective, FreezeNpcDirective, GiveItemAction, GiveItemResult, GuardZoneDirective,
+    IntruderObservation, KeepChunkLoadedDirective, MarkMapAction, MarkMapResult, ModerationFlag,
+    NpcSnapshot, NpcStateSnapshot, NpcTransferUpdate, PlayMusicDirective, PlayParticleDirective,
+    PlaySoundDirective, PlayerSnapshot, PrepareNpcTransfer, QuarantineNpcDirective, QuestOffer,
+    QuestUpdate, RegisterLandmark, RemoveDisplayDirective, RequestVoiceCapture, RestoreNpcState,
+    ResumeNpcDirective, SetCombatPolicyDirective, SetTimeDirective, SetWeatherDirective,
+    ShopDefinition, ShopTradeObservation, ShowDisplayDirective, SpeakDirective, SpeakResult,
+    SpeechInterrupted, StationOutputObservation, StopSpeaking, StopVoiceCapture,
+    TransactionObservation, TransferCurrencyDirective, TransportItemsDirective,
+    TransportItemsResult, TransportProgress, VisemeTimeline, VoiceConsentObservation, VoicePcmFrame,
+    WriteBookAction, WriteBookResult,
+};
+
+/// Longest id accepted by [`NpcId`], [`DirectiveId`] and [`StreamId`]
+pub const MAX_ID_LEN: usize = 128;
+
+/// A string that is not a valid id of the expected kind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdError(pub String);
+
+impl fmt::Display for IdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid id: {}", self.0)
+    }
+}
+
+impl std::error::Error for IdError {}
+
+/// Non-empty, at most [`MAX_ID_LEN`] bytes, no whitespace or control characters
+fn check_plain(kind: &str, value: &str) -> Result<(), IdError> {
+    if value.is_empty() {
+        return Err(IdError(format!("empty {}", kind)));
+    }
+    if value.len() > MAX_ID_LEN {
+        return Err(IdError(format!("{} longer than {} bytes", kind, MAX_ID_LEN)));
+    }
+    if value.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(IdError(format!("{} '{}' contains whitespace", kind, value.escape_debug())));
+    }
+    Ok(())
+}
+
+/// Hyphenated UUID, e.g. `069a79f4-44e9-4726-a5be-fca90e38aaf5`
+fn check_uuid(kind: &str, value: &str) -> Result<(), IdError> {
+    let valid = value.len() == 36
+        && value.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(IdError(format!("{} '{}' is not a UUID", kind, value.escape_debug())))
+    }
+}
+
+macro_rules! id_type {
+    ($(#[$doc:meta])* $name:ident, $kind:literal, $check:ident) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(String);
+
+        impl $name {
+            /// Validate `value`
+            pub fn new(value: impl Into<String>) -> Result<Self, IdError> {
+                let value = value.into();
+                $check($kind, &value)?;
+                Ok(Self(value))
+            }
+
+            /// The id as a string slice
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl Borrow<str> for $name {
+            fn borrow(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl PartialEq<str> for $name {
+            fn eq(&self, other: &str) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $name {
+            fn eq(&self, other: &&str) -> bool {
+                self.0 == *other
+            }
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = IdError;
+
+            fn try_from(value: String) -> Result<Self, IdError> {
+                Self::new(value)
+            }
+        }
+
+        impl TryFrom<&str> for $name {
+            type Error = IdError;
+
+            fn try_from(value: &str) -> Result<Self, IdError> {
+                Self::new(value)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(id: $name) -> String {
+                id.0
+            }
+        }
+    };
+}
+
+/// Read an id field out of messages: `TryFrom<&Message> for Id`
+macro_rules! id_from_messages {
+    ($name:ident, $field:ident: $($message:ty),+ $(,)?) => {
+        $(
+            impl TryFrom<&$message> for $name {
+                type Error = IdError;
+
+                fn try_from(message: &$message) -> Result<Self, IdError> {
+                    Self::new(message.$field.as_str())
+                }
+            }
+        )+
+    };
+}
+
+id_type!(
+    /// Config-defined NPC identifier (`npc_id`)
+    NpcId,
+    "npc_id",
+    check_plain
+);
+id_type!(
+    /// Minecraft player UUID (`player_uuid`)
+    PlayerUuid,
+    "player_uuid",
+    check_uuid
+);
+id_type!(
+    /// Directive correlation id (`directive_id`)
+    DirectiveId,
+    "directive_id",
+    check_plain
+);
+id_type!(
+    /// Audio stream id shared by a SpeakDirective and its AudioChunks (`stream_id`)
+    StreamId,
+    "stream_id",
+    check_plain
+);
+
+id_from_messages!(NpcId, npc_id:
+    NpcSnapshot, ChatObservation, EventObservation, VoicePcmFrame, ActionResult, SpeakResult,
+    SpeechInterrupted, QuestUpdate, TransactionObservation, ChangeDimensionObservation,
+    BlockWatchUpdate, StationOutputObservation, CombatPolicyObservation, ActionDirective,
+    SpeakDirective, AudioChunk, VisemeTimeline, StopSpeaking, QuestOffer, TransferCurrencyDirective,
+    SetCombatPolicyDirective, DirectiveRejected, DirectiveAck, RestoreNpcState, NpcStateSnapshot,
+    PrepareNpcTransfer, FinishNpcTransfer, NpcTransferUpdate, FreezeNpcDirective,
+    ResumeNpcDirective, DialogueOptionsDirective, DialogueChoiceObservation, ShopDefinition,
+    ShopTradeObservation, ShowDisplayDirective, RemoveDisplayDirective,
+    PlayParticleDirective, PlaySoundDirective, SetTimeDirective, SetWeatherDirective,
+    AudioBufferStatus, PlayMusicDirective, GuardZoneDirective, IntruderObservation,
+    ClaimContainerDirective, ContainerAccessObservation, RequestVoiceCapture, StopVoiceCapture,
+    ModerationFlag, QuarantineNpcDirective, DebugPathDirective, ChunkLoadObservation,
+    KeepChunkLoadedDirective, TransportItemsDirective, TransportProgress, TransportItemsResult,
+);
+id_from_messages!(PlayerUuid, player_uuid:
+    PlayerSnapshot, ChatObservation, VoicePcmFrame, SpeechInterrupted, QuestUpdate,
+    TransactionObservation, QuestOffer, TransferCurrencyDirective, DialogueOptionsDirective,
+    DialogueChoiceObservation, ShopTradeObservation, IntruderObservation, GiveItemAction,
+    GiveItemResult, ContainerAccessObservation, VoiceConsentObservation, WriteBookAction,
+    WriteBookResult, MarkMapAction, MarkMapResult,
+);
+id_from_messages!(DirectiveId, directive_id:
+    ActionDirective, ActionResult, SpeakDirective, SpeakResult, AudioChunk, VisemeTimeline,
+    TransactionObservation, TransferCurrencyDirective, BlockWatchUpdate, StationOutputObservation,
+    DirectiveRejected, DirectiveAck, ChoreographyDirective, ChoreographyResult, SetTimeDirective,
+    SetWeatherDirective, PlayMusicDirective, FormationDirective, GuardZoneDirective,
+    ClaimContainerDirective, RegisterLandmark, KeepChunkLoadedDirective, TransportItemsDirective,
+    TransportProgress, TransportItemsResult,
+);
+id_from_messages!(StreamId, stream_id:
+    SpeakDirective, AudioChunk, VisemeTimeline, StopSpeaking, SpeechInterrupted, AudioBufferStatus,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation_and_conversions() {
+        assert!(NpcId::new("miner_01").is_ok());
+        assert!(NpcId::new("").is_err());
+        assert!(NpcId::new("two words").is_err());
+        assert!(NpcId::new("x".repeat(MAX_ID_LEN + 1)).is_err());
+
+        assert!(PlayerUuid::new("069a79f4-44e9-4726-a5be-fca90e38aaf5").is_ok());
+        assert!(PlayerUuid::new("Steve").is_err());
+        assert!(PlayerUuid::new("069a79f444e94726a5befca90e38aaf5").is_err());
+
+        let chat = ChatObservation {
+            npc_id: "baker".to_string(),
+            player_uuid: "069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string(),
+            ..Default::default()
+        };
+        let npc = NpcId::try_from(&chat).unwrap();
+        assert_eq!(npc, "baker");
+        assert_eq!(PlayerUuid::try_from(&chat).unwrap().as_str(), chat.player_uuid);
+        assert_eq!(String::from(npc), "baker");
+        assert!(StreamId::try_from(&StopSpeaking::default()).is_err());
+    }
+}
@@ -0,0 +1,434 @@
+//! Utility AI: pick a task by scoring them all.
+//!
+//! An alternative to [`behavior`](crate::behavior) trees for deciding
+//! *what* an NPC does. A [`Reasoner`] holds [`Choice`]s ("eat", "mine",
+//! "flee"); each choice multiplies the scores of its [`Consideration`]s,
+//! which read one input from the NPC's snapshot and the [`WorldModel`],
+//! normalised to 0..1, and shape it with a [`Curve`]. Every decision cycle
+//! the highest scoring choice wins, and the task it names is then carried
+//! out by whatever the daemon uses for that: a behavior tree, a plan or a
+//! single directive.
+//!
+//! Tuning is in the curves and weights, so a designer can make an NPC
+//! greedier or more cowardly without touching the decision logic.
+
+use std::fmt;
+
+use crate::npc_society::v1::NpcSnapshot;
+use crate::world_model::WorldModel;
+
+/// What considerations may look at
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'a> {
+    /// Latest snapshot of the NPC
+    pub npc: &'a NpcSnapshot,
+    /// What the daemon knows about the world
+    pub world: &'a WorldModel,
+}
+
+/// Response curve from a 0..1 input to a 0..1 score
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Curve {
+    /// `slope * x + intercept`
+    Linear { slope: f64, intercept: f64 },
+    /// `x ^ exponent`: above 1 stays low until x is high, below 1 rises fast
+    Power { exponent: f64 },
+    /// S-curve around `midpoint`; higher `steepness` is closer to a step
+    Logistic { midpoint: f64, steepness: f64 },
+    /// 1 at or above `threshold`, else 0
+    Step { threshold: f64 },
+    /// Another curve mirrored: `1 - curve(x)`
+    Inverse(&'static Curve),
+}
+
+impl Curve {
+    /// The identity curve
+    pub const LINEAR: Curve = Curve::Linear {
+        slope: 1.0,
+        intercept: 0.0,
+    };
+
+    /// Score for `x`; both are clamped to 0..1
+    pub fn eval(&self, x: f64) -> f64 {
+        let x = clamp(x);
+        let y = match *self {
+            Curve::Linear { slope, intercept } => slope * x + intercept,
+            Curve::Power { exponent } => x.powf(exponent),
+            Curve::Logistic {
+                midpoint,
+                steepness,
+            } => 1.0 / (1.0 + (-steepness * (x - midpoint)).exp()),
+            Curve::Step { threshold } => f64::from(u8::from(x >= threshold)),
+            Curve::Inverse(curve) => 1.0 - curve.eval(x),
+        };
+        clamp(y)
+    }
+}
+
+/// `value` mapped from `min..max` to 0..1, clamped
+pub fn normalize(value: f64, min: f64, max: f64) -> f64 {
+    if max <= min {
+        return f64::from(u8::from(value >= max));
+    }
+    clamp((value - min) / (max - min))
+}
+
+fn clamp(x: f64) -> f64 {
+    if x.is_nan() {
+        0.0
+    } else {
+        x.clamp(0.0, 1.0)
+    }
+}
+
+type Input = Box<dyn Fn(&Context<'_>) -> f64 + Send + Sync>;
+
+/// One input to a choice, shaped by a curve
+pub struct Consideration {
+    /// Name for [`Reasoner::explain`]
+    pub name: String,
+    /// Shape of the score
+    pub curve: Curve,
+    input: Input,
+}
+
+impl Consideration {
+    /// A consideration scoring `curve(input(cx))`; `input` should return
+    /// 0..1 (see [`normalize`])
+    pub fn new(
+        name: &str,
+        curve: Curve,
+        input: impl Fn(&Context<'_>) -> f64 + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            curve,
+            input: Box::new(input),
+        }
+    }
+
+    /// How hurt the NPC is (1 - health_norm)
+    pub fn injury(curve: Curve) -> Self {
+        Self::new("injury", curve, |cx| 1.0 - f64::from(cx.npc.health_norm))
+    }
+
+    /// How hungry the NPC is (1 - hunger_norm)
+    pub fn hunger(curve: Curve) -> Self {
+        Self::new("hunger", curve, |cx| 1.0 - f64::from(cx.npc.hunger_norm))
+    }
+
+    /// 1 while the NPC is in combat
+    pub fn in_combat() -> Self {
+        Self::new("in_combat", Curve::LINEAR, |cx| {
+            f64::from(u8::from(cx.npc.in_combat))
+        })
+    }
+
+    /// Closeness of the nearest known `block_type`: 1 next to the NPC, 0 at
+    /// `range` blocks or when none is known
+    pub fn near_block(block_type: &str, range: f64, curve: Curve) -> Self {
+        let block_type = block_type.to_string();
+        Self::new(&format!("near {block_type}"), curve, move |cx| {
+            let Some(position) = &cx.npc.position else {
+                return 0.0;
+            };
+            cx.world
+                .nearest_block(position, &block_type)
+                .map_or(0.0, |(_, distance)| 1.0 - normalize(distance, 0.0, range))
+        })
+    }
+
+    /// Players reported within `radius` of the NPC, 1 at `crowd` or more
+    pub fn players_near(radius: f64, crowd: usize, curve: Curve) -> Self {
+        Self::new("players_near", curve, move |cx| {
+            let Some(position) = &cx.npc.position else {
+                return 0.0;
+            };
+            let count = cx.world.players_within(position, radius).len();
+            normalize(count as f64, 0.0, crowd as f64)
+        })
+    }
+
+    /// Score in `cx`
+    pub fn score(&self, cx: &Context<'_>) -> f64 {
+        self.curve.eval((self.input)(cx))
+    }
+}
+
+impl fmt::Debug for Consideration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Consideration")
+            .field("name", &self.name)
+            .field("curve", &self.curve)
+            .finish()
+    }
+}
+
+/// A task the NPC may pursue and what speaks for it
+#[derive(Debug)]
+pub struct Choice {
+    /// The task, e.g. "mine"
+    pub task: String,
+    /// Multiplies the score, to rank choices against each other
+    pub weight: f64,
+    /// Multiplied together; any 0 rules the choice out
+    pub considerations: Vec<Consideration>,
+}
+
+impl Choice {
+    /// A choice of weight 1 without considerations (it scores 1)
+    pub fn new(task: &str) -> Self {
+        Self {
+            task: task.to_string(),
+            weight: 1.0,
+            considerations: Vec::new(),
+        }
+    }
+
+    /// Set the weight
+    pub fn weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Add a consideration
+    pub fn consider(mut self, consideration: Consideration) -> Self {
+        self.considerations.push(consideration);
+        self
+    }
+
+    /// Weighted product of the considerations. Each score is compensated
+    /// for the number of considerations, so a choice isn't penalised just
+    /// for having more of them.
+    pub fn score(&self, cx: &Context<'_>) -> f64 {
+        let n = self.considerations.len();
+        if n == 0 {
+            return self.weight;
+        }
+        let compensation = 1.0 - 1.0 / n as f64;
+        let mut total = 1.0;
+        for consideration in &self.considerations {
+            let score = consideration.score(cx);
+            total *= score + (1.0 - score) * compensation * score;
+            if total == 0.0 {
+                break;
+            }
+        }
+        total * self.weight
+    }
+}
+
+/// The outcome of a decision cycle
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    /// Task of the winning choice
+    pub task: String,
+    /// Its score, including the inertia bonus if it was already running
+    pub score: f64,
+    /// Whether the task differs from the previous decision
+    pub changed: bool,
+}
+
+/// Picks one NPC's task every decision cycle.
+#[derive(Debug, Default)]
+pub struct Reasoner {
+    choices: Vec<Choice>,
+    inertia: f64,
+    current: Option<String>,
+}
+
+impl Reasoner {
+    /// A reasoner without choices
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a choice
+    pub fn choice(mut self, choice: Choice) -> Self {
+        self.choices.push(choice);
+        self
+    }
+
+    /// Bonus added to the current task's score, so close scores don't make
+    /// the NPC flip between tasks every cycle (default 0)
+    pub fn inertia(mut self, inertia: f64) -> Self {
+        self.inertia = inertia;
+        self
+    }
+
+    /// Task of the last decision
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// Every choice's score in `cx`, best first
+    pub fn scores(&self, cx: &Context<'_>) -> Vec<(&str, f64)> {
+        let mut scores: Vec<_> = self
+            .choices
+            .iter()
+            .map(|choice| {
+                let mut score = choice.score(cx);
+                if self.current.as_deref() == Some(choice.task.as_str()) && score > 0.0 {
+                    score += self.inertia;
+                }
+                (choice.task.as_str(), score)
+            })
+            .collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scores
+    }
+
+    /// Pick the best scoring task. None, and the current task is dropped,
+    /// if every choice scores 0. Ties go to the choice added first.
+    pub fn decide(&mut self, cx: &Context<'_>) -> Option<Decision> {
+        let (task, score) = self
+            .scores(cx)
+            .into_iter()
+            .find(|(_, score)| *score > 0.0)
+            .map(|(task, score)| (task.to_string(), score))
+            .unzip();
+        let changed = task != self.current;
+        self.current = task.clone();
+        Some(Decision {
+            task: task?,
+            score: score?,
+            changed,
+        })
+    }
+
+    /// One line per choice and consideration with its score in `cx`, for logs
+    pub fn explain(&self, cx: &Context<'_>) -> String {
+        let mut lines = Vec::new();
+        for choice in &self.choices {
+            lines.push(format!("{}: {:.3}", choice.task, choice.score(cx)));
+            for consideration in &choice.considerations {
+                lines.push(format!(
+                    "  {}: {:.3}",
+                    consideration.name,
+                    consideration.score(cx)
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npc(health_norm: f32, hunger_norm: f32, in_combat: bool) -> NpcSnapshot {
+        NpcSnapshot {
+            npc_id: "npc".to_string(),
+            health_norm,
+            hunger_norm,
+            in_combat,
+            ..NpcSnapshot::default()
+        }
+    }
+
+    fn reasoner() -> Reasoner {
+        Reasoner::new()
+            .choice(Choice::new("idle").weight(0.2))
+            .choice(
+                Choice::new("eat").consider(Consideration::hunger(Curve::Power { exponent: 2.0 })),
+            )
+            .choice(
+                Choice::new("flee")
+                    .weight(2.0)
+                    .consider(Consideration::in_combat())
+                    .consider(Consideration::injury(Curve::Logistic {
+                        midpoint: 0.6,
+                        steepness: 12.0,
+                    })),
+            )
+            .inertia(0.1)
+    }
+
+    #[test]
+    fn test_curves() {
+        assert_eq!(Curve::LINEAR.eval(1.5), 1.0);
+        assert_eq!(Curve::Power { exponent: 2.0 }.eval(0.5), 0.25);
+        assert_eq!(Curve::Step { threshold: 0.5 }.eval(0.4), 0.0);
+        assert_eq!(Curve::Inverse(&Curve::LINEAR).eval(0.25), 0.75);
+        let logistic = Curve::Logistic {
+            midpoint: 0.5,
+            steepness: 10.0,
+        };
+        assert!((logistic.eval(0.5) - 0.5).abs() < 1e-9);
+        assert!(logistic.eval(0.9) > 0.95);
+        assert_eq!(normalize(15.0, 10.0, 20.0), 0.5);
+    }
+
+    #[test]
+    fn test_decide() {
+        let world = WorldModel::default();
+        let mut reasoner = reasoner();
+
+        let fed = npc(1.0, 1.0, false);
+        let decision = reasoner
+            .decide(&Context {
+                npc: &fed,
+                world: &world,
+            })
+            .unwrap();
+        assert_eq!(decision.task, "idle");
+        assert!(decision.changed);
+
+        let hungry = npc(1.0, 0.3, false);
+        let decision = reasoner
+            .decide(&Context {
+                npc: &hungry,
+                world: &world,
+            })
+            .unwrap();
+        assert_eq!(decision.task, "eat");
+
+        // Fighting while healthy doesn't beat eating; badly hurt it does
+        let fighting = npc(0.9, 0.3, true);
+        assert_eq!(
+            reasoner
+                .decide(&Context {
+                    npc: &fighting,
+                    world: &world
+                })
+                .unwrap()
+                .task,
+            "eat"
+        );
+        let hurt = npc(0.2, 0.3, true);
+        let decision = reasoner
+            .decide(&Context {
+                npc: &hurt,
+                world: &world,
+            })
+            .unwrap();
+        assert_eq!(decision.task, "flee");
+        assert!(decision.changed);
+        assert_eq!(reasoner.current(), Some("flee"));
+    }
+
+    #[test]
+    fn test_inertia_keeps_current_task() {
+        let world = WorldModel::default();
+        let mut reasoner = Reasoner::new()
+            .choice(Choice::new("a").weight(0.5))
+            .choice(Choice::new("b").weight(0.55))
+            .inertia(0.1);
+        let npc = npc(1.0, 1.0, false);
+        let cx = Context {
+            npc: &npc,
+            world: &world,
+        };
+        assert_eq!(reasoner.decide(&cx).unwrap().task, "b");
+
+        reasoner.choices[0].weight = 0.6;
+        let decision = reasoner.decide(&cx).unwrap();
+        assert_eq!(decision.task, "b");
+        assert!(!decision.changed);
+
+        reasoner.choices[0].weight = 0.7;
+        assert_eq!(reasoner.decide(&cx).unwrap().task, "a");
+        assert!(Reasoner::new().decide(&cx).is_none());
+    }
+}
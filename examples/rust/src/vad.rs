@@ -0,0 +1,177 @@
+//! Energy-based voice activity detection and utterance segmentation.
+//!
+//! Feed one VAD per speaker with ordered frames (see [`crate::jitter`])
+//! converted to f32 (see [`crate::audio`]). It emits an event when an
+//! utterance starts and hands back the whole utterance when it ends, which
+//! is what ASR engines want instead of a stream of 20ms frames.
+
+/// Tuning for [`EnergyVad`]. Frame counts assume 20ms frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VadConfig {
+    /// RMS level (0.0-1.0) at or above which a frame counts as speech
+    pub threshold_rms: f32,
+    /// Consecutive speech frames needed to start an utterance
+    pub min_speech_frames: usize,
+    /// Consecutive silent frames that end an utterance
+    pub hangover_frames: usize,
+    /// Frames after which an utterance is ended even without silence
+    pub max_utterance_frames: usize,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            threshold_rms: 0.02,
+            min_speech_frames: 3,       // 60ms
+            hangover_frames: 25,        // 500ms
+            max_utterance_frames: 1500, // 30s
+        }
+    }
+}
+
+/// Utterance boundary reported by [`EnergyVad::process`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum VadEvent {
+    /// Speech started
+    UtteranceStart,
+    /// Speech ended; `audio` holds the whole utterance including trailing silence
+    UtteranceEnd { audio: Vec<f32> },
+}
+
+/// Segments a single speaker's audio into utterances by frame energy.
+#[derive(Debug, Clone, Default)]
+pub struct EnergyVad {
+    config: VadConfig,
+    speaking: bool,
+    speech_run: usize,
+    silence_run: usize,
+    frames: usize,
+    buffer: Vec<f32>,
+}
+
+impl EnergyVad {
+    /// Create a VAD with the given tuning
+    pub fn new(config: VadConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Whether an utterance is in progress
+    pub fn is_speaking(&self) -> bool {
+        self.speaking
+    }
+
+    /// Process one frame of mono f32 samples.
+    pub fn process(&mut self, samples: &[f32]) -> Option<VadEvent> {
+        let is_speech = rms(samples) >= self.config.threshold_rms;
+
+        if !self.speaking {
+            if !is_speech {
+                self.speech_run = 0;
+                self.buffer.clear();
+                return None;
+            }
+            // Keep the onset so the utterance isn't clipped
+            self.speech_run += 1;
+            self.buffer.extend_from_slice(samples);
+            if self.speech_run < self.config.min_speech_frames {
+                return None;
+            }
+            self.speaking = true;
+            self.silence_run = 0;
+            self.frames = self.speech_run;
+            return Some(VadEvent::UtteranceStart);
+        }
+
+        self.buffer.extend_from_slice(samples);
+        self.frames += 1;
+        self.silence_run = if is_speech { 0 } else { self.silence_run + 1 };
+
+        if self.silence_run >= self.config.hangover_frames
+            || self.frames >= self.config.max_utterance_frames
+        {
+            return self.finish();
+        }
+        None
+    }
+
+    /// End the current utterance early (e.g. the speaker disconnected).
+    pub fn finish(&mut self) -> Option<VadEvent> {
+        if !self.speaking {
+            return None;
+        }
+        self.speaking = false;
+        self.speech_run = 0;
+        self.silence_run = 0;
+        self.frames = 0;
+        Some(VadEvent::UtteranceEnd {
+            audio: std::mem::take(&mut self.buffer),
+        })
+    }
+}
+
+/// Root-mean-square level of a frame
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEECH: [f32; 320] = [0.5; 320];
+    const SILENCE: [f32; 320] = [0.0; 320];
+
+    fn config() -> VadConfig {
+        VadConfig {
+            min_speech_frames: 2,
+            hangover_frames: 3,
+            ..VadConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_segments_utterance() {
+        let mut vad = EnergyVad::new(config());
+
+        assert_eq!(vad.process(&SILENCE), None);
+        assert_eq!(vad.process(&SPEECH), None);
+        assert_eq!(vad.process(&SPEECH), Some(VadEvent::UtteranceStart));
+        assert_eq!(vad.process(&SPEECH), None);
+        assert_eq!(vad.process(&SILENCE), None);
+        assert_eq!(vad.process(&SILENCE), None);
+
+        match vad.process(&SILENCE) {
+            // 3 speech frames + 3 silent frames of 320 samples
+            Some(VadEvent::UtteranceEnd { audio }) => assert_eq!(audio.len(), 6 * 320),
+            other => panic!("expected UtteranceEnd, got {other:?}"),
+        }
+        assert!(!vad.is_speaking());
+    }
+
+    #[test]
+    fn test_short_noise_is_ignored() {
+        let mut vad = EnergyVad::new(config());
+        assert_eq!(vad.process(&SPEECH), None);
+        assert_eq!(vad.process(&SILENCE), None);
+        assert_eq!(vad.process(&SPEECH), None);
+        assert!(!vad.is_speaking());
+    }
+
+    #[test]
+    fn test_max_utterance_length() {
+        let mut vad = EnergyVad::new(VadConfig {
+            max_utterance_frames: 4,
+            ..config()
+        });
+        vad.process(&SPEECH);
+        assert_eq!(vad.process(&SPEECH), Some(VadEvent::UtteranceStart));
+        assert_eq!(vad.process(&SPEECH), None);
+        assert!(matches!(vad.process(&SPEECH), Some(VadEvent::UtteranceEnd { .. })));
+    }
+}
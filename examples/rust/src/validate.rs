@@ -0,0 +1,1635 @@
+//! Checks for messages crossing the wire.
+//!
+//! [`Validate::validate`] catches malformed messages (empty ids, negative
+//! radii, volumes outside 0.0-1.0, empty audio frames, ...) and says which
+//! field is wrong. Run it on everything sent and received: invalid
+//! directives are then dropped by the daemon with a precise
+//! [`ValidationError`] instead of failing obscurely in the plugin.
+//!
+//! Audio sequence numbers need state; a [`SequenceTracker`] per connection
+//! flags frames and chunks that jump backwards.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::block_pattern::BlockPattern;
+use crate::chunking::MAX_RESULT_PARTS;
+use crate::formation::MAX_FORMATION_MEMBERS;
+use crate::game_event;
+use crate::music::{MAX_PITCH, MAX_SONG_NOTES};
+use crate::npc_society::v1::{
+    action_directive::Action, check_line_of_sight_action, choreography_step::Step,
+    client_message::Message as ClientMsg, formation_directive::Leader, raycast_look_action,
+    server_message::Message as ServerMsg, set_mechanism_action, show_display_directive::Display,
+    ActionDirective, ActionResult, AudioBufferStatus, AudioChunk, AudioStreamHello,
+    BlockWatchUpdate, ChangeDimensionObservation, ChatDirective, ChatObservation,
+    ChoreographyDirective, ChunkLoadObservation, ClaimContainerDirective, ClientMessage,
+    CombatPolicyObservation, ComparatorMode, ContainerAccessObservation, DebugPathDirective,
+    DialogueChoiceObservation, DialogueOptionsDirective, DirectiveAck, DirectiveRejected, Emotion,
+    EquipmentSlot, EventObservation, EventType, FinishNpcTransfer, FormationDirective,
+    GuardZoneDirective, IntruderObservation, KeepChunkLoadedDirective, LandmarkList, ListLandmarks,
+    ModerationFlag, NpcMessage, NpcSnapshot, NpcTransferStage, NpcTransferUpdate,
+    PlayAudioAssetDirective, PlayMusicDirective, PlayParticleDirective, PlaySoundDirective,
+    PrepareNpcTransfer, Probe, ProbeReply, QuarantineNpcDirective, QuestOffer, QuestUpdate,
+    RegisterAudioAsset, RegisterLandmark, RequestVoiceCapture, RestoreNpcState, ServerMessage,
+    SetCombatPolicyDirective, SetTimeDirective, SetWeatherDirective, ShopDefinition,
+    ShopTradeObservation, ShopTradeSide, ShowDisplayDirective, SpawnTransferredNpc, SpeakDirective,
+    SpeakResult, SpeechDelivery, SpeechInterrupted, StationOutputObservation, StopSpeaking,
+    StopVoiceCapture, SubscribeEvents, TransactionObservation, TransferCurrencyDirective,
+    TransferDirection, TransportItemsDirective, TransportProgress, TransportStage, VisemeTimeline,
+    VoiceConsent, VoiceConsentObservation, VoicePcmFrame, Weather, WorldBootstrap, WorldTick,
+};
+
+/// Largest `RegisterAudioAsset.pcm_data`: about 10.9s of 48kHz S16LE
+pub const MAX_AUDIO_ASSET_BYTES: usize = 1 << 20;
+
+/// What is wrong with a message
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    /// A required field is empty or unset, e.g. "ActionDirective.npc_id"
+    Missing(&'static str),
+    /// A numeric field is outside its allowed range
+    OutOfRange {
+        /// Field, e.g. "SpeakDirective.volume"
+        field: &'static str,
+        /// The offending value
+        value: f64,
+        /// Allowed range, e.g. "0.0-1.0"
+        expected: &'static str,
+    },
+    /// The message's oneof is unset
+    EmptyMessage(&'static str),
+    /// A string field does not have the required format
+    Malformed {
+        /// Field, e.g. "ScanBlocksAction.block_types"
+        field: &'static str,
+        /// What is wrong with it
+        reason: String,
+    },
+    /// An audio frame or chunk is further behind its stream than allowed
+    SequenceRegression {
+        /// Stream key: "<npc_id>/<player_uuid>" or the stream_id
+        stream: String,
+        /// Sequence number received
+        sequence: u64,
+        /// Highest sequence number seen on the stream
+        highest: u64,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing(field) => write!(f, "{} is required", field),
+            Self::OutOfRange {
+                field,
+                value,
+                expected,
+            } => write!(f, "{} is {}, expected {}", field, value, expected),
+            Self::EmptyMessage(kind) => write!(f, "{} has no message set", kind),
+            Self::Malformed { field, reason } => write!(f, "{}: {}", field, reason),
+            Self::SequenceRegression {
+                stream,
+                sequence,
+                highest,
+            } => write!(f, "sequence {} on {} after {}", sequence, stream, highest),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Stateless checks of a message's fields
+pub trait Validate {
+    /// The first problem found, if any
+    fn validate(&self) -> Result<(), ValidationError>;
+}
+
+fn present(value: &str, field: &'static str) -> Result<(), ValidationError> {
+    if value.is_empty() {
+        Err(ValidationError::Missing(field))
+    } else {
+        Ok(())
+    }
+}
+
+fn set<T>(value: &Option<T>, field: &'static str) -> Result<(), ValidationError> {
+    match value {
+        Some(_) => Ok(()),
+        None => Err(ValidationError::Missing(field)),
+    }
+}
+
+fn within(
+    value: impl Into<f64>,
+    ok: bool,
+    field: &'static str,
+    expected: &'static str,
+) -> Result<(), ValidationError> {
+    if ok {
+        Ok(())
+    } else {
+        Err(ValidationError::OutOfRange {
+            field,
+            value: value.into(),
+            expected,
+        })
+    }
+}
+
+fn unit(value: f32, field: &'static str) -> Result<(), ValidationError> {
+    within(value, (0.0..=1.0).contains(&value), field, "0.0-1.0")
+}
+
+fn patterns(entries: &[String], field: &'static str) -> Result<(), ValidationError> {
+    for entry in entries {
+        BlockPattern::parse(entry).map_err(|e| ValidationError::Malformed {
+            field,
+            reason: e.to_string(),
+        })?;
+    }
+    Ok(())
+}
+
+impl Validate for ClientMessage {
+    fn validate(&self) -> Result<(), ValidationError> {
+        match &self.message {
+            Some(ClientMsg::Hello(_)) => Ok(()),
+            Some(ClientMsg::WorldTick(m)) => m.validate(),
+            Some(ClientMsg::ChatObservation(m)) => m.validate(),
+            Some(ClientMsg::EventObservation(m)) => m.validate(),
+            Some(ClientMsg::VoicePcmFrame(m)) => m.validate(),
+            Some(ClientMsg::ActionResult(m)) => m.validate(),
+            Some(ClientMsg::SpeechInterrupted(m)) => m.validate(),
+            Some(ClientMsg::SpeakResult(m)) => m.validate(),
+            Some(ClientMsg::NpcMessage(m)) => m.validate(),
+            Some(ClientMsg::QuestUpdate(m)) => m.validate(),
+            Some(ClientMsg::TransactionObservation(m)) => m.validate(),
+            Some(ClientMsg::ChangeDimension(m)) => m.validate(),
+            Some(ClientMsg::BlockWatchUpdate(m)) => m.validate(),
+            Some(ClientMsg::StationOutput(m)) => m.validate(),
+            Some(ClientMsg::CombatPolicy(m)) => m.validate(),
+            Some(ClientMsg::DirectiveRejected(m)) => m.validate(),
+            Some(ClientMsg::DirectiveAck(m)) => m.validate(),
+            Some(ClientMsg::NpcTransferUpdate(m)) => m.validate(),
+            Some(ClientMsg::ChoreographyResult(m)) => {
+                present(&m.directive_id, "ChoreographyResult.directive_id")
+            }
+            Some(ClientMsg::DialogueChoice(m)) => m.validate(),
+            Some(ClientMsg::ShopTrade(m)) => m.validate(),
+            Some(ClientMsg::AudioBuffer(m)) => m.validate(),
+            Some(ClientMsg::Intruder(m)) => m.validate(),
+            Some(ClientMsg::ContainerAccess(m)) => m.validate(),
+            Some(ClientMsg::LandmarkList(m)) => m.validate(),
+            Some(ClientMsg::VoiceConsent(m)) => m.validate(),
+            Some(ClientMsg::ModerationFlag(m)) => m.validate(),
+            Some(ClientMsg::AudioStreamHello(m)) => m.validate(),
+            Some(ClientMsg::WorldBootstrap(m)) => m.validate(),
+            Some(ClientMsg::ProbeReply(m)) => m.validate(),
+            Some(ClientMsg::ChunkLoad(m)) => m.validate(),
+            Some(ClientMsg::TransportProgress(m)) => m.validate(),
+            Some(ClientMsg::TransportItemsResult(m)) => {
+                present(&m.directive_id, "TransportItemsResult.directive_id")?;
+                present(&m.npc_id, "TransportItemsResult.npc_id")
+            }
+            None => Err(ValidationError::EmptyMessage("ClientMessage")),
+        }
+    }
+}
+
+impl Validate for ServerMessage {
+    fn validate(&self) -> Result<(), ValidationError> {
+        match &self.message {
+            Some(ServerMsg::ActionDirective(m)) => m.validate(),
+            Some(ServerMsg::SpeakDirective(m)) => m.validate(),
+            Some(ServerMsg::AudioChunk(m)) => m.validate(),
+            Some(ServerMsg::HelloAck(_)) => Ok(()),
+            Some(ServerMsg::StopSpeaking(m)) => m.validate(),
+            Some(ServerMsg::VisemeTimeline(m)) => m.validate(),
+            Some(ServerMsg::NpcMessage(m)) => m.validate(),
+            Some(ServerMsg::QuestOffer(m)) => m.validate(),
+            Some(ServerMsg::TransferCurrency(m)) => m.validate(),
+            Some(ServerMsg::SetCombatPolicy(m)) => m.validate(),
+            Some(ServerMsg::SubscribeEvents(m)) => m.validate(),
+            Some(ServerMsg::RestoreNpcState(m)) => m.validate(),
+            Some(ServerMsg::PrepareNpcTransfer(m)) => m.validate(),
+            Some(ServerMsg::SpawnTransferredNpc(m)) => m.validate(),
+            Some(ServerMsg::FinishNpcTransfer(m)) => m.validate(),
+            Some(ServerMsg::Chat(m)) => m.validate(),
+            Some(ServerMsg::FreezeNpc(m)) => present(&m.npc_id, "FreezeNpcDirective.npc_id"),
+            Some(ServerMsg::ResumeNpc(m)) => present(&m.npc_id, "ResumeNpcDirective.npc_id"),
+            Some(ServerMsg::Choreography(m)) => m.validate(),
+            Some(ServerMsg::DialogueOptions(m)) => m.validate(),
+            Some(ServerMsg::Shop(m)) => m.validate(),
+            Some(ServerMsg::ShowDisplay(m)) => m.validate(),
+            Some(ServerMsg::RemoveDisplay(m)) => {
+                present(&m.display_id, "RemoveDisplayDirective.display_id")
+            }
+            Some(ServerMsg::PlayParticle(m)) => m.validate(),
+            Some(ServerMsg::PlaySound(m)) => m.validate(),
+            Some(ServerMsg::SetTime(m)) => m.validate(),
+            Some(ServerMsg::SetWeather(m)) => m.validate(),
+            Some(ServerMsg::RegisterAudioAsset(m)) => m.validate(),
+            Some(ServerMsg::PlayAudioAsset(m)) => m.validate(),
+            Some(ServerMsg::PlayMusic(m)) => m.validate(),
+            Some(ServerMsg::Formation(m)) => m.validate(),
+            Some(ServerMsg::GuardZone(m)) => m.validate(),
+            Some(ServerMsg::ClaimContainer(m)) => m.validate(),
+            Some(ServerMsg::RegisterLandmark(m)) => m.validate(),
+            Some(ServerMsg::ListLandmarks(m)) => m.validate(),
+            Some(ServerMsg::RequestVoiceCapture(m)) => m.validate(),
+            Some(ServerMsg::StopVoiceCapture(m)) => m.validate(),
+            Some(ServerMsg::QuarantineNpc(m)) => m.validate(),
+            Some(ServerMsg::DebugPath(m)) => m.validate(),
+            Some(ServerMsg::Probe(m)) => m.validate(),
+            Some(ServerMsg::KeepChunkLoaded(m)) => m.validate(),
+            Some(ServerMsg::TransportItems(m)) => m.validate(),
+            None => Err(ValidationError::EmptyMessage("ServerMessage")),
+        }
+    }
+}
+
+impl Validate for WorldTick {
+    fn validate(&self) -> Result<(), ValidationError> {
+        self.npcs.iter().try_for_each(Validate::validate)
+    }
+}
+
+impl Validate for NpcSnapshot {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "NpcSnapshot.npc_id")?;
+        unit(self.health_norm, "NpcSnapshot.health_norm")?;
+        unit(self.hunger_norm, "NpcSnapshot.hunger_norm")
+    }
+}
+
+impl Validate for ChatObservation {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "ChatObservation.npc_id")?;
+        present(&self.player_uuid, "ChatObservation.player_uuid")
+    }
+}
+
+impl Validate for EventObservation {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "EventObservation.npc_id")?;
+        match &self.payload {
+            Some(payload) if game_event::payload_type(payload) != self.event_type() => {
+                Err(ValidationError::Malformed {
+                    field: "EventObservation.event_type",
+                    reason: format!(
+                        "{} does not match a {} payload",
+                        self.event_type().as_str_name(),
+                        game_event::payload_type(payload).as_str_name()
+                    ),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Validate for VoicePcmFrame {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "VoicePcmFrame.npc_id")?;
+        present(&self.player_uuid, "VoicePcmFrame.player_uuid")?;
+        if self.pcm_data.is_empty() {
+            return Err(ValidationError::Missing("VoicePcmFrame.pcm_data"));
+        }
+        within(
+            self.sample_rate_hz,
+            self.sample_rate_hz >= 0,
+            "VoicePcmFrame.sample_rate_hz",
+            ">= 0",
+        )
+    }
+}
+
+impl Validate for VoiceConsentObservation {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.player_uuid, "VoiceConsentObservation.player_uuid")?;
+        if self.consent() == VoiceConsent::Unspecified {
+            return Err(ValidationError::Missing("VoiceConsentObservation.consent"));
+        }
+        Ok(())
+    }
+}
+
+impl Validate for AudioStreamHello {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.token, "AudioStreamHello.token")
+    }
+}
+
+impl Validate for WorldBootstrap {
+    fn validate(&self) -> Result<(), ValidationError> {
+        self.npcs.iter().try_for_each(Validate::validate)?;
+        for landmark in &self.landmarks {
+            present(&landmark.name, "WorldBootstrap.landmarks.name")?;
+            set(&landmark.position, "WorldBootstrap.landmarks.position")?;
+        }
+        for region in &self.regions {
+            present(&region.region_id, "WorldBootstrap.regions.region_id")?;
+            set(&region.min, "WorldBootstrap.regions.min")?;
+            set(&region.max, "WorldBootstrap.regions.max")?;
+        }
+        self.containers.iter().try_for_each(Validate::validate)?;
+        for environment in &self.environments {
+            present(&environment.world, "WorldBootstrap.environments.world")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for Probe {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.nonce, "Probe.nonce")
+    }
+}
+
+impl Validate for ProbeReply {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.nonce, "ProbeReply.nonce")
+    }
+}
+
+impl Validate for ChunkLoadObservation {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "ChunkLoadObservation.npc_id")?;
+        present(&self.world, "ChunkLoadObservation.world")?;
+        for directive_id in &self.parked_directive_ids {
+            present(directive_id, "ChunkLoadObservation.parked_directive_ids")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for KeepChunkLoadedDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.directive_id, "KeepChunkLoadedDirective.directive_id")?;
+        present(&self.npc_id, "KeepChunkLoadedDirective.npc_id")?;
+        within(
+            self.radius_chunks,
+            self.radius_chunks >= 0,
+            "KeepChunkLoadedDirective.radius_chunks",
+            ">= 0",
+        )?;
+        within(
+            self.duration_ms as f64,
+            self.duration_ms >= 0,
+            "KeepChunkLoadedDirective.duration_ms",
+            ">= 0",
+        )
+    }
+}
+
+impl Validate for TransportItemsDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.directive_id, "TransportItemsDirective.directive_id")?;
+        present(&self.npc_id, "TransportItemsDirective.npc_id")?;
+        set(&self.from_container, "TransportItemsDirective.from_container")?;
+        set(&self.to_container, "TransportItemsDirective.to_container")?;
+        if self.from_container == self.to_container {
+            return Err(ValidationError::Malformed {
+                field: "TransportItemsDirective.to_container",
+                reason: "same as from_container".to_string(),
+            });
+        }
+        within(
+            self.max_items,
+            self.max_items >= 0,
+            "TransportItemsDirective.max_items",
+            ">= 0",
+        )?;
+        within(
+            self.max_trips,
+            self.max_trips >= 0,
+            "TransportItemsDirective.max_trips",
+            ">= 0",
+        )
+    }
+}
+
+impl Validate for TransportProgress {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.directive_id, "TransportProgress.directive_id")?;
+        present(&self.npc_id, "TransportProgress.npc_id")?;
+        if self.stage() == TransportStage::Unspecified {
+            return Err(ValidationError::Missing("TransportProgress.stage"));
+        }
+        within(self.trip, self.trip >= 1, "TransportProgress.trip", ">= 1")
+    }
+}
+
+impl Validate for RequestVoiceCapture {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "RequestVoiceCapture.npc_id")?;
+        for player in &self.player_uuids {
+            present(player, "RequestVoiceCapture.player_uuids")?;
+        }
+        within(
+            self.max_duration_ms,
+            self.max_duration_ms >= 0,
+            "RequestVoiceCapture.max_duration_ms",
+            ">= 0",
+        )
+    }
+}
+
+impl Validate for StopVoiceCapture {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "StopVoiceCapture.npc_id")?;
+        for player in &self.player_uuids {
+            present(player, "StopVoiceCapture.player_uuids")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for ModerationFlag {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.flag_id, "ModerationFlag.flag_id")?;
+        present(&self.npc_id, "ModerationFlag.npc_id")?;
+        present(&self.reporter_uuid, "ModerationFlag.reporter_uuid")
+    }
+}
+
+impl Validate for QuarantineNpcDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "QuarantineNpcDirective.npc_id")?;
+        for flag in &self.flag_ids {
+            present(flag, "QuarantineNpcDirective.flag_ids")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for DebugPathDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "DebugPathDirective.npc_id")?;
+        present(&self.viewer_uuid, "DebugPathDirective.viewer_uuid")
+    }
+}
+
+impl Validate for ActionResult {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.directive_id, "ActionResult.directive_id")?;
+        present(&self.npc_id, "ActionResult.npc_id")?;
+        if let Some(part) = &self.part {
+            within(
+                part.count,
+                (1..=MAX_RESULT_PARTS).contains(&part.count),
+                "ResultPart.count",
+                "1..=1024",
+            )?;
+            within(
+                part.index,
+                (0..part.count).contains(&part.index),
+                "ResultPart.index",
+                "0..count",
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for SpeakResult {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "SpeakResult.npc_id")?;
+        within(
+            self.played_ms as f64,
+            self.played_ms >= 0,
+            "SpeakResult.played_ms",
+            ">= 0",
+        )
+    }
+}
+
+impl Validate for SpeechInterrupted {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "SpeechInterrupted.npc_id")
+    }
+}
+
+impl Validate for AudioBufferStatus {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "AudioBufferStatus.npc_id")?;
+        present(&self.stream_id, "AudioBufferStatus.stream_id")?;
+        within(
+            self.buffered_ms as f64,
+            self.buffered_ms >= 0,
+            "AudioBufferStatus.buffered_ms",
+            ">= 0",
+        )?;
+        within(
+            self.played_ms as f64,
+            self.played_ms >= 0,
+            "AudioBufferStatus.played_ms",
+            ">= 0",
+        )?;
+        within(self.underruns, self.underruns >= 0, "AudioBufferStatus.underruns", ">= 0")
+    }
+}
+
+impl Validate for NpcMessage {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.sender_npc_id, "NpcMessage.sender_npc_id")?;
+        present(&self.topic, "NpcMessage.topic")
+    }
+}
+
+impl Validate for QuestUpdate {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.quest_id, "QuestUpdate.quest_id")?;
+        present(&self.npc_id, "QuestUpdate.npc_id")?;
+        present(&self.player_uuid, "QuestUpdate.player_uuid")
+    }
+}
+
+impl Validate for ShopTradeObservation {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.shop_id, "ShopTradeObservation.shop_id")?;
+        present(&self.npc_id, "ShopTradeObservation.npc_id")?;
+        present(&self.player_uuid, "ShopTradeObservation.player_uuid")?;
+        present(&self.listing_id, "ShopTradeObservation.listing_id")?;
+        if self.side() == ShopTradeSide::Unspecified {
+            return Err(ValidationError::Missing("ShopTradeObservation.side"));
+        }
+        within(self.count, self.count >= 0, "ShopTradeObservation.count", ">= 0")
+    }
+}
+
+impl Validate for TransactionObservation {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "TransactionObservation.npc_id")?;
+        present(&self.player_uuid, "TransactionObservation.player_uuid")?;
+        within(
+            self.amount as f64,
+            self.amount >= 0,
+            "TransactionObservation.amount",
+            ">= 0",
+        )
+    }
+}
+
+impl Validate for ChangeDimensionObservation {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "ChangeDimensionObservation.npc_id")?;
+        set(&self.to, "ChangeDimensionObservation.to")
+    }
+}
+
+impl Validate for BlockWatchUpdate {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.directive_id, "BlockWatchUpdate.directive_id")?;
+        present(&self.npc_id, "BlockWatchUpdate.npc_id")?;
+        for found in &self.found {
+            set(&found.position, "BlockWatchUpdate.found.position")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for StationOutputObservation {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "StationOutputObservation.npc_id")?;
+        present(&self.directive_id, "StationOutputObservation.directive_id")?;
+        set(&self.station_position, "StationOutputObservation.station_position")
+    }
+}
+
+impl Validate for ActionDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.directive_id, "ActionDirective.directive_id")?;
+        present(&self.npc_id, "ActionDirective.npc_id")?;
+        match &self.action {
+            Some(Action::Move(m)) => {
+                set(&m.target, "MoveAction.target")?;
+                unit(m.speed, "MoveAction.speed")
+            }
+            Some(Action::BreakBlock(m)) => set(&m.position, "BreakBlockAction.position"),
+            Some(Action::PlaceBlock(m)) => {
+                set(&m.position, "PlaceBlockAction.position")?;
+                present(&m.block_type, "PlaceBlockAction.block_type")
+            }
+            Some(Action::Attack(m)) => present(&m.target_uuid, "AttackAction.target_uuid"),
+            Some(Action::Interact(m)) => set(&m.target, "InteractAction.target"),
+            Some(Action::Inventory(m)) => {
+                within(
+                    m.quantity,
+                    m.quantity >= 0,
+                    "InventoryAction.quantity",
+                    ">= 0",
+                )?;
+                within(m.slot, m.slot >= 0, "InventoryAction.slot", ">= 0")
+            }
+            Some(Action::Look(m)) => set(&m.target, "LookAction.target"),
+            Some(Action::Stop(_)) => Ok(()),
+            Some(Action::ScanBlocks(m)) => {
+                set(&m.center, "ScanBlocksAction.center")?;
+                within(m.radius, m.radius > 0, "ScanBlocksAction.radius", "> 0")?;
+                patterns(&m.block_types, "ScanBlocksAction.block_types")?;
+                within(
+                    m.max_results,
+                    m.max_results >= 0,
+                    "ScanBlocksAction.max_results",
+                    ">= 0",
+                )
+            }
+            Some(Action::RaycastLook(m)) => {
+                if let Some(raycast_look_action::Aim::TargetEntityUuid(uuid)) = &m.aim {
+                    present(uuid, "RaycastLookAction.target_entity_uuid")?;
+                }
+                within(
+                    m.max_distance,
+                    m.max_distance > 0.0,
+                    "RaycastLookAction.max_distance",
+                    "> 0",
+                )
+            }
+            Some(Action::DepositToChest(m)) => {
+                set(&m.chest_position, "DepositToChestAction.chest_position")?;
+                within(
+                    m.max_items,
+                    m.max_items >= 0,
+                    "DepositToChestAction.max_items",
+                    ">= 0",
+                )
+            }
+            Some(Action::RegionSnapshot(m)) => {
+                set(&m.from, "RegionSnapshotAction.from")?;
+                set(&m.to, "RegionSnapshotAction.to")
+            }
+            Some(Action::WatchBlocks(m)) => {
+                set(&m.from, "WatchBlocksAction.from")?;
+                set(&m.to, "WatchBlocksAction.to")?;
+                if m.block_types.is_empty() {
+                    return Err(ValidationError::Missing("WatchBlocksAction.block_types"));
+                }
+                patterns(&m.block_types, "WatchBlocksAction.block_types")?;
+                within(
+                    m.interval_ticks,
+                    m.interval_ticks >= 0,
+                    "WatchBlocksAction.interval_ticks",
+                    ">= 0",
+                )?;
+                within(
+                    m.max_matches,
+                    m.max_matches >= 0,
+                    "WatchBlocksAction.max_matches",
+                    ">= 0",
+                )
+            }
+            Some(Action::UnwatchBlocks(m)) => present(&m.watch_id, "UnwatchBlocksAction.watch_id"),
+            Some(Action::RideAndDrive(m)) => {
+                set(&m.target, "RideAndDriveAction.target")?;
+                unit(m.speed, "RideAndDriveAction.speed")
+            }
+            Some(Action::BreedAnimals(m)) => {
+                present(&m.first_uuid, "BreedAnimalsAction.first_uuid")?;
+                present(&m.second_uuid, "BreedAnimalsAction.second_uuid")
+            }
+            Some(Action::TameAnimal(m)) => {
+                present(&m.target_uuid, "TameAnimalAction.target_uuid")?;
+                within(
+                    m.max_attempts,
+                    m.max_attempts >= 0,
+                    "TameAnimalAction.max_attempts",
+                    ">= 0",
+                )
+            }
+            Some(Action::Shear(m)) => present(&m.target_uuid, "ShearAction.target_uuid"),
+            Some(Action::Milk(m)) => present(&m.target_uuid, "MilkAction.target_uuid"),
+            Some(Action::EnchantItem(m)) => {
+                set(&m.table_position, "EnchantItemAction.table_position")?;
+                present(&m.item_type, "EnchantItemAction.item_type")?;
+                within(m.option, (0..=3).contains(&m.option), "EnchantItemAction.option", "0-3")
+            }
+            Some(Action::RepairItem(m)) => {
+                set(&m.anvil_position, "RepairItemAction.anvil_position")?;
+                present(&m.item_type, "RepairItemAction.item_type")?;
+                present(&m.material_item, "RepairItemAction.material_item")
+            }
+            Some(Action::ConsumeItem(m)) => {
+                if !m.potion_type.is_empty() {
+                    present(&m.item_type, "ConsumeItemAction.item_type")?;
+                }
+                Ok(())
+            }
+            Some(Action::Craft(m)) => {
+                present(&m.item_type, "CraftAction.item_type")?;
+                within(m.count, m.count > 0, "CraftAction.count", "> 0")
+            }
+            Some(Action::CheckLineOfSight(m)) => {
+                match &m.target {
+                    Some(check_line_of_sight_action::Target::Position(_)) => {}
+                    Some(check_line_of_sight_action::Target::EntityUuid(uuid)) => {
+                        present(uuid, "CheckLineOfSightAction.entity_uuid")?
+                    }
+                    None => return Err(ValidationError::Missing("CheckLineOfSightAction.target")),
+                }
+                within(
+                    m.max_distance,
+                    m.max_distance >= 0.0,
+                    "CheckLineOfSightAction.max_distance",
+                    ">= 0",
+                )
+            }
+            Some(Action::GiveItem(m)) => {
+                present(&m.player_uuid, "GiveItemAction.player_uuid")?;
+                present(&m.item_type, "GiveItemAction.item_type")?;
+                within(m.count, m.count > 0, "GiveItemAction.count", "> 0")
+            }
+            Some(Action::WriteSign(m)) => {
+                set(&m.position, "WriteSignAction.position")?;
+                within(
+                    m.lines.len() as f64,
+                    m.lines.len() <= 4,
+                    "WriteSignAction.lines",
+                    "at most 4",
+                )
+            }
+            Some(Action::WriteBook(m)) => {
+                present(&m.title, "WriteBookAction.title")?;
+                let title_len = m.title.chars().count();
+                within(
+                    title_len as f64,
+                    title_len <= 32,
+                    "WriteBookAction.title",
+                    "at most 32 characters",
+                )?;
+                if m.pages.is_empty() {
+                    return Err(ValidationError::Missing("WriteBookAction.pages"));
+                }
+                within(m.copies, m.copies >= 0, "WriteBookAction.copies", ">= 0")
+            }
+            Some(Action::MarkMap(m)) => {
+                set(&m.center, "MarkMapAction.center")?;
+                within(m.scale, (0..=4).contains(&m.scale), "MarkMapAction.scale", "0-4")?;
+                if m.markers.is_empty() {
+                    return Err(ValidationError::Missing("MarkMapAction.markers"));
+                }
+                for marker in &m.markers {
+                    set(&marker.position, "MapMarker.position")?;
+                }
+                Ok(())
+            }
+            Some(Action::ToggleLever(m)) => set(&m.position, "ToggleLeverAction.position"),
+            Some(Action::PressButton(m)) => set(&m.position, "PressButtonAction.position"),
+            Some(Action::SetMechanism(m)) => {
+                set(&m.position, "SetMechanismAction.position")?;
+                match m.state {
+                    Some(set_mechanism_action::State::Note(note)) => {
+                        within(note, (0..=24).contains(&note), "SetMechanismAction.note", "0-24")
+                    }
+                    Some(set_mechanism_action::State::ComparatorMode(mode)) => within(
+                        mode,
+                        ComparatorMode::try_from(mode)
+                            .is_ok_and(|mode| mode != ComparatorMode::Unspecified),
+                        "SetMechanismAction.comparator_mode",
+                        "COMPARE or SUBTRACT",
+                    ),
+                    Some(set_mechanism_action::State::RepeaterDelay(delay)) => within(
+                        delay,
+                        (1..=4).contains(&delay),
+                        "SetMechanismAction.repeater_delay",
+                        "1-4",
+                    ),
+                    None => Err(ValidationError::Missing("SetMechanismAction.state")),
+                }
+            }
+            Some(Action::ReadRedstonePower(m)) => {
+                set(&m.position, "ReadRedstonePowerAction.position")
+            }
+            Some(Action::EquipArmor(m)) => {
+                // Unequipping names the slot instead
+                if m.slot() == EquipmentSlot::Unspecified {
+                    present(&m.item_type, "EquipArmorAction.item_type")?;
+                }
+                Ok(())
+            }
+            Some(Action::Smelt(m)) => {
+                set(&m.furnace_position, "SmeltAction.furnace_position")?;
+                within(m.count, m.count >= 0, "SmeltAction.count", ">= 0")?;
+                if m.count > 0 {
+                    present(&m.input_item, "SmeltAction.input_item")?;
+                }
+                Ok(())
+            }
+            Some(Action::Brew(m)) => {
+                set(&m.stand_position, "BrewAction.stand_position")?;
+                within(
+                    m.bottle_count,
+                    (0..=3).contains(&m.bottle_count),
+                    "BrewAction.bottle_count",
+                    "0-3",
+                )?;
+                if m.bottle_count > 0 {
+                    present(&m.ingredient_item, "BrewAction.ingredient_item")?;
+                    present(&m.bottle_item, "BrewAction.bottle_item")?;
+                }
+                Ok(())
+            }
+            None => Err(ValidationError::Missing("ActionDirective.action")),
+        }
+    }
+}
+
+impl Validate for SpeakDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "SpeakDirective.npc_id")?;
+        unit(self.volume, "SpeakDirective.volume")?;
+        within(
+            self.duration_ms,
+            self.duration_ms >= 0,
+            "SpeakDirective.duration_ms",
+            ">= 0",
+        )?;
+        within(self.range, self.range >= 0.0, "SpeakDirective.range", ">= 0")?;
+        if self.emotion_type() == Emotion::Custom {
+            present(&self.emotion, "SpeakDirective.emotion")?;
+        }
+        match self.delivery() {
+            SpeechDelivery::Direct if self.target_player_uuids.is_empty() => Err(
+                ValidationError::Missing("SpeakDirective.target_player_uuids"),
+            ),
+            SpeechDelivery::Group if self.group.is_empty() => {
+                Err(ValidationError::Missing("SpeakDirective.group"))
+            }
+            SpeechDelivery::Global if !self.target_player_uuids.is_empty() => {
+                Err(ValidationError::Malformed {
+                    field: "SpeakDirective.target_player_uuids",
+                    reason: "must be empty for GLOBAL delivery".to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Validate for ChoreographyDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.directive_id, "ChoreographyDirective.directive_id")?;
+        if self.steps.is_empty() {
+            return Err(ValidationError::Missing("ChoreographyDirective.steps"));
+        }
+        let mut previous = 0;
+        for step in &self.steps {
+            within(
+                step.at_ms as f64,
+                step.at_ms >= previous,
+                "ChoreographyStep.at_ms",
+                ">= 0 and sorted",
+            )?;
+            previous = step.at_ms;
+            match &step.step {
+                // Steps have no directive_id of their own
+                Some(Step::Action(m)) => ActionDirective {
+                    directive_id: self.directive_id.clone(),
+                    ..m.clone()
+                }
+                .validate()?,
+                Some(Step::Speak(m)) => m.validate()?,
+                None => return Err(ValidationError::Missing("ChoreographyStep.step")),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Validate for AudioChunk {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "AudioChunk.npc_id")?;
+        present(&self.stream_id, "AudioChunk.stream_id")?;
+        // Only the closing chunk may be empty
+        if self.pcm_data.is_empty() && !self.is_final {
+            return Err(ValidationError::Missing("AudioChunk.pcm_data"));
+        }
+        Ok(())
+    }
+}
+
+impl Validate for RegisterAudioAsset {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.asset_id, "RegisterAudioAsset.asset_id")?;
+        present(&self.hash, "RegisterAudioAsset.hash")?;
+        if self.pcm_data.is_empty() {
+            return Err(ValidationError::Missing("RegisterAudioAsset.pcm_data"));
+        }
+        within(
+            self.pcm_data.len() as f64,
+            self.pcm_data.len() <= MAX_AUDIO_ASSET_BYTES && self.pcm_data.len().is_multiple_of(2),
+            "RegisterAudioAsset.pcm_data",
+            "whole samples, at most 1 MiB",
+        )?;
+        within(
+            self.duration_ms as f64,
+            self.duration_ms >= 0,
+            "RegisterAudioAsset.duration_ms",
+            ">= 0",
+        )
+    }
+}
+
+impl Validate for PlayAudioAssetDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.asset_id, "PlayAudioAssetDirective.asset_id")?;
+        present(&self.hash, "PlayAudioAssetDirective.hash")?;
+        let speak = self
+            .speak
+            .as_ref()
+            .ok_or(ValidationError::Missing("PlayAudioAssetDirective.speak"))?;
+        speak.validate()?;
+        if !speak.stream_id.is_empty() {
+            return Err(ValidationError::Malformed {
+                field: "PlayAudioAssetDirective.speak.stream_id",
+                reason: "must be empty; the asset is the audio".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Validate for StopSpeaking {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "StopSpeaking.npc_id")
+    }
+}
+
+impl Validate for VisemeTimeline {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "VisemeTimeline.npc_id")?;
+        present(&self.stream_id, "VisemeTimeline.stream_id")
+    }
+}
+
+impl Validate for QuestOffer {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.quest_id, "QuestOffer.quest_id")?;
+        present(&self.npc_id, "QuestOffer.npc_id")?;
+        present(&self.player_uuid, "QuestOffer.player_uuid")?;
+        if self.objectives.is_empty() {
+            return Err(ValidationError::Missing("QuestOffer.objectives"));
+        }
+        for objective in &self.objectives {
+            let count = objective.required_count;
+            within(count, count > 0, "QuestObjective.required_count", "> 0")?;
+        }
+        within(
+            self.reward_experience,
+            self.reward_experience >= 0,
+            "QuestOffer.reward_experience",
+            ">= 0",
+        )
+    }
+}
+
+impl Validate for TransferCurrencyDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.directive_id, "TransferCurrencyDirective.directive_id")?;
+        present(&self.npc_id, "TransferCurrencyDirective.npc_id")?;
+        present(&self.player_uuid, "TransferCurrencyDirective.player_uuid")?;
+        if self.direction() == TransferDirection::Unspecified {
+            return Err(ValidationError::Missing(
+                "TransferCurrencyDirective.direction",
+            ));
+        }
+        within(
+            self.amount as f64,
+            self.amount > 0,
+            "TransferCurrencyDirective.amount",
+            "> 0",
+        )
+    }
+}
+
+impl Validate for ShopDefinition {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.shop_id, "ShopDefinition.shop_id")?;
+        present(&self.npc_id, "ShopDefinition.npc_id")?;
+        within(
+            self.listings.len() as f64,
+            self.listings.len() <= 54,
+            "ShopDefinition.listings",
+            "at most 54",
+        )?;
+        for (i, listing) in self.listings.iter().enumerate() {
+            present(&listing.listing_id, "ShopListing.listing_id")?;
+            if self.listings[..i].iter().any(|l| l.listing_id == listing.listing_id) {
+                return Err(ValidationError::Malformed {
+                    field: "ShopListing.listing_id",
+                    reason: format!("{:?} appears twice", listing.listing_id),
+                });
+            }
+            let item = listing.item.as_ref().ok_or(ValidationError::Missing("ShopListing.item"))?;
+            present(&item.item_type, "ShopListing.item.item_type")?;
+            within(item.quantity, item.quantity > 0, "ShopListing.item.quantity", "> 0")?;
+            within(
+                listing.buy_price as f64,
+                listing.buy_price >= 0,
+                "ShopListing.buy_price",
+                ">= 0",
+            )?;
+            within(
+                listing.sell_price as f64,
+                listing.sell_price >= 0,
+                "ShopListing.sell_price",
+                ">= 0",
+            )?;
+            // A listing that is neither sold nor bought is an empty slot
+            if listing.buy_price == 0 && listing.sell_price == 0 {
+                return Err(ValidationError::Missing("ShopListing.buy_price or sell_price"));
+            }
+            within(listing.stock, listing.stock >= -1, "ShopListing.stock", ">= -1")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for ShowDisplayDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.display_id, "ShowDisplayDirective.display_id")?;
+        present(&self.npc_id, "ShowDisplayDirective.npc_id")?;
+        within(self.range, self.range >= 0.0, "ShowDisplayDirective.range", ">= 0")?;
+        match &self.display {
+            Some(Display::Hologram(m)) if m.lines.is_empty() => {
+                Err(ValidationError::Missing("HologramDisplay.lines"))
+            }
+            Some(Display::Hologram(_)) => Ok(()),
+            Some(Display::BossBar(m)) => {
+                unit(m.progress, "BossBarDisplay.progress")?;
+                within(
+                    m.segments,
+                    matches!(m.segments, 0 | 6 | 10 | 12 | 20),
+                    "BossBarDisplay.segments",
+                    "0, 6, 10, 12 or 20",
+                )
+            }
+            Some(Display::Scoreboard(m)) => within(
+                m.lines.len() as f64,
+                m.lines.len() <= 15,
+                "ScoreboardDisplay.lines",
+                "at most 15",
+            ),
+            None => Err(ValidationError::Missing("ShowDisplayDirective.display")),
+        }
+    }
+}
+
+impl Validate for PlayParticleDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.particle, "PlayParticleDirective.particle")?;
+        if self.npc_id.is_empty() && self.position.is_none() {
+            return Err(ValidationError::Missing("PlayParticleDirective.npc_id or position"));
+        }
+        within(self.count, self.count >= 0, "PlayParticleDirective.count", ">= 0")?;
+        for (value, field) in [
+            (self.offset_x, "PlayParticleDirective.offset_x"),
+            (self.offset_y, "PlayParticleDirective.offset_y"),
+            (self.offset_z, "PlayParticleDirective.offset_z"),
+            (self.speed, "PlayParticleDirective.speed"),
+        ] {
+            within(value, value >= 0.0, field, ">= 0")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for PlaySoundDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.sound, "PlaySoundDirective.sound")?;
+        if self.npc_id.is_empty() && self.position.is_none() {
+            return Err(ValidationError::Missing("PlaySoundDirective.npc_id or position"));
+        }
+        within(self.volume, self.volume >= 0.0, "PlaySoundDirective.volume", ">= 0")?;
+        within(
+            self.pitch,
+            self.pitch == 0.0 || (0.5..=2.0).contains(&self.pitch),
+            "PlaySoundDirective.pitch",
+            "0.5-2.0",
+        )
+    }
+}
+
+impl Validate for SetTimeDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.directive_id, "SetTimeDirective.directive_id")?;
+        if self.world.is_empty() && self.npc_id.is_empty() {
+            return Err(ValidationError::Missing("SetTimeDirective.world or npc_id"));
+        }
+        within(
+            self.time_of_day as f64,
+            (0..24_000).contains(&self.time_of_day),
+            "SetTimeDirective.time_of_day",
+            "0-23999",
+        )
+    }
+}
+
+impl Validate for FormationDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.formation_id, "FormationDirective.formation_id")?;
+        within(self.spacing, self.spacing >= 0.0, "FormationDirective.spacing", ">= 0")?;
+        within(self.tolerance, self.tolerance >= 0.0, "FormationDirective.tolerance", ">= 0")?;
+        // Without members it disbands, and needs no leader
+        if self.members.is_empty() {
+            return Ok(());
+        }
+        within(
+            self.members.len() as f64,
+            self.members.len() <= MAX_FORMATION_MEMBERS,
+            "FormationDirective.members",
+            "at most 32",
+        )?;
+        let leader = match &self.leader {
+            Some(Leader::LeaderNpcId(npc_id)) => npc_id,
+            Some(Leader::LeaderPlayerUuid(player_uuid)) => player_uuid,
+            None => return Err(ValidationError::Missing("FormationDirective.leader")),
+        };
+        present(leader, "FormationDirective.leader")?;
+        let mut seen = HashSet::new();
+        for member in &self.members {
+            present(&member.npc_id, "FormationMember.npc_id")?;
+            if !seen.insert(member.npc_id.as_str()) || member.npc_id == *leader {
+                return Err(ValidationError::Malformed {
+                    field: "FormationDirective.members",
+                    reason: format!("{} is the leader or listed twice", member.npc_id),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Validate for GuardZoneDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "GuardZoneDirective.npc_id")?;
+        present(&self.zone_id, "GuardZoneDirective.zone_id")?;
+        within(
+            self.alert_radius,
+            self.alert_radius >= 0.0,
+            "GuardZoneDirective.alert_radius",
+            ">= 0",
+        )?;
+        // Without corners it lifts the zone
+        if self.from.is_none() && self.to.is_none() {
+            return Ok(());
+        }
+        set(&self.from, "GuardZoneDirective.from")?;
+        set(&self.to, "GuardZoneDirective.to")?;
+        for player_uuid in &self.allowed_players {
+            present(player_uuid, "GuardZoneDirective.allowed_players")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for ClaimContainerDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "ClaimContainerDirective.npc_id")?;
+        set(&self.position, "ClaimContainerDirective.position")?;
+        if self.release {
+            return Ok(());
+        }
+        for player_uuid in &self.allowed_players {
+            present(player_uuid, "ClaimContainerDirective.allowed_players")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for PlayMusicDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "PlayMusicDirective.npc_id")?;
+        unit(self.volume, "PlayMusicDirective.volume")?;
+        within(
+            self.notes.len() as f64,
+            self.notes.len() <= MAX_SONG_NOTES,
+            "PlayMusicDirective.notes",
+            "at most 2048",
+        )?;
+        let mut previous = 0;
+        for note in &self.notes {
+            within(
+                note.at_ms as f64,
+                note.at_ms >= previous,
+                "MusicNote.at_ms",
+                ">= 0 and in order",
+            )?;
+            within(note.pitch, (0..=MAX_PITCH).contains(&note.pitch), "MusicNote.pitch", "0-24")?;
+            previous = note.at_ms;
+        }
+        within(
+            self.length_ms as f64,
+            self.length_ms >= 0,
+            "PlayMusicDirective.length_ms",
+            ">= 0",
+        )?;
+        if self.repeat && self.notes.is_empty() {
+            return Err(ValidationError::Missing("PlayMusicDirective.notes"));
+        }
+        Ok(())
+    }
+}
+
+impl Validate for SetWeatherDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.directive_id, "SetWeatherDirective.directive_id")?;
+        if self.world.is_empty() && self.npc_id.is_empty() {
+            return Err(ValidationError::Missing("SetWeatherDirective.world or npc_id"));
+        }
+        if self.weather() == Weather::Unspecified {
+            return Err(ValidationError::Missing("SetWeatherDirective.weather"));
+        }
+        within(
+            self.duration_ticks,
+            self.duration_ticks >= 0,
+            "SetWeatherDirective.duration_ticks",
+            ">= 0",
+        )
+    }
+}
+
+impl Validate for SetCombatPolicyDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "SetCombatPolicyDirective.npc_id")?;
+        unit(
+            self.flee_health_threshold,
+            "SetCombatPolicyDirective.flee_health_threshold",
+        )?;
+        within(
+            self.max_chase_distance,
+            self.max_chase_distance >= 0.0,
+            "SetCombatPolicyDirective.max_chase_distance",
+            ">= 0",
+        )?;
+        for filter in &self.target_filters {
+            present(&filter.entity_type, "TargetFilter.entity_type")?;
+        }
+        let types: Vec<String> = self
+            .target_filters
+            .iter()
+            .map(|f| f.entity_type.clone())
+            .collect();
+        patterns(&types, "TargetFilter.entity_type")
+    }
+}
+
+impl Validate for RestoreNpcState {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "RestoreNpcState.npc_id")?;
+        unit(self.health_norm, "RestoreNpcState.health_norm")?;
+        unit(self.xp_progress, "RestoreNpcState.xp_progress")?;
+        if let Some(xp_level) = self.xp_level {
+            within(xp_level, xp_level >= 0, "RestoreNpcState.xp_level", ">= 0")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for SubscribeEvents {
+    fn validate(&self) -> Result<(), ValidationError> {
+        for &event_type in &self.event_types {
+            within(
+                event_type,
+                EventType::try_from(event_type).is_ok_and(|t| t != EventType::Unspecified),
+                "SubscribeEvents.event_types",
+                "a known EventType",
+            )?;
+        }
+        for npc_id in &self.npc_ids {
+            present(npc_id, "SubscribeEvents.npc_ids")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for DirectiveRejected {
+    fn validate(&self) -> Result<(), ValidationError> {
+        // Messages without a directive_id are identified by their NPC
+        if self.directive_id.is_empty() {
+            present(&self.npc_id, "DirectiveRejected.npc_id")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for DirectiveAck {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.directive_id, "DirectiveAck.directive_id")?;
+        within(
+            self.queued_position,
+            self.queued_position >= 0,
+            "DirectiveAck.queued_position",
+            ">= 0",
+        )
+    }
+}
+
+impl Validate for PrepareNpcTransfer {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.transfer_id, "PrepareNpcTransfer.transfer_id")?;
+        present(&self.npc_id, "PrepareNpcTransfer.npc_id")?;
+        present(&self.target_server_id, "PrepareNpcTransfer.target_server_id")
+    }
+}
+
+impl Validate for SpawnTransferredNpc {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.transfer_id, "SpawnTransferredNpc.transfer_id")?;
+        present(&self.source_server_id, "SpawnTransferredNpc.source_server_id")?;
+        let snapshot = self
+            .snapshot
+            .as_ref()
+            .ok_or(ValidationError::Missing("SpawnTransferredNpc.snapshot"))?;
+        present(&snapshot.npc_id, "SpawnTransferredNpc.snapshot.npc_id")
+    }
+}
+
+impl Validate for FinishNpcTransfer {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.transfer_id, "FinishNpcTransfer.transfer_id")?;
+        present(&self.npc_id, "FinishNpcTransfer.npc_id")
+    }
+}
+
+impl Validate for ChatDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        if self.player_uuids.is_empty() {
+            return Err(ValidationError::Missing("ChatDirective.player_uuids"));
+        }
+        present(&self.text, "ChatDirective.text")
+    }
+}
+
+impl Validate for DialogueOptionsDirective {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.prompt_id, "DialogueOptionsDirective.prompt_id")?;
+        present(&self.npc_id, "DialogueOptionsDirective.npc_id")?;
+        present(&self.player_uuid, "DialogueOptionsDirective.player_uuid")?;
+        if self.options.is_empty() {
+            return Err(ValidationError::Missing("DialogueOptionsDirective.options"));
+        }
+        for (i, option) in self.options.iter().enumerate() {
+            present(&option.option_id, "DialogueOption.option_id")?;
+            present(&option.label, "DialogueOption.label")?;
+            // The choice names the option by id, so ids must be unique
+            if self.options[..i].iter().any(|o| o.option_id == option.option_id) {
+                return Err(ValidationError::Malformed {
+                    field: "DialogueOption.option_id",
+                    reason: format!("{:?} appears twice", option.option_id),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Validate for DialogueChoiceObservation {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.prompt_id, "DialogueChoiceObservation.prompt_id")?;
+        present(&self.npc_id, "DialogueChoiceObservation.npc_id")?;
+        present(&self.player_uuid, "DialogueChoiceObservation.player_uuid")
+    }
+}
+
+impl Validate for NpcTransferUpdate {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.transfer_id, "NpcTransferUpdate.transfer_id")?;
+        present(&self.npc_id, "NpcTransferUpdate.npc_id")?;
+        let stage = NpcTransferStage::try_from(self.stage).unwrap_or_default();
+        within(
+            self.stage,
+            stage != NpcTransferStage::Unspecified,
+            "NpcTransferUpdate.stage",
+            "a known NpcTransferStage",
+        )?;
+        if stage == NpcTransferStage::Prepared && self.snapshot.is_none() {
+            return Err(ValidationError::Missing("NpcTransferUpdate.snapshot"));
+        }
+        Ok(())
+    }
+}
+
+impl Validate for CombatPolicyObservation {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "CombatPolicyObservation.npc_id")?;
+        unit(self.health_norm, "CombatPolicyObservation.health_norm")
+    }
+}
+
+impl Validate for IntruderObservation {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "IntruderObservation.npc_id")?;
+        present(&self.zone_id, "IntruderObservation.zone_id")?;
+        present(&self.player_uuid, "IntruderObservation.player_uuid")?;
+        within(self.intruders, self.intruders >= 0, "IntruderObservation.intruders", ">= 0")
+    }
+}
+
+impl Validate for RegisterLandmark {
+    fn validate(&self) -> Result<(), ValidationError> {
+        let Some(landmark) = &self.landmark else {
+            return Err(ValidationError::Missing("RegisterLandmark.landmark"));
+        };
+        present(&landmark.name, "RegisterLandmark.landmark.name")?;
+        if !self.remove {
+            set(&landmark.position, "RegisterLandmark.landmark.position")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for ListLandmarks {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.request_id, "ListLandmarks.request_id")
+    }
+}
+
+impl Validate for LandmarkList {
+    fn validate(&self) -> Result<(), ValidationError> {
+        for landmark in &self.landmarks {
+            present(&landmark.name, "LandmarkList.landmarks.name")?;
+            set(&landmark.position, "LandmarkList.landmarks.position")?;
+        }
+        for name in &self.removed {
+            present(name, "LandmarkList.removed")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for ContainerAccessObservation {
+    fn validate(&self) -> Result<(), ValidationError> {
+        present(&self.npc_id, "ContainerAccessObservation.npc_id")?;
+        set(&self.position, "ContainerAccessObservation.position")?;
+        present(&self.player_uuid, "ContainerAccessObservation.player_uuid")?;
+        for item in &self.items {
+            present(&item.item_type, "ContainerAccessObservation.items.item_type")?;
+        }
+        Ok(())
+    }
+}
+
+/// Flags audio frames and chunks that jump back in their stream.
+///
+/// Small reordering is normal on the network and handled by the jitter
+/// buffer, so only frames more than `window` sequence numbers behind the
+/// highest one seen count as a regression.
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    window: u64,
+    highest: HashMap<String, u64>,
+}
+
+impl SequenceTracker {
+    /// Allow frames up to `window` behind the newest one
+    pub fn new(window: u64) -> Self {
+        Self {
+            window,
+            highest: HashMap::new(),
+        }
+    }
+
+    /// Check a message's sequence number, if it has one. Finished streams
+    /// (final AudioChunk) are forgotten.
+    pub fn check_client(&mut self, msg: &ClientMessage) -> Result<(), ValidationError> {
+        match &msg.message {
+            Some(ClientMsg::VoicePcmFrame(f)) => {
+                self.check(format!("{}/{}", f.npc_id, f.player_uuid), f.sequence)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// See [`SequenceTracker::check_client`]
+    pub fn check_server(&mut self, msg: &ServerMessage) -> Result<(), ValidationError> {
+        match &msg.message {
+            Some(ServerMsg::AudioChunk(c)) => {
+                let result = self.check(c.stream_id.clone(), c.sequence);
+                if c.is_final {
+                    self.highest.remove(&c.stream_id);
+                }
+                result
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check(&mut self, stream: String, sequence: u64) -> Result<(), ValidationError> {
+        let highest = self.highest.entry(stream.clone()).or_insert(sequence);
+        if sequence.saturating_add(self.window) < *highest {
+            return Err(ValidationError::SequenceRegression {
+                stream,
+                sequence,
+                highest: *highest,
+            });
+        }
+        *highest = (*highest).max(sequence);
+        Ok(())
+    }
+
+    /// Forget a voice stream, e.g. when the speaker's session ends
+    pub fn end_voice(&mut self, npc_id: &str, player_uuid: &str) {
+        self.highest.remove(&format!("{}/{}", npc_id, player_uuid));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::ScanBlocksAction;
+
+    #[test]
+    fn test_fields() {
+        let scan = ActionDirective {
+            directive_id: "d".to_string(),
+            npc_id: "miner".to_string(),
+            action: Some(Action::ScanBlocks(ScanBlocksAction {
+                center: Some(Default::default()),
+                radius: -1,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let err = scan.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ScanBlocksAction.radius is -1, expected > 0"
+        );
+        let tagged = ActionDirective {
+            action: Some(Action::ScanBlocks(ScanBlocksAction {
+                center: Some(Default::default()),
+                radius: 8,
+                block_types: vec!["#minecraft:logs".to_string(), "#minecraft:*".to_string()],
+                ..Default::default()
+            })),
+            ..scan.clone()
+        };
+        assert!(matches!(
+            tagged.validate(),
+            Err(ValidationError::Malformed {
+                field: "ScanBlocksAction.block_types",
+                ..
+            })
+        ));
+
+        let speak = SpeakDirective {
+            npc_id: "bard".to_string(),
+            volume: 1.5,
+            ..Default::default()
+        };
+        assert!(matches!(
+            speak.validate(),
+            Err(ValidationError::OutOfRange {
+                field: "SpeakDirective.volume",
+                ..
+            })
+        ));
+        let custom = SpeakDirective {
+            npc_id: "bard".to_string(),
+            emotion_type: Emotion::Custom as i32,
+            ..Default::default()
+        };
+        assert_eq!(
+            custom.validate(),
+            Err(ValidationError::Missing("SpeakDirective.emotion"))
+        );
+        assert_eq!(
+            VoicePcmFrame {
+                npc_id: "bard".to_string(),
+                player_uuid: "p".to_string(),
+                ..Default::default()
+            }
+            .validate(),
+            Err(ValidationError::Missing("VoicePcmFrame.pcm_data"))
+        );
+        assert_eq!(
+            ClientMessage::default().validate(),
+            Err(ValidationError::EmptyMessage("ClientMessage"))
+        );
+    }
+
+    #[test]
+    fn test_sequences() {
+        let frame = |sequence| ClientMessage {
+            message: Some(ClientMsg::VoicePcmFrame(VoicePcmFrame {
+                npc_id: "bard".to_string(),
+                player_uuid: "p".to_string(),
+                sequence,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let mut tracker = SequenceTracker::new(2);
+        for sequence in [10, 12, 11, 10] {
+            assert_eq!(tracker.check_client(&frame(sequence)), Ok(()));
+        }
+        assert!(matches!(
+            tracker.check_client(&frame(9)),
+            Err(ValidationError::SequenceRegression { highest: 12, .. })
+        ));
+        tracker.end_voice("bard", "p");
+        assert_eq!(tracker.check_client(&frame(0)), Ok(()));
+    }
+}
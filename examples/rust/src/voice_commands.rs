@@ -0,0 +1,504 @@
+//! Spoken commands that skip the LLM.
+//!
+//! "Stop!" shouted at an NPC walking off a cliff has to take effect now,
+//! not after a round trip through a language model. A [`VoiceGrammar`]
+//! matches each final transcript against the phrases of its
+//! [`VoiceCommand`]s and, on a match, returns the directives to send
+//! straight away; anything else goes to the LLM as before:
+//!
+//! ```ignore
+//! match grammar.dispatch(&transcription, player, npc, Some(&landmarks)) {
+//!     Some(recognized) => send(recognized.messages),
+//!     None => llm.reply(&transcription.text).await?,
+//! }
+//! ```
+//!
+//! A phrase is a few words with optional `{slots}` that take one or more
+//! words, e.g. `go to {place}`. It has to match the whole utterance after
+//! case, punctuation and filler such as "hey" or "please" are taken off,
+//! so "stop" matches "Stop!" and "please stop now" but not "don't stop".
+//! [`VoiceGrammar::movement`] has `follow me`, `stop`, `go home` and
+//! `go to {place}`; [`VoiceGrammar::command`] adds more.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::asr::VoiceTranscription;
+use crate::builders::Buildable;
+use crate::commands::PRIORITY;
+use crate::formation;
+use crate::landmarks::Landmarks;
+use crate::npc_society::v1::{
+    action_directive::Action, ActionDirective, MoveAction, NpcSnapshot, PlayerSnapshot, Position,
+    ServerMessage, StopAction,
+};
+use crate::players;
+
+/// Words taken off either end of an utterance before matching
+const FILLER: [&str; 6] = ["hey", "ok", "okay", "please", "now", "right"];
+
+/// A spoken command, with what the daemon knows about who said it
+#[derive(Debug, Clone, Copy)]
+pub struct VoiceCommandContext<'a> {
+    /// The final transcript
+    pub transcription: &'a VoiceTranscription,
+    /// The speaking player, from the latest WorldTick
+    pub player: Option<&'a PlayerSnapshot>,
+    /// The NPC that heard it, from the latest WorldTick
+    pub npc: Option<&'a NpcSnapshot>,
+    /// Named places, for commands that go somewhere
+    pub landmarks: Option<&'a Landmarks>,
+    slots: &'a HashMap<String, String>,
+}
+
+impl VoiceCommandContext<'_> {
+    /// Words the phrase's `{name}` slot matched, space-separated
+    pub fn slot(&self, name: &str) -> Option<&str> {
+        self.slots.get(name).map(String::as_str)
+    }
+}
+
+/// A command registered on a [`VoiceGrammar`]
+pub trait VoiceCommand: Send + Sync {
+    /// Name, for logs
+    fn name(&self) -> &str;
+
+    /// Phrases that run the command, e.g. `follow me`
+    fn phrases(&self) -> &[&str];
+
+    /// Permission node a non-operator needs (None = any player)
+    fn permission(&self) -> Option<&str> {
+        None
+    }
+
+    /// Messages to send, or None to leave the utterance to the LLM (e.g.
+    /// "go home" for an NPC without one). ActionDirectives have no
+    /// directive_id yet; the daemon assigns it.
+    fn run(&self, ctx: &VoiceCommandContext<'_>) -> Option<Vec<ServerMessage>>;
+}
+
+fn directive(npc_id: &str, action: impl Into<Action>) -> ServerMessage {
+    ActionDirective {
+        npc_id: npc_id.to_string(),
+        priority: PRIORITY,
+        action: Some(action.into()),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Formation an NPC follows a player in
+pub fn follow_formation_id(npc_id: &str) -> String {
+    format!("follow-{}", npc_id)
+}
+
+fn walk_to(npc_id: &str, target: &Position) -> Option<Vec<ServerMessage>> {
+    let action = MoveAction::builder().target(target.clone()).build().ok()?;
+    Some(vec![
+        formation::disband(&follow_formation_id(npc_id)).into(),
+        directive(npc_id, action),
+    ])
+}
+
+/// `follow me`: keep close to the speaker, steered plugin-side
+pub struct Follow;
+
+impl VoiceCommand for Follow {
+    fn name(&self) -> &str {
+        "follow"
+    }
+
+    fn phrases(&self) -> &[&str] {
+        &["follow me", "come with me"]
+    }
+
+    fn run(&self, ctx: &VoiceCommandContext<'_>) -> Option<Vec<ServerMessage>> {
+        let t = ctx.transcription;
+        let follow = formation::escort(
+            &follow_formation_id(&t.npc_id),
+            &t.player_uuid,
+            std::slice::from_ref(&t.npc_id),
+            formation::DEFAULT_SPACING,
+        );
+        Some(vec![follow.into()])
+    }
+}
+
+/// `stop`: drop the current action and stop following
+pub struct Stop;
+
+impl VoiceCommand for Stop {
+    fn name(&self) -> &str {
+        "stop"
+    }
+
+    fn phrases(&self) -> &[&str] {
+        &[
+            "stop",
+            "halt",
+            "wait",
+            "stay",
+            "stay here",
+            "stop following me",
+        ]
+    }
+
+    fn run(&self, ctx: &VoiceCommandContext<'_>) -> Option<Vec<ServerMessage>> {
+        let npc_id = &ctx.transcription.npc_id;
+        Some(vec![
+            formation::disband(&follow_formation_id(npc_id)).into(),
+            directive(npc_id, StopAction::default()),
+        ])
+    }
+}
+
+/// `go home`: walk to a landmark, `home` unless given another
+pub struct GoHome {
+    landmark: String,
+}
+
+impl Default for GoHome {
+    fn default() -> Self {
+        Self::to("home")
+    }
+}
+
+impl GoHome {
+    /// Go home to the landmark called `landmark`
+    pub fn to(landmark: &str) -> Self {
+        Self {
+            landmark: landmark.to_string(),
+        }
+    }
+}
+
+impl VoiceCommand for GoHome {
+    fn name(&self) -> &str {
+        "go_home"
+    }
+
+    fn phrases(&self) -> &[&str] {
+        &["go home", "go back home", "head home"]
+    }
+
+    fn run(&self, ctx: &VoiceCommandContext<'_>) -> Option<Vec<ServerMessage>> {
+        let home = ctx.landmarks?.position(&self.landmark)?;
+        walk_to(&ctx.transcription.npc_id, home)
+    }
+}
+
+/// `go to {place}`: walk to the landmark named like the place, e.g.
+/// "town square" to `town_square`
+pub struct GoTo;
+
+impl VoiceCommand for GoTo {
+    fn name(&self) -> &str {
+        "go_to"
+    }
+
+    fn phrases(&self) -> &[&str] {
+        &[
+            "go to the {place}",
+            "go to {place}",
+            "walk to the {place}",
+            "walk to {place}",
+        ]
+    }
+
+    fn run(&self, ctx: &VoiceCommandContext<'_>) -> Option<Vec<ServerMessage>> {
+        let name = ctx.slot("place")?.replace(' ', "_");
+        let place = ctx.landmarks?.position(&name)?;
+        walk_to(&ctx.transcription.npc_id, place)
+    }
+}
+
+/// A phrase as matched: words and `{slot}`s
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Slot(String),
+}
+
+fn parse(phrase: &str) -> Vec<Token> {
+    phrase
+        .split_whitespace()
+        .map(
+            |token| match token.strip_prefix('{').and_then(|t| t.strip_suffix('}')) {
+                Some(slot) => Token::Slot(slot.to_string()),
+                None => Token::Word(token.to_lowercase()),
+            },
+        )
+        .collect()
+}
+
+/// Whether `tokens` match all of `words`; slots take one or more words,
+/// as few as possible
+fn matches(tokens: &[Token], words: &[String], slots: &mut HashMap<String, String>) -> bool {
+    match tokens.split_first() {
+        None => words.is_empty(),
+        Some((Token::Word(word), rest)) => {
+            words.first() == Some(word) && matches(rest, &words[1..], slots)
+        }
+        Some((Token::Slot(name), rest)) => (1..=words.len()).any(|taken| {
+            slots.insert(name.clone(), words[..taken].join(" "));
+            matches(rest, &words[taken..], slots)
+        }),
+    }
+}
+
+/// Lowercase words without punctuation or filler at either end
+fn words(text: &str) -> Vec<String> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let filler = |word: &String| FILLER.contains(&word.as_str());
+    let start = words.iter().position(|w| !filler(w)).unwrap_or(words.len());
+    let end = words
+        .iter()
+        .rposition(|w| !filler(w))
+        .map_or(start, |i| i + 1);
+    words[start..end].to_vec()
+}
+
+/// A command a transcript matched, with what it sends
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recognized {
+    /// [`VoiceCommand::name`]
+    pub command: String,
+    /// Messages to send, in order
+    pub messages: Vec<ServerMessage>,
+}
+
+/// A command with its phrases parsed
+struct Registered {
+    phrases: Vec<Vec<Token>>,
+    command: Box<dyn VoiceCommand>,
+}
+
+/// Recognizes spoken commands and runs them
+#[derive(Default)]
+pub struct VoiceGrammar {
+    commands: Vec<Registered>,
+}
+
+impl fmt::Debug for VoiceGrammar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VoiceGrammar")
+            .field(
+                "commands",
+                &self
+                    .commands
+                    .iter()
+                    .map(|r| r.command.name())
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl VoiceGrammar {
+    /// A grammar without commands
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `follow me`, `stop`, `go home` (to the `home` landmark) and
+    /// `go to {place}`
+    pub fn movement() -> Self {
+        Self::new()
+            .command(Follow)
+            .command(Stop)
+            .command(GoHome::default())
+            .command(GoTo)
+    }
+
+    /// Register a command; earlier commands win when phrases overlap
+    pub fn command(mut self, command: impl VoiceCommand + 'static) -> Self {
+        let phrases = command.phrases().iter().map(|p| parse(p)).collect();
+        self.commands.push(Registered {
+            phrases,
+            command: Box::new(command),
+        });
+        self
+    }
+
+    /// Run the command `transcription` is, if any. Partial transcripts
+    /// never match: the utterance may not be over.
+    pub fn dispatch(
+        &self,
+        transcription: &VoiceTranscription,
+        player: Option<&PlayerSnapshot>,
+        npc: Option<&NpcSnapshot>,
+        landmarks: Option<&Landmarks>,
+    ) -> Option<Recognized> {
+        if !transcription.is_final {
+            return None;
+        }
+        let words = words(&transcription.text);
+        if words.is_empty() {
+            return None;
+        }
+        let mut slots = HashMap::new();
+        for Registered { phrases, command } in &self.commands {
+            slots.clear();
+            if !phrases
+                .iter()
+                .any(|tokens| matches(tokens, &words, &mut slots))
+            {
+                continue;
+            }
+            let allowed = match (command.permission(), player) {
+                (None, _) => true,
+                (Some(_), None) => false,
+                (Some(node), Some(player)) => player.op || players::has_permission(player, node),
+            };
+            if !allowed {
+                return None;
+            }
+            let ctx = VoiceCommandContext {
+                transcription,
+                player,
+                npc,
+                landmarks,
+                slots: &slots,
+            };
+            return command.run(&ctx).map(|messages| Recognized {
+                command: command.name().to_string(),
+                messages,
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::landmarks;
+    use crate::npc_society::v1::{server_message::Message as ServerMsg, LandmarkList};
+
+    fn said(text: &str) -> VoiceTranscription {
+        VoiceTranscription {
+            npc_id: "miner_1".to_string(),
+            player_uuid: "p-1".to_string(),
+            text: text.to_string(),
+            is_final: true,
+        }
+    }
+
+    fn places() -> Landmarks {
+        let mut places = Landmarks::default();
+        let at = |x| Position {
+            world: "world".to_string(),
+            x,
+            ..Default::default()
+        };
+        let mut register = |name, position| {
+            let landmark = landmarks::register(name, position, "").landmark;
+            places.ingest(&LandmarkList {
+                landmarks: landmark.into_iter().collect(),
+                ..Default::default()
+            });
+        };
+        register("home", at(1.0));
+        register("town_square", at(2.0));
+        places
+    }
+
+    fn target(messages: &[ServerMessage]) -> Option<f64> {
+        messages.iter().find_map(|m| match &m.message {
+            Some(ServerMsg::ActionDirective(ActionDirective {
+                action: Some(Action::Move(action)),
+                ..
+            })) => action.target.as_ref().map(|t| t.x),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_movement_commands() {
+        let grammar = VoiceGrammar::movement();
+        let places = places();
+        let run = |text: &str| grammar.dispatch(&said(text), None, None, Some(&places));
+
+        let follow = run("Hey, follow me!").unwrap();
+        assert_eq!(follow.command, "follow");
+        let Some(ServerMsg::Formation(formation)) = &follow.messages[0].message else {
+            panic!("Expected a FormationDirective, got {follow:?}");
+        };
+        assert_eq!(formation.formation_id, "follow-miner_1");
+        assert_eq!(formation.members[0].npc_id, "miner_1");
+
+        // Stop ends the follow as well as whatever the NPC was doing
+        let stop = run("STOP. Please.").unwrap();
+        assert_eq!(stop.command, "stop");
+        assert!(matches!(
+            &stop.messages[1].message,
+            Some(ServerMsg::ActionDirective(ActionDirective {
+                priority: PRIORITY,
+                action: Some(Action::Stop(_)),
+                ..
+            }))
+        ));
+
+        assert_eq!(target(&run("go home").unwrap().messages), Some(1.0));
+        let go_to = run("Go to the town square, please").unwrap();
+        assert_eq!(go_to.command, "go_to");
+        assert_eq!(target(&go_to.messages), Some(2.0));
+
+        // Only whole utterances match; the rest is for the LLM
+        assert_eq!(run("don't stop"), None);
+        assert_eq!(run("can you follow me to the mine"), None);
+        assert_eq!(run("go to the moon"), None);
+        assert_eq!(grammar.dispatch(&said("go home"), None, None, None), None);
+        let partial = VoiceTranscription {
+            is_final: false,
+            ..said("stop")
+        };
+        assert_eq!(grammar.dispatch(&partial, None, None, None), None);
+    }
+
+    #[test]
+    fn test_custom_command() {
+        struct Dig;
+        impl VoiceCommand for Dig {
+            fn name(&self) -> &str {
+                "dig"
+            }
+            fn phrases(&self) -> &[&str] {
+                &["dig {direction}"]
+            }
+            fn permission(&self) -> Option<&str> {
+                Some("npcsociety.foreman")
+            }
+            fn run(&self, ctx: &VoiceCommandContext<'_>) -> Option<Vec<ServerMessage>> {
+                let direction = ctx.slot("direction")?;
+                (direction == "down")
+                    .then(|| vec![directive(&ctx.transcription.npc_id, StopAction::default())])
+            }
+        }
+
+        let grammar = VoiceGrammar::new().command(Dig);
+        let foreman = PlayerSnapshot {
+            player_uuid: "p-1".to_string(),
+            permissions: vec!["npcsociety.foreman".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(grammar.dispatch(&said("dig down"), None, None, None), None);
+        let dig = grammar
+            .dispatch(&said("Dig down!"), Some(&foreman), None, None)
+            .unwrap();
+        assert_eq!(dig.command, "dig");
+        assert_eq!(dig.messages.len(), 1);
+        assert_eq!(
+            grammar.dispatch(&said("dig sideways"), Some(&foreman), None, None),
+            None
+        );
+        assert_eq!(
+            format!("{grammar:?}"),
+            r#"VoiceGrammar { commands: ["dig"] }"#
+        );
+    }
+}
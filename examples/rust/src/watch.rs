@@ -0,0 +1,196 @@
+//! Block watches (`WatchBlocksAction`, v1.2+).
+//!
+//! A watch makes the plugin push [`BlockWatchUpdate`]s for a region instead
+//! of the daemon re-issuing ScanBlocks on a timer. [`BlockWatches`] applies
+//! the incremental updates so the current matches of every watch are at
+//! hand: record the directive with [`BlockWatches::start`], feed it the
+//! updates and the watch's ActionResult, which arrives when the watch ends.
+
+use std::collections::HashMap;
+
+use crate::geom;
+use crate::npc_society::v1::{
+    action_directive::Action, ActionDirective, ActionResult, BlockMatch, BlockPosition,
+    BlockWatchUpdate, Position,
+};
+
+type Key = (String, i32, i32, i32);
+
+fn key(p: &BlockPosition) -> Key {
+    (p.world.clone(), p.x, p.y, p.z)
+}
+
+/// One watch: whose it is and what matches now
+#[derive(Debug, Clone, Default)]
+struct Watch {
+    npc_id: String,
+    matches: HashMap<Key, BlockMatch>,
+    server_tick: i64,
+}
+
+/// Current matches of the watches in flight, by watch id (the
+/// WatchBlocksAction's directive_id)
+#[derive(Debug, Default)]
+pub struct BlockWatches {
+    watches: HashMap<String, Watch>,
+}
+
+impl BlockWatches {
+    /// Track `directive` if it is a WatchBlocksAction; returns whether it was
+    pub fn start(&mut self, directive: &ActionDirective) -> bool {
+        if !matches!(directive.action, Some(Action::WatchBlocks(_))) {
+            return false;
+        }
+        self.watches.insert(
+            directive.directive_id.clone(),
+            Watch {
+                npc_id: directive.npc_id.clone(),
+                ..Default::default()
+            },
+        );
+        true
+    }
+
+    /// Apply an update; false if the watch is unknown (never started or
+    /// already ended)
+    pub fn apply(&mut self, update: &BlockWatchUpdate) -> bool {
+        let Some(watch) = self.watches.get_mut(&update.directive_id) else {
+            return false;
+        };
+        if update.full {
+            watch.matches.clear();
+        }
+        for position in &update.removed {
+            watch.matches.remove(&key(position));
+        }
+        for found in &update.found {
+            if let Some(position) = &found.position {
+                watch.matches.insert(key(position), found.clone());
+            }
+        }
+        watch.server_tick = update.server_tick;
+        true
+    }
+
+    /// Stop tracking the watch `result` reports on; false if it is not a
+    /// watch's result
+    pub fn finish(&mut self, result: &ActionResult) -> bool {
+        self.watches.remove(&result.directive_id).is_some()
+    }
+
+    /// Whether `watch_id` is being tracked
+    pub fn is_active(&self, watch_id: &str) -> bool {
+        self.watches.contains_key(watch_id)
+    }
+
+    /// server_tick of the watch's latest update (0 before the first)
+    pub fn server_tick(&self, watch_id: &str) -> Option<i64> {
+        self.watches.get(watch_id).map(|w| w.server_tick)
+    }
+
+    /// Ids of the watches issued for `npc_id`
+    pub fn watches_of<'a>(&'a self, npc_id: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.watches
+            .iter()
+            .filter(move |(_, w)| w.npc_id == npc_id)
+            .map(|(id, _)| id.as_str())
+    }
+
+    /// Current matches of a watch (none if unknown)
+    pub fn matches(&self, watch_id: &str) -> impl Iterator<Item = &BlockMatch> {
+        self.watches
+            .get(watch_id)
+            .into_iter()
+            .flat_map(|w| w.matches.values())
+    }
+
+    /// The match closest to `from`, in its world
+    pub fn nearest(&self, watch_id: &str, from: &Position) -> Option<&BlockMatch> {
+        self.matches(watch_id)
+            .filter_map(|m| Some((m, m.position.as_ref()?)))
+            .filter(|(_, p)| p.world == from.world)
+            .map(|(m, p)| (m, geom::distance(p, from)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(m, _)| m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::WatchBlocksAction;
+
+    fn block(x: i32) -> BlockPosition {
+        BlockPosition {
+            world: "world".to_string(),
+            x,
+            y: 12,
+            ..Default::default()
+        }
+    }
+
+    fn ore(x: i32) -> BlockMatch {
+        BlockMatch {
+            position: Some(block(x)),
+            block_type: "minecraft:diamond_ore".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_updates() {
+        let mut watches = BlockWatches::default();
+        let directive = ActionDirective {
+            directive_id: "w1".to_string(),
+            npc_id: "miner".to_string(),
+            action: Some(Action::WatchBlocks(WatchBlocksAction::default())),
+            ..Default::default()
+        };
+        assert!(watches.start(&directive));
+        assert_eq!(watches.watches_of("miner").collect::<Vec<_>>(), ["w1"]);
+
+        watches.apply(&BlockWatchUpdate {
+            directive_id: "w1".to_string(),
+            found: vec![ore(1), ore(5), ore(9)],
+            full: true,
+            ..Default::default()
+        });
+        // The nearest ore was mined, another one turned up
+        watches.apply(&BlockWatchUpdate {
+            directive_id: "w1".to_string(),
+            found: vec![ore(20)],
+            removed: vec![block(1)],
+            ..Default::default()
+        });
+        assert_eq!(watches.matches("w1").count(), 3);
+        assert_eq!(watches.server_tick("w1"), Some(0));
+        let from = Position {
+            world: "world".to_string(),
+            y: 12.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            watches.nearest("w1", &from).unwrap().position,
+            Some(block(5))
+        );
+
+        // Resync after a chunk reload
+        watches.apply(&BlockWatchUpdate {
+            directive_id: "w1".to_string(),
+            found: vec![ore(9)],
+            full: true,
+            ..Default::default()
+        });
+        assert_eq!(watches.matches("w1").count(), 1);
+
+        assert!(watches.finish(&ActionResult {
+            directive_id: "w1".to_string(),
+            ..Default::default()
+        }));
+        assert!(!watches.apply(&BlockWatchUpdate {
+            directive_id: "w1".to_string(),
+            ..Default::default()
+        }));
+        assert_eq!(watches.matches("w1").count(), 0);
+    }
+}
@@ -0,0 +1,329 @@
+//! Directives that never got an answer.
+//!
+//! A directive can vanish without a trace: lost with a broken stream,
+//! forgotten by a restarted plugin, or stuck behind a wedged NPC. Nothing
+//! fails, the behavior tree just waits forever. [`DirectiveWatchdog`]
+//! follows every sent directive through its `DirectiveAck` and
+//! `ActionResult` and reports the ones past their deadline as
+//! [`DirectiveOrphaned`].
+//!
+//! `directive_id` is an idempotency key (v1.2+), so an orphan can be resent
+//! as is: a plugin that did get it answers instead of running it twice.
+//! The watchdog does that up to [`WatchdogConfig::max_resends`] times before
+//! giving up on the directive.
+//!
+//! A `WatchBlocksAction` only gets its ActionResult when the watch ends, so
+//! once acknowledged it has no result deadline.
+//!
+//! Directives of an NPC whose chunk unloaded are parked by the plugin
+//! (v1.2+, see `ChunkLoadObservation`): they run once the chunk loads, so
+//! their deadlines pause between [`DirectiveWatchdog::park`] and
+//! [`DirectiveWatchdog::unpark`].
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use crate::npc_society::v1::{action_directive::Action, ActionDirective, DirectiveAck};
+
+/// Deadlines of a [`DirectiveWatchdog`]
+#[derive(Debug, Clone, Copy)]
+pub struct WatchdogConfig {
+    /// How long after sending the DirectiveAck must arrive. Plugins
+    /// before v1.2 send no acks: until the first ack, directives get
+    /// `result_timeout` from when they were sent instead.
+    pub ack_timeout: Duration,
+    /// How long after its expected start (the ack's `estimated_start_ms`,
+    /// or when the ack arrived) the ActionResult must arrive
+    pub result_timeout: Duration,
+    /// Times an orphan is resent before it is given up on
+    pub max_resends: u32,
+}
+
+impl Default for WatchdogConfig {
+    /// Acks within 5s, results within 2 minutes of the start, one resend
+    fn default() -> Self {
+        Self {
+            ack_timeout: Duration::from_secs(5),
+            result_timeout: Duration::from_secs(120),
+            max_resends: 1,
+        }
+    }
+}
+
+/// What an orphaned directive was still waiting for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrphanStage {
+    /// Neither acknowledged nor rejected: probably never arrived
+    Unacknowledged,
+    /// Acknowledged, but no ActionResult
+    Unfinished,
+}
+
+/// A directive past its deadline
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectiveOrphaned {
+    /// The directive as sent
+    pub directive: ActionDirective,
+    /// What it was waiting for
+    pub stage: OrphanStage,
+    /// Resends so far, including this one if `resend` is set
+    pub resends: u32,
+    /// Whether to resend `directive` unchanged. If not, the watchdog has
+    /// stopped tracking it and the caller should treat it as failed.
+    pub resend: bool,
+}
+
+#[derive(Debug)]
+struct Watched {
+    directive: ActionDirective,
+    sent_at_ms: i64,
+    /// When the action is expected to start, once acknowledged
+    start_ms: Option<i64>,
+    resends: u32,
+}
+
+/// Tracks sent directives until they are answered.
+#[derive(Debug, Default)]
+pub struct DirectiveWatchdog {
+    config: WatchdogConfig,
+    /// Whether the plugin sends DirectiveAcks
+    acks_seen: bool,
+    /// Keyed by directive_id
+    watched: HashMap<String, Watched>,
+    /// NPCs in unloaded chunks, whose directives wait without deadline
+    parked: HashSet<String>,
+}
+
+impl DirectiveWatchdog {
+    /// Create a watchdog with the given deadlines
+    pub fn new(config: WatchdogConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Watch a directive sent (or resent) at `now_ms`. A resent directive
+    /// waits for a fresh ack but keeps its resend count.
+    pub fn sent(&mut self, directive: &ActionDirective, now_ms: i64) {
+        let resends = self
+            .watched
+            .get(&directive.directive_id)
+            .map_or(0, |w| w.resends);
+        self.watched.insert(
+            directive.directive_id.clone(),
+            Watched {
+                directive: directive.clone(),
+                sent_at_ms: now_ms,
+                start_ms: None,
+                resends,
+            },
+        );
+    }
+
+    /// Record a DirectiveAck received at `now_ms`
+    pub fn acked(&mut self, ack: &DirectiveAck, now_ms: i64) {
+        self.acks_seen = true;
+        if let Some(watched) = self.watched.get_mut(&ack.directive_id) {
+            watched.start_ms = Some(ack.estimated_start_ms.max(now_ms));
+        }
+    }
+
+    /// Stop watching a directive that got its ActionResult or was rejected;
+    /// returns whether it was watched
+    pub fn finished(&mut self, directive_id: &str) -> bool {
+        self.watched.remove(directive_id).is_some()
+    }
+
+    /// Pause the deadlines of an NPC's directives while its chunk is
+    /// unloaded
+    pub fn park(&mut self, npc_id: &str) {
+        self.parked.insert(npc_id.to_string());
+    }
+
+    /// Restart the deadlines of a parked NPC's directives from `now_ms`,
+    /// when its chunk loads again; does nothing if it was not parked
+    pub fn unpark(&mut self, npc_id: &str, now_ms: i64) {
+        if !self.parked.remove(npc_id) {
+            return;
+        }
+        for watched in self.watched.values_mut() {
+            if watched.directive.npc_id == npc_id {
+                watched.sent_at_ms = now_ms;
+                if watched.start_ms.is_some() {
+                    watched.start_ms = Some(now_ms);
+                }
+            }
+        }
+    }
+
+    /// Whether an NPC's directives are parked
+    pub fn is_parked(&self, npc_id: &str) -> bool {
+        self.parked.contains(npc_id)
+    }
+
+    /// Number of directives awaiting an answer
+    pub fn len(&self) -> usize {
+        self.watched.len()
+    }
+
+    /// Whether no directives are awaiting an answer
+    pub fn is_empty(&self) -> bool {
+        self.watched.is_empty()
+    }
+
+    /// Directives past their deadline at `now_ms`. Those to resend are
+    /// watched again from `now_ms`; the others are no longer watched.
+    pub fn check(&mut self, now_ms: i64) -> Vec<DirectiveOrphaned> {
+        let ms = |d: Duration| i64::try_from(d.as_millis()).unwrap_or(i64::MAX);
+        let result_timeout = ms(self.config.result_timeout);
+        let ack_timeout = if self.acks_seen {
+            ms(self.config.ack_timeout)
+        } else {
+            result_timeout
+        };
+
+        let mut orphans = Vec::new();
+        self.watched.retain(|_, watched| {
+            if self.parked.contains(&watched.directive.npc_id) {
+                return true;
+            }
+            let (stage, deadline) = match watched.start_ms {
+                None => (
+                    OrphanStage::Unacknowledged,
+                    watched.sent_at_ms.saturating_add(ack_timeout),
+                ),
+                Some(_) if matches!(watched.directive.action, Some(Action::WatchBlocks(_))) => {
+                    return true;
+                }
+                Some(start) => (
+                    OrphanStage::Unfinished,
+                    start.saturating_add(result_timeout),
+                ),
+            };
+            if now_ms <= deadline {
+                return true;
+            }
+            let resend = watched.resends < self.config.max_resends;
+            if resend {
+                watched.resends += 1;
+                watched.sent_at_ms = now_ms;
+                watched.start_ms = None;
+            }
+            orphans.push(DirectiveOrphaned {
+                directive: watched.directive.clone(),
+                stage,
+                resends: watched.resends,
+                resend,
+            });
+            resend
+        });
+        orphans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directive(id: &str) -> ActionDirective {
+        ActionDirective {
+            directive_id: id.to_string(),
+            npc_id: "miner".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn ack(id: &str, estimated_start_ms: i64) -> DirectiveAck {
+        DirectiveAck {
+            directive_id: id.to_string(),
+            npc_id: "miner".to_string(),
+            estimated_start_ms,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_unacknowledged_is_resent_then_given_up() {
+        let mut watchdog = DirectiveWatchdog::default();
+        watchdog.acked(&ack("earlier", 0), 0);
+        watchdog.sent(&directive("lost"), 0);
+        watchdog.sent(&directive("done"), 0);
+        assert!(watchdog.finished("done"));
+        assert!(watchdog.check(5_000).is_empty());
+
+        let orphans = watchdog.check(5_001);
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].directive.directive_id, "lost");
+        assert_eq!(orphans[0].stage, OrphanStage::Unacknowledged);
+        assert!(orphans[0].resend);
+        assert_eq!(watchdog.len(), 1);
+
+        // The resend is watched from when it was resent
+        assert!(watchdog.check(10_000).is_empty());
+        let orphans = watchdog.check(10_002);
+        assert_eq!((orphans[0].resends, orphans[0].resend), (1, false));
+        assert!(watchdog.is_empty());
+    }
+
+    #[test]
+    fn test_ack_extends_deadline() {
+        let mut watchdog = DirectiveWatchdog::new(WatchdogConfig {
+            max_resends: 0,
+            ..WatchdogConfig::default()
+        });
+        watchdog.sent(&directive("queued"), 0);
+        // Third in the NPC's queue, expected to start in a minute
+        watchdog.acked(&ack("queued", 60_000), 1_000);
+        assert!(watchdog.check(179_000).is_empty());
+
+        let orphans = watchdog.check(180_001);
+        assert_eq!(orphans[0].stage, OrphanStage::Unfinished);
+        assert!(!orphans[0].resend);
+        assert!(!watchdog.finished("queued"));
+
+        // Without acks from the plugin, only results are timed
+        let mut watchdog = DirectiveWatchdog::default();
+        watchdog.sent(&directive("legacy"), 0);
+        assert!(watchdog.check(120_000).is_empty());
+        assert_eq!(
+            watchdog.check(120_001)[0].stage,
+            OrphanStage::Unacknowledged
+        );
+
+        // Watches run until unwatched
+        assert!(watchdog.finished("legacy"));
+        let watch = ActionDirective {
+            action: Some(Action::WatchBlocks(Default::default())),
+            ..directive("watch")
+        };
+        watchdog.sent(&watch, 0);
+        watchdog.acked(&ack("watch", 0), 0);
+        assert!(watchdog.check(i64::MAX).is_empty());
+    }
+
+    #[test]
+    fn test_parked_directives_wait() {
+        let mut watchdog = DirectiveWatchdog::default();
+        watchdog.acked(&ack("earlier", 0), 0);
+        watchdog.sent(&directive("mine"), 0);
+        watchdog.acked(&ack("mine", 0), 1_000);
+        watchdog.sent(&directive("walk"), 1_000);
+        // The miner's chunk unloads: nothing is late while it is parked
+        watchdog.park("miner");
+        assert!(watchdog.is_parked("miner"));
+        assert!(watchdog.check(600_000).is_empty());
+
+        // Loaded again, both deadlines restart
+        watchdog.unpark("miner", 600_000);
+        watchdog.unpark("miner", 700_000);
+        assert!(!watchdog.is_parked("miner"));
+        assert!(watchdog.check(605_000).is_empty());
+        let orphans = watchdog.check(605_001);
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].directive.directive_id, "walk");
+        assert!(watchdog.finished("walk"));
+        assert!(watchdog.check(720_000).is_empty());
+        assert_eq!(watchdog.check(720_001)[0].stage, OrphanStage::Unfinished);
+    }
+}
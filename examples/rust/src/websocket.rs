@@ -0,0 +1,437 @@
+//! Connect and ConnectAudio over WebSocket.
+//!
+//! For hosts and browsers that cannot speak raw gRPC, a [`WebSocketConnect`]
+//! serves the `Connect` stream on a WebSocket with the
+//! `npc-society.v1+proto` subprotocol: every binary frame is one
+//! protobuf-encoded `ClientMessage` or `ServerMessage`. The frames are fed
+//! to the daemon's own [`NpcSocietyService::connect`] as a gRPC request
+//! stream, so a socket is handled exactly like a gRPC call, and the
+//! replies go back one message per frame. A socket whose first frame is an
+//! `AudioStreamHello` goes to [`NpcSocietyService::connect_audio`]
+//! instead. Closing the socket ends the stream.
+//!
+//! ```ignore
+//! let ws = WebSocketConnect::new(service.clone()).with_interceptor(config.auth.interceptor());
+//! tokio::spawn(ws.serve("127.0.0.1:8081".parse()?));
+//! ```
+//!
+//! Browsers cannot set headers on a WebSocket, so an interceptor that
+//! checks `authorization` rejects them; terminate auth in a proxy instead.
+
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use http_body::Frame;
+use http_body_util::StreamBody;
+use prost::Message as _;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::codec::{Codec, ProstCodec};
+use tonic::metadata::MetadataMap;
+use tonic::service::Interceptor;
+use tonic::transport::server::TcpConnectInfo;
+use tonic::{Extensions, Request, Status, Streaming};
+
+use crate::npc_society::v1::{
+    client_message::Message as ClientMsg, npc_society_service_server::NpcSocietyService,
+    ClientMessage, ServerMessage,
+};
+
+/// The only subprotocol served: one protobuf envelope per binary frame
+pub const PROTO_SUBPROTOCOL: &str = "npc-society.v1+proto";
+
+/// Frames read ahead of the service
+const INBOUND_FRAMES: usize = 32;
+
+/// Close code for a text frame or an undecodable one
+const CLOSE_UNSUPPORTED: u16 = 1003;
+/// Close code for a stream the service ended with an error
+const CLOSE_ERROR: u16 = 1011;
+
+type Replies = Pin<Box<dyn Stream<Item = Result<ServerMessage, Status>> + Send>>;
+
+type Check = Arc<dyn Fn(Request<()>) -> Result<Request<()>, Status> + Send + Sync>;
+
+/// Serves an [`NpcSocietyService`]'s streams over WebSocket
+pub struct WebSocketConnect<S> {
+    service: Arc<S>,
+    check: Check,
+    max_message_bytes: Option<usize>,
+}
+
+impl<S> Clone for WebSocketConnect<S> {
+    fn clone(&self) -> Self {
+        Self {
+            service: self.service.clone(),
+            check: self.check.clone(),
+            max_message_bytes: self.max_message_bytes,
+        }
+    }
+}
+
+impl<S: NpcSocietyService> WebSocketConnect<S> {
+    /// Accept every socket that asks for [`PROTO_SUBPROTOCOL`]
+    pub fn new(service: S) -> Self {
+        Self {
+            service: Arc::new(service),
+            check: Arc::new(Ok),
+            max_message_bytes: None,
+        }
+    }
+
+    /// Largest ClientMessage accepted, as `max_decoding_message_size` on
+    /// the gRPC server (default: tonic's 4 MB)
+    pub fn with_max_message_bytes(mut self, bytes: usize) -> Self {
+        self.max_message_bytes = Some(bytes);
+        self
+    }
+
+    /// Run `interceptor` on each upgrade request, with its headers as
+    /// metadata, as the gRPC server would on each call; a rejected socket
+    /// gets 401
+    #[allow(clippy::result_large_err)] // tonic's Interceptor signature
+    pub fn with_interceptor(mut self, interceptor: impl Interceptor + Clone + Send + Sync + 'static) -> Self {
+        self.check = Arc::new(move |request| interceptor.clone().call(request));
+        self
+    }
+
+    /// Router with the WebSocket endpoint at `/`; serve it with
+    /// `into_make_service_with_connect_info::<SocketAddr>()` so handlers
+    /// see the peer address
+    pub fn router(self) -> Router {
+        Router::new().route("/", get(upgrade::<S>)).with_state(self)
+    }
+
+    /// Serve on `addr` until the listener fails
+    pub async fn serve(self, addr: SocketAddr) -> io::Result<()> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        axum::serve(listener, self.router().into_make_service_with_connect_info::<SocketAddr>()).await
+    }
+}
+
+async fn upgrade<S: NpcSocietyService>(
+    State(ws): State<WebSocketConnect<S>>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let offered = headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|protocol| protocol.trim() == PROTO_SUBPROTOCOL);
+    if !offered {
+        return (StatusCode::BAD_REQUEST, format!("Sec-WebSocket-Protocol must offer {PROTO_SUBPROTOCOL}"))
+            .into_response();
+    }
+    let mut extensions = Extensions::new();
+    extensions.insert(TcpConnectInfo {
+        local_addr: None,
+        remote_addr: peer.map(|ConnectInfo(addr)| addr),
+    });
+    let request = Request::from_parts(MetadataMap::from_headers(headers), extensions, ());
+    let request = match (ws.check)(request) {
+        Ok(request) => request,
+        Err(status) => return (StatusCode::UNAUTHORIZED, status.message().to_string()).into_response(),
+    };
+    upgrade
+        .protocols([PROTO_SUBPROTOCOL])
+        .on_upgrade(move |socket| run(ws, socket, request))
+}
+
+/// One gRPC length-prefixed message, uncompressed
+fn grpc_frame(payload: &[u8]) -> Bytes {
+    let mut frame = Vec::with_capacity(5 + payload.len());
+    frame.push(0);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame.into()
+}
+
+async fn close(mut socket: WebSocket, code: u16, reason: &str) {
+    let _ = socket
+        .send(Message::Close(Some(CloseFrame {
+            code,
+            reason: reason.to_string().into(),
+        })))
+        .await;
+}
+
+async fn run<S: NpcSocietyService>(ws: WebSocketConnect<S>, mut socket: WebSocket, request: Request<()>) {
+    // The first frame picks the stream, and goes to it like the rest
+    let first = match socket.recv().await {
+        Some(Ok(Message::Binary(payload))) => payload,
+        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+        Some(Ok(_)) => return close(socket, CLOSE_UNSUPPORTED, "expected a binary frame").await,
+    };
+    let audio = matches!(
+        ClientMessage::decode(&first[..]),
+        Ok(ClientMessage { message: Some(ClientMsg::AudioStreamHello(_)), .. })
+    );
+
+    let (inbound, frames) = mpsc::channel::<Result<Frame<Bytes>, Status>>(INBOUND_FRAMES);
+    let _ = inbound.send(Ok(Frame::data(grpc_frame(&first)))).await;
+    let body = StreamBody::new(ReceiverStream::new(frames));
+    let messages = Streaming::new_request(
+        ProstCodec::<ServerMessage, ClientMessage>::default().decoder(),
+        body,
+        None,
+        ws.max_message_bytes,
+    );
+    let (metadata, extensions, ()) = request.into_parts();
+    let request = Request::from_parts(metadata, extensions, messages);
+
+    let replies = if audio {
+        ws.service.connect_audio(request).await.map(|r| Box::pin(r.into_inner()) as Replies)
+    } else {
+        ws.service.connect(request).await.map(|r| Box::pin(r.into_inner()) as Replies)
+    };
+    match replies {
+        Ok(replies) => forward(socket, replies, inbound).await,
+        Err(status) => close(socket, CLOSE_ERROR, status.message()).await,
+    }
+}
+
+/// Pass frames from the socket to the service and its replies back, until
+/// either side ends
+async fn forward(
+    mut socket: WebSocket,
+    mut replies: Replies,
+    inbound: mpsc::Sender<Result<Frame<Bytes>, Status>>,
+) {
+    loop {
+        tokio::select! {
+            frame = socket.recv() => match frame {
+                Some(Ok(Message::Binary(payload))) => {
+                    // The service stopped reading
+                    if inbound.send(Ok(Frame::data(grpc_frame(&payload)))).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Text(_))) => {
+                    return close(socket, CLOSE_UNSUPPORTED, "text frames are not supported").await;
+                }
+                Some(Ok(Message::Ping(_) | Message::Pong(_))) => {}
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+            },
+            reply = replies.next() => match reply {
+                Some(Ok(msg)) => {
+                    if socket.send(Message::Binary(msg.encode_to_vec())).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Err(status)) => return close(socket, CLOSE_ERROR, status.message()).await,
+                None => return close(socket, 1000, "").await,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AuthConfig;
+
+    use crate::npc_society::v1::{server_message::Message as ServerMsg, *};
+    use futures_util::SinkExt;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    type ServerStream = Replies;
+
+    /// Answers each Hello with a SpeakDirective naming its server and the
+    /// peer's address, and refuses audio streams
+    struct Echo;
+
+    #[tonic::async_trait]
+    impl NpcSocietyService for Echo {
+        type ConnectStream = ServerStream;
+        type ConnectAudioStream = ServerStream;
+
+        #[allow(clippy::result_large_err)] // tonic's Status
+        async fn connect(
+            &self,
+            request: Request<Streaming<ClientMessage>>,
+        ) -> Result<tonic::Response<ServerStream>, Status> {
+            let peer = request.remote_addr().map(|a| a.ip().to_string()).unwrap_or_default();
+            let replies = request.into_inner().map(move |msg| match msg?.message {
+                Some(ClientMsg::Hello(hello)) => Ok(ServerMessage {
+                    message: Some(ServerMsg::SpeakDirective(SpeakDirective {
+                        text: format!("{}@{peer}", hello.server_id),
+                        ..Default::default()
+                    })),
+                    ..Default::default()
+                }),
+                _ => Err(Status::invalid_argument("expected Hello")),
+            });
+            Ok(tonic::Response::new(Box::pin(replies)))
+        }
+
+        async fn connect_audio(
+            &self,
+            _: Request<Streaming<ClientMessage>>,
+        ) -> Result<tonic::Response<ServerStream>, Status> {
+            Err(Status::permission_denied("no audio"))
+        }
+
+        async fn get_snapshot(
+            &self,
+            _: Request<GetSnapshotRequest>,
+        ) -> Result<tonic::Response<GetSnapshotResponse>, Status> {
+            Err(Status::unimplemented("get_snapshot"))
+        }
+
+        async fn list_npcs(
+            &self,
+            _: Request<ListNpcsRequest>,
+        ) -> Result<tonic::Response<ListNpcsResponse>, Status> {
+            Err(Status::unimplemented("list_npcs"))
+        }
+
+        async fn get_npc_state(
+            &self,
+            _: Request<GetNpcStateRequest>,
+        ) -> Result<tonic::Response<GetNpcStateResponse>, Status> {
+            Err(Status::unimplemented("get_npc_state"))
+        }
+
+        async fn list_pending_directives(
+            &self,
+            _: Request<ListPendingDirectivesRequest>,
+        ) -> Result<tonic::Response<ListPendingDirectivesResponse>, Status> {
+            Err(Status::unimplemented("list_pending_directives"))
+        }
+
+        async fn get_session_info(
+            &self,
+            _: Request<GetSessionInfoRequest>,
+        ) -> Result<tonic::Response<GetSessionInfoResponse>, Status> {
+            Err(Status::unimplemented("get_session_info"))
+        }
+
+        async fn list_servers(
+            &self,
+            _: Request<ListServersRequest>,
+        ) -> Result<tonic::Response<ListServersResponse>, Status> {
+            Err(Status::unimplemented("list_servers"))
+        }
+
+        async fn send_directive(
+            &self,
+            _: Request<SendDirectiveRequest>,
+        ) -> Result<tonic::Response<SendDirectiveResponse>, Status> {
+            Err(Status::unimplemented("send_directive"))
+        }
+
+        async fn export_npc_state(
+            &self,
+            _: Request<ExportNpcStateRequest>,
+        ) -> Result<tonic::Response<ExportNpcStateResponse>, Status> {
+            Err(Status::unimplemented("export_npc_state"))
+        }
+
+        async fn import_npc_state(
+            &self,
+            _: Request<ImportNpcStateRequest>,
+        ) -> Result<tonic::Response<ImportNpcStateResponse>, Status> {
+            Err(Status::unimplemented("import_npc_state"))
+        }
+
+        async fn transfer_npc(
+            &self,
+            _: Request<TransferNpcRequest>,
+        ) -> Result<tonic::Response<TransferNpcResponse>, Status> {
+            Err(Status::unimplemented("transfer_npc"))
+        }
+    }
+
+    async fn serve(ws: WebSocketConnect<Echo>) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = ws.router().into_make_service_with_connect_info::<SocketAddr>();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        addr
+    }
+
+    fn request(addr: SocketAddr, protocol: &str) -> tokio_tungstenite::tungstenite::handshake::client::Request {
+        let mut request = format!("ws://{addr}/").into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert(header::SEC_WEBSOCKET_PROTOCOL, protocol.parse().unwrap());
+        request
+    }
+
+    #[tokio::test]
+    async fn test_binary_frames_round_trip() {
+        let addr = serve(WebSocketConnect::new(Echo)).await;
+        let (mut socket, response) = tokio_tungstenite::connect_async(request(addr, PROTO_SUBPROTOCOL))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::SEC_WEBSOCKET_PROTOCOL], PROTO_SUBPROTOCOL);
+
+        for server_id in ["survival", "creative"] {
+            let hello = ClientMessage {
+                message: Some(ClientMsg::Hello(Hello {
+                    server_id: server_id.to_string(),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            };
+            socket.send(WsMessage::Binary(hello.encode_to_vec())).await.unwrap();
+            let Some(Ok(WsMessage::Binary(payload))) = socket.next().await else {
+                panic!("expected a binary frame");
+            };
+            match ServerMessage::decode(&payload[..]).unwrap().message {
+                // The service sees the socket's peer, as on gRPC
+                Some(ServerMsg::SpeakDirective(speak)) => assert_eq!(speak.text, format!("{server_id}@127.0.0.1")),
+                other => panic!("expected a SpeakDirective, got {other:?}"),
+            }
+        }
+
+        // An error from the service closes the socket with its message
+        let tick = ClientMessage {
+            message: Some(ClientMsg::WorldTick(WorldTick::default())),
+            ..Default::default()
+        };
+        socket.send(WsMessage::Binary(tick.encode_to_vec())).await.unwrap();
+        match socket.next().await {
+            Some(Ok(WsMessage::Close(Some(frame)))) => {
+                assert_eq!(u16::from(frame.code), CLOSE_ERROR);
+                assert_eq!(frame.reason, "expected Hello");
+            }
+            other => panic!("expected a close frame, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejected_upgrades() {
+        let auth = AuthConfig {
+            tokens: vec!["secret".to_string()],
+        };
+        let ws = WebSocketConnect::new(Echo).with_interceptor(auth.interceptor());
+        let addr = serve(ws).await;
+
+        // There is no JSON mode
+        let wrong_protocol = tokio_tungstenite::connect_async(request(addr, "npc-society.v1+json")).await;
+        assert!(wrong_protocol.is_err());
+        let unauthenticated = tokio_tungstenite::connect_async(request(addr, PROTO_SUBPROTOCOL)).await;
+        assert!(unauthenticated.is_err());
+
+        let mut authorized = request(addr, PROTO_SUBPROTOCOL);
+        authorized
+            .headers_mut()
+            .insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert!(tokio_tungstenite::connect_async(authorized).await.is_ok());
+    }
+}
@@ -0,0 +1,601 @@
+//! Cached view of the world around the NPCs.
+//!
+//! The plugin only reports what is near an NPC right now. [`WorldModel`]
+//! accumulates WorldTicks, scan/raycast results, region snapshots and block
+//! events into a sparse block cache plus entity and player sightings, so
+//! the daemon can ask "nearest known diamond ore", "what is within 8
+//! blocks" or "where was player Y last seen" without rebuilding that
+//! bookkeeping. A WorldBootstrap starts it off with every NPC, the land
+//! and container claims and each world's clock and weather.
+
+use std::collections::HashMap;
+
+use crate::geom;
+use crate::path;
+use crate::region::Region;
+use crate::npc_society::v1::{
+    action_result::Result as ActionResultType, event_observation::Payload, ActionResult,
+    BlockEventType, BlockPosition, BlockProperties, BlockWatchUpdate, ClaimContainerDirective,
+    EntitySnapshot, EnvironmentState, EventObservation, NpcSnapshot, PlayerSnapshot, Position,
+    RegionClaim, WorldBootstrap, WorldTick,
+};
+
+/// Block type recorded for broken blocks
+pub const AIR: &str = "minecraft:air";
+
+/// A block the daemon has learned about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownBlock {
+    /// Block type, e.g. `minecraft:diamond_ore` ([`AIR`] once broken)
+    pub block_type: String,
+    /// When it was observed (Unix ms)
+    pub seen_at_ms: i64,
+}
+
+/// Where and when something was last seen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sighting<T> {
+    /// The snapshot as last reported
+    pub snapshot: T,
+    /// When it was reported (Unix ms)
+    pub seen_at_ms: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct BlockKey {
+    world: String,
+    x: i32,
+    y: i32,
+    z: i32,
+}
+
+impl From<&BlockPosition> for BlockKey {
+    fn from(p: &BlockPosition) -> Self {
+        Self {
+            world: p.world.clone(),
+            x: p.x,
+            y: p.y,
+            z: p.z,
+        }
+    }
+}
+
+impl BlockKey {
+    fn position(&self) -> BlockPosition {
+        BlockPosition {
+            world: self.world.clone(),
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            ..Default::default()
+        }
+    }
+
+}
+
+/// Sparse block cache plus entity, player and NPC sightings, claims and
+/// each world's environment.
+#[derive(Debug, Default)]
+pub struct WorldModel {
+    blocks: HashMap<BlockKey, KnownBlock>,
+    entities: HashMap<String, Sighting<EntitySnapshot>>,
+    players: HashMap<String, Sighting<PlayerSnapshot>>,
+    npcs: HashMap<String, Sighting<NpcSnapshot>>,
+    /// Land claims by (plugin, region_id)
+    regions: HashMap<(String, String), RegionClaim>,
+    /// Container claims by the container's block
+    containers: HashMap<BlockKey, ClaimContainerDirective>,
+    /// Clock and weather by world
+    environments: HashMap<String, EnvironmentState>,
+    /// Per block type, from results that included properties
+    properties: HashMap<String, BlockProperties>,
+    latest_tick_ms: i64,
+}
+
+impl WorldModel {
+    /// Start over from a WorldBootstrap: its NPCs, claims and environments
+    /// replace those known, blocks and sightings are kept
+    pub fn ingest_bootstrap(&mut self, bootstrap: &WorldBootstrap) {
+        self.npcs.clear();
+        self.regions.clear();
+        self.containers.clear();
+        self.environments.clear();
+        for npc in &bootstrap.npcs {
+            self.ingest_npc(npc, bootstrap.timestamp_ms);
+        }
+        for region in &bootstrap.regions {
+            self.set_region(region);
+        }
+        for claim in &bootstrap.containers {
+            self.claim_container(claim);
+        }
+        for environment in &bootstrap.environments {
+            self.set_environment(environment);
+        }
+    }
+
+    /// Record the NPCs, players and entities in a WorldTick, and the claims
+    /// and environment it reports
+    pub fn ingest_tick(&mut self, tick: &WorldTick) {
+        self.latest_tick_ms = tick.timestamp_ms;
+        for npc in &tick.npcs {
+            self.ingest_npc(npc, tick.timestamp_ms);
+        }
+        if let Some(environment) = &tick.environment {
+            self.set_environment(environment);
+        }
+        for player in &tick.nearby_players {
+            self.players.insert(
+                player.player_uuid.clone(),
+                Sighting {
+                    snapshot: player.clone(),
+                    seen_at_ms: tick.timestamp_ms,
+                },
+            );
+        }
+        for entity in &tick.nearby_entities {
+            self.entities.insert(
+                entity.entity_uuid.clone(),
+                Sighting {
+                    snapshot: entity.clone(),
+                    seen_at_ms: tick.timestamp_ms,
+                },
+            );
+        }
+    }
+
+    /// Record blocks from scan, raycast and region snapshot results
+    /// (malformed snapshots are skipped)
+    pub fn ingest_action_result(&mut self, result: &ActionResult, timestamp_ms: i64) {
+        match &result.result {
+            Some(ActionResultType::ScanBlocksResult(scan)) => {
+                for m in &scan.matches {
+                    if let Some(position) = &m.position {
+                        self.set_block(position, &m.block_type, timestamp_ms);
+                    }
+                    if let Some(properties) = &m.properties {
+                        self.set_properties(&m.block_type, properties);
+                    }
+                }
+            }
+            Some(ActionResultType::RaycastLookResult(ray)) if ray.hit => {
+                if let Some(position) = &ray.hit_position {
+                    self.set_block(position, &ray.block_type, timestamp_ms);
+                }
+            }
+            Some(ActionResultType::CheckLineOfSightResult(sight)) => {
+                if let Some(position) = &sight.blocked_by {
+                    self.set_block(position, &sight.blocking_block_type, timestamp_ms);
+                }
+            }
+            Some(ActionResultType::RegionSnapshotResult(snapshot)) => {
+                if let Ok(region) = Region::decode(snapshot) {
+                    self.ingest_region(&region, timestamp_ms);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Record every block of a region outside unloaded chunks
+    pub fn ingest_region(&mut self, region: &Region, seen_at_ms: i64) {
+        for (position, block_type) in region.blocks() {
+            self.set_block(&position, block_type, seen_at_ms);
+        }
+        for (block_type, properties) in region.palette_properties() {
+            self.set_properties(block_type, properties);
+        }
+    }
+
+    /// Update the cache from a block watch. Removed blocks are forgotten:
+    /// the update does not say what replaced them.
+    pub fn ingest_block_watch(&mut self, update: &BlockWatchUpdate, seen_at_ms: i64) {
+        for position in &update.removed {
+            self.blocks.remove(&position.into());
+        }
+        for found in &update.found {
+            if let Some(position) = &found.position {
+                self.set_block(position, &found.block_type, seen_at_ms);
+            }
+            if let Some(properties) = &found.properties {
+                self.set_properties(&found.block_type, properties);
+            }
+        }
+    }
+
+    /// Record the properties of a block type (the light level is dropped,
+    /// it depends on the position)
+    pub fn set_properties(&mut self, block_type: &str, properties: &BlockProperties) {
+        let properties = BlockProperties {
+            light_level: 0,
+            ..properties.clone()
+        };
+        self.properties.insert(block_type.to_string(), properties);
+    }
+
+    /// Properties of a block type, if a result reported them
+    pub fn properties(&self, block_type: &str) -> Option<&BlockProperties> {
+        self.properties.get(block_type)
+    }
+
+    /// Whether NPCs can move through `block_type`: as reported, or guessed
+    /// with [`path::is_passable`] for types without properties
+    pub fn is_passable(&self, block_type: &str) -> bool {
+        match self.properties.get(block_type) {
+            Some(properties) => properties.passable,
+            None => path::is_passable(block_type),
+        }
+    }
+
+    /// Whether `block_type` is reported as unbreakable (bedrock, barriers)
+    pub fn is_unbreakable(&self, block_type: &str) -> bool {
+        self.properties.get(block_type).is_some_and(|p| p.hardness < 0.0)
+    }
+
+    /// Record block breaks and placements, and blocks destroyed by explosions
+    pub fn ingest_event(&mut self, event: &EventObservation) {
+        match &event.payload {
+            Some(Payload::Block(block)) => {
+                let Some(position) = &block.position else {
+                    return;
+                };
+                match block.event_type() {
+                    BlockEventType::Break => self.set_block(position, AIR, event.timestamp_ms),
+                    BlockEventType::Place => self.set_block(position, &block.block_type, event.timestamp_ms),
+                    _ => {}
+                }
+            }
+            Some(Payload::Explosion(explosion)) => {
+                for position in &explosion.destroyed_blocks {
+                    self.set_block(position, AIR, event.timestamp_ms);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ingest_npc(&mut self, npc: &NpcSnapshot, seen_at_ms: i64) {
+        for region in &npc.regions {
+            self.set_region(region);
+        }
+        self.npcs.insert(
+            npc.npc_id.clone(),
+            Sighting {
+                snapshot: npc.clone(),
+                seen_at_ms,
+            },
+        );
+    }
+
+    fn set_region(&mut self, region: &RegionClaim) {
+        let key = (region.plugin.clone(), region.region_id.clone());
+        self.regions.insert(key, region.clone());
+    }
+
+    fn set_environment(&mut self, environment: &EnvironmentState) {
+        if !environment.world.is_empty() {
+            self.environments
+                .insert(environment.world.clone(), environment.clone());
+        }
+    }
+
+    /// Record a ClaimContainerDirective; one with `release` set ends the
+    /// claim
+    pub fn claim_container(&mut self, claim: &ClaimContainerDirective) {
+        let Some(position) = &claim.position else {
+            return;
+        };
+        if claim.release {
+            self.containers.remove(&position.into());
+        } else {
+            self.containers.insert(position.into(), claim.clone());
+        }
+    }
+
+    /// Record a block directly (e.g. after the NPC broke it)
+    pub fn set_block(&mut self, position: &BlockPosition, block_type: &str, seen_at_ms: i64) {
+        self.blocks.insert(
+            position.into(),
+            KnownBlock {
+                block_type: block_type.to_string(),
+                seen_at_ms,
+            },
+        );
+    }
+
+    /// What is known about the block at `position`
+    pub fn block(&self, position: &BlockPosition) -> Option<&KnownBlock> {
+        self.blocks.get(&position.into())
+    }
+
+    /// Number of cached blocks
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Closest known block of `block_type` in `from`'s world, with its distance
+    pub fn nearest_block(&self, from: &Position, block_type: &str) -> Option<(BlockPosition, f64)> {
+        self.blocks
+            .iter()
+            .filter(|(key, block)| key.world == from.world && block.block_type == block_type)
+            .map(|(key, _)| (key, geom::distance(&key.position(), from)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(key, distance)| (key.position(), distance))
+    }
+
+    /// Entities reported in the latest WorldTick within `radius` of `center`
+    pub fn entities_within(&self, center: &Position, radius: f64) -> Vec<&EntitySnapshot> {
+        self.entities
+            .values()
+            .filter(|s| s.seen_at_ms == self.latest_tick_ms)
+            .map(|s| &s.snapshot)
+            .filter(|e| e.position.as_ref().is_some_and(|p| within(center, p, radius)))
+            .collect()
+    }
+
+    /// Players reported in the latest WorldTick within `radius` of `center`
+    pub fn players_within(&self, center: &Position, radius: f64) -> Vec<&PlayerSnapshot> {
+        self.players
+            .values()
+            .filter(|s| s.seen_at_ms == self.latest_tick_ms)
+            .map(|s| &s.snapshot)
+            .filter(|p| p.position.as_ref().is_some_and(|pos| within(center, pos, radius)))
+            .collect()
+    }
+
+    /// An NPC as last reported, however long ago
+    pub fn npc(&self, npc_id: &str) -> Option<&Sighting<NpcSnapshot>> {
+        self.npcs.get(npc_id)
+    }
+
+    /// Every NPC reported, in no particular order
+    pub fn npcs(&self) -> impl Iterator<Item = &NpcSnapshot> {
+        self.npcs.values().map(|s| &s.snapshot)
+    }
+
+    /// Land claims whose bounding box holds `position`
+    pub fn regions_at<'a>(
+        &'a self,
+        position: &'a BlockPosition,
+    ) -> impl Iterator<Item = &'a RegionClaim> + 'a {
+        self.regions.values().filter(move |region| {
+            let (Some(min), Some(max)) = (&region.min, &region.max) else {
+                return false;
+            };
+            min.world == position.world
+                && (min.x..=max.x).contains(&position.x)
+                && (min.y..=max.y).contains(&position.y)
+                && (min.z..=max.z).contains(&position.z)
+        })
+    }
+
+    /// The claim on the container at `position`, if an NPC holds it
+    pub fn container_claim(&self, position: &BlockPosition) -> Option<&ClaimContainerDirective> {
+        self.containers.get(&position.into())
+    }
+
+    /// Clock and weather of `world` as last reported
+    pub fn environment(&self, world: &str) -> Option<&EnvironmentState> {
+        self.environments.get(world)
+    }
+
+    /// Where a player was last seen, however long ago
+    pub fn last_seen_player(&self, player_uuid: &str) -> Option<&Sighting<PlayerSnapshot>> {
+        self.players.get(player_uuid)
+    }
+
+    /// Drop entity and player sightings older than `cutoff_ms`
+    pub fn forget_sightings_before(&mut self, cutoff_ms: i64) {
+        self.entities.retain(|_, s| s.seen_at_ms >= cutoff_ms);
+        self.players.retain(|_, s| s.seen_at_ms >= cutoff_ms);
+    }
+}
+
+fn within(center: &Position, p: &Position, radius: f64) -> bool {
+    p.world == center.world && geom::within_radius(center, p, radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npc_society::v1::{BlockEvent, BlockMatch, ScanBlocksResult};
+
+    fn block(x: i32) -> BlockPosition {
+        BlockPosition {
+            world: "world".to_string(),
+            x,
+            y: 10,
+            z: 0,
+            ..Default::default()
+        }
+    }
+
+    fn at(x: f64) -> Position {
+        Position {
+            world: "world".to_string(),
+            x,
+            y: 10.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_blocks_from_scans_and_events() {
+        let mut world = WorldModel::default();
+        let scan = ActionResult {
+            success: true,
+            result: Some(ActionResultType::ScanBlocksResult(ScanBlocksResult {
+                matches: [2, 9]
+                    .iter()
+                    .map(|&x| BlockMatch {
+                        position: Some(block(x)),
+                        block_type: "minecraft:diamond_ore".to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+            })),
+            ..Default::default()
+        };
+        world.ingest_action_result(&scan, 1);
+
+        let (nearest, _) = world.nearest_block(&at(8.0), "minecraft:diamond_ore").unwrap();
+        assert_eq!(nearest.x, 9);
+
+        // Someone mined it
+        world.ingest_event(&EventObservation {
+            timestamp_ms: 2,
+            payload: Some(Payload::Block(BlockEvent {
+                event_type: BlockEventType::Break as i32,
+                position: Some(block(9)),
+                ..Default::default()
+            })),
+            ..Default::default()
+        });
+        assert_eq!(world.block(&block(9)).unwrap().block_type, AIR);
+        let (nearest, _) = world.nearest_block(&at(8.0), "minecraft:diamond_ore").unwrap();
+        assert_eq!(nearest.x, 2);
+    }
+
+    #[test]
+    fn test_sightings() {
+        let mut world = WorldModel::default();
+        let player = PlayerSnapshot {
+            player_uuid: "p".to_string(),
+            position: Some(at(3.0)),
+            ..Default::default()
+        };
+        let zombie = EntitySnapshot {
+            entity_uuid: "z".to_string(),
+            position: Some(at(20.0)),
+            ..Default::default()
+        };
+        world.ingest_tick(&WorldTick {
+            timestamp_ms: 100,
+            nearby_players: vec![player],
+            nearby_entities: vec![zombie],
+            ..Default::default()
+        });
+
+        assert_eq!(world.players_within(&at(0.0), 5.0).len(), 1);
+        assert!(world.entities_within(&at(0.0), 5.0).is_empty());
+        assert_eq!(world.entities_within(&at(0.0), 25.0).len(), 1);
+
+        // The player walked out of range; only the last sighting remains
+        world.ingest_tick(&WorldTick {
+            timestamp_ms: 200,
+            ..Default::default()
+        });
+        assert!(world.players_within(&at(0.0), 5.0).is_empty());
+        assert_eq!(world.last_seen_player("p").unwrap().seen_at_ms, 100);
+
+        world.forget_sightings_before(150);
+        assert!(world.last_seen_player("p").is_none());
+    }
+
+    #[test]
+    fn test_bootstrap() {
+        let mut world = WorldModel::default();
+        let region = RegionClaim {
+            region_id: "spawn".to_string(),
+            plugin: "WorldGuard".to_string(),
+            min: Some(block(0)),
+            max: Some(BlockPosition {
+                y: 20,
+                z: 5,
+                ..block(10)
+            }),
+            ..Default::default()
+        };
+        world.ingest_bootstrap(&WorldBootstrap {
+            npcs: vec![NpcSnapshot {
+                npc_id: "miner".to_string(),
+                position: Some(at(4.0)),
+                ..Default::default()
+            }],
+            regions: vec![region],
+            containers: vec![ClaimContainerDirective {
+                npc_id: "miner".to_string(),
+                position: Some(block(3)),
+                locked: true,
+                ..Default::default()
+            }],
+            environments: vec![EnvironmentState {
+                world: "world".to_string(),
+                time_of_day: 6000,
+                ..Default::default()
+            }],
+            timestamp_ms: 50,
+            ..Default::default()
+        });
+
+        assert_eq!(world.npc("miner").unwrap().seen_at_ms, 50);
+        assert_eq!(world.regions_at(&block(7)).count(), 1);
+        assert_eq!(world.regions_at(&block(11)).count(), 0);
+        assert!(world.container_claim(&block(3)).unwrap().locked);
+        assert_eq!(world.environment("world").unwrap().time_of_day, 6000);
+
+        // Ticks keep it current
+        world.ingest_tick(&WorldTick {
+            timestamp_ms: 100,
+            environment: Some(EnvironmentState {
+                world: "world".to_string(),
+                raining: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        assert!(world.environment("world").unwrap().raining);
+        world.claim_container(&ClaimContainerDirective {
+            position: Some(block(3)),
+            release: true,
+            ..Default::default()
+        });
+        assert!(world.container_claim(&block(3)).is_none());
+
+        // A new bootstrap replaces what the last one said
+        world.ingest_bootstrap(&WorldBootstrap::default());
+        assert_eq!(world.npcs().count(), 0);
+        assert!(world.environment("world").is_none());
+    }
+
+    #[test]
+    fn test_block_properties() {
+        let mut world = WorldModel::default();
+        assert!(world.is_passable("minecraft:short_grass"));
+        assert!(!world.is_passable("minecraft:bedrock"));
+
+        let water = BlockMatch {
+            position: Some(block(1)),
+            block_type: "minecraft:water".to_string(),
+            properties: Some(BlockProperties {
+                passable: true,
+                liquid: true,
+                light_level: 7,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let bedrock = BlockMatch {
+            position: Some(block(2)),
+            block_type: "minecraft:bedrock".to_string(),
+            properties: Some(BlockProperties {
+                solid: true,
+                hardness: -1.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let result = ActionResult {
+            result: Some(ActionResultType::ScanBlocksResult(ScanBlocksResult {
+                matches: vec![water, bedrock],
+            })),
+            ..Default::default()
+        };
+        world.ingest_action_result(&result, 0);
+
+        assert!(world.is_passable("minecraft:water"));
+        assert_eq!(world.properties("minecraft:water").unwrap().light_level, 0);
+        assert!(world.is_unbreakable("minecraft:bedrock"));
+        assert!(!world.is_unbreakable("minecraft:stone"));
+    }
+}